@@ -0,0 +1,59 @@
+//! Loads a cartridge for each supported mapper and writes arbitrary values to its registers
+//! between frames.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use tetanes_core::{
+    control_deck::ControlDeck,
+    mem::{Access, Mem, RamState},
+};
+
+const MAPPERS: [u16; 25] = [
+    0, 1, 2, 3, 4, 5, 7, 9, 10, 19, 21, 22, 23, 24, 25, 26, 28, 34, 66, 69, 71, 85, 105, 155, 225,
+];
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    mapper: u8,
+    // 16KB PRG-ROM and 8KB CHR-ROM banks, kept small so each run is fast
+    prg_banks: u8,
+    chr_banks: u8,
+    flags: u8,
+    writes: Vec<(u16, u8)>,
+}
+
+fuzz_target!(|input: Input| {
+    let mapper = MAPPERS[input.mapper as usize % MAPPERS.len()];
+    let prg_banks = 1 + input.prg_banks % 8;
+    let chr_banks = input.chr_banks % 8;
+    let mut rom = vec![0x00; 16];
+    rom[..4].copy_from_slice(b"NES\x1a");
+    rom[4] = prg_banks;
+    rom[5] = chr_banks;
+    // Keep mirroring and battery flags, but not trainers or NES 2.0 headers
+    rom[6] = (mapper as u8 & 0x0F) << 4 | (input.flags & 0x0B);
+    rom[7] = mapper as u8 & 0xF0;
+    // Fill PRG-ROM with an infinite loop so the CPU stays put between writes
+    let prg_len = usize::from(prg_banks) * 0x4000;
+    let mut prg = vec![0x00; prg_len];
+    prg[..3].copy_from_slice(&[0x4C, 0x00, 0x80]); // JMP $8000
+    prg[prg_len - 4..prg_len - 2].copy_from_slice(&[0x00, 0x80]); // Reset vector
+    rom.extend(prg);
+    rom.resize(rom.len() + usize::from(chr_banks) * 0x2000, 0x00);
+
+    let mut control_deck = ControlDeck::new(RamState::AllZeros);
+    if control_deck.load_rom("fuzz", &mut &rom[..]).is_err() {
+        return;
+    }
+    for chunk in input.writes.chunks(16) {
+        for &(addr, val) in chunk {
+            control_deck.cpu_mut().write(addr, val, Access::Write);
+        }
+        if control_deck.clock_frame().is_err() {
+            return;
+        }
+        control_deck.clear_audio_samples();
+    }
+});
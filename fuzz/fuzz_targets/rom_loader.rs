@@ -0,0 +1,14 @@
+//! Feeds arbitrary data to the ROM header and cartridge parsers.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tetanes_core::{
+    cart::{Cart, NesHeader},
+    mem::RamState,
+};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = NesHeader::load(&mut &data[..]);
+    let _ = Cart::from_rom("fuzz", &mut &data[..], RamState::AllZeros);
+});
@@ -0,0 +1,21 @@
+//! Loads arbitrary save state data and runs a frame from it.
+
+#![no_main]
+
+use bincode::Options;
+use libfuzzer_sys::fuzz_target;
+use tetanes_core::{control_deck::ControlDeck, cpu::Cpu, mem::RamState};
+
+fuzz_target!(|data: &[u8]| {
+    // Limit allocations so length prefixes in the state can't exhaust memory
+    let options = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .with_limit(16 * 1024 * 1024);
+    let Ok(cpu) = options.deserialize::<Cpu>(data) else {
+        return;
+    };
+    let mut control_deck = ControlDeck::new(RamState::AllZeros);
+    control_deck.load_cpu(cpu);
+    let _ = control_deck.clock_frame();
+});
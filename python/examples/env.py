@@ -0,0 +1,56 @@
+"""A minimal Gym-style environment for Super Mario Bros.
+
+Actions are joypad bitmasks, observations are RGB frames and the reward is
+progress to the right. Episodes end when Mario loses a life.
+"""
+
+import tetanes
+
+FRAME_SKIP = 4
+
+
+class MarioEnv:
+    def __init__(self, rom_path):
+        self.nes = tetanes.ControlDeck()
+        self.nes.load_rom(rom_path)
+        # Press start on the title screen and save a state to reset to
+        self.nes.step_frames(40)
+        self.nes.set_button(1, "start", True)
+        self.nes.step_frame()
+        self.nes.set_joypad(1, 0)
+        self.nes.step_frames(180)
+        self.start = self.nes.save_state()
+
+    def _x_position(self):
+        page, x = self.nes.read_memory(0x006D)[0], self.nes.read_memory(0x0086)[0]
+        return page * 256 + x
+
+    def _observation(self):
+        return self.nes.frame()[:, :, :3]
+
+    def reset(self):
+        self.nes.load_state(self.start)
+        self.lives = self.nes.read_memory(0x075A)[0]
+        self.x = self._x_position()
+        return self._observation()
+
+    def step(self, action):
+        self.nes.set_joypad(1, action)
+        self.nes.step_frames(FRAME_SKIP)
+        x = self._x_position()
+        reward, self.x = x - self.x, x
+        done = self.nes.read_memory(0x075A)[0] != self.lives
+        return self._observation(), reward, done, {}
+
+
+if __name__ == "__main__":
+    import random
+    import sys
+
+    env = MarioEnv(sys.argv[1])
+    env.reset()
+    total, done = 0, False
+    while not done:
+        _, reward, done, _ = env.step(random.choice([0x80, 0x81, 0x82]))
+        total += reward
+    print(f"total reward: {total}")
@@ -0,0 +1,221 @@
+//! Python bindings for the `TetaNES` emulation core.
+//!
+//! Exposes a `ControlDeck` class that loads ROMs, steps frames, sets joypad state and reads and
+//! writes memory without opening a window, so games can be driven from Python, for example as
+//! Gym-style environments for reinforcement learning.
+
+#![doc(
+    html_favicon_url = "https://github.com/lukexor/tetanes/blob/main/static/tetanes_icon.png?raw=true",
+    html_logo_url = "https://github.com/lukexor/tetanes/blob/main/static/tetanes_icon.png?raw=true"
+)]
+
+use numpy::{ndarray::Array3, IntoPyArray, PyArray3};
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+use std::{fs::File, io::BufReader, path::PathBuf};
+use tetanes_core::{
+    common::{Kind, Reset},
+    control_deck,
+    cpu::Cpu,
+    input::{JoypadBtnState, Slot},
+    mem::{Access, Mem, RamState},
+    ppu::Ppu,
+    video::VideoFilter,
+    NesError,
+};
+
+/// Joypad buttons in the bit order used by `set_joypad` and `joypad`.
+const BUTTONS: [JoypadBtnState; 8] = [
+    JoypadBtnState::A,
+    JoypadBtnState::B,
+    JoypadBtnState::SELECT,
+    JoypadBtnState::START,
+    JoypadBtnState::UP,
+    JoypadBtnState::DOWN,
+    JoypadBtnState::LEFT,
+    JoypadBtnState::RIGHT,
+];
+
+fn runtime_err(err: NesError) -> PyErr {
+    PyRuntimeError::new_err(format!("{err:#}"))
+}
+
+fn slot(slot: u8) -> PyResult<Slot> {
+    match slot {
+        1 => Ok(Slot::One),
+        2 => Ok(Slot::Two),
+        3 => Ok(Slot::Three),
+        4 => Ok(Slot::Four),
+        _ => Err(PyValueError::new_err(format!(
+            "invalid slot {slot}. valid options: 1, 2, 3, or 4"
+        ))),
+    }
+}
+
+fn button(name: &str) -> PyResult<JoypadBtnState> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "a" => JoypadBtnState::A,
+        "b" => JoypadBtnState::B,
+        "select" => JoypadBtnState::SELECT,
+        "start" => JoypadBtnState::START,
+        "up" => JoypadBtnState::UP,
+        "down" => JoypadBtnState::DOWN,
+        "left" => JoypadBtnState::LEFT,
+        "right" => JoypadBtnState::RIGHT,
+        "turbo_a" => JoypadBtnState::TURBO_A,
+        "turbo_b" => JoypadBtnState::TURBO_B,
+        _ => return Err(PyValueError::new_err(format!("invalid button `{name}`"))),
+    })
+}
+
+/// A headless NES.
+///
+/// `ram_state` sets the startup contents of RAM and is one of `all_zeros`, `all_ones` or
+/// `random`. Runs are deterministic unless RAM starts random.
+#[pyclass(unsendable)]
+#[must_use]
+struct ControlDeck {
+    deck: control_deck::ControlDeck,
+}
+
+#[pymethods]
+impl ControlDeck {
+    #[new]
+    #[pyo3(signature = (ram_state = "all_zeros"))]
+    fn new(ram_state: &str) -> PyResult<Self> {
+        let ram_state = ram_state
+            .parse::<RamState>()
+            .map_err(PyValueError::new_err)?;
+        let mut deck = control_deck::ControlDeck::new(ram_state);
+        // Observations are the raw palette output, without filter noise
+        deck.set_filter(VideoFilter::Pixellate);
+        Ok(Self { deck })
+    }
+
+    /// Frame width in pixels.
+    #[classattr]
+    const WIDTH: u32 = Ppu::WIDTH;
+
+    /// Frame height in pixels.
+    #[classattr]
+    const HEIGHT: u32 = Ppu::HEIGHT;
+
+    /// Loads an iNES or NES 2.0 ROM file and powers on.
+    fn load_rom(&mut self, path: PathBuf) -> PyResult<()> {
+        let mut rom = BufReader::new(File::open(&path)?);
+        self.deck
+            .load_rom(path.to_string_lossy(), &mut rom)
+            .map_err(runtime_err)
+    }
+
+    /// Presses the reset button, or power cycles with `hard`.
+    #[pyo3(signature = (hard = false))]
+    fn reset(&mut self, hard: bool) {
+        self.deck.reset(if hard { Kind::Hard } else { Kind::Soft });
+    }
+
+    /// Runs until the next frame is complete. Audio samples are discarded.
+    fn step_frame(&mut self) -> PyResult<()> {
+        self.deck.clock_frame().map_err(runtime_err)?;
+        self.deck.clear_audio_samples();
+        Ok(())
+    }
+
+    /// Runs `count` frames with the current joypad state.
+    fn step_frames(&mut self, count: u32) -> PyResult<()> {
+        for _ in 0..count {
+            self.step_frame()?;
+        }
+        Ok(())
+    }
+
+    /// Number of frames run since power on.
+    #[getter]
+    fn frame_number(&self) -> u32 {
+        self.deck.frame_number()
+    }
+
+    /// Presses or releases a button on the joypad in `slot`, numbered from 1. Buttons are `a`,
+    /// `b`, `select`, `start`, `up`, `down`, `left`, `right`, `turbo_a` and `turbo_b`.
+    fn set_button(&mut self, slot: u8, button: &str, pressed: bool) -> PyResult<()> {
+        let button = self::button(button)?;
+        self.deck
+            .joypad_mut(self::slot(slot)?)
+            .set_button(button, pressed);
+        Ok(())
+    }
+
+    /// Sets every button on the joypad in `slot` from a bitmask, with bits 0 to 7 for A, B,
+    /// Select, Start, Up, Down, Left and Right, matching the order the NES reads them in.
+    fn set_joypad(&mut self, slot: u8, buttons: u8) -> PyResult<()> {
+        let joypad = self.deck.joypad_mut(self::slot(slot)?);
+        for (i, button) in BUTTONS.into_iter().enumerate() {
+            joypad.set_button(button, buttons & (1 << i) != 0);
+        }
+        Ok(())
+    }
+
+    /// Bitmask of the buttons held on the joypad in `slot`, in the same order as `set_joypad`.
+    fn joypad(&mut self, slot: u8) -> PyResult<u8> {
+        let joypad = self.deck.joypad_mut(self::slot(slot)?);
+        Ok(BUTTONS
+            .into_iter()
+            .enumerate()
+            .fold(0, |buttons, (i, button)| {
+                buttons | (u8::from(joypad.button(button)) << i)
+            }))
+    }
+
+    /// The last frame as an RGBA `uint8` array shaped `(HEIGHT, WIDTH, 4)`.
+    fn frame<'py>(&mut self, py: Python<'py>) -> PyResult<&'py PyArray3<u8>> {
+        let frame = Array3::from_shape_vec(
+            (Ppu::HEIGHT as usize, Ppu::WIDTH as usize, 4),
+            self.deck.frame_buffer().to_vec(),
+        )
+        .map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+        Ok(frame.into_pyarray(py))
+    }
+
+    /// Reads `length` bytes from the CPU bus starting at `addr`, without side effects such as
+    /// clearing PPU status flags.
+    #[pyo3(signature = (addr, length = 1))]
+    fn read_memory<'py>(&self, py: Python<'py>, addr: u16, length: u16) -> &'py PyBytes {
+        let cpu = self.deck.cpu();
+        let data = (0..length)
+            .map(|offset| cpu.peek(addr.wrapping_add(offset), Access::Dummy))
+            .collect::<Vec<_>>();
+        PyBytes::new(py, &data)
+    }
+
+    /// Writes `data` to the CPU bus starting at `addr`.
+    fn write_memory(&mut self, addr: u16, data: &[u8]) {
+        let cpu = self.deck.cpu_mut();
+        for (offset, &val) in (0..=u16::MAX).zip(data) {
+            cpu.poke(addr.wrapping_add(offset), val);
+        }
+    }
+
+    /// The console state, to restore with `load_state`.
+    fn save_state<'py>(&self, py: Python<'py>) -> PyResult<&'py PyBytes> {
+        let data = bincode::serialize(self.deck.cpu())
+            .map_err(|err| PyRuntimeError::new_err(format!("failed to save state: {err}")))?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    /// Restores a state returned by `save_state` for the same ROM.
+    fn load_state(&mut self, data: &[u8]) -> PyResult<()> {
+        let cpu = bincode::deserialize::<Cpu>(data)
+            .map_err(|err| PyValueError::new_err(format!("invalid state: {err}")))?;
+        self.deck.try_load_cpu(cpu).map_err(runtime_err)
+    }
+}
+
+/// Python bindings for the `TetaNES` emulation core.
+#[pymodule]
+fn tetanes(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<ControlDeck>()?;
+    Ok(())
+}
@@ -0,0 +1,25 @@
+"""Smoke test for the bindings, run with `pytest` after building them with maturin."""
+
+from pathlib import Path
+
+import tetanes
+
+ROM = Path(__file__).resolve().parents[2] / "test_roms" / "cpu" / "branch_basics.nes"
+
+
+def test_step_frame_and_read_framebuffer():
+    nes = tetanes.ControlDeck()
+    nes.load_rom(str(ROM))
+    start = nes.frame_number
+
+    nes.step_frame()
+    assert nes.frame_number == start + 1
+
+    frame = nes.frame()
+    assert frame.shape == (240, 256, 4)
+    assert frame.dtype.name == "uint8"
+
+    # The test ROM prints its results, so the screen isn't one solid color by then
+    nes.step_frames(60)
+    pixels = nes.frame()[:, :, :3].reshape(-1, 3)
+    assert len({tuple(pixel) for pixel in pixels}) > 1
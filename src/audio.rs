@@ -1,4 +1,9 @@
-use crate::{audio::filter::Filter, NesResult};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::audio::output::{AudioBackend, AudioOutput};
+use crate::{
+    audio::{equalizer::Equalizer, filter::Filter},
+    NesResult,
+};
 use anyhow::anyhow;
 #[cfg(not(target_arch = "wasm32"))]
 use pix_engine::prelude::*;
@@ -7,14 +12,12 @@ use ringbuf::{Consumer, HeapRb, Producer, SharedRb};
 use std::time::Duration;
 use std::{fmt, mem::MaybeUninit, sync::Arc};
 
-pub mod filter;
-pub mod window_sinc;
+pub use tetanes_core::audio::{dpcm, equalizer, filter, wav, window_sinc, Audio};
 
-type RbRef = Arc<SharedRb<f32, Vec<MaybeUninit<f32>>>>;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod output;
 
-pub trait Audio {
-    fn output(&self) -> f32;
-}
+type RbRef = Arc<SharedRb<f32, Vec<MaybeUninit<f32>>>>;
 
 pub struct NesAudioCallback {
     initialized: bool,
@@ -82,20 +85,36 @@ impl fmt::Debug for NesAudioCallback {
     }
 }
 
+/// A second output device that plays a copy of the mixed audio at its own volume, like a virtual
+/// device for streaming alongside headphones.
+#[cfg(not(target_arch = "wasm32"))]
+struct SecondaryOutput {
+    output: AudioOutput,
+    producer: Producer<f32, RbRef>,
+    volume: f32,
+}
+
 #[must_use]
 pub struct AudioMixer {
     #[cfg(not(target_arch = "wasm32"))]
-    device: Option<AudioDevice<NesAudioCallback>>,
+    output: Option<AudioOutput>,
+    #[cfg(not(target_arch = "wasm32"))]
+    secondary: Option<SecondaryOutput>,
     producer: Producer<f32, RbRef>,
     consumer: Option<Consumer<f32, RbRef>>,
     input_frequency: f32,
     output_frequency: f32,
+    speed: f32,
     decim_ratio: f32,
     pitch_ratio: f32,
     fraction: f32,
     avg: f32,
     count: f32,
+    target_latency: f32,
+    fill_level: f32,
+    volume: f32,
     filters: [Filter; 3],
+    equalizer: Equalizer,
 }
 
 impl AudioMixer {
@@ -104,53 +123,153 @@ impl AudioMixer {
         let (producer, consumer) = buffer.split();
         Self {
             #[cfg(not(target_arch = "wasm32"))]
-            device: None,
+            output: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            secondary: None,
             producer,
             consumer: Some(consumer),
             input_frequency,
             output_frequency,
+            speed: 1.0,
             decim_ratio: input_frequency / output_frequency,
             pitch_ratio: 1.0,
             fraction: 0.0,
             avg: 0.0,
             count: 0.0,
-            filters: [
-                Filter::high_pass(output_frequency, 90.0, 1500.0),
-                Filter::high_pass(output_frequency, 440.0, 1500.0),
-                // Should be 14k, but this allows 2X speed within the Nyquist limit
-                Filter::low_pass(output_frequency, 12_000.0, 1500.0),
-            ],
+            target_latency: 0.0,
+            fill_level: 0.0,
+            volume: 1.0,
+            filters: Self::filters(output_frequency),
+            equalizer: Equalizer::new(output_frequency, 0.0, 0.0),
         }
     }
 
+    fn filters(output_frequency: f32) -> [Filter; 3] {
+        [
+            Filter::high_pass(output_frequency, 90.0, 1500.0),
+            Filter::high_pass(output_frequency, 440.0, 1500.0),
+            // Should be 14k, but this allows 2X speed within the Nyquist limit
+            Filter::low_pass(output_frequency, 12_000.0, 1500.0),
+        ]
+    }
+
     #[must_use]
     pub const fn output_frequency(&self) -> f32 {
         self.output_frequency
     }
 
-    /// Opens audio callback device for playback
+    /// Opens audio callback device for playback using the given backend. If `device` is `None`,
+    /// the system default device is used. If the device plays at a different sample rate than
+    /// requested, the output frequency is changed to match it.
     ///
     /// # Errors
     ///
     /// This function will return an error if the audio device fails to be opened, or if
     /// `open_playback` is called more than once.
     #[cfg(not(target_arch = "wasm32"))]
-    pub fn open_playback(&mut self, s: &mut PixState) -> NesResult<()> {
+    pub fn open_playback(
+        &mut self,
+        s: &mut PixState,
+        backend: AudioBackend,
+        device: Option<&str>,
+    ) -> NesResult<()> {
         match self.consumer.take() {
             Some(consumer) => {
-                let spec = AudioSpecDesired {
-                    freq: Some(self.output_frequency as i32),
-                    channels: Some(1),
-                    samples: Some((self.capacity() / 2) as u16),
-                };
-                self.device =
-                    Some(s.open_playback(None, &spec, |_| NesAudioCallback::new(consumer))?);
+                // Close any existing device before opening a new one
+                self.output = None;
+                let output = AudioOutput::open(
+                    s,
+                    backend,
+                    device,
+                    self.output_frequency,
+                    self.capacity() / 2,
+                    NesAudioCallback::new(consumer),
+                )?;
+                if let Some(sample_rate) = output.sample_rate() {
+                    if (sample_rate - self.output_frequency).abs() > f32::EPSILON {
+                        self.set_output_frequency(sample_rate);
+                    }
+                }
+                self.output = Some(output);
                 Ok(())
             }
             None => Err(anyhow!("can only open_playback once")),
         }
     }
 
+    /// Opens a second playback device that receives a copy of the mixed output at `volume`,
+    /// replacing any already open. Only native devices can be selected, so this always uses the
+    /// [`AudioBackend::Cpal`] backend. The secondary device isn't used for dynamic rate control,
+    /// so samples are dropped if it falls behind.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the audio device fails to be opened or doesn't
+    /// support the output frequency of the primary device.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open_secondary_playback(
+        &mut self,
+        s: &mut PixState,
+        device: &str,
+        volume: f32,
+    ) -> NesResult<()> {
+        // Close any existing device before opening a new one
+        self.secondary = None;
+        let buffer = HeapRb::<f32>::new(self.capacity());
+        let (producer, consumer) = buffer.split();
+        let output = AudioOutput::open(
+            s,
+            AudioBackend::Cpal,
+            Some(device),
+            self.output_frequency,
+            self.capacity() / 2,
+            NesAudioCallback::new(consumer),
+        )?;
+        if let Some(sample_rate) = output.sample_rate() {
+            if (sample_rate - self.output_frequency).abs() > f32::EPSILON {
+                return Err(anyhow!(
+                    "audio device {device:?} doesn't support {} Hz",
+                    self.output_frequency
+                ));
+            }
+        }
+        self.secondary = Some(SecondaryOutput {
+            output,
+            producer,
+            volume: volume.clamp(0.0, 1.0),
+        });
+        Ok(())
+    }
+
+    #[inline]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn close_secondary_playback(&mut self) {
+        self.secondary = None;
+    }
+
+    /// Sets the volume of the secondary output, independent of the master volume.
+    #[inline]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_secondary_volume(&mut self, volume: f32) {
+        if let Some(ref mut secondary) = self.secondary {
+            secondary.volume = volume.clamp(0.0, 1.0);
+        }
+    }
+
+    /// Whether the playback device was lost or the system default device changed and playback
+    /// should be reopened.
+    #[inline]
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn needs_reconnect(&mut self) -> bool {
+        self.output
+            .as_mut()
+            .map_or(false, AudioOutput::needs_reconnect)
+            || self
+                .secondary
+                .as_mut()
+                .map_or(false, |secondary| secondary.output.needs_reconnect())
+    }
+
     /// Returns audio buffer device for consuming audio samples.
     ///
     /// # Errors
@@ -170,9 +289,10 @@ impl AudioMixer {
     /// This function will return an error if the audio device fails to be opened.
     #[cfg(not(target_arch = "wasm32"))]
     pub fn reset(&mut self, buffer_size: usize) {
-        self.decim_ratio = self.input_frequency / self.output_frequency;
+        self.decim_ratio = self.input_frequency * self.speed / self.output_frequency;
         self.pitch_ratio = 1.0;
         self.fraction = 0.0;
+        self.fill_level = 0.0;
         let buffer = HeapRb::<f32>::new(buffer_size);
         let (producer, consumer) = buffer.split();
         self.producer = producer;
@@ -182,16 +302,22 @@ impl AudioMixer {
     #[inline]
     #[cfg(not(target_arch = "wasm32"))]
     pub fn resume(&mut self) {
-        if let Some(ref mut device) = self.device {
-            device.resume();
+        if let Some(ref mut output) = self.output {
+            output.resume();
+        }
+        if let Some(ref mut secondary) = self.secondary {
+            secondary.output.resume();
         }
     }
 
     #[inline]
     #[cfg(not(target_arch = "wasm32"))]
     pub fn pause(&mut self) {
-        if let Some(ref mut device) = self.device {
-            device.pause();
+        if let Some(ref mut output) = self.output {
+            output.pause();
+        }
+        if let Some(ref mut secondary) = self.secondary {
+            secondary.output.pause();
         }
     }
 
@@ -203,6 +329,52 @@ impl AudioMixer {
     #[inline]
     pub fn set_output_frequency(&mut self, output_frequency: f32) {
         self.output_frequency = output_frequency;
+        self.filters = Self::filters(output_frequency);
+        self.equalizer = Equalizer::new(
+            output_frequency,
+            self.equalizer.low_gain(),
+            self.equalizer.high_gain(),
+        );
+    }
+
+    /// Sets the emulation speed multiplier. Emulation generates input samples `speed` times
+    /// faster than real time while the output device keeps its rate, so each output sample
+    /// covers proportionally more input samples.
+    #[inline]
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.max(f32::EPSILON);
+    }
+
+    /// Sets the master volume applied after mixing, from `0.0` (muted) to `1.0` (full volume).
+    #[inline]
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    /// Sets the low and high shelf equalizer gains in decibels.
+    #[inline]
+    pub fn set_equalizer(&mut self, low_gain: f32, high_gain: f32) {
+        self.equalizer = Equalizer::new(self.output_frequency, low_gain, high_gain);
+    }
+
+    /// Sets the target output latency in seconds that dynamic rate control steers the buffer
+    /// fill level towards. A value of `0.0` targets a half-full buffer.
+    #[inline]
+    pub fn set_target_latency(&mut self, seconds: f32) {
+        self.target_latency = seconds.max(0.0);
+    }
+
+    /// Number of buffered samples dynamic rate control aims to keep queued, accounting for
+    /// the delay already introduced by the FIR filters.
+    #[must_use]
+    pub fn target_fill(&self) -> f32 {
+        let capacity = self.producer.capacity() as f32;
+        if self.target_latency <= 0.0 {
+            return capacity / 2.0;
+        }
+        let filter_latency: usize = self.filters.iter().map(Filter::latency).sum();
+        (self.target_latency * self.output_frequency - filter_latency as f32)
+            .clamp(capacity * 0.1, capacity * 0.9)
     }
 
     #[inline]
@@ -211,6 +383,20 @@ impl AudioMixer {
         self.producer.len()
     }
 
+    /// Whether an output device is open and consuming samples.
+    #[inline]
+    #[must_use]
+    pub fn is_open(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.output.is_some()
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.consumer.is_none()
+        }
+    }
+
     #[inline]
     #[must_use]
     pub fn is_empty(&self) -> bool {
@@ -231,6 +417,14 @@ impl AudioMixer {
 
     /// Outputs audio using multi-rate-control re-sampling.
     ///
+    /// When `dynamic_rate_control` is enabled, the resample ratio is nudged by up to
+    /// `max_delta` based on how far the smoothed buffer fill level is from `target_fill`, so
+    /// small differences between the display refresh and NES frame rate don't cause the
+    /// buffer to underrun or overflow.
+    ///
+    /// Resampling state carries over between calls, so samples can be consumed in slices of any
+    /// size without gaps or bursts at the boundaries.
+    ///
     /// Sources:
     /// - <https://near.sh/articles/audio/dynamic-rate-control>
     /// - <https://github.com/libretro/docs/blob/master/archive/ratecontrol.pdf>
@@ -241,37 +435,78 @@ impl AudioMixer {
         max_delta: f32,
     ) -> usize {
         self.pitch_ratio = if dynamic_rate_control {
+            // Smooth out jitter from frame timing so the ratio doesn't audibly wobble
             let size = self.producer.len() as f32;
-            let capacity = self.producer.capacity() as f32;
-            ((capacity - 2.0 * size) / capacity).mul_add(max_delta, 1.0)
+            self.fill_level = if self.fill_level > 0.0 {
+                0.1f32.mul_add(size - self.fill_level, self.fill_level)
+            } else {
+                size
+            };
+            let target = self.target_fill();
+            ((target - self.fill_level) / target)
+                .clamp(-1.0, 1.0)
+                .mul_add(max_delta, 1.0)
         } else {
             1.0
         };
-        self.decim_ratio = self.input_frequency / (self.output_frequency * self.pitch_ratio);
+        self.decim_ratio =
+            self.input_frequency * self.speed / (self.output_frequency * self.pitch_ratio);
         let mut sample_count = 0;
         for sample in samples {
             self.avg += *sample;
             self.count += 1.0;
             while self.fraction <= 0.0 {
-                let sample = self
-                    .filters
-                    .iter_mut()
-                    .fold(self.avg / self.count, |sample, filter| filter.apply(sample));
-                if self.producer.push(sample).is_err() {
+                let sample = self.next_sample();
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Some(ref mut secondary) = self.secondary {
+                    // Dropped if full, only the primary output paces emulation
+                    let _ = secondary.producer.push(secondary.volume * sample);
+                }
+                if self.producer.push(self.volume * sample).is_err() {
                     #[cfg(not(target_arch = "wasm32"))]
                     {
                         std::thread::sleep(Duration::from_micros(10));
                     }
                 }
-                self.avg = 0.0;
-                self.count = 0.0;
                 sample_count += 1;
-                self.fraction += self.decim_ratio;
             }
             self.fraction -= 1.0;
         }
         sample_count
     }
+
+    /// Resamples `samples` at a fixed rate and appends them to `output`, bypassing the output
+    /// buffer.
+    ///
+    /// Unlike `consume`, samples are never dropped when a buffer fills up and no rate control is
+    /// applied, so the same input always produces bit-identical output. Used for headless
+    /// rendering where there's no audio device to keep pace with.
+    pub fn resample(&mut self, samples: &[f32], output: &mut Vec<f32>) {
+        self.pitch_ratio = 1.0;
+        self.decim_ratio = self.input_frequency * self.speed / self.output_frequency;
+        for sample in samples {
+            self.avg += *sample;
+            self.count += 1.0;
+            while self.fraction <= 0.0 {
+                let sample = self.next_sample();
+                output.push(self.volume * sample);
+            }
+            self.fraction -= 1.0;
+        }
+    }
+
+    /// Filters the input samples averaged since the last output sample into the next output
+    /// sample and advances the resampler.
+    fn next_sample(&mut self) -> f32 {
+        let sample = self
+            .filters
+            .iter_mut()
+            .fold(self.avg / self.count, |sample, filter| filter.apply(sample));
+        self.avg = 0.0;
+        self.count = 0.0;
+        self.fraction += self.decim_ratio;
+        self.equalizer.apply(sample)
+    }
 }
 
 impl fmt::Debug for AudioMixer {
@@ -281,10 +516,63 @@ impl fmt::Debug for AudioMixer {
             .field("producer_capacity", &self.producer.capacity())
             .field("input_frequency", &self.input_frequency)
             .field("output_frequency", &self.output_frequency)
+            .field("speed", &self.speed)
             .field("decim_ratio", &self.decim_ratio)
             .field("pitch_ratio", &self.pitch_ratio)
             .field("fraction", &self.fraction)
+            .field("target_latency", &self.target_latency)
+            .field("fill_level", &self.fill_level)
+            .field("volume", &self.volume)
             .field("filters", &self.filters)
+            .field("equalizer", &self.equalizer)
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NES_CLOCK_RATE: f32 = 1_789_773.0;
+
+    #[test]
+    fn consume_in_slices() {
+        // About one NTSC frame of CPU cycles
+        let samples = vec![0.5; 29_781];
+        let mut batched = AudioMixer::new(NES_CLOCK_RATE, 44_100.0, 4096);
+        let mut sliced = AudioMixer::new(NES_CLOCK_RATE, 44_100.0, 4096);
+        let batched_count = batched.consume(&samples, false, 0.0);
+        let sliced_count: usize = samples
+            .chunks(7)
+            .map(|chunk| sliced.consume(chunk, false, 0.0))
+            .sum();
+        assert_eq!(batched_count, sliced_count);
+
+        let mut fast = AudioMixer::new(NES_CLOCK_RATE, 44_100.0, 4096);
+        fast.set_speed(2.0);
+        let fast_count = fast.consume(&samples, false, 0.0);
+        assert!(fast_count.abs_diff(batched_count / 2) <= 1);
+    }
+
+    #[test]
+    fn resample_is_deterministic() {
+        let samples: Vec<f32> = (0..29_781).map(|i| (i as f32 * 0.01).sin()).collect();
+        let mut buffered = AudioMixer::new(NES_CLOCK_RATE, 48_000.0, 4096);
+        let mut callback = buffered.open_callback().expect("callback");
+        let count = buffered.consume(&samples, false, 0.0);
+        let mut expected = vec![0.0; count];
+        callback.read(&mut expected);
+
+        let resample = || {
+            let mut mixer = AudioMixer::new(NES_CLOCK_RATE, 48_000.0, 4096);
+            let mut output = vec![];
+            for chunk in samples.chunks(1000) {
+                mixer.resample(chunk, &mut output);
+            }
+            output
+        };
+        let output = resample();
+        assert_eq!(output, expected);
+        assert_eq!(output, resample());
+    }
+}
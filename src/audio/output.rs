@@ -0,0 +1,299 @@
+//! Audio output backends.
+//!
+//! Playback can either go through SDL, which the rest of the frontend already uses, or through
+//! `cpal`, which talks to the native host API directly (WASAPI, ALSA/JACK, CoreAudio) and supports
+//! enumerating and selecting specific output devices. SDL always plays on the system default
+//! device.
+
+use crate::{audio::NesAudioCallback, NesResult};
+use anyhow::{anyhow, Context};
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use pix_engine::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often the default output device is polled for changes.
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Audio output backend.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub enum AudioBackend {
+    #[default]
+    Sdl,
+    Cpal,
+}
+
+impl AudioBackend {
+    #[must_use]
+    pub const fn as_slice() -> &'static [Self] {
+        &[Self::Sdl, Self::Cpal]
+    }
+}
+
+impl AsRef<str> for AudioBackend {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Sdl => "SDL",
+            Self::Cpal => "Native",
+        }
+    }
+}
+
+impl From<usize> for AudioBackend {
+    fn from(value: usize) -> Self {
+        if value == 1 {
+            Self::Cpal
+        } else {
+            Self::Sdl
+        }
+    }
+}
+
+/// Lists output device names available to `backend`. SDL only supports the default device, so
+/// an empty list is returned.
+#[must_use]
+pub fn output_devices(backend: AudioBackend) -> Vec<String> {
+    match backend {
+        AudioBackend::Sdl => vec![],
+        AudioBackend::Cpal => cpal::default_host()
+            .output_devices()
+            .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+            .unwrap_or_else(|err| {
+                log::error!("failed to enumerate audio devices: {err:?}");
+                vec![]
+            }),
+    }
+}
+
+fn default_device_name() -> Option<String> {
+    cpal::default_host()
+        .default_output_device()
+        .and_then(|device| device.name().ok())
+}
+
+/// Picks a stream config for `device`, using `sample_rate` and a fixed `buffer_size` only when
+/// the device supports them. Many devices reject fixed buffer sizes or some sample rates, so
+/// this falls back to the default buffer size and the device's default sample rate.
+fn stream_config(
+    device: &cpal::Device,
+    sample_rate: f32,
+    buffer_size: usize,
+) -> NesResult<cpal::StreamConfig> {
+    let default = device
+        .default_output_config()
+        .context("failed to query audio device config")?;
+    let rate = cpal::SampleRate(sample_rate as u32);
+    let supported = match device.supported_output_configs() {
+        Ok(mut configs) => configs.find(|config| {
+            config.channels() == default.channels()
+                && config.sample_format() == cpal::SampleFormat::F32
+                && (config.min_sample_rate()..=config.max_sample_rate()).contains(&rate)
+        }),
+        Err(err) => {
+            log::warn!("failed to query supported audio configs: {err:?}");
+            None
+        }
+    };
+    let supported = supported.map_or_else(
+        || {
+            log::warn!(
+                "audio device doesn't support {sample_rate} Hz, using {} Hz",
+                default.sample_rate().0
+            );
+            default
+        },
+        |config| config.with_sample_rate(rate),
+    );
+    let buffer_size = buffer_size as u32;
+    let buffer_size = match *supported.buffer_size() {
+        cpal::SupportedBufferSize::Range { min, max } if (min..=max).contains(&buffer_size) => {
+            cpal::BufferSize::Fixed(buffer_size)
+        }
+        _ => cpal::BufferSize::Default,
+    };
+    Ok(cpal::StreamConfig {
+        buffer_size,
+        ..supported.config()
+    })
+}
+
+/// An open audio output stream.
+pub(crate) enum AudioOutput {
+    Sdl(AudioDevice<NesAudioCallback>),
+    Cpal {
+        stream: cpal::Stream,
+        /// Selected device, or `None` when following the system default.
+        device: Option<String>,
+        /// Name of the device that was actually opened.
+        opened: String,
+        /// Sample rate the device was opened with.
+        sample_rate: f32,
+        disconnected: Arc<AtomicBool>,
+        last_check: Instant,
+    },
+}
+
+impl AudioOutput {
+    /// Opens a mono output stream on `device`, or the default device if `None`. Native devices
+    /// that don't support `sample_rate` are opened at their default rate instead, see
+    /// [`AudioOutput::sample_rate`].
+    ///
+    /// # Errors
+    ///
+    /// If the device can't be found or the stream fails to open, an error is returned.
+    pub(crate) fn open(
+        s: &mut PixState,
+        backend: AudioBackend,
+        device: Option<&str>,
+        sample_rate: f32,
+        buffer_size: usize,
+        mut callback: NesAudioCallback,
+    ) -> NesResult<Self> {
+        match backend {
+            AudioBackend::Sdl => {
+                let spec = AudioSpecDesired {
+                    freq: Some(sample_rate as i32),
+                    channels: Some(1),
+                    samples: Some(buffer_size as u16),
+                };
+                Ok(Self::Sdl(s.open_playback(None, &spec, |_| callback)?))
+            }
+            AudioBackend::Cpal => {
+                let host = cpal::default_host();
+                let output = match device {
+                    Some(name) => host
+                        .output_devices()
+                        .context("failed to enumerate audio devices")?
+                        .find(|d| d.name().map_or(false, |n| n == name)),
+                    None => host.default_output_device(),
+                }
+                .ok_or_else(|| anyhow!("audio device {device:?} not found"))?;
+                let opened = output.name().unwrap_or_default();
+                let config = stream_config(&output, sample_rate, buffer_size)?;
+                let channels = config.channels;
+
+                // Output is mono, so duplicate each sample across every device channel
+                let mut mono = Vec::with_capacity(buffer_size);
+                let disconnected = Arc::new(AtomicBool::new(false));
+                let stream = output
+                    .build_output_stream(
+                        &config,
+                        move |out: &mut [f32], _| {
+                            mono.resize(out.len() / channels as usize, 0.0);
+                            callback.read(&mut mono);
+                            for (frame, sample) in out.chunks_mut(channels as usize).zip(&mono) {
+                                frame.fill(*sample);
+                            }
+                        },
+                        {
+                            let disconnected = Arc::clone(&disconnected);
+                            move |err| {
+                                log::error!("audio stream error: {err:?}");
+                                if matches!(err, cpal::StreamError::DeviceNotAvailable) {
+                                    disconnected.store(true, Ordering::Relaxed);
+                                }
+                            }
+                        },
+                        None,
+                    )
+                    .with_context(|| format!("failed to open audio device {opened:?}"))?;
+                // Match SDL, which opens devices paused. Not every host supports pausing.
+                if let Err(err) = stream.pause() {
+                    log::debug!("failed to pause audio stream: {err:?}");
+                }
+                Ok(Self::Cpal {
+                    stream,
+                    device: device.map(ToOwned::to_owned),
+                    opened,
+                    sample_rate: config.sample_rate.0 as f32,
+                    disconnected,
+                    last_check: Instant::now(),
+                })
+            }
+        }
+    }
+
+    /// The sample rate the output plays at, if it's known to differ from the requested rate.
+    /// SDL converts from the requested rate itself.
+    pub(crate) const fn sample_rate(&self) -> Option<f32> {
+        match self {
+            Self::Sdl(_) => None,
+            Self::Cpal { sample_rate, .. } => Some(*sample_rate),
+        }
+    }
+
+    pub(crate) fn resume(&mut self) {
+        match self {
+            Self::Sdl(device) => device.resume(),
+            Self::Cpal { stream, .. } => {
+                if let Err(err) = stream.play() {
+                    log::error!("failed to resume audio: {err:?}");
+                }
+            }
+        }
+    }
+
+    pub(crate) fn pause(&mut self) {
+        match self {
+            Self::Sdl(device) => device.pause(),
+            Self::Cpal { stream, .. } => {
+                if let Err(err) = stream.pause() {
+                    log::error!("failed to pause audio: {err:?}");
+                }
+            }
+        }
+    }
+
+    /// Whether the stream should be reopened, either because its device went away or because it
+    /// follows the system default and the default device changed.
+    pub(crate) fn needs_reconnect(&mut self) -> bool {
+        match self {
+            Self::Sdl(_) => false,
+            Self::Cpal {
+                device,
+                opened,
+                disconnected,
+                last_check,
+                ..
+            } => {
+                if disconnected.load(Ordering::Relaxed) {
+                    return true;
+                }
+                if device.is_some() || last_check.elapsed() < DEVICE_CHECK_INTERVAL {
+                    return false;
+                }
+                *last_check = Instant::now();
+                default_device_name().map_or(false, |name| name != *opened)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for AudioOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sdl(_) => f.debug_tuple("Sdl").finish(),
+            Self::Cpal {
+                device,
+                opened,
+                sample_rate,
+                disconnected,
+                ..
+            } => f
+                .debug_struct("Cpal")
+                .field("device", device)
+                .field("opened", opened)
+                .field("sample_rate", sample_rate)
+                .field("disconnected", disconnected)
+                .finish(),
+        }
+    }
+}
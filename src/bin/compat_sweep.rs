@@ -0,0 +1,67 @@
+use anyhow::bail;
+use std::{env, path::PathBuf};
+use structopt::StructOpt;
+use tetanes::{
+    compat::{self, SweepList, SweepStatus},
+    mem::RamState,
+    NesResult,
+};
+
+fn main() -> NesResult<()> {
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "info");
+    }
+    pretty_env_logger::init();
+
+    let opt = Opt::from_args();
+    let mut list = SweepList::from_path(&opt.list)?;
+    if let Some(frames) = opt.frames {
+        list.frames = frames;
+    }
+    // Random power-up RAM would make frame hashes differ between runs
+    let report = compat::run_sweep(&list, opt.ram_state.unwrap_or(RamState::AllZeros))?;
+    let (json, html) = report.save(&opt.output)?;
+
+    let failed = report.results.len() - report.count(SweepStatus::Booted);
+    println!(
+        "{} / {} booted, {} mismatched, {} black screens, {} crashed, {} failed to load",
+        report.count(SweepStatus::Booted),
+        report.results.len(),
+        report.count(SweepStatus::Mismatch),
+        report.count(SweepStatus::BlackScreen),
+        report.count(SweepStatus::Crashed),
+        report.count(SweepStatus::LoadFailed),
+    );
+    println!("Wrote {json:?} and {html:?}");
+    if opt.strict && failed > 0 {
+        bail!("{failed} ROMs failed the compatibility sweep");
+    }
+    Ok(())
+}
+
+#[derive(StructOpt, Debug)]
+#[must_use]
+struct Opt {
+    #[structopt(help = "A JSON list of ROMs, ROM directories and replays to sweep.")]
+    list: PathBuf,
+    #[structopt(
+        short = "o",
+        long = "output",
+        default_value = "compat_report",
+        help = "Output path for the `.json` and `.html` reports."
+    )]
+    output: PathBuf,
+    #[structopt(
+        short = "f",
+        long = "frames",
+        help = "Frames to run each ROM, overriding the list default."
+    )]
+    frames: Option<u32>,
+    #[structopt(
+        long = "ram_state",
+        help = "Choose power-up RAM state: `all_zeros` (default), `all_ones`, `random`."
+    )]
+    ram_state: Option<RamState>,
+    #[structopt(long = "strict", help = "Exit with an error if any ROM didn't boot.")]
+    strict: bool,
+}
@@ -0,0 +1,64 @@
+use anyhow::{anyhow, bail, Context};
+use std::{env, fs, path::PathBuf};
+use structopt::StructOpt;
+use tetanes::{determinism, mem::RamState, movie::Fm2Movie, NesResult};
+
+fn main() -> NesResult<()> {
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "warn");
+    }
+    pretty_env_logger::init();
+
+    let opt = Opt::from_args();
+    let rom = fs::read(&opt.rom).with_context(|| format!("failed to read rom {:?}", opt.rom))?;
+    let movie = opt
+        .replay
+        .as_ref()
+        .map(|replay| -> NesResult<Fm2Movie> {
+            fs::read_to_string(replay)
+                .with_context(|| format!("failed to read replay {replay:?}"))?
+                .parse()
+        })
+        .transpose()?;
+    let frames = opt
+        .frames
+        .or_else(|| movie.as_ref().map(|movie| movie.frames.len() as u32))
+        .ok_or_else(|| anyhow!("`--frames` is required without a replay"))?;
+
+    match determinism::check(&rom, movie.as_ref(), frames, opt.ram_state)? {
+        None => println!("{frames} frames matched"),
+        Some(divergence) => bail!(
+            "states diverged at frame {}: {:016X} != {:016X}",
+            divergence.frame,
+            divergence.first,
+            divergence.second
+        ),
+    }
+    Ok(())
+}
+
+#[derive(StructOpt, Debug)]
+#[must_use]
+/// Runs a ROM twice and compares the console state after every frame.
+struct Opt {
+    #[structopt(help = "The NES ROM to check.")]
+    rom: PathBuf,
+    #[structopt(
+        short = "r",
+        long = "replay",
+        help = "An FCEUX `.fm2` movie to play back for input."
+    )]
+    replay: Option<PathBuf>,
+    #[structopt(
+        short = "f",
+        long = "frames",
+        help = "Frames to run, defaults to the length of the replay."
+    )]
+    frames: Option<u32>,
+    #[structopt(
+        long = "ram_state",
+        default_value = "all_zeros",
+        help = "Choose power-up RAM state: `all_zeros` (default), `all_ones`, `random`."
+    )]
+    ram_state: RamState,
+}
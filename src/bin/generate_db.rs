@@ -11,14 +11,14 @@ use std::{
 use structopt::StructOpt;
 use tetanes::{cart::Cart, mem::RamState, NesResult};
 
-const GAME_DB: &str = "config/game_database.txt";
+const GAME_DB: &str = "tetanes-core/config/game_database.txt";
 
 fn main() -> NesResult<()> {
     let opt = Opt::from_args();
     let path = opt
         .path
         .unwrap_or_else(|| env::current_dir().unwrap_or_default());
-    let header = "# Fields: Hash, Region, Board, PCB, Chip, Mapper, PrgRomSize, ChrRomSize, ChrRamSize, PrgRamSize, Battery, Mirroring, SubMapper, Title";
+    let header = "# Fields: Hash, Region, Board, PCB, Chip, Mapper, PrgRomSize, ChrRomSize, ChrRamSize, PrgRamSize, Battery, Mirroring, SubMapper, Title, NativeTitle";
     if path.is_dir() {
         let mut db_file =
             BufWriter::new(File::create(GAME_DB).context("failed to open game_database.txt")?);
@@ -72,11 +72,17 @@ fn get_info<P: AsRef<Path>>(path: P) -> NesResult<(u64, String)> {
     let prg_rom_banks = cart.prg_ram().len() / (16 * 1024);
     let prg_ram_banks = cart.prg_ram().len() / (16 * 1024);
     let mirroring = cart.mirroring();
+    // Keep native titles already in the database
+    let native_title = cart
+        .title()
+        .and_then(|title| title.native.as_ref())
+        .map(|native| format!(",{native:?}"))
+        .unwrap_or_default();
 
     Ok((
         hash,
         format!(
-            "{},{},{},{},{},{},{},{},{},{},{},{:?},{},{:?}",
+            "{},{},{},{},{},{},{},{},{},{},{},{:?},{},{:?}{}",
             hash,
             region,
             board,
@@ -90,7 +96,8 @@ fn get_info<P: AsRef<Path>>(path: P) -> NesResult<(u64, String)> {
             cart.battery_backed(),
             mirroring,
             cart.submapper_num(),
-            filename
+            filename,
+            native_title,
         ),
     ))
 }
@@ -0,0 +1,458 @@
+//! Headless compatibility sweeps for release QA.
+//!
+//! Runs each ROM from a sweep list for a number of frames without opening a window, optionally
+//! playing back an FCEUX `.fm2` movie for input, and records whether the game booted, crashed or
+//! never drew anything but a blank screen. The hash of the last frame is recorded so it can be
+//! pinned in the sweep list and compared against on the next run to catch regressions.
+//!
+//! A sweep list is a JSON file:
+//!
+//! ```json
+//! {
+//!   "frames": 600,
+//!   "roms": [
+//!     { "path": "roms/" },
+//!     { "path": "roms/smb.nes", "frames": 1200, "replay": "movies/smb.fm2", "hash": 123 }
+//!   ]
+//! }
+//! ```
+//!
+//! Directory entries expand to every `.nes` file they contain using the default settings.
+
+use crate::{
+    cart::NesHeader,
+    common::{Kind, NesRegion, Regional, Reset},
+    control_deck::ControlDeck,
+    mem::RamState,
+    movie::Fm2Movie,
+    video::VideoFilter,
+    NesResult,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::hash_map::DefaultHasher,
+    ffi::OsStr,
+    fmt::Write as _,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::{BufReader, BufWriter},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+const DEFAULT_FRAMES: u32 = 600;
+
+/// A ROM to run as part of a sweep.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub struct SweepRom {
+    /// Path to a `.nes` ROM or a directory of ROMs.
+    pub path: PathBuf,
+    /// Frames to run, overriding the sweep default. Defaults to the replay length when a replay
+    /// is provided.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frames: Option<u32>,
+    /// An `.fm2` movie to play back for input.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay: Option<PathBuf>,
+    /// Expected hash of the last frame.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<u64>,
+}
+
+/// A list of ROMs to sweep.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub struct SweepList {
+    #[serde(default = "default_frames")]
+    pub frames: u32,
+    pub roms: Vec<SweepRom>,
+}
+
+const fn default_frames() -> u32 {
+    DEFAULT_FRAMES
+}
+
+impl SweepList {
+    /// Loads a sweep list from a JSON file. Relative ROM and replay paths are resolved from the
+    /// directory containing the list.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be read or is not a valid sweep list, an error is returned.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> NesResult<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+        let mut list: Self = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("invalid sweep list {path:?}"))?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        for rom in &mut list.roms {
+            rom.path = base.join(&rom.path);
+            if let Some(replay) = &mut rom.replay {
+                *replay = base.join(replay.as_path());
+            }
+        }
+        Ok(list)
+    }
+
+    /// Expands directory entries into an entry for each `.nes` file they contain.
+    ///
+    /// # Errors
+    ///
+    /// If a directory can't be read, an error is returned.
+    pub fn expand(&self) -> NesResult<Vec<SweepRom>> {
+        let mut roms = Vec::with_capacity(self.roms.len());
+        for rom in &self.roms {
+            if rom.path.is_dir() {
+                let mut paths = rom
+                    .path
+                    .read_dir()
+                    .with_context(|| format!("failed to read directory {:?}", rom.path))?
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension() == Some(OsStr::new("nes")))
+                    .collect::<Vec<_>>();
+                paths.sort();
+                roms.extend(paths.into_iter().map(|path| SweepRom {
+                    path,
+                    frames: rom.frames,
+                    ..SweepRom::default()
+                }));
+            } else {
+                roms.push(rom.clone());
+            }
+        }
+        Ok(roms)
+    }
+}
+
+/// Outcome of running a single ROM.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub enum SweepStatus {
+    /// Drew a non-blank frame and ran every frame without crashing.
+    Booted,
+    /// Last frame doesn't match the expected hash.
+    Mismatch,
+    /// Ran every frame, but never drew anything other than a blank screen.
+    BlackScreen,
+    /// Emulation failed with an error or panic.
+    Crashed,
+    /// The ROM or replay failed to load, including unsupported mappers.
+    LoadFailed,
+}
+
+impl SweepStatus {
+    #[must_use]
+    pub const fn passed(self) -> bool {
+        matches!(self, Self::Booted)
+    }
+
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Booted => "Booted",
+            Self::Mismatch => "Mismatch",
+            Self::BlackScreen => "Black Screen",
+            Self::Crashed => "Crashed",
+            Self::LoadFailed => "Load Failed",
+        }
+    }
+}
+
+/// Result of running a single ROM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub struct SweepResult {
+    pub path: PathBuf,
+    pub mapper: Option<String>,
+    pub status: SweepStatus,
+    pub frames_run: u32,
+    /// First frame that wasn't blank.
+    pub first_visible_frame: Option<u32>,
+    pub hash: Option<u64>,
+    pub expected_hash: Option<u64>,
+    pub message: Option<String>,
+    pub duration_ms: u128,
+}
+
+/// Results of a sweep.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub struct SweepReport {
+    pub version: String,
+    pub date: String,
+    pub results: Vec<SweepResult>,
+}
+
+/// Hash a frame the same way the ROM test snapshots do.
+pub(crate) fn frame_hash(frame: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    frame.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Whether every pixel in an RGBA frame is the same color.
+fn is_blank(frame: &[u8]) -> bool {
+    frame
+        .chunks_exact(4)
+        .all(|pixel| pixel == frame.get(..4).unwrap_or_default())
+}
+
+pub(crate) fn panic_message(err: &(dyn std::any::Any + Send)) -> String {
+    err.downcast_ref::<&str>()
+        .map(|s| (*s).to_string())
+        .or_else(|| err.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+/// Runs a single ROM headlessly.
+pub fn run_rom(rom: &SweepRom, default_frames: u32, ram_state: RamState) -> SweepResult {
+    let start = Instant::now();
+    let mut result = SweepResult {
+        path: rom.path.clone(),
+        mapper: NesHeader::from_path(&rom.path)
+            .ok()
+            .map(|header| header.mapper_board().to_string()),
+        status: SweepStatus::LoadFailed,
+        frames_run: 0,
+        first_visible_frame: None,
+        hash: None,
+        expected_hash: rom.hash,
+        message: None,
+        duration_ms: 0,
+    };
+
+    let load = || -> NesResult<(ControlDeck, Option<Fm2Movie>)> {
+        let movie = rom
+            .replay
+            .as_ref()
+            .map(|replay| -> NesResult<Fm2Movie> {
+                fs::read_to_string(replay)
+                    .with_context(|| format!("failed to read replay {replay:?}"))?
+                    .parse()
+            })
+            .transpose()?;
+        let mut control_deck = ControlDeck::new(ram_state);
+        let mut rom_file = BufReader::new(
+            File::open(&rom.path).with_context(|| format!("failed to open rom {:?}", rom.path))?,
+        );
+        control_deck.load_rom(rom.path.to_string_lossy(), &mut rom_file)?;
+        if movie.as_ref().map_or(false, |movie| movie.pal) {
+            control_deck.set_region(NesRegion::Pal);
+            control_deck.reset(Kind::Hard);
+        }
+        // Hash the raw palette output so results don't depend on filter noise
+        control_deck.set_filter(VideoFilter::Pixellate);
+        Ok((control_deck, movie))
+    };
+    let (mut control_deck, movie) = match panic::catch_unwind(AssertUnwindSafe(load)) {
+        Ok(Ok(loaded)) => loaded,
+        Ok(Err(err)) => {
+            result.message = Some(format!("{err:#}"));
+            result.duration_ms = start.elapsed().as_millis();
+            return result;
+        }
+        Err(err) => {
+            result.message = Some(panic_message(&*err));
+            result.duration_ms = start.elapsed().as_millis();
+            return result;
+        }
+    };
+
+    let frames = rom.frames.unwrap_or_else(|| {
+        movie
+            .as_ref()
+            .map_or(default_frames, |movie| movie.frames.len() as u32)
+    });
+    let run = panic::catch_unwind(AssertUnwindSafe(|| -> NesResult<()> {
+        for frame_number in 0..frames {
+            if let Some(frame) = movie
+                .as_ref()
+                .and_then(|movie| movie.frames.get(frame_number as usize))
+            {
+                frame.apply(&mut control_deck);
+            }
+            control_deck.clock_frame()?;
+            control_deck.clear_audio_samples();
+            result.frames_run = frame_number + 1;
+            if result.first_visible_frame.is_none() && !is_blank(control_deck.frame_buffer()) {
+                result.first_visible_frame = Some(frame_number);
+            }
+        }
+        Ok(())
+    }));
+    let crash = match run {
+        Ok(Ok(())) => None,
+        Ok(Err(err)) => Some(format!("{err:#}")),
+        Err(err) => Some(panic_message(&*err)),
+    };
+    result.hash = Some(frame_hash(control_deck.frame_buffer()));
+    result.status = if let Some(crash) = crash {
+        result.message = Some(crash);
+        SweepStatus::Crashed
+    } else if result.first_visible_frame.is_none() {
+        SweepStatus::BlackScreen
+    } else if rom.hash.is_some() && rom.hash != result.hash {
+        SweepStatus::Mismatch
+    } else {
+        SweepStatus::Booted
+    };
+    result.duration_ms = start.elapsed().as_millis();
+    result
+}
+
+/// Runs every ROM in `list` headlessly.
+///
+/// # Errors
+///
+/// If a directory in the list can't be read, an error is returned. Failures running individual
+/// ROMs are recorded in the report instead.
+pub fn run_sweep(list: &SweepList, ram_state: RamState) -> NesResult<SweepReport> {
+    let roms = list.expand()?;
+    let total = roms.len();
+    let mut results = Vec::with_capacity(total);
+    for (i, rom) in roms.iter().enumerate() {
+        let result = run_rom(rom, list.frames, ram_state);
+        log::info!(
+            "[{}/{total}] {:?}: {}",
+            i + 1,
+            rom.path,
+            result.status.as_str()
+        );
+        results.push(result);
+    }
+    Ok(SweepReport {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        date: chrono::Local::now().to_rfc3339(),
+        results,
+    })
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl SweepReport {
+    /// Number of results with each status.
+    #[must_use]
+    pub fn count(&self, status: SweepStatus) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.status == status)
+            .count()
+    }
+
+    /// Renders the report as a standalone HTML page.
+    #[must_use]
+    pub fn to_html(&self) -> String {
+        const STATUSES: [SweepStatus; 5] = [
+            SweepStatus::Booted,
+            SweepStatus::Mismatch,
+            SweepStatus::BlackScreen,
+            SweepStatus::Crashed,
+            SweepStatus::LoadFailed,
+        ];
+        let mut html = String::new();
+        let _ = write!(
+            html,
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+            <title>TetaNES Compatibility Report</title>\n<style>\n\
+            body {{ font-family: sans-serif; }}\n\
+            table {{ border-collapse: collapse; }}\n\
+            th, td {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; }}\n\
+            .Booted {{ background: #cfc; }}\n\
+            .Mismatch, .BlackScreen {{ background: #ffc; }}\n\
+            .Crashed, .LoadFailed {{ background: #fcc; }}\n\
+            </style>\n</head>\n<body>\n\
+            <h1>TetaNES {} Compatibility Report</h1>\n<p>{}</p>\n<ul>\n",
+            escape_html(&self.version),
+            escape_html(&self.date),
+        );
+        for status in STATUSES {
+            let _ = writeln!(html, "<li>{}: {}</li>", status.as_str(), self.count(status));
+        }
+        html.push_str(
+            "</ul>\n<table>\n<tr><th>ROM</th><th>Mapper</th><th>Status</th><th>Frames</th>\
+            <th>First Visible Frame</th><th>Hash</th><th>Time (ms)</th><th>Message</th></tr>\n",
+        );
+        for result in &self.results {
+            let optional = |val: Option<String>| val.unwrap_or_default();
+            let _ = writeln!(
+                html,
+                "<tr class=\"{:?}\"><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td>\
+                <td>{}</td><td>{}</td><td>{}</td></tr>",
+                result.status,
+                escape_html(&result.path.to_string_lossy()),
+                escape_html(&optional(result.mapper.clone())),
+                result.status.as_str(),
+                result.frames_run,
+                optional(result.first_visible_frame.map(|frame| frame.to_string())),
+                optional(result.hash.map(|hash| hash.to_string())),
+                result.duration_ms,
+                escape_html(&optional(result.message.clone())),
+            );
+        }
+        html.push_str("</table>\n</body>\n</html>\n");
+        html
+    }
+
+    /// Writes the report as JSON and HTML to `output` with `.json` and `.html` extensions.
+    ///
+    /// # Errors
+    ///
+    /// If either file can't be written, an error is returned.
+    pub fn save<P: AsRef<Path>>(&self, output: P) -> NesResult<(PathBuf, PathBuf)> {
+        let output = output.as_ref();
+        if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).with_context(|| format!("failed to create {dir:?}"))?;
+        }
+        let json_path = output.with_extension("json");
+        let file = File::create(&json_path)
+            .with_context(|| format!("failed to create file {json_path:?}"))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .with_context(|| format!("failed to write {json_path:?}"))?;
+        let html_path = output.with_extension("html");
+        fs::write(&html_path, self.to_html())
+            .with_context(|| format!("failed to write {html_path:?}"))?;
+        Ok((json_path, html_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_sweep_list() {
+        let list: SweepList = serde_json::from_str(
+            r#"{ "roms": [{ "path": "a.nes" }, { "path": "b.nes", "frames": 10, "hash": 5 }] }"#,
+        )
+        .expect("valid sweep list");
+        assert_eq!(list.frames, DEFAULT_FRAMES);
+        assert_eq!(list.roms[0].frames, None);
+        assert_eq!(list.roms[1].frames, Some(10));
+        assert_eq!(list.roms[1].hash, Some(5));
+    }
+
+    #[test]
+    fn blank_frames() {
+        assert!(is_blank(&[0, 0, 0, 255, 0, 0, 0, 255]));
+        assert!(!is_blank(&[0, 0, 0, 255, 255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn missing_rom_fails_to_load() {
+        let rom = SweepRom {
+            path: PathBuf::from("missing.nes"),
+            ..SweepRom::default()
+        };
+        let result = run_rom(&rom, 1, RamState::default());
+        assert_eq!(result.status, SweepStatus::LoadFailed);
+        assert!(result.message.is_some());
+    }
+}
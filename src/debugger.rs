@@ -1,14 +1,56 @@
 use crate::mem::Access;
-use std::ops::RangeInclusive;
+use std::{fmt, ops::RangeInclusive};
+
+pub(crate) mod assembler;
+pub(crate) mod console;
+pub(crate) mod mem_search;
+pub(crate) mod reset_schedule;
+pub(crate) mod symbols;
+pub(crate) mod trace_diff;
+pub(crate) mod watch;
 
-// TODO: Use Address
-#[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) enum Address {
     Addr(u16),
     AddrRange(RangeInclusive<u16>),
 }
 
+impl Address {
+    #[inline]
+    #[must_use]
+    pub(crate) fn contains(&self, addr: u16) -> bool {
+        match self {
+            Self::Addr(a) => *a == addr,
+            Self::AddrRange(range) => range.contains(&addr),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Addr(addr) => write!(f, "${addr:04X}"),
+            Self::AddrRange(range) => write!(f, "${:04X}-${:04X}", range.start(), range.end()),
+        }
+    }
+}
+
+impl std::str::FromStr for Address {
+    type Err = std::num::ParseIntError;
+
+    /// Parses a hex address or inclusive address range, e.g. `$C000` or `0000-07FF`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |s: &str| u16::from_str_radix(s.trim().trim_start_matches('$'), 16);
+        match s.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                Ok(Self::AddrRange(start.min(end)..=start.max(end)))
+            }
+            None => Ok(Self::Addr(parse(s)?)),
+        }
+    }
+}
+
 // Conditions:
 // - A/X/Y/P/SP
 // - PC
@@ -29,4 +71,160 @@ pub(crate) struct Breakpoint {
     pub(crate) access: Vec<Access>,
     pub(crate) conditions: Vec<Condition>,
     pub(crate) enabled: bool,
+    /// Number of times this breakpoint has matched.
+    pub(crate) hit_count: u32,
+    /// Only break once `hit_count` reaches this value. `0` or `1` breaks on every hit.
+    pub(crate) break_after: u32,
+    /// Remove this breakpoint once it breaks.
+    pub(crate) temporary: bool,
+}
+
+impl Breakpoint {
+    pub(crate) fn new(addr: Address, access: Access) -> Self {
+        Self {
+            addr,
+            access: vec![access],
+            conditions: vec![],
+            enabled: true,
+            hit_count: 0,
+            break_after: 0,
+            temporary: false,
+        }
+    }
+
+    /// Records a hit, returning whether execution should break.
+    fn hit(&mut self) -> bool {
+        self.hit_count = self.hit_count.saturating_add(1);
+        self.hit_count >= self.break_after
+    }
+}
+
+/// Set of active breakpoints along with a list of addresses whose writes are never reported.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct Breakpoints {
+    pub(crate) list: Vec<Breakpoint>,
+    pub(crate) ignore: Vec<Address>,
+}
+
+impl Breakpoints {
+    /// Whether any breakpoint is enabled and needs checking.
+    #[inline]
+    #[must_use]
+    pub(crate) fn is_active(&self) -> bool {
+        self.list.iter().any(|bp| bp.enabled)
+    }
+
+    /// Whether any enabled breakpoint watches memory writes.
+    #[inline]
+    #[must_use]
+    pub(crate) fn watches_writes(&self) -> bool {
+        self.list
+            .iter()
+            .any(|bp| bp.enabled && bp.access.contains(&Access::Write))
+    }
+
+    #[inline]
+    pub(crate) fn add(&mut self, breakpoint: Breakpoint) {
+        self.list.push(breakpoint);
+    }
+
+    #[inline]
+    pub(crate) fn remove(&mut self, index: usize) {
+        if index < self.list.len() {
+            self.list.remove(index);
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub(crate) fn is_ignored(&self, addr: u16) -> bool {
+        self.ignore.iter().any(|ignore| ignore.contains(addr))
+    }
+
+    /// Resets hit counters for all breakpoints.
+    pub(crate) fn reset_hits(&mut self) {
+        for bp in &mut self.list {
+            bp.hit_count = 0;
+        }
+    }
+
+    /// Checks breakpoints against the next instruction address and memory written by the last
+    /// instruction, updating hit counters. Returns the matched address if execution should
+    /// break. Temporary breakpoints are removed once they break.
+    pub(crate) fn check(&mut self, pc: u16, writes: &[u16]) -> Option<u16> {
+        let mut result = None;
+        let ignore = &self.ignore;
+        self.list.retain_mut(|bp| {
+            if !bp.enabled {
+                return true;
+            }
+            let matched = if bp.access.contains(&Access::Execute) && bp.addr.contains(pc) {
+                Some(pc)
+            } else if bp.access.contains(&Access::Write) {
+                writes.iter().copied().find(|&addr| {
+                    bp.addr.contains(addr) && !ignore.iter().any(|ignore| ignore.contains(addr))
+                })
+            } else {
+                None
+            };
+            match matched {
+                Some(addr) if bp.hit() => {
+                    result = result.or(Some(addr));
+                    !bp.temporary
+                }
+                _ => true,
+            }
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_address() {
+        assert_eq!("$C000".parse(), Ok(Address::Addr(0xC000)));
+        assert_eq!("07ff-0000".parse(), Ok(Address::AddrRange(0x0000..=0x07FF)));
+        assert!("zz".parse::<Address>().is_err());
+    }
+
+    #[test]
+    fn break_after_hits() {
+        let mut bps = Breakpoints::default();
+        let mut bp = Breakpoint::new(Address::Addr(0x8000), Access::Execute);
+        bp.break_after = 3;
+        bps.add(bp);
+        assert_eq!(bps.check(0x8000, &[]), None);
+        assert_eq!(bps.check(0x8001, &[]), None);
+        assert_eq!(bps.check(0x8000, &[]), None);
+        assert_eq!(bps.check(0x8000, &[]), Some(0x8000));
+        assert_eq!(bps.list[0].hit_count, 3);
+    }
+
+    #[test]
+    fn temporary_breakpoint() {
+        let mut bps = Breakpoints::default();
+        let mut bp = Breakpoint::new(Address::Addr(0x8000), Access::Execute);
+        bp.temporary = true;
+        bps.add(bp);
+        assert_eq!(bps.check(0x8000, &[]), Some(0x8000));
+        assert!(bps.list.is_empty());
+    }
+
+    #[test]
+    fn ignored_writes() {
+        let mut bps = Breakpoints::default();
+        bps.add(Breakpoint::new(
+            Address::AddrRange(0x0000..=0x07FF),
+            Access::Write,
+        ));
+        bps.ignore.push(Address::Addr(0x0010));
+        assert_eq!(bps.check(0x8000, &[0x0010]), None);
+        assert_eq!(bps.check(0x8000, &[0x0010, 0x0200]), Some(0x0200));
+        assert_eq!(bps.check(0x8000, &[0x2000]), None);
+        assert_eq!(bps.list[0].hit_count, 1);
+    }
 }
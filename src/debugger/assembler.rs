@@ -0,0 +1,199 @@
+//! A 6502 assembler for patching code from the debugger console.
+//!
+//! Instructions use the usual syntax, like `LDA #$01`, `STA $0200,X` or `JMP ($FFFC)`, and several
+//! can be separated with `;`. Numbers are decimal, or hex with a `$` or `0x` prefix. Operands
+//! written with at most 2 hex digits, or below 256 in decimal, use zero page addressing when the
+//! instruction has it. Branches take the address to branch to.
+//!
+//! Official opcodes are preferred, but unofficial ones like `LAX` can be assembled too.
+
+use crate::cpu::{
+    instr::{AddrMode, Instr},
+    Cpu,
+};
+
+/// Assembles `text` to be placed at `pc`, returning the encoded bytes.
+pub(crate) fn assemble(text: &str, pc: u16) -> Result<Vec<u8>, String> {
+    let mut bytes = vec![];
+    for instr in text.split(';').map(str::trim).filter(|s| !s.is_empty()) {
+        let addr = pc.wrapping_add(bytes.len() as u16);
+        bytes.extend(assemble_instr(instr, addr).map_err(|err| format!("`{instr}`: {err}"))?);
+    }
+    if bytes.is_empty() {
+        return Err("expected an instruction".to_string());
+    }
+    Ok(bytes)
+}
+
+fn assemble_instr(text: &str, pc: u16) -> Result<Vec<u8>, String> {
+    let (mnemonic, operand) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let mnemonic = mnemonic.to_ascii_uppercase();
+    // BRK is listed as immediate, for its padding byte, but is usually written alone
+    if mnemonic == "BRK" && operand.trim().is_empty() {
+        return Ok(vec![0x00]);
+    }
+    let (modes, value) = parse_operand(operand)?;
+    for &mode in modes {
+        let instr = Cpu::INSTRUCTIONS
+            .iter()
+            .filter(|instr| instr.addr_mode() == mode && format!("{:?}", instr.op()) == mnemonic)
+            .min_by_key(|instr| instr.is_unofficial());
+        if let Some(instr) = instr {
+            return encode(instr, value, pc);
+        }
+    }
+    if Cpu::INSTRUCTIONS
+        .iter()
+        .any(|instr| format!("{:?}", instr.op()) == mnemonic)
+    {
+        Err(format!("{mnemonic} doesn't support this addressing mode"))
+    } else {
+        Err(format!("unknown instruction `{mnemonic}`"))
+    }
+}
+
+fn encode(instr: &Instr, value: u16, pc: u16) -> Result<Vec<u8>, String> {
+    let opcode = instr.opcode();
+    let [lo, hi] = value.to_le_bytes();
+    match instr.addr_mode() {
+        AddrMode::IMP | AddrMode::ACC => Ok(vec![opcode]),
+        AddrMode::IMM
+        | AddrMode::ZP0
+        | AddrMode::ZPX
+        | AddrMode::ZPY
+        | AddrMode::IDX
+        | AddrMode::IDY => {
+            if hi == 0 {
+                Ok(vec![opcode, lo])
+            } else {
+                Err(format!("${value:04X} doesn't fit in a byte"))
+            }
+        }
+        AddrMode::ABS | AddrMode::ABX | AddrMode::ABY | AddrMode::IND => Ok(vec![opcode, lo, hi]),
+        AddrMode::REL => {
+            let offset = i32::from(value) - (i32::from(pc) + 2);
+            i8::try_from(offset)
+                .map(|offset| vec![opcode, offset as u8])
+                .map_err(|_| format!("branch to ${value:04X} is out of range"))
+        }
+    }
+}
+
+/// Parses an operand into the addressing modes it could be, in order of preference, and its
+/// value.
+fn parse_operand(operand: &str) -> Result<(&'static [AddrMode], u16), String> {
+    let operand = operand
+        .replace(char::is_whitespace, "")
+        .to_ascii_uppercase();
+    if operand.is_empty() {
+        return Ok((&[AddrMode::IMP, AddrMode::ACC], 0));
+    } else if operand == "A" {
+        return Ok((&[AddrMode::ACC], 0));
+    } else if let Some(value) = operand.strip_prefix('#') {
+        return Ok((&[AddrMode::IMM], parse_number(value)?.0));
+    } else if let Some(value) = operand
+        .strip_prefix('(')
+        .and_then(|operand| operand.strip_suffix(",X)"))
+    {
+        return Ok((&[AddrMode::IDX], parse_number(value)?.0));
+    } else if let Some(value) = operand
+        .strip_prefix('(')
+        .and_then(|operand| operand.strip_suffix("),Y"))
+    {
+        return Ok((&[AddrMode::IDY], parse_number(value)?.0));
+    } else if let Some(value) = operand
+        .strip_prefix('(')
+        .and_then(|operand| operand.strip_suffix(')'))
+    {
+        return Ok((&[AddrMode::IND], parse_number(value)?.0));
+    }
+
+    if let Some(value) = operand.strip_suffix(",X") {
+        let (value, zero_page) = parse_number(value)?;
+        let modes: &[_] = match zero_page {
+            Some(true) => &[AddrMode::ZPX, AddrMode::ABX],
+            Some(false) => &[AddrMode::ABX, AddrMode::ZPX],
+            None => &[AddrMode::ABX],
+        };
+        Ok((modes, value))
+    } else if let Some(value) = operand.strip_suffix(",Y") {
+        let (value, zero_page) = parse_number(value)?;
+        let modes: &[_] = match zero_page {
+            Some(true) => &[AddrMode::ZPY, AddrMode::ABY],
+            Some(false) => &[AddrMode::ABY, AddrMode::ZPY],
+            None => &[AddrMode::ABY],
+        };
+        Ok((modes, value))
+    } else {
+        let (value, zero_page) = parse_number(&operand)?;
+        let modes: &[_] = match zero_page {
+            Some(true) => &[AddrMode::ZP0, AddrMode::ABS, AddrMode::REL],
+            Some(false) => &[AddrMode::ABS, AddrMode::REL, AddrMode::ZP0],
+            None => &[AddrMode::ABS, AddrMode::REL],
+        };
+        Ok((modes, value))
+    }
+}
+
+/// Parses a number, and whether it was written as zero page: `Some(true)` if written short,
+/// `Some(false)` if written long but still fits in zero page, or `None` if it doesn't fit.
+fn parse_number(text: &str) -> Result<(u16, Option<bool>), String> {
+    let (digits, radix, short) = match text.strip_prefix('$').or_else(|| text.strip_prefix("0X")) {
+        Some(digits) => (digits, 16, digits.len() <= 2),
+        None => (text, 10, true),
+    };
+    let value =
+        u16::from_str_radix(digits, radix).map_err(|_| format!("invalid number `{text}`"))?;
+    let zero_page = (value <= 0xFF).then_some(short);
+    Ok((value, zero_page))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assemble_addressing_modes() {
+        let tests: &[(&str, &[u8])] = &[
+            ("LDA #$01", &[0xA9, 0x01]),
+            ("lda $10", &[0xA5, 0x10]),
+            ("LDA $0010", &[0xAD, 0x10, 0x00]),
+            ("LDA $0200,X", &[0xBD, 0x00, 0x02]),
+            ("LDA $20, Y", &[0xB9, 0x20, 0x00]),
+            ("LDX $20,Y", &[0xB6, 0x20]),
+            ("STX $0020,Y", &[0x96, 0x20]),
+            ("LDA ($40,X)", &[0xA1, 0x40]),
+            ("LDA ($40),Y", &[0xB1, 0x40]),
+            ("JMP ($FFFC)", &[0x6C, 0xFC, 0xFF]),
+            ("JMP 49152", &[0x4C, 0x00, 0xC0]),
+            ("ASL", &[0x0A]),
+            ("ASL A", &[0x0A]),
+            ("NOP", &[0xEA]),
+            ("BRK", &[0x00]),
+            ("LAX $10", &[0xA7, 0x10]),
+            ("BNE $C010", &[0xD0, 0x0E]),
+            ("BEQ $BFF0", &[0xF0, 0xEE]),
+        ];
+        for &(text, bytes) in tests {
+            assert_eq!(assemble(text, 0xC000).as_deref(), Ok(bytes), "{text}");
+        }
+    }
+
+    #[test]
+    fn assemble_several() {
+        assert_eq!(
+            assemble("LDA #$01; STA $4016 ; BNE $C000", 0xC000),
+            Ok(vec![0xA9, 0x01, 0x8D, 0x16, 0x40, 0xD0, 0xF9])
+        );
+    }
+
+    #[test]
+    fn assemble_errors() {
+        assert!(assemble("", 0xC000).is_err());
+        assert!(assemble("FOO #1", 0xC000).is_err());
+        assert!(assemble("LDA #$100", 0xC000).is_err());
+        assert!(assemble("STA #1", 0xC000).is_err());
+        assert!(assemble("BNE $D000", 0xC000).is_err());
+        assert!(assemble("LDA ($40,Y)", 0xC000).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+//! Debugger console commands, for assembling code or writing bytes into memory, evaluating
+//! expressions and keeping patches that are re-applied on every reset.
+//!
+//! Writes to addresses mapped to PRG-ROM patch the ROM directly, so they stay patched across
+//! resets and can be exported as an [IPS](https://zerosoft.zophar.net/ips.php) patch. Other
+//! writes go through the CPU bus, so patches to RAM are written again after each reset.
+
+use crate::debugger::{assembler::assemble, watch::Expression};
+use std::{collections::BTreeMap, fmt, path::PathBuf, str::FromStr};
+
+/// Size of the iNES header before PRG-ROM in a ROM file. Trainers aren't supported.
+const NES_HEADER_LEN: usize = 16;
+/// IPS offsets are 24-bit.
+const IPS_MAX_OFFSET: usize = 0xFF_FFFF;
+
+pub(crate) const HELP: &[&str] = &[
+    "a <addr> <instr>[; <instr>...]  Assemble, e.g. a $C000 LDA #$01",
+    "w <addr> <bytes>  Write bytes, e.g. w $0300 EA EA",
+    "? <expr>  Evaluate, e.g. ? [$42]+X",
+    "patch <a or w command>  Write, and again after every reset",
+    "patches  List patches",
+    "unpatch <n>  Remove a patch, restoring PRG-ROM",
+    "run <path>  Run commands from a file, one per line",
+    "ips <path>  Export PRG-ROM patches as an IPS file",
+    "clear  Clear the console",
+];
+
+/// Where a patched byte was written.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Target {
+    /// An offset into PRG-ROM, and the byte that was there before.
+    PrgRom { offset: usize, original: u8 },
+    /// An address on the CPU bus.
+    Bus(u16),
+}
+
+/// Bytes to write starting at an address, from an `a` or `w` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct Patch {
+    text: String,
+    pub(crate) addr: u16,
+    pub(crate) bytes: Vec<u8>,
+    /// Where each byte was written, once applied.
+    pub(crate) targets: Vec<Target>,
+}
+
+impl Patch {
+    /// Bytes to write and the address to write each to.
+    pub(crate) fn writes(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        (0..)
+            .zip(&self.bytes)
+            .map(|(i, &val)| (self.addr.wrapping_add(i), val))
+    }
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl FromStr for Patch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (command, args) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let (addr, rest) = args
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("usage: {command} <addr> ..."))?;
+        let addr = parse_addr(addr)?;
+        let bytes = match command.to_ascii_lowercase().as_str() {
+            "a" => assemble(rest, addr)?,
+            "w" => parse_bytes(rest)?,
+            _ => return Err(format!("can't patch with `{command}`")),
+        };
+        Ok(Self {
+            text: s.to_string(),
+            addr,
+            bytes,
+            targets: vec![],
+        })
+    }
+}
+
+fn parse_addr(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches('$');
+    let digits = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+        .unwrap_or(digits);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address `{text}`"))
+}
+
+/// Parses hex bytes, optionally separated by spaces or commas and prefixed with `$`, like
+/// `DE AD BEEF` or `$DE,$AD`. Used for hex entry everywhere bytes are typed in.
+pub(crate) fn parse_bytes(text: &str) -> Result<Vec<u8>, String> {
+    let digits = text.replace(|c: char| c.is_whitespace() || c == '$' || c == ',', "");
+    if digits.is_empty() || digits.len() % 2 != 0 || !digits.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Err(format!("invalid hex bytes `{}`", text.trim()));
+    }
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap_or_default())
+        .collect())
+}
+
+/// A command entered in the debugger console.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) enum Command {
+    /// Writes bytes, keeping them as a patch if `keep` is set.
+    Write {
+        patch: Patch,
+        keep: bool,
+    },
+    Eval(Expression),
+    Patches,
+    Unpatch(usize),
+    Run(PathBuf),
+    ExportIps(PathBuf),
+    Clear,
+    Help,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (command, args) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let args = args.trim();
+        let path = || {
+            if args.is_empty() {
+                Err(format!("usage: {command} <path>"))
+            } else {
+                Ok(PathBuf::from(args))
+            }
+        };
+        match command.to_ascii_lowercase().as_str() {
+            "a" | "w" => Ok(Self::Write {
+                patch: s.parse()?,
+                keep: false,
+            }),
+            "?" => Ok(Self::Eval(args.parse()?)),
+            "patch" => Ok(Self::Write {
+                patch: args.parse()?,
+                keep: true,
+            }),
+            "patches" => Ok(Self::Patches),
+            "unpatch" => args
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| n.checked_sub(1))
+                .map(Self::Unpatch)
+                .ok_or_else(|| "usage: unpatch <n>".to_string()),
+            "run" => path().map(Self::Run),
+            "ips" => path().map(Self::ExportIps),
+            "clear" => Ok(Self::Clear),
+            "help" => Ok(Self::Help),
+            _ => Err(format!("unknown command `{command}`, try `help`")),
+        }
+    }
+}
+
+/// Patches kept for the loaded game.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct Patches {
+    pub(crate) list: Vec<Patch>,
+}
+
+impl Patches {
+    /// Patched PRG-ROM bytes by offset, later patches taking precedence.
+    pub(crate) fn prg_rom(&self) -> BTreeMap<usize, u8> {
+        let mut bytes = BTreeMap::new();
+        for patch in &self.list {
+            for (target, &val) in patch.targets.iter().zip(&patch.bytes) {
+                if let Target::PrgRom { offset, .. } = *target {
+                    bytes.insert(offset, val);
+                }
+            }
+        }
+        bytes
+    }
+
+    /// Encodes the patched PRG-ROM bytes as an IPS patch for the ROM file.
+    pub(crate) fn to_ips(&self) -> Vec<u8> {
+        let mut records: Vec<(usize, Vec<u8>)> = vec![];
+        for (offset, val) in self.prg_rom() {
+            let offset = NES_HEADER_LEN + offset;
+            match records.last_mut() {
+                Some((start, data))
+                    if *start + data.len() == offset && data.len() < usize::from(u16::MAX) =>
+                {
+                    data.push(val);
+                }
+                _ => records.push((offset, vec![val])),
+            }
+        }
+
+        let mut ips = b"PATCH".to_vec();
+        for (offset, data) in records {
+            if offset > IPS_MAX_OFFSET {
+                break;
+            }
+            ips.extend_from_slice(&(offset as u32).to_be_bytes()[1..]);
+            ips.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            ips.extend_from_slice(&data);
+        }
+        ips.extend_from_slice(b"EOF");
+        ips
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        let Ok(Command::Write { patch, keep }) = "patch a $C000 LDA #$01; NOP".parse() else {
+            panic!("expected a patch");
+        };
+        assert!(keep);
+        assert_eq!(patch.addr, 0xC000);
+        assert_eq!(patch.bytes, [0xA9, 0x01, 0xEA]);
+        assert_eq!(patch.to_string(), "a $C000 LDA #$01; NOP");
+
+        let Ok(Command::Write { patch, keep }) = "w 0300 EA $12,34".parse() else {
+            panic!("expected a write");
+        };
+        assert!(!keep);
+        assert_eq!(
+            patch.writes().collect::<Vec<_>>(),
+            [(0x0300, 0xEA), (0x0301, 0x12), (0x0302, 0x34)]
+        );
+
+        assert!(matches!(
+            "? [$42]+X".parse::<Command>(),
+            Ok(Command::Eval(_))
+        ));
+        assert_eq!("unpatch 2".parse::<Command>(), Ok(Command::Unpatch(1)));
+        assert_eq!(
+            "ips out.ips".parse::<Command>(),
+            Ok(Command::ExportIps(PathBuf::from("out.ips")))
+        );
+        assert!("unpatch 0".parse::<Command>().is_err());
+        assert!("w $0300 E".parse::<Command>().is_err());
+        assert!("patch ? 1".parse::<Command>().is_err());
+        assert!("ips".parse::<Command>().is_err());
+        assert!("foo".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn export_ips() {
+        let patch = |targets: &[usize], bytes: &[u8]| Patch {
+            text: String::new(),
+            addr: 0x8000,
+            bytes: bytes.to_vec(),
+            targets: targets
+                .iter()
+                .map(|&offset| Target::PrgRom {
+                    offset,
+                    original: 0,
+                })
+                .collect(),
+        };
+        let mut patches = Patches::default();
+        patches.list.push(patch(&[0x10, 0x11, 0x12], &[1, 2, 3]));
+        patches.list.push(patch(&[0x12, 0x40], &[4, 5]));
+        patches.list.push(Patch {
+            targets: vec![Target::Bus(0x0300)],
+            ..patch(&[], &[6])
+        });
+        assert_eq!(
+            patches.to_ips(),
+            [
+                b"PATCH".as_slice(),
+                &[0x00, 0x00, 0x20, 0x00, 0x03, 1, 2, 4],
+                &[0x00, 0x00, 0x50, 0x00, 0x01, 5],
+                b"EOF",
+            ]
+            .concat()
+        );
+    }
+}
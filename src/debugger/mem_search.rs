@@ -0,0 +1,287 @@
+//! Byte pattern search across CPU and PPU memory, for finding values to cheat or graphics to
+//! hack.
+
+use crate::{
+    cpu::Cpu,
+    mem::{Access, Mem},
+    ppu::Ppu,
+};
+use std::{fmt, str::FromStr};
+
+/// Address space to search.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) enum MemSpace {
+    /// 2K internal CPU work RAM at `$0000-$07FF`.
+    CpuRam,
+    /// Cartridge PRG-RAM at `$6000-$7FFF`.
+    PrgRam,
+    /// The four nametables as mapped on the PPU bus at `$2000-$2FFF`.
+    Nametables,
+    /// Palette RAM at `$3F00-$3F1F`.
+    Palette,
+    /// Sprite Object Attribute Memory.
+    Oam,
+    /// Cartridge CHR-RAM, across all banks.
+    ChrRam,
+}
+
+impl MemSpace {
+    const NAMETABLES_SIZE: u16 = 4 * Ppu::NT_SIZE;
+    const ATTR_OFFSET: usize = 0x03C0;
+
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[
+            Self::CpuRam,
+            Self::PrgRam,
+            Self::Nametables,
+            Self::Palette,
+            Self::Oam,
+            Self::ChrRam,
+        ]
+    }
+
+    /// Copies the current contents of this address space.
+    #[must_use]
+    pub(crate) fn snapshot(self, cpu: &Cpu) -> Vec<u8> {
+        let ppu = cpu.ppu();
+        match self {
+            Self::CpuRam => cpu.wram().to_vec(),
+            Self::PrgRam => cpu.sram().to_vec(),
+            Self::Nametables => (Ppu::NT_START..Ppu::NT_START + Self::NAMETABLES_SIZE)
+                .map(|addr| ppu.peek(addr, Access::Dummy))
+                .collect(),
+            Self::Palette => (Ppu::PALETTE_START..Ppu::PALETTE_END)
+                .map(|addr| ppu.peek(addr, Access::Dummy))
+                .collect(),
+            Self::Oam => ppu.oamdata().to_vec(),
+            Self::ChrRam => ppu.chr_ram().to_vec(),
+        }
+    }
+
+    /// Address of `offset` on the bus this space is mapped to. OAM and CHR-RAM aren't directly
+    /// addressable, so their offset is used.
+    #[must_use]
+    pub(crate) const fn addr(self, offset: usize) -> usize {
+        match self {
+            Self::CpuRam | Self::Oam | Self::ChrRam => offset,
+            Self::PrgRam => 0x6000 + offset,
+            Self::Nametables => Ppu::NT_START as usize + offset,
+            Self::Palette => Ppu::PALETTE_START as usize + offset,
+        }
+    }
+
+    /// Describes what lives at `offset`, like the sprite, tile or palette entry.
+    #[must_use]
+    pub(crate) fn annotate(self, offset: usize) -> String {
+        match self {
+            Self::CpuRam => match offset {
+                0x0000..=0x00FF => "Zero page".to_string(),
+                0x0100..=0x01FF => "Stack".to_string(),
+                _ => String::new(),
+            },
+            Self::PrgRam => String::new(),
+            Self::Nametables => {
+                let nametable = offset / Ppu::NT_SIZE as usize;
+                let offset = offset % Ppu::NT_SIZE as usize;
+                if offset >= Self::ATTR_OFFSET {
+                    let attr = offset - Self::ATTR_OFFSET;
+                    format!(
+                        "Nametable {nametable} attribute ({}, {})",
+                        attr % 8,
+                        attr / 8
+                    )
+                } else {
+                    format!(
+                        "Nametable {nametable} tile ({}, {})",
+                        offset % 32,
+                        offset / 32
+                    )
+                }
+            }
+            Self::Palette => {
+                let kind = if offset < 0x10 {
+                    "Background"
+                } else {
+                    "Sprite"
+                };
+                format!("{kind} palette {} color {}", (offset / 4) % 4, offset % 4)
+            }
+            Self::Oam => {
+                let field = match offset % 4 {
+                    0 => "Y",
+                    1 => "tile",
+                    2 => "attributes",
+                    _ => "X",
+                };
+                format!("Sprite {} {field}", offset / 4)
+            }
+            Self::ChrRam => {
+                // Tiles are 16 bytes: 8 rows of the low bit plane, then 8 rows of the high plane
+                let tile = offset / 16;
+                let plane = (offset / 8) % 2;
+                format!(
+                    "Pattern table {} tile ${:02X} row {} plane {plane}",
+                    tile / 256,
+                    tile % 256,
+                    offset % 8
+                )
+            }
+        }
+    }
+}
+
+impl AsRef<str> for MemSpace {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::CpuRam => "CPU RAM",
+            Self::PrgRam => "PRG-RAM",
+            Self::Nametables => "Nametables",
+            Self::Palette => "Palette RAM",
+            Self::Oam => "OAM",
+            Self::ChrRam => "CHR-RAM",
+        }
+    }
+}
+
+impl From<usize> for MemSpace {
+    fn from(value: usize) -> Self {
+        Self::as_slice().get(value).copied().unwrap_or(Self::CpuRam)
+    }
+}
+
+/// A sequence of bytes to search for, where `None` matches any byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct Pattern(Vec<Option<u8>>);
+
+impl Pattern {
+    #[must_use]
+    pub(crate) fn matches_at(&self, data: &[u8], offset: usize) -> bool {
+        data.get(offset..offset + self.0.len())
+            .map_or(false, |bytes| {
+                self.0
+                    .iter()
+                    .zip(bytes)
+                    .all(|(expected, byte)| expected.map_or(true, |expected| expected == *byte))
+            })
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    /// Parses hex bytes with `??` as a wildcard, e.g. `A9 ?? 8D` or `A9??8D`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '$')
+            .collect::<Vec<_>>();
+        if digits.is_empty() || digits.len() % 2 != 0 {
+            return Err(format!(
+                "invalid search pattern `{s}`: expected pairs of hex digits"
+            ));
+        }
+        digits
+            .chunks(2)
+            .map(|pair| match pair {
+                ['?', '?'] => Ok(None),
+                [hi, lo] => u8::from_str_radix(&format!("{hi}{lo}"), 16)
+                    .map(Some)
+                    .map_err(|_| format!("invalid search pattern byte `{hi}{lo}`")),
+                _ => unreachable!("chunks of 2"),
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            match byte {
+                Some(byte) => write!(f, "{byte:02X}")?,
+                None => write!(f, "??")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Offsets where a pattern matched in an address space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct MemSearch {
+    pub(crate) space: MemSpace,
+    pub(crate) pattern: Pattern,
+    pub(crate) results: Vec<usize>,
+}
+
+impl MemSearch {
+    pub(crate) fn search(space: MemSpace, pattern: Pattern, data: &[u8]) -> Self {
+        let results = (0..data.len())
+            .filter(|&offset| pattern.matches_at(data, offset))
+            .collect();
+        Self {
+            space,
+            pattern,
+            results,
+        }
+    }
+
+    /// Keeps only results that match `pattern` in `data`, for narrowing down values that change
+    /// between searches.
+    pub(crate) fn refine(&mut self, pattern: Pattern, data: &[u8]) {
+        self.results
+            .retain(|&offset| pattern.matches_at(data, offset));
+        self.pattern = pattern;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pattern() {
+        let pattern = "A9 ?? 8d".parse::<Pattern>().expect("valid pattern");
+        assert_eq!(pattern, Pattern(vec![Some(0xA9), None, Some(0x8D)]));
+        assert_eq!(pattern.to_string(), "A9 ?? 8D");
+        assert_eq!("$A9??8D".parse::<Pattern>(), Ok(pattern));
+        assert!("A9 8".parse::<Pattern>().is_err());
+        assert!("ZZ".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn search_and_refine() {
+        let pattern = "01 ??".parse::<Pattern>().expect("valid pattern");
+        let mut search = MemSearch::search(MemSpace::Oam, pattern, &[0x01, 0x02, 0x01, 0x03, 0x01]);
+        assert_eq!(search.results, [0, 2]);
+
+        let pattern = "01 03".parse::<Pattern>().expect("valid pattern");
+        search.refine(pattern, &[0x01, 0x02, 0x01, 0x03, 0x01]);
+        assert_eq!(search.results, [2]);
+    }
+
+    #[test]
+    fn annotations() {
+        assert_eq!(MemSpace::Oam.annotate(9), "Sprite 2 tile");
+        assert_eq!(MemSpace::Palette.annotate(0x16), "Sprite palette 1 color 2");
+        assert_eq!(
+            MemSpace::Nametables.annotate(0x0421),
+            "Nametable 1 tile (1, 1)"
+        );
+        assert_eq!(
+            MemSpace::Nametables.annotate(0x03C9),
+            "Nametable 0 attribute (1, 1)"
+        );
+        assert_eq!(
+            MemSpace::ChrRam.annotate(0x1018),
+            "Pattern table 1 tile $01 row 0 plane 1"
+        );
+        assert_eq!(MemSpace::PrgRam.addr(0x10), 0x6010);
+    }
+}
@@ -0,0 +1,135 @@
+//! Resets and power cycles scheduled for an exact frame and scanline, to reproduce reset-based
+//! glitches deterministically.
+//!
+//! Frames are counted by the PPU since the last reset or power cycle, and count up at the
+//! post-render scanline, so scanlines within a frame run from 240 through the pre-render line
+//! and then from 0 to 239. Execution is checked between instructions, so the reset happens at
+//! the first instruction boundary on the scheduled scanline, or as soon as the frame starts if
+//! no scanline is given.
+
+use crate::common::Kind;
+use std::fmt;
+
+/// A reset scheduled for a future frame, and optionally a scanline within it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct ScheduledReset {
+    pub(crate) kind: Kind,
+    pub(crate) frame: u32,
+    pub(crate) scanline: Option<u32>,
+}
+
+impl ScheduledReset {
+    /// Parses a frame, or a number of frames from `current_frame` prefixed with `+`, and an
+    /// optional scanline. Frames that have already started can't be scheduled.
+    pub(crate) fn parse(
+        kind: Kind,
+        frame: &str,
+        scanline: &str,
+        current_frame: u32,
+    ) -> Result<Self, String> {
+        let frame = frame.trim();
+        let (relative, digits) = match frame.strip_prefix('+') {
+            Some(digits) => (true, digits.trim()),
+            None => (false, frame),
+        };
+        let value = digits
+            .parse::<u32>()
+            .map_err(|_| format!("invalid frame `{frame}`"))?;
+        let frame = if relative {
+            current_frame.saturating_add(value)
+        } else {
+            value
+        };
+        if frame <= current_frame {
+            return Err(format!("frame {frame} has already started"));
+        }
+        let scanline = scanline.trim();
+        let scanline = if scanline.is_empty() {
+            None
+        } else {
+            Some(
+                scanline
+                    .parse()
+                    .map_err(|_| format!("invalid scanline `{scanline}`"))?,
+            )
+        };
+        Ok(Self {
+            kind,
+            frame,
+            scanline,
+        })
+    }
+
+    /// Whether the reset should happen at this point of execution.
+    #[must_use]
+    pub(crate) fn is_due(&self, frame: u32, scanline: u32) -> bool {
+        frame == self.frame && self.scanline.map_or(true, |target| scanline == target)
+    }
+
+    /// Whether the scheduled point has passed without being reached, e.g. because the scanline
+    /// doesn't exist in this region.
+    #[must_use]
+    pub(crate) const fn is_missed(&self, frame: u32) -> bool {
+        frame > self.frame
+    }
+}
+
+impl fmt::Display for ScheduledReset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            Kind::Soft => "Reset",
+            Kind::Hard => "Power cycle",
+        };
+        write!(f, "{kind} at frame {}", self.frame)?;
+        if let Some(scanline) = self.scanline {
+            write!(f, ", scanline {scanline}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_schedule() {
+        assert_eq!(
+            ScheduledReset::parse(Kind::Soft, "120", "", 10),
+            Ok(ScheduledReset {
+                kind: Kind::Soft,
+                frame: 120,
+                scanline: None,
+            })
+        );
+        let reset = ScheduledReset::parse(Kind::Hard, "+60", " 241 ", 10).unwrap();
+        assert_eq!((reset.frame, reset.scanline), (70, Some(241)));
+        assert_eq!(reset.to_string(), "Power cycle at frame 70, scanline 241");
+        assert!(ScheduledReset::parse(Kind::Soft, "10", "", 10).is_err());
+        assert!(ScheduledReset::parse(Kind::Soft, "+0", "", 10).is_err());
+        assert!(ScheduledReset::parse(Kind::Soft, "abc", "", 10).is_err());
+        assert!(ScheduledReset::parse(Kind::Soft, "20", "x", 10).is_err());
+    }
+
+    #[test]
+    fn due_at_frame_and_scanline() {
+        let reset = ScheduledReset {
+            kind: Kind::Soft,
+            frame: 5,
+            scanline: Some(100),
+        };
+        assert!(!reset.is_due(4, 100));
+        assert!(!reset.is_due(5, 99));
+        assert!(reset.is_due(5, 100));
+        assert!(!reset.is_missed(5));
+        assert!(reset.is_missed(6));
+
+        let reset = ScheduledReset {
+            scanline: None,
+            ..reset
+        };
+        assert!(reset.is_due(5, 240));
+        assert!(!reset.is_due(4, 0));
+    }
+}
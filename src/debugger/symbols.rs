@@ -0,0 +1,428 @@
+//! Labels and comments for addresses, loaded from symbol files built alongside a ROM.
+//!
+//! Two formats are supported:
+//!
+//! - FCEUX name lists, with lines like `$C000#Reset#Called on power up`. `game.nes.ram.nl` holds
+//!   RAM and register names, and `game.nes.0.nl`, `game.nes.1.nl` and so on hold the names in
+//!   each 16K bank of PRG-ROM.
+//! - ca65 debug files written by `ld65 --dbgfile`, using the segments to find where in PRG-ROM
+//!   each label ended up.
+//!
+//! Symbols in PRG-ROM are stored by their offset into PRG-ROM so games that switch banks get the
+//! right name for whichever bank is mapped in.
+
+use crate::NesResult;
+use anyhow::Context;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Size of a PRG-ROM bank in FCEUX name lists.
+const NL_BANK_SIZE: usize = 0x4000;
+/// Size of the iNES header, which ca65 output file offsets include.
+const NES_HEADER_LEN: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct Symbol {
+    pub(crate) addr: u16,
+    pub(crate) name: String,
+    pub(crate) comment: Option<String>,
+}
+
+/// Symbols loaded for the current game, and the files they came from so they can be reloaded
+/// when rebuilt.
+#[derive(Default, Debug, Clone)]
+#[must_use]
+pub(crate) struct Symbols {
+    /// Symbols outside PRG-ROM, like RAM and registers, by CPU address.
+    cpu: BTreeMap<u16, Symbol>,
+    /// Symbols in PRG-ROM, by offset into PRG-ROM.
+    prg_rom: BTreeMap<usize, Symbol>,
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+    last_check: Option<Instant>,
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl Symbols {
+    /// Symbol files next to a ROM: FCEUX name lists named after the ROM file, like
+    /// `game.nes.0.nl`, and a ca65 debug file like `game.dbg`.
+    pub(crate) fn find_files(rom_path: &Path) -> Vec<PathBuf> {
+        let (Some(dir), Some(name)) = (rom_path.parent(), rom_path.file_name()) else {
+            return vec![];
+        };
+        let prefix = format!("{}.", name.to_string_lossy());
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name().map_or(false, |name| {
+                    let name = name.to_string_lossy();
+                    name.starts_with(&prefix) && name.ends_with(".nl")
+                })
+            })
+            .collect();
+        files.sort();
+        let dbg = rom_path.with_extension("dbg");
+        if dbg.is_file() {
+            files.push(dbg);
+        }
+        files
+    }
+
+    /// Loads symbols from each file.
+    ///
+    /// # Errors
+    ///
+    /// If a file can't be read, an error is returned.
+    pub(crate) fn load(files: impl IntoIterator<Item = PathBuf>) -> NesResult<Self> {
+        let mut symbols = Self::default();
+        for path in files {
+            symbols.load_file(path)?;
+        }
+        Ok(symbols)
+    }
+
+    /// Adds symbols from a FCEUX name list (`.nl`) or ca65 debug file (`.dbg`).
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be read, an error is returned.
+    pub(crate) fn load_file(&mut self, path: PathBuf) -> NesResult<()> {
+        let text = fs::read_to_string(&path).with_context(|| format!("failed to read {path:?}"))?;
+        if path.extension().map_or(false, |ext| ext == "dbg") {
+            self.parse_dbg(&text);
+        } else {
+            // `game.nes.3.nl` holds bank 3, while `game.nes.ram.nl` holds CPU addresses
+            let bank = path
+                .file_stem()
+                .and_then(|stem| Path::new(stem).extension())
+                .and_then(|bank| bank.to_str()?.parse().ok());
+            self.parse_nl(&text, bank);
+        }
+        let modified = modified_time(&path);
+        self.files.retain(|(file, _)| *file != path);
+        self.files.push((path, modified));
+        Ok(())
+    }
+
+    /// Loads the same files again.
+    ///
+    /// # Errors
+    ///
+    /// If a file can't be read, an error is returned.
+    pub(crate) fn reload(&self) -> NesResult<Self> {
+        Self::load(self.files.iter().map(|(path, _)| path.clone()))
+    }
+
+    /// Whether any loaded file was modified since last checked, checking at most once a second.
+    #[must_use]
+    pub(crate) fn changed(&mut self) -> bool {
+        if self
+            .last_check
+            .map_or(false, |last| last.elapsed() < CHECK_INTERVAL)
+        {
+            return false;
+        }
+        self.last_check = Some(Instant::now());
+        let mut changed = false;
+        for (path, modified) in &mut self.files {
+            let current = modified_time(path);
+            if current != *modified {
+                *modified = current;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    #[must_use]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.cpu.is_empty() && self.prg_rom.is_empty()
+    }
+
+    #[must_use]
+    pub(crate) fn len(&self) -> usize {
+        self.cpu.len() + self.prg_rom.len()
+    }
+
+    fn insert(&mut self, prg_rom_offset: Option<usize>, symbol: Symbol) {
+        match prg_rom_offset {
+            Some(offset) => self.prg_rom.entry(offset).or_insert(symbol),
+            None => self.cpu.entry(symbol.addr).or_insert(symbol),
+        };
+    }
+
+    fn parse_nl(&mut self, text: &str, bank: Option<usize>) {
+        for line in text.lines() {
+            let mut fields = line.trim().splitn(3, '#');
+            let Some(addr) = fields.next().and_then(|addr| addr.strip_prefix('$')) else {
+                continue;
+            };
+            // Arrays are written as `$0200/10`
+            let addr = addr.split_once('/').map_or(addr, |(addr, _)| addr);
+            let Ok(addr) = u16::from_str_radix(addr, 16) else {
+                continue;
+            };
+            let name = fields.next().unwrap_or_default().trim().to_string();
+            let comment = fields
+                .next()
+                .map(|comment| comment.trim().to_string())
+                .filter(|comment| !comment.is_empty());
+            if name.is_empty() && comment.is_none() {
+                continue;
+            }
+            let offset = bank
+                .filter(|_| addr >= 0x8000)
+                .map(|bank| bank * NL_BANK_SIZE + usize::from(addr) % NL_BANK_SIZE);
+            self.insert(
+                offset,
+                Symbol {
+                    addr,
+                    name,
+                    comment,
+                },
+            );
+        }
+    }
+
+    fn parse_dbg(&mut self, text: &str) {
+        let records = text.lines().filter_map(|line| {
+            let (kind, fields) = line.split_once(char::is_whitespace)?;
+            Some((kind, dbg_fields(fields)))
+        });
+        // Segments by id, with their start address and offset in the output file
+        let mut segments = HashMap::new();
+        let mut labels = vec![];
+        for (kind, fields) in records {
+            match kind {
+                "seg" => {
+                    let start = fields.get("start").copied().and_then(parse_dbg_number);
+                    let offset = fields.get("ooffs").and_then(|offset| offset.parse().ok());
+                    if let (Some(&id), Some(start)) = (fields.get("id"), start) {
+                        segments.insert(id.to_string(), (start, offset));
+                    }
+                }
+                "sym" => {
+                    let addr_size = fields.get("addrsize").copied();
+                    let is_label = match fields.get("type").copied() {
+                        Some("lab") => true,
+                        Some("equ") => addr_size == Some("absolute"),
+                        _ => false,
+                    };
+                    let addr = fields
+                        .get("val")
+                        .copied()
+                        .and_then(parse_dbg_number)
+                        .and_then(|val| u16::try_from(val).ok());
+                    if let (true, Some(&name), Some(addr)) = (is_label, fields.get("name"), addr) {
+                        let segment = fields.get("seg").map(ToString::to_string);
+                        labels.push((name.to_string(), addr, segment));
+                    }
+                }
+                _ => (),
+            }
+        }
+        for (name, addr, segment) in labels {
+            let offset = segment
+                .and_then(|segment| segments.get(&segment))
+                .and_then(|&(start, offset)| Some((start, offset?)))
+                .filter(|_| addr >= 0x8000)
+                .and_then(|(start, offset)| {
+                    (offset + usize::from(addr)).checked_sub(NES_HEADER_LEN + start)
+                });
+            self.insert(
+                offset,
+                Symbol {
+                    addr,
+                    name,
+                    comment: None,
+                },
+            );
+        }
+    }
+
+    /// The symbol for an address, given the PRG-ROM offset it's mapped to, if any.
+    pub(crate) fn get(&self, addr: u16, prg_rom_offset: Option<usize>) -> Option<&Symbol> {
+        prg_rom_offset
+            .and_then(|offset| self.prg_rom.get(&offset))
+            .or_else(|| self.cpu.get(&addr))
+    }
+
+    /// The address of a label, matched ignoring case.
+    #[must_use]
+    pub(crate) fn find(&self, name: &str) -> Option<u16> {
+        self.cpu
+            .values()
+            .chain(self.prg_rom.values())
+            .find(|symbol| !symbol.name.is_empty() && symbol.name.eq_ignore_ascii_case(name))
+            .map(|symbol| symbol.addr)
+    }
+
+    /// Replaces the address operand in a disassembled instruction with its label, if it has one.
+    pub(crate) fn annotate(
+        &self,
+        disasm: &str,
+        prg_rom_offset: impl Fn(u16) -> Option<usize>,
+    ) -> String {
+        // Skip the PC at the start of the line and immediate operands
+        let operand = disasm
+            .match_indices('$')
+            .map(|(i, _)| i)
+            .find(|&i| i > 0 && !disasm[..i].ends_with('#'));
+        let Some(start) = operand else {
+            return disasm.to_string();
+        };
+        let digits = &disasm[start + 1..];
+        let len = digits
+            .find(|c: char| !c.is_ascii_hexdigit())
+            .unwrap_or(digits.len());
+        let label = match u16::from_str_radix(&digits[..len], 16) {
+            Ok(addr) if len == 2 || len == 4 => self
+                .get(addr, prg_rom_offset(addr))
+                .map(|symbol| symbol.name.as_str())
+                .filter(|name| !name.is_empty()),
+            _ => None,
+        };
+        match label {
+            Some(label) => format!("{}{label}{}", &disasm[..start], &digits[len..]),
+            None => disasm.to_string(),
+        }
+    }
+}
+
+/// Splits the comma separated `key=value` fields of a ca65 debug file line, removing quotes
+/// around values.
+fn dbg_fields(fields: &str) -> HashMap<&str, &str> {
+    let mut map = HashMap::new();
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in fields.char_indices().chain([(fields.len(), ',')]) {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                if let Some((key, value)) = fields[start..i].split_once('=') {
+                    map.insert(key.trim(), value.trim().trim_matches('"'));
+                }
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    map
+}
+
+/// Parses a number in a ca65 debug file, written in hex like `0x00C000` or in decimal.
+fn parse_dbg_number(value: &str) -> Option<usize> {
+    match value.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NL: &str = "\
+$C000#Reset#Called on power up
+$C123#ReadJoypad#
+$D000##Unused padding
+bogus line
+";
+
+    const RAM_NL: &str = "\
+$0010#player_x#
+$0200/100#oam_buffer#Copied to OAM every NMI
+";
+
+    const DBG: &str = "\
+version\tmajor=2,minor=0
+seg\tid=0,name=\"ZEROPAGE\",start=0x000000,size=0x0010,addrsize=zeropage,type=rw
+seg\tid=1,name=\"CODE\",start=0x008000,size=0x0123,addrsize=absolute,type=ro,oname=\"game, final.nes\",ooffs=16
+sym\tid=0,name=\"reset\",addrsize=absolute,scope=0,def=5,val=0x8000,seg=1,type=lab
+sym\tid=1,name=\"@loop\",addrsize=absolute,scope=1,def=6,val=0x8010,seg=1,type=lab
+sym\tid=2,name=\"frame\",addrsize=zeropage,scope=0,def=7,val=0x4,seg=0,type=lab
+sym\tid=3,name=\"PPUCTRL\",addrsize=absolute,scope=0,def=8,val=0x2000,type=equ
+sym\tid=4,name=\"SPEED\",addrsize=zeropage,scope=0,def=9,val=0x3,type=equ
+";
+
+    #[test]
+    fn parse_name_lists() {
+        let mut symbols = Symbols::default();
+        symbols.parse_nl(NL, Some(1));
+        symbols.parse_nl(RAM_NL, None);
+        assert_eq!(symbols.len(), 5);
+
+        let reset = symbols.get(0xC000, Some(0x4000)).expect("reset label");
+        assert_eq!(reset.name, "Reset");
+        assert_eq!(reset.comment.as_deref(), Some("Called on power up"));
+        // Another bank mapped in
+        assert_eq!(symbols.get(0xC000, Some(0x0000)), None);
+        assert_eq!(
+            symbols
+                .get(0xD000, Some(0x5000))
+                .map(|symbol| symbol.name.as_str()),
+            Some("")
+        );
+        assert_eq!(
+            symbols.get(0x0200, None).map(|symbol| symbol.name.as_str()),
+            Some("oam_buffer")
+        );
+        assert_eq!(symbols.find("readjoypad"), Some(0xC123));
+        assert_eq!(symbols.find("missing"), None);
+    }
+
+    #[test]
+    fn parse_ca65_debug_file() {
+        let mut symbols = Symbols::default();
+        symbols.parse_dbg(DBG);
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(
+            symbols
+                .get(0x8010, Some(0x10))
+                .map(|symbol| symbol.name.as_str()),
+            Some("@loop")
+        );
+        assert_eq!(symbols.find("frame"), Some(0x0004));
+        assert_eq!(symbols.find("PPUCTRL"), Some(0x2000));
+        assert_eq!(symbols.find("SPEED"), None);
+    }
+
+    #[test]
+    fn annotate_disassembly() {
+        let mut symbols = Symbols::default();
+        symbols.parse_nl(RAM_NL, None);
+        symbols.parse_nl(NL, Some(1));
+        let offset = |addr: u16| (addr >= 0x8000).then(|| 0x4000 + usize::from(addr) % 0x4000);
+
+        assert_eq!(
+            symbols.annotate("C000 20 23 C1 JSR $C123", offset),
+            "C000 20 23 C1 JSR ReadJoypad"
+        );
+        assert_eq!(
+            symbols.annotate("C003 A5 10    LDA $10 = #$05", offset),
+            "C003 A5 10    LDA player_x = #$05"
+        );
+        assert_eq!(
+            symbols.annotate("C005 A9 10    LDA #$10", offset),
+            "C005 A9 10    LDA #$10"
+        );
+        assert_eq!(
+            symbols.annotate("C007 8D 00 20 STA $2000 = #$00", offset),
+            "C007 8D 00 20 STA $2000 = #$00"
+        );
+    }
+}
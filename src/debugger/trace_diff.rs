@@ -0,0 +1,291 @@
+//! Differential debugging against a reference trace log.
+//!
+//! Each instruction is compared against the next line of a log from another emulator (e.g.
+//! Mesen) or `nestest.log`, stopping at the first line where the CPU registers disagree. Lines
+//! are expected to start with the PC in hex, followed somewhere by `A:`, `X:`, `Y:`, `P:` and
+//! `SP:`/`S:` fields. `CYC:`/`Cycle:` is compared relative to the first line when present.
+
+use crate::{cpu::Cpu, NesResult};
+use anyhow::{anyhow, Context};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+/// Status bits compared between traces. The B and unused bits aren't real flags and many
+/// emulators log them differently.
+const STATUS_MASK: u8 = 0xCF;
+
+/// CPU state for one line of a trace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct TraceLine {
+    pub(crate) pc: u16,
+    pub(crate) a: u8,
+    pub(crate) x: u8,
+    pub(crate) y: u8,
+    pub(crate) p: u8,
+    pub(crate) sp: u8,
+    pub(crate) cycle: Option<usize>,
+    pub(crate) text: String,
+}
+
+impl TraceLine {
+    /// Parses a reference log line, returning `None` for lines without CPU state.
+    pub(crate) fn parse(line: &str) -> Option<Self> {
+        let mut tokens = line.split_whitespace();
+        let pc = tokens.next().filter(|pc| pc.len() == 4)?;
+        let pc = u16::from_str_radix(pc, 16).ok()?;
+        let (mut a, mut x, mut y, mut p, mut sp, mut cycle) = (None, None, None, None, None, None);
+        for token in tokens {
+            let Some((name, val)) = token.split_once(':') else {
+                continue;
+            };
+            let hex = || u8::from_str_radix(val, 16).ok();
+            match name {
+                "A" => a = hex(),
+                "X" => x = hex(),
+                "Y" => y = hex(),
+                "P" => p = hex().or_else(|| parse_flags(val)),
+                "SP" | "S" => sp = hex(),
+                "CYC" | "Cycle" => cycle = val.parse().ok(),
+                _ => (),
+            }
+        }
+        Some(Self {
+            pc,
+            a: a?,
+            x: x?,
+            y: y?,
+            p: p?,
+            sp: sp?,
+            cycle,
+            text: line.trim_end().to_string(),
+        })
+    }
+
+    /// Captures the current CPU state, disassembling the next instruction for display.
+    pub(crate) fn from_cpu(cpu: &mut Cpu) -> Self {
+        let mut pc = cpu.pc();
+        cpu.disassemble(&mut pc);
+        let text = format!(
+            "{:<30} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            cpu.disasm(),
+            cpu.a(),
+            cpu.x(),
+            cpu.y(),
+            cpu.status().bits(),
+            cpu.sp(),
+            cpu.cycle()
+        );
+        Self {
+            pc: cpu.pc(),
+            a: cpu.a(),
+            x: cpu.x(),
+            y: cpu.y(),
+            p: cpu.status().bits(),
+            sp: cpu.sp(),
+            cycle: Some(cpu.cycle()),
+            text,
+        }
+    }
+}
+
+/// Parses status flags written as letters in `NV-BDIZC` order, where uppercase means set.
+fn parse_flags(flags: &str) -> Option<u8> {
+    if flags.len() != 8 {
+        return None;
+    }
+    Some(flags.chars().enumerate().fold(0, |p, (i, c)| {
+        if c.is_ascii_uppercase() {
+            p | (0x80 >> i)
+        } else {
+            p
+        }
+    }))
+}
+
+/// A register that differed between the reference and actual traces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct Mismatch {
+    pub(crate) field: &'static str,
+    pub(crate) expected: String,
+    pub(crate) actual: String,
+}
+
+/// The first line where execution diverged from the reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct Divergence {
+    /// Line number in the reference log, starting at 1.
+    pub(crate) line: usize,
+    pub(crate) expected: TraceLine,
+    pub(crate) actual: TraceLine,
+    pub(crate) mismatches: Vec<Mismatch>,
+}
+
+#[derive(Debug, Clone)]
+#[must_use]
+pub(crate) struct TraceDiff {
+    path: PathBuf,
+    // Reference lines paired with their line number in the log
+    lines: Vec<(usize, TraceLine)>,
+    next: usize,
+    // Cycle counts of the first reference line and the CPU when comparing started
+    cycle_start: Option<(usize, usize)>,
+    divergence: Option<Divergence>,
+}
+
+impl TraceDiff {
+    /// Loads a reference trace log.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be read or contains no CPU state, an error is returned.
+    pub(crate) fn load<P: AsRef<Path>>(path: P) -> NesResult<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+        let mut lines = vec![];
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.with_context(|| format!("failed to read {path:?}"))?;
+            if let Some(line) = TraceLine::parse(&line) {
+                lines.push((i + 1, line));
+            }
+        }
+        if lines.is_empty() {
+            return Err(anyhow!("no trace lines found in {path:?}"));
+        }
+        Ok(Self::new(path.to_path_buf(), lines))
+    }
+
+    fn new(path: PathBuf, lines: Vec<(usize, TraceLine)>) -> Self {
+        Self {
+            path,
+            lines,
+            next: 0,
+            cycle_start: None,
+            divergence: None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Number of reference lines that matched so far.
+    #[inline]
+    #[must_use]
+    pub(crate) const fn matched(&self) -> usize {
+        self.next
+    }
+
+    #[inline]
+    #[must_use]
+    pub(crate) fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    #[inline]
+    #[must_use]
+    pub(crate) fn finished(&self) -> bool {
+        self.next >= self.lines.len()
+    }
+
+    #[inline]
+    pub(crate) const fn divergence(&self) -> Option<&Divergence> {
+        self.divergence.as_ref()
+    }
+
+    /// Compares the CPU state before the next instruction against the next reference line,
+    /// returning `true` when execution first diverges or the reference log ends.
+    pub(crate) fn check(&mut self, cpu: &mut Cpu) -> bool {
+        if self.divergence.is_some() {
+            return false;
+        }
+        let Some((line, expected)) = self.lines.get(self.next) else {
+            return false;
+        };
+        let cycle_start = *self
+            .cycle_start
+            .get_or_insert((expected.cycle.unwrap_or(0), cpu.cycle()));
+
+        let mut mismatches = vec![];
+        let mut compare = |field, expected: u16, actual: u16, width| {
+            if expected != actual {
+                mismatches.push(Mismatch {
+                    field,
+                    expected: format!("${expected:0width$X}"),
+                    actual: format!("${actual:0width$X}"),
+                });
+            }
+        };
+        compare("PC", expected.pc, cpu.pc(), 4);
+        compare("A", expected.a.into(), cpu.a().into(), 2);
+        compare("X", expected.x.into(), cpu.x().into(), 2);
+        compare("Y", expected.y.into(), cpu.y().into(), 2);
+        compare(
+            "P",
+            (expected.p & STATUS_MASK).into(),
+            (cpu.status().bits() & STATUS_MASK).into(),
+            2,
+        );
+        compare("SP", expected.sp.into(), cpu.sp().into(), 2);
+        if let Some(cycle) = expected.cycle {
+            let expected = cycle.wrapping_sub(cycle_start.0);
+            let actual = cpu.cycle().wrapping_sub(cycle_start.1);
+            if expected != actual {
+                mismatches.push(Mismatch {
+                    field: "CYC",
+                    expected: expected.to_string(),
+                    actual: actual.to_string(),
+                });
+            }
+        }
+
+        if mismatches.is_empty() {
+            self.next += 1;
+            self.finished()
+        } else {
+            self.divergence = Some(Divergence {
+                line: *line,
+                expected: expected.clone(),
+                actual: TraceLine::from_cpu(cpu),
+                mismatches,
+            });
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_nestest_line() {
+        let line = TraceLine::parse(
+            "C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+        )
+        .expect("valid line");
+        assert_eq!(
+            (line.pc, line.a, line.x, line.y, line.p, line.sp, line.cycle),
+            (0xC000, 0x00, 0x00, 0x00, 0x24, 0xFD, Some(7))
+        );
+    }
+
+    #[test]
+    fn parse_mesen_line() {
+        let line = TraceLine::parse(
+            "8000  78        SEI                A:01 X:02 Y:03 S:FD P:nvubdIzc  V:0 H:0 Fr:0 Cycle:8",
+        )
+        .expect("valid line");
+        assert_eq!(
+            (line.pc, line.a, line.x, line.y, line.p, line.sp, line.cycle),
+            (0x8000, 0x01, 0x02, 0x03, 0x04, 0xFD, Some(8))
+        );
+        assert!(TraceLine::parse("[NMI - Cycle: 1234]").is_none());
+    }
+}
@@ -0,0 +1,381 @@
+//! Watch expressions, for following values in games with dynamic data structures.
+//!
+//! An expression gives the address of the watched value. Numbers are decimal, or hex with a `$`
+//! or `0x` prefix, and can be added or subtracted. `[expr]` reads the little-endian 16-bit
+//! pointer at `expr`, so pointer chains can be followed: `[[$0042]+3]` reads the pointer at
+//! `$0042`, then the pointer 3 bytes past where it points, and watches the value there.
+//!
+//! CPU registers can be used by name, like `$0300+X`: `A`, `X`, `Y`, `P` for the status flags,
+//! `SP` and `PC`.
+
+use crate::cpu::Cpu;
+use std::{fmt, iter::Peekable, str::Chars, str::FromStr};
+
+/// Size of a watched value in bytes, stored little-endian.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) enum WatchSize {
+    #[default]
+    Byte,
+    Word,
+    Long,
+}
+
+impl WatchSize {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[Self::Byte, Self::Word, Self::Long]
+    }
+
+    const fn bytes(self) -> u16 {
+        match self {
+            Self::Byte => 1,
+            Self::Word => 2,
+            Self::Long => 3,
+        }
+    }
+}
+
+impl AsRef<str> for WatchSize {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Byte => "8-bit",
+            Self::Word => "16-bit",
+            Self::Long => "24-bit",
+        }
+    }
+}
+
+impl From<usize> for WatchSize {
+    fn from(value: usize) -> Self {
+        Self::as_slice().get(value).copied().unwrap_or_default()
+    }
+}
+
+/// A CPU register used in an expression.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) enum Register {
+    A,
+    X,
+    Y,
+    P,
+    Sp,
+    Pc,
+}
+
+impl Register {
+    pub(crate) fn value(self, cpu: &Cpu) -> u16 {
+        match self {
+            Self::A => cpu.a().into(),
+            Self::X => cpu.x().into(),
+            Self::Y => cpu.y().into(),
+            Self::P => cpu.status().bits().into(),
+            Self::Sp => cpu.sp().into(),
+            Self::Pc => cpu.pc(),
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "A" => Ok(Self::A),
+            "X" => Ok(Self::X),
+            "Y" => Ok(Self::Y),
+            "P" => Ok(Self::P),
+            "SP" => Ok(Self::Sp),
+            "PC" => Ok(Self::Pc),
+            _ => Err(format!("unknown register `{s}`")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Number(u16),
+    Register(Register),
+    /// The 16-bit pointer stored at an address.
+    Deref(Box<Expr>),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn eval(&self, peek: &impl Fn(u16) -> u8, registers: &impl Fn(Register) -> u16) -> u16 {
+        match self {
+            Self::Number(value) => *value,
+            Self::Register(register) => registers(*register),
+            Self::Deref(expr) => {
+                let addr = expr.eval(peek, registers);
+                u16::from_le_bytes([peek(addr), peek(addr.wrapping_add(1))])
+            }
+            Self::Add(lhs, rhs) => lhs
+                .eval(peek, registers)
+                .wrapping_add(rhs.eval(peek, registers)),
+            Self::Sub(lhs, rhs) => lhs
+                .eval(peek, registers)
+                .wrapping_sub(rhs.eval(peek, registers)),
+        }
+    }
+}
+
+/// Recursive descent parser for watch expressions.
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some('+') => {
+                    self.chars.next();
+                    expr = Expr::Add(Box::new(expr), Box::new(self.term()?));
+                }
+                Some('-') => {
+                    self.chars.next();
+                    expr = Expr::Sub(Box::new(expr), Box::new(self.term()?));
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some('[') => {
+                let expr = self.expr()?;
+                self.close(']')?;
+                Ok(Expr::Deref(Box::new(expr)))
+            }
+            Some('(') => {
+                let expr = self.expr()?;
+                self.close(')')?;
+                Ok(expr)
+            }
+            Some('$') => self.number(16),
+            Some('0') if self.chars.next_if(|&c| c == 'x' || c == 'X').is_some() => self.number(16),
+            Some(c) if c.is_ascii_digit() => {
+                let mut digits = c.to_string();
+                while let Some(c) = self.chars.next_if(char::is_ascii_digit) {
+                    digits.push(c);
+                }
+                digits
+                    .parse()
+                    .map(Expr::Number)
+                    .map_err(|_| format!("invalid number `{digits}`"))
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let mut name = c.to_string();
+                while let Some(c) = self.chars.next_if(char::is_ascii_alphanumeric) {
+                    name.push(c);
+                }
+                name.parse().map(Expr::Register)
+            }
+            Some(c) => Err(format!("unexpected `{c}`")),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    fn number(&mut self, radix: u32) -> Result<Expr, String> {
+        let mut digits = String::new();
+        while let Some(c) = self.chars.next_if(|c| c.is_digit(radix)) {
+            digits.push(c);
+        }
+        u16::from_str_radix(&digits, radix)
+            .map(Expr::Number)
+            .map_err(|_| format!("invalid number `{digits}`"))
+    }
+
+    fn close(&mut self, delimiter: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == delimiter => Ok(()),
+            _ => Err(format!("expected `{delimiter}`")),
+        }
+    }
+}
+
+/// A parsed expression, evaluated against the current CPU state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct Expression(Expr);
+
+impl Expression {
+    /// Evaluates the expression, reading memory with `peek` and registers with `registers`.
+    pub(crate) fn eval(
+        &self,
+        peek: impl Fn(u16) -> u8,
+        registers: impl Fn(Register) -> u16,
+    ) -> u16 {
+        self.0.eval(&peek, &registers)
+    }
+}
+
+impl FromStr for Expression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            chars: s.chars().peekable(),
+        };
+        let expr = parser.expr()?;
+        parser.skip_whitespace();
+        match parser.chars.next() {
+            Some(c) => Err(format!("unexpected `{c}`")),
+            None => Ok(Self(expr)),
+        }
+    }
+}
+
+/// A value watched in the debugger, read from the CPU bus every refresh.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct Watch {
+    text: String,
+    expr: Expression,
+    pub(crate) size: WatchSize,
+    pub(crate) signed: bool,
+}
+
+impl Watch {
+    /// Parses a watch expression, returning an error describing where it's invalid.
+    pub(crate) fn new(text: &str, size: WatchSize, signed: bool) -> Result<Self, String> {
+        let expr = text
+            .parse()
+            .map_err(|err| format!("invalid watch `{text}`: {err}"))?;
+        Ok(Self {
+            text: text.trim().to_string(),
+            expr,
+            size,
+            signed,
+        })
+    }
+
+    /// Resolves the address and reads the value at it using `peek`, with registers in the
+    /// expression read using `registers`.
+    pub(crate) fn read(
+        &self,
+        peek: impl Fn(u16) -> u8,
+        registers: impl Fn(Register) -> u16,
+    ) -> WatchValue {
+        let addr = self.expr.eval(&peek, &registers);
+        let bytes = self.size.bytes();
+        let raw = (0..bytes).rev().fold(0, |value, i| {
+            (value << 8) | u32::from(peek(addr.wrapping_add(i)))
+        });
+        let bits = 8 * u32::from(bytes);
+        let value = if self.signed && raw & (1 << (bits - 1)) != 0 {
+            i64::from(raw) - (1 << bits)
+        } else {
+            i64::from(raw)
+        };
+        WatchValue {
+            addr,
+            raw,
+            value,
+            size: self.size,
+        }
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl FromStr for Watch {
+    type Err = String;
+
+    /// Parses an unsigned 8-bit watch.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s, WatchSize::default(), false)
+    }
+}
+
+/// A watch read at a point in time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct WatchValue {
+    /// Address the expression resolved to.
+    pub(crate) addr: u16,
+    raw: u32,
+    pub(crate) value: i64,
+    size: WatchSize,
+}
+
+impl fmt::Display for WatchValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits = 2 * self.size.bytes() as usize;
+        write!(
+            f,
+            "${:04X} = {} (${:0digits$X})",
+            self.addr, self.value, self.raw
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pointer_chain() {
+        let mut ram = [0u8; 0x800];
+        ram[0x42..0x44].copy_from_slice(&[0x00, 0x03]);
+        ram[0x303..0x305].copy_from_slice(&[0x10, 0x05]);
+        ram[0x510..0x513].copy_from_slice(&[0xFE, 0xFF, 0x01]);
+        let peek = |addr: u16| ram[addr as usize % ram.len()];
+
+        let watch = "[[0x0042]+3]".parse::<Watch>().expect("valid watch");
+        assert_eq!(watch.read(peek, |_| 0).addr, 0x0510);
+        assert_eq!(watch.read(peek, |_| 0).value, 0xFE);
+
+        let watch = Watch::new("[[$42] + 3]", WatchSize::Word, true).expect("valid watch");
+        assert_eq!(watch.read(peek, |_| 0).value, -2);
+        assert_eq!(watch.read(peek, |_| 0).to_string(), "$0510 = -2 ($FFFE)");
+
+        let watch = Watch::new("($500 + 32) - 16", WatchSize::Long, false).expect("valid watch");
+        assert_eq!(watch.read(peek, |_| 0).value, 0x01FFFE);
+
+        assert!("[$42".parse::<Watch>().is_err());
+        assert!("$42 + Q".parse::<Watch>().is_err());
+        assert!("$42 +".parse::<Watch>().is_err());
+        assert!("$42 $43".parse::<Watch>().is_err());
+    }
+
+    #[test]
+    fn registers() {
+        let ram = [0x12u8; 0x800];
+        let peek = |addr: u16| ram[addr as usize % ram.len()];
+        let registers = |register| match register {
+            Register::X => 0x04,
+            Register::Pc => 0xC000,
+            _ => 0,
+        };
+
+        let expr = "$0300 + x".parse::<Expression>().expect("valid expression");
+        assert_eq!(expr.eval(peek, registers), 0x0304);
+        let expr = "PC - 2".parse::<Expression>().expect("valid expression");
+        assert_eq!(expr.eval(peek, registers), 0xBFFE);
+        let expr = "[sp]".parse::<Expression>().expect("valid expression");
+        assert_eq!(expr.eval(peek, registers), 0x1212);
+
+        let watch = "$10+Y".parse::<Watch>().expect("valid watch");
+        assert_eq!(watch.read(peek, registers).addr, 0x0010);
+        assert_eq!(
+            "PCX".parse::<Expression>(),
+            Err("unknown register `PCX`".to_string())
+        );
+    }
+}
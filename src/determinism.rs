@@ -0,0 +1,117 @@
+//! Checks that emulation is deterministic.
+//!
+//! A ROM is run twice from power-on, optionally playing back an FCEUX `.fm2` movie for input,
+//! and the full console state is checksummed after every frame. Replays, rewind and netplay all
+//! rely on the same inputs producing the same states, so the first frame where the two runs
+//! disagree points at state that isn't saved or depends on something outside the console.
+
+use crate::{
+    common::{Kind, NesRegion, Regional, Reset},
+    control_deck::ControlDeck,
+    input::FourPlayer,
+    mem::RamState,
+    movie::Fm2Movie,
+    NesResult,
+};
+use anyhow::Context;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+/// The first frame where two runs of the same ROM and inputs produced different states.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct Divergence {
+    pub frame: u32,
+    pub first: u64,
+    pub second: u64,
+}
+
+/// Checksum of the full console state, as it would be written to a save state.
+///
+/// # Errors
+///
+/// If the state fails to serialize, an error is returned.
+pub fn state_checksum(control_deck: &ControlDeck) -> NesResult<u64> {
+    let state = bincode::serialize(control_deck.cpu()).context("failed to serialize state")?;
+    let mut hasher = DefaultHasher::new();
+    state.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+/// Runs `rom` for `frames` frames and returns the state checksum after each one.
+///
+/// # Errors
+///
+/// If the ROM fails to load or emulation fails, an error is returned.
+pub fn checksums(
+    rom: &[u8],
+    movie: Option<&Fm2Movie>,
+    frames: u32,
+    ram_state: RamState,
+) -> NesResult<Vec<u64>> {
+    let mut control_deck = ControlDeck::new(ram_state);
+    control_deck.load_rom("determinism", &mut &rom[..])?;
+    if let Some(movie) = movie {
+        if movie.pal {
+            control_deck.set_region(NesRegion::Pal);
+        }
+        if movie.four_score {
+            control_deck.set_four_player(FourPlayer::FourScore);
+        }
+        control_deck.reset(Kind::Hard);
+    }
+    (0..frames)
+        .map(|frame_number| {
+            if let Some(frame) = movie.and_then(|movie| movie.frames.get(frame_number as usize)) {
+                frame.apply(&mut control_deck);
+            }
+            control_deck.clock_frame()?;
+            control_deck.clear_audio_samples();
+            state_checksum(&control_deck)
+        })
+        .collect()
+}
+
+/// Runs `rom` twice and returns the first frame where the states differ, if any.
+///
+/// # Errors
+///
+/// If the ROM fails to load or emulation fails, an error is returned.
+pub fn check(
+    rom: &[u8],
+    movie: Option<&Fm2Movie>,
+    frames: u32,
+    ram_state: RamState,
+) -> NesResult<Option<Divergence>> {
+    let first = checksums(rom, movie, frames, ram_state)?;
+    let second = checksums(rom, movie, frames, ram_state)?;
+    Ok(first
+        .into_iter()
+        .zip(second)
+        .zip(0..)
+        .find(|((first, second), _)| first != second)
+        .map(|((first, second), frame)| Divergence {
+            frame,
+            first,
+            second,
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nrom_is_deterministic() {
+        let rom = std::fs::read("test_roms/cpu/nestest.nes").expect("valid rom");
+        assert_eq!(
+            check(&rom, None, 30, RamState::AllZeros).expect("ran rom"),
+            None
+        );
+
+        let first = checksums(&rom, None, 2, RamState::AllZeros).expect("ran rom");
+        assert_ne!(first[0], first[1], "state changes every frame");
+    }
+}
@@ -0,0 +1,358 @@
+//! Headless test ROM automation.
+//!
+//! Runs CPU, PPU, APU and mapper test ROMs without opening a window and detects whether they
+//! passed. ROMs using the status protocol of blargg's test framework are checked by reading the
+//! result they write to `$6000`. Other ROMs, like those that only draw their results, are checked
+//! against the hash of a frame.
+//!
+//! ROMs to run are listed in a JSON manifest:
+//!
+//! ```json
+//! {
+//!   "frames": 3600,
+//!   "roms": [
+//!     { "path": "cpu/instr_basics.nes" },
+//!     { "path": "mapper/holy_diver/" },
+//!     { "path": "ppu/palette.nes", "frame": 10, "hash": 12345 },
+//!     { "path": "cpu/overclock.nes", "ignore": "need to fix frame timing" }
+//!   ]
+//! }
+//! ```
+//!
+//! Entries with a `hash` are compared against the frame with that number, all others use the
+//! status protocol, giving up after `frames` frames. Directory entries expand to every `.nes`
+//! file they contain. Paths are relative to the manifest.
+//!
+//! <https://github.com/christopherpow/nes-test-roms/blob/master/README.md>
+
+use crate::{
+    common::{Kind, Reset},
+    compat::{frame_hash, panic_message},
+    control_deck::ControlDeck,
+    mem::{Access, Mem, RamState},
+    video::VideoFilter,
+    NesResult,
+};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsStr,
+    fmt,
+    fs::File,
+    io::BufReader,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+};
+
+const DEFAULT_FRAMES: u32 = 3600;
+
+const STATUS_ADDR: u16 = 0x6000;
+const SIGNATURE_ADDR: u16 = 0x6001;
+const MESSAGE_ADDR: u16 = 0x6004;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET: u8 = 0x81;
+/// The status protocol asks for a reset to be delayed at least 100ms after it's requested.
+const RESET_DELAY_FRAMES: u32 = 6;
+
+/// A test ROM to run.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub struct TestRom {
+    /// Path to a `.nes` ROM or a directory of ROMs.
+    pub path: PathBuf,
+    /// Frames to run before giving up on the status protocol, overriding the manifest default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frames: Option<u32>,
+    /// Frame to compare against `hash`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame: Option<u32>,
+    /// Expected frame hash. Uses the status protocol if not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<u64>,
+    /// Reason to skip this ROM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ignore: Option<String>,
+}
+
+impl TestRom {
+    /// Name of the test, used in reports.
+    #[must_use]
+    pub fn name(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+}
+
+/// A list of test ROMs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub struct TestManifest {
+    #[serde(default = "default_frames")]
+    pub frames: u32,
+    pub roms: Vec<TestRom>,
+}
+
+const fn default_frames() -> u32 {
+    DEFAULT_FRAMES
+}
+
+impl TestManifest {
+    /// Loads a manifest from a JSON file. Relative paths are resolved from the directory
+    /// containing the manifest.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be read or is not a valid manifest, an error is returned.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> NesResult<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+        let mut manifest: Self = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("invalid test manifest {path:?}"))?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        for rom in &mut manifest.roms {
+            rom.path = base.join(&rom.path);
+        }
+        Ok(manifest)
+    }
+
+    /// Expands directory entries into an entry for each `.nes` file they contain.
+    ///
+    /// # Errors
+    ///
+    /// If a directory can't be read, an error is returned.
+    pub fn expand(&self) -> NesResult<Vec<TestRom>> {
+        let mut roms = Vec::with_capacity(self.roms.len());
+        for rom in &self.roms {
+            if rom.path.is_dir() {
+                let mut paths = rom
+                    .path
+                    .read_dir()
+                    .with_context(|| format!("failed to read directory {:?}", rom.path))?
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .filter(|path| path.extension() == Some(OsStr::new("nes")))
+                    .collect::<Vec<_>>();
+                paths.sort();
+                roms.extend(paths.into_iter().map(|path| TestRom {
+                    path,
+                    frames: rom.frames,
+                    ignore: rom.ignore.clone(),
+                    ..TestRom::default()
+                }));
+            } else {
+                roms.push(rom.clone());
+            }
+        }
+        Ok(roms)
+    }
+}
+
+/// Result of running a test ROM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub enum TestResult {
+    Passed,
+    /// Skipped with the reason given in the manifest.
+    Ignored(String),
+    /// Result code and message written by the status protocol.
+    Failed {
+        code: u8,
+        message: String,
+    },
+    HashMismatch {
+        frame: u32,
+        expected: u64,
+        actual: u64,
+    },
+    /// The status protocol never reported a result.
+    TimedOut {
+        frames: u32,
+        message: String,
+    },
+    /// The ROM failed to load or emulation failed.
+    Error(String),
+}
+
+impl TestResult {
+    #[must_use]
+    pub const fn failed(&self) -> bool {
+        !matches!(self, Self::Passed | Self::Ignored(_))
+    }
+}
+
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Passed => write!(f, "ok"),
+            Self::Ignored(reason) => write!(f, "ignored, {reason}"),
+            Self::Failed { code, message } => {
+                write!(f, "FAILED with code {code}: {}", message.trim())
+            }
+            Self::HashMismatch {
+                frame,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "FAILED frame {frame} hash {actual} did not match {expected}"
+            ),
+            Self::TimedOut { frames, message } => {
+                write!(f, "TIMED OUT after {frames} frames: {}", message.trim())
+            }
+            Self::Error(err) => write!(f, "ERROR {err}"),
+        }
+    }
+}
+
+fn load_control_deck(path: &Path) -> NesResult<ControlDeck> {
+    let mut rom =
+        BufReader::new(File::open(path).with_context(|| format!("failed to open {path:?}"))?);
+    let mut control_deck = ControlDeck::new(RamState::AllZeros);
+    control_deck.load_rom(path.to_string_lossy(), &mut rom)?;
+    control_deck.set_filter(VideoFilter::Pixellate);
+    Ok(control_deck)
+}
+
+/// Whether the status protocol signature has been written to PRG-RAM.
+fn has_signature(control_deck: &ControlDeck) -> bool {
+    SIGNATURE
+        .iter()
+        .zip(SIGNATURE_ADDR..)
+        .all(|(&val, addr)| control_deck.cpu().peek(addr, Access::Dummy) == val)
+}
+
+/// Reads the NUL terminated status message.
+fn status_message(control_deck: &ControlDeck) -> String {
+    let message = (MESSAGE_ADDR..=0x7FFF)
+        .map(|addr| control_deck.cpu().peek(addr, Access::Dummy))
+        .take_while(|&val| val != 0x00)
+        .collect::<Vec<_>>();
+    String::from_utf8_lossy(&message).into_owned()
+}
+
+fn run_status(control_deck: &mut ControlDeck, frames: u32) -> NesResult<TestResult> {
+    let mut reset_at = None;
+    for frame in 0..frames {
+        control_deck.clock_frame()?;
+        control_deck.clear_audio_samples();
+        if !has_signature(control_deck) {
+            continue;
+        }
+        match control_deck.cpu().peek(STATUS_ADDR, Access::Dummy) {
+            STATUS_RUNNING => reset_at = None,
+            STATUS_RESET => match reset_at {
+                None => reset_at = Some(frame + RESET_DELAY_FRAMES),
+                Some(reset_frame) if frame >= reset_frame => {
+                    control_deck.reset(Kind::Soft);
+                    reset_at = None;
+                }
+                Some(_) => (),
+            },
+            0x00 => return Ok(TestResult::Passed),
+            code => {
+                return Ok(TestResult::Failed {
+                    code,
+                    message: status_message(control_deck),
+                })
+            }
+        }
+    }
+    let message = if has_signature(control_deck) {
+        status_message(control_deck)
+    } else {
+        "no status written to $6000".to_string()
+    };
+    Ok(TestResult::TimedOut { frames, message })
+}
+
+fn run_hash(control_deck: &mut ControlDeck, frame: u32, expected: u64) -> NesResult<TestResult> {
+    while control_deck.frame_number() < frame {
+        control_deck.clock_frame()?;
+        control_deck.clear_audio_samples();
+    }
+    let actual = frame_hash(control_deck.frame_buffer());
+    Ok(if actual == expected {
+        TestResult::Passed
+    } else {
+        TestResult::HashMismatch {
+            frame,
+            expected,
+            actual,
+        }
+    })
+}
+
+/// Runs a single test ROM headlessly.
+pub fn run_test_rom(rom: &TestRom, default_frames: u32) -> TestResult {
+    if let Some(reason) = &rom.ignore {
+        return TestResult::Ignored(reason.clone());
+    }
+    let run = panic::catch_unwind(AssertUnwindSafe(|| {
+        let mut control_deck = load_control_deck(&rom.path)?;
+        match rom.hash {
+            Some(hash) => run_hash(&mut control_deck, rom.frame.unwrap_or_default(), hash),
+            None => run_status(&mut control_deck, rom.frames.unwrap_or(default_frames)),
+        }
+    }));
+    match run {
+        Ok(Ok(result)) => result,
+        Ok(Err(err)) => TestResult::Error(format!("{err:#}")),
+        Err(err) => TestResult::Error(panic_message(&*err)),
+    }
+}
+
+/// Runs every test ROM in `manifest` whose name contains `filter`.
+///
+/// # Errors
+///
+/// If a directory in the manifest can't be read, an error is returned. Failures running
+/// individual ROMs are returned as results instead.
+pub fn run_manifest(
+    manifest: &TestManifest,
+    filter: Option<&str>,
+) -> NesResult<Vec<(TestRom, TestResult)>> {
+    Ok(manifest
+        .expand()?
+        .into_iter()
+        .filter(|rom| filter.map_or(true, |filter| rom.name().contains(filter)))
+        .map(|rom| {
+            let result = run_test_rom(&rom, manifest.frames);
+            (rom, result)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_manifest() {
+        let manifest: TestManifest = serde_json::from_str(
+            r#"{ "roms": [
+                { "path": "a.nes" },
+                { "path": "b.nes", "frame": 10, "hash": 5 },
+                { "path": "c.nes", "ignore": "broken" }
+            ] }"#,
+        )
+        .expect("valid manifest");
+        assert_eq!(manifest.frames, DEFAULT_FRAMES);
+        assert_eq!(manifest.roms[0].hash, None);
+        assert_eq!(manifest.roms[1].frame, Some(10));
+        assert_eq!(
+            run_test_rom(&manifest.roms[2], manifest.frames),
+            TestResult::Ignored("broken".to_string())
+        );
+    }
+
+    #[test]
+    fn missing_rom_errors() {
+        let rom = TestRom {
+            path: PathBuf::from("missing.nes"),
+            ..TestRom::default()
+        };
+        let result = run_test_rom(&rom, 1);
+        assert!(matches!(result, TestResult::Error(_)));
+        assert!(result.failed());
+    }
+}
@@ -0,0 +1,270 @@
+//! Headless emulation for scripted runs.
+//!
+//! Runs a ROM without opening a window for a number of frames, optionally starting from a save
+//! state and playing back an FCEUX `.fm2` movie for input. The final frame and console state are
+//! hashed so regression runs and CI jobs for homebrew projects can compare them against known
+//! good values, and every frame can be written out as a PNG image.
+//!
+//! Runs can also stop early when a [`StopCondition`] is met, such as a test ROM reaching the
+//! address it finishes at.
+//!
+//! Long runs that don't need video can skip drawing frames. Emulation is unaffected, and the last
+//! frames are always drawn so the hashes match a run that draws every frame.
+
+use crate::{
+    common::{Kind, NesRegion, Regional, Reset},
+    compat::frame_hash,
+    control_deck::ControlDeck,
+    cpu::Cpu,
+    determinism::state_checksum,
+    input::FourPlayer,
+    mem::RamState,
+    movie::{Fm2Frame, Fm2Movie},
+    nes::filesystem::load_data,
+    ppu::Ppu,
+    stop_condition::StopCondition,
+    video::VideoFilter,
+    NesResult,
+};
+use anyhow::{anyhow, Context};
+use pix_engine::prelude::{Image, PixelFormat};
+use std::{
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+/// Options for a headless run.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct HeadlessOptions {
+    /// Frames to run. Defaults to the length of the movie.
+    pub frames: Option<u32>,
+    /// An `.fm2` movie to play back for input.
+    pub movie: Option<PathBuf>,
+    /// A save state file to load before running.
+    pub state: Option<PathBuf>,
+    /// Stop when the movie ends, even if fewer than `frames` frames have run.
+    pub exit_on_end: bool,
+    /// Frames to skip drawing between each drawn frame.
+    pub frame_skip: u32,
+    pub ram_state: RamState,
+    pub genie_codes: Vec<String>,
+    /// Conditions checked after every instruction that end the run early.
+    pub stop_conditions: Vec<StopCondition>,
+}
+
+/// Outcome of a headless run.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct HeadlessSummary {
+    pub frames: u32,
+    /// Hash of the last frame, matching the hashes in compatibility sweep reports.
+    pub frame_hash: u64,
+    /// Checksum of the console state after the last frame.
+    pub state_hash: u64,
+    /// The stop condition that ended the run early.
+    pub stopped: Option<StopCondition>,
+}
+
+fn load(rom: &Path, movie: Option<&Fm2Movie>, options: &HeadlessOptions) -> NesResult<ControlDeck> {
+    let ram_state = movie
+        .and_then(Fm2Movie::ram_state)
+        .unwrap_or(options.ram_state);
+    let mut control_deck = ControlDeck::new(ram_state);
+    let mut rom_file =
+        BufReader::new(File::open(rom).with_context(|| format!("failed to open rom {rom:?}"))?);
+    control_deck.load_rom(rom.to_string_lossy(), &mut rom_file)?;
+    if let Some(movie) = movie {
+        if movie.pal {
+            control_deck.set_region(NesRegion::Pal);
+        }
+        if movie.four_score {
+            control_deck.set_four_player(FourPlayer::FourScore);
+        }
+        if let Some(alignment) = movie.ppu_alignment() {
+            control_deck.set_ppu_alignment(alignment);
+        }
+        control_deck.reset(Kind::Hard);
+    }
+    // Hash the raw palette output so results don't depend on filter noise
+    control_deck.set_filter(VideoFilter::Pixellate);
+    for code in &options.genie_codes {
+        control_deck.add_genie_code(code.clone())?;
+    }
+    if let Some(state) = &options.state {
+        let cpu: Cpu = bincode::deserialize(&load_data(state)?)
+            .with_context(|| format!("failed to deserialize save state {state:?}"))?;
+        control_deck.try_load_cpu(cpu)?;
+    }
+    Ok(control_deck)
+}
+
+/// Runs `rom` headlessly, calling `on_frame` with the frame number after each frame. Frames that
+/// weren't drawn because of `frame_skip` are reported by `ControlDeck::frame_skipped`, and
+/// `on_frame` can draw every frame again with `ControlDeck::set_frame_skip`.
+///
+/// # Errors
+///
+/// If the ROM, movie or save state fail to load, no frame count is given without a movie, or
+/// emulation fails, an error is returned.
+pub fn run<F>(rom: &Path, options: &HeadlessOptions, mut on_frame: F) -> NesResult<HeadlessSummary>
+where
+    F: FnMut(u32, &mut ControlDeck) -> NesResult<()>,
+{
+    let movie = options
+        .movie
+        .as_ref()
+        .map(|movie| -> NesResult<Fm2Movie> {
+            fs::read_to_string(movie)
+                .with_context(|| format!("failed to read movie {movie:?}"))?
+                .parse()
+        })
+        .transpose()?;
+    let movie_frames = movie.as_ref().map(|movie| movie.frames.len() as u32);
+    let mut frames = options
+        .frames
+        .or(movie_frames)
+        .ok_or_else(|| anyhow!("a frame count is required without a movie"))?;
+    if let Some(movie_frames) = movie_frames.filter(|_| options.exit_on_end) {
+        frames = frames.min(movie_frames);
+    }
+
+    let mut control_deck = load(rom, movie.as_ref(), options)?;
+    control_deck.set_frame_skip(options.frame_skip);
+    let mut frames_run = frames;
+    let mut stopped = None;
+    for frame_number in 0..frames {
+        // Draw the last two frames so both frame buffers match a run without frame skip
+        if frame_number + 2 >= frames {
+            control_deck.set_frame_skip(0);
+        }
+        if let Some(movie) = &movie {
+            // Release every button once the movie ends
+            movie
+                .frames
+                .get(frame_number as usize)
+                .copied()
+                .unwrap_or_default()
+                .apply(&mut control_deck);
+        }
+        if options.stop_conditions.is_empty() {
+            control_deck.clock_frame()?;
+        } else {
+            control_deck.clock_frame_until(|cpu| {
+                stopped = StopCondition::first_met(&options.stop_conditions, cpu);
+                stopped.is_some()
+            })?;
+        }
+        control_deck.clear_audio_samples();
+        on_frame(frame_number, &mut control_deck)?;
+        if stopped.is_some() {
+            frames_run = frame_number + 1;
+            break;
+        }
+    }
+
+    Ok(HeadlessSummary {
+        frames: frames_run,
+        frame_hash: frame_hash(control_deck.frame_buffer()),
+        state_hash: state_checksum(&control_deck)?,
+        stopped,
+    })
+}
+
+/// Runs `rom` headlessly, writing every drawn frame to `output` as `frame_000000.png`, numbered
+/// from zero.
+///
+/// # Errors
+///
+/// If the run fails or a frame fails to save, an error is returned.
+pub fn dump_frames<O: AsRef<Path>>(
+    rom: &Path,
+    options: &HeadlessOptions,
+    output: O,
+) -> NesResult<HeadlessSummary> {
+    let output = output.as_ref();
+    fs::create_dir_all(output).with_context(|| format!("failed to create {output:?}"))?;
+    run(rom, options, |frame_number, control_deck| {
+        if control_deck.frame_skipped() {
+            return Ok(());
+        }
+        let path = output.join(format!("frame_{frame_number:06}.png"));
+        Image::from_bytes(
+            Ppu::WIDTH,
+            Ppu::HEIGHT,
+            control_deck.frame_buffer(),
+            PixelFormat::Rgba,
+        )
+        .and_then(|image| image.save(&path))
+        .with_context(|| format!("failed to save frame {path:?}"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_run() {
+        let rom = Path::new("test_roms/cpu/nestest.nes");
+        let options = HeadlessOptions {
+            frames: Some(10),
+            ram_state: RamState::AllZeros,
+            ..HeadlessOptions::default()
+        };
+        let mut count = 0;
+        let first = run(rom, &options, |_, _| {
+            count += 1;
+            Ok(())
+        })
+        .expect("ran rom");
+        assert_eq!(count, 10);
+        assert_eq!(first.frames, 10);
+        let second = run(rom, &options, |_, _| Ok(())).expect("ran rom");
+        assert_eq!(first, second);
+
+        let no_frames = HeadlessOptions::default();
+        assert!(run(rom, &no_frames, |_, _| Ok(())).is_err());
+    }
+
+    #[test]
+    fn stop_condition() {
+        let rom = Path::new("test_roms/cpu/nestest.nes");
+        let condition = "mem=0000:00/2".parse().expect("valid condition");
+        let options = HeadlessOptions {
+            frames: Some(10),
+            ram_state: RamState::AllZeros,
+            stop_conditions: vec![condition],
+            ..HeadlessOptions::default()
+        };
+        let summary = run(rom, &options, |_, _| Ok(())).expect("ran rom");
+        assert_eq!(summary.frames, 1);
+        assert_eq!(summary.stopped, Some(condition));
+    }
+
+    #[test]
+    fn frame_skip() {
+        let rom = Path::new("test_roms/cpu/nestest.nes");
+        let options = HeadlessOptions {
+            frames: Some(30),
+            ram_state: RamState::AllZeros,
+            ..HeadlessOptions::default()
+        };
+        let skip_options = HeadlessOptions {
+            frame_skip: 3,
+            ..options.clone()
+        };
+        let mut drawn = 0;
+        let skipped = run(rom, &skip_options, |_, control_deck| {
+            if !control_deck.frame_skipped() {
+                drawn += 1;
+            }
+            Ok(())
+        })
+        .expect("ran rom");
+        assert!(drawn < 30 / 2, "drew {drawn} frames");
+        let full = run(rom, &options, |_, _| Ok(())).expect("ran rom");
+        assert_eq!(skipped, full);
+    }
+}
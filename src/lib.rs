@@ -40,26 +40,25 @@
     html_logo_url = "https://github.com/lukexor/tetanes/blob/main/static/tetanes_icon.png?raw=true"
 )]
 
-pub mod audio;
-pub mod genie;
+pub use tetanes_core::{
+    apu, bus, cart, common, control_deck, cpu, genie, input, legacy, mapper, mem, ppu, profiling,
+    trace, video, NesError, NesResult,
+};
 
-pub mod apu;
-pub mod bus;
-pub mod cart;
-#[macro_use]
-pub mod common;
-pub mod control_deck;
-pub mod cpu;
+pub mod audio;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod compat;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod debugger;
-pub mod input;
-pub mod mapper;
-pub mod mem;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod determinism;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod harness;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod headless;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod movie;
 #[cfg(not(target_arch = "wasm32"))]
 pub mod nes;
-pub mod ppu;
-pub mod video;
-pub mod trace;
-
-pub type NesError = anyhow::Error;
-pub type NesResult<T> = anyhow::Result<T, NesError>;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod stop_condition;
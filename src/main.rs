@@ -2,24 +2,65 @@
 //!
 //! USAGE:
 //!     tetanes [FLAGS] [OPTIONS] [path]
+//!     tetanes [FLAGS] [OPTIONS] <SUBCOMMAND>
 //!
 //! FLAGS:
-//!     -f, --fullscreen    Start fullscreen.
-//!     -h, --help          Prints help information
-//!     -V, --version       Prints version information
+//!         --audio-hash       Print a hash of the `--dump-movie` audio instead of dumping.
+//!     -f, --fullscreen       Start fullscreen.
+//!     -h, --help             Prints help information
+//!         --no-cheats        Disable Game Genie codes for this launch.
+//!     -V, --version          Prints version information
+//!         --write-protect    Write-protect PRG-RAM for this launch.
+//!         --zapper           Connect the Zapper for this launch.
 //!
 //! OPTIONS:
-//!     -s, --scale <scale>    Window scale [default: 3.0]
+//!     -s, --scale <scale>              Window scale [default: 3.0]
+//!         --region <region>            Start region: `ntsc`, `pal` or `dendy`.
+//!         --filter <filter>            Video filter: `pixellate` or `ntsc`.
+//!         --four-player <mode>         Four player adapter: `disabled`, `fourscore` or `satellite`.
+//!         --load-slot <slot>           Save state slot to load once the game starts.
+//!         --input-echo <target>        Echo per-frame joypad state as JSON to a file, pipe or `-`.
+//!         --battery <battery>          Override battery-backed Save RAM: `true` or `false`.
+//!         --dump-movie <dump-movie>    Headlessly dump an `.fm2` movie to a lossless AVI and WAV.
+//!     -o, --output <output>            Output path for `--dump-movie` [default: movie.avi]
+//!         --dump-codec <dump-codec>    Lossless codec for `--dump-movie`: `ffv1` or `raw`.
+//!         --migrate-saves <backend>    Copy saved data to another backend: `filesystem` or `sqlite`.
+//!         --profile <profile>          Record performance spans to a folded stack file.
+//!         --profile-level <level>      Span detail for `--profile`: `debug` or `trace`.
+//!         --set <key=value>...         Override a configuration setting, e.g. `video.filter=Ntsc`.
 //!
 //! ARGS:
 //!     <path>    The NES ROM to load, a directory containing `.nes` ROM files, or a recording
 //!               playback `.playback` file. [default: current directory]
+//!
+//! SUBCOMMANDS:
+//!     run            Run a ROM, optionally headless or for a number of frames.
+//!     play-movie     Play an `.fm2` movie headlessly and print the final hashes.
+//!     record         Run a ROM, recording a replay from the first frame.
+//!     dump-frames    Headlessly write every frame to a directory of PNG images.
+//!     verify-rom     Check that a ROM loads and boots, exiting with an error if it doesn't.
+//!     list-cheats    List the Game Genie codes enabled for a ROM.
 
 #![windows_subsystem = "windows"]
 
+use anyhow::{anyhow, bail};
 use std::{env, path::PathBuf};
 use structopt::StructOpt;
-use tetanes::{mem::RamState, nes::NesBuilder, NesResult};
+use tetanes::{
+    cart::NesHeader,
+    common::NesRegion,
+    compat::{self, SweepRom},
+    headless::{self, HeadlessOptions, HeadlessSummary},
+    input::FourPlayer,
+    mem::RamState,
+    movie,
+    nes::{self, ConfigOverride, LaunchOptions, NesBuilder, PersistenceBackend, ScriptOptions},
+    profiling,
+    stop_condition::StopCondition,
+    video::VideoFilter,
+    NesResult,
+};
+use tracing::Level;
 
 fn main() -> NesResult<()> {
     if env::var("RUST_LOG").is_err() {
@@ -27,18 +68,319 @@ fn main() -> NesResult<()> {
     }
     pretty_env_logger::init();
 
-    let opt = Opt::from_args();
-    NesBuilder::new()
+    let mut opt = Opt::from_args();
+    let _profile = opt
+        .profile
+        .as_ref()
+        .map(|path| profiling::profile_to_file(path, opt.profile_level.unwrap_or(Level::DEBUG)))
+        .transpose()?;
+    if let Some(backend) = opt.migrate_saves {
+        return nes::migrate_saves(backend);
+    }
+    if let Some(command) = opt.command.take() {
+        return run_command(opt, command);
+    }
+    if let Some(dump_movie) = opt.dump_movie {
+        let rom = opt
+            .path
+            .ok_or_else(|| anyhow!("a ROM path is required for `--dump-movie`"))?;
+        let ram_state = opt.ram_state.unwrap_or_default();
+        if opt.audio_hash {
+            let audio_hash = movie::hash_movie_audio(rom, dump_movie, ram_state)?;
+            println!("{audio_hash:016X}");
+            return Ok(());
+        }
+        return movie::dump_movie(
+            rom,
+            dump_movie,
+            opt.output.unwrap_or_else(|| PathBuf::from("movie.avi")),
+            opt.dump_codec.unwrap_or_default(),
+            ram_state,
+        );
+    }
+    run_window(opt, ScriptOptions::default())
+}
+
+/// Runs `opt.path` in a window.
+fn run_window(opt: Opt, script: ScriptOptions) -> NesResult<()> {
+    let mut nes = NesBuilder::new()
         .path(opt.path)
         .replay(opt.replay)
+        .input_echo(opt.input_echo)
         .fullscreen(opt.fullscreen)
         .ram_state(opt.ram_state)
         .scale(opt.scale)
         .speed(opt.speed)
         .genie_codes(opt.genie_codes)
+        .launch_options(LaunchOptions {
+            region: opt.region,
+            filter: opt.filter,
+            cheats: opt.no_cheats.then_some(false),
+            four_player: opt.four_player,
+            zapper: opt.zapper.then_some(true),
+            load_slot: opt.load_slot,
+            battery: opt.battery,
+            write_protect: opt.write_protect.then_some(true),
+            ..LaunchOptions::default()
+        })
+        .config_overrides(opt.set)
+        .script(script)
         .debug(opt.debug)
-        .build()?
-        .run()
+        .build()?;
+    nes.run()?;
+    if let Some(code) = nes.exit_code() {
+        std::process::exit(code);
+    }
+    Ok(())
+}
+
+/// Prints the final hashes, exiting with the status code of the stop condition that ended the
+/// run, if any.
+fn finish_headless(summary: HeadlessSummary) {
+    println!(
+        "{} frames, frame hash {:016X}, state hash {:016X}",
+        summary.frames, summary.frame_hash, summary.state_hash
+    );
+    if let Some(stopped) = summary.stopped {
+        println!("Stopped on {stopped}");
+        std::process::exit(stopped.exit_code);
+    }
+}
+
+fn headless_options(opt: &Opt, run: &RunOpt, movie: Option<PathBuf>) -> HeadlessOptions {
+    HeadlessOptions {
+        frames: run.frames,
+        movie,
+        state: run.state.clone(),
+        exit_on_end: run.exit_on_end,
+        frame_skip: run.frame_skip,
+        ram_state: opt.ram_state.unwrap_or_default(),
+        genie_codes: if opt.no_cheats {
+            vec![]
+        } else {
+            opt.genie_codes.clone()
+        },
+        stop_conditions: run.stop_at.clone(),
+    }
+}
+
+fn run_command(mut opt: Opt, command: Command) -> NesResult<()> {
+    match command {
+        Command::Run { rom, run } if run.headless => {
+            let summary = headless::run(&rom, &headless_options(&opt, &run, None), |_, _| Ok(()))?;
+            finish_headless(summary);
+        }
+        Command::Run { rom, run } => {
+            opt.path = Some(rom);
+            return run_window(
+                opt,
+                ScriptOptions {
+                    state: run.state,
+                    frames: run.frames,
+                    exit_on_end: run.exit_on_end,
+                    stop_conditions: run.stop_at,
+                    ..ScriptOptions::default()
+                },
+            );
+        }
+        Command::PlayMovie { rom, movie, run } => {
+            let summary =
+                headless::run(&rom, &headless_options(&opt, &run, Some(movie)), |_, _| {
+                    Ok(())
+                })?;
+            finish_headless(summary);
+        }
+        Command::Record { rom, output, run } => {
+            opt.path = Some(rom);
+            return run_window(
+                opt,
+                ScriptOptions {
+                    state: run.state,
+                    record: true,
+                    output,
+                    frames: run.frames,
+                    exit_on_end: run.exit_on_end,
+                    stop_conditions: run.stop_at,
+                },
+            );
+        }
+        Command::DumpFrames {
+            rom,
+            movie,
+            output,
+            run,
+        } => {
+            let summary =
+                headless::dump_frames(&rom, &headless_options(&opt, &run, movie), output)?;
+            finish_headless(summary);
+        }
+        Command::VerifyRom { rom, frames, hash } => {
+            let header = NesHeader::from_path(&rom)?;
+            println!("Mapper: {}", header.mapper_board());
+            println!(
+                "PRG-ROM: {}KB, CHR-ROM: {}KB",
+                u32::from(header.prg_rom_banks) * 16,
+                u32::from(header.chr_rom_banks) * 8
+            );
+            let result = compat::run_rom(
+                &SweepRom {
+                    path: rom,
+                    frames,
+                    replay: None,
+                    hash,
+                },
+                DEFAULT_VERIFY_FRAMES,
+                opt.ram_state.unwrap_or(RamState::AllZeros),
+            );
+            if let Some(frame) = result.first_visible_frame {
+                println!("First visible frame: {frame}");
+            }
+            if let Some(hash) = result.hash {
+                println!("Frame hash: {hash:016X}");
+            }
+            println!("{} frames run", result.frames_run);
+            if !result.status.passed() {
+                bail!(
+                    "{:?}{}",
+                    result.status,
+                    result
+                        .message
+                        .map(|message| format!(": {message}"))
+                        .unwrap_or_default()
+                );
+            }
+            println!("{:?}", result.status);
+        }
+        Command::ListCheats { rom } => {
+            for cheat in nes::list_cheats(rom)? {
+                let code = cheat.code;
+                let compare = code
+                    .compare()
+                    .map(|compare| format!(" if ${compare:02X}"))
+                    .unwrap_or_default();
+                println!(
+                    "{:<8}  ${:04X} = ${:02X}{compare}{}",
+                    code.code(),
+                    code.addr(),
+                    code.data(),
+                    if cheat.per_game { "  (game)" } else { "" }
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Frames `verify-rom` runs by default, matching the compatibility sweep.
+const DEFAULT_VERIFY_FRAMES: u32 = 600;
+
+fn parse_hash(hash: &str) -> Result<u64, std::num::ParseIntError> {
+    u64::from_str_radix(hash.trim_start_matches("0x"), 16)
+}
+
+#[derive(StructOpt, Debug, Clone)]
+#[must_use]
+enum Command {
+    #[structopt(about = "Run a ROM, optionally headless or for a number of frames.")]
+    Run {
+        #[structopt(help = "The NES ROM to run.")]
+        rom: PathBuf,
+        #[structopt(flatten)]
+        run: RunOpt,
+    },
+    #[structopt(
+        about = "Play an `.fm2` movie headlessly and print the final frame and state hashes."
+    )]
+    PlayMovie {
+        #[structopt(help = "The NES ROM the movie was recorded with.")]
+        rom: PathBuf,
+        #[structopt(help = "An FCEUX `.fm2` movie.")]
+        movie: PathBuf,
+        #[structopt(flatten)]
+        run: RunOpt,
+    },
+    #[structopt(about = "Run a ROM, recording a replay from the first frame.")]
+    Record {
+        #[structopt(help = "The NES ROM to run.")]
+        rom: PathBuf,
+        #[structopt(
+            short = "o",
+            long = "output",
+            help = "Where to save the recording, defaults to a timestamped file. The extension picks the format: `.replay`, `.json`, `.json.gz` or `.fm2`."
+        )]
+        output: Option<PathBuf>,
+        #[structopt(flatten)]
+        run: RunOpt,
+    },
+    #[structopt(about = "Headlessly write every frame to a directory of PNG images.")]
+    DumpFrames {
+        #[structopt(help = "The NES ROM to run.")]
+        rom: PathBuf,
+        #[structopt(long = "movie", help = "An FCEUX `.fm2` movie to play back for input.")]
+        movie: Option<PathBuf>,
+        #[structopt(
+            short = "o",
+            long = "output",
+            default_value = "frames",
+            help = "Directory to write frames to."
+        )]
+        output: PathBuf,
+        #[structopt(flatten)]
+        run: RunOpt,
+    },
+    #[structopt(about = "Check that a ROM loads and boots, exiting with an error if it doesn't.")]
+    VerifyRom {
+        #[structopt(help = "The NES ROM to check.")]
+        rom: PathBuf,
+        #[structopt(long = "frames", help = "Frames to run, defaults to 600.")]
+        frames: Option<u32>,
+        #[structopt(
+            long = "hash",
+            parse(try_from_str = parse_hash),
+            help = "Expected hash of the last frame, as printed by a previous run."
+        )]
+        hash: Option<u64>,
+    },
+    #[structopt(about = "List the Game Genie codes enabled for a ROM.")]
+    ListCheats {
+        #[structopt(help = "The NES ROM to list codes for.")]
+        rom: PathBuf,
+    },
+}
+
+/// Options shared by subcommands that run a ROM.
+#[derive(StructOpt, Debug, Clone)]
+#[must_use]
+struct RunOpt {
+    #[structopt(
+        long = "frames",
+        help = "Frames to run before pausing or exiting. Required with `--headless`."
+    )]
+    frames: Option<u32>,
+    #[structopt(long = "state", help = "A save state file to load before running.")]
+    state: Option<PathBuf>,
+    #[structopt(
+        long = "headless",
+        help = "Run without a window and print the final hashes."
+    )]
+    headless: bool,
+    #[structopt(
+        long = "exit-on-end",
+        help = "Exit when the frame limit is reached or the movie or replay ends, instead of pausing."
+    )]
+    exit_on_end: bool,
+    #[structopt(
+        long = "frame-skip",
+        default_value = "0",
+        help = "Headless only: skip drawing this many frames between each drawn frame, for faster long runs. The last frames are always drawn."
+    )]
+    frame_skip: u32,
+    #[structopt(
+        long = "stop-at",
+        number_of_values = 1,
+        help = "Stop when a condition is met: `pc=ADDR`, `mem=ADDR:VALUE` or `opcode=OP` in hex, optionally followed by `/CODE` to exit with. Headless runs and `--exit-on-end` exit with the code, otherwise emulation pauses. Can be repeated."
+    )]
+    stop_at: Vec<StopCondition>,
 }
 
 #[derive(StructOpt, Debug)]
@@ -58,14 +400,20 @@ struct Opt {
     #[structopt(
         short = "r",
         long = "replay",
-        help = "A `.replay` recording file for gameplay recording and playback."
+        help = "A `.replay`, `.json`, `.json.gz` or `.fm2` recording file for gameplay recording and playback."
     )]
     replay: Option<PathBuf>,
+    #[structopt(
+        long = "input-echo",
+        help = "Echo each frame's joypad state as line-delimited JSON to a file or named pipe, or `-` for stdout."
+    )]
+    input_echo: Option<PathBuf>,
     #[structopt(short = "f", long = "fullscreen", help = "Start fullscreen.")]
     fullscreen: bool,
     #[structopt(
         long = "ram_state",
-        help = "Choose power-up RAM state: 'all_zeros', `all_ones`, `random` (default)."
+        help = "Choose power-up RAM state: 'all_zeros', `all_ones`, `random` (default), `fceux` \
+                or `seeded:<seed>`."
     )]
     ram_state: Option<RamState>,
     #[structopt(short = "s", long = "scale", help = "Window scale, defaults to 3.0.")]
@@ -78,6 +426,81 @@ struct Opt {
         help = "List of Game Genie Codes (space separated)."
     )]
     genie_codes: Vec<String>,
+    #[structopt(
+        long = "region",
+        help = "Start region, overriding the detected region: `ntsc`, `pal` or `dendy`."
+    )]
+    region: Option<NesRegion>,
+    #[structopt(long = "filter", help = "Video filter: `pixellate` or `ntsc`.")]
+    filter: Option<VideoFilter>,
+    #[structopt(long = "no-cheats", help = "Disable Game Genie codes for this launch.")]
+    no_cheats: bool,
+    #[structopt(
+        long = "four-player",
+        help = "Four player adapter: `disabled`, `fourscore` or `satellite`."
+    )]
+    four_player: Option<FourPlayer>,
+    #[structopt(long = "zapper", help = "Connect the Zapper for this launch.")]
+    zapper: bool,
+    #[structopt(
+        long = "load-slot",
+        help = "Save state slot to load once the game starts."
+    )]
+    load_slot: Option<u8>,
+    #[structopt(
+        long = "battery",
+        help = "Override battery-backed Save RAM for this launch: `true` or `false`."
+    )]
+    battery: Option<bool>,
+    #[structopt(
+        long = "write-protect",
+        help = "Write-protect PRG-RAM for this launch."
+    )]
+    write_protect: bool,
+    #[structopt(
+        long = "set",
+        number_of_values = 1,
+        help = "Override a configuration setting for this session as `key=value`, e.g. `video.filter=Ntsc`. Can be repeated."
+    )]
+    set: Vec<ConfigOverride>,
     #[structopt(long = "debug", help = "Start debugging")]
     debug: bool,
+    #[structopt(
+        long = "dump-movie",
+        help = "Headlessly play an `.fm2` movie and dump it to a lossless AVI and WAV."
+    )]
+    dump_movie: Option<PathBuf>,
+    #[structopt(
+        short = "o",
+        long = "output",
+        help = "Output path for `--dump-movie`, defaults to `movie.avi`."
+    )]
+    output: Option<PathBuf>,
+    #[structopt(
+        long = "dump-codec",
+        help = "Lossless video codec for `--dump-movie`: `ffv1` (default) or `raw`."
+    )]
+    dump_codec: Option<movie::DumpCodec>,
+    #[structopt(
+        long = "audio-hash",
+        help = "Print a hash of the `--dump-movie` audio instead of dumping, to check that dumps are reproducible."
+    )]
+    audio_hash: bool,
+    #[structopt(
+        long = "migrate-saves",
+        help = "Copy Save RAM, save states and replays from the configured backend to `filesystem` or `sqlite` and switch to it."
+    )]
+    migrate_saves: Option<PersistenceBackend>,
+    #[structopt(
+        long = "profile",
+        help = "Record performance spans to a folded stack file for flamegraph tools."
+    )]
+    profile: Option<PathBuf>,
+    #[structopt(
+        long = "profile-level",
+        help = "Span detail for `--profile`: `debug` (default) or `trace`, which includes every CPU instruction."
+    )]
+    profile_level: Option<Level>,
+    #[structopt(subcommand)]
+    command: Option<Command>,
 }
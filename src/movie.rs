@@ -0,0 +1,529 @@
+//! Headless movie dumping for lossless TAS encodes.
+//!
+//! Plays back an FCEUX `.fm2` movie without opening a window and writes every emulated frame to
+//! a lossless AVI with a matching WAV file. Each frame is followed by exactly the number of audio
+//! samples dictated by the region frame rate, so the two streams never drift apart. Video
+//! encoding is delegated to an external `ffmpeg` binary which must be available on the `PATH`.
+//!
+//! Audio is rendered deterministically so the same movie always dumps bit-identical audio, which
+//! can be checked by comparing audio hashes between runs.
+
+use crate::{
+    audio::{wav::WavWriter, AudioMixer},
+    common::{Kind, NesRegion, Regional, Reset},
+    control_deck::ControlDeck,
+    input::{FourPlayer, JoypadBtnState, Slot},
+    mem::RamState,
+    ppu::Ppu,
+    video::VideoFilter,
+    NesResult,
+};
+use anyhow::{anyhow, bail, Context};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fmt,
+    fs::{self, File},
+    hash::{Hash, Hasher},
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+const FFMPEG: &str = "ffmpeg";
+const AUDIO_SAMPLE_RATE: u32 = 48_000;
+const AUDIO_BUFFER_SIZE: usize = 8192;
+
+/// FM2 frame command flags.
+pub const CMD_SOFT_RESET: u8 = 0x01;
+pub const CMD_HARD_RESET: u8 = 0x02;
+/// Header key for the power-up RAM contents of movies saved by TetaNES.
+pub const RAM_STATE_KEY: &str = "tetanesRamState";
+/// Header key for the CPU/PPU alignment of movies saved by TetaNES.
+pub const PPU_ALIGNMENT_KEY: &str = "tetanesPpuAlignment";
+
+/// Gamepad buttons in FM2 `RLDUTSBA` order.
+const FM2_BUTTONS: [(char, JoypadBtnState); 8] = [
+    ('R', JoypadBtnState::RIGHT),
+    ('L', JoypadBtnState::LEFT),
+    ('D', JoypadBtnState::DOWN),
+    ('U', JoypadBtnState::UP),
+    ('T', JoypadBtnState::START),
+    ('S', JoypadBtnState::SELECT),
+    ('B', JoypadBtnState::B),
+    ('A', JoypadBtnState::A),
+];
+
+/// Lossless video codec used for movie dumps.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub enum DumpCodec {
+    #[default]
+    Ffv1,
+    Raw,
+}
+
+impl DumpCodec {
+    const fn args(self) -> &'static [&'static str] {
+        match self {
+            Self::Ffv1 => &["-c:v", "ffv1", "-level", "3", "-pix_fmt", "bgr0"],
+            Self::Raw => &["-c:v", "rawvideo", "-pix_fmt", "bgr24"],
+        }
+    }
+}
+
+impl FromStr for DumpCodec {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ffv1" => Ok(Self::Ffv1),
+            "raw" => Ok(Self::Raw),
+            _ => Err("invalid DumpCodec value. valid options: `ffv1` or `raw`"),
+        }
+    }
+}
+
+/// A single frame of FM2 input.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct Fm2Frame {
+    pub commands: u8,
+    pub joypads: [JoypadBtnState; 4],
+}
+
+/// A parsed FCEUX `.fm2` text movie.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct Fm2Movie {
+    pub rom_filename: Option<String>,
+    pub pal: bool,
+    pub four_score: bool,
+    pub rerecord_count: u32,
+    pub frames: Vec<Fm2Frame>,
+    /// Header keys not used for playback, like comments, kept in order so they're written back
+    /// out.
+    pub extra: Vec<(String, String)>,
+}
+
+impl Fm2Movie {
+    fn extra_value(&self, key: &str) -> Option<&str> {
+        self.extra
+            .iter()
+            .find(|(extra_key, _)| extra_key == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Power-up RAM contents the movie was recorded with, if saved by TetaNES.
+    #[must_use]
+    pub fn ram_state(&self) -> Option<RamState> {
+        self.extra_value(RAM_STATE_KEY)
+            .and_then(|value| value.parse().ok())
+    }
+
+    /// CPU/PPU alignment the movie was recorded with, if saved by TetaNES.
+    #[must_use]
+    pub fn ppu_alignment(&self) -> Option<u8> {
+        self.extra_value(PPU_ALIGNMENT_KEY)
+            .and_then(|value| value.parse().ok())
+    }
+
+    /// Parses gamepad input in FM2 `RLDUTSBA` order. Any character other than `.` or space
+    /// counts as pressed.
+    fn parse_joypad(field: &str) -> JoypadBtnState {
+        field
+            .chars()
+            .zip(FM2_BUTTONS)
+            .filter(|(c, _)| !matches!(c, '.' | ' '))
+            .fold(JoypadBtnState::empty(), |state, (_, (_, button))| {
+                state | button
+            })
+    }
+
+    fn format_joypad(state: JoypadBtnState) -> String {
+        FM2_BUTTONS
+            .iter()
+            .map(|&(c, button)| if state.contains(button) { c } else { '.' })
+            .collect()
+    }
+
+    fn parse_frame(line: &str) -> NesResult<Fm2Frame> {
+        let mut fields = line.trim_end().trim_matches('|').split('|');
+        let commands = fields
+            .next()
+            .and_then(|commands| commands.trim().parse().ok())
+            .ok_or_else(|| anyhow!("invalid fm2 input line: {line:?}"))?;
+        let mut frame = Fm2Frame {
+            commands,
+            ..Fm2Frame::default()
+        };
+        // Gamepad fields are always 8 characters, anything else is a non-gamepad port
+        for (joypad, field) in frame
+            .joypads
+            .iter_mut()
+            .zip(fields.filter(|field| field.len() == 8))
+        {
+            *joypad = Self::parse_joypad(field);
+        }
+        Ok(frame)
+    }
+}
+
+impl Fm2Frame {
+    /// Applies the reset commands and joypad state for this frame to `control_deck`.
+    pub fn apply(&self, control_deck: &mut ControlDeck) {
+        if self.commands & CMD_HARD_RESET != 0 {
+            control_deck.reset(Kind::Hard);
+        } else if self.commands & CMD_SOFT_RESET != 0 {
+            control_deck.reset(Kind::Soft);
+        }
+        for (slot, state) in [Slot::One, Slot::Two, Slot::Three, Slot::Four]
+            .into_iter()
+            .zip(self.joypads)
+        {
+            let joypad = control_deck.joypad_mut(slot);
+            joypad.set_button(JoypadBtnState::all(), false);
+            joypad.set_button(state, true);
+        }
+    }
+}
+
+impl FromStr for Fm2Movie {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut movie = Self::default();
+        for line in s.lines() {
+            if line.starts_with('|') {
+                movie.frames.push(Self::parse_frame(line)?);
+                continue;
+            }
+            let Some((key, value)) = line.split_once(' ') else {
+                continue;
+            };
+            let value = value.trim();
+            match key {
+                "binary" if value == "1" => bail!("binary fm2 movies are not supported"),
+                "romFilename" => movie.rom_filename = Some(value.to_string()),
+                "palFlag" => movie.pal = value == "1",
+                "fourscore" => movie.four_score = value == "1",
+                "rerecordCount" => movie.rerecord_count = value.parse().unwrap_or_default(),
+                // Always written from the rest of the movie
+                "version" | "emuVersion" | "port0" | "port1" | "port2" => (),
+                _ => movie.extra.push((key.to_string(), value.to_string())),
+            }
+        }
+        Ok(movie)
+    }
+}
+
+/// Writes the movie as an FM2 text movie that starts from power on. The ROM checksum and GUID
+/// are left out, which FCEUX only warns about.
+impl fmt::Display for Fm2Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "version 3")?;
+        writeln!(f, "emuVersion 22020")?;
+        writeln!(f, "rerecordCount {}", self.rerecord_count)?;
+        writeln!(f, "palFlag {}", u8::from(self.pal))?;
+        if let Some(ref rom_filename) = self.rom_filename {
+            writeln!(f, "romFilename {rom_filename}")?;
+        }
+        writeln!(f, "fourscore {}", u8::from(self.four_score))?;
+        // Gamepads on both ports, or the Four Score in place of them
+        let ports = u8::from(!self.four_score);
+        writeln!(f, "port0 {ports}")?;
+        writeln!(f, "port1 {ports}")?;
+        writeln!(f, "port2 0")?;
+        for (key, value) in &self.extra {
+            writeln!(f, "{key} {value}")?;
+        }
+        let joypads = if self.four_score { 4 } else { 2 };
+        for frame in &self.frames {
+            write!(f, "|{}|", frame.commands)?;
+            for &joypad in &frame.joypads[..joypads] {
+                write!(f, "{}|", Self::format_joypad(joypad))?;
+            }
+            writeln!(f, "|")?;
+        }
+        Ok(())
+    }
+}
+
+/// Frame rate passed to the encoder as an exact ratio of the master clock.
+const fn frame_rate(region: NesRegion) -> &'static str {
+    match region {
+        NesRegion::Ntsc => "39375000/655171",
+        NesRegion::Pal | NesRegion::Dendy => "838977920/16777215",
+    }
+}
+
+/// Loads `rom` and sets up the console the way `movie` was recorded.
+fn load_movie(rom: &Path, movie: &Fm2Movie, ram_state: RamState) -> NesResult<ControlDeck> {
+    let ram_state = movie.ram_state().unwrap_or(ram_state);
+    if ram_state == RamState::Random {
+        log::warn!("random RAM state makes movie output differ between runs");
+    }
+    let mut control_deck = ControlDeck::new(ram_state);
+    let mut rom_file =
+        BufReader::new(File::open(rom).with_context(|| format!("failed to open rom {rom:?}"))?);
+    control_deck.load_rom(rom.to_string_lossy(), &mut rom_file)?;
+    if movie.pal {
+        control_deck.set_region(NesRegion::Pal);
+    }
+    if movie.four_score {
+        control_deck.set_four_player(FourPlayer::FourScore);
+    }
+    if let Some(alignment) = movie.ppu_alignment() {
+        control_deck.set_ppu_alignment(alignment);
+    }
+    // Dump the raw palette output, leaving any filtering to the final encode
+    control_deck.set_filter(VideoFilter::Pixellate);
+    control_deck.reset(Kind::Hard);
+    Ok(control_deck)
+}
+
+fn read_movie(movie: &Path) -> NesResult<Fm2Movie> {
+    fs::read_to_string(movie)
+        .with_context(|| format!("failed to read movie {movie:?}"))?
+        .parse()
+}
+
+/// Plays every frame of `movie`, passing each video frame and exactly the audio samples for that
+/// frame to `write_frame`. Returns a hash of the audio stream.
+///
+/// Audio is resampled directly rather than through the playback buffer and nothing depends on
+/// timing, so the same ROM, movie and RAM state always produce bit-identical audio.
+fn play_movie<F>(
+    control_deck: &mut ControlDeck,
+    movie: &Fm2Movie,
+    mut write_frame: F,
+) -> NesResult<u64>
+where
+    F: FnMut(&[u8], &[f32]) -> NesResult<()>,
+{
+    let mut mixer = AudioMixer::new(
+        control_deck.sample_rate(),
+        AUDIO_SAMPLE_RATE as f32,
+        AUDIO_BUFFER_SIZE,
+    );
+    let mut samples = Vec::with_capacity(AUDIO_BUFFER_SIZE);
+    let samples_per_frame =
+        f64::from(AUDIO_SAMPLE_RATE) / f64::from(control_deck.region().frame_rate());
+    let mut samples_written = 0;
+    let mut hasher = DefaultHasher::new();
+
+    for (frame_number, frame) in movie.frames.iter().enumerate() {
+        frame.apply(control_deck);
+        control_deck.clock_frame()?;
+
+        mixer.resample(control_deck.audio_samples(), &mut samples);
+        control_deck.clear_audio_samples();
+        // Pad with the last sample if the resampler came up short for this frame, any extra
+        // samples carry over to the next one
+        let target = ((frame_number + 1) as f64 * samples_per_frame).round() as usize;
+        let count = target - samples_written;
+        if samples.len() < count {
+            let last = samples.last().copied().unwrap_or_default();
+            samples.resize(count, last);
+        }
+        for sample in &samples[..count] {
+            sample.to_bits().hash(&mut hasher);
+        }
+        write_frame(control_deck.frame_buffer(), &samples[..count])?;
+        samples.drain(..count);
+        samples_written = target;
+    }
+
+    Ok(hasher.finish())
+}
+
+/// Plays `movie` on `rom` headlessly and returns a hash of the audio that would be dumped,
+/// without encoding anything. Running this twice checks that movie dumps are reproducible.
+///
+/// # Errors
+///
+/// If the ROM or movie fail to load or emulation encounters an invalid opcode, an error is
+/// returned.
+pub fn hash_movie_audio<R, M>(rom: R, movie: M, ram_state: RamState) -> NesResult<u64>
+where
+    R: AsRef<Path>,
+    M: AsRef<Path>,
+{
+    let movie = read_movie(movie.as_ref())?;
+    let mut control_deck = load_movie(rom.as_ref(), &movie, ram_state)?;
+    play_movie(&mut control_deck, &movie, |_, _| Ok(()))
+}
+
+/// Plays `movie` on `rom` headlessly, writing a lossless video to `output` and the audio to a
+/// WAV file alongside it.
+///
+/// # Errors
+///
+/// If the ROM or movie fail to load, the encoder fails, or emulation encounters an invalid
+/// opcode, an error is returned.
+pub fn dump_movie<R, M, O>(
+    rom: R,
+    movie: M,
+    output: O,
+    codec: DumpCodec,
+    ram_state: RamState,
+) -> NesResult<()>
+where
+    R: AsRef<Path>,
+    M: AsRef<Path>,
+    O: AsRef<Path>,
+{
+    let output = output.as_ref();
+    let movie = read_movie(movie.as_ref())?;
+    let mut control_deck = load_movie(rom.as_ref(), &movie, ram_state)?;
+
+    let region = control_deck.region();
+    log::info!(
+        "Dumping {} frames of {:?} to {output:?}",
+        movie.frames.len(),
+        movie.rom_filename.as_deref().unwrap_or_default(),
+    );
+
+    let mut encoder = Command::new(FFMPEG)
+        .args(["-y", "-loglevel", "error"])
+        .args(["-f", "rawvideo", "-pixel_format", "rgba"])
+        .args(["-video_size", &format!("{}x{}", Ppu::WIDTH, Ppu::HEIGHT)])
+        .args(["-framerate", frame_rate(region)])
+        .args(["-i", "-"])
+        .args(codec.args())
+        .arg(output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("failed to start `ffmpeg`. is it installed?")?;
+    let mut video = encoder
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow!("failed to open encoder input"))?;
+
+    let audio_path = output.with_extension("wav");
+    let mut wav = WavWriter::new(
+        BufWriter::new(
+            File::create(&audio_path)
+                .with_context(|| format!("failed to create file {audio_path:?}"))?,
+        ),
+        AUDIO_SAMPLE_RATE,
+        1,
+    )?;
+
+    let audio_hash = play_movie(&mut control_deck, &movie, |frame, samples| {
+        video
+            .write_all(frame)
+            .context("failed to write video frame")?;
+        wav.write_samples(samples)
+    })?;
+
+    wav.finish()?;
+    // Closing stdin signals the encoder to finish
+    drop(video);
+    let status = encoder.wait().context("failed to wait for video encoder")?;
+    if !status.success() {
+        return Err(anyhow!("video encoder exited with {status}"));
+    }
+    log::info!("Dumped movie to {output:?} and {audio_path:?}, audio hash {audio_hash:016X}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_fm2() {
+        let movie: Fm2Movie = "version 3\nromFilename Test Game\npalFlag 0\n\
+            |0|........|........||\n\
+            |1|R......A|...T....||\n"
+            .parse()
+            .expect("valid movie");
+        assert_eq!(movie.rom_filename.as_deref(), Some("Test Game"));
+        assert!(!movie.pal);
+        assert_eq!(movie.frames.len(), 2);
+        assert_eq!(movie.frames[0], Fm2Frame::default());
+        assert_eq!(movie.frames[1].commands, CMD_SOFT_RESET);
+        assert_eq!(
+            movie.frames[1].joypads[0],
+            JoypadBtnState::RIGHT | JoypadBtnState::A
+        );
+        assert_eq!(movie.frames[1].joypads[1], JoypadBtnState::START);
+    }
+
+    #[test]
+    fn write_fm2() {
+        let mut frame = Fm2Frame {
+            commands: CMD_HARD_RESET,
+            ..Fm2Frame::default()
+        };
+        frame.joypads[1] = JoypadBtnState::LEFT | JoypadBtnState::B;
+        let movie = Fm2Movie {
+            rom_filename: Some("Test Game".to_string()),
+            rerecord_count: 12,
+            frames: vec![Fm2Frame::default(), frame],
+            ..Fm2Movie::default()
+        };
+        let text = movie.to_string();
+        assert!(text.contains("|2|........|.L....B.||\n"), "{text}");
+        assert_eq!(text.parse::<Fm2Movie>().expect("valid movie"), movie);
+    }
+
+    #[test]
+    fn recorded_initial_state() {
+        let movie: Fm2Movie = "version 3\ntetanesRamState seeded:42\ntetanesPpuAlignment 2\n"
+            .parse()
+            .expect("valid movie");
+        assert_eq!(movie.ram_state(), Some(RamState::Seeded(42)));
+        assert_eq!(movie.ppu_alignment(), Some(2));
+        assert_eq!(Fm2Movie::default().ram_state(), None);
+    }
+
+    #[test]
+    fn keep_extra_keys() {
+        let movie: Fm2Movie = "version 3\nguid 1234\ncomment author Someone\nport0 1\n"
+            .parse()
+            .expect("valid movie");
+        assert_eq!(
+            movie.extra,
+            [
+                ("guid".to_string(), "1234".to_string()),
+                ("comment".to_string(), "author Someone".to_string()),
+            ]
+        );
+        let text = movie.to_string();
+        assert!(
+            text.contains("guid 1234\ncomment author Someone\n"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn movie_audio_is_deterministic() {
+        let rom = Path::new("test_roms/cpu/nestest.nes");
+        let movie = Fm2Movie {
+            frames: vec![Fm2Frame::default(); 30],
+            ..Fm2Movie::default()
+        };
+        let audio_hash = || -> NesResult<(u64, usize)> {
+            let mut control_deck = load_movie(rom, &movie, RamState::AllZeros)?;
+            let mut sample_count = 0;
+            let audio_hash = play_movie(&mut control_deck, &movie, |frame, samples| {
+                assert_eq!(frame.len(), 4 * Ppu::SIZE);
+                sample_count += samples.len();
+                Ok(())
+            })?;
+            Ok((audio_hash, sample_count))
+        };
+        let (first, sample_count) = audio_hash().expect("played movie");
+        assert_eq!(audio_hash().expect("played movie"), (first, sample_count));
+        let expected =
+            30.0 * f64::from(AUDIO_SAMPLE_RATE) / f64::from(NesRegion::Ntsc.frame_rate());
+        assert_eq!(sample_count, expected.round() as usize);
+    }
+
+    #[test]
+    fn reject_binary_fm2() {
+        assert!("version 3\nbinary 1\n".parse::<Fm2Movie>().is_err());
+    }
+}
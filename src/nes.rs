@@ -2,37 +2,142 @@
 
 use crate::{
     audio::AudioMixer,
-    common::Regional,
+    cart::GameTitle,
+    common::{config_dir, NesRegion, Regional},
     control_deck::ControlDeck,
+    cpu::Cpu,
+    debugger::{console::Patches, symbols::Symbols},
     input::Slot,
+    mapper::AudioChip,
     mem::RamState,
     nes::{
+        adaptive_filter::AdaptiveFilter,
         apu_viewer::ApuViewer,
+        bezel::Bezel,
+        clip_capture::ClipBuffer,
+        command_palette::CommandPalette,
+        controllers::Controllers,
         debug::Debugger,
+        diff_overlay::DiffOverlay,
+        dpcm_tool::DpcmTool,
+        feedback::Feedback,
+        frame_pacing::{FramePacer, SyncMode},
+        gallery::Gallery,
+        hd_pack::HdPackState,
+        keybinds::KeybindEditor,
+        latency::LatencyMonitor,
+        latency_test::LatencyTest,
+        menu::{changelog::Release, settings_search::SettingsSearch},
+        menu_nav::MenuNav,
+        mixer::MixerSettings,
+        motion_aim::MotionAim,
+        nametable_viewer::NametableViewer,
+        oam_viewer::OamViewer,
+        osd::FrameTimes,
+        palette_viewer::PaletteViewer,
+        persistence::{Filesystem, Persistence},
+        platform::IdleDim,
         ppu_viewer::PpuViewer,
-        state::{Replay, ReplayMode},
+        rom_patch::RomPatches,
+        rom_watch::RomWatch,
+        rpc::RpcServer,
+        screenshot::Burst,
+        script::Script,
+        session_replay::SessionReplay,
+        sound_recording::SoundRecorder,
+        sram_editor::SramEditor,
+        state::{Replay, ReplayMode, QUICK_SLOT_COUNT},
+        state_bookmarks::StateBookmarks,
+        state_labels::{RamMap, SAVE_SLOT_COUNT},
+        tas_editor::TasEditor,
+        turbo::Turbo,
+        tutorial::Tutorial,
+        video_recording::VideoRecorder,
     },
     ppu::Ppu,
+    stop_condition::StopCondition,
     NesResult,
 };
 use config::Config;
+use config_reload::ConfigWatch;
+#[cfg(not(target_arch = "wasm32"))]
+use input_echo::InputEcho;
 use menu::Menu;
 use pix_engine::prelude::*;
-use std::{
-    collections::{hash_map::Entry, HashMap, VecDeque},
-    env,
-    path::PathBuf,
-    time::Instant,
-};
+#[cfg(not(target_arch = "wasm32"))]
+use platform::ScreenSaver;
+#[cfg(not(target_arch = "wasm32"))]
+use power::PowerMonitor;
+use std::{collections::VecDeque, env, ops::ControlFlow, path::PathBuf, time::Instant};
 
+pub(crate) mod adaptive_filter;
 pub(crate) mod apu_viewer;
+pub(crate) mod bezel;
+pub(crate) mod clip_capture;
+pub(crate) mod command_palette;
 pub(crate) mod config;
+pub(crate) mod config_reload;
+pub(crate) mod controllers;
 pub(crate) mod debug;
+pub(crate) mod diff_overlay;
+pub(crate) mod dpcm_tool;
 pub(crate) mod event;
+pub(crate) mod feedback;
 pub(crate) mod filesystem;
+pub(crate) mod frame_pacing;
+pub(crate) mod gallery;
+pub(crate) mod greenzone;
+pub(crate) mod hd_pack;
+pub(crate) mod initial_state;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod input_echo;
+pub(crate) mod input_sharing;
+pub(crate) mod interrupt_overlay;
+pub(crate) mod keybinds;
+pub(crate) mod latency;
+pub(crate) mod latency_test;
+pub(crate) mod launch;
+pub(crate) mod legacy;
 pub(crate) mod menu;
+pub(crate) mod menu_nav;
+pub(crate) mod mixer;
+pub(crate) mod motion_aim;
+pub(crate) mod nametable_viewer;
+pub(crate) mod oam_viewer;
+pub(crate) mod osd;
+pub(crate) mod palette_viewer;
+pub(crate) mod persistence;
+pub(crate) mod platform;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod power;
 pub(crate) mod ppu_viewer;
+pub(crate) mod region_check;
+pub(crate) mod replay_controls;
+pub(crate) mod replay_format;
+pub(crate) mod rom_patch;
+pub(crate) mod rom_watch;
+pub(crate) mod rpc;
+pub(crate) mod screenshot;
+pub(crate) mod script;
+pub(crate) mod scroll_overlay;
+pub(crate) mod session_replay;
+pub(crate) mod sound_recording;
+pub(crate) mod sram_editor;
 pub(crate) mod state;
+pub(crate) mod state_bookmarks;
+pub(crate) mod state_labels;
+pub(crate) mod tas_editor;
+pub(crate) mod title;
+pub(crate) mod turbo;
+pub(crate) mod tutorial;
+pub(crate) mod video_recording;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod window_options;
+
+pub use config::ConfigOverride;
+pub use launch::{list_cheats, GameCheat, LaunchOptions};
+pub use persistence::{migrate_saves, PersistenceBackend};
+pub use script::ScriptOptions;
 
 const APP_NAME: &str = "TetaNES";
 #[cfg(not(target_arch = "wasm32"))]
@@ -48,11 +153,15 @@ pub(crate) const NES_FRAME_SRC: Rect<i32> = rect![0, 8, Ppu::WIDTH as i32, Ppu::
 pub struct NesBuilder {
     path: PathBuf,
     replay: Option<PathBuf>,
+    input_echo: Option<PathBuf>,
     fullscreen: bool,
     ram_state: Option<RamState>,
     scale: Option<f32>,
     speed: Option<f32>,
     genie_codes: Vec<String>,
+    launch_options: Option<LaunchOptions>,
+    config_overrides: Vec<ConfigOverride>,
+    script: ScriptOptions,
     debug: bool,
 }
 
@@ -62,11 +171,15 @@ impl NesBuilder {
         Self {
             path: PathBuf::new(),
             replay: None,
+            input_echo: None,
             fullscreen: false,
             ram_state: None,
             scale: None,
             speed: None,
             genie_codes: vec![],
+            launch_options: None,
+            config_overrides: vec![],
+            script: ScriptOptions::default(),
             debug: false,
         }
     }
@@ -89,6 +202,16 @@ impl NesBuilder {
         self
     }
 
+    /// A file or named pipe to echo each frame's joypad state to as line-delimited JSON, or `-`
+    /// for stdout.
+    pub fn input_echo<P>(&mut self, target: Option<P>) -> &mut Self
+    where
+        P: Into<PathBuf>,
+    {
+        self.input_echo = target.map(Into::into);
+        self
+    }
+
     /// Enables fullscreen mode.
     pub fn fullscreen(&mut self, val: bool) -> &mut Self {
         self.fullscreen = val;
@@ -119,6 +242,25 @@ impl NesBuilder {
         self
     }
 
+    /// Set launch options for the initial ROM, overriding any saved for that game.
+    pub fn launch_options(&mut self, options: LaunchOptions) -> &mut Self {
+        self.launch_options = Some(options);
+        self
+    }
+
+    /// Override configuration settings for this session, reapplied whenever the configuration
+    /// file is reloaded.
+    pub fn config_overrides(&mut self, overrides: Vec<ConfigOverride>) -> &mut Self {
+        self.config_overrides = overrides;
+        self
+    }
+
+    /// Set options for a scripted run of the initial ROM.
+    pub fn script(&mut self, options: ScriptOptions) -> &mut Self {
+        self.script = options;
+        self
+    }
+
     pub fn debug(&mut self, debug: bool) -> &mut Self {
         self.debug = debug;
         self
@@ -130,7 +272,7 @@ impl NesBuilder {
     ///
     /// If the default configuration directories and files can't be created, an error is returned.
     pub fn build(&self) -> NesResult<Nes> {
-        let mut config = Config::load();
+        let mut config = Config::load_with_overrides(&self.config_overrides);
         config.rom_path = self.path.clone().canonicalize()?;
         config.fullscreen = self.fullscreen || config.fullscreen;
         config.ram_state = self.ram_state.unwrap_or(config.ram_state);
@@ -140,16 +282,30 @@ impl NesBuilder {
 
         let mut control_deck = ControlDeck::new(config.ram_state);
         control_deck.set_region(config.region);
+        control_deck.set_ppu_alignment(config.ppu_alignment);
         control_deck.set_filter(config.filter);
+        control_deck.set_color_filter(config.color_filter, config.color_filter_simulate);
         control_deck.set_four_player(config.four_player);
+        control_deck.set_apu_mixing(config.apu_mixing);
+        control_deck.set_dmc_pop_reduction(config.dmc_pop_reduction);
+        control_deck.set_dip_switches(config.dip_switches);
+        control_deck.set_cycle_accurate(config.cycle_accurate);
+        control_deck.set_unofficial_opcodes(config.unofficial_opcodes);
+        control_deck.set_state_cheats(config.save_state_cheats);
+        for chip in AudioChip::as_slice() {
+            control_deck.set_audio_chip_gain(*chip, config.audio_chip_gain(*chip));
+        }
         control_deck.connect_zapper(config.zapper);
 
-        Ok(Nes::new(
-            control_deck,
-            config,
-            self.replay.clone(),
-            self.debug,
-        ))
+        let mut nes = Nes::new(control_deck, config, self.replay.clone(), self.debug);
+        nes.launch_options = self.launch_options.clone();
+        nes.config_watch = ConfigWatch::new(self.config_overrides.clone());
+        nes.script.options = self.script.clone();
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(ref target) = self.input_echo {
+            nes.input_echo = Some(InputEcho::spawn(target)?);
+        }
+        Ok(nes)
     }
 }
 
@@ -180,24 +336,85 @@ impl Default for Mode {
 pub struct Nes {
     control_deck: ControlDeck,
     audio: AudioMixer,
-    players: HashMap<Slot, ControllerId>,
+    audio_devices: Vec<String>,
+    mixer: MixerSettings,
+    controllers: Controllers,
+    feedback: Feedback,
     emulation: Option<(WindowId, TextureId)>,
     debugger: Option<Debugger>,
+    /// Debugger console patches to write again after every reset.
+    patches: Patches,
+    rom_patches: RomPatches,
+    /// Labels from symbol files for the debugger.
+    symbols: Symbols,
     ppu_viewer: Option<PpuViewer>,
+    nametable_viewer: Option<NametableViewer>,
+    oam_viewer: Option<OamViewer>,
+    palette_viewer: Option<PaletteViewer>,
     apu_viewer: Option<ApuViewer>,
+    tas_editor: Option<TasEditor>,
+    dpcm_tool: Option<DpcmTool>,
+    sram_editor: Option<SramEditor>,
+    scroll_overlay: bool,
+    interrupt_overlay: bool,
+    diff_overlay: DiffOverlay,
+    bezel: Bezel,
+    hd_pack: HdPackState,
     config: Config,
+    config_watch: ConfigWatch,
+    rpc: Option<RpcServer>,
+    persistence: Box<dyn Persistence>,
+    launch_options: Option<LaunchOptions>,
+    launch_restore: Option<LaunchOptions>,
+    script: Script,
     mode: Mode,
     replay_path: Option<PathBuf>,
-    record_sound: bool,
+    sound_recorder: Option<SoundRecorder>,
+    video_recorder: Option<VideoRecorder>,
+    clip: ClipBuffer,
+    frame_times: FrameTimes,
+    adaptive_filter: AdaptiveFilter,
+    latency: LatencyMonitor,
+    latency_test: Option<LatencyTest>,
+    gallery: Gallery,
+    screenshot_burst: Option<Burst>,
     debug: bool,
     rewind_frame: u32,
     rewind_buffer: VecDeque<Vec<u8>>,
+    session_replay: SessionReplay,
+    quick_slots: [Option<Vec<u8>>; QUICK_SLOT_COUNT],
+    ram_map: Option<RamMap>,
+    state_labels: [Option<String>; SAVE_SLOT_COUNT],
+    state_bookmarks: StateBookmarks,
     replay: Replay,
     messages: Vec<(String, Instant)>,
     paths: Vec<PathBuf>,
+    rom_titles: Vec<Option<GameTitle>>,
+    rom_watch: RomWatch,
+    unicode_font: Option<Font>,
     selected_path: usize,
     error: Option<String>,
     confirm_quit: Option<(String, bool)>,
+    /// Region the loaded game was made for, while warning it's running with the wrong timing.
+    region_warning: Option<NesRegion>,
+    command_palette: CommandPalette,
+    settings_search: SettingsSearch,
+    keybinds: KeybindEditor,
+    menu_nav: MenuNav,
+    turbo: Turbo,
+    /// Pad Player One drives while alternating pads.
+    shared_pad: Slot,
+    tutorial: Tutorial,
+    whats_new: Vec<Release>,
+    frame_pacer: FramePacer,
+    motion_aim: MotionAim,
+    idle_dim: IdleDim,
+    #[cfg(not(target_arch = "wasm32"))]
+    input_echo: Option<InputEcho>,
+    #[cfg(not(target_arch = "wasm32"))]
+    power_monitor: PowerMonitor,
+    #[cfg(not(target_arch = "wasm32"))]
+    screensaver: ScreenSaver,
     #[cfg(feature = "profile-rate-control")]
     stats: std::io::BufWriter<std::fs::File>,
 }
@@ -209,32 +426,96 @@ impl Nes {
         replay_path: Option<PathBuf>,
         debug: bool,
     ) -> Self {
-        let audio = AudioMixer::new(
+        let mut audio = AudioMixer::new(
             control_deck.sample_rate(),
-            config.audio_sample_rate / config.speed,
-            config.audio_buffer_size,
+            config.audio_sample_rate,
+            config.device_buffer_size(),
         );
+        audio.set_speed(config.speed);
+        audio.set_target_latency(config.audio_latency / 1000.0);
+        let persistence = persistence::open(config.persistence).unwrap_or_else(|err| {
+            log::error!("{err:?}, falling back to filesystem persistence");
+            Box::new(Filesystem::new(config_dir()))
+        });
+        let unicode_font = title::unicode_font(config.unicode_font.as_ref());
         Self {
             control_deck,
             audio,
-            players: HashMap::new(),
+            audio_devices: vec![],
+            mixer: config.mixer,
+            controllers: Controllers::default(),
+            feedback: Feedback::default(),
             emulation: None,
             debugger: None,
+            patches: Patches::default(),
+            rom_patches: RomPatches::default(),
+            symbols: Symbols::default(),
             ppu_viewer: None,
+            nametable_viewer: None,
+            oam_viewer: None,
+            palette_viewer: None,
             apu_viewer: None,
+            tas_editor: None,
+            dpcm_tool: None,
+            sram_editor: None,
+            scroll_overlay: false,
+            interrupt_overlay: false,
+            diff_overlay: DiffOverlay::default(),
+            bezel: Bezel::default(),
+            hd_pack: HdPackState::default(),
             config,
+            config_watch: ConfigWatch::new(vec![]),
+            rpc: None,
+            persistence,
+            launch_options: None,
+            launch_restore: None,
+            script: Script::default(),
             mode: if debug { Mode::Paused } else { Mode::default() },
             replay_path,
-            record_sound: false,
+            sound_recorder: None,
+            video_recorder: None,
+            clip: ClipBuffer::default(),
+            frame_times: FrameTimes::default(),
+            adaptive_filter: AdaptiveFilter::default(),
+            latency: LatencyMonitor::default(),
+            latency_test: None,
+            gallery: Gallery::default(),
+            screenshot_burst: None,
             debug,
             rewind_frame: 0,
             rewind_buffer: VecDeque::new(),
+            session_replay: SessionReplay::default(),
+            quick_slots: Default::default(),
+            ram_map: None,
+            state_labels: Default::default(),
+            state_bookmarks: StateBookmarks::default(),
             replay: Replay::default(),
             messages: vec![],
             paths: vec![],
+            rom_titles: vec![],
+            rom_watch: RomWatch::default(),
+            unicode_font,
             selected_path: 0,
             error: None,
             confirm_quit: None,
+            region_warning: None,
+            command_palette: CommandPalette::default(),
+            settings_search: SettingsSearch::default(),
+            keybinds: KeybindEditor::default(),
+            menu_nav: MenuNav::default(),
+            turbo: Turbo::default(),
+            shared_pad: Slot::One,
+            tutorial: Tutorial::default(),
+            whats_new: vec![],
+            frame_pacer: FramePacer::default(),
+            motion_aim: MotionAim::default(),
+            idle_dim: IdleDim::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            input_echo: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            power_monitor: PowerMonitor::default(),
+            #[cfg(not(target_arch = "wasm32"))]
+            screensaver: ScreenSaver::default(),
             #[cfg(feature = "profile-rate-control")]
             stats: std::io::BufWriter::new(std::fs::File::create("./stats.dat").unwrap()),
         }
@@ -259,6 +540,7 @@ impl Nes {
         #[cfg(not(target_arch = "wasm32"))]
         {
             engine.icon(Image::from_read(ICON)?);
+            ScreenSaver::allow_by_default();
         }
 
         if self.config.fullscreen {
@@ -267,19 +549,28 @@ impl Nes {
         if self.config.vsync {
             engine.vsync_enabled();
         }
+        if self.config.mini_view {
+            engine.borderless();
+        }
 
         engine.build()?.run(self)
     }
 
+    /// Status code to exit with after [`Nes::run`] returns, set when a stop condition ends a
+    /// scripted run.
+    #[must_use]
+    pub const fn exit_code(&self) -> Option<i32> {
+        self.script.exit_code
+    }
+
     /// Update rendering textures with emulation state
     fn render_views(&mut self, s: &mut PixState) -> PixResult<()> {
         if let Some((_, texture_id)) = self.emulation {
-            s.update_texture(
-                texture_id,
-                None,
-                self.control_deck.frame_buffer(),
-                4 * Ppu::WIDTH as usize,
-            )?;
+            let frame = self.control_deck.frame_number();
+            let _span = tracing::debug_span!("frame_present", frame).entered();
+            self.update_adaptive_filter();
+            let output = self.control_deck.frame();
+            s.update_texture(texture_id, None, output.pixels(), output.stride())?;
 
             if self.config.zapper {
                 s.set_texture_target(texture_id)?;
@@ -289,10 +580,103 @@ impl Nes {
                 s.line([x, y - 8, x, y + 8])?;
                 s.clear_texture_target();
             }
-            s.texture(texture_id, NES_FRAME_SRC, None)?;
+            match self.render_hd_pack(s)? {
+                Some((hd_texture_id, scale)) => {
+                    let scale = scale as i32;
+                    let src = rect![
+                        NES_FRAME_SRC.x() * scale,
+                        NES_FRAME_SRC.y() * scale,
+                        NES_FRAME_SRC.width() * scale,
+                        NES_FRAME_SRC.height() * scale
+                    ];
+                    self.render_frame(s, hd_texture_id, src)?;
+                }
+                None => self.render_frame(s, texture_id, NES_FRAME_SRC)?,
+            }
+            self.render_diff_overlay(s)?;
+            self.render_scroll_overlay(s)?;
+            self.render_interrupt_overlay(s)?;
+            self.render_sprite_highlight(s)?;
         }
         self.render_debugger(s)?;
         self.render_ppu_viewer(s)?;
+        self.render_nametable_viewer(s)?;
+        self.render_oam_viewer(s)?;
+        self.render_palette_viewer(s)?;
+        self.render_apu_viewer(s)?;
+        self.render_tas_editor(s)?;
+        self.render_dpcm_tool(s)?;
+        self.render_sram_editor(s)?;
+        Ok(())
+    }
+
+    /// Emulated time to run for this update.
+    fn seconds_to_run(&mut self) -> f32 {
+        let frame_rate = self.config.region.frame_rate();
+        match self.config.sync_mode {
+            // Without audio playing there's nothing to pace against, so fall back to video
+            SyncMode::Audio if self.audio_output_enabled() && self.audio.is_open() => {
+                let missing = self.audio.target_fill() - self.audio.len() as f32;
+                self.frame_pacer.seconds_to_fill(
+                    Instant::now(),
+                    missing,
+                    self.audio.output_frequency(),
+                    self.config.speed,
+                    frame_rate,
+                )
+            }
+            SyncMode::Unsynced => 1.0 / frame_rate,
+            _ => self.frame_pacer.seconds_to_run(
+                Instant::now(),
+                self.config.frame_pacing,
+                self.config.speed,
+                frame_rate,
+            ),
+        }
+    }
+
+    /// Skips drawing frames while running faster than normal speed, as at most one frame is shown
+    /// per update. The Zapper needs every frame drawn to detect light.
+    fn update_frame_skip(&mut self) {
+        let frame_skip = if self.config.fast_forward_frame_skip && !self.config.zapper {
+            self.config.speed.ceil() as u32 - 1
+        } else {
+            0
+        };
+        if frame_skip != self.control_deck.frame_skip() {
+            self.control_deck.set_frame_skip(frame_skip);
+        }
+    }
+
+    /// Handles the outcome of clocking the deck, starting from `prev_frame`.
+    fn handle_clock_result(
+        &mut self,
+        s: &mut PixState,
+        prev_frame: u32,
+        result: NesResult<ControlFlow<usize, usize>>,
+    ) -> PixResult<()> {
+        if let Some((pc, instr)) = self.control_deck.cpu().trapped_opcode() {
+            self.pause_play();
+            self.add_message(format!("Unofficial opcode {instr:?} at ${pc:04X}"));
+        }
+        match result {
+            Ok(_) => {
+                let frame = self.control_deck.frame_number();
+                if prev_frame != frame {
+                    self.update_rewind();
+                    self.update_session_replay(prev_frame, frame);
+                    self.record_video_frames(frame.wrapping_sub(prev_frame));
+                    self.capture_clip_frames(prev_frame, frame);
+                    self.capture_burst_frame();
+                    self.latency
+                        .frame_emulated(prev_frame, self.control_deck.lagged());
+                    #[cfg(not(target_arch = "wasm32"))]
+                    self.echo_input(prev_frame, frame);
+                }
+                self.process_audio()?;
+            }
+            Err(err) => return self.handle_emulation_error(s, &err),
+        }
         Ok(())
     }
 }
@@ -303,8 +687,9 @@ impl PixEngine for Nes {
         if self.config.zapper {
             s.cursor(None)?;
         }
-        self.audio.open_playback(s)?;
+        self.open_audio(s)?;
         self.set_scale(s, self.config.scale);
+        self.update_rpc_server();
         for code in self.config.genie_codes.clone() {
             if let Err(err) = self.control_deck.add_genie_code(code.clone()) {
                 log::warn!("{}", err);
@@ -317,7 +702,13 @@ impl PixEngine for Nes {
             s.window_id(),
             s.create_texture(Ppu::WIDTH, Ppu::HEIGHT, PixelFormat::Rgba)?,
         ));
+        #[cfg(not(target_arch = "wasm32"))]
+        self.apply_window_options();
+        if self.config.show_tutorial && !self.debug {
+            self.start_tutorial();
+        }
         self.load_rom(s)?;
+        self.check_whats_new(s)?;
 
         if self.debug {
             self.toggle_debugger(s)?;
@@ -327,55 +718,127 @@ impl PixEngine for Nes {
     }
 
     fn on_update(&mut self, s: &mut PixState) -> PixResult<()> {
+        let now = Instant::now();
+        self.frame_times.update(now);
+        self.latency.presented(now);
         s.clear()?;
 
+        // The test pattern replaces everything else while it's shown, and emulation stops
+        if let Some(ref mut test) = self.latency_test {
+            test.presented(now);
+            return self.render_latency_test(s);
+        }
+
         if self.replay.mode == ReplayMode::Playback {
-            self.replay_action(s)?;
+            self.replay_action(s, false)?;
         }
 
+        self.check_audio_device(s)?;
+        self.check_config_reload(s)?;
+        self.check_rom_watch();
+        self.check_symbol_reload();
+        self.handle_rpc(s)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.check_power();
+        #[cfg(not(target_arch = "wasm32"))]
+        self.update_screensaver();
+        self.update_motion_aim(s);
+        self.update_feedback();
+        self.check_script_end(s);
+        self.update_dpcm_preview();
         if self.mode == Mode::Playing {
-            // Clamp prevents wide swings in emulation speed and audio clipping due to jitter
-            let seconds_to_run = (self.config.speed * s.delta_time().as_secs_f32())
-                .clamp(0.0, self.config.speed * (1.0 / 20.0));
+            self.update_turbo();
+            self.update_frame_skip();
+        }
+
+        if self.mode == Mode::Playing && self.tas_editor.as_ref().map_or(false, TasEditor::active) {
+            self.clock_tas(s)?;
+        } else if self.mode == Mode::Playing {
+            let seconds_to_run = self.seconds_to_run();
             let prev_frame = self.control_deck.frame_number();
-            match self
-                .control_deck
-                .clock_seconds_inspect(seconds_to_run, |cpu| {
-                    if let Some(ref mut viewer) = self.ppu_viewer {
-                        if cpu.ppu().cycle() <= 3 && cpu.ppu().scanline() == viewer.scanline() {
-                            viewer.load_nametables(cpu.ppu());
-                            viewer.load_pattern_tables(cpu.ppu());
-                            viewer.load_palettes(cpu.ppu());
-                        }
+            let ppu_viewer = &mut self.ppu_viewer;
+            let nametable_viewer = &mut self.nametable_viewer;
+            let mut load_ppu_viewer = |cpu: &mut Cpu| {
+                if let Some(ref mut viewer) = ppu_viewer {
+                    if cpu.ppu().cycle() <= 3 && cpu.ppu().scanline() == viewer.scanline() {
+                        viewer.load_nametables(cpu.ppu());
+                        viewer.load_pattern_tables(cpu.ppu());
+                        viewer.load_palettes(cpu.ppu());
                     }
-                }) {
-                Ok(_) => {
-                    if prev_frame != self.control_deck.frame_number() {
-                        self.update_rewind();
-                        if self.config.sound {
-                            #[cfg(feature = "profile-rate-control")]
-                            {
-                                use std::io::Write;
-                                let frame = self.control_deck.frame_number();
-                                writeln!(
-                                    self.stats,
-                                    "{} {} {}",
-                                    frame,
-                                    self.audio.len(),
-                                    self.audio.pitch_ratio()
-                                )?;
-                            }
-                            self.audio.consume(
-                                self.control_deck.audio_samples(),
-                                self.config.dynamic_rate_control,
-                                self.config.dynamic_rate_delta,
-                            );
-                        }
-                        self.control_deck.clear_audio_samples();
+                }
+                if let Some(ref mut viewer) = nametable_viewer {
+                    if cpu.ppu().cycle() <= 3 && cpu.ppu().scanline() == viewer.scanline() {
+                        viewer.load(cpu.ppu());
                     }
                 }
-                Err(err) => return self.handle_emulation_error(s, &err),
+            };
+            let mut breakpoint_hit = None;
+            let mut trace_diverged = false;
+            let mut reset_due = None;
+            let mut stopped = None;
+            let result = match self.debugger {
+                Some(ref mut debugger) if debugger.is_active() => {
+                    debugger.break_addr = None;
+                    let breakpoints = &mut debugger.breakpoints;
+                    let trace_diff = &mut debugger.trace_diff;
+                    let scheduled_reset = debugger.scheduled_reset;
+                    self.control_deck
+                        .cpu_mut()
+                        .set_watch_writes(breakpoints.watches_writes());
+                    self.control_deck
+                        .clock_seconds_until(seconds_to_run, |cpu| {
+                            load_ppu_viewer(cpu);
+                            breakpoint_hit = breakpoints.check(cpu.pc(), cpu.writes());
+                            cpu.clear_writes();
+                            trace_diverged =
+                                trace_diff.as_mut().map_or(false, |diff| diff.check(cpu));
+                            let frame = cpu.ppu().frame_number();
+                            reset_due = scheduled_reset.filter(|reset| {
+                                reset.is_due(frame, cpu.ppu().scanline()) || reset.is_missed(frame)
+                            });
+                            breakpoint_hit.is_some() || trace_diverged || reset_due.is_some()
+                        })
+                }
+                _ if !self.script.options.stop_conditions.is_empty() => {
+                    let conditions = &self.script.options.stop_conditions;
+                    self.control_deck
+                        .clock_seconds_until(seconds_to_run, |cpu| {
+                            load_ppu_viewer(cpu);
+                            stopped = StopCondition::first_met(conditions, cpu);
+                            stopped.is_some()
+                        })
+                }
+                _ => self
+                    .control_deck
+                    .clock_seconds_inspect(seconds_to_run, load_ppu_viewer),
+            };
+            if let Some(condition) = stopped {
+                self.handle_stop_condition(s, condition);
+            }
+            if let Some(addr) = breakpoint_hit {
+                if let Some(ref mut debugger) = self.debugger {
+                    debugger.break_addr = Some(addr);
+                }
+                self.pause_play();
+                self.add_message(format!("Breakpoint hit at ${addr:04X}"));
             }
+            if let Some(reset) = reset_due {
+                self.run_scheduled_reset(s, reset)?;
+            }
+            if trace_diverged {
+                self.pause_play();
+                let diverged = self
+                    .debugger
+                    .as_ref()
+                    .and_then(|debugger| debugger.trace_diff.as_ref())
+                    .and_then(|diff| diff.divergence())
+                    .map(|divergence| divergence.line);
+                match diverged {
+                    Some(line) => self.add_message(format!("Trace diverged at line {line}")),
+                    None => self.add_message("Trace diff finished"),
+                }
+            }
+            self.handle_clock_result(s, prev_frame, result)?;
         }
 
         self.render_views(s)?;
@@ -385,25 +848,38 @@ impl PixEngine for Nes {
                     if self.render_confirm_quit(s)? {
                         s.quit();
                     }
+                } else if self.region_warning.is_some() {
+                    self.render_region_warning(s)?;
                 } else {
-                    self.render_status(s, "Paused")?;
+                    #[cfg(not(target_arch = "wasm32"))]
+                    let prompted = self.render_auto_save_prompt(s)?;
+                    #[cfg(target_arch = "wasm32")]
+                    let prompted = false;
+                    if !prompted {
+                        self.render_status(s, "Paused")?;
+                    }
                 }
             }
             Mode::InMenu(menu) => self.render_menu(s, menu)?,
-            Mode::Rewinding => {
-                self.render_status(s, "Rewinding")?;
-                self.rewind();
-            }
-            Mode::Playing => match self.replay.mode {
-                ReplayMode::Recording => self.render_status(s, "Recording Replay")?,
-                ReplayMode::Playback => self.render_status(s, "Replay Playback")?,
-                ReplayMode::Off => (),
-            },
+            Mode::Rewinding => self.rewind(),
+            Mode::Playing => (),
         }
-        if (self.config.speed - 1.0).abs() > f32::EPSILON {
-            self.render_status(s, &format!("Speed {:.2}", self.config.speed))?;
+        if !self.config.mini_view {
+            if !matches!(self.mode, Mode::InMenu(_)) {
+                self.render_osd(s)?;
+            }
+            if let Some(status) = self.turbo_status() {
+                self.render_status(s, &status)?;
+            }
+            if (self.config.speed - 1.0).abs() > f32::EPSILON {
+                self.render_status(s, &format!("Speed {:.2}", self.config.speed))?;
+            }
+            self.render_latency_status(s)?;
         }
+        self.render_motion_aim_calibration(s)?;
+        self.render_tutorial(s)?;
         self.render_messages(s)?;
+        self.render_idle_dim(s)?;
         Ok(())
     }
 
@@ -431,12 +907,18 @@ impl PixEngine for Nes {
             if self.replay.mode == ReplayMode::Recording {
                 self.stop_replay();
             }
+            self.stop_video_recording();
+            self.stop_sound_recording();
+            self.save_game_mixer();
+            self.save_state_bookmarks();
+            self.restore_launch_options();
         }
         self.save_config();
         Ok(())
     }
 
     fn on_key_pressed(&mut self, s: &mut PixState, event: KeyEvent) -> PixResult<bool> {
+        self.idle_dim.input();
         Ok(self.handle_key_event(s, event, true))
     }
 
@@ -444,12 +926,17 @@ impl PixEngine for Nes {
         Ok(self.handle_key_event(s, event, false))
     }
 
+    fn on_key_typed(&mut self, _s: &mut PixState, text: &str) -> PixResult<bool> {
+        Ok(self.type_command_query(text))
+    }
+
     fn on_mouse_pressed(
         &mut self,
         s: &mut PixState,
         btn: Mouse,
         _pos: Point<i32>,
     ) -> PixResult<bool> {
+        self.idle_dim.input();
         Ok(self.handle_mouse_click(s, btn))
     }
 
@@ -459,6 +946,7 @@ impl PixEngine for Nes {
         pos: Point<i32>,
         _rel_pos: Point<i32>,
     ) -> PixResult<bool> {
+        self.idle_dim.input();
         Ok(self.handle_mouse_motion(pos))
     }
 
@@ -470,18 +958,11 @@ impl PixEngine for Nes {
     ) -> PixResult<bool> {
         match update {
             ControllerUpdate::Added => {
-                match self.players.entry(Slot::One) {
-                    Entry::Vacant(v) => {
-                        v.insert(controller_id);
-                    }
-                    Entry::Occupied(_) => {
-                        self.players.entry(Slot::Two).or_insert(controller_id);
-                    }
-                }
+                self.connect_controller(controller_id);
                 Ok(true)
             }
             ControllerUpdate::Removed => {
-                self.players.retain(|_, &mut id| id != controller_id);
+                self.disconnect_controller(controller_id);
                 Ok(true)
             }
             ControllerUpdate::Remapped => Ok(false),
@@ -493,6 +974,7 @@ impl PixEngine for Nes {
         s: &mut PixState,
         event: ControllerEvent,
     ) -> PixResult<bool> {
+        self.idle_dim.input();
         self.handle_controller_event(s, event, true)
     }
 
@@ -527,16 +1009,39 @@ impl PixEngine for Nes {
                 } else if matches!(&self.debugger, Some(ref debugger) if debugger.window_id() == window_id)
                 {
                     self.debugger = None;
+                    self.control_deck.cpu_mut().set_watch_writes(false);
                 } else if matches!(self.ppu_viewer, Some(ref viewer) if viewer.window_id() == window_id)
                 {
                     self.ppu_viewer = None;
+                } else if matches!(self.nametable_viewer, Some(ref viewer) if viewer.window_id() == window_id)
+                {
+                    self.close_nametable_viewer();
+                } else if matches!(self.oam_viewer, Some(ref viewer) if viewer.window_id() == window_id)
+                {
+                    self.close_oam_viewer();
+                } else if matches!(self.palette_viewer, Some(ref viewer) if viewer.window_id() == window_id)
+                {
+                    self.close_palette_viewer();
                 } else if matches!(self.apu_viewer, Some(ref view) if view.window_id() == window_id)
                 {
-                    self.apu_viewer = None;
+                    self.close_apu_viewer();
+                } else if matches!(self.tas_editor, Some(ref editor) if editor.window_id() == window_id)
+                {
+                    self.tas_editor = None;
+                } else if matches!(self.dpcm_tool, Some(ref tool) if tool.window_id() == window_id)
+                {
+                    self.close_dpcm_tool();
+                } else if matches!(self.sram_editor, Some(ref editor) if editor.window_id() == window_id)
+                {
+                    self.close_sram_editor();
                 }
             }
             WindowEvent::Hidden | WindowEvent::FocusLost => {
-                if self.mode == Mode::Playing && self.config.pause_in_bg && !s.focused() {
+                if self.mode == Mode::Playing
+                    && self.config.pause_in_bg
+                    && !self.config.mini_view
+                    && !s.focused()
+                {
                     self.mode = Mode::PausedBg;
                 }
             }
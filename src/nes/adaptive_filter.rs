@@ -0,0 +1,144 @@
+//! Lowers the resolution the video filter runs at when it can't keep up, and restores full
+//! quality once there's headroom again.
+//!
+//! Filtering is timed for every new frame. When frames are arriving late and filtering takes a
+//! large share of the frame budget, the filter scale doubles so only every other scanline is
+//! filtered and the rest are repeated. The scale halves again once filtering at the higher
+//! resolution would fit comfortably, with a settling period between changes so the two don't
+//! fight each other.
+
+use crate::nes::{Mode, Nes};
+use std::time::Instant;
+
+/// Most scanlines sharing a filtered line.
+const MAX_SCALE: u32 = 4;
+/// Weight of each new filter time in the running average.
+const SMOOTHING: f32 = 0.05;
+/// Share of the frame budget filtering can take while frames are late before scaling down.
+const OVERLOAD: f32 = 0.25;
+/// Share of the frame budget filtering at the next higher resolution has to fit in to scale up.
+const HEADROOM: f32 = 0.15;
+/// Frames are late when the time between them is this much over budget.
+const LATE: f32 = 1.1;
+/// Seconds to wait after a change so the averages reflect the new scale.
+const SETTLE_SECONDS: f32 = 2.0;
+
+#[derive(Default, Debug)]
+#[must_use]
+pub(crate) struct AdaptiveFilter {
+    /// Running average of the time to filter a frame, in seconds.
+    filter_time: f32,
+    last_frame: Option<u32>,
+    last_change: Option<Instant>,
+}
+
+impl AdaptiveFilter {
+    /// Returns the filter scale to use after filtering a frame at `scale` in `filter_time`
+    /// seconds, with frames being presented `frame_time` seconds apart against a `budget`.
+    fn next_scale(
+        &mut self,
+        scale: u32,
+        filter_time: f32,
+        frame_time: f32,
+        budget: f32,
+        now: Instant,
+    ) -> u32 {
+        self.filter_time = if self.filter_time > 0.0 {
+            self.filter_time + SMOOTHING * (filter_time - self.filter_time)
+        } else {
+            filter_time
+        };
+        let settled = self.last_change.map_or(true, |last_change| {
+            now.duration_since(last_change).as_secs_f32() >= SETTLE_SECONDS
+        });
+        if !settled {
+            return scale;
+        }
+        let late = frame_time > LATE * budget;
+        let next = if late && scale < MAX_SCALE && self.filter_time > OVERLOAD * budget {
+            scale * 2
+        } else if !late && scale > 1 && 2.0 * self.filter_time < HEADROOM * budget {
+            scale / 2
+        } else {
+            return scale;
+        };
+        // Estimate the cost at the new scale until new samples come in
+        self.filter_time *= scale as f32 / next as f32;
+        self.last_change = Some(now);
+        next
+    }
+}
+
+impl Nes {
+    /// Filters the current frame, adjusting the filter scale based on how long filtering takes.
+    pub(crate) fn update_adaptive_filter(&mut self) {
+        if !self.config.adaptive_filter {
+            if self.control_deck.filter_scale() != 1 {
+                self.control_deck.set_filter_scale(1);
+            }
+            self.adaptive_filter = AdaptiveFilter::default();
+            return;
+        }
+        // Only time frames that need filtering, as already filtered frames are free
+        let frame = self.control_deck.frame_number();
+        if self.adaptive_filter.last_frame == Some(frame) {
+            return;
+        }
+        self.adaptive_filter.last_frame = Some(frame);
+        let start = Instant::now();
+        self.control_deck.frame();
+        let filter_time = start.elapsed().as_secs_f32();
+        if self.mode != Mode::Playing {
+            return;
+        }
+
+        let scale = self.control_deck.filter_scale();
+        let budget = self.config.region.frame_rate().recip();
+        let next = self.adaptive_filter.next_scale(
+            scale,
+            filter_time,
+            self.frame_times.average(),
+            budget,
+            Instant::now(),
+        );
+        if next != scale {
+            log::debug!("filter scale changed from {scale} to {next}");
+            self.control_deck.set_filter_scale(next);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn scales_with_load() {
+        let budget = 1.0 / 60.0;
+        let mut filter = AdaptiveFilter::default();
+        let mut now = Instant::now();
+
+        // Keeping up doesn't change anything
+        assert_eq!(filter.next_scale(1, 0.005, budget, budget, now), 1);
+
+        // Late frames with filtering over budget drop the resolution
+        filter = AdaptiveFilter::default();
+        assert_eq!(filter.next_scale(1, 0.008, 1.5 * budget, budget, now), 2);
+        // Nothing changes while settling
+        assert_eq!(filter.next_scale(2, 0.008, 1.5 * budget, budget, now), 2);
+        now += Duration::from_secs(3);
+        assert_eq!(filter.next_scale(2, 0.008, 1.5 * budget, budget, now), 4);
+        now += Duration::from_secs(3);
+        assert_eq!(
+            filter.next_scale(4, 0.008, 1.5 * budget, budget, now),
+            4,
+            "stays at the lowest resolution"
+        );
+
+        // Full quality comes back once there's headroom
+        filter = AdaptiveFilter::default();
+        now += Duration::from_secs(3);
+        assert_eq!(filter.next_scale(2, 0.001, budget, budget, now), 1);
+    }
+}
@@ -1,40 +1,524 @@
-use crate::nes::Nes;
+//! APU viewer window with an oscilloscope, pitch and volume readout for each channel, the last
+//! values written to the APU registers, and a piano roll of channel activity over the last few
+//! seconds.
+//!
+//! Waveforms come from the per-channel samples the core captures every CPU cycle, so they show
+//! exactly what each channel outputs before mixing.
+
+use crate::{
+    apu::{Channel, ChannelState},
+    nes::Nes,
+};
 use pix_engine::prelude::*;
+use std::collections::VecDeque;
+
+const PADDING: i32 = 10;
+const SCOPE_WIDTH: i32 = 320;
+const SCOPE_HEIGHT: i32 = 64;
+const ROW_HEIGHT: i32 = SCOPE_HEIGHT + 16;
+/// CPU cycles per oscilloscope point.
+const DECIMATION: usize = 16;
+/// Points shown in each oscilloscope, about 5ms of audio.
+const SCOPE_SAMPLES: usize = 320;
+/// Frames of history in the piano roll, filling the width of the window.
+const HISTORY: usize = 350;
+const ROLL_COLUMN: i32 = 3;
+/// MIDI notes shown in the piano roll, the range of an 88 key piano.
+const LOWEST_NOTE: f32 = 21.0;
+const HIGHEST_NOTE: f32 = 108.0;
+const NOTE_HEIGHT: i32 = 2;
+const LANE_HEIGHT: i32 = 8;
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Values per CPU cycle in the core's channel samples: each APU channel then expansion audio.
+const CHANNEL_STRIDE: usize = 6;
+
+const fn channel_rgb(channel: Channel) -> [u8; 3] {
+    match channel {
+        Channel::Pulse1 => [240, 80, 80],
+        Channel::Pulse2 => [240, 180, 60],
+        Channel::Triangle => [80, 200, 120],
+        Channel::Noise => [200, 200, 200],
+        Channel::Dmc => [160, 110, 240],
+    }
+}
+
+fn channel_color(channel: Channel, alpha: u8) -> Color {
+    let [red, green, blue] = channel_rgb(channel);
+    rgb!(red, green, blue, alpha)
+}
+
+/// A channel's state for one frame of the piano roll.
+#[derive(Default, Debug, Copy, Clone)]
+struct Activity {
+    /// MIDI note, for pitched channels.
+    note: Option<f32>,
+    /// Loudness from 0.0 to 1.0.
+    level: f32,
+}
+
+/// Recent oscilloscope points for each channel.
+#[derive(Default, Debug)]
+struct Scopes {
+    samples: [VecDeque<f32>; 5],
+    /// Cycles left until the next point.
+    skip: usize,
+}
+
+impl Scopes {
+    /// Keeps every `DECIMATION`th cycle of interleaved channel samples.
+    fn push(&mut self, samples: &[f32]) {
+        for cycle in samples.chunks_exact(CHANNEL_STRIDE) {
+            if self.skip > 0 {
+                self.skip -= 1;
+                continue;
+            }
+            self.skip = DECIMATION - 1;
+            for (scope, &sample) in self.samples.iter_mut().zip(cycle) {
+                if scope.len() == 2 * SCOPE_SAMPLES {
+                    scope.pop_front();
+                }
+                scope.push_back(sample);
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub(crate) struct ApuViewer {
     window_id: WindowId,
+    scopes: Scopes,
+    history: VecDeque<[Activity; 5]>,
+    last_frame: Option<u32>,
 }
 
 impl ApuViewer {
-    const fn new(window_id: WindowId) -> Self {
-        Self { window_id }
+    fn new(window_id: WindowId) -> Self {
+        Self {
+            window_id,
+            scopes: Scopes::default(),
+            history: VecDeque::with_capacity(HISTORY),
+            last_frame: None,
+        }
     }
 
     pub(crate) const fn window_id(&self) -> WindowId {
         self.window_id
     }
+
+    fn push_activity(&mut self, activity: [Activity; 5]) {
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(activity);
+    }
+}
+
+/// MIDI note number for `frequency`, if it's in the audible range.
+fn midi_note(frequency: f32) -> Option<f32> {
+    (20.0..=20_000.0)
+        .contains(&frequency)
+        .then(|| 69.0 + 12.0 * (frequency / 440.0).log2())
+}
+
+/// Nearest note name to `frequency` and how far off it is in cents, e.g. `A4 +2c`.
+fn note_name(frequency: f32) -> Option<String> {
+    let note = midi_note(frequency)?;
+    let nearest = note.round();
+    let cents = ((note - nearest) * 100.0).round() as i32;
+    let index = nearest as i32;
+    Some(format!(
+        "{}{} {cents:+}c",
+        NOTE_NAMES[index.rem_euclid(12) as usize],
+        index.div_euclid(12) - 1
+    ))
+}
+
+/// Whether a channel is producing sound and how loud, for the piano roll.
+fn channel_activity(channel: Channel, state: ChannelState) -> Activity {
+    let playing = state.enabled && state.length > 0;
+    match channel {
+        Channel::Pulse1 | Channel::Pulse2 | Channel::Triangle => {
+            // Pulse periods under 8 are silenced by the sweep unit
+            let audible =
+                playing && state.volume > 0 && (channel == Channel::Triangle || state.period >= 8);
+            Activity {
+                note: audible.then(|| midi_note(state.frequency)).flatten(),
+                level: if audible {
+                    f32::from(state.volume) / 15.0
+                } else {
+                    0.0
+                },
+            }
+        }
+        Channel::Noise => Activity {
+            note: None,
+            level: if playing {
+                f32::from(state.volume) / 15.0
+            } else {
+                0.0
+            },
+        },
+        Channel::Dmc => Activity {
+            note: None,
+            level: if state.length > 0 { 1.0 } else { 0.0 },
+        },
+    }
+}
+
+fn describe_state(channel: Channel, state: ChannelState) -> String {
+    let status = if state.enabled { "on" } else { "off" };
+    match channel {
+        Channel::Pulse1 | Channel::Pulse2 => {
+            let duty = ["12.5%", "25%", "50%", "75%"][usize::from(state.mode & 0x03)];
+            let volume = if state.envelope {
+                "envelope"
+            } else {
+                "constant"
+            };
+            format!(
+                "{status}  Vol {:2} ({volume})  Len {:3}  Duty {duty}",
+                state.volume, state.length
+            )
+        }
+        Channel::Triangle => format!(
+            "{status}  Len {:3}  Linear {:3}",
+            state.length, state.linear_counter
+        ),
+        Channel::Noise => {
+            let volume = if state.envelope {
+                "envelope"
+            } else {
+                "constant"
+            };
+            let mode = if state.mode == 1 { "short" } else { "long" };
+            format!(
+                "{status}  Vol {:2} ({volume})  Len {:3}  Period {}  {mode} mode",
+                state.volume, state.length, state.period
+            )
+        }
+        Channel::Dmc => format!(
+            "{status}  Level {:3}  Bytes left {}{}",
+            state.volume,
+            state.length,
+            if state.mode == 1 { "  looping" } else { "" }
+        ),
+    }
 }
 
 impl Nes {
     pub(crate) fn toggle_apu_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
         match self.apu_viewer {
             None => {
-                let w = s.width()?;
-                let h = s.height()?;
+                let w = 2 * PADDING + HISTORY as i32 * ROLL_COLUMN;
+                let roll_height =
+                    ((HIGHEST_NOTE - LOWEST_NOTE) as i32 + 1) * NOTE_HEIGHT + 2 * LANE_HEIGHT;
+                let h = 3 * PADDING + 5 * ROW_HEIGHT + roll_height + 40;
                 let window_id = s
                     .window()
-                    .dimensions(w, h)
+                    .dimensions(w as u32, h as u32)
                     .title("APU Viewer")
                     .position(10, 10)
                     .build()?;
                 self.apu_viewer = Some(ApuViewer::new(window_id));
+                self.update_channel_samples();
             }
             Some(ref viewer) => {
                 s.close_window(viewer.window_id())?;
-                self.apu_viewer = None;
+                self.close_apu_viewer();
             }
         }
         Ok(())
     }
+
+    pub(crate) fn close_apu_viewer(&mut self) {
+        self.apu_viewer = None;
+        self.update_channel_samples();
+    }
+
+    /// Captures each audio channel separately while the APU viewer is open or a sound recording
+    /// has stems.
+    pub(crate) fn update_channel_samples(&mut self) {
+        let enabled = self.apu_viewer.is_some()
+            || self
+                .sound_recorder
+                .as_ref()
+                .map_or(false, |recorder| recorder.has_stems());
+        if enabled != self.control_deck.channel_samples_enabled() {
+            self.control_deck.set_channel_samples_enabled(enabled);
+        }
+    }
+
+    /// Keeps the channel samples generated since the last update for the oscilloscopes.
+    pub(crate) fn capture_apu_samples(&mut self) {
+        if let Some(ref mut viewer) = self.apu_viewer {
+            viewer.scopes.push(self.control_deck.channel_samples());
+        }
+    }
+
+    pub(crate) fn render_apu_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
+        if self.apu_viewer.is_none() {
+            return Ok(());
+        }
+        // Loading a save state replaces the bus and stops capturing
+        self.update_channel_samples();
+        let frame = self.control_deck.frame_number();
+        let apu = self.control_deck.apu();
+        let states = [
+            Channel::Pulse1,
+            Channel::Pulse2,
+            Channel::Triangle,
+            Channel::Noise,
+            Channel::Dmc,
+        ]
+        .map(|channel| apu.channel_state(channel));
+        let registers = *apu.registers();
+        let Some(ref mut viewer) = self.apu_viewer else {
+            return Ok(());
+        };
+        if viewer.last_frame != Some(frame) {
+            viewer.last_frame = Some(frame);
+            let mut frame_activity = [Activity::default(); 5];
+            for ((activity, &channel), &state) in frame_activity
+                .iter_mut()
+                .zip(Channel::as_slice())
+                .zip(&states)
+            {
+                *activity = channel_activity(channel, state);
+            }
+            viewer.push_activity(frame_activity);
+        }
+
+        s.set_window_target(viewer.window_id())?;
+        s.clear()?;
+        s.fill(Color::WHITE);
+        s.stroke(None);
+
+        // Oscilloscopes and channel state
+        for (i, (&channel, &state)) in Channel::as_slice().iter().zip(&states).enumerate() {
+            let y = PADDING + i as i32 * ROW_HEIGHT;
+            render_scope(s, &viewer.scopes.samples[i], channel, [PADDING, y])?;
+            let x = 2 * PADDING + SCOPE_WIDTH;
+            s.push();
+            s.fill(channel_color(channel, 255));
+            s.set_cursor_pos([x, y]);
+            let pitch = match channel {
+                Channel::Pulse1 | Channel::Pulse2 | Channel::Triangle => {
+                    note_name(state.frequency).unwrap_or_else(|| "--".to_string())
+                }
+                Channel::Noise | Channel::Dmc => String::new(),
+            };
+            s.text(format!(
+                "{}  {:.1} Hz  {pitch}",
+                channel.as_ref(),
+                state.frequency
+            ))?;
+            s.pop();
+            s.set_cursor_pos([x, y + 20]);
+            s.text(describe_state(channel, state))?;
+            // Volume meter
+            let max = if channel == Channel::Dmc { 127 } else { 15 };
+            let meter = rect![x, y + 44, 200, 10];
+            s.push();
+            s.fill(rgb!(40));
+            s.rect(meter)?;
+            s.fill(channel_color(channel, 255));
+            s.rect([
+                meter.x(),
+                meter.y(),
+                i32::from(state.volume) * meter.width() / max,
+                meter.height(),
+            ])?;
+            s.pop();
+        }
+
+        // Registers
+        let x = 2 * PADDING + SCOPE_WIDTH + 480;
+        s.set_cursor_pos([x, PADDING]);
+        s.text("Registers")?;
+        s.same_line(None);
+        s.help_marker(
+            "Last values written. The APU registers are write-only, and loading a save state \
+            clears them until the game writes them again.",
+        )?;
+        for (name, addr, len) in [
+            ("Pulse 1", 0x4000, 4),
+            ("Pulse 2", 0x4004, 4),
+            ("Triangle", 0x4008, 4),
+            ("Noise", 0x400C, 4),
+            ("DMC", 0x4010, 4),
+            ("Status", 0x4015, 1),
+            ("Frame", 0x4017, 1),
+        ] {
+            let start = addr - 0x4000;
+            let values = registers[start..start + len]
+                .iter()
+                .map(|val| format!("{val:02X}"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            s.text(format!("{name:<9}${addr:04X}: {values}"))?;
+        }
+
+        // Piano roll
+        let top = 2 * PADDING + 5 * ROW_HEIGHT;
+        s.set_cursor_pos([PADDING, top]);
+        s.text("Channel Activity")?;
+        let roll_top = top + 24;
+        render_piano_roll(s, &viewer.history, [PADDING, roll_top])?;
+
+        s.reset_window_target();
+        Ok(())
+    }
+}
+
+/// Draws the newest points of a channel's waveform, starting on a rising edge where there is one
+/// so periodic waves hold still.
+fn render_scope(
+    s: &mut PixState,
+    samples: &VecDeque<f32>,
+    channel: Channel,
+    pos: [i32; 2],
+) -> PixResult<()> {
+    let [x, y] = pos;
+    s.push();
+    s.fill(rgb!(20));
+    s.rect([x, y, SCOPE_WIDTH, SCOPE_HEIGHT])?;
+    if samples.len() >= SCOPE_SAMPLES {
+        let latest = samples.len() - SCOPE_SAMPLES;
+        let (min, max) = samples.iter().fold((f32::MAX, f32::MIN), |(min, max), &v| {
+            (min.min(v), max.max(v))
+        });
+        let mid = (min + max) / 2.0;
+        let start = (1..=latest)
+            .rev()
+            .find(|&i| samples[i - 1] < mid && samples[i] >= mid)
+            .unwrap_or(latest);
+        // Silent channels sit at the bottom
+        let range = (max - min).max(f32::EPSILON);
+        let sample_y = |v: f32| {
+            let level = if max > min { (v - min) / range } else { 0.0 };
+            y + SCOPE_HEIGHT - 2 - (level * (SCOPE_HEIGHT - 4) as f32) as i32
+        };
+        s.stroke(channel_color(channel, 255));
+        let mut prev = None;
+        for i in 0..SCOPE_SAMPLES {
+            let px = x + i as i32 * SCOPE_WIDTH / SCOPE_SAMPLES as i32;
+            let py = sample_y(samples[start + i]);
+            if let Some((prev_x, prev_y)) = prev {
+                s.line([prev_x, prev_y, px, py])?;
+            }
+            prev = Some((px, py));
+        }
+    }
+    s.pop();
+    Ok(())
+}
+
+/// Draws the pitched channels by note over time, with noise and DMC activity in lanes below.
+/// Brighter notes are louder.
+fn render_piano_roll(
+    s: &mut PixState,
+    history: &VecDeque<[Activity; 5]>,
+    pos: [i32; 2],
+) -> PixResult<()> {
+    let [x, y] = pos;
+    let notes = (HIGHEST_NOTE - LOWEST_NOTE) as i32 + 1;
+    let width = HISTORY as i32 * ROLL_COLUMN;
+    let height = notes * NOTE_HEIGHT;
+    s.push();
+    s.stroke(None);
+    s.fill(rgb!(20));
+    s.rect([x, y, width, height + 2 * LANE_HEIGHT])?;
+    // C of each octave
+    s.fill(rgb!(45));
+    for note in (LOWEST_NOTE as i32..=HIGHEST_NOTE as i32).filter(|note| note % 12 == 0) {
+        let note_y = y + height - (note - LOWEST_NOTE as i32 + 1) * NOTE_HEIGHT;
+        s.rect([x, note_y, width, 1])?;
+    }
+    // Newest frame on the right
+    let offset = HISTORY - history.len();
+    for (i, frame) in history.iter().enumerate() {
+        let column_x = x + (offset + i) as i32 * ROLL_COLUMN;
+        for (&channel, activity) in Channel::as_slice().iter().zip(frame) {
+            if activity.level <= 0.0 {
+                continue;
+            }
+            s.fill(channel_color(
+                channel,
+                (80.0 + 175.0 * activity.level) as u8,
+            ));
+            match (channel, activity.note) {
+                (_, Some(note)) if (LOWEST_NOTE..=HIGHEST_NOTE).contains(&note) => {
+                    let note_y =
+                        y + height - ((note - LOWEST_NOTE).round() as i32 + 1) * NOTE_HEIGHT;
+                    s.rect([column_x, note_y, ROLL_COLUMN, NOTE_HEIGHT])?;
+                }
+                (Channel::Noise, _) => {
+                    s.rect([column_x, y + height, ROLL_COLUMN, LANE_HEIGHT - 1])?;
+                }
+                (Channel::Dmc, _) => {
+                    s.rect([
+                        column_x,
+                        y + height + LANE_HEIGHT,
+                        ROLL_COLUMN,
+                        LANE_HEIGHT - 1,
+                    ])?;
+                }
+                _ => (),
+            }
+        }
+    }
+    s.pop();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn note_names() {
+        assert_eq!(note_name(440.0).as_deref(), Some("A4 +0c"));
+        assert_eq!(note_name(261.63).as_deref(), Some("C4 +0c"));
+        assert_eq!(note_name(445.0).as_deref(), Some("A4 +20c"));
+        assert_eq!(note_name(50_000.0), None);
+    }
+
+    #[test]
+    fn decimates_channel_samples() {
+        let mut scopes = Scopes::default();
+        let samples = (0..4 * DECIMATION)
+            .flat_map(|cycle| [cycle as f32; CHANNEL_STRIDE])
+            .collect::<Vec<_>>();
+        // Points carry over between updates
+        let (first, second) = samples.split_at(CHANNEL_STRIDE * (DECIMATION + 1));
+        scopes.push(first);
+        scopes.push(second);
+        let expected = (0..4).map(|i| (i * DECIMATION) as f32).collect::<Vec<_>>();
+        assert_eq!(
+            scopes.samples[0].iter().copied().collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(scopes.samples[4].len(), 4);
+    }
+
+    #[test]
+    fn silenced_pulse_is_inactive() {
+        let state = ChannelState {
+            enabled: true,
+            period: 0x0FD,
+            frequency: 440.0,
+            volume: 8,
+            length: 10,
+            ..ChannelState::default()
+        };
+        let activity = channel_activity(Channel::Pulse1, state);
+        assert_eq!(activity.note, Some(69.0));
+        assert!((activity.level - 8.0 / 15.0).abs() < f32::EPSILON);
+        let activity = channel_activity(Channel::Pulse1, ChannelState { period: 4, ..state });
+        assert_eq!(activity.note, None);
+        assert_eq!(activity.level, 0.0);
+    }
 }
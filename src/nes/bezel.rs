@@ -0,0 +1,256 @@
+//! Decorative frames drawn around the game, for a polished fullscreen look.
+//!
+//! The TV frame is drawn without any art. Bezel packs are directories of PNG images, with
+//! `<rom name>.png` used for a game if it exists and `default.png` otherwise. Most community packs
+//! are made for a 4:3 screen centered in the image, which is where the game goes unless a
+//! `<image name>.json` next to the image gives the screen area in image pixels:
+//!
+//! ```json
+//! { "screen": [240, 0, 1440, 1080] }
+//! ```
+//!
+//! The game keeps its aspect ratio and overscan crop inside the screen area.
+
+use crate::{
+    common::config_dir,
+    nes::{config::Config, Nes, NES_FRAME_SRC},
+    NesResult,
+};
+use anyhow::Context;
+use pix_engine::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+/// Fraction of the window on each side taken up by the TV frame.
+const TV_BORDER: f32 = 0.08;
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub(crate) enum BezelMode {
+    #[default]
+    Off,
+    Tv,
+    Pack,
+}
+
+impl BezelMode {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[Self::Off, Self::Tv, Self::Pack]
+    }
+}
+
+impl AsRef<str> for BezelMode {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Off => "Off",
+            Self::Tv => "TV Frame",
+            Self::Pack => "Bezel Pack",
+        }
+    }
+}
+
+impl From<usize> for BezelMode {
+    fn from(value: usize) -> Self {
+        match value {
+            1 => Self::Tv,
+            2 => Self::Pack,
+            _ => Self::Off,
+        }
+    }
+}
+
+impl Config {
+    /// Directory bezel packs are loaded from, defaulting to `bezels` in the config directory.
+    pub(crate) fn bezel_dir(&self) -> PathBuf {
+        self.bezel_dir
+            .clone()
+            .unwrap_or_else(|| config_dir().join("bezels"))
+    }
+}
+
+#[derive(Deserialize)]
+struct BezelLayout {
+    screen: [i32; 4],
+}
+
+struct BezelArt {
+    image: Image,
+    /// Where the game goes, in image pixels.
+    screen: Rect<i32>,
+}
+
+/// Bezel art for the loaded game, loaded when the game or bezel directory changes.
+#[derive(Default)]
+#[must_use]
+pub(crate) struct Bezel {
+    /// Bezel directory and ROM the art was looked up for.
+    loaded_for: Option<(PathBuf, Option<String>)>,
+    art: Option<BezelArt>,
+}
+
+impl std::fmt::Debug for Bezel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Bezel")
+            .field("loaded_for", &self.loaded_for)
+            .field("screen", &self.art.as_ref().map(|art| art.screen))
+            .finish()
+    }
+}
+
+impl Bezel {
+    /// Forgets loaded art so it's looked up again, to pick up changes to the pack.
+    pub(crate) fn reload(&mut self) {
+        self.loaded_for = None;
+        self.art = None;
+    }
+}
+
+/// The image to use from a bezel pack for a game.
+fn bezel_path(dir: &Path, rom: Option<&str>) -> Option<PathBuf> {
+    rom.and_then(|rom| Path::new(rom).file_stem().and_then(OsStr::to_str))
+        .map(|name| dir.join(name).with_extension("png"))
+        .filter(|path| path.exists())
+        .or_else(|| Some(dir.join("default.png")).filter(|path| path.exists()))
+}
+
+fn load_art(path: &Path) -> NesResult<BezelArt> {
+    let image = Image::from_file(path).with_context(|| format!("failed to load {path:?}"))?;
+    let (width, height) = (image.width() as i32, image.height() as i32);
+    let layout_path = path.with_extension("json");
+    let screen = if layout_path.exists() {
+        let file =
+            File::open(&layout_path).with_context(|| format!("failed to open {layout_path:?}"))?;
+        let layout: BezelLayout = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("failed to parse {layout_path:?}"))?;
+        let [x, y, w, h] = layout.screen;
+        rect![x, y, w, h]
+    } else {
+        let screen_width = (height * 4 / 3).min(width);
+        rect![(width - screen_width) / 2, 0, screen_width, height]
+    };
+    Ok(BezelArt { image, screen })
+}
+
+/// Fits a `width` by `height` area into `bounds`, centered and keeping its aspect ratio.
+fn fit(bounds: Rect<i32>, width: f32, height: f32) -> Rect<i32> {
+    let scale = (bounds.width() as f32 / width).min(bounds.height() as f32 / height);
+    let (width, height) = ((width * scale) as i32, (height * scale) as i32);
+    rect![
+        bounds.x() + (bounds.width() - width) / 2,
+        bounds.y() + (bounds.height() - height) / 2,
+        width,
+        height
+    ]
+}
+
+impl Nes {
+    /// Draws the bezel and returns where the game should be drawn, or `None` to fill the window.
+    pub(crate) fn render_bezel(&mut self, s: &mut PixState) -> PixResult<Option<Rect<i32>>> {
+        let window = rect![0, 0, s.width()? as i32, s.height()? as i32];
+        // Keeps the 8:7 or PAL pixel aspect ratio of the cropped frame
+        let (game_width, game_height) = self.config.get_dimensions();
+        let (game_width, game_height) = (game_width as f32, game_height as f32);
+        match self.config.bezel {
+            BezelMode::Off => Ok(None),
+            BezelMode::Tv => {
+                let border_x = (window.width() as f32 * TV_BORDER) as i32;
+                let border_y = (window.height() as f32 * TV_BORDER) as i32;
+                let bounds = rect![
+                    border_x,
+                    border_y,
+                    window.width() - 2 * border_x,
+                    window.height() - 2 * border_y
+                ];
+                let screen = fit(bounds, game_width, game_height);
+                s.push();
+                s.stroke(None);
+                s.fill(rgb!(40, 36, 32));
+                s.rect(window)?;
+                // Recessed edge around the tube
+                let edge = (border_x.min(border_y) / 3).max(2);
+                s.fill(rgb!(12, 12, 12));
+                s.rect([
+                    screen.x() - edge,
+                    screen.y() - edge,
+                    screen.width() + 2 * edge,
+                    screen.height() + 2 * edge,
+                ])?;
+                // Power light
+                s.fill(rgb!(220, 40, 30));
+                s.circle([
+                    window.width() - border_x,
+                    window.height() - border_y / 2,
+                    (border_y / 8).max(2),
+                ])?;
+                s.pop();
+                Ok(Some(screen))
+            }
+            BezelMode::Pack => {
+                let dir = self.config.bezel_dir();
+                let rom = self.control_deck.loaded_rom().clone();
+                let key = (dir, rom);
+                if self.bezel.loaded_for.as_ref() != Some(&key) {
+                    self.bezel.art = match bezel_path(&key.0, key.1.as_deref()) {
+                        Some(path) => match load_art(&path) {
+                            Ok(art) => Some(art),
+                            Err(err) => {
+                                log::error!("{err:?}");
+                                self.add_message("Failed to load bezel");
+                                None
+                            }
+                        },
+                        None => {
+                            log::info!("no bezel found in {:?}", key.0);
+                            None
+                        }
+                    };
+                    self.bezel.loaded_for = Some(key);
+                }
+                let Some(art) = &self.bezel.art else {
+                    return Ok(None);
+                };
+                let (image_width, image_height) =
+                    (art.image.width() as f32, art.image.height() as f32);
+                let bounds = fit(window, image_width, image_height);
+                let scale = bounds.width() as f32 / image_width;
+                let screen = rect![
+                    bounds.x() + (art.screen.x() as f32 * scale) as i32,
+                    bounds.y() + (art.screen.y() as f32 * scale) as i32,
+                    (art.screen.width() as f32 * scale) as i32,
+                    (art.screen.height() as f32 * scale) as i32
+                ];
+                s.image_resized(&art.image, bounds)?;
+                Ok(Some(fit(screen, game_width, game_height)))
+            }
+        }
+    }
+
+    /// Draws the game frame inside the bezel, from the area of the texture given by `src`.
+    pub(crate) fn render_frame(
+        &mut self,
+        s: &mut PixState,
+        texture_id: TextureId,
+        src: Rect<i32>,
+    ) -> PixResult<()> {
+        let dst = self.render_bezel(s)?;
+        s.texture(texture_id, src, dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_screen() {
+        let window = rect![0, 0, 1920, 1080];
+        assert_eq!(fit(window, 4.0, 3.0), rect![240, 0, 1440, 1080]);
+        assert_eq!(fit(window, 32.0, 9.0), rect![0, 270, 1920, 540]);
+        assert_eq!(fit(window, 16.0, 9.0), window);
+    }
+}
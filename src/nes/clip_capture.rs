@@ -0,0 +1,294 @@
+//! Short animated clip capture to GIF or APNG.
+//!
+//! Frames are captured with the active video filter at half the emulated frame rate, which is
+//! the practical limit for animated GIF frame delays, and compressed in memory until saved. A
+//! rolling buffer of the last few seconds is kept so a clip can be saved after the fact.
+
+use crate::{
+    common::NesRegion,
+    nes::{
+        filesystem::{decode_data, encode_data},
+        Nes,
+    },
+    ppu::Ppu,
+    NesResult,
+};
+use anyhow::{anyhow, Context};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+const FFMPEG: &str = "ffmpeg";
+/// Only every Nth emulated frame is captured.
+const FRAME_SKIP: u32 = 2;
+/// Upper limit for a manually started clip.
+const MAX_CLIP_SECONDS: u32 = 60;
+
+/// Animated image format used for clips.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub(crate) enum ClipFormat {
+    #[default]
+    Gif,
+    Apng,
+}
+
+impl ClipFormat {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[Self::Gif, Self::Apng]
+    }
+
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Gif => "gif",
+            Self::Apng => "png",
+        }
+    }
+}
+
+impl AsRef<str> for ClipFormat {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Gif => "GIF",
+            Self::Apng => "APNG",
+        }
+    }
+}
+
+impl From<usize> for ClipFormat {
+    fn from(value: usize) -> Self {
+        if value == 1 {
+            Self::Apng
+        } else {
+            Self::Gif
+        }
+    }
+}
+
+/// Buffer of compressed, filtered frames waiting to be written out as a clip.
+#[derive(Default, Debug)]
+#[must_use]
+pub(crate) struct ClipBuffer {
+    frames: VecDeque<Vec<u8>>,
+    recording: bool,
+}
+
+impl ClipBuffer {
+    #[inline]
+    pub(crate) const fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Maximum number of frames held for a given duration.
+    #[inline]
+    fn max_frames(region: NesRegion, seconds: u32) -> usize {
+        (region.frame_rate() / FRAME_SKIP as f32 * seconds as f32) as usize
+    }
+
+    /// Captures a filtered RGBA frame `count` times, discarding the oldest frames beyond
+    /// `max_frames`.
+    fn push(&mut self, frame: &[u8], count: usize, max_frames: usize) -> NesResult<()> {
+        let frame = encode_data(frame)?;
+        self.frames
+            .extend(std::iter::repeat(frame).take(count.min(max_frames)));
+        while self.frames.len() > max_frames {
+            self.frames.pop_front();
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+/// Number of captured frames between `prev_frame` and `frame`, counting every `FRAME_SKIP`th
+/// emulated frame crossed. Going backwards, as when loading a state, captures nothing.
+const fn captured_frames(prev_frame: u32, frame: u32) -> u32 {
+    if frame <= prev_frame {
+        0
+    } else {
+        frame / FRAME_SKIP - prev_frame / FRAME_SKIP
+    }
+}
+
+/// Encodes a sequence of compressed frames into an animated GIF or APNG.
+///
+/// # Errors
+///
+/// If the encoder fails to start or exits unsuccessfully, an error is returned.
+fn write_clip<'a, P, I>(
+    dir: P,
+    format: ClipFormat,
+    region: NesRegion,
+    scale: f32,
+    frames: I,
+) -> NesResult<PathBuf>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = &'a Vec<u8>>,
+{
+    let dir = dir.as_ref();
+    if !dir.exists() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create directory {dir:?}"))?;
+    }
+    let output = dir.join(
+        PathBuf::from(Local::now().format("Clip_%Y-%m-%d_at_%H_%M_%S").to_string())
+            .with_extension(format.extension()),
+    );
+
+    // Trim top and bottom 8 scanlines and scale to match the window, including the 8:7 ratio
+    let width = (scale * Ppu::WIDTH as f32 * 8.0 / 7.0) as u32;
+    let height = (scale * (Ppu::HEIGHT - 16) as f32) as u32;
+    let filter = match format {
+        ClipFormat::Gif => format!(
+            "crop=256:224:0:8,scale={width}:{height}:flags=neighbor,split[a][b];[a]palettegen=stats_mode=diff[p];[b][p]paletteuse=dither=none:diff_mode=rectangle"
+        ),
+        ClipFormat::Apng => format!("crop=256:224:0:8,scale={width}:{height}:flags=neighbor"),
+    };
+
+    let mut encoder = Command::new(FFMPEG)
+        .args(["-y", "-loglevel", "error"])
+        .args(["-f", "rawvideo", "-pixel_format", "rgba"])
+        .args(["-video_size", &format!("{}x{}", Ppu::WIDTH, Ppu::HEIGHT)])
+        .args([
+            "-framerate",
+            &(region.frame_rate() / FRAME_SKIP as f32).to_string(),
+        ])
+        .args(["-i", "-"])
+        .args(["-filter_complex", &filter])
+        .args(match format {
+            ClipFormat::Gif => ["-loop", "0"],
+            ClipFormat::Apng => ["-plays", "0"],
+        })
+        .arg(&output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("failed to start `ffmpeg`. is it installed?")?;
+    {
+        let stdin = encoder
+            .stdin
+            .as_mut()
+            .ok_or_else(|| anyhow!("failed to open encoder input"))?;
+        for frame in frames {
+            stdin
+                .write_all(&decode_data(frame)?)
+                .context("failed to write clip frame")?;
+        }
+    }
+    // Close stdin so the encoder finishes
+    drop(encoder.stdin.take());
+    let status = encoder.wait().context("failed to wait for clip encoder")?;
+    if status.success() {
+        Ok(output)
+    } else {
+        Err(anyhow!("clip encoder exited with {status}"))
+    }
+}
+
+impl Nes {
+    /// Starts or stops manual clip capture. Stopping writes out every frame captured since the
+    /// clip was started.
+    pub(crate) fn toggle_clip_capture(&mut self) {
+        if self.clip.is_recording() {
+            self.clip.recording = false;
+            self.save_clip_buffer();
+        } else if self.control_deck.loaded_rom().is_some() {
+            self.clip.clear();
+            self.clip.recording = true;
+            self.add_message("Clip Capture Started");
+        }
+    }
+
+    /// Saves a manually started clip and clears the clip buffer, so clips don't span games.
+    pub(crate) fn stop_clip_capture(&mut self) {
+        if self.clip.is_recording() {
+            self.toggle_clip_capture();
+        }
+        self.clip.clear();
+    }
+
+    /// Writes the last `clip_seconds` of gameplay out as a clip.
+    pub(crate) fn save_clip(&mut self) {
+        if self.clip.is_recording() {
+            self.toggle_clip_capture();
+        } else if self.config.clip_seconds == 0 {
+            self.add_message("Clip buffer disabled. You can enable it in the Config menu.");
+        } else {
+            self.save_clip_buffer();
+        }
+    }
+
+    fn save_clip_buffer(&mut self) {
+        if self.clip.frames.is_empty() {
+            self.add_message("No frames captured for clip");
+            return;
+        }
+        match write_clip(
+            &self.config.video_recording_dir,
+            self.config.clip_format,
+            self.config.region,
+            self.config.scale,
+            &self.clip.frames,
+        ) {
+            Ok(output) => self.add_message(format!("Saved clip {output:?}")),
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message("Failed to save clip");
+            }
+        }
+        self.clip.clear();
+    }
+
+    /// Captures the frames emulated since `prev_frame` into the clip buffer if a clip is being
+    /// recorded or the rolling clip buffer is enabled. Only the latest frame is available, so it's
+    /// duplicated if more than one captured frame was emulated since the last update, keeping the
+    /// clip in emulated time.
+    pub(crate) fn capture_clip_frames(&mut self, prev_frame: u32, frame: u32) {
+        let seconds = if self.clip.is_recording() {
+            MAX_CLIP_SECONDS
+        } else {
+            self.config.clip_seconds
+        };
+        let count = captured_frames(prev_frame, frame);
+        if seconds == 0 || count == 0 {
+            return;
+        }
+        let max_frames = ClipBuffer::max_frames(self.config.region, seconds);
+        let frame_buffer = self.control_deck.frame_buffer();
+        if let Err(err) = self.clip.push(frame_buffer, count as usize, max_frames) {
+            log::error!("{err:?}");
+            self.config.clip_seconds = 0;
+            self.clip.recording = false;
+            self.clip.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn captures_crossed_frames() {
+        assert_eq!(captured_frames(10, 11), 0);
+        assert_eq!(captured_frames(11, 12), 1);
+        assert_eq!(captured_frames(10, 12), 1);
+        assert_eq!(captured_frames(11, 16), 3);
+        assert_eq!(captured_frames(12, 12), 0);
+        assert_eq!(captured_frames(100, 12), 0);
+
+        let mut clip = ClipBuffer::default();
+        clip.push(&[0; 4], 3, 4).expect("captured");
+        clip.push(&[1; 4], 2, 4).expect("captured");
+        assert_eq!(clip.frames.len(), 4);
+        clip.push(&[2; 4], 10, 4).expect("captured");
+        assert_eq!(clip.frames.len(), 4);
+    }
+}
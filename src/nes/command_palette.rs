@@ -0,0 +1,520 @@
+//! Command palette for searching and running any action by name, so rarely used features don't
+//! all need dedicated key bindings.
+
+use crate::{
+    input::Slot,
+    nes::{
+        event::{Action, DebugAction, Feature, Input, NesState, Setting},
+        menu::{types::ConfigSection, Menu, Player},
+        menu_nav::NavAction,
+        Mode, Nes,
+    },
+};
+use pix_engine::prelude::*;
+use std::collections::HashMap;
+
+/// Every action that can be run from the command palette. Held inputs like joypad buttons and
+/// fast forward aren't included.
+pub(crate) const COMMANDS: &[(&str, Action)] = &[
+    ("Open Menu", Action::Menu(Menu::Main)),
+    (
+        "Configuration: General",
+        Action::Menu(Menu::Config(ConfigSection::General)),
+    ),
+    (
+        "Configuration: Emulation",
+        Action::Menu(Menu::Config(ConfigSection::Emulation)),
+    ),
+    (
+        "Configuration: Audio",
+        Action::Menu(Menu::Config(ConfigSection::Audio)),
+    ),
+    (
+        "Configuration: Video",
+        Action::Menu(Menu::Config(ConfigSection::Video)),
+    ),
+    ("Keybindings", Action::Menu(Menu::Keybind(Player::One))),
+    ("Load/Open ROM", Action::Menu(Menu::LoadRom)),
+    ("Replay Timeline & Bookmarks", Action::Menu(Menu::Replay)),
+    ("State Bookmarks", Action::Menu(Menu::Bookmarks)),
+    ("Screenshot Gallery", Action::Menu(Menu::Gallery)),
+    ("About TetaNES", Action::Menu(Menu::About)),
+    ("What's New", Action::Menu(Menu::WhatsNew)),
+    ("Toggle Pause", Action::Nes(NesState::TogglePause)),
+    ("Reset", Action::Nes(NesState::SoftReset)),
+    ("Power Cycle", Action::Nes(NesState::HardReset)),
+    ("Quit", Action::Nes(NesState::Quit)),
+    ("Show Tutorial", Action::Feature(Feature::ShowTutorial)),
+    (
+        "Switch Shared Pad",
+        Action::Feature(Feature::SwitchSharedPad),
+    ),
+    ("Save State", Action::Feature(Feature::SaveState)),
+    ("Load State", Action::Feature(Feature::LoadState)),
+    ("Load Auto-Save", Action::Feature(Feature::LoadAutoSave)),
+    (
+        "Set Save State Slot 1",
+        Action::Setting(Setting::SetSaveSlot(1)),
+    ),
+    (
+        "Set Save State Slot 2",
+        Action::Setting(Setting::SetSaveSlot(2)),
+    ),
+    (
+        "Set Save State Slot 3",
+        Action::Setting(Setting::SetSaveSlot(3)),
+    ),
+    (
+        "Set Save State Slot 4",
+        Action::Setting(Setting::SetSaveSlot(4)),
+    ),
+    (
+        "Save Quick Slot 1",
+        Action::Feature(Feature::SaveQuickSlot(1)),
+    ),
+    (
+        "Save Quick Slot 2",
+        Action::Feature(Feature::SaveQuickSlot(2)),
+    ),
+    (
+        "Save Quick Slot 3",
+        Action::Feature(Feature::SaveQuickSlot(3)),
+    ),
+    (
+        "Save Quick Slot 4",
+        Action::Feature(Feature::SaveQuickSlot(4)),
+    ),
+    (
+        "Load Quick Slot 1",
+        Action::Feature(Feature::LoadQuickSlot(1)),
+    ),
+    (
+        "Load Quick Slot 2",
+        Action::Feature(Feature::LoadQuickSlot(2)),
+    ),
+    (
+        "Load Quick Slot 3",
+        Action::Feature(Feature::LoadQuickSlot(3)),
+    ),
+    (
+        "Load Quick Slot 4",
+        Action::Feature(Feature::LoadQuickSlot(4)),
+    ),
+    ("Instant Rewind", Action::Feature(Feature::Rewind)),
+    ("Take Screenshot", Action::Feature(Feature::TakeScreenshot)),
+    (
+        "Toggle Gameplay Recording",
+        Action::Feature(Feature::ToggleGameplayRecording),
+    ),
+    (
+        "Add Replay Bookmark",
+        Action::Feature(Feature::AddReplayBookmark),
+    ),
+    (
+        "Add State Bookmark",
+        Action::Feature(Feature::AddStateBookmark),
+    ),
+    (
+        "Jump to Next State Bookmark",
+        Action::Feature(Feature::NextStateBookmark),
+    ),
+    (
+        "Jump to Previous State Bookmark",
+        Action::Feature(Feature::PrevStateBookmark),
+    ),
+    (
+        "Seek Replay Backward",
+        Action::Feature(Feature::SeekReplayBackward),
+    ),
+    (
+        "Seek Replay Forward",
+        Action::Feature(Feature::SeekReplayForward),
+    ),
+    (
+        "Toggle Replay Fast Forward",
+        Action::Feature(Feature::ToggleReplayFastForward),
+    ),
+    (
+        "Take Replay Control",
+        Action::Feature(Feature::TakeReplayControl),
+    ),
+    (
+        "Save Session Replay",
+        Action::Feature(Feature::SaveSessionReplay),
+    ),
+    (
+        "Toggle Sound Recording",
+        Action::Feature(Feature::ToggleSoundRecording),
+    ),
+    (
+        "Toggle Video Recording",
+        Action::Feature(Feature::ToggleVideoRecording),
+    ),
+    (
+        "Toggle GIF/APNG Clip Capture",
+        Action::Feature(Feature::ToggleClipCapture),
+    ),
+    (
+        "Save Last Seconds as Clip",
+        Action::Feature(Feature::SaveClip),
+    ),
+    ("Increase Speed", Action::Setting(Setting::IncSpeed)),
+    ("Decrease Speed", Action::Setting(Setting::DecSpeed)),
+    ("Slow Motion", Action::Setting(Setting::SlowMotion)),
+    ("Frame Advance", Action::Setting(Setting::FrameAdvance)),
+    (
+        "Toggle Fullscreen",
+        Action::Setting(Setting::ToggleFullscreen),
+    ),
+    ("Toggle Vsync", Action::Setting(Setting::ToggleVsync)),
+    ("Toggle Mini View", Action::Setting(Setting::ToggleMiniView)),
+    (
+        "Toggle Always on Top",
+        Action::Setting(Setting::ToggleAlwaysOnTop),
+    ),
+    (
+        "Toggle Click-Through",
+        Action::Setting(Setting::ToggleClickThrough),
+    ),
+    (
+        "Toggle NTSC Filter",
+        Action::Setting(Setting::ToggleNtscFilter),
+    ),
+    ("Toggle Music/Sound", Action::Setting(Setting::ToggleSound)),
+    (
+        "Toggle Pulse Channel 1",
+        Action::Setting(Setting::TogglePulse1),
+    ),
+    (
+        "Toggle Pulse Channel 2",
+        Action::Setting(Setting::TogglePulse2),
+    ),
+    (
+        "Toggle Triangle Channel",
+        Action::Setting(Setting::ToggleTriangle),
+    ),
+    (
+        "Toggle Noise Channel",
+        Action::Setting(Setting::ToggleNoise),
+    ),
+    ("Toggle DMC Channel", Action::Setting(Setting::ToggleDmc)),
+    (
+        "Debug: Toggle CPU Debugger",
+        Action::Debug(DebugAction::ToggleCpuDebugger),
+    ),
+    (
+        "Debug: Toggle PPU Viewer",
+        Action::Debug(DebugAction::TogglePpuDebugger),
+    ),
+    (
+        "Debug: Toggle Nametable Viewer",
+        Action::Debug(DebugAction::ToggleNametableViewer),
+    ),
+    (
+        "Debug: Toggle OAM Viewer",
+        Action::Debug(DebugAction::ToggleOamViewer),
+    ),
+    (
+        "Debug: Toggle Palette Viewer",
+        Action::Debug(DebugAction::TogglePaletteViewer),
+    ),
+    (
+        "Debug: Toggle APU Viewer",
+        Action::Debug(DebugAction::ToggleApuDebugger),
+    ),
+    (
+        "Debug: Toggle Scroll Overlay",
+        Action::Debug(DebugAction::ToggleScrollOverlay),
+    ),
+    (
+        "Debug: Toggle Interrupt Overlay",
+        Action::Debug(DebugAction::ToggleInterruptOverlay),
+    ),
+    (
+        "Debug: Toggle Diff Overlay",
+        Action::Debug(DebugAction::ToggleDiffOverlay),
+    ),
+    (
+        "Debug: Toggle Latency Monitor",
+        Action::Debug(DebugAction::ToggleLatencyMonitor),
+    ),
+    (
+        "Debug: Show Latency Test Pattern",
+        Action::Debug(DebugAction::ToggleLatencyTest),
+    ),
+    (
+        "Debug: Toggle TAS Editor",
+        Action::Debug(DebugAction::ToggleTasEditor),
+    ),
+    (
+        "Debug: Toggle DPCM Converter",
+        Action::Debug(DebugAction::ToggleDpcmTool),
+    ),
+    (
+        "Debug: Toggle SRAM Editor",
+        Action::Debug(DebugAction::ToggleSramEditor),
+    ),
+    (
+        "Debug: Capture HD Pack Tiles",
+        Action::Debug(DebugAction::CaptureHdTiles),
+    ),
+    ("Debug: Step Into", Action::Debug(DebugAction::StepInto)),
+    ("Debug: Step Over", Action::Debug(DebugAction::StepOver)),
+    ("Debug: Step Out", Action::Debug(DebugAction::StepOut)),
+    ("Debug: Step Frame", Action::Debug(DebugAction::StepFrame)),
+    (
+        "Debug: Step Scanline",
+        Action::Debug(DebugAction::StepScanline),
+    ),
+    (
+        "Debug: Move PPU Viewer Scanline Down",
+        Action::Debug(DebugAction::IncScanline),
+    ),
+    (
+        "Debug: Move PPU Viewer Scanline Up",
+        Action::Debug(DebugAction::DecScanline),
+    ),
+];
+
+/// Scores how well `query` matches `name` as a case-insensitive subsequence, favoring the starts
+/// of words and consecutive characters. Returns `None` if `query` doesn't match.
+fn fuzzy_score(query: &str, name: &str) -> Option<i32> {
+    let name: Vec<char> = name.chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last_match = None;
+    for c in query.chars().filter(|c| !c.is_whitespace()) {
+        let found = pos
+            + name[pos..]
+                .iter()
+                .position(|n| n.eq_ignore_ascii_case(&c))?;
+        score += 1;
+        if found == 0 || !name[found - 1].is_alphanumeric() {
+            score += 8;
+        }
+        if found > 0 && last_match == Some(found - 1) {
+            score += 4;
+        }
+        // Penalize characters skipped between matches
+        if last_match.is_some() {
+            score -= (found - pos) as i32;
+        }
+        last_match = Some(found);
+        pos = found + 1;
+    }
+    Some(score)
+}
+
+/// Commands matching `query`, best matches first.
+fn matching_commands(query: &str) -> Vec<(&'static str, Action)> {
+    let mut matches: Vec<_> = COMMANDS
+        .iter()
+        .filter_map(|&(name, action)| fuzzy_score(query, name).map(|score| (score, name, action)))
+        .collect();
+    // Stable sort keeps the listed order for equal scores
+    matches.sort_by_key(|&(score, ..)| -score);
+    matches
+        .into_iter()
+        .map(|(_, name, action)| (name, action))
+        .collect()
+}
+
+#[derive(Default, Debug, Clone)]
+#[must_use]
+pub(crate) struct CommandPalette {
+    query: String,
+    selected: usize,
+    matches: Vec<(&'static str, Action)>,
+    /// Key bindings shown next to each command.
+    bindings: HashMap<Action, String>,
+}
+
+impl CommandPalette {
+    fn update_matches(&mut self) {
+        self.matches = matching_commands(&self.query);
+        self.selected = 0;
+    }
+}
+
+impl Nes {
+    /// Player one key bindings for each action, shown as hints.
+    pub(crate) fn key_bindings(&self) -> HashMap<Action, String> {
+        let mut bindings: HashMap<Action, String> = HashMap::new();
+        for (input, action) in self.config.input_map.iter() {
+            if let Input::Key((Slot::One, ..)) = input {
+                let binding = input.to_string();
+                // Prefer the shortest binding so the hint doesn't change between openings
+                match bindings.get(action) {
+                    Some(existing)
+                        if (existing.len(), existing.as_str())
+                            <= (binding.len(), binding.as_str()) => {}
+                    _ => {
+                        bindings.insert(*action, binding);
+                    }
+                }
+            }
+        }
+        bindings
+    }
+
+    /// Resets the search and looks up key bindings when the command palette is opened.
+    pub(crate) fn open_command_palette(&mut self) {
+        self.command_palette = CommandPalette {
+            bindings: self.key_bindings(),
+            ..CommandPalette::default()
+        };
+        self.command_palette.update_matches();
+    }
+
+    #[inline]
+    fn command_palette_open(&self) -> bool {
+        self.mode == Mode::InMenu(Menu::Commands)
+    }
+
+    /// Adds typed text to the search query.
+    pub(crate) fn type_command_query(&mut self, text: &str) -> bool {
+        if !self.command_palette_open() {
+            return false;
+        }
+        self.command_palette.query.push_str(text);
+        self.command_palette.update_matches();
+        true
+    }
+
+    /// Handles editing the search query. Returns `None` for key presses with modifiers so they
+    /// can still trigger their bindings, such as closing the palette.
+    pub(crate) fn handle_command_palette_key(
+        &mut self,
+        event: KeyEvent,
+        pressed: bool,
+    ) -> Option<bool> {
+        if !self.command_palette_open()
+            || event
+                .keymod
+                .intersects(KeyMod::CTRL | KeyMod::ALT | KeyMod::GUI)
+        {
+            return None;
+        }
+        if pressed && event.key == Key::Backspace {
+            self.command_palette.query.pop();
+            self.command_palette.update_matches();
+        }
+        Some(true)
+    }
+
+    /// Moves the selection, runs the selected command or closes the palette.
+    pub(crate) fn navigate_command_palette(
+        &mut self,
+        s: &mut PixState,
+        action: NavAction,
+    ) -> PixResult<()> {
+        let palette = &mut self.command_palette;
+        match action {
+            NavAction::Up => palette.selected = palette.selected.saturating_sub(1),
+            NavAction::Down => {
+                palette.selected = (palette.selected + 1).min(palette.matches.len().max(1) - 1);
+            }
+            NavAction::Confirm => self.run_selected_command(s)?,
+            NavAction::Cancel => {
+                self.exit_menu(s)?;
+                if self.command_palette_open() {
+                    self.mode = Mode::default();
+                }
+            }
+            NavAction::PrevTab | NavAction::NextTab => (),
+        }
+        Ok(())
+    }
+
+    fn run_selected_command(&mut self, s: &mut PixState) -> PixResult<()> {
+        let Some(&(name, action)) = self
+            .command_palette
+            .matches
+            .get(self.command_palette.selected)
+        else {
+            return Ok(());
+        };
+        log::debug!("running command: {name}");
+        self.exit_menu(s)?;
+        if self.command_palette_open() {
+            // No game is running to return to
+            self.mode = Mode::default();
+        }
+        // Some actions, like rewind, run when released
+        self.handle_action(s, Slot::One, action, true, false)?;
+        self.handle_action(s, Slot::One, action, false, false)?;
+        Ok(())
+    }
+
+    pub(crate) fn render_command_palette(&mut self, s: &mut PixState) -> PixResult<()> {
+        self.render_heading(s, "Commands")?;
+
+        let colors = s.theme().colors;
+        let font_size = s.theme().font_size;
+        let spacing = s.theme().spacing;
+
+        s.text(format!("> {}_", self.command_palette.query))?;
+        s.spacing()?;
+
+        let palette = &mut self.command_palette;
+        if palette.matches.is_empty() {
+            s.fill(colors.secondary);
+            s.text("No matching commands")?;
+            return Ok(());
+        }
+        let labels: Vec<String> = palette
+            .matches
+            .iter()
+            .map(|(name, action)| match palette.bindings.get(action) {
+                Some(binding) => format!("{name}  ({binding})"),
+                None => (*name).to_string(),
+            })
+            .collect();
+        let line_height = font_size as i32 + 4 * spacing.item_pad.y();
+        let displayed_count =
+            (s.height()? as usize - s.cursor_pos().y() as usize) / line_height as usize;
+        s.fill(colors.secondary);
+        s.next_width((s.ui_width()? - spacing.scroll_size) as u32);
+        s.select_list(
+            "Up/Down to select, Enter to run",
+            &mut palette.selected,
+            &labels,
+            displayed_count,
+        )?;
+        if s.dbl_clicked() {
+            self.run_selected_command(s)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_matching() {
+        assert!(fuzzy_score("", "Save State").is_some());
+        assert!(fuzzy_score("svst", "Save State").is_some());
+        assert!(fuzzy_score("stsv", "Save State").is_none());
+        assert!(
+            fuzzy_score("ss", "Save State") > fuzzy_score("ss", "Toggle Sound Recording"),
+            "word starts score higher"
+        );
+        assert!(
+            fuzzy_score("rec", "Toggle Video Recording")
+                > fuzzy_score("rec", "Toggle Triangle Channel"),
+            "consecutive characters score higher"
+        );
+    }
+
+    #[test]
+    fn best_match_first() {
+        let matches = matching_commands("fullscreen");
+        assert_eq!(matches[0].0, "Toggle Fullscreen");
+        let matches = matching_commands("ppu");
+        assert_eq!(matches[0].0, "Debug: Toggle PPU Viewer");
+        assert_eq!(matching_commands("").len(), COMMANDS.len());
+    }
+}
@@ -1,56 +1,379 @@
 use crate::{
+    apu::ApuMixing,
+    audio::{output::AudioBackend, AudioMixer},
     common::{config_dir, config_path, NesRegion},
-    input::FourPlayer,
+    cpu::{Cpu, UnofficialOpcodes},
+    input::{FourPlayer, JoypadBtn, Slot},
+    mapper::{AudioChip, NesEvent},
     mem::RamState,
     nes::{
-        event::{Input, InputBindings, InputMapping},
-        Nes, WINDOW_HEIGHT, WINDOW_WIDTH_NTSC, WINDOW_WIDTH_PAL,
+        bezel::BezelMode,
+        clip_capture::ClipFormat,
+        event::{InputBindings, InputMapping},
+        frame_pacing::{FramePacing, SyncMode},
+        input_sharing::InputSharing,
+        menu_nav::NavBindings,
+        mixer::MixerSettings,
+        osd::OsdConfig,
+        persistence::PersistenceBackend,
+        replay_format::ReplayFormat,
+        screenshot::ScreenshotFormat,
+        sound_recording::SoundFormat,
+        video_recording::VideoFormat,
+        Mode, Nes, WINDOW_HEIGHT, WINDOW_WIDTH_NTSC, WINDOW_WIDTH_PAL,
     },
-    video::VideoFilter,
+    video::{ColorFilter, VideoFilter},
+    NesResult,
 };
-use anyhow::Context;
+use anyhow::{anyhow, bail, Context};
 use pix_engine::{
     point,
     prelude::{PixResult, PixState},
 };
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::{BTreeMap, HashMap},
     fs::{self, File},
-    io::{BufReader, BufWriter},
+    io::BufReader,
     path::PathBuf,
+    str::FromStr,
 };
 
-pub(crate) const CONFIG: &str = "config.json";
-const DEFAULT_CONFIG: &[u8] = include_bytes!("../../config/config.json");
-const MIN_SPEED: f32 = 0.25; // 25% - 15 Hz
+pub(crate) const CONFIG: &str = "config.toml";
+/// Configuration file used before TOML, migrated on first load.
+const LEGACY_CONFIG: &str = "config.json";
+pub(crate) const DEFAULT_CONFIG: &[u8] = include_bytes!("../../config/config.json");
+/// Tables the configuration file is split into, to make it easier to edit by hand. Settings are
+/// flattened back into a single table when loaded.
+const SECTIONS: [(&str, &[&str]); 5] = [
+    (
+        "general",
+        &[
+            "rom_path",
+            "watch_rom_dir",
+            "soft_patch",
+            "pause_in_bg",
+            "power_save",
+            "low_battery_percent",
+            "suppress_screensaver",
+            "idle_dim_minutes",
+            "show_tutorial",
+            "last_version",
+            "save_slot",
+            "save_state_cheats",
+            "rewind",
+            "rewind_frames",
+            "rewind_buffer_size",
+            "session_replay_minutes",
+            "replay_format",
+            "persistence",
+            "unicode_font",
+            "genie_codes",
+            "rpc_server",
+            "rpc_address",
+        ],
+    ),
+    (
+        "emulation",
+        &[
+            "region",
+            "region_warning",
+            "ram_state",
+            "ppu_alignment",
+            "dip_switches",
+            "cycle_accurate",
+            "unofficial_opcodes",
+            "speed",
+            "fast_forward_frame_skip",
+            "four_player",
+            "zapper",
+            "motion_aim",
+            "motion_aim_sensitivity",
+            "motion_aim_bias",
+        ],
+    ),
+    (
+        "video",
+        &[
+            "scale",
+            "fullscreen",
+            "vsync",
+            "mini_view",
+            "always_on_top",
+            "click_through",
+            "osd",
+            "frame_pacing",
+            "sync_mode",
+            "filter",
+            "color_filter",
+            "color_filter_simulate",
+            "adaptive_filter",
+            "bezel",
+            "bezel_dir",
+            "hd_pack",
+            "screenshot_dir",
+            "screenshot_format",
+            "screenshot_raw",
+            "screenshot_clipboard",
+            "screenshot_burst",
+            "video_recording_dir",
+            "video_format",
+            "clip_format",
+            "clip_seconds",
+        ],
+    ),
+    (
+        "audio",
+        &[
+            "sound",
+            "audio_backend",
+            "audio_device",
+            "audio_secondary_device",
+            "audio_secondary_volume",
+            "audio_sample_rate",
+            "audio_buffer_size",
+            "audio_device_buffer_sizes",
+            "dynamic_rate_control",
+            "dynamic_rate_delta",
+            "audio_latency",
+            "mute_slow_motion",
+            "mixer",
+            "mixer_per_game",
+            "apu_mixing",
+            "dmc_pop_reduction",
+            "audio_chip_gains",
+            "sound_recording_dir",
+            "sound_recording_format",
+            "sound_recording_stems",
+        ],
+    ),
+    (
+        "input",
+        &[
+            "concurrent_dpad",
+            "lightbar_status",
+            "input_sharing",
+            "turbo_rate",
+            "turbo_rates",
+            "binding_profiles",
+            "bindings",
+            "nav_bindings",
+        ],
+    ),
+];
+
+/// A setting overridden from the command line with `--set key=value`, such as
+/// `--set video.filter=Ntsc`. The section name is optional.
+#[derive(Debug, Clone, PartialEq)]
+#[must_use]
+pub struct ConfigOverride {
+    path: Vec<String>,
+    value: toml::Value,
+}
+
+impl FromStr for ConfigOverride {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected `key=value`, found `{s}`"))?;
+        let mut path: Vec<String> = key.trim().split('.').map(ToString::to_string).collect();
+        if path.iter().any(String::is_empty) {
+            bail!("invalid config key `{key}`");
+        }
+        if path.len() > 1 && SECTIONS.iter().any(|(section, _)| *section == path[0]) {
+            path.remove(0);
+        }
+        // Anything that isn't a valid TOML value, like an enum variant, is taken as a string
+        let value = value.trim();
+        let value = toml::from_str::<toml::Table>(&format!("value = {value}"))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(value.to_string()));
+        Ok(Self { path, value })
+    }
+}
+
+impl ConfigOverride {
+    fn apply(&self, table: &mut toml::Table) -> NesResult<()> {
+        let key = self.path.join(".");
+        let (name, parents) = self.path.split_last().expect("non-empty path");
+        let mut table = table;
+        for parent in parents {
+            table = table
+                .entry(parent.clone())
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| anyhow!("`{key}` is not a table"))?;
+        }
+        table.insert(name.clone(), self.value.clone());
+        Ok(())
+    }
+}
+
+/// Every setting in the configuration file.
+fn known_keys() -> impl Iterator<Item = &'static str> {
+    SECTIONS.iter().flat_map(|(_, keys)| keys.iter().copied())
+}
+
+/// Finds the closest known key to an unknown one, for suggesting fixes to typos.
+fn closest_key<'a>(key: &str, keys: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let distance = |a: &str, b: &str| {
+        let b: Vec<char> = b.chars().collect();
+        let mut prev: Vec<usize> = (0..=b.len()).collect();
+        for (i, a) in a.chars().enumerate() {
+            let mut row = vec![i + 1];
+            for (j, &b) in b.iter().enumerate() {
+                let cost = usize::from(a != b);
+                row.push((prev[j] + cost).min(prev[j + 1] + 1).min(row[j] + 1));
+            }
+            prev = row;
+        }
+        prev[b.len()]
+    };
+    keys.map(|known| (distance(key, known), known))
+        .filter(|&(distance, _)| distance <= 3)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, known)| known)
+}
+
+/// The variants listed by an `unknown variant` error.
+fn expected_variants(err: &str) -> Option<Vec<&str>> {
+    if !err.contains("unknown variant") {
+        return None;
+    }
+    let (_, expected) = err.lines().next()?.split_once("expected")?;
+    Some(expected.split('`').skip(1).step_by(2).collect())
+}
+
+/// Replaces strings in `value` that match one of `variants` ignoring case with the variant, so
+/// enum settings can be written in any case. Returns whether anything was replaced.
+fn match_variants(value: &mut toml::Value, variants: &[&str]) -> bool {
+    match value {
+        toml::Value::String(s) => match variants
+            .iter()
+            .find(|variant| variant.eq_ignore_ascii_case(s) && *variant != s)
+        {
+            Some(variant) => {
+                *s = variant.to_string();
+                true
+            }
+            None => false,
+        },
+        toml::Value::Array(values) => values.iter_mut().fold(false, |replaced, value| {
+            match_variants(value, variants) || replaced
+        }),
+        toml::Value::Table(table) => table.values_mut().fold(false, |replaced, value| {
+            match_variants(value, variants) || replaced
+        }),
+        _ => false,
+    }
+}
+const MIN_SPEED: f32 = 0.1; // 10% - 6 Hz
 const MAX_SPEED: f32 = 2.0; // 200% - 120 Hz
+/// Speeds slow motion steps through before returning to normal speed.
+const SLOW_MOTION_SPEEDS: [f32; 4] = [0.75, 0.5, 0.25, 0.1];
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// NES emulation configuration settings.
 pub(crate) struct Config {
     pub(crate) rom_path: PathBuf,
+    pub(crate) watch_rom_dir: bool,
+    /// Apply IPS, BPS and UPS patches found next to a ROM when it's loaded.
+    pub(crate) soft_patch: bool,
     pub(crate) pause_in_bg: bool,
+    pub(crate) power_save: bool,
+    pub(crate) low_battery_percent: u32,
+    /// Keep the screensaver and display sleep from starting while a game is running.
+    pub(crate) suppress_screensaver: bool,
+    /// Minutes without input before dimming the screen. `0` disables dimming.
+    pub(crate) idle_dim_minutes: u32,
+    pub(crate) show_tutorial: bool,
+    /// Version last run, to show what's new after an update.
+    pub(crate) last_version: String,
     pub(crate) sound: bool,
     pub(crate) fullscreen: bool,
     pub(crate) vsync: bool,
+    pub(crate) mini_view: bool,
+    /// Keep the window above other windows, even when they're focused.
+    pub(crate) always_on_top: bool,
+    /// Let mouse clicks go through the window to the windows behind it.
+    pub(crate) click_through: bool,
+    pub(crate) osd: OsdConfig,
+    pub(crate) frame_pacing: FramePacing,
+    pub(crate) sync_mode: SyncMode,
     pub(crate) filter: VideoFilter,
+    pub(crate) color_filter: ColorFilter,
+    pub(crate) color_filter_simulate: bool,
+    pub(crate) adaptive_filter: bool,
+    pub(crate) bezel: BezelMode,
+    pub(crate) bezel_dir: Option<PathBuf>,
+    pub(crate) hd_pack: bool,
     pub(crate) concurrent_dpad: bool,
+    pub(crate) lightbar_status: bool,
+    pub(crate) input_sharing: InputSharing,
+    pub(crate) turbo_rate: u32,
+    pub(crate) turbo_rates: HashMap<Slot, HashMap<JoypadBtn, u32>>,
     pub(crate) region: NesRegion,
+    pub(crate) region_warning: bool,
     pub(crate) ram_state: RamState,
+    pub(crate) ppu_alignment: u8,
+    pub(crate) dip_switches: u8,
+    pub(crate) cycle_accurate: bool,
+    pub(crate) unofficial_opcodes: UnofficialOpcodes,
     pub(crate) save_slot: u8,
+    pub(crate) save_state_cheats: bool,
     pub(crate) scale: f32,
     pub(crate) speed: f32,
+    pub(crate) fast_forward_frame_skip: bool,
     pub(crate) rewind: bool,
     pub(crate) rewind_frames: u32,
     pub(crate) rewind_buffer_size: usize,
+    pub(crate) session_replay_minutes: u32,
+    pub(crate) replay_format: ReplayFormat,
     pub(crate) four_player: FourPlayer,
     pub(crate) zapper: bool,
+    pub(crate) motion_aim: bool,
+    pub(crate) motion_aim_sensitivity: f32,
+    pub(crate) motion_aim_bias: [f32; 2],
+    pub(crate) audio_backend: AudioBackend,
+    pub(crate) audio_device: Option<String>,
+    pub(crate) audio_secondary_device: Option<String>,
+    pub(crate) audio_secondary_volume: f32,
     pub(crate) audio_sample_rate: f32,
     pub(crate) audio_buffer_size: usize,
+    pub(crate) audio_device_buffer_sizes: HashMap<String, usize>,
     pub(crate) dynamic_rate_control: bool,
     pub(crate) dynamic_rate_delta: f32,
+    pub(crate) audio_latency: f32,
+    pub(crate) mute_slow_motion: bool,
+    pub(crate) mixer: MixerSettings,
+    pub(crate) mixer_per_game: bool,
+    pub(crate) apu_mixing: ApuMixing,
+    pub(crate) dmc_pop_reduction: bool,
+    pub(crate) audio_chip_gains: HashMap<AudioChip, f32>,
+    pub(crate) video_recording_dir: PathBuf,
+    pub(crate) video_format: VideoFormat,
+    pub(crate) clip_format: ClipFormat,
+    pub(crate) clip_seconds: u32,
+    pub(crate) sound_recording_dir: PathBuf,
+    pub(crate) screenshot_dir: PathBuf,
+    pub(crate) screenshot_format: ScreenshotFormat,
+    pub(crate) screenshot_raw: bool,
+    pub(crate) screenshot_clipboard: bool,
+    pub(crate) screenshot_burst: u32,
+    pub(crate) sound_recording_format: SoundFormat,
+    pub(crate) sound_recording_stems: bool,
+    pub(crate) persistence: PersistenceBackend,
+    pub(crate) unicode_font: Option<PathBuf>,
     pub(crate) genie_codes: Vec<String>,
+    pub(crate) rpc_server: bool,
+    pub(crate) rpc_address: String,
+    pub(crate) binding_profiles: BTreeMap<String, InputBindings>,
     pub(crate) bindings: InputBindings,
+    /// Menu and dialog navigation, separate from gameplay bindings.
+    pub(crate) nav_bindings: NavBindings,
     #[serde(skip)]
     pub(crate) input_map: InputMapping,
 }
@@ -59,28 +382,94 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             rom_path: PathBuf::from("./"),
+            watch_rom_dir: true,
+            soft_patch: true,
             pause_in_bg: true,
+            power_save: true,
+            low_battery_percent: 10,
+            suppress_screensaver: true,
+            idle_dim_minutes: 0,
+            show_tutorial: true,
+            last_version: String::new(),
             sound: true,
             fullscreen: false,
             vsync: true,
+            mini_view: false,
+            always_on_top: false,
+            click_through: false,
+            osd: OsdConfig::default(),
+            frame_pacing: FramePacing::default(),
+            sync_mode: SyncMode::default(),
             filter: VideoFilter::default(),
+            color_filter: ColorFilter::default(),
+            color_filter_simulate: false,
+            adaptive_filter: true,
+            bezel: BezelMode::default(),
+            bezel_dir: None,
+            hd_pack: true,
             concurrent_dpad: false,
+            lightbar_status: true,
+            input_sharing: InputSharing::default(),
+            turbo_rate: 10,
+            turbo_rates: HashMap::new(),
             region: NesRegion::default(),
+            region_warning: true,
             ram_state: RamState::default(),
+            ppu_alignment: Cpu::DEFAULT_PPU_ALIGNMENT,
+            dip_switches: NesEvent::DEFAULT_DIP_SWITCHES,
+            cycle_accurate: true,
+            unofficial_opcodes: UnofficialOpcodes::default(),
             save_slot: 1,
+            save_state_cheats: true,
             scale: 3.0,
             speed: 1.0,
+            fast_forward_frame_skip: true,
             rewind: false,
             rewind_frames: 2,
             rewind_buffer_size: 20,
+            session_replay_minutes: 0,
+            replay_format: ReplayFormat::default(),
             four_player: FourPlayer::default(),
             zapper: false,
+            motion_aim: false,
+            motion_aim_sensitivity: 1.0,
+            motion_aim_bias: [0.0; 2],
+            audio_backend: AudioBackend::Sdl,
+            audio_device: None,
+            audio_secondary_device: None,
+            audio_secondary_volume: 1.0,
             audio_sample_rate: 44_100.0,
             audio_buffer_size: 4096,
+            audio_device_buffer_sizes: HashMap::new(),
             dynamic_rate_control: true,
             dynamic_rate_delta: 0.005,
+            audio_latency: 40.0,
+            mute_slow_motion: false,
+            mixer: MixerSettings::default(),
+            mixer_per_game: false,
+            apu_mixing: ApuMixing::Accurate,
+            dmc_pop_reduction: false,
+            audio_chip_gains: HashMap::new(),
+            video_recording_dir: PathBuf::from("./"),
+            video_format: VideoFormat::default(),
+            clip_format: ClipFormat::default(),
+            clip_seconds: 0,
+            sound_recording_dir: PathBuf::from("./"),
+            screenshot_dir: PathBuf::from("./"),
+            screenshot_format: ScreenshotFormat::default(),
+            screenshot_raw: false,
+            screenshot_clipboard: false,
+            screenshot_burst: 1,
+            sound_recording_format: SoundFormat::default(),
+            sound_recording_stems: false,
+            persistence: PersistenceBackend::default(),
+            unicode_font: None,
             genie_codes: vec![],
+            rpc_server: false,
+            rpc_address: "127.0.0.1:7777".to_string(),
+            binding_profiles: BTreeMap::new(),
             bindings: InputBindings::default(),
+            nav_bindings: NavBindings::default(),
             input_map: InputMapping::default(),
         }
     }
@@ -88,6 +477,13 @@ impl Default for Config {
 
 impl Config {
     pub(crate) fn load() -> Self {
+        Self::load_with_overrides(&[])
+    }
+
+    /// Loads the configuration file, creating it if it doesn't exist, with `overrides` applied.
+    /// An invalid file is reported and replaced by the defaults for this session, but left on
+    /// disk to be fixed.
+    pub(crate) fn load_with_overrides(overrides: &[ConfigOverride]) -> Self {
         let config_dir = config_dir();
         if !config_dir.exists() {
             if let Err(err) =
@@ -96,84 +492,221 @@ impl Config {
                 log::error!("{:?}", err);
             }
         }
+        let legacy_path = config_path(LEGACY_CONFIG);
         let config_path = config_path(CONFIG);
         if !config_path.exists() {
-            if let Err(err) =
-                fs::write(&config_path, DEFAULT_CONFIG).context("failed to create default config")
-            {
+            let config = if legacy_path.exists() {
+                log::info!("Migrating {legacy_path:?} to {config_path:?}");
+                File::open(&legacy_path)
+                    .with_context(|| format!("failed to open {legacy_path:?}"))
+                    .and_then(|file| {
+                        serde_json::from_reader(BufReader::new(file))
+                            .with_context(|| format!("failed to parse {legacy_path:?}"))
+                    })
+                    .unwrap_or_else(|err| {
+                        log::error!("{err:?}");
+                        Self::default_config()
+                    })
+            } else {
+                Self::default_config()
+            };
+            if let Err(err) = config.save().context("failed to create default config") {
                 log::error!("{:?}", err);
             }
         }
 
-        let mut config = File::open(&config_path)
+        let mut config = fs::read_to_string(&config_path)
             .with_context(|| format!("failed to open {config_path:?}"))
-            .and_then(|file| Ok(serde_json::from_reader::<_, Config>(BufReader::new(file))?))
-            .or_else(|err| {
-                log::error!(
-                    "Invalid config: {config_path:?}, reverting to defaults. Error: {err:?}",
-                );
-                serde_json::from_reader(DEFAULT_CONFIG)
-            })
-            .with_context(|| format!("failed to parse {config_path:?}"))
-            .expect("valid configuration");
-
-        for bind in &config.bindings.keys {
-            config.input_map.insert(
-                Input::Key((bind.player, bind.key, bind.keymod)),
-                bind.action,
-            );
+            .and_then(|text| Self::from_toml(&text, overrides))
+            .unwrap_or_else(|err| {
+                log::error!("Invalid config: {config_path:?}, reverting to defaults. Error: {err}");
+                let mut config = Self::default_config();
+                for config_override in overrides {
+                    if let Err(err) = config.set(config_override) {
+                        log::error!("{err}");
+                    }
+                }
+                config
+            });
+        config.input_map = config.bindings.to_mapping();
+        config
+    }
+
+    /// The default configuration shipped with `TetaNES`.
+    fn default_config() -> Self {
+        serde_json::from_slice(DEFAULT_CONFIG).expect("valid default configuration")
+    }
+
+    fn default_table() -> toml::Table {
+        Self::to_table(&Self::default_config()).expect("valid default configuration")
+    }
+
+    fn to_table(&self) -> NesResult<toml::Table> {
+        match toml::Value::try_from(self).context("failed to serialize config")? {
+            toml::Value::Table(table) => Ok(table),
+            _ => bail!("config is not a table"),
         }
-        for bind in &config.bindings.mouse {
-            config
-                .input_map
-                .insert(Input::Mouse((bind.player, bind.button)), bind.action);
+    }
+
+    /// Parses a configuration file, validating every setting. Missing settings use their
+    /// defaults so older or hand-written files keep working.
+    ///
+    /// # Errors
+    ///
+    /// If the file isn't valid TOML, has unknown settings or a setting has an invalid value, an
+    /// error is returned naming the setting.
+    pub(crate) fn from_toml(text: &str, overrides: &[ConfigOverride]) -> NesResult<Self> {
+        let mut table: toml::Table = toml::from_str(text).context("invalid TOML")?;
+        for (section, _) in SECTIONS {
+            if let Some(value) = table.remove(section) {
+                match value {
+                    toml::Value::Table(settings) => table.extend(settings),
+                    _ => bail!("`{section}` must be a table"),
+                }
+            }
         }
-        for bind in &config.bindings.buttons {
-            config
-                .input_map
-                .insert(Input::Button((bind.player, bind.button)), bind.action);
+        for config_override in overrides {
+            config_override.apply(&mut table)?;
         }
-        for bind in &config.bindings.axes {
-            config.input_map.insert(
-                Input::Axis((bind.player, bind.axis, bind.direction)),
-                bind.action,
-            );
+        Self::from_table(table)
+    }
+
+    fn from_table(mut table: toml::Table) -> NesResult<Self> {
+        // Settings that default to `None` aren't serialized, so check against every setting
+        if let Some(key) = table
+            .keys()
+            .find(|key| !known_keys().any(|known| known == *key))
+        {
+            match closest_key(key, known_keys()) {
+                Some(known) => bail!("unknown setting `{key}`, did you mean `{known}`?"),
+                None => bail!("unknown setting `{key}`"),
+            }
+        }
+        let defaults = Self::default_table();
+        let merged = |table: &toml::Table| {
+            let mut merged = defaults.clone();
+            merged.extend(table.clone());
+            toml::Value::Table(merged).try_into::<Self>()
+        };
+        if let Ok(config) = merged(&table) {
+            return Ok(config);
+        }
+        // Find the setting responsible, since the error alone doesn't say which it was, fixing
+        // the case of enum values along the way
+        for (key, value) in &mut table {
+            loop {
+                let mut single = defaults.clone();
+                single.insert(key.clone(), value.clone());
+                let Err(err) = toml::Value::Table(single).try_into::<Self>() else {
+                    break;
+                };
+                let err = err.to_string();
+                if !expected_variants(&err)
+                    .map_or(false, |variants| match_variants(value, &variants))
+                {
+                    bail!("invalid value for `{}`: {err}", Self::section_key(key));
+                }
+            }
         }
+        merged(&table).context("invalid configuration")
+    }
 
-        config
+    /// The key of a setting including its section, as written in the configuration file.
+    fn section_key(key: &str) -> String {
+        SECTIONS
+            .iter()
+            .find(|(_, keys)| keys.contains(&key))
+            .map_or_else(
+                || key.to_string(),
+                |(section, _)| format!("{section}.{key}"),
+            )
     }
 
-    // pub(crate) fn add_binding(&mut self, input: Input, action: Action) {
-    //     self.input_map.insert(input, action);
-    //     self.bindings.update_from_map(&self.input_map);
-    // }
+    /// Sets a single setting, validating the new value.
+    ///
+    /// # Errors
+    ///
+    /// If the setting is unknown or the value is invalid, an error is returned.
+    pub(crate) fn set(&mut self, config_override: &ConfigOverride) -> NesResult<()> {
+        let mut table = self.to_table()?;
+        config_override.apply(&mut table)?;
+        let input_map = std::mem::take(&mut self.input_map);
+        *self = Self::from_table(table)?;
+        self.input_map = input_map;
+        Ok(())
+    }
 
-    // pub(crate) fn remove_binding(&mut self, input: Input) {
-    //     self.input_map.remove(&input);
-    //     self.bindings.update_from_map(&self.input_map);
-    // }
+    /// Audio buffer size for the selected output device, falling back to `audio_buffer_size` for
+    /// the default device or devices without their own setting.
+    pub(crate) fn device_buffer_size(&self) -> usize {
+        self.audio_device
+            .as_ref()
+            .and_then(|device| self.audio_device_buffer_sizes.get(device))
+            .copied()
+            .unwrap_or(self.audio_buffer_size)
+    }
+
+    pub(crate) fn set_device_buffer_size(&mut self, buffer_size: usize) {
+        match self.audio_device {
+            Some(ref device) => {
+                self.audio_device_buffer_sizes
+                    .insert(device.clone(), buffer_size);
+            }
+            None => self.audio_buffer_size = buffer_size,
+        }
+    }
+
+    /// Gain for a cartridge sound chip, defaulting to `1.0` for chips without their own setting.
+    pub(crate) fn audio_chip_gain(&self, chip: AudioChip) -> f32 {
+        self.audio_chip_gains.get(&chip).copied().unwrap_or(1.0)
+    }
+
+    /// Writes the configuration to disk.
+    ///
+    /// # Errors
+    ///
+    /// If the configuration fails to serialize or write, an error is returned.
+    pub(crate) fn save(&self) -> NesResult<()> {
+        let path = config_path(CONFIG);
+        fs::write(&path, self.to_toml()?).with_context(|| format!("failed to write {path:?}"))
+    }
+
+    /// Serializes the configuration split into sections.
+    pub(crate) fn to_toml(&self) -> NesResult<String> {
+        let mut table = self.to_table()?;
+        let mut sectioned = toml::Table::new();
+        for (section, keys) in SECTIONS {
+            let settings: toml::Table = keys
+                .iter()
+                .filter_map(|&key| table.remove_entry(key))
+                .collect();
+            sectioned.insert(section.to_string(), toml::Value::Table(settings));
+        }
+        // Settings not in a section yet stay at the top
+        sectioned.extend(table);
+        toml::to_string_pretty(&sectioned).context("failed to serialize config")
+    }
 
     pub(crate) fn get_dimensions(&self) -> (u32, u32) {
         let width = match self.region {
             NesRegion::Ntsc => WINDOW_WIDTH_NTSC,
             NesRegion::Pal | NesRegion::Dendy => WINDOW_WIDTH_PAL,
         };
-        let width = (self.scale * width) as u32;
-        let height = (self.scale * WINDOW_HEIGHT) as u32;
+        // Mini view always uses the smallest window
+        let scale = if self.mini_view { 1.0 } else { self.scale };
+        let width = (scale * width) as u32;
+        let height = (scale * WINDOW_HEIGHT) as u32;
         (width, height)
     }
 }
 
 impl Nes {
     pub(crate) fn save_config(&mut self) {
-        let path = config_path(CONFIG);
-        match File::create(&path)
-            .with_context(|| format!("failed to open {path:?}"))
-            .and_then(|file| {
-                serde_json::to_writer_pretty(BufWriter::new(file), &self.config)
-                    .context("failed to serialize config")
-            }) {
-            Ok(_) => log::info!("Saved configuration"),
+        match self.config.save() {
+            Ok(_) => {
+                self.config_watch.saved();
+                log::info!("Saved configuration");
+            }
             Err(err) => {
                 log::error!("{:?}", err);
                 self.add_message("Failed to save configuration");
@@ -195,17 +728,176 @@ impl Nes {
     }
 
     pub(crate) fn change_speed(&mut self, delta: f32) {
-        self.config.speed = (self.config.speed + delta).clamp(MIN_SPEED, MAX_SPEED);
-        self.set_speed(self.config.speed);
+        // Stay on 25% steps when leaving the slowest speed
+        let speed = (4.0 * (self.config.speed + delta)).round() / 4.0;
+        self.set_speed(speed.clamp(MIN_SPEED, MAX_SPEED));
+    }
+
+    /// Steps down to the next slow motion speed, or back to normal speed from the slowest.
+    pub(crate) fn cycle_slow_motion(&mut self) {
+        let speed = SLOW_MOTION_SPEEDS
+            .into_iter()
+            .find(|&speed| speed < self.config.speed - f32::EPSILON)
+            .unwrap_or(1.0);
+        self.set_speed(speed);
+        self.add_message(format!("Speed {:.0}%", 100.0 * speed));
     }
 
     pub(crate) fn set_speed(&mut self, speed: f32) {
         self.config.speed = speed;
+        self.audio.set_speed(speed);
+    }
+
+    /// Toggles a compact window that keeps running in the background and hides status text,
+    /// for keeping the game visible while working in or streaming from other windows.
+    pub(crate) fn set_mini_view(&mut self, s: &mut PixState, enabled: bool) -> PixResult<()> {
+        self.config.mini_view = enabled;
+        if self.config.fullscreen {
+            self.config.fullscreen = false;
+            s.fullscreen(false)?;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        self.set_window_bordered(!enabled);
+        s.set_window_dimensions(self.config.get_dimensions())?;
+        if self.mode == Mode::PausedBg {
+            self.resume_play();
+        }
+        Ok(())
+    }
+
+    /// Recreates the audio mixer from the current configuration and opens playback on the
+    /// selected backend and device, falling back to the default device and then to SDL if it's
+    /// unavailable. If no device can be opened, emulation continues without sound.
+    pub(crate) fn open_audio(&mut self, s: &mut PixState) -> PixResult<()> {
+        self.audio = AudioMixer::new(
+            self.control_deck.sample_rate(),
+            self.config.audio_sample_rate,
+            self.config.device_buffer_size(),
+        );
+        self.audio.set_speed(self.config.speed);
         self.audio
-            .set_output_frequency(self.config.audio_sample_rate / self.config.speed);
+            .set_target_latency(self.config.audio_latency / 1000.0);
+        self.audio.set_volume(self.mixer.master);
+        self.audio.set_equalizer(self.mixer.bass, self.mixer.treble);
+        let backend = self.config.audio_backend;
+        let device = self.config.audio_device.clone();
+        let mut result = self.audio.open_playback(s, backend, device.as_deref());
+        if let (Err(err), Some(device)) = (&result, &device) {
+            log::error!("{err:?}");
+            self.add_message(format!(
+                "Failed to open audio device {device:?}. Using default device."
+            ));
+            self.audio.reset(self.config.audio_buffer_size);
+            result = self.audio.open_playback(s, backend, None);
+        }
+        if let (Err(err), AudioBackend::Cpal) = (&result, backend) {
+            log::error!("{err:?}");
+            self.add_message("Failed to open native audio device. Using SDL.");
+            self.audio.reset(self.config.audio_buffer_size);
+            result = self.audio.open_playback(s, AudioBackend::Sdl, None);
+        }
+        if let Err(err) = result {
+            log::error!("{err:?}");
+            self.add_message("Failed to open audio device. Sound is disabled.");
+            self.audio.reset(self.config.audio_buffer_size);
+            return Ok(());
+        }
+        self.open_secondary_audio(s);
+        Ok(())
+    }
+
+    /// Opens the secondary output device, if one is selected. Failing to open it doesn't stop
+    /// playback on the primary device.
+    pub(crate) fn open_secondary_audio(&mut self, s: &mut PixState) {
+        match self.config.audio_secondary_device.clone() {
+            Some(device) => {
+                if let Err(err) = self.audio.open_secondary_playback(
+                    s,
+                    &device,
+                    self.config.audio_secondary_volume,
+                ) {
+                    log::error!("{err:?}");
+                    self.add_message(format!("Failed to open second audio device {device:?}"));
+                    return;
+                }
+                if self.mode == Mode::Playing {
+                    self.audio.resume();
+                }
+            }
+            None => self.audio.close_secondary_playback(),
+        }
+    }
+
+    /// Hands audio generated since the last call to the recorders and, while playing, to the
+    /// output device. Audio is generated every CPU cycle, so this is called after every slice
+    /// of emulated time rather than once per video frame. Audio emulated while paused, like
+    /// when frame stepping, is only recorded so it doesn't play back in a burst on resume.
+    pub(crate) fn process_audio(&mut self) -> NesResult<()> {
+        self.record_sound_samples();
+        self.record_video_samples();
+        self.capture_apu_samples();
+        if self.audio_output_enabled() && self.mode == Mode::Playing {
+            #[cfg(feature = "profile-rate-control")]
+            {
+                use std::io::Write;
+                let frame = self.control_deck.frame_number();
+                writeln!(
+                    self.stats,
+                    "{} {} {}",
+                    frame,
+                    self.audio.len(),
+                    self.audio.pitch_ratio()
+                )?;
+            }
+            self.audio.consume(
+                self.control_deck.audio_samples(),
+                // Audio sync keeps the buffer filled by pacing emulation instead
+                self.config.dynamic_rate_control && self.config.sync_mode != SyncMode::Audio,
+                self.config.dynamic_rate_delta,
+            );
+        }
+        self.control_deck.clear_audio_samples();
+        Ok(())
+    }
+
+    /// Reopens audio playback if the output device was lost or the system default changed.
+    pub(crate) fn check_audio_device(&mut self, s: &mut PixState) -> PixResult<()> {
+        if self.config.sound && self.audio.needs_reconnect() {
+            log::info!("Audio device changed, reconnecting");
+            self.open_audio(s)?;
+            if self.mode == Mode::Playing {
+                self.audio.resume();
+            }
+        }
+        Ok(())
+    }
+
+    /// Changes the sync mode, restoring vsync when leaving unsynced mode.
+    pub(crate) fn set_sync_mode(&mut self, s: &mut PixState, sync_mode: SyncMode) -> PixResult<()> {
+        let previous = self.config.sync_mode;
+        self.config.sync_mode = sync_mode;
+        self.update_frame_rate(s)?;
+        if previous == SyncMode::Unsynced && self.config.vsync {
+            s.vsync(true)?;
+        }
+        Ok(())
+    }
+
+    /// Whether emulated audio is played, rather than disabled, muted or skipped for benchmarking.
+    pub(crate) fn audio_output_enabled(&self) -> bool {
+        let muted = self.config.mute_slow_motion && self.config.speed < 1.0;
+        self.config.sound && !muted && self.config.sync_mode != SyncMode::Unsynced
     }
 
     pub(crate) fn update_frame_rate(&mut self, s: &mut PixState) -> PixResult<()> {
+        if self.config.sync_mode == SyncMode::Unsynced {
+            // Run as many updates as possible for benchmarking
+            s.clear_frame_rate();
+            if self.config.vsync {
+                s.vsync(false)?;
+            }
+            return Ok(());
+        }
         match self.config.region {
             NesRegion::Ntsc => s.frame_rate(60),
             NesRegion::Pal => s.frame_rate(50),
@@ -216,10 +908,89 @@ impl Nes {
             self.config.region,
             s.target_frame_rate()
         );
+        // Frame pacing repeats frames to fit other refresh rates, otherwise emulation speed would
+        // follow the display
         // TODO: Should actually check current screen refresh rate here instead of region
-        if self.config.vsync && self.config.region != NesRegion::Ntsc {
+        if self.config.vsync
+            && self.config.frame_pacing == FramePacing::Time
+            && self.config.region != NesRegion::Ntsc
+        {
             s.vsync(false)?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toml_config() {
+        let defaults = Config::default_table();
+        for key in defaults.keys() {
+            assert!(
+                SECTIONS
+                    .iter()
+                    .any(|(_, keys)| keys.contains(&key.as_str())),
+                "`{key}` has no section"
+            );
+        }
+
+        let config = Config::default_config();
+        let text = config.to_toml().expect("serialized config");
+        assert!(text.contains("[video]"));
+        let loaded = Config::from_toml(&text, &[]).expect("valid config");
+        assert_eq!(loaded.to_table().ok(), config.to_table().ok());
+
+        // Missing settings use defaults
+        let loaded =
+            Config::from_toml("[video]\nfilter = \"Pixellate\"\n", &[]).expect("valid config");
+        assert_eq!(loaded.filter, VideoFilter::Pixellate);
+        assert_eq!(loaded.scale, config.scale);
+
+        let overrides = [
+            "video.filter=Pixellate".parse().expect("valid override"),
+            "audio.mixer.master = 0.5".parse().expect("valid override"),
+            "speed=1.5".parse().expect("valid override"),
+        ];
+        let loaded = Config::from_toml(&text, &overrides).expect("valid config");
+        assert_eq!(loaded.filter, VideoFilter::Pixellate);
+        assert!((loaded.mixer.master - 0.5).abs() < f32::EPSILON);
+        assert!((loaded.speed - 1.5).abs() < f32::EPSILON);
+        assert!("filter".parse::<ConfigOverride>().is_err());
+
+        let err = Config::from_toml("[video]\nfiltr = \"Ntsc\"\n", &[]).expect_err("unknown key");
+        assert!(err.to_string().contains("did you mean `filter`"), "{err}");
+        let err = Config::from_toml("[video]\nfilter = \"blurry\"\n", &[]).expect_err("bad value");
+        assert!(err.to_string().contains("`video.filter`"), "{err}");
+
+        // Enum values are matched ignoring case
+        let loaded = Config::from_toml("[video]\nfilter = \"ntsc\"\n", &[]).expect("valid config");
+        assert_eq!(loaded.filter, VideoFilter::Ntsc);
+        let overrides = ["video.filter=ntsc".parse().expect("valid override")];
+        let loaded = Config::from_toml(&text, &overrides).expect("valid config");
+        assert_eq!(loaded.filter, VideoFilter::Ntsc);
+    }
+
+    #[test]
+    fn optional_settings() {
+        let mut config = Config::default_config();
+        config.audio_device = Some("Speakers".to_string());
+        config.audio_secondary_device = Some("Headphones".to_string());
+        config.bezel_dir = Some(PathBuf::from("bezels"));
+        config.unicode_font = Some(PathBuf::from("font.ttf"));
+        let table = config.to_table().expect("serialized config");
+        assert_eq!(table.len(), known_keys().count());
+
+        let text = config.to_toml().expect("serialized config");
+        let loaded = Config::from_toml(&text, &[]).expect("valid config");
+        assert_eq!(loaded.to_table().ok(), Some(table));
+
+        let overrides = ["audio.audio_device=\"Headphones\""
+            .parse()
+            .expect("valid override")];
+        let loaded = Config::from_toml(&text, &overrides).expect("valid config");
+        assert_eq!(loaded.audio_device.as_deref(), Some("Headphones"));
+    }
+}
@@ -0,0 +1,189 @@
+//! Reloads the configuration file when it's edited while `TetaNES` is running.
+//!
+//! The file's modified time is polled rather than watched so it works the same on every
+//! platform. Settings are applied as if they had been changed in the menu, and an invalid file is
+//! reported without changing anything so it can be fixed and saved again.
+
+use crate::{
+    common::config_path,
+    mapper::AudioChip,
+    nes::{
+        config::{Config, ConfigOverride, CONFIG},
+        state::ReplayMode,
+        Nes,
+    },
+    NesResult,
+};
+use anyhow::Context;
+use pix_engine::prelude::*;
+use std::{
+    fs,
+    time::{Duration, Instant, SystemTime},
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+#[must_use]
+pub(crate) struct ConfigWatch {
+    /// Command-line overrides, reapplied on every reload.
+    overrides: Vec<ConfigOverride>,
+    modified: Option<SystemTime>,
+    last_check: Instant,
+}
+
+impl ConfigWatch {
+    pub(crate) fn new(overrides: Vec<ConfigOverride>) -> Self {
+        Self {
+            overrides,
+            modified: modified_time(),
+            last_check: Instant::now(),
+        }
+    }
+
+    /// Records the current modified time so saving the configuration doesn't trigger a reload.
+    pub(crate) fn saved(&mut self) {
+        self.modified = modified_time();
+    }
+}
+
+fn modified_time() -> Option<SystemTime> {
+    fs::metadata(config_path(CONFIG))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl Nes {
+    /// Reloads the configuration if the file changed since it was last loaded or saved.
+    pub(crate) fn check_config_reload(&mut self, s: &mut PixState) -> PixResult<()> {
+        if self.config_watch.last_check.elapsed() < CHECK_INTERVAL {
+            return Ok(());
+        }
+        self.config_watch.last_check = Instant::now();
+        let modified = modified_time();
+        if modified.is_none() || modified == self.config_watch.modified {
+            return Ok(());
+        }
+        self.config_watch.modified = modified;
+
+        match self.read_config() {
+            Ok(config) => {
+                let previous = std::mem::replace(&mut self.config, config);
+                self.apply_config_changes(s, &previous)?;
+                log::info!("Reloaded configuration");
+                self.add_message("Reloaded configuration");
+            }
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message(format!("Invalid configuration: {err}"));
+            }
+        }
+        Ok(())
+    }
+
+    fn read_config(&self) -> NesResult<Config> {
+        let path = config_path(CONFIG);
+        let text = fs::read_to_string(&path).with_context(|| format!("failed to read {path:?}"))?;
+        let mut config = Config::from_toml(&text, &self.config_watch.overrides)?;
+        // The loaded ROM decides these for the session
+        config.rom_path = self.config.rom_path.clone();
+        config.region = self.config.region;
+        config.input_map = config.bindings.to_mapping();
+        Ok(config)
+    }
+
+    /// Applies settings that differ from `previous` to the running emulator.
+    fn apply_config_changes(&mut self, s: &mut PixState, previous: &Config) -> PixResult<()> {
+        let config = self.config.clone();
+        if config.speed != previous.speed {
+            self.set_speed(config.speed);
+        }
+        if config.fullscreen != previous.fullscreen {
+            s.fullscreen(config.fullscreen)?;
+        }
+        if config.vsync != previous.vsync {
+            s.vsync(config.vsync)?;
+        }
+        if config.scale != previous.scale || config.mini_view != previous.mini_view {
+            self.set_scale(s, config.scale);
+            s.set_window_dimensions(config.get_dimensions())?;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if config.mini_view != previous.mini_view {
+                self.set_window_bordered(!config.mini_view);
+            }
+            if config.always_on_top != previous.always_on_top {
+                self.set_always_on_top(config.always_on_top);
+            }
+            if config.click_through != previous.click_through {
+                self.set_click_through(config.click_through);
+            }
+        }
+        if config.sync_mode != previous.sync_mode {
+            let sync_mode = config.sync_mode;
+            self.config.sync_mode = previous.sync_mode;
+            self.set_sync_mode(s, sync_mode)?;
+        } else if config.frame_pacing != previous.frame_pacing {
+            self.update_frame_rate(s)?;
+        }
+
+        self.control_deck.set_filter(config.filter);
+        self.control_deck
+            .set_color_filter(config.color_filter, config.color_filter_simulate);
+        if config.four_player != previous.four_player {
+            self.control_deck.set_four_player(config.four_player);
+        }
+        if config.zapper != previous.zapper {
+            self.control_deck.connect_zapper(config.zapper);
+        }
+        self.control_deck.set_apu_mixing(config.apu_mixing);
+        self.control_deck
+            .set_dmc_pop_reduction(config.dmc_pop_reduction);
+        self.control_deck.set_dip_switches(config.dip_switches);
+        self.control_deck.set_cycle_accurate(config.cycle_accurate);
+        if self.replay.mode != ReplayMode::Playback {
+            self.control_deck.set_ram_state(config.ram_state);
+            self.control_deck.set_ppu_alignment(config.ppu_alignment);
+        }
+        self.control_deck
+            .set_unofficial_opcodes(config.unofficial_opcodes);
+        self.control_deck.set_state_cheats(config.save_state_cheats);
+        for &chip in AudioChip::as_slice() {
+            self.control_deck
+                .set_audio_chip_gain(chip, config.audio_chip_gain(chip));
+        }
+        if config.genie_codes != previous.genie_codes {
+            for code in &previous.genie_codes {
+                self.control_deck.remove_genie_code(code);
+            }
+            for code in config.genie_codes {
+                if let Err(err) = self.control_deck.add_genie_code(code.clone()) {
+                    log::warn!("{}", err);
+                    self.add_message(format!("Invalid Genie Code: '{code}'"));
+                }
+            }
+        }
+
+        let audio_device = |config: &Config| {
+            (
+                config.audio_backend,
+                config.audio_device.clone(),
+                config.audio_secondary_device.clone(),
+                config.audio_secondary_volume,
+                config.audio_sample_rate,
+                config.device_buffer_size(),
+                config.audio_latency,
+            )
+        };
+        if audio_device(&self.config) != audio_device(previous) {
+            self.open_audio(s)?;
+        }
+        self.update_rpc_server();
+        if !self.config.mixer_per_game && self.config.mixer != previous.mixer {
+            self.mixer = self.config.mixer;
+            self.apply_mixer();
+        }
+        Ok(())
+    }
+}
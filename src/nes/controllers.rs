@@ -0,0 +1,286 @@
+//! Assignment of connected controllers to player slots.
+//!
+//! Controllers take the first free slot when connected. When one disconnects its slot is held,
+//! so plugging a controller back in or waking a wireless one returns it to the same player
+//! instead of shuffling everyone else around. Assignments can be changed from the Keybindings
+//! menu, swapping players if the controller was already in use.
+
+use crate::{input::Slot, nes::Nes};
+use pix_engine::prelude::*;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+const SLOTS: [Slot; 4] = [Slot::One, Slot::Two, Slot::Three, Slot::Four];
+
+/// Haptic feedback for a player's controller, so scripts and achievements can signal events.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[must_use]
+pub(crate) struct Rumble {
+    /// Low frequency motor strength from `0.0` to `1.0`.
+    pub(crate) low_frequency: f32,
+    /// High frequency motor strength from `0.0` to `1.0`.
+    pub(crate) high_frequency: f32,
+    pub(crate) duration: Duration,
+}
+
+impl Rumble {
+    pub(crate) fn new(low_frequency: f32, high_frequency: f32, duration: Duration) -> Self {
+        Self {
+            low_frequency: low_frequency.clamp(0.0, 1.0),
+            high_frequency: high_frequency.clamp(0.0, 1.0),
+            duration,
+        }
+    }
+
+    /// A gap between rumbles in a pattern.
+    pub(crate) fn pause(duration: Duration) -> Self {
+        Self::new(0.0, 0.0, duration)
+    }
+
+    #[must_use]
+    pub(crate) fn is_pause(&self) -> bool {
+        self.low_frequency == 0.0 && self.high_frequency == 0.0
+    }
+}
+
+#[derive(Debug, Clone)]
+#[must_use]
+pub(crate) struct Controllers<Id = ControllerId> {
+    /// Connected controllers in the order they were connected.
+    connected: Vec<Id>,
+    assigned: Vec<(Slot, Id)>,
+    /// Slots whose controller disconnected, most recent last.
+    vacated: Vec<Slot>,
+    /// Rumble patterns still to play for each slot, with when the first step started.
+    rumble: Vec<(Slot, VecDeque<Rumble>, Instant)>,
+}
+
+impl<Id> Default for Controllers<Id> {
+    fn default() -> Self {
+        Self {
+            connected: vec![],
+            assigned: vec![],
+            vacated: vec![],
+            rumble: vec![],
+        }
+    }
+}
+
+impl<Id: Copy + PartialEq> Controllers<Id> {
+    /// Connects a controller, returning the slot it was assigned to, if any were free.
+    pub(crate) fn connect(&mut self, id: Id) -> Option<Slot> {
+        if !self.connected.contains(&id) {
+            self.connected.push(id);
+        }
+        if let Some(slot) = self.slot(id) {
+            return Some(slot);
+        }
+        let slot = self
+            .vacated
+            .iter()
+            .rev()
+            .copied()
+            .find(|&slot| self.controller(slot).is_none())
+            .or_else(|| {
+                SLOTS
+                    .into_iter()
+                    .find(|&slot| self.controller(slot).is_none() && !self.vacated.contains(&slot))
+            })
+            .or_else(|| {
+                SLOTS
+                    .into_iter()
+                    .find(|&slot| self.controller(slot).is_none())
+            })?;
+        self.vacated.retain(|&vacated| vacated != slot);
+        self.assigned.push((slot, id));
+        Some(slot)
+    }
+
+    /// Disconnects a controller, returning the slot it was assigned to, if any.
+    pub(crate) fn disconnect(&mut self, id: Id) -> Option<Slot> {
+        self.connected.retain(|&connected| connected != id);
+        let slot = self.slot(id)?;
+        self.assigned.retain(|&(_, assigned)| assigned != id);
+        self.rumble.retain(|&(rumble_slot, ..)| rumble_slot != slot);
+        self.vacated.retain(|&vacated| vacated != slot);
+        self.vacated.push(slot);
+        Some(slot)
+    }
+
+    /// Assigns a controller to `slot`, or unassigns it with `None`. A controller already assigned
+    /// to another slot swaps with the current one.
+    pub(crate) fn assign(&mut self, slot: Slot, id: Option<Id>) {
+        let current = self.controller(slot);
+        self.assigned.retain(|&(assigned, _)| assigned != slot);
+        if let Some(id) = id {
+            if let Some(other) = self.slot(id) {
+                self.assigned.retain(|&(_, assigned)| assigned != id);
+                if let Some(current) = current {
+                    self.assigned.push((other, current));
+                }
+            }
+            self.assigned.push((slot, id));
+        }
+        self.vacated.retain(|&vacated| vacated != slot);
+    }
+
+    /// The slot `id` is assigned to.
+    pub(crate) fn slot(&self, id: Id) -> Option<Slot> {
+        self.assigned
+            .iter()
+            .find_map(|&(slot, assigned)| (assigned == id).then_some(slot))
+    }
+
+    /// The controller assigned to `slot`.
+    pub(crate) fn controller(&self, slot: Slot) -> Option<Id> {
+        self.assigned
+            .iter()
+            .find_map(|&(assigned, id)| (assigned == slot).then_some(id))
+    }
+
+    pub(crate) fn connected(&self) -> &[Id] {
+        &self.connected
+    }
+
+    /// Plays a rumble pattern on the controller for `slot`, replacing any pattern already
+    /// playing, or queues it after the current one. Returns whether a controller is assigned.
+    pub(crate) fn rumble(
+        &mut self,
+        slot: Slot,
+        pattern: impl IntoIterator<Item = Rumble>,
+        queue: bool,
+    ) -> bool {
+        if self.controller(slot).is_none() {
+            return false;
+        }
+        match self
+            .rumble
+            .iter_mut()
+            .find(|(rumble_slot, ..)| *rumble_slot == slot)
+        {
+            Some((_, steps, _)) if queue => steps.extend(pattern),
+            Some((_, steps, started)) => {
+                *steps = pattern.into_iter().collect();
+                *started = Instant::now();
+            }
+            None => {
+                self.rumble
+                    .push((slot, pattern.into_iter().collect(), Instant::now()));
+            }
+        }
+        true
+    }
+
+    /// Moves on to the next step of each rumble pattern once the current one finishes.
+    pub(crate) fn update_rumble(&mut self, now: Instant) {
+        for (_, steps, started) in &mut self.rumble {
+            while let Some(step) = steps.front() {
+                let end = *started + step.duration;
+                if now < end {
+                    break;
+                }
+                steps.pop_front();
+                *started = end;
+            }
+        }
+        self.rumble.retain(|(_, steps, _)| !steps.is_empty());
+    }
+
+    /// The rumble currently playing on the controller for `slot`, ignoring pauses.
+    pub(crate) fn active_rumble(&self, slot: Slot) -> Option<Rumble> {
+        self.rumble
+            .iter()
+            .find(|(rumble_slot, ..)| *rumble_slot == slot)
+            .and_then(|(_, steps, started)| {
+                steps
+                    .front()
+                    .filter(|step| !step.is_pause() && started.elapsed() < step.duration)
+                    .copied()
+            })
+    }
+}
+
+impl Nes {
+    pub(crate) fn connect_controller(&mut self, controller_id: ControllerId) {
+        match self.controllers.connect(controller_id) {
+            Some(slot) => self.add_message(format!("Controller connected to Player {slot:?}")),
+            None => self.add_message("Controller connected, but every player has one"),
+        }
+    }
+
+    pub(crate) fn disconnect_controller(&mut self, controller_id: ControllerId) {
+        if let Some(slot) = self.controllers.disconnect(controller_id) {
+            self.add_message(format!("Player {slot:?} controller disconnected"));
+        }
+    }
+
+    /// Rumbles the controller for `slot` with a pattern of rumbles and pauses. Used by anything
+    /// that wants to signal the player through haptics. Patterns play in the background, with
+    /// `queue` waiting for the current pattern to finish instead of replacing it.
+    pub(crate) fn rumble(&mut self, slot: Slot, pattern: &[Rumble], queue: bool) {
+        if self
+            .controllers
+            .rumble(slot, pattern.iter().copied(), queue)
+        {
+            log::debug!("rumble {slot:?}: {pattern:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reattach_and_swap() {
+        let mut controllers = Controllers::<u32>::default();
+        assert_eq!(controllers.connect(10), Some(Slot::One));
+        assert_eq!(controllers.connect(11), Some(Slot::Two));
+        assert_eq!(controllers.connect(11), Some(Slot::Two));
+
+        // Reconnecting takes back the vacated slot, even with a new id
+        assert_eq!(controllers.disconnect(10), Some(Slot::One));
+        assert_eq!(controllers.connected(), &[11]);
+        assert_eq!(controllers.connect(12), Some(Slot::One));
+        assert_eq!(controllers.connect(13), Some(Slot::Three));
+
+        controllers.assign(Slot::One, Some(11));
+        assert_eq!(controllers.controller(Slot::One), Some(11));
+        assert_eq!(controllers.controller(Slot::Two), Some(12));
+        controllers.assign(Slot::Three, None);
+        assert_eq!(controllers.slot(13), None);
+        assert_eq!(controllers.connect(14), Some(Slot::Three));
+        assert_eq!(controllers.connect(15), Some(Slot::Four));
+        assert_eq!(controllers.connect(16), None);
+
+        let rumble = Rumble::new(2.0, 0.5, Duration::from_secs(60));
+        assert_eq!(rumble.low_frequency, 1.0);
+        assert!(controllers.rumble(Slot::One, [rumble], false));
+        assert_eq!(controllers.active_rumble(Slot::One), Some(rumble));
+        controllers.disconnect(11);
+        assert_eq!(controllers.active_rumble(Slot::One), None);
+        assert!(!controllers.rumble(Slot::One, [rumble], false));
+    }
+
+    #[test]
+    fn rumble_pattern() {
+        let mut controllers = Controllers::<u32>::default();
+        controllers.connect(10);
+        let long = Duration::from_secs(60);
+        let buzz = Rumble::new(1.0, 0.0, Duration::ZERO);
+        let hum = Rumble::new(0.2, 0.2, long);
+        assert!(controllers.rumble(Slot::One, [buzz, Rumble::pause(Duration::ZERO)], false));
+        assert!(controllers.rumble(Slot::One, [hum], true));
+        controllers.update_rumble(Instant::now());
+        assert_eq!(controllers.active_rumble(Slot::One), Some(hum));
+
+        // Replacing the pattern starts over
+        assert!(controllers.rumble(Slot::One, [Rumble::pause(long), hum], false));
+        controllers.update_rumble(Instant::now());
+        assert_eq!(controllers.active_rumble(Slot::One), None);
+        controllers.update_rumble(Instant::now() + long);
+        assert_eq!(controllers.active_rumble(Slot::One), Some(hum));
+    }
+}
@@ -1,26 +1,640 @@
 use crate::{
-    cpu::Status,
+    common::Kind,
+    cpu::{Cpu, Status},
+    debugger::{
+        console::{Command, Patch, Target, HELP},
+        mem_search::{MemSearch, MemSpace, Pattern},
+        reset_schedule::ScheduledReset,
+        symbols::Symbols,
+        trace_diff::TraceDiff,
+        watch::{Watch, WatchSize},
+        Address, Breakpoint, Breakpoints,
+    },
     mem::{Access, Mem},
-    nes::Nes,
+    nes::{event::NesState, tutorial::TutorialEvent, Nes},
 };
 use pix_engine::prelude::*;
+use std::{fs, path::PathBuf};
 
 #[derive(Debug)]
 pub(crate) struct Debugger {
     window_id: WindowId,
+    pub(crate) breakpoints: Breakpoints,
+    /// Address of the breakpoint execution last stopped at, until it runs again.
+    pub(crate) break_addr: Option<u16>,
+    bp_addr: String,
+    bp_access: usize,
+    bp_break_after: String,
+    bp_temporary: bool,
+    ignore_addr: String,
+    pub(crate) trace_diff: Option<TraceDiff>,
+    trace_diff_path: String,
+    trace_diff_error: Option<String>,
+    search_space: usize,
+    search_pattern: String,
+    search: Option<MemSearch>,
+    search_error: Option<String>,
+    watches: Vec<Watch>,
+    watch_expr: String,
+    watch_size: usize,
+    watch_signed: bool,
+    watch_error: Option<String>,
+    console_input: String,
+    console_output: Vec<String>,
+    symbol_path: String,
+    symbol_error: Option<String>,
+    pub(crate) scheduled_reset: Option<ScheduledReset>,
+    reset_kind: usize,
+    reset_frame: String,
+    reset_scanline: String,
+    reset_error: Option<String>,
 }
 
 impl Debugger {
-    const fn new(window_id: WindowId) -> Self {
-        Self { window_id }
+    fn new(window_id: WindowId) -> Self {
+        Self {
+            window_id,
+            breakpoints: Breakpoints::default(),
+            break_addr: None,
+            bp_addr: String::new(),
+            bp_access: 0,
+            bp_break_after: String::new(),
+            bp_temporary: false,
+            ignore_addr: String::new(),
+            trace_diff: None,
+            trace_diff_path: String::new(),
+            trace_diff_error: None,
+            search_space: 0,
+            search_pattern: String::new(),
+            search: None,
+            search_error: None,
+            watches: vec![],
+            watch_expr: String::new(),
+            watch_size: 0,
+            watch_signed: false,
+            watch_error: None,
+            console_input: String::new(),
+            console_output: vec![],
+            symbol_path: String::new(),
+            symbol_error: None,
+            scheduled_reset: None,
+            reset_kind: 0,
+            reset_frame: String::new(),
+            reset_scanline: String::new(),
+            reset_error: None,
+        }
     }
 
     pub(crate) const fn window_id(&self) -> WindowId {
         self.window_id
     }
+
+    /// Whether execution needs to be checked after every instruction.
+    #[inline]
+    #[must_use]
+    pub(crate) fn is_active(&self) -> bool {
+        self.breakpoints.is_active() || self.trace_diff.is_some() || self.scheduled_reset.is_some()
+    }
+
+    fn render_trace_diff(
+        &mut self,
+        s: &mut PixState,
+        cpu: &mut Cpu,
+        symbols: &Symbols,
+    ) -> PixResult<()> {
+        s.text("Trace Diff:")?;
+        match self.trace_diff {
+            Some(ref diff) => {
+                s.text(&format!(
+                    "{}: {}/{} lines matched",
+                    diff.path().display(),
+                    diff.matched(),
+                    diff.len()
+                ))?;
+                if let Some(divergence) = diff.divergence() {
+                    s.push();
+                    s.fill(Color::RED);
+                    s.text(&format!("Diverged at line {}", divergence.line))?;
+                    s.pop();
+                    let annotate =
+                        |text: &str| symbols.annotate(text, |addr| cpu.prg_rom_offset(addr));
+                    s.text(&format!(
+                        "Expected: {}",
+                        annotate(&divergence.expected.text)
+                    ))?;
+                    s.text(&format!("Actual:   {}", annotate(&divergence.actual.text)))?;
+                    s.text(&format!("{:<6}{:<12}{:<12}", "", "Expected", "Actual"))?;
+                    for mismatch in &divergence.mismatches {
+                        s.text(&format!(
+                            "{:<6}{:<12}{:<12}",
+                            mismatch.field, mismatch.expected, mismatch.actual
+                        ))?;
+                    }
+                } else if diff.finished() {
+                    s.text("Reached the end of the reference log")?;
+                }
+                if s.button("Stop Trace Diff")? {
+                    self.trace_diff = None;
+                }
+            }
+            None => {
+                s.text_field("Reference Log", &mut self.trace_diff_path)?;
+                s.same_line(None);
+                if s.button("Start Trace Diff")? {
+                    match TraceDiff::load(self.trace_diff_path.trim()) {
+                        Ok(mut diff) => {
+                            // The first line is the state before the next instruction runs
+                            diff.check(cpu);
+                            self.trace_diff = Some(diff);
+                            self.trace_diff_error = None;
+                        }
+                        Err(err) => {
+                            log::error!("{err:?}");
+                            self.trace_diff_error = Some(err.to_string());
+                        }
+                    }
+                }
+                if let Some(ref err) = self.trace_diff_error {
+                    s.push();
+                    s.fill(Color::RED);
+                    s.text(err)?;
+                    s.pop();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn render_mem_search(&mut self, s: &mut PixState, cpu: &Cpu) -> PixResult<()> {
+        const MAX_RESULTS: usize = 64;
+
+        s.text("Memory Search:")?;
+        let spaces = MemSpace::as_slice();
+        s.select_box(
+            "Address Space",
+            &mut self.search_space,
+            spaces,
+            spaces.len(),
+        )?;
+        s.text_field("Pattern (hex, ?? for any)", &mut self.search_pattern)?;
+        let space = MemSpace::from(self.search_space);
+        let search = s.button("Search")?;
+        let refine = match self.search {
+            Some(ref search) if search.space == space => {
+                s.same_line(None);
+                s.button("Refine")?
+            }
+            _ => false,
+        };
+        if search || refine {
+            match self.search_pattern.parse::<Pattern>() {
+                Ok(pattern) => {
+                    let data = space.snapshot(cpu);
+                    match self.search {
+                        Some(ref mut search) if refine => search.refine(pattern, &data),
+                        _ => self.search = Some(MemSearch::search(space, pattern, &data)),
+                    }
+                    self.search_error = None;
+                }
+                Err(err) => self.search_error = Some(err),
+            }
+        }
+        if let Some(ref err) = self.search_error {
+            s.push();
+            s.fill(Color::RED);
+            s.text(err)?;
+            s.pop();
+        }
+
+        if let Some(ref search) = self.search {
+            let data = search.space.snapshot(cpu);
+            s.text(&format!(
+                "{} matches for {} in {}",
+                search.results.len(),
+                search.pattern,
+                search.space.as_ref()
+            ))?;
+            for &offset in search.results.iter().take(MAX_RESULTS) {
+                s.text(&format!(
+                    "${:04X} = ${:02X}  {}",
+                    search.space.addr(offset),
+                    data.get(offset).copied().unwrap_or_default(),
+                    search.space.annotate(offset)
+                ))?;
+            }
+            if search.results.len() > MAX_RESULTS {
+                s.text(&format!(
+                    "...and {} more",
+                    search.results.len() - MAX_RESULTS
+                ))?;
+            }
+            if s.button("Clear Search")? {
+                self.search = None;
+            }
+        }
+        Ok(())
+    }
+
+    fn render_watches(&mut self, s: &mut PixState, cpu: &Cpu) -> PixResult<()> {
+        s.text("Watches:")?;
+        let mut remove = None;
+        for (i, watch) in self.watches.iter().enumerate() {
+            let value = watch.read(
+                |addr| cpu.peek(addr, Access::Dummy),
+                |register| register.value(cpu),
+            );
+            let sign = if watch.signed { "signed" } else { "unsigned" };
+            s.text(&format!(
+                "{watch}  {value}  ({} {sign})",
+                watch.size.as_ref()
+            ))?;
+            s.same_line(None);
+            if s.button(format!("Remove##watch{i}"))? {
+                remove = Some(i);
+            }
+        }
+        if let Some(i) = remove {
+            self.watches.remove(i);
+        }
+
+        s.text_field("Expression, e.g. [[$42]+3]", &mut self.watch_expr)?;
+        let sizes = WatchSize::as_slice();
+        s.select_box("Size", &mut self.watch_size, sizes, sizes.len())?;
+        s.checkbox("Signed", &mut self.watch_signed)?;
+        if s.button("Add Watch")? {
+            match Watch::new(
+                &self.watch_expr,
+                WatchSize::from(self.watch_size),
+                self.watch_signed,
+            ) {
+                Ok(watch) => {
+                    self.watches.push(watch);
+                    self.watch_expr.clear();
+                    self.watch_error = None;
+                }
+                Err(err) => self.watch_error = Some(err),
+            }
+        }
+        if let Some(ref err) = self.watch_error {
+            s.push();
+            s.fill(Color::RED);
+            s.text(err)?;
+            s.pop();
+        }
+        Ok(())
+    }
+
+    /// Renders the console, returning a command to run once entered.
+    fn render_console(&mut self, s: &mut PixState) -> PixResult<Option<Command>> {
+        const MAX_LINES: usize = 16;
+
+        s.text("Console:")?;
+        let skip = self.console_output.len().saturating_sub(MAX_LINES);
+        for line in self.console_output.iter().skip(skip) {
+            s.text(line)?;
+        }
+        s.text_field("Command, e.g. a $C000 LDA #$01", &mut self.console_input)?;
+        s.same_line(None);
+        let mut command = None;
+        if s.button("Run")? && !self.console_input.trim().is_empty() {
+            let input = std::mem::take(&mut self.console_input);
+            self.console_output.push(format!("> {}", input.trim()));
+            match input.parse::<Command>() {
+                Ok(Command::Clear) => self.console_output.clear(),
+                Ok(cmd) => command = Some(cmd),
+                Err(err) => self.console_output.push(err),
+            }
+        }
+        Ok(command)
+    }
+
+    fn render_symbols(&mut self, s: &mut PixState, symbols: &mut Symbols) -> PixResult<()> {
+        s.text(&format!("Symbols: {} loaded", symbols.len()))?;
+        s.text_field("Symbol File (.nl or .dbg)", &mut self.symbol_path)?;
+        s.same_line(None);
+        if s.button("Load Symbols")? {
+            match symbols.load_file(PathBuf::from(self.symbol_path.trim())) {
+                Ok(()) => {
+                    self.symbol_path.clear();
+                    self.symbol_error = None;
+                }
+                Err(err) => {
+                    log::error!("{err:?}");
+                    self.symbol_error = Some(err.to_string());
+                }
+            }
+        }
+        if let Some(ref err) = self.symbol_error {
+            s.push();
+            s.fill(Color::RED);
+            s.text(err)?;
+            s.pop();
+        }
+        Ok(())
+    }
+
+    fn render_reset_schedule(&mut self, s: &mut PixState, current_frame: u32) -> PixResult<()> {
+        s.text("Scheduled Reset:")?;
+        if let Some(reset) = self.scheduled_reset {
+            s.text(reset.to_string())?;
+            s.same_line(None);
+            if s.button("Cancel##scheduled_reset")? {
+                self.scheduled_reset = None;
+            }
+        }
+        s.select_box("Kind", &mut self.reset_kind, &["Reset", "Power Cycle"], 2)?;
+        s.text_field("Frame (+N from now)", &mut self.reset_frame)?;
+        s.text_field("Scanline (optional)", &mut self.reset_scanline)?;
+        if s.button("Schedule")? {
+            let kind = if self.reset_kind == 1 {
+                Kind::Hard
+            } else {
+                Kind::Soft
+            };
+            match ScheduledReset::parse(
+                kind,
+                &self.reset_frame,
+                &self.reset_scanline,
+                current_frame,
+            ) {
+                Ok(reset) => {
+                    self.scheduled_reset = Some(reset);
+                    self.reset_error = None;
+                }
+                Err(err) => self.reset_error = Some(err),
+            }
+        }
+        if let Some(ref err) = self.reset_error {
+            s.push();
+            s.fill(Color::RED);
+            s.text(err)?;
+            s.pop();
+        }
+        Ok(())
+    }
+
+    fn render_breakpoints(
+        &mut self,
+        s: &mut PixState,
+        cpu: &Cpu,
+        symbols: &Symbols,
+    ) -> PixResult<()> {
+        s.text("Breakpoints:")?;
+
+        let mut remove = None;
+        for (i, bp) in self.breakpoints.list.iter_mut().enumerate() {
+            s.checkbox(format!("##bp_enabled{i}"), &mut bp.enabled)?;
+            s.same_line(None);
+            let access = if bp.access.contains(&Access::Write) {
+                "W"
+            } else {
+                "X"
+            };
+            let mut text = format!("{access} {}", bp.addr);
+            if let Address::Addr(addr) = bp.addr {
+                let symbol = symbols.get(addr, cpu.prg_rom_offset(addr));
+                if let Some(symbol) = symbol.filter(|symbol| !symbol.name.is_empty()) {
+                    text.push_str(&format!(" {}", symbol.name));
+                }
+            }
+            text.push_str(&format!("  Hits: {}", bp.hit_count));
+            if bp.break_after > 1 {
+                text.push_str(&format!("/{}", bp.break_after));
+            }
+            if bp.temporary {
+                text.push_str("  (once)");
+            }
+            s.text(text)?;
+            s.same_line(None);
+            if s.button(format!("Remove##bp{i}"))? {
+                remove = Some(i);
+            }
+        }
+        if let Some(i) = remove {
+            self.breakpoints.remove(i);
+        }
+
+        s.text_field("Address (hex) or Label", &mut self.bp_addr)?;
+        s.select_box("Type", &mut self.bp_access, &["Execute", "Write"], 2)?;
+        s.text_field("Break After Hits", &mut self.bp_break_after)?;
+        s.checkbox("One-shot", &mut self.bp_temporary)?;
+        if s.button("Add Breakpoint")? {
+            let addr = symbols
+                .find(self.bp_addr.trim())
+                .map(Address::Addr)
+                .or_else(|| self.bp_addr.parse::<Address>().ok());
+            if let Some(addr) = addr {
+                let access = if self.bp_access == 1 {
+                    Access::Write
+                } else {
+                    Access::Execute
+                };
+                let mut bp = Breakpoint::new(addr, access);
+                bp.break_after = self.bp_break_after.trim().parse().unwrap_or(0);
+                bp.temporary = self.bp_temporary;
+                self.breakpoints.add(bp);
+                self.bp_addr.clear();
+            }
+        }
+        s.same_line(None);
+        if s.button("Reset Hits")? {
+            self.breakpoints.reset_hits();
+        }
+
+        s.spacing()?;
+        s.text("Ignored Writes:")?;
+        let mut remove = None;
+        for (i, addr) in self.breakpoints.ignore.iter().enumerate() {
+            s.text(addr.to_string())?;
+            s.same_line(None);
+            if s.button(format!("Remove##ignore{i}"))? {
+                remove = Some(i);
+            }
+        }
+        if let Some(i) = remove {
+            self.breakpoints.ignore.remove(i);
+        }
+        s.text_field("Ignore Address (hex)", &mut self.ignore_addr)?;
+        s.same_line(None);
+        if s.button("Ignore")? {
+            if let Ok(addr) = self.ignore_addr.parse::<Address>() {
+                self.breakpoints.ignore.push(addr);
+                self.ignore_addr.clear();
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Nes {
+    /// Writes a patch, recording where each byte went so it can be removed or written again.
+    /// Bytes at addresses mapped to PRG-ROM are patched into the ROM.
+    fn write_patch(&mut self, patch: &mut Patch) {
+        patch.targets = patch
+            .writes()
+            .map(|(addr, val)| match self.control_deck.prg_rom_offset(addr) {
+                Some(offset) => {
+                    let original =
+                        std::mem::replace(&mut self.control_deck.prg_rom_mut()[offset], val);
+                    Target::PrgRom { offset, original }
+                }
+                None => {
+                    self.control_deck.cpu_mut().poke(addr, val);
+                    Target::Bus(addr)
+                }
+            })
+            .collect();
+    }
+
+    /// Loads symbol files found next to the ROM.
+    pub(crate) fn load_symbols(&mut self) {
+        match Symbols::load(Symbols::find_files(&self.config.rom_path)) {
+            Ok(symbols) => {
+                if !symbols.is_empty() {
+                    self.add_message(format!("Loaded {} symbols", symbols.len()));
+                }
+                self.symbols = symbols;
+            }
+            Err(err) => {
+                log::error!("{err:?}");
+                self.symbols = Symbols::default();
+                self.add_message("Failed to load symbols");
+            }
+        }
+    }
+
+    /// Reloads symbols when their files are rebuilt.
+    pub(crate) fn check_symbol_reload(&mut self) {
+        if !self.symbols.changed() {
+            return;
+        }
+        match self.symbols.reload() {
+            Ok(symbols) => {
+                self.symbols = symbols;
+                self.add_message("Reloaded symbols");
+            }
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message("Failed to reload symbols");
+            }
+        }
+    }
+
+    /// Resets or power cycles once execution reaches a scheduled point, pausing afterwards like
+    /// any reset with the debugger open.
+    pub(crate) fn run_scheduled_reset(
+        &mut self,
+        s: &mut PixState,
+        reset: ScheduledReset,
+    ) -> PixResult<()> {
+        if let Some(ref mut debugger) = self.debugger {
+            debugger.scheduled_reset = None;
+        }
+        let ppu = self.control_deck.ppu();
+        if reset.is_missed(ppu.frame_number()) {
+            self.pause_play();
+            self.add_message(format!("Missed scheduled reset: {reset}"));
+            return Ok(());
+        }
+        log::info!("{reset} (cycle {})", ppu.cycle());
+        let state = match reset.kind {
+            Kind::Soft => NesState::SoftReset,
+            Kind::Hard => NesState::HardReset,
+        };
+        if !self.handle_nes_state(s, state)? {
+            self.add_message("Can't reset while recording a replay");
+        }
+        Ok(())
+    }
+
+    /// Writes patches again after a reset.
+    pub(crate) fn reapply_patches(&mut self) {
+        for patch in &self.patches.list {
+            for ((addr, val), target) in patch.writes().zip(&patch.targets) {
+                match *target {
+                    Target::PrgRom { offset, .. } => self.control_deck.prg_rom_mut()[offset] = val,
+                    Target::Bus(_) => self.control_deck.cpu_mut().poke(addr, val),
+                }
+            }
+        }
+    }
+
+    fn run_console_command(&mut self, command: Command) -> Result<Vec<String>, String> {
+        match command {
+            Command::Write { mut patch, keep } => {
+                self.write_patch(&mut patch);
+                let bytes = patch
+                    .bytes
+                    .iter()
+                    .map(|byte| format!("{byte:02X}"))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let mut output = vec![format!("${:04X}: {bytes}", patch.addr)];
+                if keep {
+                    self.patches.list.push(patch);
+                    output.push(format!("Added patch {}", self.patches.list.len()));
+                }
+                Ok(output)
+            }
+            Command::Eval(expr) => {
+                let cpu = self.control_deck.cpu();
+                let value = expr.eval(
+                    |addr| cpu.peek(addr, Access::Dummy),
+                    |register| register.value(cpu),
+                );
+                Ok(vec![format!("${value:04X} = {value}")])
+            }
+            Command::Patches if self.patches.list.is_empty() => Ok(vec!["No patches".to_string()]),
+            Command::Patches => Ok(self
+                .patches
+                .list
+                .iter()
+                .enumerate()
+                .map(|(i, patch)| format!("{}: {patch}", i + 1))
+                .collect()),
+            Command::Unpatch(i) => {
+                if i >= self.patches.list.len() {
+                    return Err(format!("no patch {}", i + 1));
+                }
+                let patch = self.patches.list.remove(i);
+                for target in patch.targets.iter().rev() {
+                    if let Target::PrgRom { offset, original } = *target {
+                        self.control_deck.prg_rom_mut()[offset] = original;
+                    }
+                }
+                // Restore bytes from any other patches that overlapped it
+                self.reapply_patches();
+                Ok(vec![format!("Removed patch {patch}")])
+            }
+            Command::Run(path) => {
+                let script = fs::read_to_string(&path)
+                    .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+                let mut output = vec![];
+                for line in script.lines().map(str::trim) {
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    match line.parse::<Command>()? {
+                        Command::Run(_) => return Err("scripts can't run other scripts".into()),
+                        Command::Clear => (),
+                        command => output.extend(self.run_console_command(command)?),
+                    }
+                }
+                Ok(output)
+            }
+            Command::ExportIps(path) => {
+                if self.patches.prg_rom().is_empty() {
+                    return Err("no PRG-ROM patches to export".to_string());
+                }
+                fs::write(&path, self.patches.to_ips())
+                    .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+                Ok(vec![format!("Exported {}", path.display())])
+            }
+            Command::Clear => Ok(vec![]),
+            Command::Help => Ok(HELP.iter().map(ToString::to_string).collect()),
+        }
+    }
+
     pub(crate) fn toggle_debugger(&mut self, s: &mut PixState) -> PixResult<()> {
         match self.debugger {
             None => {
@@ -34,17 +648,20 @@ impl Nes {
                     .build()?;
                 self.debugger = Some(Debugger::new(window_id));
                 self.pause_play();
+                self.tutorial_event(TutorialEvent::DebuggerOpened);
             }
             Some(ref debugger) => {
                 s.close_window(debugger.window_id())?;
                 self.debugger = None;
+                self.control_deck.cpu_mut().set_watch_writes(false);
             }
         }
         Ok(())
     }
 
     pub(crate) fn render_debugger(&mut self, s: &mut PixState) -> PixResult<()> {
-        if let Some(ref debugger) = self.debugger {
+        let mut command = None;
+        if let Some(ref mut debugger) = self.debugger {
             s.set_window_target(debugger.window_id())?;
             s.clear()?;
             s.fill(Color::WHITE);
@@ -149,13 +766,54 @@ impl Nes {
                 s.spacing()?;
                 let mut pc = cpu.pc();
                 for _ in 0..10 {
+                    let addr = pc;
                     cpu.disassemble(&mut pc);
-                    s.text(cpu.disasm())?;
+                    let symbol = self.symbols.get(addr, cpu.prg_rom_offset(addr));
+                    if let Some(symbol) = symbol.filter(|symbol| !symbol.name.is_empty()) {
+                        s.text(&format!("{}:", symbol.name))?;
+                    }
+                    let mut line = self
+                        .symbols
+                        .annotate(cpu.disasm(), |addr| cpu.prg_rom_offset(addr));
+                    if let Some(ref comment) = symbol.and_then(|symbol| symbol.comment.as_ref()) {
+                        line.push_str(&format!("  ; {comment}"));
+                    }
+                    s.text(line)?;
                 }
             }
 
+            s.spacing()?;
+            debugger.render_symbols(s, &mut self.symbols)?;
+
+            s.spacing()?;
+            debugger.render_reset_schedule(s, self.control_deck.frame_number())?;
+
+            s.spacing()?;
+            debugger.render_breakpoints(s, self.control_deck.cpu(), &self.symbols)?;
+
+            s.spacing()?;
+            debugger.render_watches(s, self.control_deck.cpu())?;
+
+            s.spacing()?;
+            command = debugger.render_console(s)?;
+
+            s.spacing()?;
+            debugger.render_mem_search(s, self.control_deck.cpu())?;
+
+            s.spacing()?;
+            debugger.render_trace_diff(s, self.control_deck.cpu_mut(), &self.symbols)?;
+
             s.reset_window_target();
         }
+
+        if let Some(command) = command {
+            let output = self
+                .run_console_command(command)
+                .unwrap_or_else(|err| vec![err]);
+            if let Some(ref mut debugger) = self.debugger {
+                debugger.console_output.extend(output);
+            }
+        }
         Ok(())
     }
 }
@@ -0,0 +1,231 @@
+//! Developer overlay comparing the live frame against a reference image, such as a capture from
+//! real hardware or a screenshot from another emulator, for tuning PPU accuracy.
+//!
+//! Pixels within the tolerance are shown dimmed in grayscale so the scene stays recognizable,
+//! while pixels that differ are colored from yellow for small differences to red for large ones.
+
+use crate::{
+    nes::{Nes, NES_FRAME_SRC},
+    ppu::Ppu,
+};
+use pix_engine::prelude::*;
+use std::path::PathBuf;
+
+const CHANNELS: usize = 4;
+
+/// How much the live frame differs from the reference.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct DiffStats {
+    /// Pixels with a channel differing by more than the tolerance.
+    pub(crate) pixels: usize,
+    /// Largest difference of any channel.
+    pub(crate) max: u8,
+}
+
+#[derive(Debug, Clone)]
+#[must_use]
+pub(crate) struct DiffOverlay {
+    pub(crate) enabled: bool,
+    pub(crate) path: String,
+    /// Channel difference allowed before a pixel is marked, to ignore capture noise.
+    pub(crate) tolerance: u8,
+    /// Reference pixels in RGBA, scaled to the size of a frame.
+    reference: Option<Vec<u8>>,
+    heatmap: Vec<u8>,
+    texture_id: Option<TextureId>,
+    stats: DiffStats,
+}
+
+impl Default for DiffOverlay {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: String::new(),
+            tolerance: 8,
+            reference: None,
+            heatmap: vec![],
+            texture_id: None,
+            stats: DiffStats::default(),
+        }
+    }
+}
+
+impl DiffOverlay {
+    #[inline]
+    #[must_use]
+    pub(crate) const fn loaded(&self) -> bool {
+        self.reference.is_some()
+    }
+
+    #[inline]
+    pub(crate) const fn stats(&self) -> DiffStats {
+        self.stats
+    }
+}
+
+/// Scales an image with `channels` bytes per pixel to the size of a frame in RGBA, using the
+/// nearest pixel. Captures are often saved at an integer scale of the frame, which this undoes.
+fn scale_reference(width: u32, height: u32, channels: usize, pixels: &[u8]) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let (frame_width, frame_height) = (Ppu::WIDTH as usize, Ppu::HEIGHT as usize);
+    let mut scaled = Vec::with_capacity(frame_width * frame_height * CHANNELS);
+    for y in 0..frame_height {
+        let src_y = y * height / frame_height;
+        for x in 0..frame_width {
+            let src_x = x * width / frame_width;
+            let offset = (src_y * width + src_x) * channels;
+            let pixel = pixels.get(offset..offset + 3).unwrap_or(&[0, 0, 0]);
+            scaled.extend_from_slice(pixel);
+            scaled.push(255);
+        }
+    }
+    scaled
+}
+
+/// Writes a heatmap of the differences between two RGBA frames into `heatmap`.
+fn diff(reference: &[u8], frame: &[u8], tolerance: u8, heatmap: &mut Vec<u8>) -> DiffStats {
+    heatmap.clear();
+    let mut stats = DiffStats::default();
+    for (expected, actual) in reference
+        .chunks_exact(CHANNELS)
+        .zip(frame.chunks_exact(CHANNELS))
+    {
+        let difference = expected[..3]
+            .iter()
+            .zip(&actual[..3])
+            .map(|(expected, actual)| expected.abs_diff(*actual))
+            .max()
+            .unwrap_or(0);
+        stats.max = stats.max.max(difference);
+        if difference > tolerance {
+            stats.pixels += 1;
+            heatmap.extend_from_slice(&[255, 255 - difference, 0, 255]);
+        } else {
+            let luma =
+                (u32::from(actual[0]) * 3 + u32::from(actual[1]) * 6 + u32::from(actual[2])) / 10;
+            let dimmed = (luma / 3) as u8;
+            heatmap.extend_from_slice(&[dimmed, dimmed, dimmed, 255]);
+        }
+    }
+    stats
+}
+
+impl Nes {
+    pub(crate) fn toggle_diff_overlay(&mut self) {
+        if !self.diff_overlay.loaded() {
+            self.add_message("Load a reference image in the Video config menu first");
+            return;
+        }
+        self.diff_overlay.enabled = !self.diff_overlay.enabled;
+        self.add_message(if self.diff_overlay.enabled {
+            "Diff Overlay Enabled"
+        } else {
+            "Diff Overlay Disabled"
+        });
+    }
+
+    /// Loads the reference image to compare against and enables the overlay.
+    pub(crate) fn load_diff_reference(&mut self) {
+        let path = PathBuf::from(self.diff_overlay.path.trim());
+        match Image::from_file(&path) {
+            Ok(image) => {
+                self.diff_overlay.reference = Some(scale_reference(
+                    image.width(),
+                    image.height(),
+                    image.format().channels(),
+                    image.as_bytes(),
+                ));
+                self.diff_overlay.enabled = true;
+                self.add_message(format!("Loaded reference image {path:?}"));
+            }
+            Err(err) => {
+                log::error!("failed to load reference image {path:?}: {err:?}");
+                self.add_message(format!("Failed to load reference image {path:?}"));
+            }
+        }
+    }
+
+    pub(crate) fn clear_diff_reference(&mut self) {
+        self.diff_overlay.reference = None;
+        self.diff_overlay.enabled = false;
+        self.diff_overlay.stats = DiffStats::default();
+    }
+
+    /// Draws the difference heatmap over the frame, with a count of the differing pixels.
+    pub(crate) fn render_diff_overlay(&mut self, s: &mut PixState) -> PixResult<()> {
+        let overlay = &mut self.diff_overlay;
+        let Some(reference) = overlay.reference.as_ref().filter(|_| overlay.enabled) else {
+            return Ok(());
+        };
+        let texture_id = match overlay.texture_id {
+            Some(texture_id) => texture_id,
+            None => {
+                let texture_id = s.create_texture(Ppu::WIDTH, Ppu::HEIGHT, PixelFormat::Rgba)?;
+                overlay.texture_id = Some(texture_id);
+                texture_id
+            }
+        };
+        let frame = self.control_deck.frame();
+        overlay.stats = diff(
+            reference,
+            frame.pixels(),
+            overlay.tolerance,
+            &mut overlay.heatmap,
+        );
+        s.update_texture(
+            texture_id,
+            None,
+            &overlay.heatmap,
+            Ppu::WIDTH as usize * CHANNELS,
+        )?;
+        s.texture(texture_id, NES_FRAME_SRC, None)?;
+
+        let stats = overlay.stats;
+        let total = (Ppu::WIDTH * Ppu::HEIGHT) as f32;
+        s.push();
+        s.stroke(None);
+        s.fill(if stats.pixels == 0 {
+            Color::LIME
+        } else {
+            Color::RED
+        });
+        s.set_cursor_pos([4, 4]);
+        s.text(&format!(
+            "Diff: {} pixels ({:.2}%), max {}",
+            stats.pixels,
+            100.0 * stats.pixels as f32 / total,
+            stats.max
+        ))?;
+        s.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_heatmap() {
+        let size = (Ppu::WIDTH * Ppu::HEIGHT) as usize;
+        // A reference captured at 2x without alpha
+        let reference =
+            scale_reference(Ppu::WIDTH * 2, Ppu::HEIGHT * 2, 3, &vec![100; size * 4 * 3]);
+        assert_eq!(reference.len(), size * CHANNELS);
+        assert_eq!(&reference[..4], &[100, 100, 100, 255]);
+
+        let mut frame = reference.clone();
+        frame[0] = 104;
+        frame[4..7].copy_from_slice(&[100, 150, 100]);
+        let mut heatmap = vec![];
+        let stats = diff(&reference, &frame, 8, &mut heatmap);
+        assert_eq!(stats, DiffStats { pixels: 1, max: 50 });
+        assert_eq!(heatmap.len(), reference.len());
+        assert_eq!(&heatmap[4..8], &[255, 205, 0, 255]);
+        assert_eq!(heatmap[0], heatmap[1], "matching pixels are grayscale");
+
+        let stats = diff(&reference, &frame, 50, &mut heatmap);
+        assert_eq!(stats.pixels, 0);
+    }
+}
@@ -0,0 +1,319 @@
+//! DPCM converter window that encodes a WAV file as a DMC sample and previews it through an
+//! emulated DMC channel.
+//!
+//! Previewing pauses the game so the sample is heard on its own, and stops when the game resumes.
+
+use crate::{
+    apu::dmc::Dmc,
+    audio::dpcm::{Dpcm, DpcmPlayer, Wav},
+    nes::{Mode, Nes},
+    NesResult,
+};
+use anyhow::Context;
+use pix_engine::prelude::*;
+use std::{fs, path::PathBuf, time::Instant};
+
+const WAVEFORM_WIDTH: i32 = 560;
+const WAVEFORM_HEIGHT: i32 = 128;
+/// Most emulated time previewed per update, so a slow update doesn't overflow the audio buffer.
+const MAX_PREVIEW_SECONDS: f32 = 0.1;
+
+#[derive(Debug)]
+pub(crate) struct DpcmTool {
+    window_id: WindowId,
+    wav_path: String,
+    rate: usize,
+    normalize: bool,
+    wav: Option<Wav>,
+    dpcm: Option<Dpcm>,
+    player: Option<DpcmPlayer>,
+    last_update: Instant,
+}
+
+impl DpcmTool {
+    fn new(window_id: WindowId) -> Self {
+        Self {
+            window_id,
+            wav_path: String::new(),
+            rate: 0x0F,
+            normalize: true,
+            wav: None,
+            dpcm: None,
+            player: None,
+            last_update: Instant::now(),
+        }
+    }
+
+    pub(crate) const fn window_id(&self) -> WindowId {
+        self.window_id
+    }
+}
+
+impl Nes {
+    pub(crate) fn toggle_dpcm_tool(&mut self, s: &mut PixState) -> PixResult<()> {
+        match self.dpcm_tool {
+            None => {
+                let window_id = s
+                    .window()
+                    .dimensions(600, 440)
+                    .title("DPCM Converter")
+                    .position(10, 10)
+                    .build()?;
+                self.dpcm_tool = Some(DpcmTool::new(window_id));
+            }
+            Some(ref tool) => {
+                s.close_window(tool.window_id())?;
+                self.close_dpcm_tool();
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn close_dpcm_tool(&mut self) {
+        self.stop_dpcm_preview();
+        self.dpcm_tool = None;
+    }
+
+    fn load_dpcm_wav(&mut self) -> NesResult<()> {
+        let Some(ref mut tool) = self.dpcm_tool else {
+            return Ok(());
+        };
+        let path = tool.wav_path.clone();
+        let data = fs::read(&path).with_context(|| format!("failed to read {path:?}"))?;
+        let wav = Wav::decode(&data).with_context(|| format!("failed to decode {path:?}"))?;
+        tool.wav = Some(wav);
+        self.encode_dpcm();
+        self.add_message(format!("Converted {path}"));
+        Ok(())
+    }
+
+    fn encode_dpcm(&mut self) {
+        self.stop_dpcm_preview();
+        let region = self.config.region;
+        if let Some(ref mut tool) = self.dpcm_tool {
+            tool.dpcm = tool
+                .wav
+                .as_ref()
+                .map(|wav| Dpcm::encode(wav, tool.rate as u8, region, tool.normalize));
+        }
+    }
+
+    fn save_dmc(&mut self) -> NesResult<()> {
+        let Some(DpcmTool {
+            dpcm: Some(ref dpcm),
+            ref wav_path,
+            ..
+        }) = self.dpcm_tool
+        else {
+            return Ok(());
+        };
+        let path = PathBuf::from(wav_path).with_extension("dmc");
+        fs::write(&path, &dpcm.data).with_context(|| format!("failed to write {path:?}"))?;
+        self.add_message(format!("Saved {}", path.display()));
+        Ok(())
+    }
+
+    fn play_dpcm_preview(&mut self) {
+        if !self.audio_output_enabled() || !self.audio.is_open() {
+            self.add_message("Enable sound to preview DPCM samples");
+            return;
+        }
+        let Some(ref mut tool) = self.dpcm_tool else {
+            return;
+        };
+        let Some(ref dpcm) = tool.dpcm else {
+            return;
+        };
+        tool.player = Some(DpcmPlayer::new(dpcm));
+        tool.last_update = Instant::now();
+        self.pause_play();
+        self.audio.resume();
+    }
+
+    fn stop_dpcm_preview(&mut self) {
+        let stopped = self
+            .dpcm_tool
+            .as_mut()
+            .map_or(false, |tool| tool.player.take().is_some());
+        if stopped && self.mode != Mode::Playing {
+            self.audio.pause();
+        }
+    }
+
+    /// Feeds the next part of a playing preview to the audio output.
+    pub(crate) fn update_dpcm_preview(&mut self) {
+        if self.mode == Mode::Playing && self.control_deck.is_running() {
+            self.stop_dpcm_preview();
+            return;
+        }
+        let clock_rate = self.control_deck.sample_rate();
+        let Some(DpcmTool {
+            player: Some(ref mut player),
+            ref mut last_update,
+            ..
+        }) = self.dpcm_tool
+        else {
+            return;
+        };
+        let now = Instant::now();
+        let elapsed = now
+            .duration_since(*last_update)
+            .as_secs_f32()
+            .min(MAX_PREVIEW_SECONDS);
+        *last_update = now;
+        let mut samples = Vec::with_capacity((elapsed * clock_rate) as usize);
+        player.clock((elapsed * clock_rate) as usize, &mut samples);
+        let finished = player.finished();
+        self.audio.consume(&samples, false, 0.0);
+        if finished {
+            self.stop_dpcm_preview();
+        }
+    }
+
+    pub(crate) fn render_dpcm_tool(&mut self, s: &mut PixState) -> PixResult<()> {
+        let Some(ref tool) = self.dpcm_tool else {
+            return Ok(());
+        };
+        s.set_window_target(tool.window_id())?;
+        s.clear()?;
+        s.fill(Color::WHITE);
+        s.stroke(None);
+        let result = self.render_dpcm_controls(s);
+        s.reset_window_target();
+        result
+    }
+
+    fn render_dpcm_controls(&mut self, s: &mut PixState) -> PixResult<()> {
+        let region = self.config.region;
+        let Some(ref mut tool) = self.dpcm_tool else {
+            return Ok(());
+        };
+
+        s.next_width(400);
+        s.text_field("WAV Path", &mut tool.wav_path)?;
+        s.same_line(None);
+        let convert = s.button("Convert")?;
+
+        let rates: Vec<String> = (0..16)
+            .map(|rate| format!("{rate:X}: {:.0} Hz", Dmc::bit_rate(region, rate)))
+            .collect();
+        let mut reencode = false;
+        s.next_width(150);
+        reencode |= s.select_box("Rate", &mut tool.rate, &rates, 4)?;
+        s.same_line(None);
+        reencode |= s.checkbox("Normalize", &mut tool.normalize)?;
+        s.same_line(None);
+        s.help_marker(
+            "Higher rates sound clearer but use more ROM space. Normalize scales the sample to \
+            use the DMC's full output range.",
+        )?;
+
+        let mut play = false;
+        let mut stop = false;
+        let mut save = false;
+        if let Some(ref dpcm) = tool.dpcm {
+            s.spacing()?;
+            s.text(format!(
+                "{} bytes, {:.2}s at {:.0} Hz",
+                dpcm.data.len(),
+                dpcm.duration(),
+                Dmc::bit_rate(region, dpcm.rate)
+            ))?;
+            let [rate, level, addr, length] = dpcm.registers(0xC000);
+            s.text(format!(
+                "$4010: ${rate:02X}  $4011: ${level:02X}  $4012: ${addr:02X}  $4013: ${length:02X}"
+            ))?;
+            s.same_line(None);
+            s.help_marker(
+                "Register values to play the sample from $C000. Add 1 to $4012 for every 64 bytes \
+                the sample is placed after $C000.",
+            )?;
+            if dpcm.truncated {
+                s.push();
+                s.fill(Color::YELLOW);
+                s.text("The WAV file was cut short to fit the longest sample the DMC can play.")?;
+                s.pop();
+            }
+
+            // Source audio in gray with the DMC output over it
+            let pos = s.cursor_pos();
+            s.push();
+            s.fill(rgb!(20));
+            s.rect([pos.x(), pos.y(), WAVEFORM_WIDTH, WAVEFORM_HEIGHT])?;
+            let levels = dpcm.levels();
+            let column = |x: i32, len: usize| x as usize * len / WAVEFORM_WIDTH as usize;
+            if let Some(ref wav) = tool.wav {
+                let samples = (dpcm.duration() * wav.sample_rate as f32) as usize;
+                let gain = if tool.normalize {
+                    let peak = wav.samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+                    if peak > 0.0 {
+                        1.0 / peak
+                    } else {
+                        1.0
+                    }
+                } else {
+                    1.0
+                };
+                s.stroke(Color::GRAY);
+                let y = |sample: f32| {
+                    let half = WAVEFORM_HEIGHT as f32 / 2.0;
+                    pos.y() + (half - (gain * sample).clamp(-1.0, 1.0) * half) as i32
+                };
+                let mut prev = None;
+                for x in 0..WAVEFORM_WIDTH {
+                    let sample = wav
+                        .samples
+                        .get(column(x, samples))
+                        .copied()
+                        .unwrap_or_default();
+                    let y = y(sample);
+                    if let Some((prev_x, prev_y)) = prev {
+                        s.line([prev_x, prev_y, pos.x() + x, y])?;
+                    }
+                    prev = Some((pos.x() + x, y));
+                }
+            }
+            s.stroke(Color::GREEN);
+            let mut prev = None;
+            for x in 0..WAVEFORM_WIDTH {
+                let level = levels.get(column(x, levels.len())).copied().unwrap_or(64);
+                let y = pos.y() + WAVEFORM_HEIGHT - 1 - i32::from(level) * WAVEFORM_HEIGHT / 128;
+                if let Some((prev_x, prev_y)) = prev {
+                    s.line([prev_x, prev_y, pos.x() + x, y])?;
+                }
+                prev = Some((pos.x() + x, y));
+            }
+            s.pop();
+            s.set_cursor_pos([pos.x(), pos.y() + WAVEFORM_HEIGHT + 4]);
+
+            if tool.player.is_some() {
+                stop = s.button("Stop")?;
+            } else {
+                play = s.button("Play")?;
+            }
+            s.same_line(None);
+            save = s.button("Save DMC")?;
+        }
+
+        if convert {
+            if let Err(err) = self.load_dpcm_wav() {
+                log::error!("{err:?}");
+                self.add_message(format!("Failed to convert WAV: {err}"));
+            }
+        } else if reencode {
+            self.encode_dpcm();
+        }
+        if play {
+            self.play_dpcm_preview();
+        } else if stop {
+            self.stop_dpcm_preview();
+        }
+        if save {
+            if let Err(err) = self.save_dmc() {
+                log::error!("{err:?}");
+                self.add_message(format!("Failed to save DMC sample: {err}"));
+            }
+        }
+        Ok(())
+    }
+}
@@ -8,7 +8,13 @@ use crate::{
     input::{JoypadBtn, JoypadBtnState, Slot},
     mapper::MapperRevision,
     mem::{Access, Mem},
-    nes::{menu::Menu, Mode, Nes, NesResult, ReplayMode, NES_FRAME_SRC},
+    nes::{
+        menu::Menu,
+        menu_nav::{NavAction, NavInput},
+        tas_editor::TasEditor,
+        tutorial::TutorialEvent,
+        Mode, Nes, NesResult, ReplayMode, NES_FRAME_SRC,
+    },
     video::VideoFilter,
 };
 use pix_engine::prelude::*;
@@ -63,7 +69,11 @@ impl fmt::Display for Input {
                 }
             }
             Input::Button((_, btn)) => write!(f, "{btn:?}"),
-            Input::Axis((_, axis, _)) => write!(f, "{axis:?}"),
+            Input::Axis((_, axis, direction)) => match direction {
+                AxisDirection::None => write!(f, "{axis:?}"),
+                AxisDirection::Positive => write!(f, "{axis:?}+"),
+                AxisDirection::Negative => write!(f, "{axis:?}-"),
+            },
             Input::Mouse((_, btn)) => write!(f, "{btn:?}"),
         }
     }
@@ -132,11 +142,33 @@ pub(crate) enum Action {
     Feature(Feature),
     Setting(Setting),
     Joypad(JoypadBtn),
+    Turbo(JoypadBtn),
     ZapperTrigger,
     ZeroAxis([JoypadBtn; 2]),
     Debug(DebugAction),
 }
 
+impl Action {
+    /// Whether the action changes emulation, so it has to be replayed when seeking. Other
+    /// recorded actions, like saving states or opening menus, are skipped while seeking.
+    pub(crate) const fn affects_emulation(self) -> bool {
+        matches!(
+            self,
+            Self::Joypad(_)
+                | Self::Turbo(_)
+                | Self::ZapperTrigger
+                | Self::ZeroAxis(_)
+                | Self::Nes(
+                    NesState::SoftReset | NesState::HardReset | NesState::MapperRevision(_)
+                )
+                | Self::Setting(Setting::SetNesFormat(_))
+                | Self::Feature(
+                    Feature::LoadState | Feature::LoadQuickSlot(_) | Feature::LoadAutoSave
+                )
+        )
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) enum NesState {
     Quit,
@@ -150,10 +182,27 @@ pub(crate) enum NesState {
 pub(crate) enum Feature {
     ToggleGameplayRecording,
     ToggleSoundRecording,
+    ToggleVideoRecording,
+    ToggleClipCapture,
+    SaveClip,
     Rewind,
     TakeScreenshot,
     SaveState,
     LoadState,
+    SaveQuickSlot(u8),
+    LoadQuickSlot(u8),
+    LoadAutoSave,
+    AddReplayBookmark,
+    AddStateBookmark,
+    NextStateBookmark,
+    PrevStateBookmark,
+    SeekReplayBackward,
+    SeekReplayForward,
+    ToggleReplayFastForward,
+    TakeReplayControl,
+    SaveSessionReplay,
+    ShowTutorial,
+    SwitchSharedPad,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -161,6 +210,9 @@ pub(crate) enum Setting {
     SetSaveSlot(u8),
     ToggleFullscreen,
     ToggleVsync,
+    ToggleMiniView,
+    ToggleAlwaysOnTop,
+    ToggleClickThrough,
     ToggleNtscFilter,
     SetVideoFilter(VideoFilter),
     SetNesFormat(NesRegion),
@@ -173,13 +225,27 @@ pub(crate) enum Setting {
     FastForward,
     IncSpeed,
     DecSpeed,
+    SlowMotion,
+    FrameAdvance,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) enum DebugAction {
     ToggleCpuDebugger,
     TogglePpuDebugger,
+    ToggleNametableViewer,
+    ToggleOamViewer,
+    TogglePaletteViewer,
     ToggleApuDebugger,
+    ToggleScrollOverlay,
+    ToggleInterruptOverlay,
+    ToggleDiffOverlay,
+    ToggleLatencyMonitor,
+    ToggleLatencyTest,
+    ToggleTasEditor,
+    ToggleDpcmTool,
+    ToggleSramEditor,
+    CaptureHdTiles,
     StepInto,
     StepOver,
     StepOut,
@@ -294,6 +360,25 @@ impl Nes {
         event: KeyEvent,
         pressed: bool,
     ) -> bool {
+        if self.capture_key(event, pressed) {
+            return true;
+        }
+        let cancel =
+            self.config.nav_bindings.action(NavInput::Key(event.key)) == Some(NavAction::Cancel);
+        if self.latency_test_input(pressed && !event.repeat, cancel) {
+            return true;
+        }
+        match self.handle_nav_key(s, event, pressed) {
+            Ok(true) => return true,
+            Ok(false) => (),
+            Err(err) => {
+                log::error!("{err:?}");
+                return true;
+            }
+        }
+        if let Some(handled) = self.handle_command_palette_key(event, pressed) {
+            return handled;
+        }
         for slot in [Slot::One, Slot::Two, Slot::Three, Slot::Four] {
             let input = Input::Key((slot, event.key, event.keymod));
             if slot == Slot::Three {
@@ -307,6 +392,9 @@ impl Nes {
     }
 
     pub fn handle_mouse_click(&mut self, s: &mut PixState, btn: Mouse) -> bool {
+        if self.latency_test_input(true, false) {
+            return true;
+        }
         // To avoid consuming events while in menus
         if self.mode == Mode::Playing {
             for slot in [Slot::One, Slot::Two] {
@@ -353,6 +441,20 @@ impl Nes {
         event: ControllerEvent,
         pressed: bool,
     ) -> PixResult<bool> {
+        if self.capture_button(event.button, pressed) {
+            return Ok(true);
+        }
+        let cancel = self
+            .config
+            .nav_bindings
+            .action(NavInput::Button(event.button))
+            == Some(NavAction::Cancel);
+        if self.latency_test_input(pressed, cancel) {
+            return Ok(true);
+        }
+        if self.handle_nav_button(s, event.button, pressed)? {
+            return Ok(true);
+        }
         self.get_controller_slot(event.controller_id)
             .map_or(Ok(false), |slot| {
                 let input = Input::Button((slot, event.button));
@@ -368,6 +470,12 @@ impl Nes {
         axis: Axis,
         value: i32,
     ) -> PixResult<bool> {
+        if self.capture_axis(axis, value) {
+            return Ok(true);
+        }
+        if self.config.motion_aim && self.motion_aim.set_rate(axis, value) {
+            return Ok(true);
+        }
         self.get_controller_slot(controller_id)
             .map_or(Ok(false), |slot| {
                 let direction = match value.cmp(&0) {
@@ -401,6 +509,7 @@ impl Nes {
             }
             Action::Setting(setting) => self.handle_setting(s, setting, pressed, repeat)?,
             Action::Joypad(button) => self.handle_joypad_pressed(slot, button, pressed),
+            Action::Turbo(button) => self.handle_turbo(slot, button, pressed),
             Action::ZapperTrigger if pressed => {
                 self.handle_zapper_trigger();
                 true
@@ -428,39 +537,46 @@ impl Nes {
             );
         }
 
-        if self.replay.mode == ReplayMode::Recording {
+        // Bookmarks are stored separately, so replaying them would duplicate them, and jumping
+        // to a bookmark rewrites the recording instead. Playback controls only apply to playback
+        if self.replay.mode == ReplayMode::Recording
+            && !matches!(
+                action,
+                Action::Feature(
+                    Feature::AddReplayBookmark
+                        | Feature::AddStateBookmark
+                        | Feature::NextStateBookmark
+                        | Feature::PrevStateBookmark
+                        | Feature::SeekReplayBackward
+                        | Feature::SeekReplayForward
+                        | Feature::ToggleReplayFastForward
+                        | Feature::TakeReplayControl
+                )
+            )
+        {
             self.replay
                 .buffer
                 .push(self.action_event(slot, action, pressed, repeat));
         }
+        self.record_session_event(slot, action, pressed, repeat);
 
         Ok(handled)
     }
 
-    pub(crate) fn replay_action(&mut self, s: &mut PixState) -> NesResult<()> {
+    /// Plays back the replay events for the current frame. While `seeking`, only events that
+    /// change emulation are handled.
+    pub(crate) fn replay_action(&mut self, s: &mut PixState, seeking: bool) -> NesResult<()> {
+        self.update_replay_greenzone();
         let current_frame = self.control_deck.frame_number();
-        while let Some(action_event) = self.replay.buffer.last() {
-            match action_event.frame.cmp(&current_frame) {
-                Ordering::Equal => {
-                    let ActionEvent {
-                        slot,
-                        action,
-                        pressed,
-                        repeat,
-                        ..
-                    } = self.replay.buffer.pop().expect("valid action event");
-                    self.handle_action(s, slot, action, pressed, repeat)?;
-                }
-                Ordering::Less => {
-                    log::warn!(
-                        "Encountered action event out of order: {} < {}",
-                        action_event.frame,
-                        current_frame
-                    );
-                    self.replay.buffer.pop();
-                }
-                Ordering::Greater => break,
-            }
+        for action_event in self.replay.take_frame_events(current_frame, seeking) {
+            let ActionEvent {
+                slot,
+                action,
+                pressed,
+                repeat,
+                ..
+            } = action_event;
+            self.handle_action(s, slot, action, pressed, repeat)?;
         }
         if self.replay.buffer.is_empty() {
             self.stop_replay();
@@ -471,7 +587,7 @@ impl Nes {
 
 impl Nes {
     #[inline]
-    const fn action_event(
+    pub(crate) const fn action_event(
         &self,
         slot: Slot,
         action: Action,
@@ -489,16 +605,14 @@ impl Nes {
 
     #[inline]
     fn get_controller_slot(&self, controller_id: ControllerId) -> Option<Slot> {
-        self.players.iter().find_map(|(&slot, &id)| {
-            if id == controller_id {
-                Some(slot)
-            } else {
-                None
-            }
-        })
+        self.controllers.slot(controller_id)
     }
 
-    fn handle_nes_state(&mut self, s: &mut PixState, state: NesState) -> NesResult<bool> {
+    pub(crate) fn handle_nes_state(
+        &mut self,
+        s: &mut PixState,
+        state: NesState,
+    ) -> NesResult<bool> {
         if self.replay.mode == ReplayMode::Recording {
             return Ok(false);
         }
@@ -511,6 +625,8 @@ impl Nes {
             NesState::SoftReset => {
                 self.error = None;
                 self.control_deck.reset(Kind::Soft);
+                self.reapply_patches();
+                self.restart_session_replay();
                 self.add_message("Reset");
                 if self.debugger.is_some() && self.mode != Mode::Paused {
                     self.mode = Mode::Paused;
@@ -519,6 +635,9 @@ impl Nes {
             NesState::HardReset => {
                 self.error = None;
                 self.control_deck.reset(Kind::Hard);
+                self.reapply_patches();
+                self.clear_rewind();
+                self.restart_session_replay();
                 self.add_message("Power Cycled");
                 if self.debugger.is_some() {
                     self.mode = Mode::Paused;
@@ -534,6 +653,7 @@ impl Nes {
             if repeat {
                 if self.config.rewind {
                     self.mode = Mode::Rewinding;
+                    self.tutorial_event(TutorialEvent::Rewound);
                 } else {
                     self.add_message("Rewind disabled. You can enable it in the Config menu.");
                 }
@@ -550,10 +670,34 @@ impl Nes {
                     ReplayMode::Off => self.start_replay(),
                     ReplayMode::Recording | ReplayMode::Playback => self.stop_replay(),
                 },
-                Feature::ToggleSoundRecording => self.toggle_sound_recording(s),
+                Feature::ToggleSoundRecording => self.toggle_sound_recording(),
+                Feature::ToggleVideoRecording => self.toggle_video_recording(),
+                Feature::ToggleClipCapture => self.toggle_clip_capture(),
+                Feature::SaveClip => self.save_clip(),
                 Feature::TakeScreenshot => self.save_screenshot(s),
                 Feature::SaveState => self.save_state(self.config.save_slot),
                 Feature::LoadState => self.load_state(self.config.save_slot),
+                Feature::SaveQuickSlot(slot) => self.save_quick_slot(slot),
+                Feature::LoadQuickSlot(slot) => self.load_quick_slot(slot),
+                #[cfg(not(target_arch = "wasm32"))]
+                Feature::LoadAutoSave => self.load_auto_save(),
+                #[cfg(target_arch = "wasm32")]
+                Feature::LoadAutoSave => (),
+                Feature::AddReplayBookmark => self.add_replay_bookmark(),
+                Feature::AddStateBookmark => self.add_state_bookmark(),
+                Feature::NextStateBookmark => self.jump_to_adjacent_state_bookmark(true),
+                Feature::PrevStateBookmark => self.jump_to_adjacent_state_bookmark(false),
+                Feature::SeekReplayBackward | Feature::SeekReplayForward => {
+                    let forward = feature == Feature::SeekReplayForward;
+                    if let Err(err) = self.step_replay(s, forward) {
+                        self.error = Some(err.to_string());
+                    }
+                }
+                Feature::ToggleReplayFastForward => self.toggle_replay_fast_forward(),
+                Feature::TakeReplayControl => self.take_replay_control(),
+                Feature::SaveSessionReplay => self.save_session_replay(),
+                Feature::ShowTutorial => self.start_tutorial(),
+                Feature::SwitchSharedPad => self.switch_shared_pad(),
                 Feature::Rewind => (), // Handled above
             }
         }
@@ -592,6 +736,24 @@ impl Nes {
                         self.add_message("Vsync Disabled");
                     }
                 }
+                Setting::ToggleMiniView => {
+                    self.set_mini_view(s, !self.config.mini_view)?;
+                }
+                Setting::ToggleAlwaysOnTop => {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    {
+                        self.set_always_on_top(!self.config.always_on_top);
+                        if self.config.always_on_top {
+                            self.add_message("Always on Top Enabled");
+                        } else {
+                            self.add_message("Always on Top Disabled");
+                        }
+                    }
+                }
+                Setting::ToggleClickThrough => {
+                    #[cfg(not(target_arch = "wasm32"))]
+                    self.set_click_through(!self.config.click_through);
+                }
                 Setting::ToggleNtscFilter => {
                     self.config.filter = match self.config.filter {
                         VideoFilter::Pixellate => VideoFilter::Ntsc,
@@ -614,6 +776,9 @@ impl Nes {
                 Setting::ToggleDmc => self.control_deck.toggle_channel(Channel::Dmc),
                 Setting::IncSpeed => self.change_speed(0.25),
                 Setting::DecSpeed => self.change_speed(-0.25),
+                Setting::SlowMotion if !repeat => self.cycle_slow_motion(),
+                // Repeats while held
+                Setting::FrameAdvance => self.frame_advance(s)?,
                 // Toggling fast forward happens on key release
                 _ => return Ok(false),
             }
@@ -627,6 +792,21 @@ impl Nes {
         if self.mode != Mode::Playing {
             return false;
         }
+        if pressed {
+            let frame = self.control_deck.frame_number();
+            self.latency.input(Instant::now(), frame);
+        }
+        let (slot, shared) = self.config.input_sharing.targets(slot, self.shared_pad);
+        if let Some(shared) = shared {
+            self.press_joypad(shared, button, pressed);
+        }
+        self.press_joypad(slot, button, pressed)
+    }
+
+    fn press_joypad(&mut self, slot: Slot, button: JoypadBtn, pressed: bool) -> bool {
+        if matches!(button, JoypadBtn::TurboA | JoypadBtn::TurboB) {
+            return self.handle_turbo(slot, button, pressed);
+        }
         let joypad = self.control_deck.joypad_mut(slot);
         if !self.config.concurrent_dpad && pressed {
             match button {
@@ -638,17 +818,10 @@ impl Nes {
             }
         }
         joypad.set_button(button.into(), pressed);
-
-        // Ensure that primary button isn't stuck pressed
-        match button {
-            JoypadBtn::TurboA => joypad.set_button(JoypadBtnState::A, pressed),
-            JoypadBtn::TurboB => joypad.set_button(JoypadBtnState::B, pressed),
-            _ => (),
-        };
         true
     }
 
-    fn handle_debug(
+    pub(crate) fn handle_debug(
         &mut self,
         s: &mut PixState,
         action: DebugAction,
@@ -658,21 +831,39 @@ impl Nes {
         match action {
             DebugAction::ToggleCpuDebugger if !repeat => self.toggle_debugger(s)?,
             DebugAction::TogglePpuDebugger if !repeat => self.toggle_ppu_viewer(s)?,
+            DebugAction::ToggleNametableViewer if !repeat => self.toggle_nametable_viewer(s)?,
+            DebugAction::ToggleOamViewer if !repeat => self.toggle_oam_viewer(s)?,
+            DebugAction::TogglePaletteViewer if !repeat => self.toggle_palette_viewer(s)?,
             DebugAction::ToggleApuDebugger if !repeat => self.toggle_apu_viewer(s)?,
+            DebugAction::ToggleScrollOverlay if !repeat => self.toggle_scroll_overlay(),
+            DebugAction::ToggleInterruptOverlay if !repeat => self.toggle_interrupt_overlay(),
+            DebugAction::ToggleDiffOverlay if !repeat => self.toggle_diff_overlay(),
+            DebugAction::ToggleLatencyMonitor if !repeat => self.toggle_latency_monitor(),
+            DebugAction::ToggleLatencyTest if !repeat => self.toggle_latency_test(),
+            DebugAction::ToggleTasEditor if !repeat => self.toggle_tas_editor(s)?,
+            DebugAction::ToggleDpcmTool if !repeat => self.toggle_dpcm_tool(s)?,
+            DebugAction::ToggleSramEditor if !repeat => self.toggle_sram_editor(s)?,
+            DebugAction::CaptureHdTiles if !repeat => self.capture_hd_tiles(),
             DebugAction::StepInto if debugging => self.debug_step_into(s)?,
             DebugAction::StepOver if debugging => self.debug_step_over(s)?,
             DebugAction::StepOut if debugging => self.debug_step_out(s)?,
             DebugAction::StepFrame if debugging => self.debug_step_frame(s)?,
             DebugAction::StepScanline if debugging => self.debug_step_scanline(s)?,
             DebugAction::IncScanline => {
+                let increment = if s.keymod_down(KeyMod::SHIFT) { 10 } else { 1 };
                 if let Some(ref mut viewer) = self.ppu_viewer {
-                    let increment = if s.keymod_down(KeyMod::SHIFT) { 10 } else { 1 };
+                    viewer.inc_scanline(increment);
+                }
+                if let Some(ref mut viewer) = self.nametable_viewer {
                     viewer.inc_scanline(increment);
                 }
             }
             DebugAction::DecScanline => {
+                let decrement = if s.keymod_down(KeyMod::SHIFT) { 10 } else { 1 };
                 if let Some(ref mut viewer) = self.ppu_viewer {
-                    let decrement = if s.keymod_down(KeyMod::SHIFT) { 10 } else { 1 };
+                    viewer.dec_scanline(decrement);
+                }
+                if let Some(ref mut viewer) = self.nametable_viewer {
                     viewer.dec_scanline(decrement);
                 }
             }
@@ -686,7 +877,7 @@ impl Nes {
         if let Err(err) = self.control_deck.clock_instr() {
             self.handle_emulation_error(s, &err)?;
         }
-        Ok(())
+        self.process_audio()
     }
 
     fn next_instr(&mut self) -> Instr {
@@ -710,7 +901,7 @@ impl Nes {
                 }
             }
         }
-        Ok(())
+        self.process_audio()
     }
 
     fn debug_step_out(&mut self, s: &mut PixState) -> NesResult<()> {
@@ -725,8 +916,7 @@ impl Nes {
         if let Err(err) = self.control_deck.clock_instr() {
             self.handle_emulation_error(s, &err)?;
         }
-
-        Ok(())
+        self.process_audio()
     }
 
     fn debug_step_frame(&mut self, s: &mut PixState) -> NesResult<()> {
@@ -734,7 +924,24 @@ impl Nes {
         if let Err(err) = self.control_deck.clock_frame() {
             self.handle_emulation_error(s, &err)?;
         }
-        Ok(())
+        self.process_audio()
+    }
+
+    /// Runs a single frame and stays paused, for stepping through gameplay outside the debugger.
+    fn frame_advance(&mut self, s: &mut PixState) -> PixResult<()> {
+        if !self.control_deck.is_running() || matches!(self.mode, Mode::InMenu(_)) {
+            return Ok(());
+        }
+        self.pause_play();
+        if self.tas_editor.as_ref().map_or(false, TasEditor::active) {
+            return self.step_tas(s);
+        }
+        if self.replay.mode == ReplayMode::Playback {
+            self.replay_action(s, false)?;
+        }
+        let prev_frame = self.control_deck.frame_number();
+        let result = self.control_deck.clock_frame();
+        self.handle_clock_result(s, prev_frame, result)
     }
 
     fn debug_step_scanline(&mut self, s: &mut PixState) -> NesResult<()> {
@@ -742,6 +949,6 @@ impl Nes {
         if let Err(err) = self.control_deck.clock_scanline() {
             self.handle_emulation_error(s, &err)?;
         }
-        Ok(())
+        self.process_audio()
     }
 }
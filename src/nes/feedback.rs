@@ -0,0 +1,158 @@
+//! Controller feedback: rumble and lightbar colors.
+//!
+//! Rumble patterns are queued on [`Controllers`](super::controllers::Controllers) and played back
+//! over the following frames without blocking emulation. Lightbars, like the one on a `DualSense`,
+//! show what the emulator is doing: red while recording and blue while rewinding, falling back to
+//! the controller's own color otherwise.
+//!
+//! Feedback is sent through a [`FeedbackOutput`], which also reports what each controller
+//! supports so effects are only sent to controllers that can show them.
+
+use crate::{
+    input::Slot,
+    nes::{controllers::Rumble, state::ReplayMode, Mode, Nes},
+};
+use pix_engine::prelude::*;
+use std::{fmt, time::Instant};
+
+const SLOTS: [Slot; 4] = [Slot::One, Slot::Two, Slot::Three, Slot::Four];
+
+/// Feedback a controller supports.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct Capabilities {
+    pub(crate) rumble: bool,
+    pub(crate) lightbar: bool,
+}
+
+/// Emulator state shown on controller lightbars, highest priority first.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) enum LightbarStatus {
+    Recording,
+    Rewinding,
+}
+
+impl LightbarStatus {
+    pub(crate) const fn color(self) -> Color {
+        match self {
+            Self::Recording => Color::RED,
+            Self::Rewinding => Color::BLUE,
+        }
+    }
+}
+
+/// Sends feedback to controllers.
+pub(crate) trait FeedbackOutput: fmt::Debug {
+    fn capabilities(&self, controller_id: ControllerId) -> Capabilities;
+
+    /// Sets motor strengths, or stops rumbling with `None`.
+    fn set_rumble(&mut self, controller_id: ControllerId, rumble: Option<Rumble>);
+
+    /// Sets the lightbar color, or restores the controller's own color with `None`.
+    fn set_lightbar(&mut self, controller_id: ControllerId, color: Option<Color>);
+}
+
+/// Logs feedback for controllers that can't be driven directly. The window backend doesn't
+/// report controller capabilities, so every controller is treated as having rumble and none as
+/// having a lightbar.
+#[derive(Default, Debug, Copy, Clone)]
+pub(crate) struct LogOutput;
+
+impl FeedbackOutput for LogOutput {
+    fn capabilities(&self, _controller_id: ControllerId) -> Capabilities {
+        Capabilities {
+            rumble: true,
+            lightbar: false,
+        }
+    }
+
+    fn set_rumble(&mut self, controller_id: ControllerId, rumble: Option<Rumble>) {
+        log::debug!("controller {controller_id:?} rumble: {rumble:?}");
+    }
+
+    fn set_lightbar(&mut self, controller_id: ControllerId, color: Option<Color>) {
+        log::debug!("controller {controller_id:?} lightbar: {color:?}");
+    }
+}
+
+/// Feedback last sent to each slot's controller, so it's only sent again when it changes.
+#[derive(Debug)]
+pub(crate) struct Feedback {
+    output: Box<dyn FeedbackOutput>,
+    sent: [(Option<ControllerId>, Option<Rumble>, Option<Color>); 4],
+}
+
+impl Default for Feedback {
+    fn default() -> Self {
+        Self {
+            output: Box::new(LogOutput),
+            sent: [(None, None, None); 4],
+        }
+    }
+}
+
+impl Feedback {
+    pub(crate) fn capabilities(&self, controller_id: ControllerId) -> Capabilities {
+        self.output.capabilities(controller_id)
+    }
+}
+
+impl Nes {
+    /// The emulator state to show on lightbars, if any.
+    pub(crate) fn lightbar_status(&self) -> Option<LightbarStatus> {
+        if self.video_recorder.is_some()
+            || self.sound_recorder.is_some()
+            || self.replay.mode == ReplayMode::Recording
+        {
+            Some(LightbarStatus::Recording)
+        } else if self.mode == Mode::Rewinding {
+            Some(LightbarStatus::Rewinding)
+        } else {
+            None
+        }
+    }
+
+    /// Advances rumble patterns and sends any changed feedback to controllers.
+    pub(crate) fn update_feedback(&mut self) {
+        self.controllers.update_rumble(Instant::now());
+        let color = self
+            .lightbar_status()
+            .filter(|_| self.config.lightbar_status)
+            .map(LightbarStatus::color);
+        for (i, slot) in SLOTS.into_iter().enumerate() {
+            let controller_id = self.controllers.controller(slot);
+            let (sent_id, sent_rumble, sent_color) = self.feedback.sent[i];
+            // Clear feedback from a controller that's no longer assigned to this slot
+            if sent_id != controller_id {
+                if let Some(sent_id) = sent_id {
+                    if sent_rumble.is_some() {
+                        self.feedback.output.set_rumble(sent_id, None);
+                    }
+                    if sent_color.is_some() {
+                        self.feedback.output.set_lightbar(sent_id, None);
+                    }
+                }
+                self.feedback.sent[i] = (controller_id, None, None);
+            }
+            let Some(controller_id) = controller_id else {
+                continue;
+            };
+            let capabilities = self.feedback.output.capabilities(controller_id);
+            let rumble = self
+                .controllers
+                .active_rumble(slot)
+                .filter(|_| capabilities.rumble);
+            let color = color.filter(|_| capabilities.lightbar);
+            let sent = &mut self.feedback.sent[i];
+            if rumble != sent.1 {
+                self.feedback.output.set_rumble(controller_id, rumble);
+                sent.1 = rumble;
+            }
+            if color != sent.2 {
+                self.feedback.output.set_lightbar(controller_id, color);
+                sent.2 = color;
+            }
+        }
+    }
+}
@@ -1,5 +1,5 @@
-use super::{Menu, Mode, Nes, NesResult};
-use crate::{audio::AudioMixer, cart::NesHeader, common::Regional};
+use super::{persistence::DataKind, tutorial::TutorialEvent, Menu, Mode, Nes, NesResult};
+use crate::{cart::NesHeader, common::Regional, debugger::console::Patches};
 use anyhow::{anyhow, Context};
 use flate2::{bufread::DeflateDecoder, write::DeflateEncoder, Compression};
 use pix_engine::prelude::PixState;
@@ -148,6 +148,15 @@ impl Nes {
             })
     }
 
+    /// Drops the in-memory state kept for the previous game, which can't be applied to the one
+    /// just loaded.
+    pub(crate) fn reset_game_state(&mut self) {
+        self.clear_quick_slots();
+        self.clear_rewind();
+        self.patches = Patches::default();
+        self.restart_session_replay();
+    }
+
     /// Loads a ROM cartridge into memory
     pub(crate) fn load_rom(&mut self, s: &mut PixState) -> NesResult<()> {
         if self.config.rom_path.is_dir() {
@@ -162,7 +171,11 @@ impl Nes {
         self.error = None;
         self.mode = Mode::Paused;
         self.audio.pause();
-        let rom = match File::open(&self.config.rom_path)
+        self.stop_video_recording();
+        self.stop_sound_recording();
+        self.stop_clip_capture();
+        self.save_game_mixer();
+        let rom = match fs::read(&self.config.rom_path)
             .with_context(|| format!("failed to open rom {:?}", self.config.rom_path))
         {
             Ok(rom) => rom,
@@ -183,24 +196,38 @@ impl Nes {
             log::warn!("{:?}", err);
         }
 
-        let mut rom = BufReader::new(rom);
-        match self.control_deck.load_rom(&name, &mut rom) {
+        let rom = self.patch_rom(rom);
+        match self.control_deck.load_rom(&name, &mut rom.as_slice()) {
             Ok(()) => {
+                if let Err(err) = s.set_title(self.window_title()) {
+                    log::warn!("{:?}", err);
+                }
+                self.reset_game_state();
                 self.config.region = self.control_deck.region();
+                let load_slot = self.apply_launch_options();
                 s.set_window_dimensions(self.config.get_dimensions())?;
                 self.update_frame_rate(s)?;
-                self.audio = AudioMixer::new(
-                    self.control_deck.sample_rate(),
-                    self.config.audio_sample_rate / self.config.speed,
-                    self.config.audio_buffer_size,
-                );
-                self.audio.open_playback(s)?;
+                self.open_audio(s)?;
+                self.load_game_mixer();
+                self.load_state_labels();
+                self.load_state_bookmarks();
+                self.load_sram_bookmarks();
+                self.load_symbols();
+                self.load_hd_pack();
                 self.audio.resume();
                 if let Err(err) = self.load_sram() {
                     log::error!("{:?}: {:?}", self.config.rom_path, err);
                     self.add_message("Failed to load game state");
                 }
+                if let Some(slot) = load_slot {
+                    self.load_state(slot);
+                }
+                for issue in self.control_deck.dump_issues().to_vec() {
+                    self.add_message(format!("Warning: {issue}"));
+                }
                 self.mode = Mode::Playing;
+                self.tutorial_event(TutorialEvent::RomLoaded);
+                self.check_region();
             }
             Err(err) => {
                 log::error!("{:?}, {:?}", self.config.rom_path, err);
@@ -209,12 +236,13 @@ impl Nes {
             }
         }
 
-        if let Ok(path) = self.save_path(1) {
-            if path.exists() {
+        if let Ok(key) = self.save_key(1) {
+            if self.persistence.exists(DataKind::State, &key) {
                 self.load_state(1);
             }
         }
         self.load_replay();
+        self.start_script();
 
         Ok(())
     }
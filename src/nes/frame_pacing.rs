@@ -0,0 +1,213 @@
+//! Schedules emulated frames off a high-resolution clock instead of the display refresh.
+//!
+//! Each update, the time since the last one is converted into a whole number of emulated frames.
+//! When the display is faster than the console, like 50Hz PAL on a 60Hz display or 60Hz NTSC on
+//! a 144Hz display, some updates run no frames and the last frame is shown again. When it's
+//! slower, some updates run two frames and only the last one is shown.
+//!
+//! With audio sync, the audio device is the clock instead. Each update runs just enough
+//! emulation to refill the audio buffer to its target level, so audio never underruns or drifts
+//! and video follows along.
+
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// How emulated time is scheduled against the display.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub(crate) enum FramePacing {
+    /// Runs whole frames, repeating or skipping frames to match the display refresh.
+    #[default]
+    Frames,
+    /// Runs exactly the time elapsed since the last update, which may end partway through a
+    /// frame.
+    Time,
+}
+
+impl FramePacing {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[Self::Frames, Self::Time]
+    }
+}
+
+impl AsRef<str> for FramePacing {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Frames => "Whole Frames",
+            Self::Time => "Elapsed Time",
+        }
+    }
+}
+
+impl From<usize> for FramePacing {
+    fn from(value: usize) -> Self {
+        if value == 1 {
+            Self::Time
+        } else {
+            Self::Frames
+        }
+    }
+}
+
+/// Which clock emulation is paced by.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub(crate) enum SyncMode {
+    /// Paced by the display, with dynamic rate control keeping audio in step.
+    #[default]
+    Video,
+    /// Paced by how quickly the audio device consumes samples.
+    Audio,
+    /// Runs a frame every update with no frame rate limit or audio, for benchmarking.
+    Unsynced,
+}
+
+impl SyncMode {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[Self::Video, Self::Audio, Self::Unsynced]
+    }
+}
+
+impl AsRef<str> for SyncMode {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Video => "Video",
+            Self::Audio => "Audio",
+            Self::Unsynced => "Unsynced",
+        }
+    }
+}
+
+impl From<usize> for SyncMode {
+    fn from(value: usize) -> Self {
+        match value {
+            1 => Self::Audio,
+            2 => Self::Unsynced,
+            _ => Self::Video,
+        }
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone)]
+#[must_use]
+pub(crate) struct FramePacer {
+    last_update: Option<Instant>,
+    // Emulated seconds owed, may be slightly negative after snapping to a frame early
+    accumulated: f32,
+}
+
+impl FramePacer {
+    /// Most frames run in a single update at normal speed, so a stall doesn't try to catch up
+    /// all at once.
+    const MAX_FRAMES: f32 = 3.0;
+    /// Fraction of a frame an update can be early and still run it. Absorbs display timing
+    /// jitter that would otherwise alternate between zero and two frames per update.
+    const SNAP: f32 = 0.05;
+
+    /// Emulated seconds to run for an update at `now`.
+    pub(crate) fn seconds_to_run(
+        &mut self,
+        now: Instant,
+        pacing: FramePacing,
+        speed: f32,
+        frame_rate: f32,
+    ) -> f32 {
+        let elapsed = self
+            .last_update
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f32());
+        self.last_update = Some(now);
+        self.advance(elapsed, pacing, speed, frame_rate)
+    }
+
+    /// Emulated seconds to run for an update at `now` to produce `missing` more output samples at
+    /// `sample_rate`, when paced by audio.
+    pub(crate) fn seconds_to_fill(
+        &mut self,
+        now: Instant,
+        missing: f32,
+        sample_rate: f32,
+        speed: f32,
+        frame_rate: f32,
+    ) -> f32 {
+        // Switching back to video sync picks up from here instead of catching up
+        self.last_update = Some(now);
+        self.accumulated = 0.0;
+        // Emulation produces `sample_rate / speed` output samples per emulated second
+        let max_time = Self::MAX_FRAMES * speed.max(1.0) / frame_rate;
+        (speed * missing / sample_rate).clamp(0.0, max_time)
+    }
+
+    fn advance(&mut self, elapsed: f32, pacing: FramePacing, speed: f32, frame_rate: f32) -> f32 {
+        match pacing {
+            // Clamp prevents wide swings in emulation speed and audio clipping due to jitter
+            FramePacing::Time => (speed * elapsed).clamp(0.0, speed * (1.0 / 20.0)),
+            FramePacing::Frames => {
+                let frame_time = 1.0 / frame_rate;
+                let max_time = Self::MAX_FRAMES * speed.max(1.0) * frame_time;
+                self.accumulated = (self.accumulated + speed * elapsed).min(max_time);
+                let mut frames = (self.accumulated / frame_time).floor();
+                if self.accumulated - frames * frame_time > (1.0 - Self::SNAP) * frame_time {
+                    frames += 1.0;
+                }
+                let seconds = frames * frame_time;
+                self.accumulated -= seconds;
+                seconds
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames_run(display_rate: f32, frame_rate: f32, updates: usize) -> f32 {
+        let mut pacer = FramePacer::default();
+        (0..updates)
+            .map(|_| {
+                pacer.advance(1.0 / display_rate, FramePacing::Frames, 1.0, frame_rate) * frame_rate
+            })
+            .inspect(|frames| assert!((frames - frames.round()).abs() < 1e-3, "whole frames"))
+            .sum()
+    }
+
+    #[test]
+    fn repeats_and_duplicates_frames() {
+        let pal_on_60hz = frames_run(60.0, 50.0, 600);
+        assert!((pal_on_60hz - 500.0).abs() <= 1.0, "{pal_on_60hz}");
+        let ntsc_on_144hz = frames_run(144.0, 60.0, 1440);
+        assert!((ntsc_on_144hz - 600.0).abs() <= 1.0, "{ntsc_on_144hz}");
+        let ntsc_on_50hz = frames_run(50.0, 60.0, 500);
+        assert!((ntsc_on_50hz - 600.0).abs() <= 1.0, "{ntsc_on_50hz}");
+    }
+
+    #[test]
+    fn fills_audio_buffer() {
+        let mut pacer = FramePacer::default();
+        let now = Instant::now();
+        let seconds = pacer.seconds_to_fill(now, 735.0, 44_100.0, 1.0, 60.0);
+        assert!((seconds * 60.0 - 1.0).abs() < 1e-3, "one frame of samples");
+        let seconds = pacer.seconds_to_fill(now, 735.0, 44_100.0, 0.5, 60.0);
+        assert!(
+            (seconds * 60.0 - 0.5).abs() < 1e-3,
+            "half a frame at half speed"
+        );
+        let seconds = pacer.seconds_to_fill(now, -10.0, 44_100.0, 1.0, 60.0);
+        assert!(seconds.abs() < f32::EPSILON, "buffer already full");
+        let seconds = pacer.seconds_to_fill(now, 44_100.0, 44_100.0, 1.0, 60.0);
+        assert!((seconds * 60.0 - 3.0).abs() < 1e-3, "limited after a stall");
+    }
+
+    #[test]
+    fn snaps_jittery_updates() {
+        let mut pacer = FramePacer::default();
+        let frame_rate = 60.098_8;
+        for elapsed in [0.0166, 0.0164, 0.0169, 0.0163] {
+            let seconds = pacer.advance(elapsed, FramePacing::Frames, 1.0, frame_rate);
+            assert!(
+                (seconds * frame_rate - 1.0).abs() < 1e-3,
+                "{elapsed}: one frame"
+            );
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Per-ROM screenshot gallery.
+//!
+//! Screenshots are named `<rom>_Screen_Shot_<timestamp>.png`, so every screenshot taken for the
+//! loaded ROM can be found by scanning the screenshot directory. Previews are loaded lazily for
+//! the selected screenshot only.
+
+use crate::nes::Nes;
+use pix_engine::prelude::*;
+use std::{
+    ffi::OsStr,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+const SCREENSHOT_TAG: &str = "Screen_Shot_";
+
+/// Returns the filename prefix for screenshots of `rom`, or the untagged prefix if no ROM is
+/// loaded.
+pub(crate) fn screenshot_prefix(rom: Option<&str>) -> String {
+    rom.and_then(|rom| Path::new(rom).file_stem().and_then(OsStr::to_str))
+        .map_or_else(
+            || SCREENSHOT_TAG.to_string(),
+            |name| format!("{name}_{SCREENSHOT_TAG}"),
+        )
+}
+
+/// Whether `path` is a screenshot with the given `prefix`.
+fn is_screenshot(path: &Path, prefix: &str) -> bool {
+    path.extension().and_then(OsStr::to_str) == Some("png")
+        && path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .map_or(false, |name| name.starts_with(prefix))
+}
+
+/// Screenshots found for the loaded ROM, newest first.
+#[derive(Default)]
+#[must_use]
+pub(crate) struct Gallery {
+    pub(crate) paths: Vec<PathBuf>,
+    pub(crate) selected: usize,
+    preview: Option<(PathBuf, Image)>,
+}
+
+impl Gallery {
+    #[inline]
+    pub(crate) fn selected_path(&self) -> Option<&PathBuf> {
+        self.paths.get(self.selected)
+    }
+
+    /// Returns the full-size image for the selected screenshot, loading it if the selection
+    /// changed.
+    ///
+    /// # Errors
+    ///
+    /// If the image fails to load, an error is returned.
+    pub(crate) fn preview(&mut self) -> PixResult<Option<&Image>> {
+        let Some(path) = self.paths.get(self.selected) else {
+            self.preview = None;
+            return Ok(None);
+        };
+        if !matches!(self.preview, Some((ref loaded, _)) if loaded == path) {
+            self.preview = Some((path.clone(), Image::from_file(path)?));
+        }
+        Ok(self.preview.as_ref().map(|(_, image)| image))
+    }
+}
+
+impl fmt::Debug for Gallery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Gallery")
+            .field("paths", &self.paths.len())
+            .field("selected", &self.selected)
+            .field("preview", &self.preview.as_ref().map(|(path, _)| path))
+            .finish()
+    }
+}
+
+impl Nes {
+    /// Rescans the screenshot directory for screenshots of the loaded ROM.
+    pub(crate) fn update_gallery(&mut self) {
+        let prefix = screenshot_prefix(self.control_deck.loaded_rom().as_deref());
+        self.gallery.paths.clear();
+        match self.config.screenshot_dir.read_dir() {
+            Ok(read_dir) => {
+                self.gallery.paths.extend(
+                    read_dir
+                        .filter_map(Result::ok)
+                        .map(|f| f.path())
+                        .filter(|p| is_screenshot(p, &prefix)),
+                );
+                // Timestamps sort chronologically, so reverse to show the newest first
+                self.gallery.paths.sort_by(|a, b| b.cmp(a));
+            }
+            Err(err) => {
+                log::error!("{:?}", err);
+                self.error = Some(format!("Failed to read {:?}", self.config.screenshot_dir));
+            }
+        }
+        self.gallery.selected = self
+            .gallery
+            .selected
+            .min(self.gallery.paths.len().saturating_sub(1));
+    }
+
+    /// Permanently deletes the selected screenshot.
+    pub(crate) fn delete_screenshot(&mut self) {
+        let Some(path) = self.gallery.selected_path().cloned() else {
+            return;
+        };
+        match fs::remove_file(&path) {
+            Ok(()) => self.add_message(format!("Deleted {path:?}")),
+            Err(err) => {
+                log::error!("{err:?}");
+                self.error = Some(format!("Failed to delete {path:?}"));
+            }
+        }
+        self.update_gallery();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screenshot_names() {
+        let prefix = screenshot_prefix(Some("roms/Super Mario Bros.nes"));
+        assert_eq!(prefix, "Super Mario Bros_Screen_Shot_");
+        assert_eq!(screenshot_prefix(None), SCREENSHOT_TAG);
+        assert!(is_screenshot(
+            Path::new("./Super Mario Bros_Screen_Shot_2023-01-01_at_10_00_00.png"),
+            &prefix
+        ));
+        assert!(!is_screenshot(
+            Path::new("./Zelda_Screen_Shot_2023-01-01_at_10_00_00.png"),
+            &prefix
+        ));
+        assert!(!is_screenshot(
+            Path::new("./Super Mario Bros_Screen_Shot_2023-01-01_at_10_00_00.gif"),
+            &prefix
+        ));
+    }
+}
@@ -0,0 +1,115 @@
+//! Save states kept every few frames while running recorded input, for replay playback and the
+//! TAS editor to seek from without emulating everything from the start again.
+
+use crate::{
+    cpu::Cpu,
+    nes::filesystem::{decode_data, encode_data},
+    NesResult,
+};
+use anyhow::Context;
+use std::collections::BTreeMap;
+
+/// Greenzone size before every other state is dropped, in bytes.
+const GREENZONE_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Compressed save states taken before running a frame, keyed by frame, at least `INTERVAL`
+/// frames apart.
+#[derive(Default, Debug, Clone)]
+#[must_use]
+pub(crate) struct Greenzone<const INTERVAL: usize> {
+    states: BTreeMap<usize, Vec<u8>>,
+}
+
+impl<const INTERVAL: usize> Greenzone<INTERVAL> {
+    /// Whether a state should be taken at `frame`.
+    pub(crate) fn needs_state(&self, frame: usize) -> bool {
+        self.nearest(frame)
+            .map_or(true, |(nearest, _)| frame - nearest >= INTERVAL)
+    }
+
+    /// Takes a state of `cpu` at `frame` if one is needed.
+    pub(crate) fn update(&mut self, frame: usize, cpu: &Cpu) {
+        if !self.needs_state(frame) {
+            return;
+        }
+        match bincode::serialize(cpu)
+            .context("failed to serialize greenzone state")
+            .and_then(|data| encode_data(&data))
+        {
+            Ok(state) => self.insert(frame, state),
+            Err(err) => log::error!("{err:?}"),
+        }
+    }
+
+    fn insert(&mut self, frame: usize, state: Vec<u8>) {
+        self.states.insert(frame, state);
+        let size = self.states.values().map(Vec::len).sum::<usize>();
+        if size > GREENZONE_BUDGET {
+            let mut keep = false;
+            self.states.retain(|_, _| {
+                keep = !keep;
+                keep
+            });
+        }
+    }
+
+    /// The latest state at or before `frame`.
+    fn nearest(&self, frame: usize) -> Option<(usize, &[u8])> {
+        self.states
+            .range(..=frame)
+            .next_back()
+            .map(|(&frame, state)| (frame, state.as_slice()))
+    }
+
+    /// The frame of the latest state at or before `frame`.
+    pub(crate) fn nearest_frame(&self, frame: usize) -> Option<usize> {
+        self.nearest(frame).map(|(frame, _)| frame)
+    }
+
+    /// Loads the latest state at or before `frame`. On error the greenzone is cleared, since its
+    /// states can't be trusted.
+    pub(crate) fn load(&mut self, frame: usize) -> Option<NesResult<(usize, Cpu)>> {
+        let (nearest, state) = self.nearest(frame)?;
+        let cpu = decode_data(state).and_then(|data| {
+            bincode::deserialize::<Cpu>(&data).context("failed to deserialize greenzone state")
+        });
+        if cpu.is_err() {
+            self.clear();
+        }
+        Some(cpu.map(|cpu| (nearest, cpu)))
+    }
+
+    /// Drops states that depend on the input of `frame`.
+    pub(crate) fn invalidate(&mut self, frame: usize) {
+        self.states.split_off(&(frame + 1));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.states.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn greenzone_states() {
+        let mut greenzone = Greenzone::<60>::default();
+        assert!(greenzone.needs_state(0));
+        greenzone.insert(10, vec![0; 4]);
+        assert!(!greenzone.needs_state(10));
+        assert!(!greenzone.needs_state(69));
+        assert!(greenzone.needs_state(70));
+        assert!(greenzone.needs_state(5));
+        greenzone.insert(70, vec![1; 4]);
+        assert_eq!(greenzone.nearest(5), None);
+        assert_eq!(greenzone.nearest(69), Some((10, [0; 4].as_slice())));
+        assert_eq!(greenzone.nearest_frame(100), Some(70));
+
+        greenzone.invalidate(69);
+        assert_eq!(greenzone.nearest_frame(100), Some(10));
+        assert!(greenzone.load(100).expect("state").is_err());
+        assert_eq!(greenzone.nearest_frame(100), None);
+    }
+}
@@ -0,0 +1,977 @@
+//! HD packs replacing 8x8 tiles with high-resolution art, drawn over the emulated frame at a
+//! higher resolution.
+//!
+//! Packs are directories in `hdpacks` in the config directory named after the ROM, like
+//! `hdpacks/Super Mario Bros/hires.txt`. The definition file follows the HDNes layout, one tag per
+//! line:
+//!
+//! ```text
+//! <scale>2
+//! <img>tiles.png
+//! <condition>big,memoryCheckConstant,0754,==,00
+//! [big]<tile>0,8A3E61F0C2D94B17,0F162737,32,0
+//! <tile>0,8A3E61F0C2D94B17,0F162737,16,0
+//! ```
+//!
+//! Tiles are matched by the hash of their 16 pattern bytes and the four colors of the palette
+//! they're drawn with, both in hex, and are drawn from the `<img>` with that index at `x`, `y`.
+//! The replacement art is `8 * scale` pixels square, and transparent parts show the emulated
+//! pixels underneath. Lines starting with `#` are comments.
+//!
+//! A `<tile>` can be limited to when named conditions hold, listed in brackets and joined with
+//! `&`, with `!` negating one. The first `<tile>` whose conditions all hold is used, so
+//! conditional tiles go before their fallback. Conditions are:
+//!
+//! - `memoryCheckConstant,addr,op,value`: compares a CPU byte to a constant.
+//! - `memoryCheck,addr,op,addr`: compares two CPU bytes.
+//! - `frameRange,divisor,remainder`: the frame number modulo `divisor` is at least `remainder`,
+//!   for animation.
+//! - `tileAtPosition,x,y,tile,palette`: another tile is drawn at screen pixel `x`, `y`.
+//! - `hmirror`, `vmirror` and `bgpriority`: the sprite is flipped, or drawn behind the
+//!   background.
+//!
+//! Operators are `==`, `!=`, `>`, `<`, `>=` and `<=`, and addresses and values are hex.
+//!
+//! Capturing tiles writes the unique tiles of the current frame to a PNG sheet in the game's pack
+//! directory, with a matching `<tile>` line for each to copy into `hires.txt`.
+
+use crate::{
+    common::config_dir,
+    mem::{Access, Mem},
+    nes::{Mode, Nes},
+    ppu::{tile_capture::TilePixel, Ppu},
+    NesResult,
+};
+use anyhow::{anyhow, bail, Context};
+use pix_engine::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const DEFINITION_FILE: &str = "hires.txt";
+const MAX_SCALE: u32 = 8;
+const CHANNELS: usize = 4;
+/// Tiles per row in captured tile sheets.
+const SHEET_COLUMNS: usize = 16;
+
+/// Directory the HD pack for a ROM is loaded from.
+pub(crate) fn pack_dir(rom_path: &Path) -> Option<PathBuf> {
+    rom_path
+        .file_stem()
+        .map(|name| config_dir().join("hdpacks").join(name))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) enum Compare {
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+    GreaterEqual,
+    LessEqual,
+}
+
+impl Compare {
+    fn parse(op: &str) -> NesResult<Self> {
+        Ok(match op {
+            "==" => Self::Equal,
+            "!=" => Self::NotEqual,
+            ">" => Self::Greater,
+            "<" => Self::Less,
+            ">=" => Self::GreaterEqual,
+            "<=" => Self::LessEqual,
+            _ => bail!("invalid operator `{op}`"),
+        })
+    }
+
+    #[must_use]
+    const fn test(self, a: u8, b: u8) -> bool {
+        match self {
+            Self::Equal => a == b,
+            Self::NotEqual => a != b,
+            Self::Greater => a > b,
+            Self::Less => a < b,
+            Self::GreaterEqual => a >= b,
+            Self::LessEqual => a <= b,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) enum Condition {
+    MemoryConstant {
+        addr: u16,
+        op: Compare,
+        value: u8,
+    },
+    Memory {
+        addr: u16,
+        op: Compare,
+        other: u16,
+    },
+    FrameRange {
+        divisor: u32,
+        remainder: u32,
+    },
+    TileAtPosition {
+        x: u32,
+        y: u32,
+        tile: u64,
+        palette: u32,
+    },
+    FlipHorizontal,
+    FlipVertical,
+    BgPriority,
+}
+
+impl Condition {
+    fn parse(kind: &str, args: &[&str]) -> NesResult<Self> {
+        let condition = match (kind, args) {
+            ("memoryCheckConstant", [addr, op, value]) => Self::MemoryConstant {
+                addr: parse_hex(addr)?,
+                op: Compare::parse(op)?,
+                value: parse_hex(value)?,
+            },
+            ("memoryCheck", [addr, op, other]) => Self::Memory {
+                addr: parse_hex(addr)?,
+                op: Compare::parse(op)?,
+                other: parse_hex(other)?,
+            },
+            ("frameRange", [divisor, remainder]) => {
+                let divisor = parse_dec(divisor)?;
+                if divisor == 0 {
+                    bail!("frameRange divisor must be greater than 0");
+                }
+                Self::FrameRange {
+                    divisor,
+                    remainder: parse_dec(remainder)?,
+                }
+            }
+            ("tileAtPosition", [x, y, tile, palette]) => Self::TileAtPosition {
+                x: parse_dec(x)?,
+                y: parse_dec(y)?,
+                tile: parse_hex(tile)?,
+                palette: parse_hex(palette)?,
+            },
+            ("hmirror", []) => Self::FlipHorizontal,
+            ("vmirror", []) => Self::FlipVertical,
+            ("bgpriority", []) => Self::BgPriority,
+            _ => bail!("invalid condition `{kind}` with {} argument(s)", args.len()),
+        };
+        Ok(condition)
+    }
+
+    /// Whether this condition depends on the pixel being drawn rather than the whole frame.
+    #[must_use]
+    const fn is_per_pixel(&self) -> bool {
+        matches!(
+            self,
+            Self::FlipHorizontal | Self::FlipVertical | Self::BgPriority
+        )
+    }
+
+    #[must_use]
+    fn holds(&self, frame: &FrameState<'_>, pixel: TilePixel) -> bool {
+        match *self {
+            Self::MemoryConstant { addr, op, value } => op.test((frame.peek)(addr), value),
+            Self::Memory { addr, op, other } => op.test((frame.peek)(addr), (frame.peek)(other)),
+            Self::FrameRange { divisor, remainder } => frame.number % divisor >= remainder,
+            Self::TileAtPosition {
+                x,
+                y,
+                tile,
+                palette,
+            } => {
+                x < Ppu::WIDTH
+                    && y < Ppu::HEIGHT
+                    && frame
+                        .tiles
+                        .get((y * Ppu::WIDTH + x) as usize)
+                        .map_or(false, |pixel| {
+                            pixel.tile == tile && frame.palette_key(*pixel) == palette
+                        })
+            }
+            Self::FlipHorizontal => pixel.flip_horizontal(),
+            Self::FlipVertical => pixel.flip_vertical(),
+            Self::BgPriority => pixel.bg_priority(),
+        }
+    }
+}
+
+/// A replacement for a tile, used while its conditions hold.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct TileRule {
+    /// Condition indexes, and whether each is negated.
+    conditions: Vec<(usize, bool)>,
+    image: usize,
+    x: u32,
+    y: u32,
+}
+
+/// Replacement art loaded as RGBA pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct HdImage {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) pixels: Vec<u8>,
+}
+
+impl HdImage {
+    fn load(path: &Path) -> NesResult<Self> {
+        let image = Image::from_file(path).with_context(|| format!("failed to load {path:?}"))?;
+        let channels = image.format().channels();
+        let pixels = image
+            .as_bytes()
+            .chunks_exact(channels)
+            .flat_map(|pixel| match *pixel {
+                [red, green, blue, alpha, ..] => [red, green, blue, alpha],
+                [red, green, blue] => [red, green, blue, 255],
+                [luma, alpha] => [luma, luma, luma, alpha],
+                [luma] => [luma, luma, luma, 255],
+                _ => [0, 0, 0, 0],
+            })
+            .collect();
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            pixels,
+        })
+    }
+
+    #[must_use]
+    fn pixel(&self, x: u32, y: u32) -> Option<&[u8]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let offset = (y * self.width + x) as usize * CHANNELS;
+        self.pixels.get(offset..offset + CHANNELS)
+    }
+}
+
+/// State of the frame being drawn that conditions are checked against.
+pub(crate) struct FrameState<'a> {
+    number: u32,
+    tiles: &'a [TilePixel],
+    /// Colors of the 4 background and 4 sprite palettes, packed as keys.
+    palettes: [u32; 8],
+    peek: &'a dyn Fn(u16) -> u8,
+}
+
+impl FrameState<'_> {
+    /// Key of the palette a pixel is drawn with, its four colors packed high to low.
+    #[must_use]
+    fn palette_key(&self, pixel: TilePixel) -> u32 {
+        self.palettes[usize::from(pixel.palette & 0x07)]
+    }
+}
+
+/// Packs the colors of each palette into keys.
+#[must_use]
+fn palette_keys(ppu: &Ppu) -> [u32; 8] {
+    let mut keys = [0; 8];
+    for (palette, key) in (0..).zip(keys.iter_mut()) {
+        *key = (0..4).fold(0, |key, color| {
+            let addr = Ppu::PALETTE_START + palette * 4 + color;
+            (key << 8) | u32::from(ppu.peek(addr, Access::Dummy) & 0x3F)
+        });
+    }
+    keys
+}
+
+/// A parsed HD pack.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct HdPack {
+    pub(crate) scale: u32,
+    images: Vec<HdImage>,
+    conditions: Vec<Condition>,
+    tiles: HashMap<(u64, u32), Vec<TileRule>>,
+}
+
+impl HdPack {
+    /// Loads `hires.txt` and its images from a pack directory.
+    pub(crate) fn load(dir: &Path) -> NesResult<Self> {
+        let path = dir.join(DEFINITION_FILE);
+        let text = fs::read_to_string(&path).with_context(|| format!("failed to read {path:?}"))?;
+        Self::parse(&text, |name| HdImage::load(&dir.join(name)))
+            .with_context(|| format!("failed to parse {path:?}"))
+    }
+
+    /// Parses a pack definition, loading images by name with `load_image`.
+    pub(crate) fn parse(
+        text: &str,
+        mut load_image: impl FnMut(&str) -> NesResult<HdImage>,
+    ) -> NesResult<Self> {
+        let mut pack = Self {
+            scale: 1,
+            ..Self::default()
+        };
+        let mut names = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            pack.parse_line(line, &mut names, &mut load_image)
+                .with_context(|| format!("line {}", number + 1))?;
+        }
+        for image in &pack.images {
+            if image.width % (8 * pack.scale) != 0 || image.height % (8 * pack.scale) != 0 {
+                log::warn!(
+                    "HD pack image size {}x{} isn't a multiple of the tile size",
+                    image.width,
+                    image.height
+                );
+            }
+        }
+        Ok(pack)
+    }
+
+    fn parse_line(
+        &mut self,
+        line: &str,
+        names: &mut HashMap<String, usize>,
+        load_image: &mut impl FnMut(&str) -> NesResult<HdImage>,
+    ) -> NesResult<()> {
+        let (conditions, line) = match line.strip_prefix('[') {
+            Some(rest) => {
+                let (conditions, line) =
+                    rest.split_once(']').ok_or_else(|| anyhow!("missing `]`"))?;
+                let conditions = conditions
+                    .split('&')
+                    .map(|name| {
+                        let name = name.trim();
+                        let (name, negated) = match name.strip_prefix('!') {
+                            Some(name) => (name.trim(), true),
+                            None => (name, false),
+                        };
+                        names
+                            .get(name)
+                            .map(|&index| (index, negated))
+                            .ok_or_else(|| anyhow!("unknown condition `{name}`"))
+                    })
+                    .collect::<NesResult<Vec<_>>>()?;
+                (conditions, line.trim())
+            }
+            None => (vec![], line),
+        };
+        let (tag, value) = line
+            .strip_prefix('<')
+            .and_then(|line| line.split_once('>'))
+            .ok_or_else(|| anyhow!("expected a `<tag>`"))?;
+        if !conditions.is_empty() && tag != "tile" {
+            bail!("only `<tile>` can have conditions");
+        }
+        let args = value.split(',').map(str::trim).collect::<Vec<_>>();
+        match tag {
+            "ver" | "patch" | "options" => log::debug!("ignoring HD pack tag <{tag}>"),
+            "scale" => {
+                let scale = parse_dec(value)?;
+                if !(1..=MAX_SCALE).contains(&scale) {
+                    bail!("scale must be between 1 and {MAX_SCALE}");
+                }
+                self.scale = scale;
+            }
+            "img" => self.images.push(load_image(value.trim())?),
+            "condition" => {
+                let [name, kind, args @ ..] = args.as_slice() else {
+                    bail!("expected `<condition>name,type,...`");
+                };
+                if names.contains_key(*name) {
+                    bail!("condition `{name}` is already defined");
+                }
+                names.insert((*name).to_string(), self.conditions.len());
+                self.conditions.push(Condition::parse(kind, args)?);
+            }
+            "tile" => {
+                let [image, tile, palette, x, y, ..] = args.as_slice() else {
+                    bail!("expected `<tile>image,tile,palette,x,y`");
+                };
+                let image = parse_dec(image)? as usize;
+                if image >= self.images.len() {
+                    bail!("image {image} hasn't been defined");
+                }
+                self.tiles
+                    .entry((parse_hex(tile)?, parse_hex(palette)?))
+                    .or_default()
+                    .push(TileRule {
+                        conditions,
+                        image,
+                        x: parse_dec(x)?,
+                        y: parse_dec(y)?,
+                    });
+            }
+            _ => bail!("unknown tag <{tag}>"),
+        }
+        Ok(())
+    }
+
+    /// Number of tile replacements defined.
+    #[must_use]
+    pub(crate) fn len(&self) -> usize {
+        self.tiles.values().map(Vec::len).sum()
+    }
+
+    /// The replacement to draw for a pixel, if any.
+    fn find_rule(
+        &self,
+        frame: &FrameState<'_>,
+        frame_conditions: &[bool],
+        pixel: TilePixel,
+    ) -> Option<&TileRule> {
+        self.tiles
+            .get(&(pixel.tile, frame.palette_key(pixel)))?
+            .iter()
+            .find(|rule| {
+                rule.conditions.iter().all(|&(index, negated)| {
+                    let condition = &self.conditions[index];
+                    let holds = if condition.is_per_pixel() {
+                        condition.holds(frame, pixel)
+                    } else {
+                        frame_conditions[index]
+                    };
+                    holds != negated
+                })
+            })
+    }
+
+    /// Draws `frame`, an RGBA frame with rows `stride` bytes apart, scaled up into `output` with
+    /// replaced tiles blended over it.
+    pub(crate) fn compose(
+        &self,
+        frame: &[u8],
+        stride: usize,
+        state: &FrameState<'_>,
+        output: &mut Vec<u8>,
+    ) {
+        let scale = self.scale as usize;
+        let (width, height) = (Ppu::WIDTH as usize, Ppu::HEIGHT as usize);
+        let out_stride = width * scale * CHANNELS;
+        output.clear();
+        output.resize(out_stride * height * scale, 0);
+        // Conditions on the whole frame only need checking once
+        let frame_conditions = self
+            .conditions
+            .iter()
+            .map(|condition| {
+                !condition.is_per_pixel() && condition.holds(state, TilePixel::default())
+            })
+            .collect::<Vec<_>>();
+
+        for y in 0..height {
+            for x in 0..width {
+                let offset = y * stride + x * CHANNELS;
+                let Some(base) = frame.get(offset..offset + CHANNELS) else {
+                    continue;
+                };
+                let pixel = state.tiles.get(y * width + x).copied().unwrap_or_default();
+                let rule = if pixel.is_backdrop() {
+                    None
+                } else {
+                    self.find_rule(state, &frame_conditions, pixel)
+                };
+                for sub_y in 0..scale {
+                    let row = (y * scale + sub_y) * out_stride;
+                    for sub_x in 0..scale {
+                        let out = row + (x * scale + sub_x) * CHANNELS;
+                        let out = &mut output[out..out + CHANNELS];
+                        out.copy_from_slice(base);
+                        let Some(rule) = rule else {
+                            continue;
+                        };
+                        // Subpixels run across the screen, so flipped tiles read art backwards
+                        let art_x = if pixel.flip_horizontal() {
+                            scale - 1 - sub_x
+                        } else {
+                            sub_x
+                        };
+                        let art_y = if pixel.flip_vertical() {
+                            scale - 1 - sub_y
+                        } else {
+                            sub_y
+                        };
+                        let art = self.images[rule.image].pixel(
+                            rule.x + (usize::from(pixel.x) * scale + art_x) as u32,
+                            rule.y + (usize::from(pixel.y) * scale + art_y) as u32,
+                        );
+                        if let Some(art) = art {
+                            blend(out, art);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Blends an RGBA `src` pixel over `dst`.
+fn blend(dst: &mut [u8], src: &[u8]) {
+    let alpha = u16::from(src[3]);
+    for (dst, &src) in dst.iter_mut().zip(src).take(3) {
+        *dst = ((u16::from(src) * alpha + u16::from(*dst) * (255 - alpha)) / 255) as u8;
+    }
+}
+
+fn parse_hex<T: TryFrom<u64>>(value: &str) -> NesResult<T> {
+    let value = value.trim();
+    u64::from_str_radix(value, 16)
+        .ok()
+        .and_then(|parsed| T::try_from(parsed).ok())
+        .ok_or_else(|| anyhow!("invalid hex value `{value}`"))
+}
+
+fn parse_dec(value: &str) -> NesResult<u32> {
+    let value = value.trim();
+    value
+        .parse()
+        .map_err(|_| anyhow!("invalid number `{value}`"))
+}
+
+/// Unique tiles drawn in a frame with the frame's colors, for starting a pack.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct TileSheet {
+    /// Tile hash and palette key of each tile, in sheet order.
+    pub(crate) tiles: Vec<(u64, u32)>,
+    /// RGBA pixels, `SHEET_COLUMNS` tiles across.
+    pub(crate) pixels: Vec<u8>,
+}
+
+impl TileSheet {
+    /// Collects the tiles drawn in an RGBA `frame` with rows `stride` bytes apart.
+    pub(crate) fn capture(frame: &[u8], stride: usize, state: &FrameState<'_>) -> Self {
+        let width = Ppu::WIDTH as usize;
+        let mut seen = HashSet::new();
+        let mut tiles = vec![];
+        for pixel in state.tiles.iter().filter(|pixel| !pixel.is_backdrop()) {
+            let key = (pixel.tile, state.palette_key(*pixel));
+            if seen.insert(key) {
+                tiles.push(key);
+            }
+        }
+        let index = tiles
+            .iter()
+            .enumerate()
+            .map(|(i, &key)| (key, i))
+            .collect::<HashMap<_, _>>();
+
+        let sheet_width = SHEET_COLUMNS * 8;
+        let rows = (tiles.len() + SHEET_COLUMNS - 1) / SHEET_COLUMNS;
+        let mut pixels = vec![0; sheet_width * rows * 8 * CHANNELS];
+        for (i, pixel) in state.tiles.iter().enumerate() {
+            if pixel.is_backdrop() {
+                continue;
+            }
+            let (x, y) = (i % width, i / width);
+            let offset = y * stride + x * CHANNELS;
+            let Some(color) = frame.get(offset..offset + CHANNELS) else {
+                continue;
+            };
+            let tile = index[&(pixel.tile, state.palette_key(*pixel))];
+            let sheet_x = (tile % SHEET_COLUMNS) * 8 + usize::from(pixel.x & 0x07);
+            let sheet_y = (tile / SHEET_COLUMNS) * 8 + usize::from(pixel.y & 0x07);
+            let offset = (sheet_y * sheet_width + sheet_x) * CHANNELS;
+            pixels[offset..offset + CHANNELS].copy_from_slice(color);
+        }
+        Self { tiles, pixels }
+    }
+
+    #[must_use]
+    pub(crate) const fn width(&self) -> u32 {
+        (SHEET_COLUMNS * 8) as u32
+    }
+
+    #[must_use]
+    pub(crate) fn height(&self) -> u32 {
+        (self.pixels.len() / CHANNELS / (SHEET_COLUMNS * 8)) as u32
+    }
+
+    /// Pack definition lines placing each tile from the sheet, loaded as image `image`.
+    #[must_use]
+    pub(crate) fn definition(&self, image_name: &str) -> String {
+        let mut definition = format!("<scale>1\n<img>{image_name}\n");
+        for (i, (tile, palette)) in self.tiles.iter().enumerate() {
+            let (x, y) = ((i % SHEET_COLUMNS) * 8, (i / SHEET_COLUMNS) * 8);
+            definition += &format!("<tile>0,{tile:016X},{palette:08X},{x},{y}\n");
+        }
+        definition
+    }
+}
+
+/// The HD pack for the loaded game and the texture it's drawn to.
+#[derive(Default)]
+#[must_use]
+pub(crate) struct HdPackState {
+    pub(crate) pack: Option<HdPack>,
+    /// Textures created for each scale, reused when packs are reloaded.
+    textures: Vec<(u32, TextureId)>,
+    /// Copy of the emulated frame and its stride, taken so the console can be inspected while
+    /// drawing.
+    frame: Vec<u8>,
+    stride: usize,
+    output: Vec<u8>,
+    /// Frame number tile capture was requested at, until a whole frame has been captured.
+    capture_requested: Option<u32>,
+}
+
+impl std::fmt::Debug for HdPackState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HdPackState")
+            .field("scale", &self.pack.as_ref().map(|pack| pack.scale))
+            .field("tiles", &self.pack.as_ref().map(HdPack::len))
+            .field("capture_requested", &self.capture_requested)
+            .finish()
+    }
+}
+
+impl Nes {
+    /// Loads the HD pack for the loaded ROM, if enabled and one exists.
+    pub(crate) fn load_hd_pack(&mut self) {
+        self.hd_pack.pack = None;
+        let dir = pack_dir(&self.config.rom_path);
+        if self.config.hd_pack {
+            if let Some(dir) = dir.filter(|dir| dir.join(DEFINITION_FILE).exists()) {
+                match HdPack::load(&dir) {
+                    Ok(pack) => {
+                        self.add_message(format!("Loaded HD pack with {} tiles", pack.len()));
+                        self.hd_pack.pack = Some(pack);
+                    }
+                    Err(err) => {
+                        log::error!("{err:?}");
+                        self.add_message(format!("Failed to load HD pack: {}", err.root_cause()));
+                    }
+                }
+            }
+        }
+        self.update_tile_capture();
+    }
+
+    /// Captures tiles while an HD pack is drawn or tiles are about to be written out.
+    pub(crate) fn update_tile_capture(&mut self) {
+        let enabled = self.hd_pack.pack.is_some() || self.hd_pack.capture_requested.is_some();
+        let ppu = self.control_deck.ppu_mut();
+        if enabled != ppu.tile_capture_enabled() {
+            ppu.set_tile_capture_enabled(enabled);
+        }
+    }
+
+    /// Writes the tiles drawn in the next complete frame to the game's pack directory.
+    pub(crate) fn capture_hd_tiles(&mut self) {
+        if self.control_deck.loaded_rom().is_none() {
+            self.add_message("Load a ROM to capture tiles from");
+            return;
+        }
+        let frame = self.control_deck.frame_number();
+        // A frame captured since before capturing was enabled would be incomplete
+        let frame = if self.control_deck.ppu().tile_capture_enabled() {
+            frame.saturating_sub(1)
+        } else {
+            frame
+        };
+        self.hd_pack.capture_requested = Some(frame);
+        self.update_tile_capture();
+        if self.mode != Mode::Playing {
+            self.add_message("Tiles will be captured when the game is resumed");
+        }
+    }
+
+    /// Copies the emulated frame and calls `f` with the state conditions are checked against.
+    fn with_frame_state<T>(&mut self, f: impl FnOnce(&mut HdPackState, &FrameState<'_>) -> T) -> T {
+        let frame = self.control_deck.frame();
+        self.hd_pack.stride = frame.stride();
+        self.hd_pack.frame.clear();
+        self.hd_pack.frame.extend_from_slice(frame.pixels());
+        let cpu = self.control_deck.cpu();
+        let ppu = self.control_deck.ppu();
+        let peek = |addr| cpu.peek(addr, Access::Dummy);
+        let state = FrameState {
+            number: self.control_deck.frame_number(),
+            tiles: ppu.tile_capture(),
+            palettes: palette_keys(ppu),
+            peek: &peek,
+        };
+        f(&mut self.hd_pack, &state)
+    }
+
+    fn write_captured_tiles(&mut self) -> NesResult<PathBuf> {
+        let dir = pack_dir(&self.config.rom_path)
+            .ok_or_else(|| anyhow!("invalid rom path {:?}", self.config.rom_path))?;
+        let sheet = self.with_frame_state(|hd_pack, state| {
+            TileSheet::capture(&hd_pack.frame, hd_pack.stride, state)
+        });
+        if sheet.tiles.is_empty() {
+            bail!("no tiles were drawn");
+        }
+
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {dir:?}"))?;
+        let name = format!("capture-{}", self.control_deck.frame_number());
+        let image_path = dir.join(&name).with_extension("png");
+        Image::from_bytes(
+            sheet.width(),
+            sheet.height(),
+            &sheet.pixels,
+            PixelFormat::Rgba,
+        )
+        .and_then(|image| image.save(&image_path))
+        .with_context(|| format!("failed to save {image_path:?}"))?;
+        let image_name = image_path
+            .file_name()
+            .map_or_else(|| "unknown".into(), OsStr::to_string_lossy);
+        let definition_path = dir.join(&name).with_extension("txt");
+        fs::write(&definition_path, sheet.definition(&image_name))
+            .with_context(|| format!("failed to write {definition_path:?}"))?;
+        Ok(image_path)
+    }
+
+    /// Draws the frame with the HD pack into its own texture, returning the texture and scale to
+    /// draw instead of the emulated frame.
+    pub(crate) fn render_hd_pack(
+        &mut self,
+        s: &mut PixState,
+    ) -> PixResult<Option<(TextureId, u32)>> {
+        // Loading a save state replaces the PPU and stops capturing
+        self.update_tile_capture();
+        if let Some(requested) = self.hd_pack.capture_requested {
+            if self.control_deck.frame_number() > requested.saturating_add(1) {
+                self.hd_pack.capture_requested = None;
+                match self.write_captured_tiles() {
+                    Ok(path) => self.add_message(format!("Captured tiles to {path:?}")),
+                    Err(err) => {
+                        log::error!("{err:?}");
+                        self.add_message(format!("Failed to capture tiles: {}", err.root_cause()));
+                    }
+                }
+                self.update_tile_capture();
+            }
+        }
+
+        let Some(scale) = self.hd_pack.pack.as_ref().map(|pack| pack.scale) else {
+            return Ok(None);
+        };
+        let texture_id = match self
+            .hd_pack
+            .textures
+            .iter()
+            .find(|&&(texture_scale, _)| texture_scale == scale)
+        {
+            Some(&(_, texture_id)) => texture_id,
+            None => {
+                let texture_id =
+                    s.create_texture(Ppu::WIDTH * scale, Ppu::HEIGHT * scale, PixelFormat::Rgba)?;
+                self.hd_pack.textures.push((scale, texture_id));
+                texture_id
+            }
+        };
+        self.with_frame_state(|hd_pack, state| {
+            if let Some(ref pack) = hd_pack.pack {
+                pack.compose(&hd_pack.frame, hd_pack.stride, state, &mut hd_pack.output);
+            }
+        });
+        s.update_texture(
+            texture_id,
+            None,
+            &self.hd_pack.output,
+            (Ppu::WIDTH * scale) as usize * CHANNELS,
+        )?;
+        Ok(Some((texture_id, scale)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, color: [u8; 4]) -> HdImage {
+        HdImage {
+            width,
+            height,
+            pixels: color.repeat((width * height) as usize),
+        }
+    }
+
+    fn state<'a>(tiles: &'a [TilePixel], peek: &'a dyn Fn(u16) -> u8) -> FrameState<'a> {
+        FrameState {
+            number: 10,
+            tiles,
+            palettes: [0x0F16_2737; 8],
+            peek,
+        }
+    }
+
+    #[test]
+    fn parse_pack() {
+        let text = "\
+            # comment\n\
+            <ver>100\n\
+            <scale>2\n\
+            <img>tiles.png\n\
+            <condition>big,memoryCheckConstant,0754,==,00\n\
+            <condition>odd,frameRange,2,1\n\
+            [big&!odd]<tile>0,00000000000000AB,0F162737,16,0\n\
+            <tile>0,00000000000000AB,0F162737,0,0\n";
+        let mut loaded = vec![];
+        let pack = HdPack::parse(text, |name| {
+            loaded.push(name.to_string());
+            Ok(image(32, 16, [0; 4]))
+        })
+        .unwrap();
+        assert_eq!(loaded, ["tiles.png"]);
+        assert_eq!(pack.scale, 2);
+        assert_eq!(pack.len(), 2);
+        assert_eq!(
+            pack.conditions,
+            [
+                Condition::MemoryConstant {
+                    addr: 0x0754,
+                    op: Compare::Equal,
+                    value: 0x00,
+                },
+                Condition::FrameRange {
+                    divisor: 2,
+                    remainder: 1,
+                },
+            ]
+        );
+        let rules = &pack.tiles[&(0xAB, 0x0F16_2737)];
+        assert_eq!(rules[0].conditions, [(0, false), (1, true)]);
+        assert_eq!((rules[1].x, rules[1].y), (0, 0));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let load = |_: &str| Ok(image(16, 16, [0; 4]));
+        assert!(HdPack::parse("<scale>0", load).is_err());
+        assert!(HdPack::parse("<tile>0,AB,0F162737,0,0", load).is_err());
+        assert!(HdPack::parse("<img>a.png\n[missing]<tile>0,AB,0,0,0", load).is_err());
+        assert!(HdPack::parse("<condition>a,frameRange,0,0", load).is_err());
+        assert!(HdPack::parse("<condition>a,memoryCheck,10,=,11", load).is_err());
+        assert!(HdPack::parse("<condition>a,hmirror\n<condition>a,vmirror", load).is_err());
+        assert!(HdPack::parse("<unknown>", load).is_err());
+        let err = HdPack::parse("<scale>2\nnot a tag", load).unwrap_err();
+        assert_eq!(format!("{err:#}"), "line 2: expected a `<tag>`");
+    }
+
+    #[test]
+    fn conditions_pick_rule() {
+        let text = "\
+            <img>a.png\n\
+            <condition>flag,memoryCheckConstant,0010,>=,80\n\
+            <condition>flipped,hmirror\n\
+            [flag]<tile>0,AB,0F162737,8,0\n\
+            [flipped]<tile>0,AB,0F162737,16,0\n\
+            <tile>0,AB,0F162737,0,0\n";
+        let pack = HdPack::parse(text, |_| Ok(image(24, 8, [0; 4]))).unwrap();
+        let pixel = TilePixel {
+            tile: 0xAB,
+            ..TilePixel::default()
+        };
+        let flipped = TilePixel {
+            flags: TilePixel::SPRITE | TilePixel::FLIP_HORIZONTAL,
+            ..pixel
+        };
+        let tiles = [pixel];
+        let x_of = |peek: &dyn Fn(u16) -> u8, pixel| {
+            let state = state(&tiles, peek);
+            let frame_conditions = pack
+                .conditions
+                .iter()
+                .map(|condition| condition.holds(&state, TilePixel::default()))
+                .collect::<Vec<_>>();
+            pack.find_rule(&state, &frame_conditions, pixel)
+                .map(|rule| rule.x)
+        };
+        assert_eq!(x_of(&|_| 0x80, pixel), Some(8));
+        assert_eq!(x_of(&|_| 0x00, pixel), Some(0));
+        assert_eq!(x_of(&|_| 0x00, flipped), Some(16));
+        let other_palette = TilePixel {
+            palette: 1,
+            ..pixel
+        };
+        let state = FrameState {
+            palettes: [0x0F16_2737, 0x0F00_1020, 0, 0, 0, 0, 0, 0],
+            ..state(&tiles, &|_| 0)
+        };
+        assert!(pack
+            .find_rule(&state, &[false, false], other_palette)
+            .is_none());
+    }
+
+    #[test]
+    fn tile_at_position() {
+        let condition = Condition::TileAtPosition {
+            x: 1,
+            y: 0,
+            tile: 0xAB,
+            palette: 0x0F16_2737,
+        };
+        let mut tiles = vec![TilePixel::default(); 2];
+        assert!(!condition.holds(&state(&tiles, &|_| 0), TilePixel::default()));
+        tiles[1].tile = 0xAB;
+        assert!(condition.holds(&state(&tiles, &|_| 0), TilePixel::default()));
+    }
+
+    #[test]
+    fn compose_blends_scaled_art() {
+        let text = "<scale>2\n<img>a.png\n<tile>0,AB,0F162737,0,0\n";
+        let mut art = image(16, 16, [0, 0, 0, 0]);
+        // Opaque red in the top left of the art for tile pixel (0, 0), half transparent blue in
+        // the top right of the art for tile pixel (7, 0)
+        art.pixels[..4].copy_from_slice(&[255, 0, 0, 255]);
+        art.pixels[15 * 4..16 * 4].copy_from_slice(&[0, 0, 255, 128]);
+        let pack = HdPack::parse(text, |_| Ok(art.clone())).unwrap();
+
+        let width = Ppu::WIDTH as usize;
+        let frame = vec![100; width * 240 * 4];
+        let mut tiles = vec![TilePixel::default(); width * 240];
+        tiles[0] = TilePixel {
+            tile: 0xAB,
+            ..TilePixel::default()
+        };
+        // Flipped horizontally, so the art for pixel 7 is read right to left
+        tiles[1] = TilePixel {
+            tile: 0xAB,
+            x: 7,
+            flags: TilePixel::SPRITE | TilePixel::FLIP_HORIZONTAL,
+            ..TilePixel::default()
+        };
+        let mut output = vec![];
+        pack.compose(&frame, width * 4, &state(&tiles, &|_| 0), &mut output);
+        assert_eq!(output.len(), width * 2 * 240 * 2 * 4);
+        assert_eq!(output[..4], [255, 0, 0, 255]);
+        assert_eq!(output[4..8], [100, 100, 100, 100]);
+        assert_eq!(output[8..12], [49, 49, 177, 100]);
+        assert_eq!(output[12..16], [100, 100, 100, 100]);
+    }
+
+    #[test]
+    fn capture_tile_sheet() {
+        let width = Ppu::WIDTH as usize;
+        let mut frame = vec![0; width * 240 * 4];
+        frame[4..8].copy_from_slice(&[1, 2, 3, 255]);
+        let mut tiles = vec![TilePixel::default(); width * 240];
+        for (x, pixel) in tiles.iter_mut().take(16).enumerate() {
+            *pixel = TilePixel {
+                tile: 0xAB + (x / 8) as u64,
+                x: (x % 8) as u8,
+                ..TilePixel::default()
+            };
+        }
+        let sheet = TileSheet::capture(&frame, width * 4, &state(&tiles, &|_| 0));
+        assert_eq!(sheet.tiles, [(0xAB, 0x0F16_2737), (0xAC, 0x0F16_2737)]);
+        assert_eq!((sheet.width(), sheet.height()), (128, 8));
+        assert_eq!(sheet.pixels[4..8], [1, 2, 3, 255]);
+        assert_eq!(
+            sheet.definition("capture-1.png"),
+            "<scale>1\n<img>capture-1.png\n\
+            <tile>0,00000000000000AB,0F162737,0,0\n\
+            <tile>0,00000000000000AC,0F162737,8,0\n"
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! Console settings a replay depends on besides its input: the power-up RAM contents and the
+//! CPU/PPU alignment.
+//!
+//! Replays record them in their header so a run plays back the same on any machine, and they're
+//! applied automatically while a replay plays back. The configured settings are put back when
+//! playback stops. Replays recorded with `Random` RAM can't reproduce a power cycle, so use
+//! another RAM state for verifiable recordings.
+
+use crate::{mem::RamState, nes::Nes};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub(crate) struct InitialState {
+    pub(crate) ram_state: RamState,
+    pub(crate) ppu_alignment: u8,
+}
+
+impl fmt::Display for InitialState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.ram_state {
+            RamState::Seeded(seed) => write!(f, "RAM {} {seed}", self.ram_state.as_ref())?,
+            state => write!(f, "RAM {}", state.as_ref())?,
+        }
+        write!(f, ", CPU/PPU Alignment {}", self.ppu_alignment)
+    }
+}
+
+impl Nes {
+    /// The initial state replays recorded now depend on.
+    pub(crate) fn initial_state(&self) -> InitialState {
+        InitialState {
+            ram_state: self.control_deck.ram_state(),
+            ppu_alignment: self.control_deck.ppu_alignment(),
+        }
+    }
+
+    pub(crate) fn apply_initial_state(&mut self, initial: InitialState) {
+        self.control_deck.set_ram_state(initial.ram_state);
+        self.control_deck.set_ppu_alignment(initial.ppu_alignment);
+    }
+
+    /// Puts back the configured initial state after playing back a replay.
+    pub(crate) fn restore_initial_state(&mut self) {
+        self.apply_initial_state(InitialState {
+            ram_state: self.config.ram_state,
+            ppu_alignment: self.config.ppu_alignment,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn initial_state_display() {
+        let initial = InitialState {
+            ram_state: RamState::Seeded(42),
+            ppu_alignment: 2,
+        };
+        assert_eq!(
+            initial.to_string(),
+            "RAM Random with Seed 42, CPU/PPU Alignment 2"
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Echoes the joypad state of every emulated frame as line-delimited JSON, so external programs
+//! like input displays or agents can follow along without the JSON-RPC control server.
+//!
+//! Each line looks like `{"frame":120,"joypads":[{"bits":129,"buttons":["A","Right"]},...]}`,
+//! with `bits` in the order the game reads them: A, B, Select, Start, Up, Down, Left, Right.
+//! Lines are written on a worker thread so a slow reader, or a named pipe nobody has opened yet,
+//! doesn't stall emulation. Lines that don't fit in the backlog are dropped.
+
+use crate::{
+    input::{FourPlayer, Joypad, JoypadBtnState, Slot},
+    nes::Nes,
+    NesResult,
+};
+use anyhow::Context;
+use serde::Serialize;
+use std::{
+    fs::OpenOptions,
+    io::{self, Write},
+    path::Path,
+    sync::mpsc::{sync_channel, SyncSender, TrySendError},
+    thread,
+};
+
+/// Target that writes to stdout instead of a file or named pipe.
+const STDOUT: &str = "-";
+/// Lines buffered for the writer before new ones are dropped, about 10 seconds of frames.
+const BACKLOG: usize = 600;
+
+const SLOTS: [Slot; 4] = [Slot::One, Slot::Two, Slot::Three, Slot::Four];
+
+const BUTTONS: [(&str, JoypadBtnState); 8] = [
+    ("A", JoypadBtnState::A),
+    ("B", JoypadBtnState::B),
+    ("Select", JoypadBtnState::SELECT),
+    ("Start", JoypadBtnState::START),
+    ("Up", JoypadBtnState::UP),
+    ("Down", JoypadBtnState::DOWN),
+    ("Left", JoypadBtnState::LEFT),
+    ("Right", JoypadBtnState::RIGHT),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct JoypadEcho {
+    bits: u8,
+    buttons: Vec<&'static str>,
+}
+
+impl From<&Joypad> for JoypadEcho {
+    fn from(joypad: &Joypad) -> Self {
+        let mut bits = 0;
+        let mut buttons = vec![];
+        for (i, (name, button)) in BUTTONS.into_iter().enumerate() {
+            if joypad.button(button) {
+                bits |= 1 << i;
+                buttons.push(name);
+            }
+        }
+        Self { bits, buttons }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct FrameEcho {
+    frame: u32,
+    joypads: Vec<JoypadEcho>,
+}
+
+#[derive(Debug)]
+#[must_use]
+pub(crate) struct InputEcho {
+    lines: SyncSender<String>,
+}
+
+impl InputEcho {
+    /// Starts writing lines to `target`, a file or named pipe, or stdout for `-`.
+    ///
+    /// # Errors
+    ///
+    /// If the writer thread fails to start, an error is returned.
+    pub(crate) fn spawn(target: &Path) -> NesResult<Self> {
+        let (lines, line_rx) = sync_channel::<String>(BACKLOG);
+        let target = target.to_path_buf();
+        thread::Builder::new()
+            .name("input echo".into())
+            .spawn(move || {
+                // Opening a named pipe blocks until a reader opens it
+                let writer: io::Result<Box<dyn Write>> = if target == Path::new(STDOUT) {
+                    Ok(Box::new(io::stdout()))
+                } else {
+                    OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(&target)
+                        .map(|file| Box::new(file) as Box<dyn Write>)
+                };
+                let mut writer = match writer {
+                    Ok(writer) => writer,
+                    Err(err) => {
+                        log::error!("failed to open input echo {target:?}: {err:?}");
+                        return;
+                    }
+                };
+                for line in line_rx {
+                    if let Err(err) = writeln!(writer, "{line}").and_then(|_| writer.flush()) {
+                        log::warn!("stopped input echo to {target:?}: {err:?}");
+                        break;
+                    }
+                }
+            })
+            .context("failed to start input echo")?;
+        Ok(Self { lines })
+    }
+}
+
+impl Nes {
+    /// Echoes the joypad state for frames `start` up to `end`. Frames run in the same update all
+    /// had the same input.
+    pub(crate) fn echo_input(&mut self, start: u32, end: u32) {
+        let Some(ref echo) = self.input_echo else {
+            return;
+        };
+        let players = if self.config.four_player == FourPlayer::Disabled {
+            2
+        } else {
+            4
+        };
+        let joypads = SLOTS
+            .into_iter()
+            .take(players)
+            .map(|slot| JoypadEcho::from(self.control_deck.joypad(slot)))
+            .collect::<Vec<_>>();
+        for frame in start..end {
+            let line = serde_json::to_string(&FrameEcho {
+                frame,
+                joypads: joypads.clone(),
+            })
+            .expect("valid input echo");
+            match echo.lines.try_send(line) {
+                Ok(()) | Err(TrySendError::Full(_)) => (),
+                Err(TrySendError::Disconnected(_)) => {
+                    self.input_echo = None;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_echo_json() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(JoypadBtnState::A, true);
+        joypad.set_button(JoypadBtnState::RIGHT, true);
+        let echo = FrameEcho {
+            frame: 120,
+            joypads: vec![JoypadEcho::from(&joypad), JoypadEcho::from(&Joypad::new())],
+        };
+        assert_eq!(
+            serde_json::to_string(&echo).expect("valid json"),
+            r#"{"frame":120,"joypads":[{"bits":129,"buttons":["A","Right"]},{"bits":0,"buttons":[]}]}"#
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Player One's input driving a second pad, for playing games that need two controllers alone.
+//!
+//! With `Both Pads`, every button Player One presses is pressed on Player Two's pad as well. With
+//! `Alternate Pads`, Player One's input goes to one pad at a time, switched with `Switch Shared
+//! Pad`. Player Two's own bindings keep working either way.
+
+use crate::{
+    input::{JoypadBtnState, Slot},
+    nes::Nes,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub(crate) enum InputSharing {
+    #[default]
+    Off,
+    Both,
+    Alternate,
+}
+
+impl InputSharing {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[Self::Off, Self::Both, Self::Alternate]
+    }
+
+    /// Pads driven by input for `slot`, where `active` is the pad Player One drives while
+    /// alternating.
+    pub(crate) const fn targets(self, slot: Slot, active: Slot) -> (Slot, Option<Slot>) {
+        match (self, slot) {
+            (Self::Both, Slot::One) => (Slot::One, Some(Slot::Two)),
+            (Self::Alternate, Slot::One) => (active, None),
+            _ => (slot, None),
+        }
+    }
+}
+
+impl AsRef<str> for InputSharing {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Off => "Off",
+            Self::Both => "Both Pads",
+            Self::Alternate => "Alternate Pads",
+        }
+    }
+}
+
+impl From<usize> for InputSharing {
+    fn from(value: usize) -> Self {
+        match value {
+            1 => Self::Both,
+            2 => Self::Alternate,
+            _ => Self::Off,
+        }
+    }
+}
+
+impl Nes {
+    /// Switches which pad Player One drives while alternating pads.
+    pub(crate) fn switch_shared_pad(&mut self) {
+        if self.config.input_sharing != InputSharing::Alternate {
+            self.add_message("Set Share Player 1 Input to Alternate Pads to switch pads");
+            return;
+        }
+        let prev = self.shared_pad;
+        self.shared_pad = match prev {
+            Slot::One => Slot::Two,
+            _ => Slot::One,
+        };
+        // Buttons held on the pad being left would otherwise stay down
+        self.release_pad(prev);
+        self.add_message(format!(
+            "Player 1 controls Pad {}",
+            self.shared_pad as usize + 1
+        ));
+    }
+
+    /// Releases every button and turbo button on a pad.
+    pub(crate) fn release_pad(&mut self, slot: Slot) {
+        self.turbo.release(slot);
+        self.control_deck
+            .joypad_mut(slot)
+            .set_button(JoypadBtnState::all(), false);
+    }
+
+    /// Releases pads that stop being shared when the sharing mode changes.
+    pub(crate) fn set_input_sharing(&mut self, sharing: InputSharing) {
+        if sharing == self.config.input_sharing {
+            return;
+        }
+        self.config.input_sharing = sharing;
+        self.release_pad(Slot::Two);
+        self.shared_pad = Slot::One;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sharing_targets() {
+        assert_eq!(
+            InputSharing::Off.targets(Slot::One, Slot::Two),
+            (Slot::One, None)
+        );
+        assert_eq!(
+            InputSharing::Both.targets(Slot::One, Slot::One),
+            (Slot::One, Some(Slot::Two))
+        );
+        assert_eq!(
+            InputSharing::Alternate.targets(Slot::One, Slot::Two),
+            (Slot::Two, None)
+        );
+        // Other players are never redirected
+        assert_eq!(
+            InputSharing::Both.targets(Slot::Two, Slot::One),
+            (Slot::Two, None)
+        );
+        assert_eq!(
+            InputSharing::Alternate.targets(Slot::Three, Slot::Two),
+            (Slot::Three, None)
+        );
+    }
+}
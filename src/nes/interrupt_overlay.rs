@@ -0,0 +1,88 @@
+//! Developer overlay showing the latency between an interrupt being asserted and the CPU entering
+//! its handler, for tuning timed IRQ raster effects that land a few pixels off.
+
+use crate::{
+    cpu::interrupt::InterruptKind,
+    nes::{Nes, NES_FRAME_SRC},
+    ppu::Ppu,
+};
+use pix_engine::prelude::*;
+
+impl Nes {
+    pub(crate) fn toggle_interrupt_overlay(&mut self) {
+        self.interrupt_overlay = !self.interrupt_overlay;
+        self.control_deck
+            .cpu_mut()
+            .set_interrupt_latency_enabled(self.interrupt_overlay);
+        self.add_message(if self.interrupt_overlay {
+            "Interrupt Overlay Enabled"
+        } else {
+            "Interrupt Overlay Disabled"
+        });
+    }
+
+    /// Marks where each interrupt was asserted and handled during the last frame, joined by a
+    /// line labeled with the latency in CPU cycles and PPU dots. Interrupts outside the visible
+    /// scanlines, such as the vblank NMI, are listed at the bottom of the frame.
+    pub(crate) fn render_interrupt_overlay(&mut self, s: &mut PixState) -> PixResult<()> {
+        if !self.interrupt_overlay {
+            return Ok(());
+        }
+        // Loading a save state replaces the CPU and stops recording
+        let cpu = self.control_deck.cpu_mut();
+        if !cpu.interrupt_latency_enabled() {
+            cpu.set_interrupt_latency_enabled(true);
+        }
+
+        let width = s.width()? as f32;
+        let height = s.height()? as f32;
+        let x_scale = width / Ppu::WIDTH as f32;
+        let y_scale = height / NES_FRAME_SRC.height() as f32;
+        let visible = NES_FRAME_SRC.top() as u32..NES_FRAME_SRC.bottom() as u32;
+        let position = |scanline: u32, dot: u32| {
+            let x = (dot.min(Ppu::WIDTH - 1) as f32 * x_scale) as i32;
+            let y = ((scanline as i32 - NES_FRAME_SRC.top()) as f32 * y_scale) as i32;
+            point![x, y]
+        };
+
+        s.push();
+        let mut offscreen = vec![];
+        for latency in self.control_deck.cpu().interrupt_latencies() {
+            let color = match latency.kind {
+                InterruptKind::Irq => Color::YELLOW,
+                InterruptKind::Nmi => Color::CYAN,
+            };
+            let label = format!(
+                "{} +{} cycles ({} dots)",
+                latency.kind.as_ref(),
+                latency.cycles(),
+                latency.dots()
+            );
+            let (asserted, handled) = (latency.asserted, latency.handled);
+            if !visible.contains(&asserted.scanline) || !visible.contains(&handled.scanline) {
+                offscreen.push((color, format!("{label} at line {}", asserted.scanline)));
+                continue;
+            }
+            let start = position(asserted.scanline, asserted.dot);
+            let end = position(handled.scanline, handled.dot);
+            s.stroke(color);
+            s.fill(None);
+            s.circle([start.x(), start.y(), 3])?;
+            s.line([start, end])?;
+            s.rect([end.x() - 2, end.y() - 2, 5, 5])?;
+            s.stroke(None);
+            s.fill(color);
+            s.set_cursor_pos([4, end.y() + 2]);
+            s.text(&label)?;
+        }
+        let mut y = height as i32;
+        for (color, label) in offscreen.iter().rev() {
+            y -= s.size_of(label)?.1 as i32 + 2;
+            s.fill(*color);
+            s.set_cursor_pos([4, y]);
+            s.text(label)?;
+        }
+        s.pop();
+        Ok(())
+    }
+}
@@ -0,0 +1,529 @@
+//! In-app editing of input bindings.
+//!
+//! Bindings are edited per player from the Keybindings menu. Clicking "Bind" waits for the next
+//! key, controller button or axis and binds it to that action. Bindings that would trigger more
+//! than one action are reported as conflicts so they can be removed. Keys and buttons used to
+//! navigate menus are bound the same way from player one's tab.
+//!
+//! Complete sets of bindings can also be exported to and imported from a standalone JSON file to
+//! back them up or share layouts, independent of the rest of the configuration.
+
+use crate::{
+    common::config_dir,
+    input::Slot,
+    nes::{
+        config::{Config, DEFAULT_CONFIG},
+        event::{
+            Action, AxisDirection, ControllerAxisBinding, ControllerButtonBinding, Input,
+            InputBindings, InputMapping, KeyBinding, MouseBinding,
+        },
+        menu_nav::{NavAction, NavBindings, NavInput},
+        Nes,
+    },
+    NesResult,
+};
+use anyhow::Context;
+use pix_engine::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+/// Default file name for exported bindings.
+const BINDINGS_FILE: &str = "bindings.json";
+
+/// Minimum axis value that binds an axis while capturing, so resting sticks aren't picked up.
+const AXIS_THRESHOLD: i32 = i16::MAX as i32 / 2;
+
+#[derive(Debug, Clone)]
+#[must_use]
+pub(crate) struct KeybindEditor {
+    /// Action waiting for an input to bind to.
+    pub(crate) capturing: Option<(Slot, Action)>,
+    /// Menu navigation action waiting for a key or button to bind to.
+    pub(crate) capturing_nav: Option<NavAction>,
+    /// Modifier key held while capturing, bound on release if no other key is pressed.
+    pending_modifier: Option<KeyMod>,
+    pub(crate) profile_name: String,
+    pub(crate) selected_profile: usize,
+    /// File bindings are imported from and exported to.
+    pub(crate) file_path: String,
+}
+
+impl Default for KeybindEditor {
+    fn default() -> Self {
+        Self {
+            capturing: None,
+            capturing_nav: None,
+            pending_modifier: None,
+            profile_name: String::new(),
+            selected_profile: 0,
+            file_path: config_dir().join(BINDINGS_FILE).display().to_string(),
+        }
+    }
+}
+
+impl Input {
+    #[must_use]
+    pub(crate) const fn slot(&self) -> Slot {
+        match self {
+            Self::Key((slot, ..))
+            | Self::Button((slot, _))
+            | Self::Axis((slot, ..))
+            | Self::Mouse((slot, _)) => *slot,
+        }
+    }
+
+    /// Inputs that can only trigger one action. Keys are checked for every player in turn, so the
+    /// same key bound for two players conflicts.
+    const fn conflict_group(self) -> Self {
+        match self {
+            Self::Key((_, key, keymod)) => Self::Key((Slot::One, key, keymod)),
+            _ => self,
+        }
+    }
+}
+
+impl InputBindings {
+    /// Bindings from the default configuration.
+    pub(crate) fn defaults() -> Self {
+        serde_json::from_slice::<Config>(DEFAULT_CONFIG)
+            .map(|config| config.bindings)
+            .unwrap_or_default()
+    }
+
+    /// Reads a complete set of bindings from a JSON file.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be opened or isn't a valid set of bindings, an error is returned.
+    pub(crate) fn load(path: impl AsRef<Path>) -> NesResult<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("invalid bindings in {path:?}"))
+    }
+
+    /// Writes the complete set of bindings to a JSON file.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be created or written, an error is returned.
+    pub(crate) fn save(&self, path: impl AsRef<Path>) -> NesResult<()> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("failed to create {path:?}"))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self)
+            .with_context(|| format!("failed to write bindings to {path:?}"))
+    }
+
+    /// Every binding as an input and the action it triggers.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (Input, Action)> + '_ {
+        let keys = self.keys.iter().map(|bind| {
+            (
+                Input::Key((bind.player, bind.key, bind.keymod)),
+                bind.action,
+            )
+        });
+        let mouse = self
+            .mouse
+            .iter()
+            .map(|bind| (Input::Mouse((bind.player, bind.button)), bind.action));
+        let buttons = self
+            .buttons
+            .iter()
+            .map(|bind| (Input::Button((bind.player, bind.button)), bind.action));
+        let axes = self.axes.iter().map(|bind| {
+            (
+                Input::Axis((bind.player, bind.axis, bind.direction)),
+                bind.action,
+            )
+        });
+        keys.chain(mouse).chain(buttons).chain(axes)
+    }
+
+    /// Inputs bound to `action` for `slot`.
+    pub(crate) fn inputs(&self, slot: Slot, action: Action) -> Vec<Input> {
+        self.iter()
+            .filter(|&(input, bound)| bound == action && input.slot() == slot)
+            .map(|(input, _)| input)
+            .collect()
+    }
+
+    pub(crate) fn bind(&mut self, input: Input, action: Action) {
+        fn push<T: PartialEq>(binds: &mut Vec<T>, bind: T) {
+            if !binds.contains(&bind) {
+                binds.push(bind);
+            }
+        }
+        match input {
+            Input::Key((player, key, keymod)) => push(
+                &mut self.keys,
+                KeyBinding {
+                    player,
+                    key,
+                    keymod,
+                    action,
+                },
+            ),
+            Input::Mouse((player, button)) => push(
+                &mut self.mouse,
+                MouseBinding {
+                    player,
+                    button,
+                    action,
+                },
+            ),
+            Input::Button((player, button)) => push(
+                &mut self.buttons,
+                ControllerButtonBinding {
+                    player,
+                    button,
+                    action,
+                },
+            ),
+            Input::Axis((player, axis, direction)) => push(
+                &mut self.axes,
+                ControllerAxisBinding {
+                    player,
+                    axis,
+                    direction,
+                    action,
+                },
+            ),
+        }
+    }
+
+    pub(crate) fn unbind(&mut self, input: Input, action: Action) {
+        match input {
+            Input::Key((player, key, keymod)) => self.keys.retain(|bind| {
+                (bind.player, bind.key, bind.keymod, bind.action) != (player, key, keymod, action)
+            }),
+            Input::Mouse((player, button)) => self
+                .mouse
+                .retain(|bind| (bind.player, bind.button, bind.action) != (player, button, action)),
+            Input::Button((player, button)) => self
+                .buttons
+                .retain(|bind| (bind.player, bind.button, bind.action) != (player, button, action)),
+            Input::Axis((player, axis, direction)) => self.axes.retain(|bind| {
+                (bind.player, bind.axis, bind.direction, bind.action)
+                    != (player, axis, direction, action)
+            }),
+        }
+    }
+
+    /// Inputs bound more than once where only one binding can ever trigger.
+    pub(crate) fn conflicts(&self) -> HashSet<Input> {
+        let mut bound = HashMap::<Input, HashSet<(Slot, Action)>>::new();
+        for (input, action) in self.iter() {
+            bound
+                .entry(input.conflict_group())
+                .or_default()
+                .insert((input.slot(), action));
+        }
+        self.iter()
+            .map(|(input, _)| input)
+            .filter(|input| bound[&input.conflict_group()].len() > 1)
+            .collect()
+    }
+
+    /// Replaces the bindings for `slot` with those from `defaults`.
+    pub(crate) fn reset_slot(&mut self, slot: Slot, defaults: &Self) {
+        self.keys.retain(|bind| bind.player != slot);
+        self.mouse.retain(|bind| bind.player != slot);
+        self.buttons.retain(|bind| bind.player != slot);
+        self.axes.retain(|bind| bind.player != slot);
+        self.keys
+            .extend(defaults.keys.iter().filter(|bind| bind.player == slot));
+        self.mouse
+            .extend(defaults.mouse.iter().filter(|bind| bind.player == slot));
+        self.buttons
+            .extend(defaults.buttons.iter().filter(|bind| bind.player == slot));
+        self.axes
+            .extend(defaults.axes.iter().filter(|bind| bind.player == slot));
+    }
+
+    pub(crate) fn to_mapping(&self) -> InputMapping {
+        let mut mapping = InputMapping::default();
+        mapping.extend(self.iter());
+        mapping
+    }
+}
+
+const fn is_modifier(key: Key) -> bool {
+    matches!(
+        key,
+        Key::LShift
+            | Key::RShift
+            | Key::LCtrl
+            | Key::RCtrl
+            | Key::LAlt
+            | Key::RAlt
+            | Key::LGui
+            | Key::RGui
+    )
+}
+
+impl Nes {
+    pub(crate) fn start_binding(&mut self, slot: Slot, action: Action) {
+        self.keybinds.capturing = Some((slot, action));
+        self.keybinds.pending_modifier = None;
+    }
+
+    pub(crate) fn start_nav_binding(&mut self, action: NavAction) {
+        self.cancel_binding();
+        self.keybinds.capturing_nav = Some(action);
+    }
+
+    pub(crate) fn cancel_binding(&mut self) {
+        self.keybinds.capturing = None;
+        self.keybinds.capturing_nav = None;
+        self.keybinds.pending_modifier = None;
+    }
+
+    /// Binds a key while waiting for input. Returns whether the event was consumed.
+    pub(crate) fn capture_key(&mut self, event: KeyEvent, pressed: bool) -> bool {
+        let cancel =
+            self.config.nav_bindings.action(NavInput::Key(event.key)) == Some(NavAction::Cancel);
+        if let Some(action) = self.keybinds.capturing_nav {
+            if pressed && !event.repeat && !is_modifier(event.key) {
+                if cancel && action != NavAction::Cancel {
+                    self.cancel_binding();
+                } else {
+                    self.finish_nav_binding(NavInput::Key(event.key), action);
+                }
+            }
+            return true;
+        }
+        let Some((slot, _)) = self.keybinds.capturing else {
+            return false;
+        };
+        if event.repeat {
+            return true;
+        }
+        if is_modifier(event.key) {
+            if pressed {
+                self.keybinds.pending_modifier = Some(event.keymod);
+            } else if let Some(keymod) = self.keybinds.pending_modifier.take() {
+                // A held modifier reports itself in `keymod`, so bind both press and release
+                self.finish_binding(&[
+                    Input::Key((slot, event.key, keymod)),
+                    Input::Key((slot, event.key, KeyMod::empty())),
+                ]);
+            }
+        } else if pressed {
+            if cancel {
+                self.cancel_binding();
+            } else {
+                self.finish_binding(&[Input::Key((slot, event.key, event.keymod))]);
+            }
+        }
+        true
+    }
+
+    /// Binds a controller button while waiting for input. Returns whether the event was consumed.
+    pub(crate) fn capture_button(&mut self, button: ControllerButton, pressed: bool) -> bool {
+        if let Some(action) = self.keybinds.capturing_nav {
+            if pressed {
+                self.finish_nav_binding(NavInput::Button(button), action);
+            }
+            return true;
+        }
+        let Some((slot, _)) = self.keybinds.capturing else {
+            return false;
+        };
+        if pressed {
+            self.finish_binding(&[Input::Button((slot, button))]);
+        }
+        true
+    }
+
+    /// Binds a controller axis while waiting for input. Returns whether the event was consumed.
+    pub(crate) fn capture_axis(&mut self, axis: Axis, value: i32) -> bool {
+        let Some((slot, _)) = self.keybinds.capturing else {
+            return false;
+        };
+        if value.abs() >= AXIS_THRESHOLD {
+            let direction = if value > 0 {
+                AxisDirection::Positive
+            } else {
+                AxisDirection::Negative
+            };
+            self.finish_binding(&[Input::Axis((slot, axis, direction))]);
+        }
+        true
+    }
+
+    fn finish_binding(&mut self, inputs: &[Input]) {
+        if let Some((_, action)) = self.keybinds.capturing {
+            for &input in inputs {
+                self.config.bindings.bind(input, action);
+            }
+            self.update_input_map();
+        }
+        self.cancel_binding();
+    }
+
+    fn finish_nav_binding(&mut self, input: NavInput, action: NavAction) {
+        self.config.nav_bindings.bind(input, action);
+        self.cancel_binding();
+    }
+
+    pub(crate) fn unbind_nav(&mut self, input: NavInput) {
+        self.config.nav_bindings.unbind(input);
+    }
+
+    pub(crate) fn reset_nav_bindings(&mut self) {
+        self.config.nav_bindings = NavBindings::default();
+        self.add_message("Reset menu navigation bindings to defaults");
+    }
+
+    pub(crate) fn unbind(&mut self, input: Input, action: Action) {
+        self.config.bindings.unbind(input, action);
+        self.update_input_map();
+    }
+
+    pub(crate) fn reset_bindings(&mut self, slot: Slot) {
+        self.config
+            .bindings
+            .reset_slot(slot, &InputBindings::defaults());
+        self.update_input_map();
+        self.add_message(format!("Reset {slot:?} bindings to defaults"));
+    }
+
+    pub(crate) fn save_binding_profile(&mut self) {
+        let name = self.keybinds.profile_name.trim().to_string();
+        if name.is_empty() {
+            self.add_message("Enter a name for the binding profile");
+            return;
+        }
+        self.config
+            .binding_profiles
+            .insert(name.clone(), self.config.bindings.clone());
+        self.keybinds.selected_profile = self
+            .config
+            .binding_profiles
+            .keys()
+            .position(|profile| *profile == name)
+            .unwrap_or_default();
+        self.add_message(format!("Saved binding profile {name}"));
+    }
+
+    pub(crate) fn load_binding_profile(&mut self) {
+        if let Some((name, bindings)) = self
+            .config
+            .binding_profiles
+            .iter()
+            .nth(self.keybinds.selected_profile)
+        {
+            self.config.bindings = bindings.clone();
+            self.keybinds.profile_name = name.clone();
+            self.add_message(format!("Loaded binding profile {name}"));
+            self.update_input_map();
+        }
+    }
+
+    pub(crate) fn delete_binding_profile(&mut self) {
+        if let Some(name) = self
+            .config
+            .binding_profiles
+            .keys()
+            .nth(self.keybinds.selected_profile)
+            .cloned()
+        {
+            self.config.binding_profiles.remove(&name);
+            self.keybinds.selected_profile = self.keybinds.selected_profile.saturating_sub(1);
+            self.add_message(format!("Deleted binding profile {name}"));
+        }
+    }
+
+    pub(crate) fn export_bindings(&mut self) {
+        let path = self.keybinds.file_path.trim().to_string();
+        match self.config.bindings.save(&path) {
+            Ok(()) => self.add_message(format!("Exported bindings to {path}")),
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message(format!("Failed to export bindings to {path}"));
+            }
+        }
+    }
+
+    pub(crate) fn import_bindings(&mut self) {
+        let path = self.keybinds.file_path.trim().to_string();
+        match InputBindings::load(&path) {
+            Ok(bindings) => {
+                self.config.bindings = bindings;
+                self.update_input_map();
+                self.add_message(format!("Imported bindings from {path}"));
+            }
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message(format!("Failed to import bindings from {path}"));
+            }
+        }
+    }
+
+    fn update_input_map(&mut self) {
+        self.config.input_map = self.config.bindings.to_mapping();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::JoypadBtn;
+
+    #[test]
+    fn bind_and_detect_conflicts() {
+        let mut bindings = InputBindings::default();
+        let a = Action::Joypad(JoypadBtn::A);
+        let b = Action::Joypad(JoypadBtn::B);
+        let key_z = |slot| Input::Key((slot, Key::Z, KeyMod::empty()));
+
+        bindings.bind(key_z(Slot::One), a);
+        bindings.bind(key_z(Slot::One), a);
+        assert_eq!(bindings.inputs(Slot::One, a), vec![key_z(Slot::One)]);
+        assert!(bindings.conflicts().is_empty());
+
+        // Keys are shared between players
+        bindings.bind(key_z(Slot::Two), b);
+        assert_eq!(
+            bindings.conflicts(),
+            HashSet::from([key_z(Slot::One), key_z(Slot::Two)])
+        );
+        bindings.unbind(key_z(Slot::Two), b);
+        assert!(bindings.conflicts().is_empty());
+
+        // Controller buttons aren't
+        let button = |slot| Input::Button((slot, ControllerButton::A));
+        bindings.bind(button(Slot::One), a);
+        bindings.bind(button(Slot::Two), a);
+        assert!(bindings.conflicts().is_empty());
+        bindings.bind(button(Slot::One), b);
+        assert_eq!(bindings.conflicts(), HashSet::from([button(Slot::One)]));
+        assert_eq!(bindings.to_mapping().len(), 3);
+
+        let defaults = InputBindings::defaults();
+        assert!(!defaults.keys.is_empty());
+        assert!(defaults.conflicts().is_empty());
+        bindings.reset_slot(Slot::One, &defaults);
+        assert_eq!(bindings.inputs(Slot::Two, a), vec![button(Slot::Two)]);
+        assert_eq!(bindings.inputs(Slot::One, a), defaults.inputs(Slot::One, a));
+    }
+
+    #[test]
+    fn export_and_import() {
+        let path = std::env::temp_dir().join("tetanes_test_bindings.json");
+        let defaults = InputBindings::defaults();
+        defaults.save(&path).expect("exported bindings");
+        assert_eq!(
+            InputBindings::load(&path).expect("imported bindings"),
+            defaults
+        );
+
+        std::fs::write(&path, "{\"keys\": 1}").expect("wrote file");
+        assert!(InputBindings::load(&path).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,192 @@
+//! Instrumentation measuring end-to-end input latency, for tuning vsync, frame pacing and audio
+//! settings.
+//!
+//! Each sample is the time from a joypad button press being handled to the first frame that read
+//! the joypads after it being presented. Frames started before the press aren't counted. The
+//! time the game itself takes to react isn't included, since that varies from game to game.
+
+use crate::nes::Nes;
+use pix_engine::prelude::*;
+use std::{collections::VecDeque, fmt, time::Instant};
+
+/// Number of samples statistics are reported over.
+const MAX_SAMPLES: usize = 600;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[must_use]
+pub(crate) struct LatencyStats {
+    pub(crate) count: usize,
+    pub(crate) min: f32,
+    pub(crate) average: f32,
+    pub(crate) p95: f32,
+    pub(crate) max: f32,
+}
+
+impl LatencyStats {
+    /// Summarizes latencies in milliseconds, or `None` if there are none.
+    pub(crate) fn from_samples(samples: impl IntoIterator<Item = f32>) -> Option<Self> {
+        let mut sorted = samples.into_iter().collect::<Vec<_>>();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort_by(f32::total_cmp);
+        let count = sorted.len();
+        let p95 = ((count as f32 * 0.95).ceil() as usize).clamp(1, count) - 1;
+        Some(Self {
+            count,
+            min: sorted[0],
+            average: sorted.iter().sum::<f32>() / count as f32,
+            p95: sorted[p95],
+            max: sorted[count - 1],
+        })
+    }
+}
+
+impl fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Latency: {:.1} ms avg, {:.1} min, {:.1} p95, {:.1} max ({} samples)",
+            self.average, self.min, self.p95, self.max, self.count
+        )
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+#[must_use]
+pub(crate) struct LatencyMonitor {
+    enabled: bool,
+    /// When the oldest unanswered press was handled and the frame number at the time.
+    input: Option<(Instant, u32)>,
+    /// Press time of a frame waiting to be presented.
+    presenting: Option<Instant>,
+    /// Latencies in milliseconds.
+    samples: VecDeque<f32>,
+}
+
+impl LatencyMonitor {
+    pub(crate) const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        *self = Self {
+            enabled,
+            ..Self::default()
+        };
+    }
+
+    /// Records a button press at `frame`. Later presses before it's presented are ignored.
+    pub(crate) fn input(&mut self, now: Instant, frame: u32) {
+        if self.enabled && self.input.is_none() {
+            self.input = Some((now, frame));
+        }
+    }
+
+    /// Records frames clocked starting at `prev_frame`, where `lagged` is whether the last one
+    /// didn't read the joypads.
+    pub(crate) fn frame_emulated(&mut self, prev_frame: u32, lagged: bool) {
+        if lagged {
+            return;
+        }
+        if let Some((pressed, frame)) = self.input {
+            if prev_frame >= frame {
+                self.input = None;
+                self.presenting = Some(pressed);
+            }
+        }
+    }
+
+    /// Records that the last frame was presented, completing a sample if one was waiting.
+    pub(crate) fn presented(&mut self, now: Instant) {
+        if let Some(pressed) = self.presenting.take() {
+            if self.samples.len() == MAX_SAMPLES {
+                self.samples.pop_front();
+            }
+            self.samples
+                .push_back(1000.0 * now.duration_since(pressed).as_secs_f32());
+        }
+    }
+
+    pub(crate) fn stats(&self) -> Option<LatencyStats> {
+        LatencyStats::from_samples(self.samples.iter().copied())
+    }
+}
+
+impl Nes {
+    pub(crate) fn toggle_latency_monitor(&mut self) {
+        let enabled = !self.latency.enabled();
+        if !enabled {
+            match self.latency.stats() {
+                Some(stats) => log::info!("{stats}"),
+                None => log::info!("Latency: no samples recorded"),
+            }
+        }
+        self.latency.set_enabled(enabled);
+        self.add_message(if enabled {
+            "Latency Monitor Enabled"
+        } else {
+            "Latency Monitor Disabled"
+        });
+    }
+
+    pub(crate) fn render_latency_status(&mut self, s: &mut PixState) -> PixResult<()> {
+        if !self.latency.enabled() {
+            return Ok(());
+        }
+        let status = self.latency.stats().map_or_else(
+            || "Latency: press a button to measure".to_string(),
+            |stats| stats.to_string(),
+        );
+        self.render_status(s, &status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn latency_samples() {
+        let mut latency = LatencyMonitor::default();
+        let start = Instant::now();
+        latency.input(start, 10);
+        latency.frame_emulated(10, false);
+        latency.presented(start + Duration::from_millis(20));
+        assert!(latency.stats().is_none(), "disabled monitor recorded");
+
+        latency.set_enabled(true);
+        latency.input(start, 10);
+        // Started before the press
+        latency.frame_emulated(9, false);
+        // Didn't read the joypads
+        latency.frame_emulated(10, true);
+        latency.presented(start + Duration::from_millis(10));
+        assert!(latency.stats().is_none());
+
+        latency.frame_emulated(11, false);
+        latency.presented(start + Duration::from_millis(40));
+        let stats = latency.stats().expect("latency sample");
+        assert_eq!(stats.count, 1);
+        assert!((stats.average - 40.0).abs() < 0.1, "{stats}");
+    }
+
+    #[test]
+    fn latency_stats() {
+        let mut latency = LatencyMonitor::default();
+        latency.set_enabled(true);
+        let start = Instant::now();
+        for ms in 1..=100 {
+            latency.input(start, 0);
+            latency.frame_emulated(0, false);
+            latency.presented(start + Duration::from_millis(ms));
+        }
+        let stats = latency.stats().expect("latency samples");
+        assert_eq!(stats.count, 100);
+        assert!((stats.min - 1.0).abs() < 0.1);
+        assert!((stats.average - 50.5).abs() < 0.1);
+        assert!((stats.p95 - 95.0).abs() < 0.1);
+        assert!((stats.max - 100.0).abs() < 0.1);
+    }
+}
@@ -0,0 +1,269 @@
+//! A test pattern for auditing input-to-photon latency with a light sensor or high-speed camera,
+//! without loading a ROM.
+//!
+//! The window stays black until a key, mouse button or controller button is pressed, then turns
+//! white for a few frames. Each press records three timestamps, measured from when the pattern
+//! started: when the input event was received, when the white frame was drawn and when it was
+//! presented. A frame counts as presented at the start of the next update, once the display has
+//! taken it, so the time a sensor or camera sees the flash after the presented time is the
+//! display's own latency. A running clock and frame counter are shown for matching camera
+//! footage to timestamps, which are written to a CSV file in the config directory on exit.
+
+use crate::{
+    common::config_dir,
+    nes::{latency::LatencyStats, Mode, Nes},
+    NesResult,
+};
+use anyhow::Context;
+use chrono::Local;
+use pix_engine::prelude::*;
+use std::{
+    fmt::Write,
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+/// Frames the screen stays white for after a press.
+const FLASH_FRAMES: u32 = 3;
+
+/// Timestamps of a single press, from the start of the test.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct LatencySample {
+    pub(crate) received: Duration,
+    pub(crate) drawn: Duration,
+    pub(crate) presented: Duration,
+}
+
+impl LatencySample {
+    /// Milliseconds from the event being received to the flash being presented.
+    #[must_use]
+    pub(crate) fn total_ms(&self) -> f32 {
+        ms(self.presented - self.received)
+    }
+}
+
+fn ms(duration: Duration) -> f32 {
+    1000.0 * duration.as_secs_f32()
+}
+
+#[derive(Debug, Clone)]
+#[must_use]
+pub(crate) struct LatencyTest {
+    start: Instant,
+    /// When the press waiting to be drawn was received.
+    received: Option<Instant>,
+    /// When the flash waiting to be presented was received and drawn.
+    drawn: Option<(Instant, Instant)>,
+    /// Frames left to keep the screen white for.
+    flash: u32,
+    frame: u64,
+    samples: Vec<LatencySample>,
+}
+
+impl LatencyTest {
+    pub(crate) const fn new(start: Instant) -> Self {
+        Self {
+            start,
+            received: None,
+            drawn: None,
+            flash: 0,
+            frame: 0,
+            samples: vec![],
+        }
+    }
+
+    /// Records a press. Presses while a flash is still on its way to the screen are ignored.
+    pub(crate) fn press(&mut self, now: Instant) {
+        if self.received.is_none() && self.drawn.is_none() {
+            self.received = Some(now);
+        }
+    }
+
+    /// Records drawing a frame at `now`, returning whether it's white.
+    pub(crate) fn draw(&mut self, now: Instant) -> bool {
+        self.frame += 1;
+        if let Some(received) = self.received.take() {
+            self.drawn = Some((received, now));
+            self.flash = FLASH_FRAMES;
+        }
+        if self.flash > 0 {
+            self.flash -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Records that the last frame drawn was presented, completing a sample if it was a flash.
+    pub(crate) fn presented(&mut self, now: Instant) {
+        if let Some((received, drawn)) = self.drawn.take() {
+            self.samples.push(LatencySample {
+                received: received.saturating_duration_since(self.start),
+                drawn: drawn.saturating_duration_since(self.start),
+                presented: now.saturating_duration_since(self.start),
+            });
+        }
+    }
+
+    pub(crate) fn stats(&self) -> Option<LatencyStats> {
+        LatencyStats::from_samples(self.samples.iter().map(LatencySample::total_ms))
+    }
+
+    /// Timestamps of every sample in milliseconds from the start of the test.
+    #[must_use]
+    pub(crate) fn to_csv(&self) -> String {
+        let mut csv = "sample,received_ms,drawn_ms,presented_ms,total_ms\n".to_string();
+        for (i, sample) in self.samples.iter().enumerate() {
+            let _ = writeln!(
+                csv,
+                "{},{:.3},{:.3},{:.3},{:.3}",
+                i + 1,
+                ms(sample.received),
+                ms(sample.drawn),
+                ms(sample.presented),
+                sample.total_ms(),
+            );
+        }
+        csv
+    }
+}
+
+impl Nes {
+    pub(crate) fn toggle_latency_test(&mut self) {
+        if self.latency_test.is_some() {
+            self.stop_latency_test();
+        } else {
+            self.latency_test = Some(LatencyTest::new(Instant::now()));
+            self.audio.pause();
+        }
+    }
+
+    fn stop_latency_test(&mut self) {
+        let Some(test) = self.latency_test.take() else {
+            return;
+        };
+        if self.mode == Mode::Playing {
+            self.audio.resume();
+        }
+        let Some(stats) = test.stats() else {
+            return;
+        };
+        log::info!("Latency test: {stats}");
+        match save_latency_test(&test) {
+            Ok(path) => self.add_message(format!("Saved latency timestamps to {path:?}")),
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message("Failed to save latency timestamps");
+            }
+        }
+    }
+
+    /// Handles input while the test pattern is shown, exiting on `cancel` and flashing on any
+    /// other press. Returns whether the pattern is shown, consuming the input.
+    pub(crate) fn latency_test_input(&mut self, pressed: bool, cancel: bool) -> bool {
+        let Some(ref mut test) = self.latency_test else {
+            return false;
+        };
+        if pressed {
+            if cancel {
+                self.stop_latency_test();
+            } else {
+                test.press(Instant::now());
+            }
+        }
+        true
+    }
+
+    pub(crate) fn render_latency_test(&mut self, s: &mut PixState) -> PixResult<()> {
+        let Some(ref mut test) = self.latency_test else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let flash = test.draw(now);
+        let (background, foreground) = if flash {
+            (Color::WHITE, Color::BLACK)
+        } else {
+            (Color::BLACK, Color::WHITE)
+        };
+        s.push();
+        s.stroke(None);
+        s.fill(background);
+        s.rect([0, 0, s.width()? as i32, s.height()? as i32])?;
+        s.fill(foreground);
+        s.set_cursor_pos([10, 10]);
+        s.text(format!(
+            "Frame {}  {:.1} ms",
+            test.frame,
+            ms(now.saturating_duration_since(test.start))
+        ))?;
+        if let Some(sample) = test.samples.last() {
+            s.text(format!(
+                "Last press: received {:.1} ms, drawn +{:.1} ms, presented +{:.1} ms",
+                ms(sample.received),
+                ms(sample.drawn - sample.received),
+                ms(sample.presented - sample.drawn),
+            ))?;
+        }
+        match test.stats() {
+            Some(stats) => s.text(stats.to_string())?,
+            None => s.text("Press any key or button to flash the screen")?,
+        }
+        s.text("Press Escape to exit and save timestamps")?;
+        s.pop();
+        Ok(())
+    }
+}
+
+fn save_latency_test(test: &LatencyTest) -> NesResult<PathBuf> {
+    let dir = config_dir().join("latency");
+    fs::create_dir_all(&dir).with_context(|| format!("failed to create directory {dir:?}"))?;
+    let name = Local::now()
+        .format("latency_test_%Y-%m-%d_at_%H.%M.%S")
+        .to_string();
+    let path = dir.join(name).with_extension("csv");
+    fs::write(&path, test.to_csv()).with_context(|| format!("failed to write {path:?}"))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flash_timestamps() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut test = LatencyTest::new(start);
+        assert!(!test.draw(at(5)));
+        test.presented(at(6));
+        assert!(test.stats().is_none());
+
+        test.press(at(10));
+        // Ignored while the first press is on its way
+        test.press(at(12));
+        assert!(test.draw(at(16)));
+        test.press(at(18));
+        test.presented(at(33));
+        assert!(test.draw(at(34)));
+        assert!(test.draw(at(50)));
+        assert!(!test.draw(at(66)));
+
+        assert_eq!(
+            test.samples,
+            [LatencySample {
+                received: Duration::from_millis(10),
+                drawn: Duration::from_millis(16),
+                presented: Duration::from_millis(33),
+            }]
+        );
+        let stats = test.stats().expect("latency sample");
+        assert!((stats.average - 23.0).abs() < 0.1, "{stats}");
+        assert_eq!(
+            test.to_csv(),
+            "sample,received_ms,drawn_ms,presented_ms,total_ms\n\
+            1,10.000,16.000,33.000,23.000\n"
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Per-game launch options.
+//!
+//! Options can be saved for each game in `games/<rom name>.json` under the config directory, or
+//! passed on the command line so frontends like `EmulationStation` can launch a game with exact
+//! settings. Command-line options take precedence and only apply to the ROM given on the command
+//! line. Settings changed by launch options are restored when another game is loaded.
+
+use crate::{
+    common::{config_dir, NesRegion},
+    genie::GenieCode,
+    input::FourPlayer,
+    nes::{config::Config, Nes},
+    video::VideoFilter,
+    NesResult,
+};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsStr,
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+/// Settings to override when launching a game.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[must_use]
+pub struct LaunchOptions {
+    /// Start region, overriding the region detected from the ROM.
+    pub region: Option<NesRegion>,
+    /// Video filter.
+    pub filter: Option<VideoFilter>,
+    /// Whether Game Genie codes are enabled.
+    pub cheats: Option<bool>,
+    /// Game Genie codes to enable for this game in addition to the configured codes.
+    pub genie_codes: Vec<String>,
+    /// Four player adapter.
+    pub four_player: Option<FourPlayer>,
+    /// Whether the Zapper is connected.
+    pub zapper: Option<bool>,
+    /// Save state slot to load once the game starts.
+    pub load_slot: Option<u8>,
+    /// Whether Save RAM is battery-backed, overriding the game database and ROM header.
+    pub battery: Option<bool>,
+    /// Whether PRG-RAM is write-protected.
+    pub write_protect: Option<bool>,
+}
+
+impl LaunchOptions {
+    /// Combines two sets of options, preferring values from `self`.
+    pub fn or(self, other: Self) -> Self {
+        let mut genie_codes = self.genie_codes;
+        for code in other.genie_codes {
+            if !genie_codes.contains(&code) {
+                genie_codes.push(code);
+            }
+        }
+        Self {
+            region: self.region.or(other.region),
+            filter: self.filter.or(other.filter),
+            cheats: self.cheats.or(other.cheats),
+            genie_codes,
+            four_player: self.four_player.or(other.four_player),
+            zapper: self.zapper.or(other.zapper),
+            load_slot: self.load_slot.or(other.load_slot),
+            battery: self.battery.or(other.battery),
+            write_protect: self.write_protect.or(other.write_protect),
+        }
+    }
+}
+
+/// Returns the path where launch options for `rom` are stored.
+fn options_path(rom: &Path) -> NesResult<PathBuf> {
+    rom.file_stem().and_then(OsStr::to_str).map_or_else(
+        || {
+            Err(anyhow!(
+                "failed to create launch options path for `{rom:?}`"
+            ))
+        },
+        |name| Ok(config_dir().join("games").join(name).with_extension("json")),
+    )
+}
+
+fn read_options(path: &Path) -> NesResult<LaunchOptions> {
+    if !path.exists() {
+        return Ok(LaunchOptions::default());
+    }
+    let file = File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+    serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("failed to parse {path:?}"))
+}
+
+/// A Game Genie code enabled for a game.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct GameCheat {
+    pub code: GenieCode,
+    /// Whether the code comes from the game's launch options rather than the configuration.
+    pub per_game: bool,
+}
+
+/// Lists the Game Genie codes enabled when `rom` is launched, from the configuration and the
+/// game's saved launch options.
+///
+/// # Errors
+///
+/// If the launch options fail to load or a code is invalid, an error is returned.
+pub fn list_cheats<P: AsRef<Path>>(rom: P) -> NesResult<Vec<GameCheat>> {
+    let options = read_options(&options_path(rom.as_ref())?)?;
+    if options.cheats == Some(false) {
+        return Ok(vec![]);
+    }
+    let config = Config::load();
+    let mut cheats = vec![];
+    for code in config.genie_codes {
+        cheats.push(GameCheat {
+            code: GenieCode::new(code)?,
+            per_game: false,
+        });
+    }
+    for code in options.genie_codes {
+        if !cheats.iter().any(|cheat| cheat.code.code() == code) {
+            cheats.push(GameCheat {
+                code: GenieCode::new(code)?,
+                per_game: true,
+            });
+        }
+    }
+    Ok(cheats)
+}
+
+impl Nes {
+    /// Returns the path where per-game launch options are stored.
+    pub(crate) fn launch_options_path(&self) -> NesResult<PathBuf> {
+        match self.control_deck.loaded_rom() {
+            Some(ref rom) => options_path(Path::new(rom)),
+            None => Err(anyhow!("no rom is loaded")),
+        }
+    }
+
+    fn load_launch_options(&self) -> NesResult<LaunchOptions> {
+        read_options(&self.launch_options_path()?)
+    }
+
+    /// Updates the saved launch options for the loaded game.
+    pub(crate) fn save_launch_options<F>(&mut self, update: F) -> NesResult<()>
+    where
+        F: FnOnce(&mut LaunchOptions),
+    {
+        let path = self.launch_options_path()?;
+        let mut options = self.load_launch_options()?;
+        update(&mut options);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).with_context(|| format!("failed to create {dir:?}"))?;
+        }
+        let file = File::create(&path).with_context(|| format!("failed to create {path:?}"))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &options)
+            .with_context(|| format!("failed to save {path:?}"))
+    }
+
+    /// Applies saved and command-line launch options for the loaded game, returning the save
+    /// slot to load once the game starts.
+    pub(crate) fn apply_launch_options(&mut self) -> Option<u8> {
+        self.restore_launch_options();
+        let mut options = self.load_launch_options().unwrap_or_else(|err| {
+            log::error!("{err:?}");
+            self.add_message("Failed to load launch options");
+            LaunchOptions::default()
+        });
+        if let Some(cli_options) = self.launch_options.take() {
+            options = cli_options.or(options);
+        }
+
+        // Settings to put back when another game is loaded
+        let mut restore = LaunchOptions::default();
+        if let Some(region) = options.region {
+            self.config.region = region;
+            self.control_deck.set_region(region);
+        }
+        if let Some(filter) = options.filter {
+            restore.filter = Some(self.config.filter);
+            self.config.filter = filter;
+            self.control_deck.set_filter(filter);
+        }
+        if let Some(four_player) = options.four_player {
+            restore.four_player = Some(self.config.four_player);
+            self.config.four_player = four_player;
+            self.control_deck.set_four_player(four_player);
+        }
+        if let Some(zapper) = options.zapper {
+            restore.zapper = Some(self.config.zapper);
+            self.config.zapper = zapper;
+            self.control_deck.connect_zapper(zapper);
+        }
+        // Cartridge settings are reset when the next cartridge loads, so don't need restoring
+        if let Some(battery) = options.battery {
+            self.control_deck.set_cart_battery_backed(battery);
+        }
+        if let Some(write_protect) = options.write_protect {
+            self.control_deck.set_prg_ram_write_protected(write_protect);
+        }
+        if options.cheats == Some(false) {
+            restore.cheats = Some(true);
+            for code in &self.config.genie_codes {
+                self.control_deck.remove_genie_code(code);
+            }
+        } else {
+            for code in options.genie_codes {
+                match self.control_deck.add_genie_code(code.clone()) {
+                    Ok(()) => restore.genie_codes.push(code),
+                    Err(err) => {
+                        log::warn!("{}", err);
+                        self.add_message(format!("Invalid Genie Code: '{code}'"));
+                    }
+                }
+            }
+        }
+        self.launch_restore = Some(restore);
+
+        options.load_slot
+    }
+
+    /// Restores settings changed by launch options for the previous game.
+    pub(crate) fn restore_launch_options(&mut self) {
+        let Some(restore) = self.launch_restore.take() else {
+            return;
+        };
+        if let Some(filter) = restore.filter {
+            self.config.filter = filter;
+            self.control_deck.set_filter(filter);
+        }
+        if let Some(four_player) = restore.four_player {
+            self.config.four_player = four_player;
+            self.control_deck.set_four_player(four_player);
+        }
+        if let Some(zapper) = restore.zapper {
+            self.config.zapper = zapper;
+            self.control_deck.connect_zapper(zapper);
+        }
+        for code in &restore.genie_codes {
+            self.control_deck.remove_genie_code(code);
+        }
+        if restore.cheats == Some(true) {
+            for code in self.config.genie_codes.clone() {
+                if let Err(err) = self.control_deck.add_genie_code(code) {
+                    log::warn!("{}", err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_line_takes_precedence() {
+        let cli = LaunchOptions {
+            region: Some(NesRegion::Pal),
+            genie_codes: vec!["SXIOPO".to_string()],
+            ..LaunchOptions::default()
+        };
+        let saved = LaunchOptions {
+            region: Some(NesRegion::Dendy),
+            filter: Some(VideoFilter::Pixellate),
+            genie_codes: vec!["SXIOPO".to_string(), "AAEAULPA".to_string()],
+            load_slot: Some(2),
+            battery: Some(false),
+            ..LaunchOptions::default()
+        };
+        let options = cli.or(saved);
+        assert_eq!(options.region, Some(NesRegion::Pal));
+        assert_eq!(options.filter, Some(VideoFilter::Pixellate));
+        assert_eq!(options.load_slot, Some(2));
+        assert_eq!(options.battery, Some(false));
+        assert_eq!(options.write_protect, None);
+        assert_eq!(options.cheats, None);
+        assert_eq!(options.genie_codes, vec!["SXIOPO", "AAEAULPA"]);
+    }
+}
@@ -0,0 +1,217 @@
+//! Save states and replays written before they were versioned.
+//!
+//! Those files hold the console state and input events in the layouts of the time, with no
+//! version to tell them apart. The console state is read through [`crate::legacy`], and the events
+//! through copies of the event enums as they were then, since variants added since shifted the
+//! indices `bincode` stores.
+
+use crate::{
+    common::NesRegion,
+    cpu::Cpu,
+    input::{JoypadBtn, Slot},
+    legacy,
+    nes::{
+        event::{Action, ActionEvent, DebugAction, Feature, NesState, Setting},
+        menu::{types::ConfigSection, Menu, Player},
+        state::{Replay, ReplayMode},
+    },
+    video::VideoFilter,
+};
+use bincode::Options;
+use serde::{de::DeserializeOwned, Deserialize};
+
+/// Deserializes `data` if it's in the legacy layout, which has to account for every byte.
+pub(crate) fn deserialize<T: DeserializeOwned>(data: &[u8]) -> Option<T> {
+    legacy::decode(|| {
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .deserialize(data)
+            .ok()
+    })
+}
+
+/// Deserializes a replay saved before replays were versioned.
+pub(crate) fn deserialize_replay(data: &[u8]) -> Option<Replay> {
+    let LegacyReplay {
+        mode,
+        start,
+        buffer,
+    } = deserialize(data)?;
+    Some(Replay {
+        mode,
+        start,
+        buffer: buffer.into_iter().map(ActionEvent::from).collect(),
+        ..Replay::default()
+    })
+}
+
+#[derive(Deserialize)]
+struct LegacyReplay {
+    mode: ReplayMode,
+    start: Option<Cpu>,
+    buffer: Vec<LegacyActionEvent>,
+}
+
+#[derive(Deserialize)]
+struct LegacyActionEvent {
+    frame: u32,
+    slot: Slot,
+    action: LegacyAction,
+    pressed: bool,
+    repeat: bool,
+}
+
+impl From<LegacyActionEvent> for ActionEvent {
+    fn from(event: LegacyActionEvent) -> Self {
+        Self {
+            frame: event.frame,
+            slot: event.slot,
+            action: event.action.into(),
+            pressed: event.pressed,
+            repeat: event.repeat,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+enum LegacyAction {
+    Nes(NesState),
+    Menu(LegacyMenu),
+    Feature(LegacyFeature),
+    Setting(LegacySetting),
+    Joypad(JoypadBtn),
+    ZapperTrigger,
+    ZeroAxis([JoypadBtn; 2]),
+    Debug(LegacyDebugAction),
+}
+
+impl From<LegacyAction> for Action {
+    fn from(action: LegacyAction) -> Self {
+        match action {
+            LegacyAction::Nes(state) => Self::Nes(state),
+            LegacyAction::Menu(menu) => Self::Menu(menu.into()),
+            LegacyAction::Feature(feature) => Self::Feature(feature.into()),
+            LegacyAction::Setting(setting) => Self::Setting(setting.into()),
+            LegacyAction::Joypad(button) => Self::Joypad(button),
+            LegacyAction::ZapperTrigger => Self::ZapperTrigger,
+            LegacyAction::ZeroAxis(buttons) => Self::ZeroAxis(buttons),
+            LegacyAction::Debug(action) => Self::Debug(action.into()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+enum LegacyMenu {
+    Main,
+    Config(ConfigSection),
+    Keybind(Player),
+    LoadRom,
+    About,
+}
+
+impl From<LegacyMenu> for Menu {
+    fn from(menu: LegacyMenu) -> Self {
+        match menu {
+            LegacyMenu::Main => Self::Main,
+            LegacyMenu::Config(section) => Self::Config(section),
+            LegacyMenu::Keybind(player) => Self::Keybind(player),
+            LegacyMenu::LoadRom => Self::LoadRom,
+            LegacyMenu::About => Self::About,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+enum LegacyFeature {
+    ToggleGameplayRecording,
+    ToggleSoundRecording,
+    Rewind,
+    TakeScreenshot,
+    SaveState,
+    LoadState,
+}
+
+impl From<LegacyFeature> for Feature {
+    fn from(feature: LegacyFeature) -> Self {
+        match feature {
+            LegacyFeature::ToggleGameplayRecording => Self::ToggleGameplayRecording,
+            LegacyFeature::ToggleSoundRecording => Self::ToggleSoundRecording,
+            LegacyFeature::Rewind => Self::Rewind,
+            LegacyFeature::TakeScreenshot => Self::TakeScreenshot,
+            LegacyFeature::SaveState => Self::SaveState,
+            LegacyFeature::LoadState => Self::LoadState,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+enum LegacySetting {
+    SetSaveSlot(u8),
+    ToggleFullscreen,
+    ToggleVsync,
+    ToggleNtscFilter,
+    SetVideoFilter(VideoFilter),
+    SetNesFormat(NesRegion),
+    ToggleSound,
+    TogglePulse1,
+    TogglePulse2,
+    ToggleTriangle,
+    ToggleNoise,
+    ToggleDmc,
+    FastForward,
+    IncSpeed,
+    DecSpeed,
+}
+
+impl From<LegacySetting> for Setting {
+    fn from(setting: LegacySetting) -> Self {
+        match setting {
+            LegacySetting::SetSaveSlot(slot) => Self::SetSaveSlot(slot),
+            LegacySetting::ToggleFullscreen => Self::ToggleFullscreen,
+            LegacySetting::ToggleVsync => Self::ToggleVsync,
+            LegacySetting::ToggleNtscFilter => Self::ToggleNtscFilter,
+            LegacySetting::SetVideoFilter(filter) => Self::SetVideoFilter(filter),
+            LegacySetting::SetNesFormat(region) => Self::SetNesFormat(region),
+            LegacySetting::ToggleSound => Self::ToggleSound,
+            LegacySetting::TogglePulse1 => Self::TogglePulse1,
+            LegacySetting::TogglePulse2 => Self::TogglePulse2,
+            LegacySetting::ToggleTriangle => Self::ToggleTriangle,
+            LegacySetting::ToggleNoise => Self::ToggleNoise,
+            LegacySetting::ToggleDmc => Self::ToggleDmc,
+            LegacySetting::FastForward => Self::FastForward,
+            LegacySetting::IncSpeed => Self::IncSpeed,
+            LegacySetting::DecSpeed => Self::DecSpeed,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+enum LegacyDebugAction {
+    ToggleCpuDebugger,
+    TogglePpuDebugger,
+    ToggleApuDebugger,
+    StepInto,
+    StepOver,
+    StepOut,
+    StepFrame,
+    StepScanline,
+    IncScanline,
+    DecScanline,
+}
+
+impl From<LegacyDebugAction> for DebugAction {
+    fn from(action: LegacyDebugAction) -> Self {
+        match action {
+            LegacyDebugAction::ToggleCpuDebugger => Self::ToggleCpuDebugger,
+            LegacyDebugAction::TogglePpuDebugger => Self::TogglePpuDebugger,
+            LegacyDebugAction::ToggleApuDebugger => Self::ToggleApuDebugger,
+            LegacyDebugAction::StepInto => Self::StepInto,
+            LegacyDebugAction::StepOver => Self::StepOver,
+            LegacyDebugAction::StepOut => Self::StepOut,
+            LegacyDebugAction::StepFrame => Self::StepFrame,
+            LegacyDebugAction::StepScanline => Self::StepScanline,
+            LegacyDebugAction::IncScanline => Self::IncScanline,
+            LegacyDebugAction::DecScanline => Self::DecScanline,
+        }
+    }
+}
@@ -1,32 +1,71 @@
 use crate::{
-    apu::Channel,
-    audio::AudioMixer,
-    common::{config_path, NesRegion, Regional, SAVE_DIR, SRAM_DIR},
-    input::FourPlayer,
+    apu::{ApuMixing, Channel},
+    audio::output::{output_devices, AudioBackend},
+    common::{config_path, NesRegion, SAVE_DIR, SRAM_DIR},
+    cpu::{Cpu, UnofficialOpcodes},
+    input::{FourPlayer, JoypadBtn, Slot},
+    mapper::{AudioChip, NesEvent},
     mem::RamState,
     nes::{
+        bezel::BezelMode,
+        clip_capture::ClipFormat,
+        command_palette::COMMANDS,
         config::CONFIG,
+        controllers::Rumble,
+        event::{Action, Feature, Input, Setting},
         filesystem::is_nes_rom,
+        frame_pacing::{FramePacing, SyncMode},
+        input_sharing::InputSharing,
         menu::types::{ConfigSection, EmuSpeed, SampleRate},
+        menu_nav::NavAction,
+        mixer::{MixerSettings, MAX_EQ_GAIN},
+        osd::{OsdElement, OsdPosition},
+        replay_format::ReplayFormat,
+        rom_patch::PatchFormat,
+        screenshot::ScreenshotFormat,
+        sound_recording::SoundFormat,
+        state::ReplayMode,
+        turbo::{MAX_TURBO_RATE, MIN_TURBO_RATE, TURBO_BUTTONS},
+        video_recording::VideoFormat,
         Mode, Nes,
     },
-    video::VideoFilter,
+    video::{ColorFilter, VideoFilter},
 };
 use pix_engine::prelude::*;
-use std::{borrow::Cow, ffi::OsStr, path::PathBuf};
+use std::{borrow::Cow, collections::HashSet, ffi::OsStr, path::PathBuf, time::Duration};
 
+pub(crate) mod changelog;
+pub(crate) mod settings_search;
 pub(crate) mod types;
 pub(crate) use types::{Menu, Player};
 
+/// Held actions the command palette can't run that can still be bound.
+const HELD_ACTIONS: &[(&str, Action)] = &[
+    ("Fast Forward", Action::Setting(Setting::FastForward)),
+    ("Rewind", Action::Feature(Feature::Rewind)),
+];
+
 impl Nes {
     pub(crate) fn open_menu(&mut self, s: &mut PixState, menu: Menu) -> PixResult<()> {
         s.cursor(Cursor::arrow())?;
+        if menu == Menu::Gallery {
+            self.update_gallery();
+        } else if menu == Menu::Commands {
+            self.open_command_palette();
+        } else if menu == Menu::WhatsNew {
+            self.open_whats_new();
+        } else if menu == Menu::Main {
+            self.menu_nav.selected = 0;
+        }
         self.mode = Mode::InMenu(menu);
         self.audio.pause();
         Ok(())
     }
 
     pub(crate) fn exit_menu(&mut self, s: &mut PixState) -> PixResult<()> {
+        self.save_game_mixer();
+        self.save_state_bookmarks();
+        self.cancel_binding();
         if self.config.zapper {
             s.cursor(None)?;
         }
@@ -56,7 +95,12 @@ impl Nes {
             Menu::Config(section) => self.render_config(s, section)?,
             Menu::Keybind(player) => self.render_keybinds(s, player)?,
             Menu::LoadRom => self.render_load_rom(s)?,
+            Menu::Replay => self.render_replay(s)?,
+            Menu::Bookmarks => self.render_state_bookmarks(s)?,
+            Menu::Gallery => self.render_gallery(s)?,
             Menu::About => self.render_about(s)?,
+            Menu::Commands => self.render_command_palette(s)?,
+            Menu::WhatsNew => self.render_whats_new(s)?,
         }
 
         Ok(())
@@ -64,7 +108,7 @@ impl Nes {
 }
 
 impl Nes {
-    fn render_heading(&mut self, s: &mut PixState, heading: &str) -> PixResult<()> {
+    pub(crate) fn render_heading(&mut self, s: &mut PixState, heading: &str) -> PixResult<()> {
         s.heading(heading)?;
         if self.control_deck.is_running() && s.menu("< Exit")? {
             self.exit_menu(s)?;
@@ -75,13 +119,97 @@ impl Nes {
 
     fn render_config_general(&mut self, s: &mut PixState) -> PixResult<()> {
         s.checkbox("Pause in Background", &mut self.config.pause_in_bg)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            s.checkbox(
+                "Pause on Sleep and Low Battery",
+                &mut self.config.power_save,
+            )?;
+            s.same_line(None);
+            s.help_marker(
+                "Pauses, saves Save RAM and writes an auto-save state before the system sleeps or \
+                when the battery drops below the set level. Battery levels are only read on \
+                Linux.",
+            )?;
+            if self.config.power_save {
+                s.indent()?;
+                s.next_width(200);
+                s.slider(
+                    "Low Battery (%)",
+                    &mut self.config.low_battery_percent,
+                    1,
+                    50,
+                )?;
+            }
+            s.checkbox(
+                "Keep Display Awake While Playing",
+                &mut self.config.suppress_screensaver,
+            )?;
+            s.same_line(None);
+            s.help_marker(
+                "Stops the screensaver and display sleep from starting while a game is running, \
+                but not while paused or in menus. Supported on Linux and macOS.",
+            )?;
+        }
+        s.next_width(200);
+        s.slider(
+            "Dim When Idle (minutes)",
+            &mut self.config.idle_dim_minutes,
+            0,
+            60,
+        )?;
+        s.same_line(None);
+        s.help_marker("Dims the screen after this long without any input. 0 turns dimming off.")?;
+        s.checkbox("Show Tutorial on Startup", &mut self.config.show_tutorial)?;
+        s.checkbox("Watch ROM Folder", &mut self.config.watch_rom_dir)?;
+        s.same_line(None);
+        s.help_marker(
+            "Updates the ROM browser and shows a notification when ROMs are added to or removed \
+            from the ROM folder.",
+        )?;
+        s.checkbox("Apply ROM Patches", &mut self.config.soft_patch)?;
+        s.same_line(None);
+        s.help_marker(
+            "Applies IPS, BPS and UPS patches named after the ROM when it's loaded, without \
+            changing the ROM file. Number them, like game.1.ips, to apply several in order.",
+        )?;
+        if s.checkbox("Control Server", &mut self.config.rpc_server)? {
+            self.update_rpc_server();
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Lets external tools like test frameworks drive the emulator with JSON-RPC \
+            requests over a local TCP or Unix socket. Use `unix:<path>` for a Unix socket.",
+        )?;
+        if self.config.rpc_server {
+            s.indent()?;
+            s.next_width(200);
+            s.text_field("Address", &mut self.config.rpc_address)?;
+            s.same_line(None);
+            if s.button("Apply")? {
+                self.update_rpc_server();
+            }
+        }
 
         let mut save_slot = self.config.save_slot as usize - 1;
-        s.next_width(50);
-        if s.select_box("Save Slot", &mut save_slot, &["1", "2", "3", "4"], 4)? {
+        s.next_width(300);
+        if s.select_box("Save Slot", &mut save_slot, &self.save_slot_names(), 4)? {
             self.config.save_slot = save_slot as u8 + 1;
         }
 
+        if s.checkbox(
+            "Restore Cheats from States",
+            &mut self.config.save_state_cheats,
+        )? {
+            self.control_deck
+                .set_state_cheats(self.config.save_state_cheats);
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Loading a save state, quick slot or rewinding restores the Game Genie codes that \
+            were active when it was saved. Disable to keep the current codes instead.",
+        )?;
+
         s.checkbox("Enable Rewind", &mut self.config.rewind)?;
         if self.config.rewind {
             s.indent()?;
@@ -97,7 +225,63 @@ impl Nes {
             )?;
         }
 
+        s.next_width(200);
+        s.slider(
+            "Session Replay (minutes)",
+            &mut self.config.session_replay_minutes,
+            0,
+            60,
+        )?;
+        s.same_line(None);
+        s.help_marker(
+            "Keep the last few minutes of input to save as a replay with Save Session Replay. \
+            0 disables.",
+        )?;
+
+        let mut replay_format = self.config.replay_format as usize;
+        s.next_width(200);
+        if s.select_box(
+            "Replay Format",
+            &mut replay_format,
+            ReplayFormat::as_slice(),
+            4,
+        )? {
+            self.config.replay_format = ReplayFormat::from(replay_format);
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Format for new replays. JSON can be read and diffed, and FM2 movies play in other \
+            emulators but only keep joypad input. Replays load in any format.",
+        )?;
+
         s.checkbox("Enable Zapper", &mut self.config.zapper)?;
+        if self.config.zapper {
+            s.indent()?;
+            s.checkbox("Motion Aim", &mut self.config.motion_aim)?;
+            s.same_line(None);
+            s.help_marker(
+                "Aim by tilting a controller with a gyro. Map the gyro to the right stick with \
+                Steam Input, DS4Windows or similar. Calibrate if the crosshair drifts.",
+            )?;
+            if self.config.motion_aim {
+                s.indent()?;
+                s.next_width(200);
+                s.slider(
+                    "Sensitivity",
+                    &mut self.config.motion_aim_sensitivity,
+                    0.1,
+                    4.0,
+                )?;
+                s.indent()?;
+                if s.button("Calibrate")? {
+                    self.motion_aim.start_calibration();
+                }
+                s.same_line(None);
+                if s.button("Recenter")? {
+                    self.motion_aim.recenter();
+                }
+            }
+        }
 
         let mut four_player = self.config.four_player as usize;
         s.next_width(150);
@@ -118,20 +302,18 @@ impl Nes {
         let mut region = self.config.region as usize;
         s.next_width(150);
         if s.select_box("NES Region", &mut region, NesRegion::as_slice(), 3)? {
-            self.config.region = NesRegion::from(region);
-            self.control_deck.set_region(self.config.region);
-            s.set_window_dimensions(self.config.get_dimensions())?;
-            self.update_frame_rate(s)?;
-            self.audio = AudioMixer::new(
-                self.control_deck.sample_rate(),
-                self.config.audio_sample_rate / self.config.speed,
-                self.config.audio_buffer_size,
-            );
-            self.audio.open_playback(s)?;
+            self.set_region(s, NesRegion::from(region))?;
         }
+        self.render_region_mismatch(s)?;
+        s.checkbox("Warn on Region Mismatch", &mut self.config.region_warning)?;
+        s.same_line(None);
+        s.help_marker(
+            "Pause with a warning when a game made for PAL consoles is started with NTSC timing, \
+            or the reverse.",
+        )?;
 
         s.next_width(125);
-        let mut selected_state = self.config.ram_state as usize;
+        let mut selected_state = self.config.ram_state.index();
         if s.select_box(
             "Power-up RAM State",
             &mut selected_state,
@@ -139,26 +321,136 @@ impl Nes {
             3,
         )? {
             self.config.ram_state = selected_state.into();
+            self.control_deck.set_ram_state(self.config.ram_state);
         }
+        s.same_line(None);
+        s.help_marker(
+            "Takes effect on the next power cycle. Use a RAM state other than Random for replays \
+            that play back the same on every machine.",
+        )?;
+        if let RamState::Seeded(seed) = self.config.ram_state {
+            let mut seed_text = seed.to_string();
+            s.next_width(125);
+            if s.text_field("RAM Seed", &mut seed_text)? {
+                seed_text.retain(|c| c.is_ascii_digit());
+                self.config.ram_state = RamState::Seeded(seed_text.parse().unwrap_or_default());
+                self.control_deck.set_ram_state(self.config.ram_state);
+            }
+        }
+
+        let mut alignment = u32::from(self.config.ppu_alignment);
+        let max_alignment = u32::from(Cpu::max_ppu_alignment(self.config.region));
+        s.next_width(100);
+        if s.slider("CPU/PPU Alignment", &mut alignment, 0, max_alignment)? {
+            self.config.ppu_alignment = alignment as u8;
+            self.control_deck
+                .set_ppu_alignment(self.config.ppu_alignment);
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Master clocks the PPU runs behind the CPU, which real consoles pick at random on \
+            power up. Some games behave differently with each.",
+        )?;
 
         let mut selected_speed = EmuSpeed::from(self.config.speed) as usize;
         s.next_width(100);
         if s.select_box("Speed", &mut selected_speed, EmuSpeed::as_slice(), 4)? {
             self.set_speed(EmuSpeed::from(selected_speed).as_f32());
         }
+        s.same_line(None);
+        s.checkbox("Skip Frames", &mut self.config.fast_forward_frame_skip)?;
+        s.same_line(None);
+        s.help_marker(
+            "Skip drawing frames that won't be shown when running faster than 100%. Emulation is \
+            unaffected. Disabled while the Zapper is connected.",
+        )?;
 
         s.checkbox("Concurrent D-Pad", &mut self.config.concurrent_dpad)?;
         s.same_line(None);
         s.help_marker("Allow pressing U/D and L/R at the same time.")?;
 
+        if s.checkbox("Cycle Accurate", &mut self.config.cycle_accurate)? {
+            self.control_deck
+                .set_cycle_accurate(self.config.cycle_accurate);
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Clock the PPU and APU every CPU cycle and emulate DMA glitches, like DMC playback \
+            dropping controller input on NTSC. Disable for speed.",
+        )?;
+
+        let mut unofficial_opcodes = self.config.unofficial_opcodes as usize;
+        s.next_width(150);
+        if s.select_box(
+            "Unofficial Opcodes",
+            &mut unofficial_opcodes,
+            UnofficialOpcodes::as_slice(),
+            3,
+        )? {
+            self.config.unofficial_opcodes = UnofficialOpcodes::from(unofficial_opcodes);
+            self.control_deck
+                .set_unofficial_opcodes(self.config.unofficial_opcodes);
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Some games and test ROMs use undocumented 6502 opcodes. Log warns the first time each \
+            one runs and Trap pauses after it runs, which helps catch bugs in homebrew.",
+        )?;
+
+        let timers: Vec<String> = (0..16)
+            .map(|dip_switches| {
+                let seconds = NesEvent::timer_seconds(dip_switches).round() as u32;
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            })
+            .collect();
+        let mut dip_switches = self.config.dip_switches as usize;
+        s.next_width(100);
+        if s.select_box("NWC Timer", &mut dip_switches, &timers, 4)? {
+            self.config.dip_switches = dip_switches as u8;
+            self.control_deck.set_dip_switches(self.config.dip_switches);
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Time limit set by the DIP switches on the Nintendo World Championships 1990 cartridge.",
+        )?;
+
+        if self.control_deck.loaded_rom().is_some() {
+            s.spacing()?;
+            s.text("Cartridge (saved for this game)")?;
+
+            let mut battery = self.control_deck.cart_battery_backed();
+            if s.checkbox("Battery-backed Save RAM", &mut battery)? {
+                self.control_deck.set_cart_battery_backed(battery);
+                if let Err(err) =
+                    self.save_launch_options(|options| options.battery = Some(battery))
+                {
+                    log::error!("{err:?}");
+                    self.add_message("Failed to save cartridge settings");
+                }
+            }
+            s.same_line(None);
+            s.help_marker("Keep Save RAM between sessions. Defaults to the game database.")?;
+
+            let mut write_protect = self.control_deck.prg_ram_write_protected();
+            if s.checkbox("Write-protect PRG-RAM", &mut write_protect)? {
+                self.control_deck.set_prg_ram_write_protected(write_protect);
+                if let Err(err) =
+                    self.save_launch_options(|options| options.write_protect = Some(write_protect))
+                {
+                    log::error!("{err:?}");
+                    self.add_message("Failed to save cartridge settings");
+                }
+            }
+            s.same_line(None);
+            s.help_marker("Ignore writes to PRG-RAM, which some games behave differently with.")?;
+        }
+
         Ok(())
     }
 
     fn render_config_audio(&mut self, s: &mut PixState) -> PixResult<()> {
         s.checkbox("Enabled", &mut self.config.sound)?;
         if self.config.sound {
-            let audio = &mut self.audio;
-
             let mut selected_sample_rate = SampleRate::from(self.config.audio_sample_rate) as usize;
             s.next_width(200);
             if s.select_box(
@@ -168,14 +460,88 @@ impl Nes {
                 4,
             )? {
                 self.config.audio_sample_rate = SampleRate::from(selected_sample_rate).as_f32();
-                audio.set_output_frequency(self.config.audio_sample_rate / self.config.speed);
+                // Reopen the device so it plays at the new rate, or falls back if unsupported
+                self.open_audio(s)?;
+            }
+
+            let mut backend = self.config.audio_backend as usize;
+            s.next_width(200);
+            if s.select_box("Backend", &mut backend, AudioBackend::as_slice(), 2)? {
+                self.config.audio_backend = AudioBackend::from(backend);
+                self.config.audio_device = None;
+                self.open_audio(s)?;
+            }
+
+            // Native devices, which the secondary output always uses
+            if self.audio_devices.is_empty() {
+                self.audio_devices = output_devices(AudioBackend::Cpal);
+            }
+            if self.config.audio_backend == AudioBackend::Sdl {
+                s.text("Device: System Default")?;
+            } else {
+                let devices: Vec<&str> = std::iter::once("System Default")
+                    .chain(self.audio_devices.iter().map(String::as_str))
+                    .collect();
+                let mut selected_device = self
+                    .config
+                    .audio_device
+                    .as_ref()
+                    .and_then(|device| self.audio_devices.iter().position(|d| d == device))
+                    .map_or(0, |i| i + 1);
+                s.next_width(300);
+                if s.select_box("Device", &mut selected_device, &devices, 4)? {
+                    self.config.audio_device = selected_device
+                        .checked_sub(1)
+                        .and_then(|i| self.audio_devices.get(i).cloned());
+                    self.open_audio(s)?;
+                }
             }
 
+            let devices: Vec<&str> = std::iter::once("None")
+                .chain(self.audio_devices.iter().map(String::as_str))
+                .collect();
+            let mut selected_device = self
+                .config
+                .audio_secondary_device
+                .as_ref()
+                .and_then(|device| self.audio_devices.iter().position(|d| d == device))
+                .map_or(0, |i| i + 1);
+            s.next_width(300);
+            if s.select_box("Second Output", &mut selected_device, &devices, 4)? {
+                self.config.audio_secondary_device = selected_device
+                    .checked_sub(1)
+                    .and_then(|i| self.audio_devices.get(i).cloned());
+                self.open_secondary_audio(s);
+            }
+            s.same_line(None);
+            s.help_marker(
+                "Also play audio on another device, like a virtual device for streaming while \
+                listening on headphones.",
+            )?;
+            if self.config.audio_secondary_device.is_some() {
+                s.next_width(200);
+                if s.slider(
+                    "Second Output Volume",
+                    &mut self.config.audio_secondary_volume,
+                    0.0,
+                    1.0,
+                )? {
+                    self.audio
+                        .set_secondary_volume(self.config.audio_secondary_volume);
+                }
+            }
+            if s.button("Refresh Devices")? {
+                self.audio_devices = output_devices(AudioBackend::Cpal);
+            }
+
+            let mut buffer_size = self.config.device_buffer_size();
             s.next_width(200);
-            if s.slider("Buffer Size", &mut self.config.audio_buffer_size, 512, 8192)? {
-                audio.reset(self.config.audio_buffer_size);
-                audio.open_playback(s)?;
+            if s.slider("Buffer Size", &mut buffer_size, 512, 8192)? {
+                self.config.set_device_buffer_size(buffer_size);
+                self.open_audio(s)?;
             }
+            s.same_line(None);
+            s.help_marker("Saved separately for each selected device.")?;
 
             s.checkbox(
                 "Dynamic Rate Control",
@@ -189,8 +555,24 @@ impl Nes {
                     0.001,
                     0.1,
                 )?;
+                s.next_width(200);
+                if s.slider(
+                    "Target Latency (ms)",
+                    &mut self.config.audio_latency,
+                    10.0,
+                    200.0,
+                )? {
+                    self.audio
+                        .set_target_latency(self.config.audio_latency / 1000.0);
+                }
             }
 
+            s.checkbox("Mute Slow Motion", &mut self.config.mute_slow_motion)?;
+            s.same_line(None);
+            s.help_marker(
+                "Mute audio when running slower than 100% instead of playing it at a lower pitch.",
+            )?;
+
             let deck = &mut self.control_deck;
             s.collapsing_tree("Channels", |s: &mut PixState| {
                 let mut pulse1 = deck.channel_enabled(Channel::Pulse1);
@@ -219,7 +601,108 @@ impl Nes {
                 }
                 Ok(())
             })?;
+
+            s.collapsing_tree("Mixer", |s: &mut PixState| self.render_mixer(s))?;
+            s.collapsing_tree("Expansion Audio", |s: &mut PixState| {
+                self.render_expansion_audio(s)
+            })?;
+
+            let mut apu_mixing = self.config.apu_mixing as usize;
+            s.next_width(200);
+            if s.select_box("APU Mixing", &mut apu_mixing, ApuMixing::as_slice(), 2)? {
+                self.config.apu_mixing = ApuMixing::from(apu_mixing);
+                self.control_deck.set_apu_mixing(self.config.apu_mixing);
+            }
+            s.same_line(None);
+            s.help_marker(
+                "Accurate uses the nonlinear NES mixer formulas. Legacy uses the older lookup \
+                tables for comparison.",
+            )?;
+            if s.checkbox("Reduce DMC Pops", &mut self.config.dmc_pop_reduction)? {
+                self.control_deck
+                    .set_dmc_pop_reduction(self.config.dmc_pop_reduction);
+            }
+            s.same_line(None);
+            s.help_marker("Softens clicks when games write the DMC output level directly.")?;
         }
+
+        s.spacing()?;
+        let mut sound_format = self.config.sound_recording_format as usize;
+        s.next_width(200);
+        if s.select_box(
+            "Recording Format",
+            &mut sound_format,
+            SoundFormat::as_slice(),
+            2,
+        )? {
+            self.config.sound_recording_format = SoundFormat::from(sound_format);
+        }
+        s.checkbox(
+            "Record Channel Stems",
+            &mut self.config.sound_recording_stems,
+        )?;
+        s.same_line(None);
+        s.help_marker("Also record each APU and expansion audio channel to a separate file.")?;
+
+        Ok(())
+    }
+
+    fn render_mixer(&mut self, s: &mut PixState) -> PixResult<()> {
+        let mut changed = false;
+        s.next_width(200);
+        changed |= s.slider("Master", &mut self.mixer.master, 0.0, 1.0)?;
+        for channel in Channel::as_slice() {
+            s.next_width(200);
+            changed |= s.slider(channel, self.mixer.channel_mut(*channel), 0.0, 1.0)?;
+        }
+        s.next_width(200);
+        changed |= s.slider("Expansion", &mut self.mixer.expansion, 0.0, 1.0)?;
+        s.next_width(200);
+        changed |= s.slider("Bass (dB)", &mut self.mixer.bass, -MAX_EQ_GAIN, MAX_EQ_GAIN)?;
+        s.next_width(200);
+        changed |= s.slider(
+            "Treble (dB)",
+            &mut self.mixer.treble,
+            -MAX_EQ_GAIN,
+            MAX_EQ_GAIN,
+        )?;
+        if s.button("Reset Mixer")? {
+            self.mixer = MixerSettings::default();
+            changed = true;
+        }
+        if changed {
+            self.update_mixer();
+        }
+
+        if s.checkbox("Save Per Game", &mut self.config.mixer_per_game)? {
+            if self.config.mixer_per_game {
+                self.save_game_mixer();
+            } else {
+                self.config.mixer = self.mixer;
+            }
+        }
+        s.same_line(None);
+        s.help_marker("Remember mixer settings separately for each game.")?;
+        Ok(())
+    }
+
+    fn render_expansion_audio(&mut self, s: &mut PixState) -> PixResult<()> {
+        let active = self.control_deck.audio_chip();
+        for chip in AudioChip::as_slice() {
+            let mut gain = self.config.audio_chip_gain(*chip);
+            let label = if active == Some(*chip) {
+                format!("{} (Active)", chip.as_ref())
+            } else {
+                chip.as_ref().to_string()
+            };
+            s.next_width(200);
+            if s.slider(&label, &mut gain, 0.0, 2.0)? {
+                self.config.audio_chip_gains.insert(*chip, gain);
+                self.control_deck.set_audio_chip_gain(*chip, gain);
+            }
+        }
+        s.same_line(None);
+        s.help_marker("Balances cartridge sound chips against the built-in APU channels.")?;
         Ok(())
     }
 
@@ -249,6 +732,68 @@ impl Nes {
             self.control_deck.set_filter(self.config.filter);
         }
 
+        let mut color_filter = self.config.color_filter as usize;
+        s.next_width(150);
+        let mut color_filter_changed = s.select_box(
+            "Color Blindness Filter",
+            &mut color_filter,
+            ColorFilter::as_slice(),
+            4,
+        )?;
+        if self.config.color_filter != ColorFilter::None {
+            color_filter_changed |=
+                s.checkbox("Simulate Only", &mut self.config.color_filter_simulate)?;
+            s.same_line(None);
+            s.help_marker(
+                "Show colors as they appear with the selected deficiency instead of correcting them.",
+            )?;
+        }
+        if color_filter_changed {
+            self.config.color_filter = ColorFilter::from(color_filter);
+            self.control_deck
+                .set_color_filter(self.config.color_filter, self.config.color_filter_simulate);
+        }
+
+        s.checkbox(
+            "Adaptive Filter Resolution",
+            &mut self.config.adaptive_filter,
+        )?;
+        s.same_line(None);
+        s.help_marker(
+            "Filter fewer scanlines when the filter can't keep up, restoring full quality once \
+            it can. The FPS display shows when the resolution is reduced.",
+        )?;
+
+        let mut bezel = self.config.bezel as usize;
+        s.next_width(150);
+        if s.select_box("Bezel", &mut bezel, BezelMode::as_slice(), 3)? {
+            self.config.bezel = BezelMode::from(bezel);
+        }
+        if self.config.bezel == BezelMode::Pack {
+            s.same_line(None);
+            if s.button("Reload Bezel")? {
+                self.bezel.reload();
+            }
+            s.text(&format!(
+                "Bezel Pack: {}",
+                self.config.bezel_dir().to_string_lossy()
+            ))?;
+            s.same_line(None);
+            s.help_marker(
+                "Uses <rom name>.png from the pack for each game, or default.png. Set \
+                `bezel_dir` in the configuration file to use another pack.",
+            )?;
+        }
+
+        if s.checkbox("HD Packs", &mut self.config.hd_pack)? {
+            self.load_hd_pack();
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Replaces tiles with high-resolution art from hdpacks/<rom name>/hires.txt in the \
+            configuration directory. Shift-H captures the tiles on screen to start a pack.",
+        )?;
+
         if s.checkbox("Fullscreen", &mut self.config.fullscreen)? {
             s.fullscreen(self.config.fullscreen)?;
         }
@@ -257,23 +802,217 @@ impl Nes {
             s.vsync(self.config.vsync)?;
         }
 
+        let mut mini_view = self.config.mini_view;
+        if s.checkbox("Mini View", &mut mini_view)? {
+            self.set_mini_view(s, mini_view)?;
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Shrinks the window, removes its borders, hides status text and keeps playing while \
+            other windows are focused.",
+        )?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let mut always_on_top = self.config.always_on_top;
+            if s.checkbox("Always on Top", &mut always_on_top)? {
+                self.set_always_on_top(always_on_top);
+            }
+            s.same_line(None);
+            s.help_marker("Keeps the window above other windows, even when they're focused.")?;
+
+            let mut click_through = self.config.click_through;
+            if s.checkbox("Click-Through", &mut click_through)? {
+                self.set_click_through(click_through);
+            }
+            s.same_line(None);
+            s.help_marker(
+                "Lets mouse clicks go through the window to the windows behind it. Use the \
+                keyboard shortcut to turn it off again. Not available on Wayland.",
+            )?;
+        }
+
+        let mut frame_pacing = self.config.frame_pacing as usize;
+        s.next_width(150);
+        if s.select_box(
+            "Frame Pacing",
+            &mut frame_pacing,
+            FramePacing::as_slice(),
+            2,
+        )? {
+            self.config.frame_pacing = FramePacing::from(frame_pacing);
+            self.update_frame_rate(s)?;
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Whole Frames keeps motion smooth on displays that don't match the console, like PAL \
+            on 60Hz or 144Hz displays, by repeating or skipping frames. Elapsed Time follows the \
+            display more closely but may show partial frames.",
+        )?;
+
+        let mut sync_mode = self.config.sync_mode as usize;
+        s.next_width(150);
+        if s.select_box("Sync Mode", &mut sync_mode, SyncMode::as_slice(), 3)? {
+            self.set_sync_mode(s, SyncMode::from(sync_mode))?;
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Video runs emulation at the display rate and stretches audio to match. Audio runs \
+            emulation as fast as the audio device plays samples, which avoids audio crackle but \
+            may drop or repeat frames. Unsynced runs as fast as possible without audio, for \
+            benchmarking.",
+        )?;
+
+        let mut video_format = self.config.video_format as usize;
+        s.next_width(150);
+        if s.select_box(
+            "Video Recording Format",
+            &mut video_format,
+            VideoFormat::as_slice(),
+            2,
+        )? {
+            self.config.video_format = VideoFormat::from(video_format);
+        }
+
+        let mut clip_format = self.config.clip_format as usize;
+        s.next_width(150);
+        if s.select_box("Clip Format", &mut clip_format, ClipFormat::as_slice(), 2)? {
+            self.config.clip_format = ClipFormat::from(clip_format);
+        }
+        s.next_width(200);
+        s.slider(
+            "Clip Buffer (seconds)",
+            &mut self.config.clip_seconds,
+            0,
+            30,
+        )?;
+        s.same_line(None);
+        s.help_marker("Keep the last few seconds of gameplay to save as a clip. 0 disables.")?;
+
+        let mut screenshot_format = self.config.screenshot_format as usize;
+        s.next_width(200);
+        if s.select_box(
+            "Screenshot Format",
+            &mut screenshot_format,
+            ScreenshotFormat::as_slice(),
+            3,
+        )? {
+            self.config.screenshot_format = ScreenshotFormat::from(screenshot_format);
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Palette Indices saves a .idx file with the system palette index of every pixel, one \
+            byte each for the full 256x240 frame, and a .pal file with the 64 colors they map to, \
+            for tools that re-color or analyze frames.",
+        )?;
+        s.checkbox("Save Raw Screenshots", &mut self.config.screenshot_raw)?;
+        s.same_line(None);
+        s.help_marker(
+            "Also save the unfiltered 256x240 frame next to each screenshot, ending in _raw.png.",
+        )?;
+        s.checkbox(
+            "Copy Screenshots to Clipboard",
+            &mut self.config.screenshot_clipboard,
+        )?;
+        s.same_line(None);
+        s.help_marker(
+            "Requires xclip or wl-copy on Linux. Only the first frame of a burst is copied.",
+        )?;
+        s.next_width(200);
+        s.slider(
+            "Screenshot Burst (frames)",
+            &mut self.config.screenshot_burst,
+            1,
+            60,
+        )?;
+        s.same_line(None);
+        s.help_marker(
+            "Save this many consecutive frames per screenshot, numbered in order, for animation \
+            reference.",
+        )?;
+
+        s.collapsing_tree("On-Screen Display", |s: &mut PixState| {
+            self.render_osd_config(s)
+        })?;
+
+        s.collapsing_tree("Diff Overlay", |s: &mut PixState| {
+            self.render_diff_overlay_config(s)
+        })?;
+
         Ok(())
     }
 
-    fn render_main(&mut self, s: &mut PixState) -> PixResult<()> {
-        self.render_heading(s, "Menu")?;
-
-        if s.menu("Config")? {
-            self.mode = Mode::InMenu(Menu::Config(ConfigSection::General));
+    fn render_osd_config(&mut self, s: &mut PixState) -> PixResult<()> {
+        for &element in OsdElement::as_slice() {
+            let item = self.config.osd.item_mut(element);
+            s.checkbox(element, &mut item.enabled)?;
+            if item.enabled {
+                s.same_line(None);
+                let mut position = item.position as usize;
+                s.next_width(150);
+                if s.select_box(
+                    format!("Position##{}", element.as_ref()),
+                    &mut position,
+                    OsdPosition::as_slice(),
+                    4,
+                )? {
+                    item.position = OsdPosition::from(position);
+                }
+            }
         }
-        if s.menu("Keybinds")? {
-            self.mode = Mode::InMenu(Menu::Keybind(Player::One));
+        s.help_marker(
+            "Status shown over the game. Elements in the same corner are stacked in the order \
+            listed. The lag counter counts frames where the game didn't read the controllers.",
+        )?;
+        Ok(())
+    }
+
+    fn render_diff_overlay_config(&mut self, s: &mut PixState) -> PixResult<()> {
+        s.next_width(300);
+        s.text_field("Reference Image", &mut self.diff_overlay.path)?;
+        s.same_line(None);
+        s.help_marker(
+            "A PNG capture of the same frame from hardware or another emulator. Pixels that \
+            differ from the live frame are highlighted from yellow to red.",
+        )?;
+        if s.button("Load")? {
+            self.load_diff_reference();
         }
-        if s.menu("Load ROM")? {
-            self.mode = Mode::InMenu(Menu::LoadRom);
+        if self.diff_overlay.loaded() {
+            s.same_line(None);
+            if s.button("Clear")? {
+                self.clear_diff_reference();
+            }
+            s.checkbox("Show Overlay", &mut self.diff_overlay.enabled)?;
+            s.next_width(200);
+            s.slider("Tolerance", &mut self.diff_overlay.tolerance, 0, 64)?;
+            let stats = self.diff_overlay.stats();
+            s.text(&format!(
+                "Differing pixels: {}, max difference: {}",
+                stats.pixels, stats.max
+            ))?;
         }
-        if s.menu("About")? {
-            self.mode = Mode::InMenu(Menu::About);
+        Ok(())
+    }
+
+    fn render_main(&mut self, s: &mut PixState) -> PixResult<()> {
+        self.render_heading(s, "Menu")?;
+
+        let entries = self.main_menu_entries();
+        self.menu_nav.selected = self.menu_nav.selected.min(entries.len() - 1);
+        for (i, (name, menu)) in entries.into_iter().enumerate() {
+            let label = if i == self.menu_nav.selected {
+                format!("> {name}")
+            } else {
+                name.to_string()
+            };
+            if s.menu(label)? {
+                self.menu_nav.selected = i;
+                if menu == Menu::Gallery {
+                    self.update_gallery();
+                }
+                self.mode = Mode::InMenu(menu);
+            }
         }
 
         Ok(())
@@ -281,6 +1020,9 @@ impl Nes {
 
     fn render_config(&mut self, s: &mut PixState, mut section: ConfigSection) -> PixResult<()> {
         self.render_heading(s, "Configuration")?;
+        if !self.render_settings_search(s)? {
+            return Ok(());
+        }
 
         if s.tab_bar(
             "Sections",
@@ -315,20 +1057,270 @@ impl Nes {
     }
 
     fn render_gamepad_binds(&mut self, s: &mut PixState, player: Player) -> PixResult<()> {
-        s.text("Coming soon!")?;
+        let slot = Slot::from(player);
+        let conflicts = self.config.bindings.conflicts();
 
+        self.render_controller_assignment(s, slot)?;
         if player == Player::One {
-            self.render_emulator_binds(s)?;
+            let mut sharing = self.config.input_sharing as usize;
+            s.next_width(200);
+            if s.select_box(
+                "Share Player 1 Input",
+                &mut sharing,
+                InputSharing::as_slice(),
+                3,
+            )? {
+                self.set_input_sharing(InputSharing::from(sharing));
+            }
+            s.same_line(None);
+            s.help_marker(
+                "Drives Player 2's pad with Player 1's input as well, for games that need two \
+                controllers. Alternate Pads sends input to one pad at a time, switched with \
+                Switch Shared Pad.",
+            )?;
         }
+        s.spacing()?;
+
+        s.text("Click a binding to remove it.")?;
+        if !conflicts.is_empty() {
+            s.push();
+            s.fill(s.theme().colors.error);
+            s.text("Bindings marked with ! conflict and only one of them will trigger.")?;
+            s.pop();
+        }
+        s.spacing()?;
+
+        s.collapsing_tree("Gamepad", |s: &mut PixState| {
+            for &button in JoypadBtn::as_slice() {
+                self.render_binding(s, slot, button.as_ref(), Action::Joypad(button), &conflicts)?;
+            }
+            self.render_binding(s, slot, "Zapper Trigger", Action::ZapperTrigger, &conflicts)
+        })?;
+        s.collapsing_tree("Turbo", |s: &mut PixState| {
+            // A and B turbo are bound above
+            for button in &TURBO_BUTTONS[2..] {
+                let name = format!("{} (Turbo)", button.as_ref());
+                self.render_binding(s, slot, &name, Action::Turbo(*button), &conflicts)?;
+            }
+            s.spacing()?;
+            s.text("Presses per second:")?;
+            for button in TURBO_BUTTONS {
+                let mut rate = self.config.turbo_rate(slot, button);
+                s.next_width(200);
+                if s.slider(button.as_ref(), &mut rate, MIN_TURBO_RATE, MAX_TURBO_RATE)? {
+                    self.config.set_turbo_rate(slot, button, rate);
+                }
+            }
+            Ok(())
+        })?;
+        if player == Player::One {
+            self.render_emulator_binds(s, &conflicts)?;
+            self.render_nav_binds(s)?;
+        }
+
+        s.spacing()?;
+        if s.button("Reset to Defaults")? {
+            self.reset_bindings(slot);
+        }
+        s.spacing()?;
+        self.render_binding_profiles(s)
+    }
+
+    fn render_controller_assignment(&mut self, s: &mut PixState, slot: Slot) -> PixResult<()> {
+        let connected = self.controllers.connected().to_vec();
+        let mut controllers = vec!["None".to_string()];
+        controllers.extend((1..=connected.len()).map(|i| format!("Controller {i}")));
+        let mut selected = self
+            .controllers
+            .controller(slot)
+            .and_then(|id| connected.iter().position(|&connected| connected == id))
+            .map_or(0, |i| i + 1);
+        s.next_width(200);
+        if s.select_box("Controller", &mut selected, &controllers, 4)? {
+            let id = selected
+                .checked_sub(1)
+                .and_then(|i| connected.get(i).copied());
+            self.controllers.assign(slot, id);
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Picking a controller used by another player swaps them. A disconnected controller \
+            returns to the same player when plugged back in.",
+        )?;
+
+        if let Some(controller_id) = self.controllers.controller(slot) {
+            let capabilities = self.feedback.capabilities(controller_id);
+            let supported = |supported| if supported { "Yes" } else { "No" };
+            s.text(format!(
+                "Rumble: {}, Lightbar: {}",
+                supported(capabilities.rumble),
+                supported(capabilities.lightbar)
+            ))?;
+            if s.button("Test Rumble")? {
+                let pulse = Rumble::new(0.5, 0.5, Duration::from_millis(200));
+                self.rumble(
+                    slot,
+                    &[pulse, Rumble::pause(Duration::from_millis(100)), pulse],
+                    false,
+                );
+            }
+            if let Some(rumble) = self.controllers.active_rumble(slot) {
+                s.same_line(None);
+                s.text(format!(
+                    "Rumbling: low {:.0}%, high {:.0}%",
+                    100.0 * rumble.low_frequency,
+                    100.0 * rumble.high_frequency
+                ))?;
+            }
+        }
+        s.checkbox("Show Status on Lightbars", &mut self.config.lightbar_status)?;
+        s.same_line(None);
+        s.help_marker(
+            "Red while recording and blue while rewinding, on controllers with a lightbar.",
+        )?;
         Ok(())
     }
 
-    fn render_emulator_binds(&mut self, _s: &mut PixState) -> PixResult<()> {
-        // Action::Nes
-        // Action::Menu
-        // Action::Feature
-        // Action::Setting
-        // Action::Debug
+    fn render_emulator_binds(
+        &mut self,
+        s: &mut PixState,
+        conflicts: &HashSet<Input>,
+    ) -> PixResult<()> {
+        s.collapsing_tree("Emulator", |s: &mut PixState| {
+            for &(name, action) in HELD_ACTIONS.iter().chain(COMMANDS) {
+                self.render_binding(s, Slot::One, name, action, conflicts)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn render_binding(
+        &mut self,
+        s: &mut PixState,
+        slot: Slot,
+        name: &str,
+        action: Action,
+        conflicts: &HashSet<Input>,
+    ) -> PixResult<()> {
+        s.text(name)?;
+        let mut remove = None;
+        for (i, input) in self
+            .config
+            .bindings
+            .inputs(slot, action)
+            .into_iter()
+            .enumerate()
+        {
+            s.same_line(None);
+            if s.button(format!("{input}##{name}{i}"))? {
+                remove = Some(input);
+            }
+            if conflicts.contains(&input) {
+                s.same_line(None);
+                s.push();
+                s.fill(s.theme().colors.error);
+                s.text("!")?;
+                s.pop();
+            }
+        }
+        s.same_line(None);
+        if self.keybinds.capturing == Some((slot, action)) {
+            s.text(format!(
+                "Press a key or button, {} cancels...",
+                self.nav_cancel_hint()
+            ))?;
+            s.same_line(None);
+            if s.button(format!("Cancel##{name}"))? {
+                self.cancel_binding();
+            }
+        } else if s.button(format!("Bind##{name}"))? {
+            self.start_binding(slot, action);
+        }
+        if let Some(input) = remove {
+            self.unbind(input, action);
+        }
+        Ok(())
+    }
+
+    fn render_nav_binds(&mut self, s: &mut PixState) -> PixResult<()> {
+        s.collapsing_tree("Menu Navigation", |s: &mut PixState| {
+            for &action in NavAction::as_slice() {
+                let name = action.as_ref();
+                s.text(name)?;
+                let mut remove = None;
+                for (i, input) in self
+                    .config
+                    .nav_bindings
+                    .inputs(action)
+                    .into_iter()
+                    .enumerate()
+                {
+                    s.same_line(None);
+                    if s.button(format!("{input}##nav{name}{i}"))? {
+                        remove = Some(input);
+                    }
+                }
+                s.same_line(None);
+                if self.keybinds.capturing_nav == Some(action) {
+                    s.text(format!(
+                        "Press a key or button, {} cancels...",
+                        self.nav_cancel_hint()
+                    ))?;
+                    s.same_line(None);
+                    if s.button(format!("Cancel##nav{name}"))? {
+                        self.cancel_binding();
+                    }
+                } else if s.button(format!("Bind##nav{name}"))? {
+                    self.start_nav_binding(action);
+                }
+                if let Some(input) = remove {
+                    self.unbind_nav(input);
+                }
+            }
+            if s.button("Reset Navigation to Defaults")? {
+                self.reset_nav_bindings();
+            }
+            Ok(())
+        })
+    }
+
+    fn render_binding_profiles(&mut self, s: &mut PixState) -> PixResult<()> {
+        s.text("Profiles:")?;
+        let profiles: Vec<String> = self.config.binding_profiles.keys().cloned().collect();
+        if profiles.is_empty() {
+            s.text("None")?;
+        } else {
+            let mut selected = self.keybinds.selected_profile.min(profiles.len() - 1);
+            s.next_width(300);
+            s.select_box("Profile", &mut selected, &profiles, 4)?;
+            self.keybinds.selected_profile = selected;
+            if s.button("Load Profile")? {
+                self.load_binding_profile();
+            }
+            s.same_line(None);
+            if s.button("Delete Profile")? {
+                self.delete_binding_profile();
+            }
+        }
+        s.next_width(300);
+        s.text_field("Profile Name", &mut self.keybinds.profile_name)?;
+        s.same_line(None);
+        if s.button("Save Profile")? {
+            self.save_binding_profile();
+        }
+
+        s.spacing()?;
+        s.next_width(300);
+        s.text_field("Bindings File", &mut self.keybinds.file_path)?;
+        s.same_line(None);
+        s.help_marker("Import or export every player's bindings, to back them up or share them.")?;
+        if s.button("Import")? {
+            self.import_bindings();
+        }
+        s.same_line(None);
+        if s.button("Export")? {
+            self.export_bindings();
+        }
         Ok(())
     }
 
@@ -364,9 +1356,19 @@ impl Nes {
         let path_list: Vec<Cow<'_, str>> = self
             .paths
             .iter()
-            .map(|p| p.strip_prefix(rom_dir).unwrap_or(p).to_string_lossy())
+            .enumerate()
+            .map(|(i, p)| {
+                let name = p.strip_prefix(rom_dir).unwrap_or(p).to_string_lossy();
+                self.rom_label(i, name)
+            })
             .collect();
 
+        s.push();
+        if self.rom_titles_need_unicode() {
+            if let Some(ref font) = self.unicode_font {
+                s.font_family(font.clone())?;
+            }
+        }
         s.fill(colors.secondary);
         s.next_width((s.ui_width()? - spacing.scroll_size) as u32);
         s.select_list(
@@ -375,6 +1377,7 @@ impl Nes {
             &path_list,
             displayed_count,
         )?;
+        s.pop();
         let path = self.paths[self.selected_path].clone();
         if s.dbl_clicked() {
             if self.selected_path == 0 {
@@ -387,15 +1390,20 @@ impl Nes {
                 self.update_paths();
             }
         }
-        if !is_nes_rom(&path) {
-            s.disable(true);
-        }
-        if s.dbl_clicked() || s.button("Open")? {
-            self.config.rom_path = path;
-            self.selected_path = 0;
-            self.load_rom(s)?;
+        if PatchFormat::from_path(&path).is_some() {
+            self.render_pick_patch(s, path)?;
+        } else {
+            if !is_nes_rom(&path) {
+                s.disable(true);
+            }
+            if s.dbl_clicked() || s.button("Open")? {
+                self.config.rom_path = path;
+                self.selected_path = 0;
+                self.load_rom(s)?;
+            }
+            s.disable(false);
         }
-        s.disable(false);
+        self.render_applied_patches(s)?;
 
         Ok(())
     }
@@ -413,13 +1421,16 @@ impl Nes {
                     .filter_map(Result::ok)
                     .map(|f| f.path())
                     .filter(|p| {
-                        p.is_dir() || matches!(p.extension().and_then(OsStr::to_str), Some("nes"))
+                        p.is_dir()
+                            || matches!(p.extension().and_then(OsStr::to_str), Some("nes"))
+                            || PatchFormat::from_path(p).is_some()
                     })
                     .for_each(|p| self.paths.push(p));
                 self.paths.sort();
                 if path.parent().is_some() {
                     self.paths.insert(0, PathBuf::from("../"));
                 }
+                self.update_rom_titles();
             }
             Err(err) => {
                 log::error!("{:?}", err);
@@ -428,12 +1439,215 @@ impl Nes {
         }
     }
 
+    fn render_replay(&mut self, s: &mut PixState) -> PixResult<()> {
+        self.render_heading(s, "Replay")?;
+
+        if self.replay.mode == ReplayMode::Off {
+            s.text("No replay is being recorded or played back.")?;
+            return Ok(());
+        }
+
+        let current_frame = self.control_deck.frame_number();
+        let start_frame = self.replay.start_frame();
+        if self.replay.mode == ReplayMode::Playback {
+            let end_frame = self.replay.end_frame();
+            let mut frame = current_frame;
+            s.text(format!(
+                "Frame {current_frame} of {start_frame}-{end_frame}"
+            ))?;
+            if s.slider("Timeline", &mut frame, start_frame, end_frame)? {
+                if let Err(err) = self.seek_replay(s, frame) {
+                    self.error = Some(err.to_string());
+                }
+            }
+            self.render_replay_controls(s)?;
+        } else {
+            s.text(format!(
+                "Recording frame {current_frame}, started at {start_frame}"
+            ))?;
+        }
+        if let Some(initial) = self.replay.initial {
+            s.text(format!("Initial State: {initial}"))?;
+        }
+        let lag_frames = self.replay.lag_frames_since_start(self.control_deck.cpu());
+        if self.replay.mode == ReplayMode::Playback {
+            s.text(format!(
+                "Lag Frames: {lag_frames} of {}",
+                self.replay.lag_frames
+            ))?;
+        } else {
+            s.text(format!("Lag Frames: {lag_frames}"))?;
+        }
+        s.same_line(None);
+        s.help_marker("Frames where the game didn't read the controllers.")?;
+        if s.button("Add Bookmark")? {
+            self.add_replay_bookmark();
+        }
+
+        s.spacing()?;
+        s.text("Bookmarks:")?;
+        if self.replay.bookmarks.is_empty() {
+            s.text("None")?;
+        }
+        let mut jump = None;
+        let mut remove = None;
+        for (i, bookmark) in self.replay.bookmarks.iter_mut().enumerate() {
+            s.bullet(format!("Frame {}", bookmark.frame))?;
+            if self.replay.mode == ReplayMode::Playback {
+                s.same_line(None);
+                if s.button(format!("Jump##bookmark{i}"))? {
+                    jump = Some(bookmark.frame);
+                }
+            }
+            s.same_line(None);
+            if s.button(format!("Remove##bookmark{i}"))? {
+                remove = Some(i);
+            }
+            s.indent()?;
+            let changed = s.text_field(format!("Name##bookmark{i}"), &mut bookmark.name)?;
+            s.indent()?;
+            if s.text_field(format!("Note##bookmark{i}"), &mut bookmark.note)? || changed {
+                self.replay.bookmarks_changed = true;
+            }
+        }
+        if let Some(i) = remove {
+            self.replay.bookmarks.remove(i);
+            self.replay.bookmarks_changed = true;
+        }
+        if let Some(frame) = jump {
+            if let Err(err) = self.seek_replay(s, frame) {
+                self.error = Some(err.to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn render_state_bookmarks(&mut self, s: &mut PixState) -> PixResult<()> {
+        self.render_heading(s, "Bookmarks")?;
+
+        if self.control_deck.loaded_rom().is_none() {
+            s.text("Load a ROM to add bookmarks.")?;
+            return Ok(());
+        }
+
+        s.text(format!("Frame {}", self.control_deck.frame_number()))?;
+        if s.button("Add Bookmark")? {
+            self.add_state_bookmark();
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Bookmarks save the state at the current frame for this game. Jumping to a bookmark \
+            while recording a replay discards the input recorded after it.",
+        )?;
+
+        s.spacing()?;
+        if self.state_bookmarks.list.is_empty() {
+            s.text("No bookmarks for this game yet.")?;
+        }
+        let mut jump = None;
+        let mut remove = None;
+        for (i, bookmark) in self.state_bookmarks.list.iter_mut().enumerate() {
+            s.bullet(format!("Frame {}", bookmark.frame))?;
+            s.same_line(None);
+            if s.button(format!("Jump##state_bookmark{i}"))? {
+                jump = Some(i);
+            }
+            s.same_line(None);
+            if s.button(format!("Remove##state_bookmark{i}"))? {
+                remove = Some(i);
+            }
+            s.indent()?;
+            if s.text_field(format!("Name##state_bookmark{i}"), &mut bookmark.name)? {
+                self.state_bookmarks.changed = true;
+            }
+        }
+        if let Some(i) = remove {
+            self.remove_state_bookmark(i);
+        } else if let Some(i) = jump {
+            self.jump_to_state_bookmark(i);
+        }
+
+        Ok(())
+    }
+
+    fn render_gallery(&mut self, s: &mut PixState) -> PixResult<()> {
+        self.render_heading(s, "Screenshots")?;
+
+        let colors = s.theme().colors;
+        let spacing = s.theme().spacing;
+
+        if let Some(ref error) = self.error {
+            s.fill(colors.error);
+            s.wrap(s.width()? - 2 * spacing.frame_pad.x() as u32);
+            s.text(error)?;
+            s.spacing()?;
+        }
+
+        if self.gallery.paths.is_empty() {
+            s.text("No screenshots taken for this game yet.")?;
+            return Ok(());
+        }
+
+        let names: Vec<Cow<'_, str>> = self
+            .gallery
+            .paths
+            .iter()
+            .map(|p| p.file_name().unwrap_or(p.as_os_str()).to_string_lossy())
+            .collect();
+        s.fill(colors.secondary);
+        s.next_width((s.ui_width()? - spacing.scroll_size) as u32);
+        s.select_list(
+            format!("{}", self.config.screenshot_dir.to_string_lossy()),
+            &mut self.gallery.selected,
+            &names,
+            5,
+        )?;
+        if s.button("Delete")? {
+            self.delete_screenshot();
+            return Ok(());
+        }
+        s.spacing()?;
+
+        // Fit the preview into the remaining space, preserving its aspect ratio
+        let pos = s.cursor_pos();
+        let max_width = s.ui_width()? as f32;
+        let max_height = (s.height()? as i32 - pos.y() - spacing.frame_pad.y()) as f32;
+        match self.gallery.preview() {
+            Ok(Some(image)) => {
+                let (width, height) = (image.width() as f32, image.height() as f32);
+                let scale = (max_width / width).min(max_height / height).min(1.0);
+                s.image_resized(
+                    image,
+                    rect![
+                        pos.x(),
+                        pos.y(),
+                        (width * scale) as i32,
+                        (height * scale) as i32
+                    ],
+                )?;
+            }
+            Ok(None) => (),
+            Err(err) => {
+                log::error!("{err:?}");
+                s.fill(colors.error);
+                s.text("Failed to load screenshot preview")?;
+            }
+        }
+
+        Ok(())
+    }
+
     fn render_about(&mut self, s: &mut PixState) -> PixResult<()> {
         self.render_heading(s, &format!("TetaNES {}", env!("CARGO_PKG_VERSION")))?;
 
         if s.link("github.com/lukexor/tetanes")? {
             s.open_url("https://github.com/lukexor/tetanes")?;
         }
+        s.same_line(None);
+        if s.link("What's New")? {
+            self.open_menu(s, Menu::WhatsNew)?;
+        }
         s.spacing()?;
 
         s.bullet("Configuration: ")?;
@@ -448,6 +1662,22 @@ impl Nes {
         s.same_line(None);
         s.monospace(config_path(SRAM_DIR).to_string_lossy())?;
 
+        s.bullet("Video recordings: ")?;
+        s.same_line(None);
+        s.monospace(self.config.video_recording_dir.to_string_lossy())?;
+
+        s.bullet("Sound recordings: ")?;
+        s.same_line(None);
+        s.monospace(self.config.sound_recording_dir.to_string_lossy())?;
+
+        s.bullet("Screenshots: ")?;
+        s.same_line(None);
+        s.monospace(self.config.screenshot_dir.to_string_lossy())?;
+
+        s.bullet("Quick slots: ")?;
+        s.same_line(None);
+        s.text("RAM only, cleared on exit or ROM change")?;
+
         Ok(())
     }
 }
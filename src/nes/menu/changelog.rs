@@ -0,0 +1,385 @@
+//! "What's New" menu generated from the changelog embedded at build time.
+//!
+//! The menu opens once after updating to a new version, listing the changes in every release
+//! since the last version run. Settings and windows named in `backticks` in a change get a link
+//! that jumps straight to them.
+
+use crate::{
+    input::Slot,
+    nes::{
+        event::{Action, DebugAction},
+        menu::types::{ConfigSection, Menu, Player},
+        Nes,
+    },
+};
+use pix_engine::prelude::*;
+
+const CHANGELOG: &str = include_str!("../../../CHANGELOG.md");
+const UNRELEASED: &str = "Unreleased";
+
+/// Names used in the changelog for settings and windows, and how to open them.
+const LINKS: &[(&str, Action)] = &[
+    ("Config", Action::Menu(Menu::Config(ConfigSection::General))),
+    ("Keybind", Action::Menu(Menu::Keybind(Player::One))),
+    (
+        "Share Player 1 Input",
+        Action::Menu(Menu::Keybind(Player::One)),
+    ),
+    ("Load ROM", Action::Menu(Menu::LoadRom)),
+    ("About", Action::Menu(Menu::About)),
+    ("Command Palette", Action::Menu(Menu::Commands)),
+    ("Screenshot Gallery", Action::Menu(Menu::Gallery)),
+    ("Bookmarks", Action::Menu(Menu::Bookmarks)),
+    (
+        "4-Player",
+        Action::Menu(Menu::Config(ConfigSection::General)),
+    ),
+    ("Zapper", Action::Menu(Menu::Config(ConfigSection::General))),
+    (
+        "Replay Format",
+        Action::Menu(Menu::Config(ConfigSection::General)),
+    ),
+    (
+        "Apply ROM Patches",
+        Action::Menu(Menu::Config(ConfigSection::General)),
+    ),
+    (
+        "Keep Display Awake While Playing",
+        Action::Menu(Menu::Config(ConfigSection::General)),
+    ),
+    (
+        "Dim When Idle",
+        Action::Menu(Menu::Config(ConfigSection::General)),
+    ),
+    (
+        "Cycle Accurate",
+        Action::Menu(Menu::Config(ConfigSection::Emulation)),
+    ),
+    (
+        "Dynamic Rate Control",
+        Action::Menu(Menu::Config(ConfigSection::Audio)),
+    ),
+    ("VSync", Action::Menu(Menu::Config(ConfigSection::Video))),
+    ("HD Packs", Action::Menu(Menu::Config(ConfigSection::Video))),
+    ("Take Control", Action::Menu(Menu::Replay)),
+    (
+        "CPU/PPU Alignment",
+        Action::Menu(Menu::Config(ConfigSection::Emulation)),
+    ),
+    (
+        "Warn on Region Mismatch",
+        Action::Menu(Menu::Config(ConfigSection::Emulation)),
+    ),
+    (
+        "Screenshot Format",
+        Action::Menu(Menu::Config(ConfigSection::Video)),
+    ),
+    (
+        "Screenshot Burst",
+        Action::Menu(Menu::Config(ConfigSection::Video)),
+    ),
+    ("APU Viewer", Action::Debug(DebugAction::ToggleApuDebugger)),
+    ("SRAM Editor", Action::Debug(DebugAction::ToggleSramEditor)),
+];
+
+/// A released version, or unreleased changes, and its changes grouped by kind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct Release {
+    pub(crate) version: String,
+    pub(crate) date: Option<String>,
+    /// Changes under each heading, like `Added` or `Fixed`.
+    pub(crate) sections: Vec<(String, Vec<String>)>,
+}
+
+/// Parses a changelog in the [Keep a Changelog](https://keepachangelog.com) format, newest
+/// release first. Changes wrapped over several lines are joined.
+pub(crate) fn parse(changelog: &str) -> Vec<Release> {
+    let mut releases: Vec<Release> = vec![];
+    for line in changelog.lines() {
+        if let Some(heading) = line.strip_prefix("## ") {
+            let (version, date) = match heading.split_once(" - ") {
+                Some((version, date)) => (version, Some(date.trim().to_string())),
+                None => (heading, None),
+            };
+            releases.push(Release {
+                version: version.trim().trim_matches(['[', ']']).to_string(),
+                date,
+                sections: vec![],
+            });
+            continue;
+        }
+        let Some(release) = releases.last_mut() else {
+            continue;
+        };
+        if let Some(heading) = line.strip_prefix("### ") {
+            release.sections.push((heading.trim().to_string(), vec![]));
+        } else if let Some((_, changes)) = release.sections.last_mut() {
+            if let Some(change) = line.strip_prefix("- ") {
+                changes.push(change.trim().to_string());
+            } else if line.starts_with("  ") && !line.trim().is_empty() {
+                if let Some(change) = changes.last_mut() {
+                    change.push(' ');
+                    change.push_str(line.trim());
+                }
+            }
+        }
+    }
+    releases.retain(|release| {
+        release
+            .sections
+            .iter()
+            .any(|(_, changes)| !changes.is_empty())
+    });
+    releases
+}
+
+/// Splits a version like `0.8.0` into its numbers to compare.
+fn version_numbers(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split(['.', '-'])
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+/// Releases newer than `since`, newest first. Unreleased changes are always included.
+pub(crate) fn releases_since(releases: &[Release], since: &str) -> Vec<Release> {
+    let since = version_numbers(since);
+    releases
+        .iter()
+        .take_while(|release| {
+            release.version == UNRELEASED || version_numbers(&release.version) > since
+        })
+        .cloned()
+        .collect()
+}
+
+/// Removes markdown formatting from a change, keeping the text of links.
+pub(crate) fn plain_text(change: &str) -> String {
+    let mut text = String::with_capacity(change.len());
+    let mut rest = change;
+    while let Some(start) = rest.find('[') {
+        let link = rest[start + 1..].split_once("](").and_then(|(label, url)| {
+            url.find(')')
+                .map(|end| (label, &url[end + 1..]))
+                .filter(|_| !label.contains(']'))
+        });
+        match link {
+            Some((label, after)) => {
+                text.push_str(&rest[..start]);
+                text.push_str(label);
+                rest = after;
+            }
+            None => {
+                text.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    text.replace('`', "")
+}
+
+/// Settings and windows named in `backticks` in a change that can be opened.
+pub(crate) fn links(change: &str) -> Vec<(&'static str, Action)> {
+    let mut links = vec![];
+    for name in change.split('`').skip(1).step_by(2) {
+        if let Some(&link) = LINKS
+            .iter()
+            .find(|(link, _)| link.eq_ignore_ascii_case(name))
+        {
+            if !links.contains(&link) {
+                links.push(link);
+            }
+        }
+    }
+    links
+}
+
+impl Nes {
+    /// Opens the What's New menu the first time a new version runs. Fresh installs skip it in
+    /// favor of the tutorial.
+    pub(crate) fn check_whats_new(&mut self, s: &mut PixState) -> PixResult<()> {
+        let version = env!("CARGO_PKG_VERSION");
+        if self.config.last_version == version {
+            return Ok(());
+        }
+        let last_version = std::mem::replace(&mut self.config.last_version, version.to_string());
+        self.save_config();
+        if self.config.show_tutorial || self.debug {
+            return Ok(());
+        }
+        let releases = parse(CHANGELOG);
+        self.whats_new = if last_version.is_empty() {
+            // Updated from a version that didn't keep track, so only show the latest changes
+            releases.into_iter().take(1).collect()
+        } else {
+            releases_since(&releases, &last_version)
+        };
+        if !self.whats_new.is_empty() {
+            self.open_menu(s, Menu::WhatsNew)?;
+        }
+        Ok(())
+    }
+
+    /// Shows the latest changes when opened from the menu.
+    pub(crate) fn open_whats_new(&mut self) {
+        if self.whats_new.is_empty() {
+            self.whats_new = parse(CHANGELOG).into_iter().take(2).collect();
+        }
+    }
+
+    pub(crate) fn render_whats_new(&mut self, s: &mut PixState) -> PixResult<()> {
+        self.render_heading(
+            s,
+            &format!("What's New in TetaNES {}", env!("CARGO_PKG_VERSION")),
+        )?;
+
+        let mut open = None;
+        for release in &self.whats_new {
+            match release.date {
+                Some(ref date) => s.text(format!("{} - {date}", release.version))?,
+                None => s.text(&release.version)?,
+            }
+            for (heading, changes) in &release.sections {
+                if changes.is_empty() {
+                    continue;
+                }
+                s.spacing()?;
+                s.text(format!("{heading}:"))?;
+                for (i, change) in changes.iter().enumerate() {
+                    s.bullet(plain_text(change))?;
+                    for (name, action) in links(change) {
+                        s.same_line(None);
+                        if s.link(format!("Open {name}##{}{heading}{i}", release.version))? {
+                            open = Some(action);
+                        }
+                    }
+                }
+            }
+            s.spacing()?;
+        }
+        s.spacing()?;
+        let done = s.button("Continue")?;
+
+        if let Some(action) = open {
+            self.open_whats_new_link(s, action)?;
+        } else if done {
+            self.whats_new.clear();
+            if self.control_deck.is_running() {
+                self.exit_menu(s)?;
+            } else {
+                self.open_menu(s, Menu::LoadRom)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn open_whats_new_link(&mut self, s: &mut PixState, action: Action) -> PixResult<()> {
+        match action {
+            Action::Menu(menu) => self.open_menu(s, menu),
+            action => {
+                // Windows open over the game, so leave the menu if there's one to return to
+                if self.control_deck.is_running() {
+                    self.exit_menu(s)?;
+                }
+                self.handle_action(s, Slot::One, action, true, false)?;
+                self.handle_action(s, Slot::One, action, false, false)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_CHANGELOG: &str = "\
+# Changelog
+
+## [Unreleased]
+
+### Added
+
+- Added the `SRAM Editor`.
+
+## [0.9.0] - 2023-01-02
+
+### Fixed
+
+- Fixed reset causing
+  segfault. [#50](https://github.com/lukexor/tetanes/issues/50)
+
+## [0.8.0] - 2022-06-20
+
+### Added
+
+- Added `Config` menu.
+
+### Removed
+";
+
+    #[test]
+    fn parse_changelog() {
+        let releases = parse(TEST_CHANGELOG);
+        assert_eq!(releases.len(), 3);
+        assert_eq!(releases[0].version, UNRELEASED);
+        assert_eq!(releases[0].date, None);
+        assert_eq!(releases[1].version, "0.9.0");
+        assert_eq!(releases[1].date.as_deref(), Some("2023-01-02"));
+        assert_eq!(
+            releases[1].sections,
+            [(
+                "Fixed".to_string(),
+                vec![
+                    "Fixed reset causing segfault. [#50](https://github.com/lukexor/tetanes/issues/50)"
+                        .to_string()
+                ]
+            )]
+        );
+        assert_eq!(releases[2].sections[1], ("Removed".to_string(), vec![]));
+    }
+
+    #[test]
+    fn releases_since_version() {
+        let releases = parse(TEST_CHANGELOG);
+        let versions = |since| {
+            releases_since(&releases, since)
+                .into_iter()
+                .map(|release| release.version)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(versions("0.8.0"), [UNRELEASED, "0.9.0"]);
+        assert_eq!(versions("0.7.1"), [UNRELEASED, "0.9.0", "0.8.0"]);
+        assert_eq!(versions("0.9.0"), [UNRELEASED]);
+    }
+
+    #[test]
+    fn plain_text_and_links() {
+        let change =
+            "Added `4-Player` support. [#32](https://github.com/lukexor/tetanes/issues/32)";
+        assert_eq!(plain_text(change), "Added 4-Player support. #32");
+        assert_eq!(
+            plain_text("Kept [brackets] and `code`"),
+            "Kept [brackets] and code"
+        );
+        assert_eq!(
+            links(change),
+            [(
+                "4-Player",
+                Action::Menu(Menu::Config(ConfigSection::General))
+            )]
+        );
+        assert!(links("Fixed `OAM` emulation.").is_empty());
+    }
+
+    #[test]
+    fn embedded_changelog_parses() {
+        let releases = parse(CHANGELOG);
+        assert!(releases
+            .iter()
+            .any(|release| release.version == env!("CARGO_PKG_VERSION")));
+    }
+}
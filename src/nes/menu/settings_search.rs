@@ -0,0 +1,550 @@
+//! Search box for the Configuration menu, finding settings on any page by name or description.
+
+use crate::nes::{
+    menu::types::{
+        ConfigSection::{self, Audio, Emulation, General, Video},
+        Menu,
+    },
+    Mode, Nes,
+};
+use pix_engine::prelude::*;
+
+/// A setting that can be searched for, and where to find it.
+#[derive(Debug, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct SettingEntry {
+    pub(crate) name: &'static str,
+    pub(crate) section: ConfigSection,
+    /// The collapsible group on the page the setting is under, if any.
+    pub(crate) group: Option<&'static str>,
+    pub(crate) description: &'static str,
+}
+
+const fn entry(
+    section: ConfigSection,
+    group: Option<&'static str>,
+    name: &'static str,
+    description: &'static str,
+) -> SettingEntry {
+    SettingEntry {
+        name,
+        section,
+        group,
+        description,
+    }
+}
+
+/// Every setting in the Configuration menu, in the order shown.
+const SETTINGS: &[SettingEntry] = &[
+    entry(
+        General,
+        None,
+        "Pause in Background",
+        "Pause when the window loses focus.",
+    ),
+    entry(
+        General,
+        None,
+        "Pause on Sleep and Low Battery",
+        "Pause and save when the system wakes from sleep or the battery runs low.",
+    ),
+    entry(
+        General,
+        None,
+        "Low Battery (%)",
+        "Battery level to pause at.",
+    ),
+    entry(
+        General,
+        None,
+        "Keep Display Awake While Playing",
+        "Stop the screensaver and display sleep while a game runs.",
+    ),
+    entry(
+        General,
+        None,
+        "Dim When Idle (minutes)",
+        "Dim the screen without any input.",
+    ),
+    entry(
+        General,
+        None,
+        "Show Tutorial on Startup",
+        "Show the tutorial.",
+    ),
+    entry(
+        General,
+        None,
+        "Watch ROM Folder",
+        "Notify when ROMs are added or removed.",
+    ),
+    entry(
+        General,
+        None,
+        "Apply ROM Patches",
+        "Soft-patch ROMs with IPS, BPS and UPS patches found next to them.",
+    ),
+    entry(
+        General,
+        None,
+        "Control Server",
+        "Drive the emulator from external tools with JSON-RPC.",
+    ),
+    entry(
+        General,
+        None,
+        "Save Slot",
+        "Slot used by save and load state.",
+    ),
+    entry(
+        General,
+        None,
+        "Restore Cheats from States",
+        "Restore Game Genie codes when loading a save state.",
+    ),
+    entry(General, None, "Enable Rewind", "Rewind gameplay."),
+    entry(
+        General,
+        None,
+        "Rewind Frames",
+        "Frames between rewind snapshots.",
+    ),
+    entry(
+        General,
+        None,
+        "Rewind Buffer Size (MB)",
+        "Memory used for rewinding.",
+    ),
+    entry(
+        General,
+        None,
+        "Session Replay (minutes)",
+        "Keep recent input to save as a replay.",
+    ),
+    entry(
+        General,
+        None,
+        "Replay Format",
+        "Save replays as binary, JSON or FM2 movies.",
+    ),
+    entry(General, None, "Enable Zapper", "Light gun on port 2."),
+    entry(
+        General,
+        None,
+        "Motion Aim",
+        "Aim the Zapper with a controller gyro.",
+    ),
+    entry(General, None, "Sensitivity", "Motion aim sensitivity."),
+    entry(
+        General,
+        None,
+        "Four Player Mode",
+        "Four Score or Satellite multitap.",
+    ),
+    entry(Emulation, None, "NES Region", "NTSC, PAL or Dendy timing."),
+    entry(
+        Emulation,
+        None,
+        "Warn on Region Mismatch",
+        "Warn when a PAL-only or NTSC-only game runs with the wrong timing.",
+    ),
+    entry(
+        Emulation,
+        None,
+        "Power-up RAM State",
+        "RAM contents at power on.",
+    ),
+    entry(
+        Emulation,
+        None,
+        "RAM Seed",
+        "Seed for random power-up RAM that's the same on every machine.",
+    ),
+    entry(
+        Emulation,
+        None,
+        "CPU/PPU Alignment",
+        "Master clocks the PPU runs behind the CPU at power up.",
+    ),
+    entry(Emulation, None, "Speed", "Emulation speed."),
+    entry(
+        Emulation,
+        None,
+        "Skip Frames",
+        "Skip drawing frames when fast forwarding.",
+    ),
+    entry(
+        Emulation,
+        None,
+        "Concurrent D-Pad",
+        "Allow pressing opposite directions at the same time.",
+    ),
+    entry(
+        Emulation,
+        None,
+        "Cycle Accurate",
+        "Clock the PPU and APU every CPU cycle.",
+    ),
+    entry(
+        Emulation,
+        None,
+        "Unofficial Opcodes",
+        "Log or trap undocumented 6502 opcodes.",
+    ),
+    entry(
+        Emulation,
+        None,
+        "NWC Timer",
+        "Nintendo World Championships DIP switches.",
+    ),
+    entry(
+        Emulation,
+        None,
+        "Battery-backed Save RAM",
+        "Keep Save RAM between sessions for this game.",
+    ),
+    entry(
+        Emulation,
+        None,
+        "Write-protect PRG-RAM",
+        "Ignore writes to PRG-RAM for this game.",
+    ),
+    entry(Audio, None, "Enabled", "Sound on or off."),
+    entry(Audio, None, "Sample Rate", "Audio output sample rate."),
+    entry(Audio, None, "Backend", "Audio driver."),
+    entry(Audio, None, "Device", "Audio output device."),
+    entry(
+        Audio,
+        None,
+        "Second Output",
+        "Also play audio on another device, like for streaming.",
+    ),
+    entry(
+        Audio,
+        None,
+        "Second Output Volume",
+        "Volume of the second output.",
+    ),
+    entry(Audio, None, "Buffer Size", "Audio buffer size and latency."),
+    entry(
+        Audio,
+        None,
+        "Dynamic Rate Control",
+        "Adjust the sample rate to avoid crackle.",
+    ),
+    entry(
+        Audio,
+        None,
+        "Dynamic Rate Delta",
+        "How far the sample rate can change.",
+    ),
+    entry(
+        Audio,
+        None,
+        "Target Latency (ms)",
+        "Audio latency to aim for.",
+    ),
+    entry(
+        Audio,
+        None,
+        "Mute Slow Motion",
+        "Mute instead of lowering the pitch when slowed down.",
+    ),
+    entry(Audio, Some("Channels"), "Pulse 1", "Mute APU channels."),
+    entry(Audio, Some("Channels"), "Pulse 2", "Mute APU channels."),
+    entry(Audio, Some("Channels"), "Triangle", "Mute APU channels."),
+    entry(Audio, Some("Channels"), "Noise", "Mute APU channels."),
+    entry(Audio, Some("Channels"), "DMC", "Mute APU channels."),
+    entry(Audio, Some("Mixer"), "Master", "Master volume."),
+    entry(Audio, Some("Mixer"), "Expansion", "Expansion audio volume."),
+    entry(Audio, Some("Mixer"), "Bass (dB)", "Equalizer bass gain."),
+    entry(
+        Audio,
+        Some("Mixer"),
+        "Treble (dB)",
+        "Equalizer treble gain.",
+    ),
+    entry(
+        Audio,
+        Some("Mixer"),
+        "Save Per Game",
+        "Remember mixer settings for each game.",
+    ),
+    entry(
+        Audio,
+        None,
+        "Expansion Audio",
+        "Balance cartridge sound chips like VRC6 and FDS.",
+    ),
+    entry(
+        Audio,
+        None,
+        "APU Mixing",
+        "Accurate nonlinear or legacy lookup table mixing.",
+    ),
+    entry(
+        Audio,
+        None,
+        "Reduce DMC Pops",
+        "Soften clicks from DMC output level writes.",
+    ),
+    entry(
+        Audio,
+        None,
+        "Recording Format",
+        "File format for sound recordings.",
+    ),
+    entry(
+        Audio,
+        None,
+        "Record Channel Stems",
+        "Record each channel to a separate file.",
+    ),
+    entry(Video, None, "Scale", "Window size."),
+    entry(Video, None, "Filter", "Video filter, like NTSC."),
+    entry(
+        Video,
+        None,
+        "Color Blindness Filter",
+        "Correct or simulate color vision deficiencies.",
+    ),
+    entry(
+        Video,
+        None,
+        "Simulate Only",
+        "Show how colors appear with the color blindness filter.",
+    ),
+    entry(
+        Video,
+        None,
+        "Adaptive Filter Resolution",
+        "Filter fewer scanlines when the filter can't keep up.",
+    ),
+    entry(Video, None, "Bezel", "Artwork around the game."),
+    entry(
+        Video,
+        None,
+        "HD Packs",
+        "Replace tiles with high-resolution art.",
+    ),
+    entry(Video, None, "Fullscreen", "Fill the display."),
+    entry(
+        Video,
+        None,
+        "VSync Enabled",
+        "Sync to the display refresh rate to avoid tearing.",
+    ),
+    entry(
+        Video,
+        None,
+        "Mini View",
+        "Small borderless window that keeps playing in the background.",
+    ),
+    entry(
+        Video,
+        None,
+        "Always on Top",
+        "Keep the window above other windows.",
+    ),
+    entry(
+        Video,
+        None,
+        "Click-Through",
+        "Let mouse clicks go to the windows behind.",
+    ),
+    entry(
+        Video,
+        None,
+        "Frame Pacing",
+        "Whole frames or elapsed time on mismatched displays.",
+    ),
+    entry(
+        Video,
+        None,
+        "Sync Mode",
+        "Sync emulation to video or audio.",
+    ),
+    entry(
+        Video,
+        None,
+        "Video Recording Format",
+        "File format for video recordings.",
+    ),
+    entry(Video, None, "Clip Format", "File format for clips."),
+    entry(
+        Video,
+        None,
+        "Clip Buffer (seconds)",
+        "Keep recent gameplay to save as a clip.",
+    ),
+    entry(
+        Video,
+        None,
+        "Screenshot Format",
+        "Save PNGs, raw palette indices or both.",
+    ),
+    entry(
+        Video,
+        None,
+        "Save Raw Screenshots",
+        "Also save the unfiltered frame with screenshots.",
+    ),
+    entry(
+        Video,
+        None,
+        "Copy Screenshots to Clipboard",
+        "Copy each screenshot to the clipboard.",
+    ),
+    entry(
+        Video,
+        None,
+        "Screenshot Burst (frames)",
+        "Capture consecutive frames per screenshot.",
+    ),
+    entry(
+        Video,
+        None,
+        "On-Screen Display",
+        "FPS, lag counter, input display and other overlays.",
+    ),
+    entry(
+        Video,
+        Some("Diff Overlay"),
+        "Reference Image",
+        "Highlight pixels that differ from a capture.",
+    ),
+    entry(
+        Video,
+        Some("Diff Overlay"),
+        "Show Overlay",
+        "Highlight pixels that differ from a capture.",
+    ),
+    entry(
+        Video,
+        Some("Diff Overlay"),
+        "Tolerance",
+        "Color difference ignored by the diff overlay.",
+    ),
+];
+
+/// Settings matching every word in `query`, matches by name first.
+pub(crate) fn search(query: &str) -> Vec<&'static SettingEntry> {
+    let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    if words.is_empty() {
+        return vec![];
+    }
+    let mut matches: Vec<_> = SETTINGS
+        .iter()
+        .filter_map(|setting| {
+            let name = setting.name.to_lowercase();
+            let group = setting.group.unwrap_or_default().to_lowercase();
+            let description = setting.description.to_lowercase();
+            let mut name_matches = 0;
+            for word in &words {
+                if name.contains(word.as_str()) {
+                    name_matches += 1;
+                } else if !group.contains(word.as_str()) && !description.contains(word.as_str()) {
+                    return None;
+                }
+            }
+            Some((name_matches, setting))
+        })
+        .collect();
+    // Stable sort keeps the listed order for equal scores
+    matches.sort_by_key(|&(name_matches, _)| std::cmp::Reverse(name_matches));
+    matches.into_iter().map(|(_, setting)| setting).collect()
+}
+
+#[derive(Default, Debug)]
+#[must_use]
+pub(crate) struct SettingsSearch {
+    query: String,
+    /// The setting last jumped to, pointed out on its page.
+    found: Option<&'static SettingEntry>,
+}
+
+impl Nes {
+    /// Renders the search box, and matching settings in place of the current page while
+    /// searching. Returns whether the page should be shown.
+    pub(crate) fn render_settings_search(&mut self, s: &mut PixState) -> PixResult<bool> {
+        s.next_width(300);
+        if s.text_field("Search Settings", &mut self.settings_search.query)? {
+            self.settings_search.found = None;
+        }
+        let query = self.settings_search.query.trim();
+        if query.is_empty() {
+            if let Some(found) = self.settings_search.found {
+                s.push();
+                s.fill(s.theme().colors.secondary);
+                match found.group {
+                    Some(group) => s.text(format!("{} is under {group} below.", found.name))?,
+                    None => s.text(format!("{} is below.", found.name))?,
+                }
+                s.pop();
+            }
+            s.spacing()?;
+            return Ok(true);
+        }
+
+        s.spacing()?;
+        let matches = search(query);
+        if matches.is_empty() {
+            s.push();
+            s.fill(s.theme().colors.secondary);
+            s.text("No matching settings")?;
+            s.pop();
+        }
+        for (i, setting) in matches.into_iter().enumerate() {
+            let location = match setting.group {
+                Some(group) => format!("{} > {group}", setting.section.as_ref()),
+                None => setting.section.as_ref().to_string(),
+            };
+            if s.menu(format!("{} ({location})##setting{i}", setting.name))? {
+                self.settings_search.query.clear();
+                self.settings_search.found = Some(setting);
+                self.mode = Mode::InMenu(Menu::Config(setting.section));
+            }
+            s.push();
+            s.fill(s.theme().colors.secondary);
+            s.indent()?;
+            s.text(setting.description)?;
+            s.pop();
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search_by_name_and_description() {
+        let names = |query| {
+            search(query)
+                .into_iter()
+                .map(|setting| setting.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("vsync"), ["VSync Enabled"]);
+        assert_eq!(names("  "), Vec::<&str>::new());
+        // Name matches come before description matches
+        let matches = names("rewind");
+        assert_eq!(matches[..2], ["Enable Rewind", "Rewind Frames"]);
+        assert!(names("tearing").contains(&"VSync Enabled"));
+        assert!(names("mixer treble").contains(&"Treble (dB)"));
+        assert!(names("zapper nothing").is_empty());
+    }
+
+    #[test]
+    fn settings_are_in_the_menu() {
+        let menu = include_str!("../menu.rs");
+        for setting in SETTINGS {
+            assert!(
+                menu.contains(&format!("\"{}\"", setting.name)),
+                "{} isn't in the menu",
+                setting.name
+            );
+        }
+    }
+}
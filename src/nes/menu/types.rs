@@ -1,3 +1,4 @@
+use crate::input::Slot;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
@@ -33,7 +34,12 @@ pub(crate) enum Menu {
     Config(ConfigSection),
     Keybind(Player),
     LoadRom,
+    Replay,
+    Bookmarks,
+    Gallery,
     About,
+    Commands,
+    WhatsNew,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -63,6 +69,17 @@ impl AsRef<str> for Player {
     }
 }
 
+impl From<Player> for Slot {
+    fn from(player: Player) -> Self {
+        match player {
+            Player::One => Self::One,
+            Player::Two => Self::Two,
+            Player::Three => Self::Three,
+            Player::Four => Self::Four,
+        }
+    }
+}
+
 impl From<usize> for Player {
     fn from(value: usize) -> Self {
         match value {
@@ -136,6 +153,7 @@ impl From<f32> for SampleRate {
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub(crate) enum EmuSpeed {
+    S10,
     S25,
     S50,
     S75,
@@ -151,6 +169,7 @@ impl EmuSpeed {
     #[must_use]
     pub(crate) const fn as_slice() -> &'static [Self] {
         &[
+            Self::S10,
             Self::S25,
             Self::S50,
             Self::S75,
@@ -166,6 +185,7 @@ impl EmuSpeed {
     #[must_use]
     pub(crate) const fn as_f32(self) -> f32 {
         match self {
+            Self::S10 => 0.1,
             Self::S25 => 0.25,
             Self::S50 => 0.50,
             Self::S75 => 0.75,
@@ -181,6 +201,7 @@ impl EmuSpeed {
 impl AsRef<str> for EmuSpeed {
     fn as_ref(&self) -> &str {
         match self {
+            Self::S10 => "10%",
             Self::S25 => "25%",
             Self::S50 => "50%",
             Self::S75 => "75%",
@@ -196,13 +217,14 @@ impl AsRef<str> for EmuSpeed {
 impl From<usize> for EmuSpeed {
     fn from(value: usize) -> Self {
         match value {
-            0 => Self::S25,
-            1 => Self::S50,
-            2 => Self::S75,
-            4 => Self::S125,
-            5 => Self::S150,
-            6 => Self::S175,
-            7 => Self::S200,
+            0 => Self::S10,
+            1 => Self::S25,
+            2 => Self::S50,
+            3 => Self::S75,
+            5 => Self::S125,
+            6 => Self::S150,
+            7 => Self::S175,
+            8 => Self::S200,
             _ => Self::S100,
         }
     }
@@ -210,6 +232,10 @@ impl From<usize> for EmuSpeed {
 
 impl From<f32> for EmuSpeed {
     fn from(value: f32) -> Self {
-        Self::from(((4.0 * value) as usize).saturating_sub(1))
+        if value < 0.25 {
+            Self::S10
+        } else {
+            Self::from((4.0 * value) as usize)
+        }
     }
 }
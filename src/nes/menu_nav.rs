@@ -0,0 +1,387 @@
+//! Navigation of menus and dialogs with re-bindable keys and controller buttons.
+//!
+//! Navigation bindings are kept separate from gameplay bindings and only apply while a menu or
+//! dialog is open, so they can share keys and buttons with gameplay, such as the controller A
+//! button confirming in menus and pressing A in game.
+
+use crate::nes::{
+    menu::{types::ConfigSection, Menu, Player},
+    state::ReplayMode,
+    Mode, Nes,
+};
+use pix_engine::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// An action used to navigate menus and dialogs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub(crate) enum NavAction {
+    Confirm,
+    Cancel,
+    Up,
+    Down,
+    PrevTab,
+    NextTab,
+}
+
+impl NavAction {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[
+            Self::Confirm,
+            Self::Cancel,
+            Self::Up,
+            Self::Down,
+            Self::PrevTab,
+            Self::NextTab,
+        ]
+    }
+}
+
+impl AsRef<str> for NavAction {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Confirm => "Confirm",
+            Self::Cancel => "Cancel/Back",
+            Self::Up => "Up",
+            Self::Down => "Down",
+            Self::PrevTab => "Previous Tab",
+            Self::NextTab => "Next Tab",
+        }
+    }
+}
+
+/// A key or controller button bound to a [`NavAction`]. Keys are matched without modifiers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[must_use]
+pub(crate) enum NavInput {
+    Key(Key),
+    Button(ControllerButton),
+}
+
+impl fmt::Display for NavInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Key(key) => write!(f, "{key:?}"),
+            Self::Button(button) => write!(f, "{button:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct NavKeyBinding {
+    pub(crate) key: Key,
+    pub(crate) action: NavAction,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct NavButtonBinding {
+    pub(crate) button: ControllerButton,
+    pub(crate) action: NavAction,
+}
+
+/// Keys and controller buttons used to navigate menus and dialogs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub(crate) struct NavBindings {
+    pub(crate) keys: Vec<NavKeyBinding>,
+    pub(crate) buttons: Vec<NavButtonBinding>,
+}
+
+impl Default for NavBindings {
+    fn default() -> Self {
+        let keys = [
+            (Key::Return, NavAction::Confirm),
+            (Key::KpEnter, NavAction::Confirm),
+            (Key::Escape, NavAction::Cancel),
+            (Key::Up, NavAction::Up),
+            (Key::Down, NavAction::Down),
+            (Key::PageUp, NavAction::PrevTab),
+            (Key::PageDown, NavAction::NextTab),
+        ];
+        let buttons = [
+            (ControllerButton::A, NavAction::Confirm),
+            (ControllerButton::B, NavAction::Cancel),
+            (ControllerButton::DPadUp, NavAction::Up),
+            (ControllerButton::DPadDown, NavAction::Down),
+            (ControllerButton::LeftShoulder, NavAction::PrevTab),
+            (ControllerButton::RightShoulder, NavAction::NextTab),
+        ];
+        Self {
+            keys: keys
+                .into_iter()
+                .map(|(key, action)| NavKeyBinding { key, action })
+                .collect(),
+            buttons: buttons
+                .into_iter()
+                .map(|(button, action)| NavButtonBinding { button, action })
+                .collect(),
+        }
+    }
+}
+
+impl NavBindings {
+    pub(crate) fn action(&self, input: NavInput) -> Option<NavAction> {
+        match input {
+            NavInput::Key(key) => self
+                .keys
+                .iter()
+                .find(|bind| bind.key == key)
+                .map(|bind| bind.action),
+            NavInput::Button(button) => self
+                .buttons
+                .iter()
+                .find(|bind| bind.button == button)
+                .map(|bind| bind.action),
+        }
+    }
+
+    /// Inputs bound to `action`, keys first.
+    pub(crate) fn inputs(&self, action: NavAction) -> Vec<NavInput> {
+        let keys = self
+            .keys
+            .iter()
+            .filter(|bind| bind.action == action)
+            .map(|bind| NavInput::Key(bind.key));
+        let buttons = self
+            .buttons
+            .iter()
+            .filter(|bind| bind.action == action)
+            .map(|bind| NavInput::Button(bind.button));
+        keys.chain(buttons).collect()
+    }
+
+    /// Binds `input` to `action`, replacing any action it was bound to before.
+    pub(crate) fn bind(&mut self, input: NavInput, action: NavAction) {
+        match input {
+            NavInput::Key(key) => {
+                self.keys.retain(|bind| bind.key != key);
+                self.keys.push(NavKeyBinding { key, action });
+            }
+            NavInput::Button(button) => {
+                self.buttons.retain(|bind| bind.button != button);
+                self.buttons.push(NavButtonBinding { button, action });
+            }
+        }
+    }
+
+    pub(crate) fn unbind(&mut self, input: NavInput) {
+        match input {
+            NavInput::Key(key) => self.keys.retain(|bind| bind.key != key),
+            NavInput::Button(button) => self.buttons.retain(|bind| bind.button != button),
+        }
+    }
+}
+
+/// Selection state for menus without their own widgets to navigate.
+#[derive(Default, Debug, Copy, Clone)]
+#[must_use]
+pub(crate) struct MenuNav {
+    /// Entry selected in the main menu.
+    pub(crate) selected: usize,
+}
+
+/// The item before or after `current`, wrapping around.
+fn cycle<T: Copy + PartialEq>(items: &[T], current: T, forward: bool) -> T {
+    let index = items.iter().position(|&item| item == current).unwrap_or(0);
+    let index = if forward {
+        (index + 1) % items.len()
+    } else {
+        (index + items.len() - 1) % items.len()
+    };
+    items[index]
+}
+
+impl Nes {
+    /// Entries in the main menu and the menus they open.
+    pub(crate) fn main_menu_entries(&self) -> Vec<(&'static str, Menu)> {
+        let mut entries = vec![
+            ("Config", Menu::Config(ConfigSection::General)),
+            ("Keybinds", Menu::Keybind(Player::One)),
+            ("Load ROM", Menu::LoadRom),
+        ];
+        if self.replay.mode != ReplayMode::Off {
+            entries.push(("Replay", Menu::Replay));
+        }
+        if self.control_deck.loaded_rom().is_some() {
+            entries.push(("Bookmarks", Menu::Bookmarks));
+            entries.push(("Screenshots", Menu::Gallery));
+        }
+        entries.push(("About", Menu::About));
+        entries
+    }
+
+    /// Handles a navigation key while a menu or dialog is open. Returns whether the event was
+    /// consumed. Keys held with modifiers are left for their bindings.
+    pub(crate) fn handle_nav_key(
+        &mut self,
+        s: &mut PixState,
+        event: KeyEvent,
+        pressed: bool,
+    ) -> PixResult<bool> {
+        if event
+            .keymod
+            .intersects(KeyMod::CTRL | KeyMod::ALT | KeyMod::GUI)
+        {
+            return Ok(false);
+        }
+        self.handle_nav_input(s, NavInput::Key(event.key), pressed, event.repeat)
+    }
+
+    /// Handles a navigation button while a menu or dialog is open. Returns whether the event was
+    /// consumed.
+    pub(crate) fn handle_nav_button(
+        &mut self,
+        s: &mut PixState,
+        button: ControllerButton,
+        pressed: bool,
+    ) -> PixResult<bool> {
+        self.handle_nav_input(s, NavInput::Button(button), pressed, false)
+    }
+
+    fn handle_nav_input(
+        &mut self,
+        s: &mut PixState,
+        input: NavInput,
+        pressed: bool,
+        repeat: bool,
+    ) -> PixResult<bool> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let prompted = self.power_monitor.confirming_auto_save();
+        #[cfg(target_arch = "wasm32")]
+        let prompted = false;
+        if !matches!(self.mode, Mode::InMenu(_))
+            && self.confirm_quit.is_none()
+            && self.region_warning.is_none()
+            && !prompted
+        {
+            return Ok(false);
+        }
+        let Some(action) = self.config.nav_bindings.action(input) else {
+            return Ok(false);
+        };
+        if !pressed {
+            return Ok(true);
+        }
+        // Only movement repeats while held
+        if repeat && matches!(action, NavAction::Confirm | NavAction::Cancel) {
+            return Ok(true);
+        }
+        self.navigate(s, action)?;
+        Ok(true)
+    }
+
+    fn navigate(&mut self, s: &mut PixState, action: NavAction) -> PixResult<()> {
+        if let Some((_, confirm)) = &mut self.confirm_quit {
+            match action {
+                NavAction::Confirm => {
+                    *confirm = true;
+                    s.quit();
+                }
+                NavAction::Cancel => {
+                    self.confirm_quit = None;
+                    self.resume_play();
+                }
+                _ => (),
+            }
+            return Ok(());
+        }
+        if self.region_warning.is_some() {
+            match action {
+                NavAction::Confirm => self.close_region_warning(s, true)?,
+                NavAction::Cancel => self.close_region_warning(s, false)?,
+                _ => (),
+            }
+            return Ok(());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.power_monitor.confirming_auto_save() {
+            match action {
+                NavAction::Confirm => self.close_auto_save_prompt(true),
+                NavAction::Cancel => self.close_auto_save_prompt(false),
+                _ => (),
+            }
+            return Ok(());
+        }
+        let Mode::InMenu(menu) = self.mode else {
+            return Ok(());
+        };
+        match (menu, action) {
+            (Menu::Commands, _) => self.navigate_command_palette(s, action)?,
+            // Matches toggling the main menu, going back from other menus
+            (_, NavAction::Cancel) => self.toggle_menu(s, Menu::Main)?,
+            (Menu::Main, NavAction::Up) => {
+                self.menu_nav.selected = self.menu_nav.selected.saturating_sub(1);
+            }
+            (Menu::Main, NavAction::Down) => {
+                let last = self.main_menu_entries().len() - 1;
+                self.menu_nav.selected = (self.menu_nav.selected + 1).min(last);
+            }
+            (Menu::Main, NavAction::Confirm) => {
+                if let Some(&(_, menu)) = self.main_menu_entries().get(self.menu_nav.selected) {
+                    self.open_menu(s, menu)?;
+                }
+            }
+            (Menu::Config(section), NavAction::PrevTab | NavAction::NextTab) => {
+                let forward = action == NavAction::NextTab;
+                let section = cycle(ConfigSection::as_slice(), section, forward);
+                self.mode = Mode::InMenu(Menu::Config(section));
+            }
+            (Menu::Keybind(player), NavAction::PrevTab | NavAction::NextTab) => {
+                let forward = action == NavAction::NextTab;
+                let player = cycle(Player::as_slice(), player, forward);
+                self.mode = Mode::InMenu(Menu::Keybind(player));
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Name of the first key bound to cancel, shown in prompts.
+    pub(crate) fn nav_cancel_hint(&self) -> String {
+        self.config
+            .nav_bindings
+            .inputs(NavAction::Cancel)
+            .first()
+            .map_or_else(|| "Cancel".to_string(), ToString::to_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rebind_nav_inputs() {
+        let mut bindings = NavBindings::default();
+        let escape = NavInput::Key(Key::Escape);
+        let select = NavInput::Button(ControllerButton::Back);
+        assert_eq!(bindings.action(escape), Some(NavAction::Cancel));
+        assert_eq!(bindings.action(select), None);
+
+        bindings.bind(select, NavAction::Cancel);
+        assert_eq!(bindings.action(select), Some(NavAction::Cancel));
+        assert!(bindings.inputs(NavAction::Cancel).contains(&select));
+
+        // An input only triggers one action
+        bindings.bind(escape, NavAction::Confirm);
+        assert_eq!(bindings.action(escape), Some(NavAction::Confirm));
+        assert!(!bindings.inputs(NavAction::Cancel).contains(&escape));
+
+        bindings.unbind(escape);
+        assert_eq!(bindings.action(escape), None);
+    }
+
+    #[test]
+    fn cycle_tabs() {
+        let sections = ConfigSection::as_slice();
+        assert_eq!(
+            cycle(sections, ConfigSection::General, true),
+            ConfigSection::Emulation
+        );
+        assert_eq!(
+            cycle(sections, ConfigSection::General, false),
+            ConfigSection::Video
+        );
+        assert_eq!(cycle(Player::as_slice(), Player::Four, true), Player::One);
+    }
+}
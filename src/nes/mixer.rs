@@ -0,0 +1,142 @@
+//! Per-channel volume mixer and equalizer settings.
+//!
+//! Channel volumes are applied inside the APU mixer so recordings pick them up, while the master
+//! volume and equalizer only affect playback. Settings can optionally be saved for each game.
+
+use crate::{apu::Channel, common::config_dir, nes::Nes, NesResult};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsStr,
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+};
+
+/// Maximum equalizer boost or cut in decibels.
+pub(crate) const MAX_EQ_GAIN: f32 = 12.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[must_use]
+pub(crate) struct MixerSettings {
+    pub(crate) master: f32,
+    pub(crate) pulse1: f32,
+    pub(crate) pulse2: f32,
+    pub(crate) triangle: f32,
+    pub(crate) noise: f32,
+    pub(crate) dmc: f32,
+    pub(crate) expansion: f32,
+    /// Low shelf gain in decibels.
+    pub(crate) bass: f32,
+    /// High shelf gain in decibels.
+    pub(crate) treble: f32,
+}
+
+impl Default for MixerSettings {
+    fn default() -> Self {
+        Self {
+            master: 1.0,
+            pulse1: 1.0,
+            pulse2: 1.0,
+            triangle: 1.0,
+            noise: 1.0,
+            dmc: 1.0,
+            expansion: 1.0,
+            bass: 0.0,
+            treble: 0.0,
+        }
+    }
+}
+
+impl MixerSettings {
+    pub(crate) fn channel_mut(&mut self, channel: Channel) -> &mut f32 {
+        match channel {
+            Channel::Pulse1 => &mut self.pulse1,
+            Channel::Pulse2 => &mut self.pulse2,
+            Channel::Triangle => &mut self.triangle,
+            Channel::Noise => &mut self.noise,
+            Channel::Dmc => &mut self.dmc,
+        }
+    }
+}
+
+impl Nes {
+    /// Applies the active mixer settings to the control deck and audio output.
+    pub(crate) fn apply_mixer(&mut self) {
+        let mut mixer = self.mixer;
+        for channel in Channel::as_slice() {
+            self.control_deck
+                .set_channel_volume(*channel, *mixer.channel_mut(*channel));
+        }
+        self.control_deck.set_expansion_volume(mixer.expansion);
+        self.audio.set_volume(mixer.master);
+        self.audio.set_equalizer(mixer.bass, mixer.treble);
+    }
+
+    /// Updates the mixer after a setting changed in the menu.
+    pub(crate) fn update_mixer(&mut self) {
+        if !self.config.mixer_per_game {
+            self.config.mixer = self.mixer;
+        }
+        self.apply_mixer();
+    }
+
+    /// Returns the path where per-game mixer settings are stored.
+    pub(crate) fn mixer_path(&self) -> NesResult<PathBuf> {
+        match self.control_deck.loaded_rom() {
+            Some(ref rom) => PathBuf::from(rom)
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .map_or_else(
+                    || Err(anyhow!("failed to create mixer path for `{rom:?}`")),
+                    |name| Ok(config_dir().join("mixer").join(name).with_extension("json")),
+                ),
+            None => Err(anyhow!("no rom is loaded")),
+        }
+    }
+
+    /// Loads saved mixer settings for the current game if enabled, otherwise the global
+    /// settings, and applies them.
+    pub(crate) fn load_game_mixer(&mut self) {
+        self.mixer = self.config.mixer;
+        if self.config.mixer_per_game {
+            match self.mixer_path().and_then(|path| {
+                if !path.exists() {
+                    return Ok(None);
+                }
+                let file = File::open(&path).with_context(|| format!("failed to open {path:?}"))?;
+                serde_json::from_reader(BufReader::new(file))
+                    .with_context(|| format!("failed to parse {path:?}"))
+                    .map(Some)
+            }) {
+                Ok(Some(mixer)) => self.mixer = mixer,
+                Ok(None) => (),
+                Err(err) => {
+                    log::error!("{err:?}");
+                    self.add_message("Failed to load mixer settings");
+                }
+            }
+        }
+        self.apply_mixer();
+    }
+
+    /// Saves the mixer settings for the current game if enabled.
+    pub(crate) fn save_game_mixer(&mut self) {
+        if !self.config.mixer_per_game || self.control_deck.loaded_rom().is_none() {
+            return;
+        }
+        let result = self.mixer_path().and_then(|path| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("failed to create directory {dir:?}"))?;
+            }
+            let file = File::create(&path).with_context(|| format!("failed to create {path:?}"))?;
+            serde_json::to_writer_pretty(BufWriter::new(file), &self.mixer)
+                .context("failed to serialize mixer settings")
+        });
+        if let Err(err) = result {
+            log::error!("{err:?}");
+            self.add_message("Failed to save mixer settings");
+        }
+    }
+}
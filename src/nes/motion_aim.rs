@@ -0,0 +1,199 @@
+//! Zapper aiming with controller motion for couch play.
+//!
+//! Motion is read as turn rates from the right stick axes, which is how Steam Input, DS4Windows
+//! and similar tools expose a controller's gyro ("gyro to joystick"). Rates are integrated into
+//! the Zapper position, so tilting the controller moves the crosshair and holding it still keeps
+//! it in place. Calibration measures the rate a resting controller reports, which is subtracted
+//! to stop the crosshair from drifting.
+
+use crate::{
+    nes::{Mode, Nes, NES_FRAME_SRC},
+    ppu::Ppu,
+};
+use pix_engine::prelude::*;
+use std::time::{Duration, Instant};
+
+/// How long the controller must be held still to calibrate.
+const CALIBRATION_TIME: Duration = Duration::from_secs(2);
+/// Rates below this after removing the bias are treated as noise.
+const DEADZONE: f32 = 0.02;
+/// Pixels moved per second at full rate and a sensitivity of `1.0`.
+const PIXELS_PER_SECOND: f32 = Ppu::WIDTH as f32;
+
+#[derive(Debug, Copy, Clone)]
+struct Calibration {
+    start: Instant,
+    sum: [f32; 2],
+    count: u32,
+}
+
+/// Tracks the turn rates reported by the controller and the crosshair position they drive.
+#[derive(Debug, Copy, Clone)]
+#[must_use]
+pub(crate) struct MotionAim {
+    rate: [f32; 2],
+    pos: [f32; 2],
+    calibration: Option<Calibration>,
+}
+
+impl Default for MotionAim {
+    fn default() -> Self {
+        let mut aim = Self {
+            rate: [0.0; 2],
+            pos: [0.0; 2],
+            calibration: None,
+        };
+        aim.recenter();
+        aim
+    }
+}
+
+impl MotionAim {
+    /// Updates the turn rate for an axis, returning whether the axis is used for aiming.
+    pub(crate) fn set_rate(&mut self, axis: Axis, value: i32) -> bool {
+        let index = match axis {
+            Axis::RightX => 0,
+            Axis::RightY => 1,
+            _ => return false,
+        };
+        self.rate[index] = value as f32 / f32::from(i16::MAX);
+        true
+    }
+
+    pub(crate) fn recenter(&mut self) {
+        self.pos = [
+            Ppu::WIDTH as f32 / 2.0,
+            (NES_FRAME_SRC.top() + NES_FRAME_SRC.bottom()) as f32 / 2.0,
+        ];
+    }
+
+    pub(crate) fn start_calibration(&mut self) {
+        self.calibration = Some(Calibration {
+            start: Instant::now(),
+            sum: [0.0; 2],
+            count: 0,
+        });
+    }
+
+    /// Fraction of calibration completed, or `None` if not calibrating.
+    #[must_use]
+    pub(crate) fn calibration_progress(&self) -> Option<f32> {
+        self.calibration.map(|calibration| {
+            (calibration.start.elapsed().as_secs_f32() / CALIBRATION_TIME.as_secs_f32()).min(1.0)
+        })
+    }
+
+    /// Samples the resting rate while calibrating and returns the measured bias once enough time
+    /// has passed.
+    pub(crate) fn calibrate(&mut self) -> Option<[f32; 2]> {
+        let calibration = self.calibration.as_mut()?;
+        calibration.sum[0] += self.rate[0];
+        calibration.sum[1] += self.rate[1];
+        calibration.count += 1;
+        if calibration.start.elapsed() < CALIBRATION_TIME {
+            return None;
+        }
+        let count = calibration.count as f32;
+        let bias = [calibration.sum[0] / count, calibration.sum[1] / count];
+        self.calibration = None;
+        self.recenter();
+        Some(bias)
+    }
+
+    /// Moves the crosshair by the current rate over `elapsed` seconds, returning the new Zapper
+    /// position.
+    pub(crate) fn update(&mut self, elapsed: f32, bias: [f32; 2], sensitivity: f32) -> (i32, i32) {
+        let max = [Ppu::WIDTH as f32 - 1.0, NES_FRAME_SRC.bottom() as f32 - 1.0];
+        let min = [0.0, NES_FRAME_SRC.top() as f32];
+        for i in 0..2 {
+            let rate = self.rate[i] - bias[i];
+            if rate.abs() > DEADZONE {
+                self.pos[i] = (self.pos[i] + rate * sensitivity * PIXELS_PER_SECOND * elapsed)
+                    .clamp(min[i], max[i]);
+            }
+        }
+        (self.pos[0] as i32, self.pos[1] as i32)
+    }
+}
+
+impl Nes {
+    /// Aims the Zapper with controller motion, or samples the resting rate while calibrating.
+    pub(crate) fn update_motion_aim(&mut self, s: &PixState) {
+        if !self.config.motion_aim {
+            return;
+        }
+        if self.motion_aim.calibration_progress().is_some() {
+            if let Some(bias) = self.motion_aim.calibrate() {
+                self.config.motion_aim_bias = bias;
+                self.add_message("Motion aim calibrated");
+            }
+            return;
+        }
+        if self.config.zapper && self.mode == Mode::Playing {
+            let (x, y) = self.motion_aim.update(
+                s.delta_time().as_secs_f32(),
+                self.config.motion_aim_bias,
+                self.config.motion_aim_sensitivity,
+            );
+            self.control_deck.aim_zapper(x, y);
+        }
+    }
+
+    /// Renders the calibration screen over the game while calibrating.
+    pub(crate) fn render_motion_aim_calibration(&mut self, s: &mut PixState) -> PixResult<()> {
+        let Some(progress) = self.motion_aim.calibration_progress() else {
+            return Ok(());
+        };
+        s.push();
+        let width = s.width()? as i32;
+        let height = s.height()? as i32;
+        let pad = s.theme().spacing.frame_pad;
+        s.stroke(None);
+        s.fill(rgb!(0, 220));
+        s.rect([0, 0, width, height])?;
+
+        s.set_cursor_pos([pad.x(), height / 3]);
+        s.fill(Color::WHITE);
+        s.text("Calibrating Motion Aim")?;
+        s.text("Set the controller down on a flat surface and keep it still.")?;
+        let bar_width = width - 2 * pad.x();
+        let pos = s.cursor_pos();
+        s.stroke(Color::WHITE);
+        s.fill(None);
+        s.rect([pos.x(), pos.y(), bar_width, 16])?;
+        s.stroke(None);
+        s.fill(Color::GREEN);
+        s.rect([pos.x(), pos.y(), (bar_width as f32 * progress) as i32, 16])?;
+        s.set_cursor_pos([pos.x(), pos.y() + 24]);
+        s.fill(Color::WHITE);
+        if s.button("Cancel##motion_aim")? {
+            self.motion_aim.calibration = None;
+        }
+        s.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn integrates_rate_without_drift() {
+        let mut aim = MotionAim::default();
+        let center = aim.update(1.0, [0.0; 2], 1.0);
+
+        // A resting controller reporting a small bias shouldn't move the crosshair
+        aim.set_rate(Axis::RightX, 3000);
+        let bias = [3000.0 / f32::from(i16::MAX), 0.0];
+        assert_eq!(aim.update(1.0, bias, 1.0), center);
+
+        // Turning right at half rate for a quarter second moves an eighth of the screen
+        aim.set_rate(Axis::RightX, i32::from(i16::MAX) / 2 + 3000);
+        let (x, y) = aim.update(0.25, bias, 1.0);
+        assert_eq!(y, center.1);
+        assert!((x - center.0 - 32).abs() <= 1, "{x}");
+
+        assert!(!aim.set_rate(Axis::LeftX, 100), "left stick isn't used");
+    }
+}
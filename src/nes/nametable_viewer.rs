@@ -0,0 +1,340 @@
+//! Nametable viewer window showing all four nametables as the PPU sees them at a scanline.
+//!
+//! The nametables are reloaded every frame when the PPU reaches the scanline selected with the
+//! PPU Viewer's scanline controls. Attribute palettes can be shown as a colored grid, and the
+//! screen's scroll position at that scanline is outlined. Hovering a tile shows its index,
+//! attribute palette and addresses.
+
+use crate::{
+    mem::{Access, Mem},
+    nes::Nes,
+    ppu::{scroll::ScrollSplit, Mirroring, Ppu},
+};
+use pix_engine::prelude::*;
+
+const WIDTH: i32 = Ppu::WIDTH as i32;
+const HEIGHT: i32 = Ppu::HEIGHT as i32;
+const PADDING: i32 = 10;
+
+/// Attribute grid fill for each background palette.
+fn palette_color(palette: u8) -> Color {
+    match palette {
+        0 => rgb!(255, 0, 0, 60),
+        1 => rgb!(0, 255, 0, 60),
+        2 => rgb!(0, 128, 255, 60),
+        _ => rgb!(255, 255, 0, 60),
+    }
+}
+
+/// Tile index and attribute palette of a nametable entry.
+#[derive(Default, Debug, Copy, Clone)]
+pub(crate) struct NametableTile {
+    pub(crate) tile: u8,
+    pub(crate) palette: u8,
+}
+
+/// Renders nametable `index` as RGBA pixels, storing each entry's tile and palette in `tiles`.
+pub(crate) fn load_nametable(
+    ppu: &Ppu,
+    index: usize,
+    pixels: &mut [u8],
+    tiles: &mut [NametableTile],
+) {
+    let base_addr = Ppu::NT_START + (index as u16) * Ppu::NT_SIZE;
+    for offset in 0..(Ppu::NT_SIZE - 64) {
+        let col = offset % 32;
+        let row = offset / 32;
+        let tile = ppu.peek(base_addr + offset, Access::Dummy);
+        let tile_addr = ppu.ctrl().bg_select() + u16::from(tile) * 16;
+        let supertile = (col / 4) + (row / 4) * 8;
+        let attr = u16::from(ppu.peek(base_addr + 0x03C0 + supertile, Access::Dummy));
+        let corner = ((col % 4) / 2 + (row % 4) / 2 * 2) << 1;
+        let palette = (attr >> corner) & 0x03;
+        tiles[offset as usize] = NametableTile {
+            tile,
+            palette: palette as u8,
+        };
+
+        for y in 0..8 {
+            let lo = u16::from(ppu.peek(tile_addr + y, Access::Dummy));
+            let hi = u16::from(ppu.peek(tile_addr + y + 8, Access::Dummy));
+            for x in 0..8 {
+                let pix_type = ((lo >> x) & 1) + (((hi >> x) & 1) << 1);
+                let palette_idx =
+                    ppu.peek(Ppu::PALETTE_START + palette * 4 + pix_type, Access::Dummy);
+                let (red, green, blue) = Ppu::system_palette(palette_idx.into());
+                let px = u32::from(col * 8 + (7 - x));
+                let py = u32::from(row * 8 + y);
+                let idx = 4 * (px + py * Ppu::WIDTH) as usize;
+                pixels[idx] = red;
+                pixels[idx + 1] = green;
+                pixels[idx + 2] = blue;
+                pixels[idx + 3] = 0xFF;
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct NametableViewer {
+    window_id: WindowId,
+    texture_id: TextureId,
+    scanline: u32,
+    mirroring: Mirroring,
+    bg_select: u16,
+    nametables: [Vec<u8>; 4],
+    tiles: [Vec<NametableTile>; 4],
+    scroll: Option<ScrollSplit>,
+    attribute_grid: bool,
+    scroll_rect: bool,
+}
+
+impl NametableViewer {
+    fn new(window_id: WindowId, texture_id: TextureId, scanline: u32) -> Self {
+        Self {
+            window_id,
+            texture_id,
+            scanline,
+            mirroring: Mirroring::default(),
+            bg_select: 0x0000,
+            nametables: std::array::from_fn(|_| vec![0x00; 4 * Ppu::SIZE]),
+            tiles: std::array::from_fn(|_| vec![NametableTile::default(); Ppu::NT_SIZE as usize]),
+            scroll: None,
+            attribute_grid: false,
+            scroll_rect: true,
+        }
+    }
+
+    pub(crate) const fn window_id(&self) -> WindowId {
+        self.window_id
+    }
+
+    pub(crate) const fn scanline(&self) -> u32 {
+        self.scanline
+    }
+
+    pub(crate) fn inc_scanline(&mut self, increment: u32) {
+        self.scanline = (self.scanline + increment).clamp(0, Ppu::HEIGHT - 1);
+    }
+
+    pub(crate) fn dec_scanline(&mut self, decrement: u32) {
+        self.scanline = self.scanline.saturating_sub(decrement);
+    }
+
+    pub(crate) fn load(&mut self, ppu: &Ppu) {
+        self.mirroring = ppu.mirroring();
+        self.bg_select = ppu.ctrl().bg_select();
+        for (i, (pixels, tiles)) in self
+            .nametables
+            .iter_mut()
+            .zip(self.tiles.iter_mut())
+            .enumerate()
+        {
+            load_nametable(ppu, i, pixels, tiles);
+        }
+        // Splits are from the last completed frame, which matches the nametables closely enough
+        self.scroll = ppu
+            .scroll_splits()
+            .iter()
+            .take_while(|split| split.scanline <= self.scanline)
+            .last()
+            .copied();
+    }
+
+    /// Draws the outline of the visible screen at `x`, `y`, wrapping around the nametables.
+    fn draw_scroll_rect(s: &mut PixState, x: i32, y: i32) -> PixResult<()> {
+        let (width, height) = (2 * WIDTH, 2 * HEIGHT);
+        for dx in [0, -width] {
+            for dy in [0, -height] {
+                let rect = rect![x + dx, y + dy, WIDTH, HEIGHT];
+                let left = rect.left().max(0);
+                let top = rect.top().max(0);
+                let right = rect.right().min(width);
+                let bottom = rect.bottom().min(height);
+                if left < right && top < bottom {
+                    s.rect([PADDING + left, PADDING + top, right - left, bottom - top])?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Nes {
+    pub(crate) fn toggle_nametable_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
+        match self.nametable_viewer {
+            None => {
+                let w = 2 * Ppu::WIDTH + 2 * PADDING as u32;
+                let h = 2 * Ppu::HEIGHT + 160;
+                let window_id = s
+                    .window()
+                    .dimensions(w, h)
+                    .title("Nametable Viewer")
+                    .position(10, 10)
+                    .resizable()
+                    .build()?;
+                s.set_window_target(window_id)?;
+                let texture_id =
+                    s.create_texture(2 * Ppu::WIDTH, 2 * Ppu::HEIGHT, PixelFormat::Rgba)?;
+                s.reset_window_target();
+                let scanline = self
+                    .ppu_viewer
+                    .as_ref()
+                    .map_or(0, |viewer| viewer.scanline());
+                let mut viewer = NametableViewer::new(window_id, texture_id, scanline);
+                viewer.load(self.control_deck.ppu());
+                self.nametable_viewer = Some(viewer);
+                self.control_deck.ppu_mut().set_scroll_splits_enabled(true);
+            }
+            Some(ref viewer) => {
+                s.close_window(viewer.window_id())?;
+                self.close_nametable_viewer();
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn close_nametable_viewer(&mut self) {
+        self.nametable_viewer = None;
+        if !self.scroll_overlay {
+            self.control_deck.ppu_mut().set_scroll_splits_enabled(false);
+        }
+    }
+
+    pub(crate) fn render_nametable_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
+        let Some(ref mut viewer) = self.nametable_viewer else {
+            return Ok(());
+        };
+        // Loading a save state replaces the PPU and stops recording
+        let ppu = self.control_deck.ppu_mut();
+        if !ppu.scroll_splits_enabled() {
+            ppu.set_scroll_splits_enabled(true);
+        }
+
+        s.set_window_target(viewer.window_id())?;
+        s.clear()?;
+        s.fill(Color::WHITE);
+        s.stroke(None);
+
+        let pitch = 4 * Ppu::WIDTH as usize;
+        for (i, pixels) in viewer.nametables.iter().enumerate() {
+            let x = (i as i32 % 2) * WIDTH;
+            let y = (i as i32 / 2) * HEIGHT;
+            s.update_texture(viewer.texture_id, rect![x, y, WIDTH, HEIGHT], pixels, pitch)?;
+        }
+        let dst = rect![PADDING, PADDING, 2 * WIDTH, 2 * HEIGHT];
+        s.texture(viewer.texture_id, rect![0, 0, 2 * WIDTH, 2 * HEIGHT], dst)?;
+
+        s.push();
+        s.stroke(None);
+        if viewer.attribute_grid {
+            for (i, tiles) in viewer.tiles.iter().enumerate() {
+                let nt_x = (i as i32 % 2) * WIDTH;
+                let nt_y = (i as i32 / 2) * HEIGHT;
+                for row in (0..30).step_by(2) {
+                    for col in (0..32).step_by(2) {
+                        let palette = tiles[row * 32 + col].palette;
+                        s.fill(palette_color(palette));
+                        s.rect([
+                            PADDING + nt_x + col as i32 * 8,
+                            PADDING + nt_y + row as i32 * 8,
+                            16,
+                            16,
+                        ])?;
+                    }
+                }
+            }
+        }
+        s.fill(None);
+        s.stroke(Color::DIM_GRAY);
+        s.stroke_weight(2);
+        for i in 0..4 {
+            s.rect([
+                PADDING + (i % 2) * WIDTH,
+                PADDING + (i / 2) * HEIGHT,
+                WIDTH,
+                HEIGHT,
+            ])?;
+        }
+        if viewer.scroll_rect {
+            if let Some(scroll) = viewer.scroll {
+                s.stroke(Color::RED);
+                NametableViewer::draw_scroll_rect(s, i32::from(scroll.x), i32::from(scroll.y))?;
+            }
+        }
+        s.pop();
+
+        // Info
+        s.set_cursor_pos([PADDING, dst.bottom() + 4]);
+        s.text(format!(
+            "Scanline: {}  Mirroring: {:?}  Background: ${:04X}",
+            viewer.scanline, viewer.mirroring, viewer.bg_select
+        ))?;
+        let scroll = viewer.scroll.map_or_else(
+            || "Scroll: waiting for a frame".to_string(),
+            |scroll| {
+                format!(
+                    "Scroll: X {} Y {} (since scanline {})",
+                    scroll.x, scroll.y, scroll.scanline
+                )
+            },
+        );
+        s.text(scroll)?;
+        s.checkbox("Attribute Grid", &mut viewer.attribute_grid)?;
+        s.same_line(None);
+        s.checkbox("Scroll Position", &mut viewer.scroll_rect)?;
+        s.same_line(None);
+        s.help_marker(
+            "The nametables reload each frame when the PPU reaches the selected scanline. Use the \
+            debug scanline keys to change it. The scroll position outlines the screen as of that \
+            scanline.",
+        )?;
+
+        // Tooltip
+        let m = s.mouse_pos();
+        if s.focused_window(viewer.window_id()) && dst.contains(m) {
+            let x = m.x() - dst.x();
+            let y = m.y() - dst.y();
+            let index = (x / WIDTH + (y / HEIGHT) * 2) as usize;
+            let col = (x % WIDTH) / 8;
+            let row = (y % HEIGHT) / 8;
+            let NametableTile { tile, palette } = viewer.tiles[index][(row * 32 + col) as usize];
+            let nt_addr = Ppu::NT_START + index as u16 * Ppu::NT_SIZE;
+            let tile_addr = nt_addr + (row * 32 + col) as u16;
+            let attr_addr = nt_addr + 0x03C0 + ((col / 4) + (row / 4) * 8) as u16;
+            let pattern_addr = viewer.bg_select + u16::from(tile) * 16;
+
+            s.push();
+            s.fill(None);
+            s.stroke(Color::WHITE);
+            s.rect([dst.x() + (x / 8) * 8, dst.y() + (y / 8) * 8, 8, 8])?;
+            let lines = [
+                format!("Tile: ${tile:02X} ({col}, {row})"),
+                format!("Palette: {palette}"),
+                format!("Address: ${tile_addr:04X}"),
+                format!("Attribute: ${attr_addr:04X}"),
+                format!("Pattern: ${pattern_addr:04X}"),
+            ];
+            let line_height = s.theme().font_size as i32 + 4;
+            let (w, h) = (170, lines.len() as i32 * line_height + 8);
+            let tip_x = if m.x() + 16 + w > dst.right() {
+                m.x() - 16 - w
+            } else {
+                m.x() + 16
+            };
+            let tip_y = (m.y() + 16).min(dst.bottom() - h);
+            s.stroke(None);
+            s.fill(rgb!(0, 220));
+            s.rect([tip_x, tip_y, w, h])?;
+            s.fill(Color::WHITE);
+            for (i, line) in lines.iter().enumerate() {
+                s.set_cursor_pos([tip_x + 4, tip_y + 4 + i as i32 * line_height]);
+                s.text(line)?;
+            }
+            s.pop();
+        }
+
+        s.reset_window_target();
+        Ok(())
+    }
+}
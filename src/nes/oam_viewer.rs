@@ -0,0 +1,337 @@
+//! OAM viewer window listing all 64 sprites with live thumbnails, and highlighting the selected
+//! sprite in the main view.
+//!
+//! Sprites are shown as they were at the end of the last frame, so sprites a game moves or hides
+//! before the frame is drawn aren't shown in the wrong place.
+
+use crate::{
+    nes::{Nes, NES_FRAME_SRC},
+    ppu::{
+        sprite::{OamEntry, SpriteSnapshot},
+        Ppu,
+    },
+};
+use pix_engine::prelude::*;
+
+/// Thumbnail scale and the size of each cell in the sprite grid.
+const THUMB_SCALE: i32 = 3;
+const CELL_WIDTH: i32 = 8 * THUMB_SCALE + 8;
+const CELL_HEIGHT: i32 = 16 * THUMB_SCALE + 8;
+const PADDING: i32 = 10;
+const GRID_WIDTH: i32 = 8 * CELL_WIDTH;
+const GRID_HEIGHT: i32 = 8 * CELL_HEIGHT;
+/// Size of the entry table next to the grid, in two columns of 32 entries.
+const TABLE_WIDTH: u32 = 440;
+const TABLE_HEIGHT: u32 = 32 * 18;
+/// Sprites at or below this Y are off the bottom of the screen, which is how games hide them.
+const HIDDEN_Y: u8 = 0xEF;
+
+#[derive(Debug)]
+pub(crate) struct OamViewer {
+    window_id: WindowId,
+    texture_id: TextureId,
+    pixels: Vec<u8>,
+    selected: Option<usize>,
+}
+
+impl OamViewer {
+    /// Thumbnails are packed 8 sprites per row, each in an 8x16 area.
+    const TEXTURE_WIDTH: u32 = 8 * 8;
+    const TEXTURE_HEIGHT: u32 = 8 * 16;
+
+    fn new(window_id: WindowId, texture_id: TextureId) -> Self {
+        Self {
+            window_id,
+            texture_id,
+            pixels: vec![0x00; 4 * (Self::TEXTURE_WIDTH * Self::TEXTURE_HEIGHT) as usize],
+            selected: None,
+        }
+    }
+
+    pub(crate) const fn window_id(&self) -> WindowId {
+        self.window_id
+    }
+
+    fn load_thumbnails(&mut self, snapshot: &SpriteSnapshot) {
+        self.pixels.fill(0x00);
+        let pitch = 4 * Self::TEXTURE_WIDTH as usize;
+        for (i, (entry, pattern)) in snapshot.oam.iter().zip(&snapshot.patterns).enumerate() {
+            let (left, top) = (8 * (i % 8), 16 * (i / 8));
+            for y in 0..snapshot.height as usize {
+                for x in 0..8 {
+                    let Some(color) = snapshot.color(*entry, pattern[y * 8 + x]) else {
+                        continue;
+                    };
+                    let (red, green, blue) = Ppu::system_palette(u16::from(color & 0x3F));
+                    let idx = (top + y) * pitch + 4 * (left + x);
+                    self.pixels[idx..idx + 4].copy_from_slice(&[red, green, blue, 0xFF]);
+                }
+            }
+        }
+    }
+}
+
+impl Nes {
+    pub(crate) fn toggle_oam_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
+        match self.oam_viewer {
+            None => {
+                let window_id = s
+                    .window()
+                    .dimensions(
+                        GRID_WIDTH as u32 + TABLE_WIDTH,
+                        TABLE_HEIGHT.max(GRID_HEIGHT as u32) + 80,
+                    )
+                    .title("OAM Viewer")
+                    .position(10, 10)
+                    .build()?;
+                s.set_window_target(window_id)?;
+                let texture_id = s.create_texture(
+                    OamViewer::TEXTURE_WIDTH,
+                    OamViewer::TEXTURE_HEIGHT,
+                    PixelFormat::Rgba,
+                )?;
+                s.reset_window_target();
+                self.oam_viewer = Some(OamViewer::new(window_id, texture_id));
+                self.update_sprite_snapshots();
+            }
+            Some(ref viewer) => {
+                s.close_window(viewer.window_id())?;
+                self.close_oam_viewer();
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn close_oam_viewer(&mut self) {
+        self.oam_viewer = None;
+        self.update_sprite_snapshots();
+    }
+
+    /// Captures sprites at the end of each frame while the OAM or palette viewer is open.
+    pub(crate) fn update_sprite_snapshots(&mut self) {
+        let enabled = self.oam_viewer.is_some() || self.palette_viewer.is_some();
+        let ppu = self.control_deck.ppu_mut();
+        if enabled != ppu.sprite_snapshots_enabled() {
+            ppu.set_sprite_snapshots_enabled(enabled);
+        }
+    }
+
+    pub(crate) fn render_oam_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
+        if self.oam_viewer.is_none() {
+            return Ok(());
+        }
+        // Loading a save state replaces the PPU and stops capturing
+        self.update_sprite_snapshots();
+        let ppu = self.control_deck.ppu();
+        let live;
+        let snapshot = match ppu.sprite_snapshot() {
+            Some(snapshot) => snapshot,
+            None => {
+                live = ppu.snapshot_sprites();
+                &live
+            }
+        };
+        let Some(ref mut viewer) = self.oam_viewer else {
+            return Ok(());
+        };
+        viewer.load_thumbnails(snapshot);
+
+        s.set_window_target(viewer.window_id())?;
+        s.clear()?;
+        s.fill(Color::WHITE);
+        s.stroke(None);
+        s.update_texture(
+            viewer.texture_id,
+            None,
+            &viewer.pixels,
+            4 * OamViewer::TEXTURE_WIDTH as usize,
+        )?;
+
+        // Sprite grid
+        let grid = rect![PADDING, PADDING, GRID_WIDTH, GRID_HEIGHT];
+        let m = s.mouse_pos();
+        let hovered = (s.focused_window(viewer.window_id()) && grid.contains(m)).then(|| {
+            let col = (m.x() - grid.x()) / CELL_WIDTH;
+            let row = (m.y() - grid.y()) / CELL_HEIGHT;
+            (row * 8 + col) as usize
+        });
+        if let Some(index) = hovered {
+            if s.mouse_clicked(Mouse::Left) {
+                viewer.selected = if viewer.selected == Some(index) {
+                    None
+                } else {
+                    Some(index)
+                };
+            }
+        }
+        let height = snapshot.height as i32;
+        s.push();
+        for (i, entry) in snapshot.oam.iter().enumerate() {
+            let (col, row) = (i as i32 % 8, i as i32 / 8);
+            let cell = rect![
+                grid.x() + col * CELL_WIDTH,
+                grid.y() + row * CELL_HEIGHT,
+                CELL_WIDTH - 2,
+                CELL_HEIGHT - 2
+            ];
+            s.stroke(None);
+            s.fill(if entry.y >= HIDDEN_Y {
+                rgb!(20)
+            } else {
+                rgb!(50)
+            });
+            s.rect(cell)?;
+            let src = rect![8 * col, 16 * row, 8, height];
+            let dst = rect![
+                cell.x() + 3,
+                cell.y() + 3,
+                8 * THUMB_SCALE,
+                height * THUMB_SCALE
+            ];
+            s.texture(viewer.texture_id, src, dst)?;
+            let outline = if viewer.selected == Some(i) {
+                Some(Color::RED)
+            } else if i == 0 {
+                Some(Color::YELLOW)
+            } else if hovered == Some(i) {
+                Some(Color::WHITE)
+            } else {
+                None
+            };
+            if let Some(color) = outline {
+                s.stroke(color);
+                s.fill(None);
+                s.rect(cell)?;
+            }
+        }
+        s.pop();
+
+        // Entry table
+        let table_x = grid.right() + PADDING;
+        let line_height = s.theme().font_size as i32 + 4;
+        s.push();
+        for (i, entry) in snapshot.oam.iter().enumerate() {
+            let x = table_x + (i as i32 / 32) * 210;
+            let y = PADDING + (i as i32 % 32) * line_height;
+            s.fill(if viewer.selected == Some(i) {
+                Color::RED
+            } else if i == 0 {
+                Color::YELLOW
+            } else if entry.y >= HIDDEN_Y {
+                Color::GRAY
+            } else {
+                Color::WHITE
+            });
+            s.set_cursor_pos([x, y]);
+            s.text(format!(
+                "{i:02}  X {:3}  Y {:3}  T ${:02X}  A ${:02X}",
+                entry.x, entry.y, entry.tile, entry.attr
+            ))?;
+        }
+        s.pop();
+
+        // Details
+        s.set_cursor_pos([PADDING, grid.bottom() + 8]);
+        let sprite_zero = format!(
+            "Sprites: 8x{}  Sprite 0 hit: {}",
+            snapshot.height,
+            if snapshot.sprite_zero_hit {
+                "yes"
+            } else {
+                "no"
+            }
+        );
+        s.text(sprite_zero)?;
+        match hovered.or(viewer.selected) {
+            Some(index) => {
+                let entry = snapshot.oam[index];
+                let ctrl = self.control_deck.ppu().ctrl();
+                s.text(describe_entry(
+                    index,
+                    entry,
+                    entry.tile_addr(snapshot.height, ctrl.spr_select()),
+                ))?;
+            }
+            None => {
+                s.text("Hover a sprite for details. Click to highlight it in the main view.")?;
+            }
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Sprites as of the end of the last frame. Sprite 0 is outlined in yellow and the \
+            selected sprite in red. Darker sprites are hidden below the screen.",
+        )?;
+
+        s.reset_window_target();
+        Ok(())
+    }
+
+    /// Outlines the sprite selected in the OAM viewer on the main view.
+    pub(crate) fn render_sprite_highlight(&mut self, s: &mut PixState) -> PixResult<()> {
+        let Some(index) = self.oam_viewer.as_ref().and_then(|viewer| viewer.selected) else {
+            return Ok(());
+        };
+        let ppu = self.control_deck.ppu();
+        let Some(snapshot) = ppu.sprite_snapshot() else {
+            return Ok(());
+        };
+        let entry = snapshot.oam[index];
+        if entry.y >= HIDDEN_Y {
+            return Ok(());
+        }
+        let x_scale = s.width()? as f32 / Ppu::WIDTH as f32;
+        let y_scale = s.height()? as f32 / NES_FRAME_SRC.height() as f32;
+        // Sprites are drawn starting the scanline after their Y position
+        let x = (f32::from(entry.x) * x_scale) as i32;
+        let y = ((i32::from(entry.y) + 1 - NES_FRAME_SRC.top()) as f32 * y_scale) as i32;
+        let width = (8.0 * x_scale).ceil() as i32;
+        let height = (snapshot.height as f32 * y_scale).ceil() as i32;
+        s.push();
+        s.fill(None);
+        s.stroke(Color::RED);
+        s.stroke_weight(2);
+        s.rect([x - 1, y - 1, width + 2, height + 2])?;
+        s.pop();
+        Ok(())
+    }
+}
+
+fn describe_entry(index: usize, entry: OamEntry, pattern_addr: u16) -> String {
+    let mut flags = vec![];
+    if entry.behind_background() {
+        flags.push("behind background");
+    }
+    if entry.flip_horizontal() {
+        flags.push("flipped horizontally");
+    }
+    if entry.flip_vertical() {
+        flags.push("flipped vertically");
+    }
+    let flags = if flags.is_empty() {
+        String::new()
+    } else {
+        format!(", {}", flags.join(", "))
+    };
+    format!(
+        "Sprite {index}: ({}, {}) Tile ${:02X} at ${pattern_addr:04X}, Palette {}{flags}",
+        entry.x,
+        entry.y,
+        entry.tile,
+        entry.palette()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_description() {
+        let entry = OamEntry::new([0x20, 0x1F, 0x62, 0x30]);
+        assert_eq!(
+            describe_entry(3, entry, 0x11F0),
+            "Sprite 3: (48, 32) Tile $1F at $11F0, Palette 2, behind background, flipped \
+            horizontally"
+        );
+    }
+}
@@ -0,0 +1,444 @@
+//! Configurable on-screen display.
+//!
+//! Extends the status messages with elements that can each be toggled and moved to a corner of
+//! the screen: an FPS counter with a graph of recent frame times, the emulated frame and lag frame
+//! counters, rewind and recording indicators, and the buttons each player is holding, which is
+//! useful for streaming and TAS work. Elements in the top left corner flow with status messages.
+
+use crate::{
+    input::{FourPlayer, JoypadBtnState, Slot},
+    nes::{state::ReplayMode, Mode, Nes},
+};
+use pix_engine::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, time::Instant};
+
+/// Number of frame times kept for the graph, one pixel wide each.
+const FRAME_TIMES: usize = 120;
+const GRAPH_HEIGHT: u32 = 32;
+
+const SLOTS: [Slot; 4] = [Slot::One, Slot::Two, Slot::Three, Slot::Four];
+
+/// Buttons shown by the input display, in the order FCEUX shows them.
+const INPUT_BUTTONS: [(&str, JoypadBtnState); 8] = [
+    ("<", JoypadBtnState::LEFT),
+    ("^", JoypadBtnState::UP),
+    ("v", JoypadBtnState::DOWN),
+    (">", JoypadBtnState::RIGHT),
+    ("Se", JoypadBtnState::SELECT),
+    ("St", JoypadBtnState::START),
+    ("B", JoypadBtnState::B),
+    ("A", JoypadBtnState::A),
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub(crate) enum OsdElement {
+    Fps,
+    FrameCounter,
+    LagCounter,
+    Indicators,
+    InputDisplay,
+}
+
+impl OsdElement {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[
+            Self::Fps,
+            Self::FrameCounter,
+            Self::LagCounter,
+            Self::Indicators,
+            Self::InputDisplay,
+        ]
+    }
+}
+
+impl AsRef<str> for OsdElement {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Fps => "FPS & Frame Times",
+            Self::FrameCounter => "Frame Counter",
+            Self::LagCounter => "Lag Frame Counter",
+            Self::Indicators => "Rewind & Recording",
+            Self::InputDisplay => "Input Display",
+        }
+    }
+}
+
+/// Corner of the screen an element is shown in.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub(crate) enum OsdPosition {
+    #[default]
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl OsdPosition {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[
+            Self::TopLeft,
+            Self::TopRight,
+            Self::BottomLeft,
+            Self::BottomRight,
+        ]
+    }
+}
+
+impl AsRef<str> for OsdPosition {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::TopLeft => "Top Left",
+            Self::TopRight => "Top Right",
+            Self::BottomLeft => "Bottom Left",
+            Self::BottomRight => "Bottom Right",
+        }
+    }
+}
+
+impl From<usize> for OsdPosition {
+    fn from(value: usize) -> Self {
+        Self::as_slice().get(value).copied().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct OsdItem {
+    pub(crate) enabled: bool,
+    pub(crate) position: OsdPosition,
+}
+
+impl OsdItem {
+    const fn new(enabled: bool, position: OsdPosition) -> Self {
+        Self { enabled, position }
+    }
+}
+
+/// Which elements are shown and where.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct OsdConfig {
+    pub(crate) fps: OsdItem,
+    pub(crate) frame_counter: OsdItem,
+    pub(crate) lag_counter: OsdItem,
+    pub(crate) indicators: OsdItem,
+    pub(crate) input_display: OsdItem,
+}
+
+impl Default for OsdConfig {
+    fn default() -> Self {
+        Self {
+            fps: OsdItem::new(false, OsdPosition::TopRight),
+            frame_counter: OsdItem::new(false, OsdPosition::TopRight),
+            lag_counter: OsdItem::new(false, OsdPosition::TopRight),
+            indicators: OsdItem::new(true, OsdPosition::TopLeft),
+            input_display: OsdItem::new(false, OsdPosition::BottomLeft),
+        }
+    }
+}
+
+impl OsdConfig {
+    pub(crate) const fn item(&self, element: OsdElement) -> OsdItem {
+        match element {
+            OsdElement::Fps => self.fps,
+            OsdElement::FrameCounter => self.frame_counter,
+            OsdElement::LagCounter => self.lag_counter,
+            OsdElement::Indicators => self.indicators,
+            OsdElement::InputDisplay => self.input_display,
+        }
+    }
+
+    pub(crate) fn item_mut(&mut self, element: OsdElement) -> &mut OsdItem {
+        match element {
+            OsdElement::Fps => &mut self.fps,
+            OsdElement::FrameCounter => &mut self.frame_counter,
+            OsdElement::LagCounter => &mut self.lag_counter,
+            OsdElement::Indicators => &mut self.indicators,
+            OsdElement::InputDisplay => &mut self.input_display,
+        }
+    }
+}
+
+/// Times between recent updates, in seconds.
+#[derive(Default, Debug, Clone)]
+#[must_use]
+pub(crate) struct FrameTimes {
+    last_update: Option<Instant>,
+    times: VecDeque<f32>,
+}
+
+impl FrameTimes {
+    pub(crate) fn update(&mut self, now: Instant) {
+        if let Some(last_update) = self.last_update {
+            if self.times.len() == FRAME_TIMES {
+                self.times.pop_front();
+            }
+            self.times
+                .push_back(now.duration_since(last_update).as_secs_f32());
+        }
+        self.last_update = Some(now);
+    }
+
+    /// Average time between updates.
+    pub(crate) fn average(&self) -> f32 {
+        if self.times.is_empty() {
+            0.0
+        } else {
+            self.times.iter().sum::<f32>() / self.times.len() as f32
+        }
+    }
+
+    fn fps(&self) -> f32 {
+        let average = self.average();
+        if average > 0.0 {
+            average.recip()
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum OsdLine {
+    Text(String, Color),
+    /// Text drawn in pieces with their own colors.
+    Segments(Vec<(String, Color)>),
+    FrameGraph,
+}
+
+impl Nes {
+    fn osd_lines(&self, element: OsdElement) -> Vec<OsdLine> {
+        match element {
+            OsdElement::Fps => {
+                let times = &self.frame_times;
+                let mut lines = vec![
+                    OsdLine::Text(
+                        format!(
+                            "FPS: {:.1} ({:.1} ms)",
+                            times.fps(),
+                            1000.0 * times.average()
+                        ),
+                        Color::WHITE,
+                    ),
+                    OsdLine::FrameGraph,
+                ];
+                let filter_scale = self.control_deck.filter_scale();
+                if filter_scale > 1 {
+                    lines.push(OsdLine::Text(
+                        format!("Filter: 1/{filter_scale} Resolution"),
+                        Color::YELLOW,
+                    ));
+                }
+                lines
+            }
+            OsdElement::FrameCounter => vec![OsdLine::Text(
+                format!("Frame: {}", self.control_deck.frame_number()),
+                Color::WHITE,
+            )],
+            OsdElement::LagCounter => {
+                let color = if self.control_deck.lagged() {
+                    Color::RED
+                } else {
+                    Color::WHITE
+                };
+                vec![OsdLine::Text(
+                    format!("Lag: {}", self.control_deck.lag_frames()),
+                    color,
+                )]
+            }
+            OsdElement::Indicators => {
+                let mut lines = vec![];
+                let mut indicator = |active: bool, text: &str, color: Color| {
+                    if active {
+                        lines.push(OsdLine::Text(text.to_string(), color));
+                    }
+                };
+                indicator(self.mode == Mode::Rewinding, "Rewinding", Color::WHITE);
+                indicator(
+                    self.replay.mode == ReplayMode::Recording,
+                    "Recording Replay",
+                    Color::RED,
+                );
+                indicator(
+                    self.replay.mode == ReplayMode::Playback,
+                    "Replay Playback",
+                    Color::WHITE,
+                );
+                indicator(self.video_recorder.is_some(), "Recording Video", Color::RED);
+                indicator(self.sound_recorder.is_some(), "Recording Sound", Color::RED);
+                indicator(self.clip.is_recording(), "Capturing Clip", Color::RED);
+                lines
+            }
+            OsdElement::InputDisplay => {
+                let players = if self.config.four_player == FourPlayer::Disabled {
+                    2
+                } else {
+                    4
+                };
+                SLOTS
+                    .into_iter()
+                    .take(players)
+                    .map(|slot| {
+                        let joypad = self.control_deck.joypad(slot);
+                        let mut segments = vec![(format!("P{} ", slot as usize + 1), Color::WHITE)];
+                        segments.extend(INPUT_BUTTONS.into_iter().map(|(name, button)| {
+                            let color = if joypad.button(button) {
+                                Color::WHITE
+                            } else {
+                                rgb!(90)
+                            };
+                            (format!("{name} "), color)
+                        }));
+                        OsdLine::Segments(segments)
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// Renders enabled elements grouped by corner, each corner on its own background.
+    pub(crate) fn render_osd(&mut self, s: &mut PixState) -> PixResult<()> {
+        for &position in OsdPosition::as_slice() {
+            let lines = OsdElement::as_slice()
+                .iter()
+                .filter(|&&element| {
+                    let item = self.config.osd.item(element);
+                    item.enabled && item.position == position
+                })
+                .flat_map(|&element| self.osd_lines(element))
+                .collect::<Vec<_>>();
+            if !lines.is_empty() {
+                self.render_osd_corner(s, position, &lines)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn render_osd_corner(
+        &mut self,
+        s: &mut PixState,
+        position: OsdPosition,
+        lines: &[OsdLine],
+    ) -> PixResult<()> {
+        let mut sizes = Vec::with_capacity(lines.len());
+        for line in lines {
+            sizes.push(match line {
+                OsdLine::Text(text, _) => s.size_of(text)?,
+                OsdLine::Segments(segments) => {
+                    let mut width = 0;
+                    let mut height = 0;
+                    for (text, _) in segments {
+                        let (w, h) = s.size_of(text)?;
+                        width += w;
+                        height = height.max(h);
+                    }
+                    (width, height)
+                }
+                OsdLine::FrameGraph => (FRAME_TIMES as u32, GRAPH_HEIGHT),
+            });
+        }
+
+        let pad = s.theme().spacing.frame_pad;
+        let (padx, pady) = (pad.x(), pad.y());
+        let width = sizes.iter().map(|&(w, _)| w).max().unwrap_or(0) as i32 + 2 * padx;
+        let height = sizes.iter().map(|&(_, h)| h).sum::<u32>() as i32 + 2 * pady;
+        let cursor = s.cursor_pos();
+        let x = match position {
+            OsdPosition::TopLeft | OsdPosition::BottomLeft => 0,
+            OsdPosition::TopRight | OsdPosition::BottomRight => s.width()? as i32 - width,
+        };
+        let y = match position {
+            OsdPosition::TopLeft => cursor.y() - pady,
+            OsdPosition::TopRight => 0,
+            OsdPosition::BottomLeft | OsdPosition::BottomRight => s.height()? as i32 - height,
+        };
+
+        s.push();
+        s.stroke(None);
+        s.fill(rgb!(0, 200));
+        s.rect([x, y, width, height])?;
+        let mut line_y = y + pady;
+        for (line, &(_, line_height)) in lines.iter().zip(&sizes) {
+            let line_x = x + padx;
+            match line {
+                OsdLine::Text(text, color) => {
+                    s.fill(*color);
+                    s.set_cursor_pos([line_x, line_y]);
+                    s.text(text)?;
+                }
+                OsdLine::Segments(segments) => {
+                    let mut segment_x = line_x;
+                    for (text, color) in segments {
+                        s.fill(*color);
+                        s.set_cursor_pos([segment_x, line_y]);
+                        s.text(text)?;
+                        segment_x += s.size_of(text)?.0 as i32;
+                    }
+                }
+                OsdLine::FrameGraph => self.render_frame_graph(s, line_x, line_y)?,
+            }
+            line_y += line_height as i32;
+        }
+        s.pop();
+
+        // Status messages continue below the top left corner
+        let cursor_y = if position == OsdPosition::TopLeft {
+            y + height + pady
+        } else {
+            cursor.y()
+        };
+        s.set_cursor_pos([cursor.x(), cursor_y]);
+        Ok(())
+    }
+
+    /// Bars for recent frame times, scaled so the console's frame time is half the height and
+    /// marked with a line. Frames that took over half as long again are red.
+    fn render_frame_graph(&self, s: &mut PixState, x: i32, y: i32) -> PixResult<()> {
+        let target = self.config.region.frame_rate().recip();
+        let bottom = y + GRAPH_HEIGHT as i32;
+        for (i, &time) in self.frame_times.times.iter().enumerate() {
+            let bar = (time / (2.0 * target)).min(1.0) * GRAPH_HEIGHT as f32;
+            s.stroke(if time > 1.5 * target {
+                Color::RED
+            } else {
+                Color::GREEN
+            });
+            let bar_x = x + i as i32;
+            s.line([bar_x, bottom, bar_x, bottom - bar as i32])?;
+        }
+        s.stroke(Color::GRAY);
+        let target_y = bottom - GRAPH_HEIGHT as i32 / 2;
+        s.line([x, target_y, x + FRAME_TIMES as i32 - 1, target_y])?;
+        s.stroke(None);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn frame_times() {
+        let mut times = FrameTimes::default();
+        assert!(times.fps().abs() < f32::EPSILON);
+        let start = Instant::now();
+        for i in 0..=(FRAME_TIMES as u32 + 10) {
+            times.update(start + Duration::from_millis(20) * i);
+        }
+        assert_eq!(times.times.len(), FRAME_TIMES);
+        assert!((times.fps() - 50.0).abs() < 0.1, "fps: {}", times.fps());
+    }
+
+    #[test]
+    fn osd_config() {
+        let mut config = OsdConfig::default();
+        assert!(config.item(OsdElement::Indicators).enabled);
+        config.item_mut(OsdElement::Fps).enabled = true;
+        assert!(config.fps.enabled);
+        assert_eq!(OsdPosition::from(3), OsdPosition::BottomRight);
+        assert_eq!(OsdPosition::from(9), OsdPosition::TopLeft);
+    }
+}
@@ -0,0 +1,152 @@
+//! Palette viewer window showing all 32 entries of palette RAM with their color indices.
+
+use crate::{nes::Nes, ppu::Ppu};
+use pix_engine::prelude::*;
+
+const SWATCH: i32 = 36;
+const PADDING: i32 = 10;
+/// Width of the row labels left of the swatches.
+const LABEL_WIDTH: i32 = 90;
+
+#[derive(Debug)]
+pub(crate) struct PaletteViewer {
+    window_id: WindowId,
+}
+
+impl PaletteViewer {
+    const fn new(window_id: WindowId) -> Self {
+        Self { window_id }
+    }
+
+    pub(crate) const fn window_id(&self) -> WindowId {
+        self.window_id
+    }
+}
+
+/// Describes the palette RAM entry at `index`, 0-31. Entries 0, 4, 8 and 12 of each half are the
+/// backdrop color or its unused copies, which sprites mirror.
+fn describe_palette_entry(index: usize) -> String {
+    let addr = Ppu::PALETTE_START + index as u16;
+    let kind = if index < 16 { "Background" } else { "Sprite" };
+    let palette = (index % 16) / 4;
+    match index % 4 {
+        0 if index == 0 => format!("${addr:04X} Backdrop"),
+        0 if index >= 16 => format!("${addr:04X} Mirror of ${:04X}", addr - 0x10),
+        0 => format!("${addr:04X} Unused"),
+        color => format!("${addr:04X} {kind} {palette} Color {color}"),
+    }
+}
+
+impl Nes {
+    pub(crate) fn toggle_palette_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
+        match self.palette_viewer {
+            None => {
+                let w = LABEL_WIDTH + 16 * SWATCH + 2 * PADDING;
+                let h = 2 * SWATCH + 100;
+                let window_id = s
+                    .window()
+                    .dimensions(w as u32, h as u32)
+                    .title("Palette Viewer")
+                    .position(10, 10)
+                    .build()?;
+                self.palette_viewer = Some(PaletteViewer::new(window_id));
+                self.update_sprite_snapshots();
+            }
+            Some(ref viewer) => {
+                s.close_window(viewer.window_id())?;
+                self.close_palette_viewer();
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn close_palette_viewer(&mut self) {
+        self.palette_viewer = None;
+        self.update_sprite_snapshots();
+    }
+
+    pub(crate) fn render_palette_viewer(&mut self, s: &mut PixState) -> PixResult<()> {
+        let Some(ref viewer) = self.palette_viewer else {
+            return Ok(());
+        };
+        let window_id = viewer.window_id();
+        // Loading a save state replaces the PPU and stops capturing
+        self.update_sprite_snapshots();
+        let ppu = self.control_deck.ppu();
+        let palette = ppu.sprite_snapshot().map_or_else(
+            || ppu.snapshot_sprites().palette,
+            |snapshot| snapshot.palette,
+        );
+
+        s.set_window_target(window_id)?;
+        s.clear()?;
+        s.fill(Color::WHITE);
+        s.stroke(None);
+
+        let left = PADDING + LABEL_WIDTH;
+        for (row, label) in ["Background", "Sprites"].into_iter().enumerate() {
+            s.set_cursor_pos([PADDING, PADDING + row as i32 * SWATCH + SWATCH / 3]);
+            s.text(label)?;
+        }
+        s.push();
+        for (i, &color) in palette.iter().enumerate() {
+            let x = left + (i as i32 % 16) * SWATCH;
+            let y = PADDING + (i as i32 / 16) * SWATCH;
+            let color = color & 0x3F;
+            let (red, green, blue) = Ppu::system_palette(u16::from(color));
+            s.stroke(Color::DIM_GRAY);
+            s.fill(rgb!(red, green, blue));
+            s.rect([x, y, SWATCH, SWATCH])?;
+            // Dark text on light colors
+            let luma = u32::from(red) * 3 + u32::from(green) * 6 + u32::from(blue);
+            s.stroke(None);
+            s.fill(if luma > 1280 {
+                Color::BLACK
+            } else {
+                Color::WHITE
+            });
+            s.set_cursor_pos([x + 6, y + SWATCH / 3]);
+            s.text(format!("{color:02X}"))?;
+        }
+        s.pop();
+
+        let swatches = rect![left, PADDING, 16 * SWATCH, 2 * SWATCH];
+        s.set_cursor_pos([PADDING, swatches.bottom() + 8]);
+        let m = s.mouse_pos();
+        if s.focused_window(window_id) && swatches.contains(m) {
+            let col = (m.x() - swatches.x()) / SWATCH;
+            let row = (m.y() - swatches.y()) / SWATCH;
+            let index = (row * 16 + col) as usize;
+            let color = palette[index] & 0x3F;
+            let (red, green, blue) = Ppu::system_palette(u16::from(color));
+            s.text(format!(
+                "{}: ${color:02X} (#{red:02X}{green:02X}{blue:02X})",
+                describe_palette_entry(index)
+            ))?;
+        } else {
+            s.text("Hover a color for details.")?;
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Palette RAM as of the end of the last frame. Each color shows its index into the \
+            system palette.",
+        )?;
+
+        s.reset_window_target();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_entry_descriptions() {
+        assert_eq!(describe_palette_entry(0), "$3F00 Backdrop");
+        assert_eq!(describe_palette_entry(4), "$3F04 Unused");
+        assert_eq!(describe_palette_entry(7), "$3F07 Background 1 Color 3");
+        assert_eq!(describe_palette_entry(0x10), "$3F10 Mirror of $3F00");
+        assert_eq!(describe_palette_entry(0x19), "$3F19 Sprite 2 Color 1");
+    }
+}
@@ -0,0 +1,481 @@
+//! Storage for battery-backed Save RAM, save states and replays.
+//!
+//! All saved data goes through a [`Persistence`] backend selected by the `persistence` config
+//! setting. The [`Filesystem`] backend keeps one file per entry under the config directory. The
+//! `SQLite` backend keeps everything in a single `tetanes.db` file, which is easier to back up or
+//! sync, and requires the `sqlite` feature.
+
+use crate::{
+    common::config_dir,
+    nes::{
+        config::Config,
+        filesystem::{load_data, save_data},
+    },
+    NesResult,
+};
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsStr,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Which storage backend to use for saved data.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub enum PersistenceBackend {
+    #[default]
+    Filesystem,
+    Sqlite,
+}
+
+impl AsRef<str> for PersistenceBackend {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Filesystem => "Filesystem",
+            Self::Sqlite => "SQLite",
+        }
+    }
+}
+
+impl FromStr for PersistenceBackend {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "filesystem" => Ok(Self::Filesystem),
+            "sqlite" => Ok(Self::Sqlite),
+            _ => Err("invalid PersistenceBackend value. valid options: `filesystem` or `sqlite`"),
+        }
+    }
+}
+
+/// The kind of data being stored.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum DataKind {
+    /// Battery-backed Save RAM, keyed by ROM name.
+    Sram,
+    /// Save states, keyed by `<ROM name>/<slot>`.
+    State,
+    /// Replay recordings, keyed by file path.
+    Replay,
+}
+
+impl DataKind {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[Self::Sram, Self::State, Self::Replay]
+    }
+
+    #[must_use]
+    pub(crate) const fn as_str(self) -> &'static str {
+        match self {
+            Self::Sram => "sram",
+            Self::State => "state",
+            Self::Replay => "replay",
+        }
+    }
+}
+
+/// A storage backend for saved data.
+pub(crate) trait Persistence: fmt::Debug {
+    /// Stores data, replacing any previous value for the same key.
+    ///
+    /// # Errors
+    ///
+    /// If the data fails to be written, an error is returned.
+    fn save(&mut self, kind: DataKind, key: &str, data: &[u8]) -> NesResult<()>;
+
+    /// Loads data, returning `None` if nothing is stored for the key.
+    ///
+    /// # Errors
+    ///
+    /// If the data exists but fails to be read, an error is returned.
+    fn load(&self, kind: DataKind, key: &str) -> NesResult<Option<Vec<u8>>>;
+
+    /// Whether data is stored for the key.
+    fn exists(&self, kind: DataKind, key: &str) -> bool;
+
+    /// Lists all stored keys of the given kind.
+    ///
+    /// # Errors
+    ///
+    /// If the backend fails to be read, an error is returned.
+    fn keys(&self, kind: DataKind) -> NesResult<Vec<String>>;
+}
+
+/// Opens the given persistence backend.
+///
+/// # Errors
+///
+/// If the backend fails to open or isn't supported by this build, an error is returned.
+pub(crate) fn open(backend: PersistenceBackend) -> NesResult<Box<dyn Persistence>> {
+    match backend {
+        PersistenceBackend::Filesystem => Ok(Box::new(Filesystem::new(config_dir()))),
+        #[cfg(feature = "sqlite")]
+        PersistenceBackend::Sqlite => Ok(Box::new(sqlite::Sqlite::open(
+            config_dir().join(sqlite::DATABASE),
+        )?)),
+        #[cfg(not(feature = "sqlite"))]
+        PersistenceBackend::Sqlite => {
+            bail!("SQLite persistence requires building with the `sqlite` feature")
+        }
+    }
+}
+
+/// Copies all saved data from one backend to another, returning the number of entries copied.
+///
+/// # Errors
+///
+/// If either backend fails to open or any entry fails to copy, an error is returned.
+pub(crate) fn migrate(from: PersistenceBackend, to: PersistenceBackend) -> NesResult<usize> {
+    if from == to {
+        bail!("already using the {} backend", to.as_ref());
+    }
+    let source = open(from)?;
+    let mut destination = open(to)?;
+    copy_all(&*source, &mut *destination)
+}
+
+/// Copies every entry of `source` into `destination`, returning the number of entries copied.
+fn copy_all(source: &dyn Persistence, destination: &mut dyn Persistence) -> NesResult<usize> {
+    let mut count = 0;
+    for &kind in DataKind::as_slice() {
+        for key in source.keys(kind)? {
+            if let Some(data) = source.load(kind, &key)? {
+                destination.save(kind, &key, &data)?;
+                count += 1;
+            }
+        }
+    }
+    Ok(count)
+}
+
+/// Copies all saved data from the configured persistence backend to `backend` and makes it the
+/// configured backend.
+///
+/// # Errors
+///
+/// If migration fails or the configuration fails to save, an error is returned.
+pub fn migrate_saves(backend: PersistenceBackend) -> NesResult<()> {
+    let mut config = Config::load();
+    let count = migrate(config.persistence, backend)?;
+    log::info!(
+        "Migrated {count} entries from {} to {}",
+        config.persistence.as_ref(),
+        backend.as_ref()
+    );
+    config.persistence = backend;
+    config.save()
+}
+
+/// Stores each entry as a separate file under the config directory. Replays are stored at their
+/// key path, relative to the current directory, and listed in an index so they can be found
+/// again wherever they were written.
+#[derive(Debug, Clone)]
+#[must_use]
+pub(crate) struct Filesystem {
+    dir: PathBuf,
+}
+
+impl Filesystem {
+    const REPLAY_INDEX: &'static str = "replays.index";
+
+    pub(crate) fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The file an entry is stored in. The extension is appended rather than replaced so keys
+    /// containing dots, like `Super Mario Bros. (W)`, round-trip through [`Persistence::keys`].
+    fn path(&self, kind: DataKind, key: &str) -> PathBuf {
+        match kind {
+            DataKind::Sram => self.dir.join("sram").join(format!("{key}.sram")),
+            DataKind::State => self.dir.join("save").join(format!("{key}.save")),
+            DataKind::Replay => PathBuf::from(key),
+        }
+    }
+
+    /// The file an entry was stored in by earlier versions, which replaced anything after the
+    /// last dot in the key with the extension.
+    fn legacy_path(&self, kind: DataKind, key: &str) -> Option<PathBuf> {
+        let path = match kind {
+            DataKind::Sram => self.dir.join("sram").join(key).with_extension("sram"),
+            DataKind::State => self.dir.join("save").join(key).with_extension("save"),
+            DataKind::Replay => return None,
+        };
+        (path != self.path(kind, key)).then_some(path)
+    }
+
+    /// The path data for the key is stored at, preferring the current layout.
+    fn existing_path(&self, kind: DataKind, key: &str) -> Option<PathBuf> {
+        let path = self.path(kind, key);
+        if path.exists() {
+            return Some(path);
+        }
+        self.legacy_path(kind, key).filter(|path| path.exists())
+    }
+
+    /// Replay keys saved through this backend.
+    fn replay_index(&self) -> NesResult<Vec<String>> {
+        let path = self.dir.join(Self::REPLAY_INDEX);
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+        let index =
+            fs::read_to_string(&path).with_context(|| format!("failed to read {path:?}"))?;
+        Ok(index.lines().map(ToString::to_string).collect())
+    }
+
+    fn add_to_replay_index(&self, key: &str) -> NesResult<()> {
+        let mut index = self.replay_index()?;
+        if index.iter().any(|entry| entry == key) {
+            return Ok(());
+        }
+        index.push(key.to_string());
+        let path = self.dir.join(Self::REPLAY_INDEX);
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create directory {:?}", self.dir))?;
+        fs::write(&path, index.join("\n") + "\n")
+            .with_context(|| format!("failed to write {path:?}"))
+    }
+
+    /// Lists file stems with the given extension in a directory, returning nothing if the
+    /// directory doesn't exist.
+    fn stems(dir: PathBuf, extension: &str) -> NesResult<Vec<String>> {
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+        let mut stems = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension() == Some(OsStr::new(extension)) {
+                if let Some(stem) = path.file_stem().and_then(OsStr::to_str) {
+                    stems.push(stem.to_string());
+                }
+            }
+        }
+        Ok(stems)
+    }
+}
+
+impl Persistence for Filesystem {
+    fn save(&mut self, kind: DataKind, key: &str, data: &[u8]) -> NesResult<()> {
+        save_data(self.path(kind, key), data)?;
+        if kind == DataKind::Replay {
+            self.add_to_replay_index(key)?;
+        }
+        Ok(())
+    }
+
+    fn load(&self, kind: DataKind, key: &str) -> NesResult<Option<Vec<u8>>> {
+        self.existing_path(kind, key).map(load_data).transpose()
+    }
+
+    fn exists(&self, kind: DataKind, key: &str) -> bool {
+        self.existing_path(kind, key).is_some()
+    }
+
+    fn keys(&self, kind: DataKind) -> NesResult<Vec<String>> {
+        match kind {
+            DataKind::Sram => Self::stems(self.dir.join("sram"), "sram"),
+            DataKind::State => {
+                let dir = self.dir.join("save");
+                if !dir.is_dir() {
+                    return Ok(vec![]);
+                }
+                let mut keys = vec![];
+                for entry in fs::read_dir(dir)? {
+                    let path = entry?.path();
+                    let Some(name) = path.file_name().and_then(OsStr::to_str) else {
+                        continue;
+                    };
+                    for slot in Self::stems(path.clone(), "save")? {
+                        keys.push(format!("{name}/{slot}"));
+                    }
+                }
+                Ok(keys)
+            }
+            DataKind::Replay => {
+                let mut keys: Vec<String> = self
+                    .replay_index()?
+                    .into_iter()
+                    .filter(|key| Path::new(key).exists())
+                    .collect();
+                // Replays recorded before the index existed were saved to the current directory
+                for stem in Self::stems(PathBuf::from("."), "replay")? {
+                    let key = format!("{stem}.replay");
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+                Ok(keys)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::{DataKind, Persistence};
+    use crate::{
+        nes::filesystem::{decode_data, encode_data, validate_save_header, write_save_header},
+        NesResult,
+    };
+    use anyhow::Context;
+    use rusqlite::{params, Connection, OptionalExtension};
+    use std::path::Path;
+
+    pub(super) const DATABASE: &str = "tetanes.db";
+
+    /// Stores all entries in a single `SQLite` database using the same header and compression
+    /// as the save files written by the filesystem backend.
+    #[derive(Debug)]
+    #[must_use]
+    pub(crate) struct Sqlite {
+        conn: Connection,
+    }
+
+    impl Sqlite {
+        pub(crate) fn open<P: AsRef<Path>>(path: P) -> NesResult<Self> {
+            let path = path.as_ref();
+            let conn =
+                Connection::open(path).with_context(|| format!("failed to open {path:?}"))?;
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS data (
+                    kind TEXT NOT NULL,
+                    key TEXT NOT NULL,
+                    data BLOB NOT NULL,
+                    updated INTEGER NOT NULL,
+                    PRIMARY KEY (kind, key)
+                )",
+            )
+            .with_context(|| format!("failed to create tables in {path:?}"))?;
+            Ok(Self { conn })
+        }
+    }
+
+    impl Persistence for Sqlite {
+        fn save(&mut self, kind: DataKind, key: &str, data: &[u8]) -> NesResult<()> {
+            let mut blob = vec![];
+            write_save_header(&mut blob)?;
+            blob.extend(encode_data(data)?);
+            self.conn
+                .execute(
+                    "INSERT OR REPLACE INTO data (kind, key, data, updated)
+                    VALUES (?1, ?2, ?3, strftime('%s', 'now'))",
+                    params![kind.as_str(), key, blob],
+                )
+                .with_context(|| format!("failed to save {} {key:?}", kind.as_str()))?;
+            Ok(())
+        }
+
+        fn load(&self, kind: DataKind, key: &str) -> NesResult<Option<Vec<u8>>> {
+            let blob: Option<Vec<u8>> = self
+                .conn
+                .query_row(
+                    "SELECT data FROM data WHERE kind = ?1 AND key = ?2",
+                    params![kind.as_str(), key],
+                    |row| row.get(0),
+                )
+                .optional()
+                .with_context(|| format!("failed to load {} {key:?}", kind.as_str()))?;
+            blob.map(|blob| {
+                let mut blob = blob.as_slice();
+                validate_save_header(&mut blob)
+                    .with_context(|| format!("failed to validate header {key:?}"))?;
+                decode_data(blob)
+            })
+            .transpose()
+        }
+
+        fn exists(&self, kind: DataKind, key: &str) -> bool {
+            self.conn
+                .query_row(
+                    "SELECT 1 FROM data WHERE kind = ?1 AND key = ?2",
+                    params![kind.as_str(), key],
+                    |_| Ok(()),
+                )
+                .optional()
+                .map_or(false, |row| row.is_some())
+        }
+
+        fn keys(&self, kind: DataKind) -> NesResult<Vec<String>> {
+            let mut stmt = self
+                .conn
+                .prepare("SELECT key FROM data WHERE kind = ?1 ORDER BY key")?;
+            let keys = stmt
+                .query_map(params![kind.as_str()], |row| row.get(0))?
+                .collect::<Result<_, _>>()?;
+            Ok(keys)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filesystem_paths() {
+        let fs = Filesystem::new("/config");
+        assert_eq!(
+            fs.path(DataKind::Sram, "game"),
+            PathBuf::from("/config/sram/game.sram")
+        );
+        assert_eq!(
+            fs.path(DataKind::State, "game/1"),
+            PathBuf::from("/config/save/game/1.save")
+        );
+        assert_eq!(
+            fs.path(DataKind::Replay, "run.replay"),
+            PathBuf::from("run.replay")
+        );
+        assert_eq!(
+            fs.path(DataKind::Sram, "Super Mario Bros. (W)"),
+            PathBuf::from("/config/sram/Super Mario Bros. (W).sram")
+        );
+        assert_eq!(
+            fs.legacy_path(DataKind::Sram, "Super Mario Bros. (W)"),
+            Some(PathBuf::from("/config/sram/Super Mario Bros.sram"))
+        );
+        assert_eq!(fs.legacy_path(DataKind::Sram, "game"), None);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn migrate_filesystem_to_sqlite() {
+        let dir = std::env::temp_dir().join(format!("tetanes_persistence_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let replay = dir.join("replays").join("run.replay");
+        let replay_key = replay.to_string_lossy().into_owned();
+
+        let mut source = Filesystem::new(dir.join("config"));
+        let name = "Super Mario Bros. (W)";
+        source.save(DataKind::Sram, name, &[1, 2, 3]).unwrap();
+        source
+            .save(DataKind::State, &format!("{name}/1"), &[4, 5])
+            .unwrap();
+        source.save(DataKind::Replay, &replay_key, &[6]).unwrap();
+        assert_eq!(source.keys(DataKind::Sram).unwrap(), [name]);
+
+        let mut destination = sqlite::Sqlite::open(":memory:").unwrap();
+        let count = copy_all(&source, &mut destination).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        assert!(count >= 3);
+        assert_eq!(
+            destination.load(DataKind::Sram, name).unwrap(),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(
+            destination
+                .load(DataKind::State, &format!("{name}/1"))
+                .unwrap(),
+            Some(vec![4, 5])
+        );
+        assert_eq!(
+            destination.load(DataKind::Replay, &replay_key).unwrap(),
+            Some(vec![6])
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Platform integration for keeping the display awake while playing and dimming it when idle.
+//!
+//! SDL stops the screensaver from starting for as long as the window is open, so it's asked not
+//! to, and the screensaver and display sleep are instead held off only while a game is running.
+//! That's done by keeping a child process running that inhibits them for us: `systemd-inhibit`
+//! on Linux and `caffeinate` on macOS. Elsewhere the OS settings apply as usual.
+//!
+//! Dimming is done by the emulator: after a while without any input the window fades to dark,
+//! and comes back as soon as a key, button or the mouse is used.
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::nes::Mode;
+use crate::nes::Nes;
+use pix_engine::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How long dimming takes to fade in.
+const DIM_FADE: Duration = Duration::from_secs(2);
+/// Opacity of the overlay once fully dimmed.
+const DIM_ALPHA: u8 = 192;
+
+/// Holds off the screensaver and display sleep while inhibited.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default, Debug)]
+#[must_use]
+pub(crate) struct ScreenSaver {
+    inhibitor: Option<Child>,
+    /// Set once inhibiting fails, so it isn't retried every frame.
+    unsupported: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ScreenSaver {
+    /// Lets the screensaver start unless inhibited. Must be called before the window is created.
+    pub(crate) fn allow_by_default() {
+        std::env::set_var("SDL_VIDEO_ALLOW_SCREENSAVER", "1");
+    }
+
+    #[cfg(target_os = "linux")]
+    fn inhibit_command() -> Option<Command> {
+        let mut command = Command::new("systemd-inhibit");
+        command.args([
+            "--what=idle",
+            "--who=TetaNES",
+            "--why=Playing a game",
+            "sleep",
+            "infinity",
+        ]);
+        Some(command)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn inhibit_command() -> Option<Command> {
+        let mut command = Command::new("caffeinate");
+        // Exits on its own if TetaNES does
+        command.args(["-d", "-w", &std::process::id().to_string()]);
+        Some(command)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    const fn inhibit_command() -> Option<Command> {
+        None
+    }
+
+    /// Starts or stops inhibiting the screensaver.
+    pub(crate) fn set_inhibited(&mut self, inhibit: bool) {
+        if inhibit == self.inhibitor.is_some() || (inhibit && self.unsupported) {
+            return;
+        }
+        if let Some(mut inhibitor) = self.inhibitor.take() {
+            log::debug!("allowing screensaver");
+            if let Err(err) = inhibitor.kill().and_then(|_| inhibitor.wait()) {
+                log::warn!("failed to stop screensaver inhibitor: {err:?}");
+            }
+            return;
+        }
+        let Some(mut command) = Self::inhibit_command() else {
+            log::debug!("screensaver inhibiting is not supported on this platform");
+            self.unsupported = true;
+            return;
+        };
+        match command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(inhibitor) => {
+                log::debug!("inhibiting screensaver");
+                self.inhibitor = Some(inhibitor);
+            }
+            Err(err) => {
+                log::warn!("failed to inhibit screensaver: {err:?}");
+                self.unsupported = true;
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for ScreenSaver {
+    fn drop(&mut self) {
+        self.set_inhibited(false);
+    }
+}
+
+/// Tracks time since the last input to dim the screen when idle.
+#[derive(Debug, Copy, Clone)]
+#[must_use]
+pub(crate) struct IdleDim {
+    last_input: Instant,
+}
+
+impl Default for IdleDim {
+    fn default() -> Self {
+        Self {
+            last_input: Instant::now(),
+        }
+    }
+}
+
+impl IdleDim {
+    pub(crate) fn input(&mut self) {
+        self.last_input = Instant::now();
+    }
+
+    /// Opacity of the dimming overlay `idle` after the last input, when dimming `after` that
+    /// long. Fades in over [`DIM_FADE`].
+    fn alpha(idle: Duration, after: Duration) -> u8 {
+        let dimmed = idle.saturating_sub(after);
+        if after.is_zero() || idle < after {
+            0
+        } else if dimmed >= DIM_FADE {
+            DIM_ALPHA
+        } else {
+            (f32::from(DIM_ALPHA) * dimmed.as_secs_f32() / DIM_FADE.as_secs_f32()) as u8
+        }
+    }
+}
+
+impl Nes {
+    /// Inhibits the screensaver only while a game is running.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn update_screensaver(&mut self) {
+        let running = self.mode == Mode::Playing && self.control_deck.is_running();
+        self.screensaver
+            .set_inhibited(running && self.config.suppress_screensaver);
+    }
+
+    pub(crate) fn render_idle_dim(&mut self, s: &mut PixState) -> PixResult<()> {
+        let after = Duration::from_secs(60 * u64::from(self.config.idle_dim_minutes));
+        let alpha = IdleDim::alpha(self.idle_dim.last_input.elapsed(), after);
+        if alpha > 0 {
+            s.push();
+            s.stroke(None);
+            s.fill(rgb!(0, 0, 0, alpha));
+            s.rect([0, 0, s.width()? as i32, s.height()? as i32])?;
+            s.pop();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dims_after_idle() {
+        let minute = Duration::from_secs(60);
+        assert_eq!(IdleDim::alpha(10 * minute, Duration::ZERO), 0);
+        assert_eq!(IdleDim::alpha(4 * minute, 5 * minute), 0);
+        assert_eq!(IdleDim::alpha(5 * minute, 5 * minute), 0);
+        assert_eq!(
+            IdleDim::alpha(5 * minute + DIM_FADE / 2, 5 * minute),
+            DIM_ALPHA / 2
+        );
+        assert_eq!(IdleDim::alpha(6 * minute, 5 * minute), DIM_ALPHA);
+    }
+}
@@ -0,0 +1,761 @@
+//! Protects progress when a laptop or handheld sleeps or runs low on battery.
+//!
+//! Where the OS announces sleep ahead of time, play is paused, Save RAM is flushed and the current
+//! state is written to an auto-save slot before the system suspends. On Linux that's logind's
+//! `PrepareForSleep` signal, watched with `gdbus`, while a delay lock taken with `systemd-inhibit`
+//! holds off sleeping until saving is done. Windows and macOS send power notifications that are
+//! answered once saving is done. Battery charge is read from the OS every few seconds where
+//! supported and handled the same way.
+//!
+//! Without sleep notifications, a sleep is only noticed after waking, from the wall clock jumping
+//! ahead of the monotonic clock used by `Instant`, which stops while suspended on Linux and macOS.
+//! That can't be told apart from the clock being changed, so Save RAM is flushed but the auto-save
+//! slot is only written once confirmed.
+
+use crate::nes::{Mode, Nes};
+use pix_engine::prelude::*;
+use std::{
+    sync::mpsc::{channel, Receiver, TryRecvError},
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Slot name the auto-save state is stored under.
+pub(crate) const AUTO_SAVE_SLOT: &str = "auto";
+/// Wall clock time beyond the monotonic time that counts as a suspend.
+const SUSPEND_GAP: Duration = Duration::from_secs(5);
+/// How often to check the battery.
+const BATTERY_INTERVAL: Duration = Duration::from_secs(10);
+/// Longest an OS sleep notification is held while progress is saved.
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+const SLEEP_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum PowerEvent {
+    /// The system is about to sleep.
+    Sleeping,
+    /// The system woke from sleep.
+    Woke,
+    /// The wall clock jumped ahead, from a sleep that wasn't announced or the clock being changed.
+    ClockJump,
+    LowBattery(u32),
+}
+
+/// Sleep notifications sent by the OS.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum SleepSignal {
+    Sleeping,
+    Woke,
+}
+
+impl From<SleepSignal> for PowerEvent {
+    fn from(signal: SleepSignal) -> Self {
+        match signal {
+            SleepSignal::Sleeping => Self::Sleeping,
+            SleepSignal::Woke => Self::Woke,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Battery {
+    percent: u32,
+    discharging: bool,
+}
+
+impl Battery {
+    fn parse(capacity: &str, status: &str) -> Option<Self> {
+        Some(Self {
+            percent: capacity.trim().parse().ok()?,
+            discharging: status.trim().eq_ignore_ascii_case("discharging"),
+        })
+    }
+
+    /// Reads the first battery reported by the OS, if any.
+    #[cfg(target_os = "linux")]
+    fn read() -> Option<Self> {
+        use std::fs;
+        fs::read_dir("/sys/class/power_supply")
+            .ok()?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("BAT"))
+            .find_map(|entry| {
+                let path = entry.path();
+                let capacity = fs::read_to_string(path.join("capacity")).ok()?;
+                let status = fs::read_to_string(path.join("status")).ok()?;
+                Self::parse(&capacity, &status)
+            })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    const fn read() -> Option<Self> {
+        None
+    }
+}
+
+/// Holds an OS sleep notification until progress is saved or [`SLEEP_DELAY`] passes.
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+struct Waiter {
+    signals: std::sync::Mutex<std::sync::mpsc::Sender<SleepSignal>>,
+    ready: std::sync::Mutex<Receiver<()>>,
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+impl Waiter {
+    fn new(signals: std::sync::mpsc::Sender<SleepSignal>) -> (std::sync::mpsc::Sender<()>, Self) {
+        let (ready_tx, ready) = channel();
+        let waiter = Self {
+            signals: std::sync::Mutex::new(signals),
+            ready: std::sync::Mutex::new(ready),
+        };
+        (ready_tx, waiter)
+    }
+
+    fn send(&self, signal: SleepSignal) {
+        if let Ok(signals) = self.signals.lock() {
+            let _ = signals.send(signal);
+        }
+    }
+
+    /// Announces the sleep and blocks until progress is saved.
+    fn sleeping(&self) {
+        let Ok(ready) = self.ready.lock() else {
+            return;
+        };
+        // Drop readiness left over from a sleep that timed out
+        while ready.try_recv().is_ok() {}
+        self.send(SleepSignal::Sleeping);
+        let _ = ready.recv_timeout(SLEEP_DELAY);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod os {
+    use super::SleepSignal;
+    use std::{
+        io::{self, BufRead, BufReader},
+        process::{Child, Command, Stdio},
+        sync::mpsc::Sender,
+        thread,
+    };
+
+    /// Watches logind with `gdbus` and holds a delay lock with `systemd-inhibit`, so logind waits
+    /// for progress to be saved, up to its `InhibitDelayMaxSec`, before sleeping.
+    #[derive(Debug)]
+    pub(super) struct Listener {
+        monitor: Child,
+        lock: Option<Child>,
+    }
+
+    pub(super) fn listen(signals: Sender<SleepSignal>) -> io::Result<Listener> {
+        let mut monitor = Command::new("gdbus")
+            .args(["monitor", "--system", "--dest", "org.freedesktop.login1"])
+            .args(["--object-path", "/org/freedesktop/login1"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdout = monitor.stdout.take();
+        let listener = Listener {
+            monitor,
+            lock: take_lock(),
+        };
+        let stdout =
+            stdout.ok_or_else(|| io::Error::new(io::ErrorKind::Other, "no gdbus output"))?;
+        thread::Builder::new()
+            .name("sleep listener".into())
+            .spawn(move || {
+                for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                    if let Some(signal) = parse_signal(&line) {
+                        if signals.send(signal).is_err() {
+                            break;
+                        }
+                    }
+                }
+            })?;
+        Ok(listener)
+    }
+
+    fn take_lock() -> Option<Child> {
+        Command::new("systemd-inhibit")
+            .args([
+                "--what=sleep",
+                "--mode=delay",
+                "--who=TetaNES",
+                "--why=Saving game progress",
+                "sleep",
+                "infinity",
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| log::warn!("failed to take sleep delay lock: {err:?}"))
+            .ok()
+    }
+
+    fn stop(mut child: Child) {
+        if let Err(err) = child.kill().and_then(|_| child.wait()) {
+            log::warn!("failed to stop {child:?}: {err:?}");
+        }
+    }
+
+    /// Parses a `PrepareForSleep` signal from a line of `gdbus monitor` output.
+    pub(super) fn parse_signal(line: &str) -> Option<SleepSignal> {
+        let (_, args) = line.split_once("org.freedesktop.login1.Manager.PrepareForSleep")?;
+        match args.trim() {
+            "(true,)" => Some(SleepSignal::Sleeping),
+            "(false,)" => Some(SleepSignal::Woke),
+            _ => None,
+        }
+    }
+
+    impl Listener {
+        /// Releases the delay lock so the system can sleep.
+        pub(super) fn ready_to_sleep(&mut self) {
+            if let Some(lock) = self.lock.take() {
+                stop(lock);
+            }
+        }
+
+        /// Takes the delay lock again for the next sleep.
+        pub(super) fn woke(&mut self) {
+            if self.lock.is_none() {
+                self.lock = take_lock();
+            }
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            self.ready_to_sleep();
+            let _ = self.monitor.kill();
+            let _ = self.monitor.wait();
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod os {
+    use super::{SleepSignal, Waiter};
+    use std::{ffi::c_void, io, ptr, sync::mpsc::Sender};
+
+    const DEVICE_NOTIFY_CALLBACK: u32 = 2;
+    const PBT_APMSUSPEND: u32 = 0x04;
+    const PBT_APMRESUMEAUTOMATIC: u32 = 0x12;
+    const ERROR_SUCCESS: u32 = 0;
+
+    type Callback = unsafe extern "system" fn(*mut c_void, u32, *mut c_void) -> u32;
+
+    #[repr(C)]
+    struct DeviceNotifySubscribeParameters {
+        callback: Callback,
+        context: *mut c_void,
+    }
+
+    #[link(name = "powrprof")]
+    extern "system" {
+        fn PowerRegisterSuspendResumeNotification(
+            flags: u32,
+            recipient: *mut c_void,
+            registration: *mut *mut c_void,
+        ) -> u32;
+    }
+
+    /// Answers suspend notifications, which Windows waits on before sleeping.
+    #[derive(Debug)]
+    pub(super) struct Listener {
+        ready: Sender<()>,
+    }
+
+    unsafe extern "system" fn callback(context: *mut c_void, kind: u32, _: *mut c_void) -> u32 {
+        // SAFETY: The context is the waiter leaked at registration, which is never freed
+        let waiter = &*context.cast::<Waiter>();
+        match kind {
+            PBT_APMSUSPEND => waiter.sleeping(),
+            PBT_APMRESUMEAUTOMATIC => waiter.send(SleepSignal::Woke),
+            _ => (),
+        }
+        ERROR_SUCCESS
+    }
+
+    pub(super) fn listen(signals: Sender<SleepSignal>) -> io::Result<Listener> {
+        let (ready, waiter) = Waiter::new(signals);
+        // Leaked, as notifications are received until exit
+        let params = Box::into_raw(Box::new(DeviceNotifySubscribeParameters {
+            callback,
+            context: Box::into_raw(Box::new(waiter)).cast(),
+        }));
+        let mut registration = ptr::null_mut();
+        // SAFETY: The parameters and waiter are valid for the rest of the program
+        let result = unsafe {
+            PowerRegisterSuspendResumeNotification(
+                DEVICE_NOTIFY_CALLBACK,
+                params.cast(),
+                &mut registration,
+            )
+        };
+        if result == ERROR_SUCCESS {
+            Ok(Listener { ready })
+        } else {
+            Err(io::Error::from_raw_os_error(result as i32))
+        }
+    }
+
+    impl Listener {
+        pub(super) fn ready_to_sleep(&mut self) {
+            let _ = self.ready.send(());
+        }
+
+        pub(super) fn woke(&mut self) {}
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod os {
+    use super::{SleepSignal, Waiter};
+    use std::{
+        ffi::c_void,
+        io, ptr,
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            mpsc::{channel, Sender},
+        },
+        thread,
+    };
+
+    const CAN_SYSTEM_SLEEP: u32 = 0xE000_0270;
+    const SYSTEM_WILL_SLEEP: u32 = 0xE000_0280;
+    const SYSTEM_HAS_POWERED_ON: u32 = 0xE000_0300;
+
+    type Callback = unsafe extern "C" fn(*mut c_void, u32, u32, *mut c_void);
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IORegisterForSystemPower(
+            refcon: *mut c_void,
+            port: *mut *mut c_void,
+            callback: Callback,
+            notifier: *mut u32,
+        ) -> u32;
+        fn IONotificationPortGetRunLoopSource(port: *mut c_void) -> *mut c_void;
+        fn IOAllowPowerChange(kernel_port: u32, notification: isize) -> i32;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    #[allow(non_upper_case_globals)]
+    extern "C" {
+        static kCFRunLoopDefaultMode: *const c_void;
+        fn CFRunLoopGetCurrent() -> *mut c_void;
+        fn CFRunLoopAddSource(run_loop: *mut c_void, source: *mut c_void, mode: *const c_void);
+        fn CFRunLoopRun();
+    }
+
+    struct Context {
+        waiter: Waiter,
+        root_port: AtomicU32,
+    }
+
+    /// Answers system power notifications, which macOS waits on before sleeping.
+    #[derive(Debug)]
+    pub(super) struct Listener {
+        ready: Sender<()>,
+    }
+
+    unsafe extern "C" fn callback(
+        refcon: *mut c_void,
+        _: u32,
+        message: u32,
+        argument: *mut c_void,
+    ) {
+        // SAFETY: The refcon is the context leaked at registration, which is never freed
+        let context = &*refcon.cast::<Context>();
+        let port = context.root_port.load(Ordering::Acquire);
+        match message {
+            CAN_SYSTEM_SLEEP => {
+                IOAllowPowerChange(port, argument as isize);
+            }
+            SYSTEM_WILL_SLEEP => {
+                context.waiter.sleeping();
+                IOAllowPowerChange(port, argument as isize);
+            }
+            SYSTEM_HAS_POWERED_ON => context.waiter.send(SleepSignal::Woke),
+            _ => (),
+        }
+    }
+
+    pub(super) fn listen(signals: Sender<SleepSignal>) -> io::Result<Listener> {
+        let (ready, waiter) = Waiter::new(signals);
+        let (started_tx, started) = channel();
+        thread::Builder::new()
+            .name("sleep listener".into())
+            .spawn(move || {
+                // Leaked, as notifications are received until exit
+                let context: &'static Context = Box::leak(Box::new(Context {
+                    waiter,
+                    root_port: AtomicU32::new(0),
+                }));
+                let mut port = ptr::null_mut();
+                let mut notifier = 0;
+                // SAFETY: The context is valid for the rest of the program
+                let root_port = unsafe {
+                    IORegisterForSystemPower(
+                        (context as *const Context as *mut Context).cast(),
+                        &mut port,
+                        callback,
+                        &mut notifier,
+                    )
+                };
+                let _ = started_tx.send(root_port != 0);
+                if root_port == 0 {
+                    return;
+                }
+                context.root_port.store(root_port, Ordering::Release);
+                // SAFETY: The port was just registered and notifications run on this thread
+                unsafe {
+                    CFRunLoopAddSource(
+                        CFRunLoopGetCurrent(),
+                        IONotificationPortGetRunLoopSource(port),
+                        kCFRunLoopDefaultMode,
+                    );
+                    CFRunLoopRun();
+                }
+            })?;
+        if started.recv().unwrap_or(false) {
+            Ok(Listener { ready })
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                "failed to register for system power notifications",
+            ))
+        }
+    }
+
+    impl Listener {
+        pub(super) fn ready_to_sleep(&mut self) {
+            let _ = self.ready.send(());
+        }
+
+        pub(super) fn woke(&mut self) {}
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows", target_os = "macos")))]
+mod os {
+    use super::SleepSignal;
+    use std::{io, sync::mpsc::Sender};
+
+    #[derive(Debug)]
+    pub(super) struct Listener;
+
+    pub(super) fn listen(_signals: Sender<SleepSignal>) -> io::Result<Listener> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "sleep notifications are not supported on this platform",
+        ))
+    }
+
+    impl Listener {
+        pub(super) fn ready_to_sleep(&mut self) {}
+
+        pub(super) fn woke(&mut self) {}
+    }
+}
+
+/// Receives sleep notifications from the OS.
+#[derive(Debug)]
+struct SleepListener {
+    signals: Receiver<SleepSignal>,
+    os: os::Listener,
+}
+
+impl SleepListener {
+    fn start() -> Option<Self> {
+        let (signals_tx, signals) = channel();
+        match os::listen(signals_tx) {
+            Ok(os) => Some(Self { signals, os }),
+            Err(err) => {
+                log::info!("sleep notifications are unavailable: {err:?}");
+                None
+            }
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+#[must_use]
+pub(crate) struct PowerMonitor {
+    last_update: Option<(Instant, SystemTime)>,
+    last_battery_check: Option<Instant>,
+    // Only handle low battery once per discharge
+    low_battery: bool,
+    sleep_listener: Option<SleepListener>,
+    /// Set once sleep notifications turn out to be unavailable, so they aren't retried every
+    /// frame.
+    sleep_unsupported: bool,
+    /// Writing the auto-save slot after a clock jump is waiting to be confirmed.
+    confirm_auto_save: bool,
+}
+
+impl PowerMonitor {
+    /// Checks for a sleep notification, a system wake or low battery since the last update.
+    /// Nothing is checked unless `enabled`.
+    pub(crate) fn update(&mut self, enabled: bool, low_battery_percent: u32) -> Option<PowerEvent> {
+        if !enabled {
+            // Stops holding off sleep
+            self.sleep_listener = None;
+            self.last_update = None;
+            return None;
+        }
+        if self.sleep_listener.is_none() && !self.sleep_unsupported {
+            self.sleep_listener = SleepListener::start();
+            self.sleep_unsupported = self.sleep_listener.is_none();
+        }
+        if let Some(ref mut listener) = self.sleep_listener {
+            match listener.signals.try_recv() {
+                Ok(signal) => {
+                    if signal == SleepSignal::Woke {
+                        listener.os.woke();
+                    }
+                    return Some(signal.into());
+                }
+                Err(TryRecvError::Empty) => (),
+                Err(TryRecvError::Disconnected) => {
+                    log::warn!("sleep notifications stopped");
+                    self.sleep_listener = None;
+                    self.sleep_unsupported = true;
+                }
+            }
+        }
+
+        let now = Instant::now();
+        let wall = SystemTime::now();
+        if let Some((last, last_wall)) = self.last_update.replace((now, wall)) {
+            // Only a fallback, sleeps are handled before suspending when they're announced
+            if self.sleep_listener.is_none()
+                && Self::suspended(
+                    now - last,
+                    wall.duration_since(last_wall).unwrap_or_default(),
+                )
+            {
+                return Some(PowerEvent::ClockJump);
+            }
+        }
+        if self
+            .last_battery_check
+            .map_or(true, |last| now - last >= BATTERY_INTERVAL)
+        {
+            self.last_battery_check = Some(now);
+            if let Some(battery) = Battery::read() {
+                return self.check_battery(battery, low_battery_percent);
+            }
+        }
+        None
+    }
+
+    /// Lets the system sleep once progress is saved after [`PowerEvent::Sleeping`].
+    pub(crate) fn ready_to_sleep(&mut self) {
+        if let Some(ref mut listener) = self.sleep_listener {
+            listener.os.ready_to_sleep();
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub(crate) const fn confirming_auto_save(&self) -> bool {
+        self.confirm_auto_save
+    }
+
+    fn suspended(elapsed: Duration, wall_elapsed: Duration) -> bool {
+        wall_elapsed.saturating_sub(elapsed) > SUSPEND_GAP
+    }
+
+    fn check_battery(&mut self, battery: Battery, low_battery_percent: u32) -> Option<PowerEvent> {
+        if !battery.discharging || battery.percent > low_battery_percent {
+            self.low_battery = false;
+            None
+        } else if self.low_battery {
+            None
+        } else {
+            self.low_battery = true;
+            Some(PowerEvent::LowBattery(battery.percent))
+        }
+    }
+}
+
+impl Nes {
+    /// Pauses and saves progress before the system sleeps or when the battery runs low.
+    pub(crate) fn check_power(&mut self) {
+        if self.mode == Mode::Playing {
+            self.power_monitor.confirm_auto_save = false;
+        }
+        let Some(event) = self
+            .power_monitor
+            .update(self.config.power_save, self.config.low_battery_percent)
+        else {
+            return;
+        };
+        log::info!("power event: {event:?}");
+        if event != PowerEvent::Woke && self.control_deck.loaded_rom().is_some() {
+            self.save_progress(event);
+        }
+        if event == PowerEvent::Sleeping {
+            self.power_monitor.ready_to_sleep();
+        }
+    }
+
+    fn save_progress(&mut self, event: PowerEvent) {
+        if matches!(self.mode, Mode::Playing | Mode::PausedBg) {
+            self.pause_play();
+        }
+        if let Err(err) = self.save_sram() {
+            log::error!("{err:?}");
+        }
+        let reason = match event {
+            PowerEvent::Sleeping | PowerEvent::Woke => "Paused for sleep".to_string(),
+            PowerEvent::ClockJump => "Paused after sleep".to_string(),
+            PowerEvent::LowBattery(percent) => format!("Paused on low battery ({percent}%)"),
+        };
+        if event == PowerEvent::ClockJump {
+            // A missed sleep can't be told apart from the clock being changed, so the auto-save
+            // isn't replaced unless asked to
+            self.power_monitor.confirm_auto_save = true;
+            self.add_message(reason);
+        } else if self.write_auto_save() {
+            self.add_message(format!("{reason}, progress auto-saved"));
+        } else {
+            self.add_message(reason);
+        }
+    }
+
+    /// Writes the current state to the auto-save slot, returning whether it was saved.
+    fn write_auto_save(&mut self) -> bool {
+        if self.config.rom_path.to_string_lossy().contains("test") {
+            return false;
+        }
+        match self
+            .save_key(AUTO_SAVE_SLOT)
+            .and_then(|key| self.write_state(&key))
+        {
+            Ok(()) => true,
+            Err(err) => {
+                log::error!("{err:?}");
+                false
+            }
+        }
+    }
+
+    /// Closes the auto-save prompt shown after a clock jump, writing the auto-save if `save`.
+    pub(crate) fn close_auto_save_prompt(&mut self, save: bool) {
+        if std::mem::take(&mut self.power_monitor.confirm_auto_save) && save {
+            if self.write_auto_save() {
+                self.add_message("Progress auto-saved");
+            } else {
+                self.add_message("Failed to auto-save");
+            }
+        }
+    }
+
+    /// Asks whether to write the auto-save after a clock jump, returning whether it's shown.
+    pub(crate) fn render_auto_save_prompt(&mut self, s: &mut PixState) -> PixResult<bool> {
+        if !self.power_monitor.confirm_auto_save {
+            return Ok(false);
+        }
+        let msg = "The system clock jumped ahead, possibly from a sleep. Save the current state \
+            to the auto-save slot?";
+        s.push();
+        s.stroke(None);
+        s.fill(rgb!(0, 200));
+        let pady = s.theme().spacing.frame_pad.y();
+        let width = s.width()?;
+        s.wrap(width);
+        let (_, height) = s.size_of(msg)?;
+        s.rect([
+            0,
+            s.cursor_pos().y() - pady,
+            width as i32,
+            2 * height as i32 + 4 * pady,
+        ])?;
+        s.fill(Color::WHITE);
+        s.text(msg)?;
+        let save = s.button("Auto-Save")?;
+        s.same_line(None);
+        let dismiss = s.button("Dismiss")?;
+        s.pop();
+        if save || dismiss {
+            self.close_auto_save_prompt(save);
+        }
+        Ok(true)
+    }
+
+    pub(crate) fn load_auto_save(&mut self) {
+        self.load_state_slot(AUTO_SAVE_SLOT, "auto-save");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_suspend_and_low_battery() {
+        let second = Duration::from_secs(1);
+        assert!(!PowerMonitor::suspended(second, second));
+        assert!(!PowerMonitor::suspended(second, 3 * second));
+        assert!(PowerMonitor::suspended(second, 60 * second));
+        // Wall clock adjusted backwards
+        assert!(!PowerMonitor::suspended(60 * second, second));
+
+        let mut monitor = PowerMonitor::default();
+        let battery = |capacity, status| Battery::parse(capacity, status).expect("valid battery");
+        assert_eq!(
+            monitor.check_battery(battery("50\n", "Discharging\n"), 10),
+            None
+        );
+        assert_eq!(
+            monitor.check_battery(battery("9\n", "Discharging\n"), 10),
+            Some(PowerEvent::LowBattery(9))
+        );
+        assert_eq!(monitor.check_battery(battery("8", "Discharging"), 10), None);
+        assert_eq!(monitor.check_battery(battery("8", "Charging"), 10), None);
+        assert_eq!(
+            monitor.check_battery(battery("7", "Discharging"), 10),
+            Some(PowerEvent::LowBattery(7))
+        );
+        assert_eq!(Battery::parse("unknown", "Full"), None);
+    }
+
+    #[test]
+    fn clock_jump_is_a_fallback() {
+        let minute = Duration::from_secs(60);
+        let mut monitor = PowerMonitor {
+            sleep_unsupported: true,
+            last_update: Some((Instant::now(), SystemTime::now() - minute)),
+            ..PowerMonitor::default()
+        };
+        assert_eq!(monitor.update(true, 0), Some(PowerEvent::ClockJump));
+
+        monitor.last_update = Some((Instant::now(), SystemTime::now() - minute));
+        assert_eq!(monitor.update(false, 0), None);
+        assert!(monitor.last_update.is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parses_logind_sleep_signals() {
+        let signal = "/org/freedesktop/login1: org.freedesktop.login1.Manager.PrepareForSleep";
+        assert_eq!(
+            os::parse_signal(&format!("{signal} (true,)")),
+            Some(SleepSignal::Sleeping)
+        );
+        assert_eq!(
+            os::parse_signal(&format!("{signal} (false,)")),
+            Some(SleepSignal::Woke)
+        );
+        assert_eq!(
+            os::parse_signal(
+                "/org/freedesktop/login1: org.freedesktop.login1.Manager.SessionNew ('3', '/')"
+            ),
+            None
+        );
+    }
+}
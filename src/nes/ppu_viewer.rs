@@ -1,7 +1,10 @@
 use crate::{
     mem::{Access, Mem},
-    nes::Nes,
-    ppu::{scroll::PpuScroll, Mirroring, Ppu},
+    nes::{
+        nametable_viewer::{load_nametable, NametableTile},
+        Nes,
+    },
+    ppu::{Mirroring, Ppu},
 };
 use pix_engine::prelude::*;
 
@@ -12,7 +15,7 @@ pub(crate) struct PpuViewer {
     mirroring: Mirroring,
     scanline: u32,
     nametables: [Vec<u8>; 4],
-    nametable_ids: Vec<u8>,
+    nametable_tiles: [Vec<NametableTile>; 4],
     pattern_tables: [Vec<u8>; 2],
     palette: [u8; Self::PALETTE_SIZE],
     palette_ids: [u8; Self::PALETTE_SIZE],
@@ -20,7 +23,6 @@ pub(crate) struct PpuViewer {
 
 impl PpuViewer {
     const NAMETABLE_SIZE: usize = 4 * Ppu::SIZE;
-    const NAMETABLE_IDS_SIZE: usize = 4 * Ppu::NT_SIZE as usize;
     const PATTERN_SIZE: usize = 4 * (Ppu::WIDTH * Ppu::WIDTH) as usize / 2;
     const PALETTE_SIZE: usize = (32 + 4) * 4;
     const PALETTE_HEIGHT: i32 = 64;
@@ -37,7 +39,9 @@ impl PpuViewer {
                 vec![0x00; Self::NAMETABLE_SIZE],
                 vec![0x00; Self::NAMETABLE_SIZE],
             ],
-            nametable_ids: vec![0; Self::NAMETABLE_IDS_SIZE],
+            nametable_tiles: std::array::from_fn(|_| {
+                vec![NametableTile::default(); Ppu::NT_SIZE as usize]
+            }),
             pattern_tables: [
                 vec![0x00; Self::PATTERN_SIZE],
                 vec![0x00; Self::PATTERN_SIZE],
@@ -69,40 +73,13 @@ impl PpuViewer {
 
     pub(crate) fn load_nametables(&mut self, ppu: &Ppu) {
         self.mirroring = ppu.mirroring();
-        for (i, nametable) in self.nametables.iter_mut().enumerate() {
-            let base_addr = Ppu::NT_START + (i as u16) * Ppu::NT_SIZE;
-            for addr in base_addr..(base_addr + Ppu::NT_SIZE - 64) {
-                let x_scroll = addr & PpuScroll::COARSE_X_MASK;
-                let y_scroll = (addr & PpuScroll::COARSE_Y_MASK) >> 5;
-
-                let nt_base_addr =
-                    Ppu::NT_START + (addr & (PpuScroll::NT_X_MASK | PpuScroll::NT_Y_MASK));
-                let tile = ppu.peek(addr, Access::Dummy);
-                let tile_addr = ppu.ctrl().bg_select() + u16::from(tile) * 16;
-                let supertile = (x_scroll / 4) + (y_scroll / 4) * 8;
-                let attr = u16::from(ppu.peek(nt_base_addr + 0x03C0 + supertile, Access::Dummy));
-                let corner = ((x_scroll % 4) / 2 + (y_scroll % 4) / 2 * 2) << 1;
-                let mask = 0x03 << corner;
-                let palette = (attr & mask) >> corner;
-
-                let tile_num = x_scroll + y_scroll * 32;
-                let tile_x = (tile_num % 32) * 8;
-                let tile_y = (tile_num / 32) * 8;
-
-                self.nametable_ids[(addr - Ppu::NT_START) as usize] = tile;
-                for y in 0..8 {
-                    let lo = u16::from(ppu.peek(tile_addr + y, Access::Dummy));
-                    let hi = u16::from(ppu.peek(tile_addr + y + 8, Access::Dummy));
-                    for x in 0..8 {
-                        let pix_type = ((lo >> x) & 1) + (((hi >> x) & 1) << 1);
-                        let palette_idx =
-                            ppu.peek(Ppu::PALETTE_START + palette * 4 + pix_type, Access::Dummy);
-                        let x = u32::from(tile_x + (7 - x));
-                        let y = u32::from(tile_y + y);
-                        Self::set_pixel(palette_idx.into(), x, y, Ppu::WIDTH, nametable);
-                    }
-                }
-            }
+        for (i, (nametable, tiles)) in self
+            .nametables
+            .iter_mut()
+            .zip(self.nametable_tiles.iter_mut())
+            .enumerate()
+        {
+            load_nametable(ppu, i, nametable, tiles);
         }
     }
 
@@ -241,7 +218,11 @@ impl Nes {
                 let y = m.y() - nametable_src.y();
                 let nt_addr = (x / width) * 0x0400 + (y / height) * 0x0800;
                 let ppu_addr = nt_addr + ((((y / 8) % 30) << 5) | ((x / 8) % 32));
-                let tile_id = viewer.nametable_ids.get(ppu_addr as usize).unwrap_or(&0x00);
+                let tile_id = viewer
+                    .nametable_tiles
+                    .get(ppu_addr as usize / 0x0400)
+                    .and_then(|tiles| tiles.get(ppu_addr as usize % 0x0400))
+                    .map_or(0x00, |tile| tile.tile);
                 s.text(&format!("Tile ID: ${tile_id:02X}"))?;
                 s.text(&format!("(X, Y): ({x}, {y})"))?;
                 s.text(&format!("Nametable: ${nt_addr:04X}"))?;
@@ -0,0 +1,143 @@
+//! Warns when a game the game database lists as made for PAL consoles is run with NTSC timing,
+//! or the reverse. PAL games run too fast at 60Hz and NTSC games too slow at 50Hz, which is easy
+//! to miss until the music sounds wrong.
+//!
+//! Games are loaded with their database region, so a mismatch comes from a launch option or from
+//! changing `NES Region` while the game is running.
+
+use crate::{
+    common::{NesRegion, Regional},
+    nes::Nes,
+};
+use pix_engine::prelude::*;
+
+/// Whether a game made for `game` runs at the wrong speed with `running` timing. Dendy timing
+/// runs PAL games at their intended speed.
+const fn region_mismatch(game: NesRegion, running: NesRegion) -> bool {
+    matches!(game, NesRegion::Ntsc) != matches!(running, NesRegion::Ntsc)
+}
+
+fn region_warning_text(game: NesRegion, running: NesRegion) -> String {
+    let speed = if game == NesRegion::Ntsc {
+        "slow"
+    } else {
+        "fast"
+    };
+    format!(
+        "This game is made for {} consoles but is running with {} timing, so music and \
+        gameplay will run too {speed}.",
+        game.as_ref(),
+        running.as_ref(),
+    )
+}
+
+impl Nes {
+    /// Region the loaded game was made for, if it's running with the wrong region's timing.
+    pub(crate) fn mismatched_game_region(&self) -> Option<NesRegion> {
+        self.control_deck
+            .game_region()
+            .filter(|&game| region_mismatch(game, self.config.region))
+    }
+
+    /// Pauses with a warning if the loaded game is running with the wrong region's timing.
+    pub(crate) fn check_region(&mut self) {
+        self.region_warning = None;
+        if !self.config.region_warning {
+            return;
+        }
+        if let Some(game) = self.mismatched_game_region() {
+            self.region_warning = Some(game);
+            self.pause_play();
+        }
+    }
+
+    pub(crate) fn set_region(&mut self, s: &mut PixState, region: NesRegion) -> PixResult<()> {
+        self.region_warning = None;
+        self.config.region = region;
+        self.control_deck.set_region(region);
+        s.set_window_dimensions(self.config.get_dimensions())?;
+        self.update_frame_rate(s)?;
+        self.open_audio(s)
+    }
+
+    /// Closes the region warning, switching to the game's region if `switch`.
+    pub(crate) fn close_region_warning(&mut self, s: &mut PixState, switch: bool) -> PixResult<()> {
+        if let Some(game) = self.region_warning.take() {
+            if switch {
+                self.set_region(s, game)?;
+                self.add_message(format!("Switched to {}", game.as_ref()));
+            }
+            self.resume_play();
+        }
+        Ok(())
+    }
+
+    pub(crate) fn render_region_warning(&mut self, s: &mut PixState) -> PixResult<()> {
+        let Some(game) = self.region_warning else {
+            return Ok(());
+        };
+        let msg = region_warning_text(game, self.config.region);
+        s.push();
+        s.stroke(None);
+        s.fill(rgb!(0, 200));
+        let pady = s.theme().spacing.frame_pad.y();
+        let width = s.width()?;
+        s.wrap(width);
+        let (_, height) = s.size_of(&msg)?;
+        s.rect([
+            0,
+            s.cursor_pos().y() - pady,
+            width as i32,
+            2 * height as i32 + 4 * pady,
+        ])?;
+        s.fill(Color::WHITE);
+        s.text(&msg)?;
+        let switch = s.button(format!("Switch to {}", game.as_ref()))?;
+        s.same_line(None);
+        let dismiss = s.button("Dismiss")?;
+        s.pop();
+        if switch || dismiss {
+            self.close_region_warning(s, switch)?;
+        }
+        Ok(())
+    }
+
+    /// Warns about a region mismatch below the `NES Region` setting, offering to switch back.
+    pub(crate) fn render_region_mismatch(&mut self, s: &mut PixState) -> PixResult<()> {
+        let Some(game) = self.mismatched_game_region() else {
+            return Ok(());
+        };
+        s.push();
+        s.fill(Color::YELLOW);
+        s.text(region_warning_text(game, self.config.region))?;
+        s.pop();
+        if s.button(format!("Switch to {}", game.as_ref()))? {
+            self.set_region(s, game)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_mismatches() {
+        use NesRegion::{Dendy, Ntsc, Pal};
+        assert!(!region_mismatch(Ntsc, Ntsc));
+        assert!(!region_mismatch(Pal, Pal));
+        assert!(!region_mismatch(Pal, Dendy));
+        assert!(region_mismatch(Ntsc, Pal));
+        assert!(region_mismatch(Ntsc, Dendy));
+        assert!(region_mismatch(Pal, Ntsc));
+    }
+
+    #[test]
+    fn warning_text() {
+        let text = region_warning_text(NesRegion::Pal, NesRegion::Ntsc);
+        assert!(text.contains("made for PAL"));
+        assert!(text.ends_with("too fast."));
+        assert!(region_warning_text(NesRegion::Ntsc, NesRegion::Pal).ends_with("too slow."));
+    }
+}
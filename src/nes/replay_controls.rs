@@ -0,0 +1,181 @@
+//! Replay playback controls.
+//!
+//! Playback keeps a greenzone state every second to seek from. Playback can be paused, fast
+//! forwarded and stepped like normal play. Taking control discards the rest of the replay and
+//! continues recording from live input at the current frame, for re-recording a section of a run.
+
+use crate::{
+    nes::{
+        event::Action,
+        greenzone::Greenzone,
+        replay_format::{BUTTONS, SLOTS},
+        state::ReplayMode,
+        Nes,
+    },
+    NesResult,
+};
+use pix_engine::prelude::*;
+use std::mem;
+
+/// Frames between greenzone save states.
+const GREENZONE_INTERVAL: usize = 60;
+/// Frames skipped by seeking backward or forward.
+const SEEK_STEP: u32 = 300;
+const FAST_FORWARD_SPEED: f32 = 2.0;
+
+pub(crate) type ReplayGreenzone = Greenzone<GREENZONE_INTERVAL>;
+
+impl Nes {
+    /// Adds a greenzone state for the current frame of playback, before its events are played.
+    pub(crate) fn update_replay_greenzone(&mut self) {
+        if self.replay.mode == ReplayMode::Playback {
+            let frame = self.control_deck.frame_number() as usize;
+            self.replay.greenzone.update(frame, self.control_deck.cpu());
+        }
+    }
+
+    /// Seeks replay playback to the given frame, starting from the nearest greenzone state.
+    ///
+    /// # Errors
+    ///
+    /// If the CPU encounters an invalid opcode while seeking, an error is returned.
+    pub(crate) fn seek_replay(&mut self, s: &mut PixState, frame: u32) -> NesResult<()> {
+        if self.replay.mode != ReplayMode::Playback {
+            return Ok(());
+        }
+        let current_frame = self.control_deck.frame_number();
+        let nearest = self.replay.greenzone.nearest_frame(frame as usize);
+        // Load a state when going backward, or when one skips some of the frames to get there
+        if frame < current_frame
+            || nearest.map_or(false, |nearest| nearest > current_frame as usize)
+        {
+            let Some(start) = self.replay.start.clone() else {
+                return Ok(());
+            };
+            let (nearest, cpu) = match self.replay.greenzone.load(frame as usize) {
+                Some(Ok((nearest, cpu))) => (nearest as u32, cpu),
+                Some(Err(err)) => {
+                    log::error!("{err:?}");
+                    (self.replay.start_frame(), start)
+                }
+                None => (self.replay.start_frame(), start),
+            };
+            self.replay.seek_events(nearest);
+            self.control_deck.load_cpu(cpu);
+            self.clear_rewind();
+        }
+        while self.control_deck.frame_number() < frame && self.replay.mode == ReplayMode::Playback {
+            self.replay_action(s, true)?;
+            self.control_deck.clock_frame()?;
+        }
+        self.control_deck.clear_audio_samples();
+        Ok(())
+    }
+
+    /// Seeks replay playback `SEEK_STEP` frames forward, or backward if not `forward`.
+    pub(crate) fn step_replay(&mut self, s: &mut PixState, forward: bool) -> NesResult<()> {
+        if self.replay.mode != ReplayMode::Playback {
+            return Ok(());
+        }
+        let current_frame = self.control_deck.frame_number();
+        let frame = if forward {
+            current_frame
+                .saturating_add(SEEK_STEP)
+                .min(self.replay.end_frame())
+        } else {
+            current_frame
+                .saturating_sub(SEEK_STEP)
+                .max(self.replay.start_frame())
+        };
+        self.seek_replay(s, frame)?;
+        self.add_message(format!("Frame {}", self.control_deck.frame_number()));
+        Ok(())
+    }
+
+    pub(crate) fn toggle_replay_fast_forward(&mut self) {
+        if self.replay.mode != ReplayMode::Playback {
+            return;
+        }
+        self.replay.fast_forward = !self.replay.fast_forward;
+        if self.replay.fast_forward {
+            self.set_speed(FAST_FORWARD_SPEED);
+            self.add_message("Replay Fast Forward On");
+        } else {
+            self.set_speed(1.0);
+            self.add_message("Replay Fast Forward Off");
+        }
+    }
+
+    /// Discards the rest of the replay being played back and continues recording it from live
+    /// input at the current frame. Buttons held by the replay are released.
+    pub(crate) fn take_replay_control(&mut self) {
+        if self.replay.mode != ReplayMode::Playback {
+            return;
+        }
+        if self.replay.bookmarks_changed {
+            self.save_replay_bookmarks();
+        }
+        if self.replay.fast_forward {
+            self.toggle_replay_fast_forward();
+        }
+        let frame = self.control_deck.frame_number();
+        // Events for the current frame have already been played
+        self.replay.seek_events(frame + 1);
+        self.replay.buffer = mem::take(&mut self.replay.played);
+        self.replay
+            .bookmarks
+            .retain(|bookmark| bookmark.frame <= frame);
+        self.replay.greenzone.clear();
+        self.replay.mode = ReplayMode::Recording;
+        for slot in SLOTS {
+            for &(_, button, state) in &BUTTONS {
+                if self.control_deck.joypad(slot).button(state) {
+                    self.control_deck.joypad_mut(slot).set_button(state, false);
+                    let event = self.action_event(slot, Action::Joypad(button), false, false);
+                    self.replay.buffer.push(event);
+                }
+            }
+        }
+        self.add_message(format!("Took control at frame {frame}, recording"));
+    }
+
+    /// Playback buttons for the `Replay` menu. Playing and pausing close the menu.
+    pub(crate) fn render_replay_controls(&mut self, s: &mut PixState) -> PixResult<()> {
+        let mut seek = None;
+        if s.button("Back 300 Frames")? {
+            seek = Some(false);
+        }
+        s.same_line(None);
+        if s.button("Play")? {
+            self.exit_menu(s)?;
+        }
+        s.same_line(None);
+        if s.button("Pause")? {
+            self.exit_menu(s)?;
+            self.pause_play();
+        }
+        s.same_line(None);
+        if s.button("Forward 300 Frames")? {
+            seek = Some(true);
+        }
+        if let Some(forward) = seek {
+            if let Err(err) = self.step_replay(s, forward) {
+                self.error = Some(err.to_string());
+            }
+        }
+
+        let mut fast_forward = self.replay.fast_forward;
+        if s.checkbox("Fast Forward", &mut fast_forward)? {
+            self.toggle_replay_fast_forward();
+        }
+        if s.button("Take Control")? {
+            self.take_replay_control();
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Discards the rest of the replay and records live input from this frame, as a new \
+            replay.",
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,659 @@
+//! On-disk encodings for replays, picked by file extension.
+//!
+//! Every format decodes to the same [`Replay`], so playback, seeking and bookmarks work the same
+//! whichever one a replay was saved in:
+//!
+//! - `.replay`: compact binary, stored through the configured persistence backend. It starts with
+//!   a format version, and replays saved before it was versioned are still read.
+//! - `.json`, `.json.gz`: JSON with one entry per input event for reading and diffing. The start
+//!   state is kept as a compressed hex string.
+//! - `.fm2`: FCEUX text movies for use with other emulators. Only joypad buttons and resets are
+//!   kept. The start state and initial state are written to `tetanes` header keys so TetaNES plays
+//!   the movie back exactly, and movies without a start state start from power on.
+//!
+//! Text formats are always written as plain files so other tools can read them.
+
+use crate::{
+    common::NesRegion,
+    cpu::Cpu,
+    input::{FourPlayer, JoypadBtn, JoypadBtnState, Slot},
+    movie::{Fm2Frame, Fm2Movie, CMD_HARD_RESET, CMD_SOFT_RESET, PPU_ALIGNMENT_KEY, RAM_STATE_KEY},
+    nes::{
+        event::{Action, ActionEvent, NesState},
+        filesystem::{decode_data, encode_data, load_data},
+        initial_state::InitialState,
+        legacy,
+        persistence::DataKind,
+        state::{Replay, ReplayBookmark},
+        Nes,
+    },
+    NesResult,
+};
+use anyhow::{anyhow, bail, Context};
+use chrono::{DateTime, Local};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+/// Version of the JSON replay layout.
+const JSON_VERSION: u32 = 1;
+/// Starts binary replays saved with a format version. Older replays start with the replay mode.
+const BINARY_MAGIC: &[u8] = b"TNRP";
+/// Version of the binary replay layout.
+const BINARY_VERSION: u32 = 1;
+/// FM2 header key holding the start state of movies saved by TetaNES.
+const FM2_START_KEY: &str = "tetanesStart";
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+pub(crate) const SLOTS: [Slot; 4] = [Slot::One, Slot::Two, Slot::Three, Slot::Four];
+
+/// Joypad buttons in FM2 `RLDUTSBA` order.
+pub(crate) const BUTTONS: [(&str, JoypadBtn, JoypadBtnState); 8] = [
+    ("R", JoypadBtn::Right, JoypadBtnState::RIGHT),
+    ("L", JoypadBtn::Left, JoypadBtnState::LEFT),
+    ("D", JoypadBtn::Down, JoypadBtnState::DOWN),
+    ("U", JoypadBtn::Up, JoypadBtnState::UP),
+    ("T", JoypadBtn::Start, JoypadBtnState::START),
+    ("S", JoypadBtn::Select, JoypadBtnState::SELECT),
+    ("B", JoypadBtn::B, JoypadBtnState::B),
+    ("A", JoypadBtn::A, JoypadBtnState::A),
+];
+
+/// Buttons a joypad can hold in a movie, without turbo.
+pub(crate) fn movie_buttons(state: JoypadBtnState) -> JoypadBtnState {
+    BUTTONS
+        .iter()
+        .fold(JoypadBtnState::empty(), |buttons, &(_, _, button)| {
+            buttons | (state & button)
+        })
+}
+
+/// File format used for replays.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub(crate) enum ReplayFormat {
+    #[default]
+    Binary,
+    Json,
+    JsonGz,
+    Fm2,
+}
+
+impl ReplayFormat {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[Self::Binary, Self::Json, Self::JsonGz, Self::Fm2]
+    }
+
+    pub(crate) const fn extension(self) -> &'static str {
+        match self {
+            Self::Binary => "replay",
+            Self::Json => "json",
+            Self::JsonGz => "json.gz",
+            Self::Fm2 => "fm2",
+        }
+    }
+
+    /// Picks the format from the extension of `path`, defaulting to binary.
+    pub(crate) fn from_path(path: &Path) -> Self {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        if name.ends_with(".fm2") {
+            Self::Fm2
+        } else if name.ends_with(".gz") {
+            Self::JsonGz
+        } else if name.ends_with(".json") {
+            Self::Json
+        } else {
+            Self::Binary
+        }
+    }
+
+    /// Encodes a replay stored with its first event last.
+    pub(crate) fn encode(self, replay: &Replay, info: &MovieInfo) -> NesResult<Vec<u8>> {
+        match self {
+            Self::Binary => {
+                let mut data = BINARY_MAGIC.to_vec();
+                bincode::serialize_into(&mut data, &BINARY_VERSION)
+                    .context("failed to serialize replay version")?;
+                bincode::serialize_into(&mut data, replay)
+                    .context("failed to serialize replay recording")?;
+                bincode::serialize_into(&mut data, &replay.initial)
+                    .context("failed to serialize replay initial state")?;
+                Ok(data)
+            }
+            Self::Json => to_json(replay),
+            Self::JsonGz => {
+                let mut encoder = GzEncoder::new(vec![], Compression::default());
+                encoder
+                    .write_all(&to_json(replay)?)
+                    .context("failed to compress replay recording")?;
+                encoder
+                    .finish()
+                    .context("failed to compress replay recording")
+            }
+            Self::Fm2 => Ok(to_fm2(replay, info)?.to_string().into_bytes()),
+        }
+    }
+
+    /// Decodes a replay, stored with its first event last. Replays without a start state start
+    /// from power on, see [`Replay::set_start`].
+    pub(crate) fn decode(self, data: &[u8]) -> NesResult<Replay> {
+        match self {
+            Self::Binary => match data.strip_prefix(BINARY_MAGIC) {
+                Some(data) => from_binary(data),
+                None => from_unversioned_binary(data),
+            },
+            // Accept either, so a renamed file still loads
+            Self::Json | Self::JsonGz if data.starts_with(&GZIP_MAGIC) => {
+                let mut json = vec![];
+                GzDecoder::new(data)
+                    .read_to_end(&mut json)
+                    .context("failed to decompress replay recording")?;
+                from_json(&json)
+            }
+            Self::Json | Self::JsonGz => from_json(data),
+            Self::Fm2 => std::str::from_utf8(data)
+                .context("invalid fm2 movie")?
+                .parse::<Fm2Movie>()
+                .and_then(from_fm2),
+        }
+    }
+}
+
+impl AsRef<str> for ReplayFormat {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Binary => "Binary",
+            Self::Json => "JSON",
+            Self::JsonGz => "Compressed JSON",
+            Self::Fm2 => "FM2",
+        }
+    }
+}
+
+impl From<usize> for ReplayFormat {
+    fn from(value: usize) -> Self {
+        match value {
+            1 => Self::Json,
+            2 => Self::JsonGz,
+            3 => Self::Fm2,
+            _ => Self::Binary,
+        }
+    }
+}
+
+/// ROM and console details written to FM2 headers.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct MovieInfo {
+    pub(crate) rom_filename: Option<String>,
+    pub(crate) pal: bool,
+    pub(crate) four_score: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonReplay {
+    version: u32,
+    /// Hex of the compressed start state.
+    start: Option<String>,
+    lag_frames: u32,
+    bookmarks: Vec<ReplayBookmark>,
+    #[serde(default)]
+    initial: Option<InitialState>,
+    /// Input events in the order they happened.
+    events: Vec<ActionEvent>,
+}
+
+fn from_binary(mut data: &[u8]) -> NesResult<Replay> {
+    let version: u32 =
+        bincode::deserialize_from(&mut data).context("failed to deserialize replay version")?;
+    if version > BINARY_VERSION {
+        bail!("replay version {version} is newer than the supported version {BINARY_VERSION}");
+    }
+    let mut replay: Replay =
+        bincode::deserialize_from(&mut data).context("failed to deserialize replay recording")?;
+    replay.initial =
+        bincode::deserialize(data).context("failed to deserialize replay initial state")?;
+    Ok(replay)
+}
+
+/// Reads replays saved before the binary format was versioned, either in the current layout with
+/// an optional initial state following it, or in the original layout of mode, start state and
+/// events.
+fn from_unversioned_binary(data: &[u8]) -> NesResult<Replay> {
+    if let Some(replay) = legacy::deserialize_replay(data) {
+        return Ok(replay);
+    }
+    // Replays saved with bookmarks and lag frames, but before the format version was added
+    let mut rest = data;
+    let mut replay: Replay =
+        bincode::deserialize_from(&mut rest).context("failed to deserialize replay recording")?;
+    if !rest.is_empty() {
+        replay.initial =
+            bincode::deserialize(rest).context("failed to deserialize replay initial state")?;
+    }
+    Ok(replay)
+}
+
+fn encode_start(start: &Cpu) -> NesResult<String> {
+    let data = bincode::serialize(start)
+        .context("failed to serialize replay start")
+        .and_then(|data| encode_data(&data))?;
+    Ok(data.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+fn decode_start(hex: &str) -> NesResult<Cpu> {
+    let hex = hex.trim();
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(anyhow!("invalid replay start"));
+    }
+    let data = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<_>, _>>()
+        .context("invalid replay start")?;
+    decode_data(&data)
+        .and_then(|data| bincode::deserialize(&data).context("failed to deserialize replay start"))
+}
+
+fn to_json(replay: &Replay) -> NesResult<Vec<u8>> {
+    let json = JsonReplay {
+        version: JSON_VERSION,
+        start: replay.start.as_ref().map(encode_start).transpose()?,
+        lag_frames: replay.lag_frames,
+        bookmarks: replay.bookmarks.clone(),
+        initial: replay.initial,
+        events: replay.buffer.iter().rev().copied().collect(),
+    };
+    serde_json::to_vec_pretty(&json).context("failed to serialize replay recording")
+}
+
+fn from_json(data: &[u8]) -> NesResult<Replay> {
+    let json: JsonReplay =
+        serde_json::from_slice(data).context("failed to deserialize replay recording")?;
+    if json.version > JSON_VERSION {
+        return Err(anyhow!("unsupported replay version {}", json.version));
+    }
+    Ok(Replay {
+        start: json.start.as_deref().map(decode_start).transpose()?,
+        buffer: json.events.into_iter().rev().collect(),
+        bookmarks: json.bookmarks,
+        lag_frames: json.lag_frames,
+        initial: json.initial,
+        ..Replay::default()
+    })
+}
+
+/// Converts a replay into per-frame joypad input. Turbo, Zapper and other non-joypad input can't
+/// be represented and is dropped.
+fn to_fm2(replay: &Replay, info: &MovieInfo) -> NesResult<Fm2Movie> {
+    let start_frame = replay.start_frame();
+    let mut joypads = replay
+        .start
+        .as_ref()
+        .map_or([JoypadBtnState::empty(); 4], |start| {
+            SLOTS.map(|slot| movie_buttons(start.joypad(slot).buttons()))
+        });
+    let mut frames = vec![];
+    let mut dropped = 0;
+    let mut events = replay.buffer.iter().rev().peekable();
+    while events.peek().is_some() {
+        let frame = start_frame + frames.len() as u32;
+        let mut input = Fm2Frame::default();
+        while let Some(event) = events.next_if(|event| event.frame <= frame) {
+            let joypad = &mut joypads[event.slot as usize];
+            match event.action {
+                Action::Nes(NesState::HardReset) if event.pressed => {
+                    input.commands |= CMD_HARD_RESET;
+                }
+                Action::Nes(NesState::SoftReset) if event.pressed => {
+                    input.commands |= CMD_SOFT_RESET;
+                }
+                Action::Joypad(button) => {
+                    match BUTTONS.iter().find(|&&(_, btn, _)| btn == button) {
+                        Some(&(_, _, state)) => joypad.set(state, event.pressed),
+                        None => dropped += 1,
+                    }
+                }
+                // Releasing an axis releases both of its directions
+                Action::ZeroAxis(buttons) if !event.pressed => {
+                    for &(_, btn, state) in &BUTTONS {
+                        if buttons.contains(&btn) {
+                            joypad.remove(state);
+                        }
+                    }
+                }
+                _ => dropped += 1,
+            }
+        }
+        input.joypads = joypads;
+        frames.push(input);
+    }
+    if dropped > 0 {
+        log::warn!("{dropped} replay events can't be represented in fm2 and were dropped");
+    }
+    let mut extra = vec![];
+    if let Some(ref start) = replay.start {
+        extra.push((FM2_START_KEY.to_string(), encode_start(start)?));
+    }
+    if let Some(initial) = replay.initial {
+        extra.push((RAM_STATE_KEY.to_string(), initial.ram_state.to_string()));
+        extra.push((
+            PPU_ALIGNMENT_KEY.to_string(),
+            initial.ppu_alignment.to_string(),
+        ));
+    }
+    Ok(Fm2Movie {
+        rom_filename: info.rom_filename.clone(),
+        pal: info.pal,
+        four_score: info.four_score,
+        rerecord_count: 0,
+        frames,
+        extra,
+    })
+}
+
+fn from_fm2(movie: Fm2Movie) -> NesResult<Replay> {
+    let start = movie
+        .extra
+        .iter()
+        .find(|(key, _)| key == FM2_START_KEY)
+        .map(|(_, value)| decode_start(value))
+        .transpose()?;
+    let (start_frame, joypads) =
+        start
+            .as_ref()
+            .map_or((0, [JoypadBtnState::empty(); 4]), |start| {
+                (
+                    start.ppu().frame_number(),
+                    SLOTS.map(|slot| movie_buttons(start.joypad(slot).buttons())),
+                )
+            });
+    let mut buffer = fm2_events(start_frame, joypads, &movie.frames);
+    buffer.reverse();
+    let initial = movie.ram_state().map(|ram_state| InitialState {
+        ram_state,
+        ppu_alignment: movie.ppu_alignment().unwrap_or(Cpu::DEFAULT_PPU_ALIGNMENT),
+    });
+    Ok(Replay {
+        start,
+        buffer,
+        initial,
+        ..Replay::default()
+    })
+}
+
+/// Input changes as replay events for movie `frames` starting at `start_frame`, with joypads
+/// holding `previous` beforehand.
+pub(crate) fn fm2_events(
+    start_frame: u32,
+    mut previous: [JoypadBtnState; 4],
+    frames: &[Fm2Frame],
+) -> Vec<ActionEvent> {
+    let mut events = vec![];
+    for (i, input) in frames.iter().enumerate() {
+        let frame = start_frame + i as u32;
+        let event = |slot, action, pressed| ActionEvent {
+            frame,
+            slot,
+            action,
+            pressed,
+            repeat: false,
+        };
+        if input.commands & CMD_HARD_RESET != 0 {
+            events.push(event(Slot::One, Action::Nes(NesState::HardReset), true));
+        } else if input.commands & CMD_SOFT_RESET != 0 {
+            events.push(event(Slot::One, Action::Nes(NesState::SoftReset), true));
+        }
+        for (slot, previous) in SLOTS.into_iter().zip(&mut previous) {
+            let state = input.joypads[slot as usize];
+            for &(_, button, button_state) in &BUTTONS {
+                let pressed = state.contains(button_state);
+                if pressed != previous.contains(button_state) {
+                    events.push(event(slot, Action::Joypad(button), pressed));
+                }
+            }
+            *previous = state;
+        }
+    }
+    events
+}
+
+impl Nes {
+    /// Console details for FM2 movies.
+    pub(crate) fn movie_info(&self) -> MovieInfo {
+        MovieInfo {
+            rom_filename: self
+                .config
+                .rom_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned()),
+            pal: self.config.region == NesRegion::Pal,
+            four_score: self.config.four_player == FourPlayer::FourScore,
+        }
+    }
+
+    /// A timestamped replay file name using `pattern` and the configured replay format.
+    pub(crate) fn new_replay_key(&self, pattern: &str) -> String {
+        let datetime: DateTime<Local> = Local::now();
+        format!(
+            "{}.{}",
+            datetime.format(pattern),
+            self.config.replay_format.extension()
+        )
+    }
+
+    /// Writes `replay`, stored with its first event last, in the format picked by the extension
+    /// of `key`.
+    pub(crate) fn write_replay(&mut self, key: &str, replay: &Replay) -> NesResult<()> {
+        let format = ReplayFormat::from_path(Path::new(key));
+        let data = format.encode(replay, &self.movie_info())?;
+        match format {
+            ReplayFormat::Binary => self.persistence.save(DataKind::Replay, key, &data),
+            _ => fs::write(key, data).with_context(|| format!("failed to write {key:?}")),
+        }
+    }
+
+    /// Reads a replay in the format picked by the extension of `path`. Binary replays are read
+    /// from the persistence backend, falling back to a replay file on disk so replays recorded
+    /// elsewhere can be played back with any backend.
+    pub(crate) fn read_replay(&self, path: &Path) -> NesResult<Replay> {
+        let format = ReplayFormat::from_path(path);
+        let data = match format {
+            ReplayFormat::Binary => match self
+                .persistence
+                .load(DataKind::Replay, &path.to_string_lossy())?
+            {
+                Some(data) => data,
+                None if path.exists() => load_data(path)?,
+                None => return Err(anyhow!("replay {path:?} not found")),
+            },
+            _ => fs::read(path).with_context(|| format!("failed to read {path:?}"))?,
+        };
+        format.decode(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mapper::Mapper,
+        mem::RamState,
+        nes::{
+            event::{DebugAction, Setting},
+            state::ReplayMode,
+        },
+    };
+
+    fn event(frame: u32, slot: Slot, button: JoypadBtn, pressed: bool) -> ActionEvent {
+        ActionEvent {
+            frame,
+            slot,
+            action: Action::Joypad(button),
+            pressed,
+            repeat: false,
+        }
+    }
+
+    fn replay(events: &[ActionEvent]) -> Replay {
+        Replay {
+            buffer: events.iter().rev().copied().collect(),
+            bookmarks: vec![ReplayBookmark {
+                frame: 1,
+                name: "Jump".to_string(),
+                note: String::new(),
+            }],
+            lag_frames: 3,
+            ..Replay::default()
+        }
+    }
+
+    #[test]
+    fn format_from_path() {
+        for (path, format) in [
+            ("run.replay", ReplayFormat::Binary),
+            ("run", ReplayFormat::Binary),
+            ("run.json", ReplayFormat::Json),
+            ("run.json.gz", ReplayFormat::JsonGz),
+            ("RUN.FM2", ReplayFormat::Fm2),
+        ] {
+            assert_eq!(ReplayFormat::from_path(Path::new(path)), format, "{path}");
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let events = [
+            event(0, Slot::One, JoypadBtn::A, true),
+            event(2, Slot::One, JoypadBtn::A, false),
+            event(2, Slot::Two, JoypadBtn::Up, true),
+            event(4, Slot::Two, JoypadBtn::Up, false),
+        ];
+        let replay = replay(&events);
+        let info = MovieInfo::default();
+        for format in [
+            ReplayFormat::Binary,
+            ReplayFormat::Json,
+            ReplayFormat::JsonGz,
+        ] {
+            let data = format.encode(&replay, &info).expect("encoded replay");
+            let decoded = format.decode(&data).expect("decoded replay");
+            assert_eq!(decoded.buffer, replay.buffer, "{format:?}");
+            assert_eq!(decoded.bookmarks, replay.bookmarks, "{format:?}");
+            assert_eq!(decoded.lag_frames, 3, "{format:?}");
+        }
+
+        // FM2 only keeps input
+        let data = ReplayFormat::Fm2
+            .encode(&replay, &info)
+            .expect("encoded replay");
+        let text = String::from_utf8(data.clone()).expect("text movie");
+        assert!(text.contains("|0|.......A|........||\n"), "{text}");
+        assert!(text.contains("|0|........|...U....||\n"), "{text}");
+        let decoded = ReplayFormat::Fm2.decode(&data).expect("decoded replay");
+        assert_eq!(decoded.buffer, replay.buffer);
+        assert!(decoded.start.is_none());
+    }
+
+    #[test]
+    fn initial_state_round_trips() {
+        let mut replay = replay(&[event(0, Slot::One, JoypadBtn::A, true)]);
+        let legacy = bincode::serialize(&replay).expect("serialized replay");
+        let decoded = ReplayFormat::Binary
+            .decode(&legacy)
+            .expect("decoded replay");
+        assert_eq!(decoded.initial, None);
+
+        replay.initial = Some(InitialState {
+            ram_state: RamState::Seeded(7),
+            ppu_alignment: 3,
+        });
+        let info = MovieInfo::default();
+        for format in ReplayFormat::as_slice() {
+            let data = format.encode(&replay, &info).expect("encoded replay");
+            let decoded = format.decode(&data).expect("decoded replay");
+            assert_eq!(decoded.initial, replay.initial, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn legacy_binary_replays_load() {
+        // A Pxrom recording in the layout written before replays were versioned, from before
+        // lag frames, the PPU open bus decay, the shared CHR latches and the new event variants
+        let mut data = vec![];
+        GzDecoder::new(&include_bytes!("../../tests/fixtures/unversioned.replay.gz")[..])
+            .read_to_end(&mut data)
+            .expect("decompressed replay");
+        let decoded = ReplayFormat::Binary.decode(&data).expect("decoded replay");
+        assert_eq!(decoded.mode, ReplayMode::Recording);
+        assert!(decoded.bookmarks.is_empty());
+        assert_eq!(decoded.lag_frames, 0);
+        assert_eq!(decoded.initial, None);
+
+        let action = |frame, action, pressed| ActionEvent {
+            frame,
+            slot: Slot::One,
+            action,
+            pressed,
+            repeat: false,
+        };
+        let events = [
+            event(0, Slot::One, JoypadBtn::A, true),
+            event(3, Slot::One, JoypadBtn::A, false),
+            action(10, Action::ZapperTrigger, true),
+            action(12, Action::Setting(Setting::ToggleSound), true),
+            action(15, Action::Debug(DebugAction::StepFrame), true),
+        ];
+        assert!(decoded.buffer.iter().rev().eq(events.iter()));
+
+        let start = decoded.start.expect("replay start");
+        assert_eq!(start.pc(), 0xC07A);
+        assert_eq!(start.wram()[0x7F], 0x7F);
+        assert_eq!(start.frame_number(), 120);
+        assert_eq!(start.ppu().open_bus(), 0x5A);
+        assert!(matches!(start.mapper(), Mapper::Pxrom(_)));
+        assert_eq!(start.genie_codes().len(), 1);
+        assert_eq!(start.lag_frames(), 0);
+        assert!(!start.prg_ram_write_protected());
+
+        let mut data = ReplayFormat::Binary
+            .encode(&replay(&events[..2]), &MovieInfo::default())
+            .expect("encoded replay");
+        assert!(data.starts_with(BINARY_MAGIC));
+        data[BINARY_MAGIC.len()] = 0xFF;
+        let err = ReplayFormat::Binary.decode(&data).unwrap_err();
+        assert!(err.to_string().contains("newer"), "{err}");
+    }
+
+    #[test]
+    fn gzipped_json_is_detected() {
+        let replay = replay(&[event(5, Slot::One, JoypadBtn::Start, true)]);
+        let data = ReplayFormat::JsonGz
+            .encode(&replay, &MovieInfo::default())
+            .expect("encoded replay");
+        let decoded = ReplayFormat::Json.decode(&data).expect("decoded replay");
+        assert_eq!(decoded.buffer, replay.buffer);
+    }
+
+    #[test]
+    fn fm2_drops_turbo() {
+        let mut replay = replay(&[event(0, Slot::One, JoypadBtn::B, true)]);
+        replay.buffer.insert(
+            0,
+            ActionEvent {
+                action: Action::Turbo(JoypadBtn::A),
+                ..event(1, Slot::One, JoypadBtn::A, true)
+            },
+        );
+        let movie = to_fm2(&replay, &MovieInfo::default()).expect("fm2 movie");
+        assert_eq!(movie.frames.len(), 2);
+        assert_eq!(movie.frames[1].joypads[0], JoypadBtnState::B);
+    }
+}
@@ -0,0 +1,605 @@
+//! Soft-patching ROMs with IPS, BPS and UPS patches when they're loaded, leaving the ROM file
+//! unchanged.
+//!
+//! Patches next to the ROM named `<rom name>.ips`, `.bps` or `.ups` are applied automatically.
+//! Several can be stacked by numbering them, like `game.1.ips` and `game.2.bps`, and are applied
+//! in numbered order after an unnumbered one. Patches picked from the Load ROM menu are applied
+//! after those, in the order picked.
+//!
+//! BPS and UPS patches carry CRC-32 checksums of the ROM they apply to, the patched result and
+//! the patch itself, and patches whose checksums don't match are skipped. IPS patches have no
+//! checksums, so are only checked to be well-formed.
+
+use crate::{cart::crc32, nes::Nes, NesResult};
+use anyhow::{anyhow, bail, Context};
+use pix_engine::prelude::*;
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: usize = 0x45_4F46;
+const BPS_MAGIC: &[u8] = b"BPS1";
+const UPS_MAGIC: &[u8] = b"UPS1";
+/// Size of the source, target and patch checksums at the end of BPS and UPS patches.
+const FOOTER_LEN: usize = 12;
+/// Largest patched ROM allowed, to guard against corrupt patches asking for huge allocations.
+const MAX_TARGET_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) enum PatchFormat {
+    Ips,
+    Bps,
+    Ups,
+}
+
+impl PatchFormat {
+    pub(crate) fn from_path(path: &Path) -> Option<Self> {
+        match path
+            .extension()
+            .and_then(OsStr::to_str)?
+            .to_ascii_lowercase()
+            .as_str()
+        {
+            "ips" => Some(Self::Ips),
+            "bps" => Some(Self::Bps),
+            "ups" => Some(Self::Ups),
+            _ => None,
+        }
+    }
+}
+
+/// Applies a patch to a ROM, returning the patched ROM.
+///
+/// # Errors
+///
+/// If the patch is malformed or its checksums don't match, then an error is returned.
+pub(crate) fn apply(rom: &[u8], patch: &[u8], format: PatchFormat) -> NesResult<Vec<u8>> {
+    match format {
+        PatchFormat::Ips => apply_ips(rom, patch),
+        PatchFormat::Bps => apply_bps(rom, patch),
+        PatchFormat::Ups => apply_ups(rom, patch),
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> NesResult<Vec<u8>> {
+    let mut reader = Reader::new(patch);
+    if reader.bytes(IPS_MAGIC.len())? != IPS_MAGIC {
+        bail!("not an IPS patch");
+    }
+    let mut target = rom.to_vec();
+    loop {
+        let offset = reader.be(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let size = reader.be(2)?;
+        let data = if size == 0 {
+            // Run-length encoded record
+            let count = reader.be(2)?;
+            vec![reader.byte()?; count]
+        } else {
+            reader.bytes(size)?.to_vec()
+        };
+        let end = offset + data.len();
+        if target.len() < end {
+            target.resize(end, 0x00);
+        }
+        target[offset..end].copy_from_slice(&data);
+    }
+    // Optional extension that truncates the ROM
+    if reader.remaining() >= 3 {
+        let len = reader.be(3)?;
+        target.truncate(len);
+    }
+    Ok(target)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> NesResult<Vec<u8>> {
+    let (source_crc, target_crc) = check_footer(patch, BPS_MAGIC, "BPS")?;
+    let mut reader = Reader::new(&patch[BPS_MAGIC.len()..patch.len() - FOOTER_LEN]);
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+    if source_size != rom.len() {
+        bail!("expected a {source_size} byte ROM, got {} bytes", rom.len());
+    } else if target_size > MAX_TARGET_SIZE {
+        bail!("patched ROM is too large: {target_size} bytes");
+    }
+    check_crc(rom, source_crc, "ROM")?;
+
+    let mut target = Vec::with_capacity(target_size);
+    let mut source_offset = 0isize;
+    let mut target_offset = 0isize;
+    let out_of_range = || anyhow!("copy offset out of range");
+    let relative = |offset: &mut isize, data: usize| -> NesResult<usize> {
+        let delta = isize::try_from(data >> 1).map_err(|_| out_of_range())?;
+        *offset = if data & 1 == 1 {
+            offset.checked_sub(delta)
+        } else {
+            offset.checked_add(delta)
+        }
+        .ok_or_else(out_of_range)?;
+        usize::try_from(*offset).map_err(|_| out_of_range())
+    };
+    let advance = |offset: &mut isize, len: usize| -> NesResult<()> {
+        *offset = isize::try_from(len)
+            .ok()
+            .and_then(|len| offset.checked_add(len))
+            .ok_or_else(out_of_range)?;
+        Ok(())
+    };
+    while reader.remaining() > 0 {
+        let data = reader.varint()?;
+        let len = (data >> 2) + 1;
+        if len > target_size - target.len() {
+            bail!("patch writes past the end of the patched ROM");
+        }
+        match data & 3 {
+            // Source read
+            0 => {
+                let start = target.len();
+                let bytes = rom
+                    .get(start..start + len)
+                    .ok_or_else(|| anyhow!("source read out of range"))?;
+                target.extend_from_slice(bytes);
+            }
+            // Target read
+            1 => target.extend_from_slice(reader.bytes(len)?),
+            // Source copy
+            2 => {
+                let start = relative(&mut source_offset, reader.varint()?)?;
+                let bytes = start
+                    .checked_add(len)
+                    .and_then(|end| rom.get(start..end))
+                    .ok_or_else(|| anyhow!("source copy out of range"))?;
+                target.extend_from_slice(bytes);
+                advance(&mut source_offset, len)?;
+            }
+            // Target copy, which can overlap what it's writing to repeat a pattern
+            _ => {
+                let start = relative(&mut target_offset, reader.varint()?)?;
+                let end = start
+                    .checked_add(len)
+                    .ok_or_else(|| anyhow!("target copy out of range"))?;
+                for i in start..end {
+                    let val = *target
+                        .get(i)
+                        .ok_or_else(|| anyhow!("target copy out of range"))?;
+                    target.push(val);
+                }
+                advance(&mut target_offset, len)?;
+            }
+        }
+    }
+    if target.len() != target_size {
+        bail!(
+            "expected a {target_size} byte patched ROM, got {} bytes",
+            target.len()
+        );
+    }
+    check_crc(&target, target_crc, "patched ROM")?;
+    Ok(target)
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> NesResult<Vec<u8>> {
+    let (source_crc, target_crc) = check_footer(patch, UPS_MAGIC, "UPS")?;
+    let mut reader = Reader::new(&patch[UPS_MAGIC.len()..patch.len() - FOOTER_LEN]);
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    if source_size != rom.len() {
+        bail!("expected a {source_size} byte ROM, got {} bytes", rom.len());
+    } else if target_size > MAX_TARGET_SIZE {
+        bail!("patched ROM is too large: {target_size} bytes");
+    }
+    check_crc(rom, source_crc, "ROM")?;
+
+    let mut target = rom.to_vec();
+    target.resize(target_size, 0x00);
+    let mut offset = 0usize;
+    let next = |offset: usize, len: usize| {
+        offset
+            .checked_add(len)
+            .ok_or_else(|| anyhow!("patch offset out of range"))
+    };
+    while reader.remaining() > 0 {
+        offset = next(offset, reader.varint()?)?;
+        // Bytes are XORed with the ROM up to a zero byte, which also skips a byte
+        loop {
+            let val = reader.byte()?;
+            if val == 0 {
+                offset = next(offset, 1)?;
+                break;
+            }
+            if let Some(byte) = target.get_mut(offset) {
+                *byte ^= val;
+            }
+            offset = next(offset, 1)?;
+        }
+    }
+    check_crc(&target, target_crc, "patched ROM")?;
+    Ok(target)
+}
+
+/// Checks the magic and patch checksum of a BPS or UPS patch, returning the expected source and
+/// target checksums.
+fn check_footer(patch: &[u8], magic: &[u8], name: &str) -> NesResult<(u32, u32)> {
+    if patch.len() < magic.len() + FOOTER_LEN || !patch.starts_with(magic) {
+        bail!("not a {name} patch");
+    }
+    let footer = &patch[patch.len() - FOOTER_LEN..];
+    let crc =
+        |i: usize| u32::from_le_bytes([footer[i], footer[i + 1], footer[i + 2], footer[i + 3]]);
+    check_crc(&patch[..patch.len() - 4], crc(8), "patch")?;
+    Ok((crc(0), crc(4)))
+}
+
+fn check_crc(data: &[u8], expected: u32, name: &str) -> NesResult<()> {
+    let crc = crc32(data);
+    if crc == expected {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "{name} checksum mismatch: expected {expected:08X}, got {crc:08X}"
+        ))
+    }
+}
+
+/// Reads fields from a patch, erroring if it ends early.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    const fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    const fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    fn bytes(&mut self, len: usize) -> NesResult<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| anyhow!("patch ended unexpectedly"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> NesResult<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// A big-endian number, as used by IPS.
+    fn be(&mut self, len: usize) -> NesResult<usize> {
+        Ok(self
+            .bytes(len)?
+            .iter()
+            .fold(0, |val, &byte| (val << 8) | usize::from(byte)))
+    }
+
+    /// A variable-length number, as used by BPS and UPS.
+    fn varint(&mut self) -> NesResult<usize> {
+        let mut val = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let byte = self.byte()?;
+            val = usize::from(byte & 0x7F)
+                .checked_mul(shift)
+                .and_then(|x| val.checked_add(x))
+                .ok_or_else(|| anyhow!("number too large"))?;
+            if byte & 0x80 != 0 {
+                return Ok(val);
+            }
+            shift = shift
+                .checked_mul(0x80)
+                .ok_or_else(|| anyhow!("number too large"))?;
+            val = val
+                .checked_add(shift)
+                .ok_or_else(|| anyhow!("number too large"))?;
+        }
+    }
+}
+
+/// Patches next to a ROM to apply automatically, in the order to apply them.
+pub(crate) fn find_patches(rom_path: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(stem)) = (
+        rom_path.parent(),
+        rom_path.file_stem().and_then(OsStr::to_str),
+    ) else {
+        return vec![];
+    };
+    let Ok(read_dir) = dir.read_dir() else {
+        return vec![];
+    };
+    let mut patches: Vec<(u32, PathBuf)> = read_dir
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| PatchFormat::from_path(path).is_some())
+        .filter_map(|path| {
+            let name = path.file_stem()?.to_str()?;
+            let order = if name == stem {
+                0
+            } else {
+                name.strip_prefix(stem)?.strip_prefix('.')?.parse().ok()?
+            };
+            Some((order, path))
+        })
+        .collect();
+    patches.sort();
+    patches.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Patches applied to the loaded ROM, and patches picked to apply to it.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct RomPatches {
+    /// ROM the patches were picked for.
+    pub(crate) rom_path: PathBuf,
+    /// Patches picked from the Load ROM menu, applied after those found next to the ROM.
+    pub(crate) picked: Vec<PathBuf>,
+    /// Patches applied when the ROM was last loaded.
+    pub(crate) applied: Vec<PathBuf>,
+}
+
+impl Nes {
+    /// Applies patches found next to the ROM, if enabled, and those picked from the Load ROM
+    /// menu. Patches that fail to apply are skipped.
+    pub(crate) fn patch_rom(&mut self, mut rom: Vec<u8>) -> Vec<u8> {
+        if self.rom_patches.rom_path != self.config.rom_path {
+            self.rom_patches.rom_path = self.config.rom_path.clone();
+            self.rom_patches.picked.clear();
+        }
+        let mut patches = if self.config.soft_patch {
+            find_patches(&self.config.rom_path)
+        } else {
+            vec![]
+        };
+        patches.extend(self.rom_patches.picked.iter().cloned());
+        self.rom_patches.applied.clear();
+        for path in patches {
+            let Some(format) = PatchFormat::from_path(&path) else {
+                continue;
+            };
+            let patched = fs::read(&path)
+                .with_context(|| format!("failed to read patch {path:?}"))
+                .and_then(|patch| {
+                    apply(&rom, &patch, format)
+                        .with_context(|| format!("failed to apply patch {path:?}"))
+                });
+            match patched {
+                Ok(patched) => {
+                    log::info!("applied patch {path:?}");
+                    rom = patched;
+                    self.rom_patches.applied.push(path);
+                }
+                Err(err) => {
+                    log::error!("{err:?}");
+                    let name = path
+                        .file_name()
+                        .map_or_else(|| "unknown".into(), OsStr::to_string_lossy);
+                    self.add_message(format!("Skipped patch {name}: {}", err.root_cause()));
+                }
+            }
+        }
+        if !self.rom_patches.applied.is_empty() {
+            self.add_message(format!(
+                "Applied {} patch(es)",
+                self.rom_patches.applied.len()
+            ));
+        }
+        rom
+    }
+
+    /// Button to apply a patch selected in the Load ROM menu to the loaded ROM.
+    pub(crate) fn render_pick_patch(&mut self, s: &mut PixState, path: PathBuf) -> PixResult<()> {
+        let loaded =
+            self.control_deck.loaded_rom().is_some() && self.rom_patches.rom_path.is_file();
+        let picked = self.rom_patches.picked.contains(&path);
+        s.disable(!loaded || picked);
+        if s.button("Apply Patch")? {
+            self.rom_patches.picked.push(path);
+            self.config.rom_path = self.rom_patches.rom_path.clone();
+            self.selected_path = 0;
+            self.load_rom(s)?;
+        }
+        s.disable(false);
+        if !loaded {
+            s.same_line(None);
+            s.text("Load a ROM to patch first")?;
+        }
+        Ok(())
+    }
+
+    /// Lists the patches applied to the loaded ROM.
+    pub(crate) fn render_applied_patches(&mut self, s: &mut PixState) -> PixResult<()> {
+        if self.rom_patches.applied.is_empty() {
+            return Ok(());
+        }
+        s.spacing()?;
+        s.text("Patches applied:")?;
+        for path in &self.rom_patches.applied {
+            let name = path
+                .file_name()
+                .map_or_else(|| "unknown".into(), OsStr::to_string_lossy);
+            s.bullet(name)?;
+        }
+        if !self.rom_patches.picked.is_empty() && s.button("Remove Picked Patches")? {
+            self.rom_patches.picked.clear();
+            self.config.rom_path = self.rom_patches.rom_path.clone();
+            self.selected_path = 0;
+            self.load_rom(s)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut val: usize, out: &mut Vec<u8>) {
+        loop {
+            let byte = (val & 0x7F) as u8;
+            val >>= 7;
+            if val == 0 {
+                out.push(0x80 | byte);
+                break;
+            }
+            out.push(byte);
+            val -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for val in [0, 1, 127, 128, 255, 16_511, 16_512, 1 << 24] {
+            let mut bytes = vec![];
+            varint(val, &mut bytes);
+            assert_eq!(Reader::new(&bytes).varint().unwrap(), val);
+        }
+    }
+
+    #[test]
+    fn ips() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]);
+        // Run of 3 bytes extending past the end of the ROM
+        patch.extend_from_slice(&[0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x03, 0xCC]);
+        patch.extend_from_slice(b"EOF");
+        let rom = [0u8; 6];
+        assert_eq!(
+            apply(&rom, &patch, PatchFormat::Ips).unwrap(),
+            [0x00, 0xAA, 0xBB, 0x00, 0x00, 0xCC, 0xCC, 0xCC]
+        );
+
+        patch.extend_from_slice(&[0x00, 0x00, 0x02]);
+        assert_eq!(apply(&rom, &patch, PatchFormat::Ips).unwrap(), [0x00, 0xAA]);
+        assert!(apply(&rom, b"PATCH\x00\x00", PatchFormat::Ips).is_err());
+        assert!(apply(&rom, b"NOPE", PatchFormat::Ips).is_err());
+    }
+
+    #[test]
+    fn bps() {
+        let source = b"ABCDEFGH";
+        let target = b"ABCXYXYXYFGH!";
+        let mut patch = b"BPS1".to_vec();
+        varint(source.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        varint(0, &mut patch);
+        // Source read "ABC"
+        varint((3 - 1) << 2, &mut patch);
+        // Target read "XY"
+        varint(((2 - 1) << 2) | 1, &mut patch);
+        patch.extend_from_slice(b"XY");
+        // Target copy "XYXY" from offset 3, overlapping itself
+        varint(((4 - 1) << 2) | 3, &mut patch);
+        varint(3 << 1, &mut patch);
+        // Source copy "FGH" from offset 5
+        varint(((3 - 1) << 2) | 2, &mut patch);
+        varint(5 << 1, &mut patch);
+        // Target read "!"
+        varint(1, &mut patch);
+        patch.push(b'!');
+        let patch = with_footer(patch, source, target);
+
+        assert_eq!(
+            apply(source, &patch, PatchFormat::Bps).unwrap(),
+            target.to_vec()
+        );
+        let err = apply(b"ABCDEFGX", &patch, PatchFormat::Bps).unwrap_err();
+        assert!(err.to_string().contains("ROM checksum mismatch"), "{err}");
+        let mut corrupt = patch.clone();
+        corrupt[10] ^= 1;
+        assert!(apply(source, &corrupt, PatchFormat::Bps).is_err());
+    }
+
+    #[test]
+    fn ups() {
+        let source = b"ABCDEFGH";
+        let target = b"ABZDEFGHIJ";
+        let mut patch = b"UPS1".to_vec();
+        varint(source.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        varint(2, &mut patch);
+        patch.extend_from_slice(&[b'C' ^ b'Z', 0x00]);
+        varint(4, &mut patch);
+        patch.extend_from_slice(&[b'I', b'J', 0x00]);
+        let patch = with_footer(patch, source, target);
+
+        assert_eq!(
+            apply(source, &patch, PatchFormat::Ups).unwrap(),
+            target.to_vec()
+        );
+        assert!(apply(b"ABCDEFG", &patch, PatchFormat::Ups).is_err());
+    }
+
+    #[test]
+    fn offsets_out_of_range() {
+        let mut reader = Reader::new(b"ABC");
+        reader.byte().unwrap();
+        assert!(reader.bytes(usize::MAX).is_err());
+        assert_eq!(reader.remaining(), 2);
+
+        // Source copy from the largest negative offset
+        let source = b"ABCD";
+        let mut patch = b"BPS1".to_vec();
+        varint(source.len(), &mut patch);
+        varint(1, &mut patch);
+        varint(0, &mut patch);
+        varint(2, &mut patch);
+        varint(usize::MAX, &mut patch);
+        let patch = with_footer(patch, source, b"A");
+        let err = apply(source, &patch, PatchFormat::Bps).unwrap_err();
+        assert!(err.to_string().contains("out of range"), "{err}");
+
+        // XOR run starting at the largest offset
+        let mut patch = b"UPS1".to_vec();
+        varint(source.len(), &mut patch);
+        varint(source.len(), &mut patch);
+        varint(usize::MAX, &mut patch);
+        patch.extend_from_slice(&[0x01, 0x00]);
+        let patch = with_footer(patch, source, source);
+        let err = apply(source, &patch, PatchFormat::Ups).unwrap_err();
+        assert!(err.to_string().contains("out of range"), "{err}");
+    }
+
+    #[test]
+    fn patch_order() {
+        let dir = std::env::temp_dir().join(format!("tetanes_rom_patch_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "game.nes",
+            "game.2.bps",
+            "game.ips",
+            "game.1.ups",
+            "game.txt",
+            "other.ips",
+            "game.x.ips",
+        ] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let names: Vec<_> = find_patches(&dir.join("game.nes"))
+            .into_iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(names, ["game.ips", "game.1.ups", "game.2.bps"]);
+    }
+}
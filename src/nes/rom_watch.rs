@@ -0,0 +1,254 @@
+//! Watches the ROM folder for ROMs being added or removed while `TetaNES` is running.
+//!
+//! Like the configuration file, the folder is polled rather than watched so it works the same on
+//! every platform. The ROM browser is updated in place, and titles for new ROMs are looked up in
+//! the game database on a worker thread so copying in a large set doesn't stall the frame.
+
+use crate::{cart::GameTitle, nes::Nes};
+use std::{
+    collections::BTreeSet,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+type TitleResult = (PathBuf, Option<GameTitle>);
+
+/// Looks up game titles on a worker thread.
+#[derive(Debug)]
+struct TitleWorker {
+    jobs: Sender<PathBuf>,
+    titles: Receiver<TitleResult>,
+}
+
+impl TitleWorker {
+    fn spawn() -> std::io::Result<Self> {
+        let (jobs, job_rx) = channel::<PathBuf>();
+        let (title_tx, titles) = channel();
+        thread::Builder::new()
+            .name("rom titles".into())
+            .spawn(move || {
+                for path in job_rx {
+                    let title = GameTitle::from_path(&path).unwrap_or_else(|err| {
+                        log::debug!("{path:?}: {err:?}");
+                        None
+                    });
+                    if title_tx.send((path, title)).is_err() {
+                        break;
+                    }
+                }
+            })?;
+        Ok(Self { jobs, titles })
+    }
+}
+
+/// ROMs in the ROM folder as of the last check.
+#[derive(Debug)]
+#[must_use]
+pub(crate) struct RomWatch {
+    dir: PathBuf,
+    roms: BTreeSet<PathBuf>,
+    last_check: Instant,
+    worker: Option<TitleWorker>,
+}
+
+impl Default for RomWatch {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::new(),
+            roms: BTreeSet::new(),
+            last_check: Instant::now(),
+            worker: None,
+        }
+    }
+}
+
+/// The folder shown in the ROM browser for `rom_path`.
+pub(crate) fn rom_dir(rom_path: &Path) -> &Path {
+    if rom_path.is_file() {
+        rom_path.parent().unwrap_or(rom_path)
+    } else {
+        rom_path
+    }
+}
+
+fn is_rom(path: &Path) -> bool {
+    path.is_file() && matches!(path.extension().and_then(OsStr::to_str), Some("nes"))
+}
+
+fn read_roms(dir: &Path) -> BTreeSet<PathBuf> {
+    dir.read_dir()
+        .map(|read_dir| {
+            read_dir
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| is_rom(path))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Describes a change in ROMs for a notification, naming the ROM if there's only one.
+fn change_message(verb: &str, paths: &[PathBuf]) -> Option<String> {
+    match paths {
+        [] => None,
+        [path] => Some(format!(
+            "{verb} ROM: {}",
+            path.file_name()
+                .map_or_else(|| path.to_string_lossy(), OsStr::to_string_lossy)
+        )),
+        paths => Some(format!("{verb} {} ROMs", paths.len())),
+    }
+}
+
+impl Nes {
+    /// Checks the ROM folder for added and removed ROMs, updating the ROM browser and notifying
+    /// the user.
+    pub(crate) fn check_rom_watch(&mut self) {
+        self.receive_rom_titles();
+        if !self.config.watch_rom_dir || self.rom_watch.last_check.elapsed() < CHECK_INTERVAL {
+            return;
+        }
+        self.rom_watch.last_check = Instant::now();
+
+        let dir = rom_dir(&self.config.rom_path).to_path_buf();
+        let roms = read_roms(&dir);
+        if dir != self.rom_watch.dir {
+            // Browsing to another folder isn't a change
+            self.rom_watch.dir = dir;
+            self.rom_watch.roms = roms;
+            return;
+        }
+        let added = roms
+            .difference(&self.rom_watch.roms)
+            .cloned()
+            .collect::<Vec<_>>();
+        let removed = self
+            .rom_watch
+            .roms
+            .difference(&roms)
+            .cloned()
+            .collect::<Vec<_>>();
+        if added.is_empty() && removed.is_empty() {
+            return;
+        }
+        self.rom_watch.roms = roms;
+
+        for path in &removed {
+            log::info!("ROM removed: {path:?}");
+            self.remove_browser_path(path);
+        }
+        for path in &added {
+            log::info!("ROM added: {path:?}");
+            self.add_browser_path(path);
+        }
+        for message in [
+            change_message("Added", &added),
+            change_message("Removed", &removed),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            self.add_message(message);
+        }
+    }
+
+    /// Whether the ROM browser has listed the watched folder.
+    fn browser_listed(&self) -> bool {
+        !self.paths.is_empty() && rom_dir(&self.config.rom_path) == self.rom_watch.dir
+    }
+
+    fn add_browser_path(&mut self, path: &Path) {
+        if !self.browser_listed() || self.paths.iter().any(|p| p == path) {
+            return;
+        }
+        // The parent folder entry stays first
+        let start = usize::from(self.paths[0] == Path::new("../"));
+        let index = start + self.paths[start..].partition_point(|p| p.as_path() < path);
+        self.paths.insert(index, path.to_path_buf());
+        self.rom_titles.insert(index, None);
+        if self.selected_path >= index && self.selected_path > 0 {
+            self.selected_path += 1;
+        }
+
+        if self.rom_watch.worker.is_none() {
+            match TitleWorker::spawn() {
+                Ok(worker) => self.rom_watch.worker = Some(worker),
+                Err(err) => {
+                    log::error!("failed to start ROM title worker: {err:?}");
+                    return;
+                }
+            }
+        }
+        if let Some(ref worker) = self.rom_watch.worker {
+            if worker.jobs.send(path.to_path_buf()).is_err() {
+                self.rom_watch.worker = None;
+            }
+        }
+    }
+
+    fn remove_browser_path(&mut self, path: &Path) {
+        if !self.browser_listed() {
+            return;
+        }
+        if let Some(index) = self.paths.iter().position(|p| p == path) {
+            self.paths.remove(index);
+            if index < self.rom_titles.len() {
+                self.rom_titles.remove(index);
+            }
+            if self.selected_path > index {
+                self.selected_path -= 1;
+            } else if self.selected_path == index {
+                self.selected_path = 0;
+            }
+        }
+    }
+
+    /// Fills in titles looked up by the worker for ROMs still listed in the ROM browser.
+    fn receive_rom_titles(&mut self) {
+        let Some(ref worker) = self.rom_watch.worker else {
+            return;
+        };
+        for (path, title) in worker.titles.try_iter() {
+            if let Some(index) = self.paths.iter().position(|p| *p == path) {
+                if let Some(entry) = self.rom_titles.get_mut(index) {
+                    *entry = title;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rom_change_messages() {
+        assert_eq!(change_message("Added", &[]), None);
+        assert_eq!(
+            change_message("Added", &[PathBuf::from("roms/zelda.nes")]),
+            Some("Added ROM: zelda.nes".to_string())
+        );
+        assert_eq!(
+            change_message("Removed", &[PathBuf::from("a.nes"), PathBuf::from("b.nes")]),
+            Some("Removed 2 ROMs".to_string())
+        );
+    }
+
+    #[test]
+    fn watch_rom_dir() {
+        let dir = std::env::temp_dir().join(format!("tetanes_rom_watch_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).expect("created test dir");
+        std::fs::write(dir.join("game.nes"), b"").expect("wrote rom");
+        std::fs::write(dir.join("notes.txt"), b"").expect("wrote text file");
+        let roms = read_roms(&dir);
+        assert_eq!(roms.into_iter().collect::<Vec<_>>(), [dir.join("game.nes")]);
+        assert_eq!(rom_dir(&dir.join("game.nes")), dir.as_path());
+        std::fs::remove_dir_all(&dir).expect("removed test dir");
+    }
+}
@@ -0,0 +1,860 @@
+//! JSON-RPC control server, so external tools like test frameworks and agents can drive the
+//! emulator without embedding it.
+//!
+//! The server listens on `rpc_address`: a TCP address like `127.0.0.1:7777`, or on Unix a socket
+//! path prefixed with `unix:`. Each line sent is a JSON-RPC 2.0 request and gets a response on
+//! its own line. Connections are read on background threads, but requests are handled between
+//! frames on the main thread so they always see a consistent console state.
+//!
+//! Debugger methods manage breakpoints, step and disassemble, so editor tasks and other tools can
+//! run debugging sessions. They open the debugger window if it isn't already open, since
+//! breakpoints are only checked while it is.
+
+use crate::{
+    debugger::{Address, Breakpoint},
+    input::{JoypadBtnState, Slot},
+    mem::{Access, Mem},
+    nes::{debug::Debugger, event::DebugAction, persistence::DataKind, Mode, Nes},
+    ppu::Ppu,
+    NesResult,
+};
+use anyhow::{anyhow, bail, Context};
+use pix_engine::prelude::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::{Shutdown, TcpListener},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// How often the listener checks for new connections and whether it should stop.
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+/// Longest request line accepted. Longer requests close the connection so a client can't grow
+/// the buffer without bound.
+const MAX_REQUEST_LEN: u64 = 1024 * 1024;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// An error returned to the client.
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(err: impl std::fmt::Display) -> Self {
+        Self {
+            code: INVALID_PARAMS,
+            message: format!("invalid params: {err}"),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(err: anyhow::Error) -> Self {
+        Self {
+            code: SERVER_ERROR,
+            message: format!("{err:#}"),
+        }
+    }
+}
+
+fn response(id: &Value, result: Result<Value, RpcError>) -> String {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(err) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": err.code, "message": err.message },
+        }),
+    }
+    .to_string()
+}
+
+/// A request waiting to be handled on the main thread.
+#[derive(Debug)]
+struct Call {
+    request: Request,
+    reply: Sender<String>,
+}
+
+/// Closes a connection from another thread, unblocking the thread serving it.
+type Closer = Box<dyn Fn() + Send>;
+
+/// Open connections by ID, so they can be closed when the server stops.
+#[derive(Default)]
+struct Connections {
+    next_id: usize,
+    open: Vec<(usize, Closer)>,
+}
+
+impl std::fmt::Debug for Connections {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Connections")
+            .field("open", &self.open.len())
+            .finish()
+    }
+}
+
+/// A running control server. Dropping it stops listening and closes open connections.
+#[derive(Debug)]
+#[must_use]
+pub(crate) struct RpcServer {
+    address: String,
+    calls: Receiver<Call>,
+    running: Arc<AtomicBool>,
+    connections: Arc<Mutex<Connections>>,
+}
+
+impl Drop for RpcServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Ok(mut connections) = self.connections.lock() {
+            for (_, close) in connections.open.drain(..) {
+                close();
+            }
+        }
+    }
+}
+
+/// Reads requests from a connection, forwarding each one to the main thread and writing back
+/// the response, until the client disconnects or the server stops.
+fn serve<S>(stream: S, calls: &Sender<Call>) -> NesResult<()>
+where
+    for<'a> &'a S: Read + Write,
+{
+    let mut writer = &stream;
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let len = (&mut reader)
+            .take(MAX_REQUEST_LEN + 1)
+            .read_line(&mut line)
+            .context("failed to read request")?;
+        if len == 0 {
+            break;
+        }
+        if len as u64 > MAX_REQUEST_LEN && !line.ends_with('\n') {
+            let response = response(
+                &Value::Null,
+                Err(RpcError {
+                    code: INVALID_REQUEST,
+                    message: format!("request longer than {MAX_REQUEST_LEN} bytes"),
+                }),
+            );
+            writeln!(writer, "{response}").context("failed to write response")?;
+            bail!("request too long");
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Value>(&line) {
+            Err(err) => response(
+                &Value::Null,
+                Err(RpcError {
+                    code: PARSE_ERROR,
+                    message: format!("parse error: {err}"),
+                }),
+            ),
+            Ok(value) => {
+                let id = value.get("id").cloned().unwrap_or_default();
+                match serde_json::from_value::<Request>(value) {
+                    Err(err) => response(
+                        &id,
+                        Err(RpcError {
+                            code: INVALID_REQUEST,
+                            message: format!("invalid request: {err}"),
+                        }),
+                    ),
+                    Ok(request) => {
+                        let (reply, response) = mpsc::channel();
+                        calls
+                            .send(Call { request, reply })
+                            .map_err(|_| anyhow!("server stopped"))?;
+                        response.recv().context("server stopped")?
+                    }
+                }
+            }
+        };
+        writeln!(writer, "{response}").context("failed to write response")?;
+    }
+    Ok(())
+}
+
+/// Accepts connections until the server stops, serving each on its own thread. `accept` returns
+/// each stream along with a way to close it when the server stops.
+fn listen<L, S>(
+    mut accept: L,
+    calls: Sender<Call>,
+    running: Arc<AtomicBool>,
+    connections: Arc<Mutex<Connections>>,
+) where
+    L: FnMut() -> io::Result<(S, Closer)> + Send + 'static,
+    S: Send + 'static,
+    for<'a> &'a S: Read + Write,
+{
+    thread::spawn(move || {
+        while running.load(Ordering::SeqCst) {
+            match accept() {
+                Ok((stream, close)) => {
+                    let id = {
+                        let Ok(mut connections) = connections.lock() else {
+                            close();
+                            continue;
+                        };
+                        let id = connections.next_id;
+                        connections.next_id += 1;
+                        connections.open.push((id, close));
+                        id
+                    };
+                    // The server may have stopped and closed connections while this one was
+                    // being added
+                    if !running.load(Ordering::SeqCst) {
+                        close_connection(&connections, id);
+                        break;
+                    }
+                    let calls = calls.clone();
+                    let connections = Arc::clone(&connections);
+                    thread::spawn(move || {
+                        if let Err(err) = serve(stream, &calls) {
+                            log::debug!("control connection closed: {err:?}");
+                        }
+                        close_connection(&connections, id);
+                    });
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => thread::sleep(ACCEPT_INTERVAL),
+                Err(err) => {
+                    log::error!("control server failed to accept connection: {err:?}");
+                    thread::sleep(ACCEPT_INTERVAL);
+                }
+            }
+        }
+    });
+}
+
+/// Closes and forgets the connection with `id`, if it's still open.
+fn close_connection(connections: &Mutex<Connections>, id: usize) {
+    if let Ok(mut connections) = connections.lock() {
+        if let Some(index) = connections.open.iter().position(|(open, _)| *open == id) {
+            let (_, close) = connections.open.swap_remove(index);
+            close();
+        }
+    }
+}
+
+impl RpcServer {
+    /// Starts listening on `address`.
+    ///
+    /// # Errors
+    ///
+    /// If the address is invalid or already in use, an error is returned.
+    pub(crate) fn start(address: &str) -> NesResult<Self> {
+        let (calls, receiver) = mpsc::channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let connections = Arc::new(Mutex::new(Connections::default()));
+        #[cfg(unix)]
+        {
+            if let Some(path) = address.strip_prefix("unix:") {
+                use std::os::unix::net::UnixListener;
+                // Remove a socket left behind by a previous session
+                let _ = std::fs::remove_file(path);
+                let listener =
+                    UnixListener::bind(path).with_context(|| format!("failed to bind {path:?}"))?;
+                listener.set_nonblocking(true)?;
+                listen(
+                    move || {
+                        let (stream, _) = listener.accept()?;
+                        stream.set_nonblocking(false)?;
+                        let closer = stream.try_clone()?;
+                        let close: Closer = Box::new(move || {
+                            let _ = closer.shutdown(Shutdown::Both);
+                        });
+                        Ok((stream, close))
+                    },
+                    calls,
+                    Arc::clone(&running),
+                    Arc::clone(&connections),
+                );
+                return Ok(Self {
+                    address: address.to_string(),
+                    calls: receiver,
+                    running,
+                    connections,
+                });
+            }
+        }
+        let listener =
+            TcpListener::bind(address).with_context(|| format!("failed to bind {address:?}"))?;
+        listener.set_nonblocking(true)?;
+        listen(
+            move || {
+                let (stream, _) = listener.accept()?;
+                stream.set_nonblocking(false)?;
+                let closer = stream.try_clone()?;
+                let close: Closer = Box::new(move || {
+                    let _ = closer.shutdown(Shutdown::Both);
+                });
+                Ok((stream, close))
+            },
+            calls,
+            Arc::clone(&running),
+            Arc::clone(&connections),
+        );
+        Ok(Self {
+            address: address.to_string(),
+            calls: receiver,
+            running,
+            connections,
+        })
+    }
+}
+
+fn params<'de, T: Deserialize<'de>>(params: &'de Value) -> Result<T, RpcError> {
+    T::deserialize(params).map_err(RpcError::invalid_params)
+}
+
+/// Params for methods where every param is optional, so they can be omitted entirely.
+fn optional_params<'de, T: Default + Deserialize<'de>>(params: &'de Value) -> Result<T, RpcError> {
+    if params.is_null() {
+        Ok(T::default())
+    } else {
+        self::params(params)
+    }
+}
+
+fn slot(slot: u8) -> Result<Slot, RpcError> {
+    match slot {
+        1 => Ok(Slot::One),
+        2 => Ok(Slot::Two),
+        3 => Ok(Slot::Three),
+        4 => Ok(Slot::Four),
+        _ => Err(RpcError::invalid_params(format!("invalid slot {slot}"))),
+    }
+}
+
+fn button(name: &str) -> Result<JoypadBtnState, RpcError> {
+    Ok(match name.to_ascii_lowercase().as_str() {
+        "a" => JoypadBtnState::A,
+        "b" => JoypadBtnState::B,
+        "select" => JoypadBtnState::SELECT,
+        "start" => JoypadBtnState::START,
+        "up" => JoypadBtnState::UP,
+        "down" => JoypadBtnState::DOWN,
+        "left" => JoypadBtnState::LEFT,
+        "right" => JoypadBtnState::RIGHT,
+        _ => return Err(RpcError::invalid_params(format!("invalid button `{name}`"))),
+    })
+}
+
+const fn default_slot() -> u8 {
+    1
+}
+
+const fn default_pressed() -> bool {
+    true
+}
+
+const fn default_length() -> u16 {
+    1
+}
+
+const fn default_enabled() -> bool {
+    true
+}
+
+const fn default_count() -> u16 {
+    10
+}
+
+#[derive(Deserialize)]
+struct PathParams {
+    path: PathBuf,
+}
+
+#[derive(Deserialize)]
+struct ButtonParams {
+    #[serde(default = "default_slot")]
+    slot: u8,
+    button: String,
+    #[serde(default = "default_pressed")]
+    pressed: bool,
+}
+
+#[derive(Deserialize)]
+struct ReadParams {
+    address: u16,
+    #[serde(default = "default_length")]
+    length: u16,
+}
+
+#[derive(Deserialize)]
+struct WriteParams {
+    address: u16,
+    bytes: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct SlotParams {
+    slot: u8,
+}
+
+/// A single address, or a hex address or range like `"$C000"` or `"0000-07FF"`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AddressParam {
+    Number(u16),
+    Text(String),
+}
+
+impl TryFrom<AddressParam> for Address {
+    type Error = RpcError;
+
+    fn try_from(address: AddressParam) -> Result<Self, Self::Error> {
+        match address {
+            AddressParam::Number(addr) => Ok(Self::Addr(addr)),
+            AddressParam::Text(text) => text
+                .parse()
+                .map_err(|_| RpcError::invalid_params(format!("invalid address `{text}`"))),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum BreakpointAccess {
+    #[default]
+    Execute,
+    Write,
+}
+
+#[derive(Deserialize)]
+struct BreakpointParams {
+    address: AddressParam,
+    #[serde(default)]
+    access: BreakpointAccess,
+    #[serde(default)]
+    break_after: u32,
+    #[serde(default)]
+    temporary: bool,
+}
+
+#[derive(Deserialize)]
+struct IndexParams {
+    index: usize,
+}
+
+#[derive(Deserialize)]
+struct EnableParams {
+    index: usize,
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum StepKind {
+    #[default]
+    Into,
+    Over,
+    Out,
+    Frame,
+    Scanline,
+}
+
+#[derive(Default, Deserialize)]
+struct StepParams {
+    #[serde(default)]
+    kind: StepKind,
+}
+
+#[derive(Deserialize)]
+struct DisassembleParams {
+    address: Option<u16>,
+    #[serde(default = "default_count")]
+    count: u16,
+}
+
+impl Default for DisassembleParams {
+    fn default() -> Self {
+        Self {
+            address: None,
+            count: default_count(),
+        }
+    }
+}
+
+fn breakpoint_json(index: usize, bp: &Breakpoint) -> Value {
+    let access = if bp.access.contains(&Access::Write) {
+        "write"
+    } else {
+        "execute"
+    };
+    json!({
+        "index": index,
+        "address": bp.addr.to_string(),
+        "access": access,
+        "enabled": bp.enabled,
+        "hit_count": bp.hit_count,
+        "break_after": bp.break_after,
+        "temporary": bp.temporary,
+    })
+}
+
+impl Nes {
+    /// Starts or stops the control server to match the configuration.
+    pub(crate) fn update_rpc_server(&mut self) {
+        if !self.config.rpc_server {
+            if self.rpc.take().is_some() {
+                log::info!("Stopped control server");
+            }
+            return;
+        }
+        if matches!(self.rpc, Some(ref rpc) if rpc.address == self.config.rpc_address) {
+            return;
+        }
+        self.rpc = None;
+        match RpcServer::start(&self.config.rpc_address) {
+            Ok(rpc) => {
+                log::info!("Control server listening on {}", rpc.address);
+                self.rpc = Some(rpc);
+            }
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message(format!(
+                    "Failed to start control server on {}",
+                    self.config.rpc_address
+                ));
+            }
+        }
+    }
+
+    /// The debugger, opened if needed.
+    fn rpc_debugger(&mut self, s: &mut PixState) -> NesResult<&mut Debugger> {
+        if self.debugger.is_none() {
+            self.toggle_debugger(s)?;
+        }
+        self.debugger
+            .as_mut()
+            .ok_or_else(|| anyhow!("failed to open debugger"))
+    }
+
+    fn registers(&self) -> Value {
+        let cpu = self.control_deck.cpu();
+        let ppu = self.control_deck.ppu();
+        json!({
+            "pc": cpu.pc(),
+            "a": cpu.a(),
+            "x": cpu.x(),
+            "y": cpu.y(),
+            "sp": cpu.sp(),
+            "status": cpu.status().bits(),
+            "cycle": cpu.cycle(),
+            "scanline": ppu.scanline(),
+            "ppu_cycle": ppu.cycle(),
+            "frame": ppu.frame_number(),
+        })
+    }
+
+    /// Handles requests received since the last frame.
+    pub(crate) fn handle_rpc(&mut self, s: &mut PixState) -> PixResult<()> {
+        loop {
+            let Some(call) = self.rpc.as_ref().and_then(|rpc| rpc.calls.try_recv().ok()) else {
+                return Ok(());
+            };
+            let result = self.handle_rpc_request(s, &call.request);
+            // The client may have disconnected while waiting
+            let _ = call.reply.send(response(&call.request.id, result));
+        }
+    }
+
+    fn handle_rpc_request(
+        &mut self,
+        s: &mut PixState,
+        request: &Request,
+    ) -> Result<Value, RpcError> {
+        let params = &request.params;
+        match request.method.as_str() {
+            "status" => Ok(json!({
+                "rom": self.control_deck.loaded_rom(),
+                "frame": self.control_deck.frame_number(),
+                "paused": self.mode != Mode::Playing,
+                "breakpoint": self.debugger.as_ref().and_then(|debugger| debugger.break_addr),
+            })),
+            "load_rom" => {
+                let PathParams { path } = self::params(params)?;
+                self.config.rom_path = path;
+                self.load_rom(s)?;
+                match self.error.clone() {
+                    Some(err) => Err(anyhow!(err).into()),
+                    None => Ok(Value::Null),
+                }
+            }
+            "pause" => {
+                self.pause_play();
+                Ok(Value::Null)
+            }
+            "resume" => {
+                self.resume_play();
+                Ok(Value::Null)
+            }
+            "set_button" => {
+                let ButtonParams {
+                    slot,
+                    button,
+                    pressed,
+                } = self::params(params)?;
+                let (slot, button) = (self::slot(slot)?, self::button(&button)?);
+                self.control_deck
+                    .joypad_mut(slot)
+                    .set_button(button, pressed);
+                Ok(Value::Null)
+            }
+            "read_memory" => {
+                let ReadParams { address, length } = self::params(params)?;
+                let cpu = self.control_deck.cpu();
+                let bytes = (0..length)
+                    .map(|offset| cpu.peek(address.wrapping_add(offset), Access::Dummy))
+                    .collect::<Vec<_>>();
+                Ok(json!(bytes))
+            }
+            "write_memory" => {
+                let WriteParams { address, bytes } = self::params(params)?;
+                let cpu = self.control_deck.cpu_mut();
+                for (addr, byte) in (address..=u16::MAX).zip(bytes) {
+                    cpu.poke(addr, byte);
+                }
+                Ok(Value::Null)
+            }
+            "save_state" => {
+                let SlotParams { slot } = self::params(params)?;
+                let key = self.save_key(slot)?;
+                self.write_state(&key)?;
+                Ok(Value::Null)
+            }
+            "load_state" => {
+                let SlotParams { slot } = self::params(params)?;
+                let key = self.save_key(slot)?;
+                let data = self
+                    .persistence
+                    .load(DataKind::State, &key)?
+                    .ok_or_else(|| anyhow!("no save state in slot {slot}"))?;
+                let cpu = bincode::deserialize(&data).context("failed to deserialize state")?;
+                self.control_deck.try_load_cpu(cpu)?;
+                Ok(Value::Null)
+            }
+            "screenshot" => {
+                let PathParams { path } = self::params(params)?;
+                Image::from_bytes(
+                    Ppu::WIDTH,
+                    Ppu::HEIGHT,
+                    self.control_deck.frame_buffer(),
+                    PixelFormat::Rgba,
+                )
+                .and_then(|image| image.save(&path))
+                .with_context(|| format!("failed to save screenshot {path:?}"))?;
+                Ok(json!(path))
+            }
+            "breakpoints" => {
+                let breakpoints = &self.rpc_debugger(s)?.breakpoints;
+                Ok(breakpoints
+                    .list
+                    .iter()
+                    .enumerate()
+                    .map(|(i, bp)| breakpoint_json(i, bp))
+                    .collect())
+            }
+            "add_breakpoint" => {
+                let BreakpointParams {
+                    address,
+                    access,
+                    break_after,
+                    temporary,
+                } = self::params(params)?;
+                let access = match access {
+                    BreakpointAccess::Execute => Access::Execute,
+                    BreakpointAccess::Write => Access::Write,
+                };
+                let mut bp = Breakpoint::new(Address::try_from(address)?, access);
+                bp.break_after = break_after;
+                bp.temporary = temporary;
+                let breakpoints = &mut self.rpc_debugger(s)?.breakpoints;
+                breakpoints.add(bp);
+                Ok(json!(breakpoints.list.len() - 1))
+            }
+            "remove_breakpoint" => {
+                let IndexParams { index } = self::params(params)?;
+                let breakpoints = &mut self.rpc_debugger(s)?.breakpoints;
+                if index >= breakpoints.list.len() {
+                    return Err(RpcError::invalid_params(format!("no breakpoint {index}")));
+                }
+                breakpoints.remove(index);
+                Ok(Value::Null)
+            }
+            "enable_breakpoint" => {
+                let EnableParams { index, enabled } = self::params(params)?;
+                let bp = self
+                    .rpc_debugger(s)?
+                    .breakpoints
+                    .list
+                    .get_mut(index)
+                    .ok_or_else(|| RpcError::invalid_params(format!("no breakpoint {index}")))?;
+                bp.enabled = enabled;
+                Ok(Value::Null)
+            }
+            "clear_breakpoints" => {
+                self.rpc_debugger(s)?.breakpoints.list.clear();
+                Ok(Value::Null)
+            }
+            "step" => {
+                let StepParams { kind } = optional_params(params)?;
+                let action = match kind {
+                    StepKind::Into => DebugAction::StepInto,
+                    StepKind::Over => DebugAction::StepOver,
+                    StepKind::Out => DebugAction::StepOut,
+                    StepKind::Frame => DebugAction::StepFrame,
+                    StepKind::Scanline => DebugAction::StepScanline,
+                };
+                self.rpc_debugger(s)?.break_addr = None;
+                self.handle_debug(s, action, false)?;
+                Ok(self.registers())
+            }
+            "continue" => {
+                self.rpc_debugger(s)?.break_addr = None;
+                self.resume_play();
+                Ok(Value::Null)
+            }
+            "registers" => Ok(self.registers()),
+            "disassemble" => {
+                let DisassembleParams { address, count } = optional_params(params)?;
+                let cpu = self.control_deck.cpu_mut();
+                let mut pc = address.unwrap_or_else(|| cpu.pc());
+                let lines = (0..count)
+                    .map(|_| {
+                        let address = pc;
+                        cpu.disassemble(&mut pc);
+                        json!({ "address": address, "text": cpu.disasm() })
+                    })
+                    .collect();
+                Ok(Value::Array(lines))
+            }
+            method => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("method not found: {method}"),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn responses() {
+        assert_eq!(
+            response(&json!(1), Ok(json!([1, 2]))),
+            r#"{"id":1,"jsonrpc":"2.0","result":[1,2]}"#
+        );
+        let err = response(&Value::Null, Err(RpcError::invalid_params("bad slot")));
+        let err: Value = serde_json::from_str(&err).expect("valid json");
+        assert_eq!(err["error"]["code"], INVALID_PARAMS);
+
+        let request: Request =
+            serde_json::from_str(r#"{"jsonrpc":"2.0","id":"a","method":"status"}"#)
+                .expect("valid request");
+        assert_eq!(request.method, "status");
+        assert_eq!(request.params, Value::Null);
+        assert!(button("Start").is_ok());
+        assert!(slot(5).is_err());
+    }
+
+    #[test]
+    fn breakpoint_params() {
+        let BreakpointParams {
+            address, access, ..
+        } = params(&json!({ "address": "0000-07FF", "access": "write" })).expect("valid params");
+        assert_eq!(
+            Address::try_from(address).ok(),
+            Some(Address::AddrRange(0x0000..=0x07FF))
+        );
+        assert!(matches!(access, BreakpointAccess::Write));
+
+        let BreakpointParams {
+            address, access, ..
+        } = params(&json!({ "address": 49152 })).expect("valid params");
+        assert_eq!(Address::try_from(address).ok(), Some(Address::Addr(0xC000)));
+        assert!(matches!(access, BreakpointAccess::Execute));
+
+        let BreakpointParams { address, .. } =
+            params(&json!({ "address": "zz" })).expect("valid params");
+        assert!(Address::try_from(address).is_err());
+        assert!(params::<StepParams>(&json!({ "kind": "sideways" })).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn long_request_closes_connection() {
+        use std::os::unix::net::UnixStream;
+
+        let (client, stream) = UnixStream::pair().expect("socket pair");
+        let (calls, _receiver) = mpsc::channel();
+        let server = thread::spawn(move || serve(stream, &calls));
+
+        let request = vec![b' '; MAX_REQUEST_LEN as usize + 1];
+        (&client).write_all(&request).expect("sent request");
+        let mut line = String::new();
+        BufReader::new(&client)
+            .read_line(&mut line)
+            .expect("read response");
+        let response: Value = serde_json::from_str(&line).expect("valid json");
+        assert_eq!(response["error"]["code"], INVALID_REQUEST);
+        assert!(server.join().expect("serve finished").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn drop_closes_connections() {
+        use std::os::unix::net::UnixStream;
+
+        let path = std::env::temp_dir().join(format!("tetanes_rpc_{}.sock", std::process::id()));
+        let server = RpcServer::start(&format!("unix:{}", path.display())).expect("started");
+        let client = UnixStream::connect(&path).expect("connected");
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .expect("set timeout");
+        let accepted = (0..100).any(|_| {
+            thread::sleep(ACCEPT_INTERVAL / 5);
+            server.connections.lock().expect("lock").open.len() == 1
+        });
+        assert!(accepted, "connection accepted");
+
+        drop(server);
+        let mut buf = [0; 1];
+        let read = (&client).read(&mut buf).expect("closed before timeout");
+        assert_eq!(read, 0, "connection closed");
+        let _ = std::fs::remove_file(&path);
+    }
+}
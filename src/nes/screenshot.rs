@@ -0,0 +1,440 @@
+//! Screenshots with embedded metadata, raw captures, clipboard copies and bursts.
+//!
+//! Every screenshot is a PNG with the game, frame number and video settings embedded as `iTXt`
+//! chunks. A raw capture of the unfiltered 256x240 frame can be saved next to the filtered one,
+//! and a burst saves that many consecutive emulated frames for animation reference. Copying to
+//! the clipboard uses the platform's clipboard tool, since images can't be copied otherwise.
+//!
+//! For tooling, frames can also be exported as palette indices: a `.idx` file with one byte per
+//! pixel holding the system palette index, 0-63, row by row for the full 256x240 frame, and a
+//! `.pal` file with the 64 RGB colors those indices map to, in the common NES `.pal` layout.
+//! Color emphasis isn't included.
+
+use crate::{
+    cart::crc32,
+    nes::{gallery::screenshot_prefix, Nes},
+    ppu::Ppu,
+    NesResult,
+};
+use anyhow::{anyhow, Context};
+use chrono::Local;
+use pix_engine::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    ffi::OsStr,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+/// Suffix added to the filename of raw captures.
+const RAW_SUFFIX: &str = "_raw";
+
+/// Files written for each screenshot.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub(crate) enum ScreenshotFormat {
+    #[default]
+    Png,
+    Indices,
+    Both,
+}
+
+impl ScreenshotFormat {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[Self::Png, Self::Indices, Self::Both]
+    }
+
+    #[inline]
+    #[must_use]
+    pub(crate) const fn png(self) -> bool {
+        matches!(self, Self::Png | Self::Both)
+    }
+
+    #[inline]
+    #[must_use]
+    pub(crate) const fn indices(self) -> bool {
+        matches!(self, Self::Indices | Self::Both)
+    }
+}
+
+impl AsRef<str> for ScreenshotFormat {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Png => "PNG",
+            Self::Indices => "Palette Indices",
+            Self::Both => "PNG and Palette Indices",
+        }
+    }
+}
+
+impl From<usize> for ScreenshotFormat {
+    fn from(value: usize) -> Self {
+        match value {
+            1 => Self::Indices,
+            2 => Self::Both,
+            _ => Self::Png,
+        }
+    }
+}
+
+/// Frames left to save in a screenshot burst.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct Burst {
+    /// Filename of the burst without the frame index or extension.
+    name: String,
+    saved: u32,
+    count: u32,
+}
+
+/// Builds an `iTXt` chunk, which holds UTF-8 text unlike `tEXt`.
+fn text_chunk(keyword: &str, text: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(keyword.len() + text.len() + 5);
+    data.extend_from_slice(keyword.as_bytes());
+    // Null separator, no compression, compression method, then empty language and translated
+    // keyword
+    data.extend_from_slice(&[0, 0, 0, 0, 0]);
+    data.extend_from_slice(text.as_bytes());
+    chunk(b"iTXt", &data)
+}
+
+fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    chunk.extend_from_slice(kind);
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk[4..]);
+    chunk.extend_from_slice(&crc.to_be_bytes());
+    chunk
+}
+
+/// Inserts text chunks into a PNG before the image data.
+///
+/// # Errors
+///
+/// If `png` isn't a valid PNG, an error is returned.
+fn add_png_text(png: &[u8], text: &[(&str, String)]) -> NesResult<Vec<u8>> {
+    if !png.starts_with(PNG_SIGNATURE) {
+        return Err(anyhow!("invalid PNG signature"));
+    }
+    let mut offset = PNG_SIGNATURE.len();
+    while offset + 8 <= png.len() {
+        let len = u32::from_be_bytes([
+            png[offset],
+            png[offset + 1],
+            png[offset + 2],
+            png[offset + 3],
+        ]) as usize;
+        if &png[offset + 4..offset + 8] == b"IDAT" {
+            let mut output = Vec::with_capacity(png.len() + 64 * text.len());
+            output.extend_from_slice(&png[..offset]);
+            for (keyword, text) in text {
+                output.extend(text_chunk(keyword, text));
+            }
+            output.extend_from_slice(&png[offset..]);
+            return Ok(output);
+        }
+        offset += len + 12;
+    }
+    Err(anyhow!("PNG has no image data"))
+}
+
+/// Embeds text metadata into the PNG at `path`.
+fn embed_png_text(path: &Path, text: &[(&str, String)]) -> NesResult<()> {
+    let png = fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+    let png = add_png_text(&png, text).with_context(|| format!("invalid PNG {path:?}"))?;
+    fs::write(path, png).with_context(|| format!("failed to write {path:?}"))
+}
+
+/// Saves RGBA pixels as a PNG with text metadata.
+fn save_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+    text: &[(&str, String)],
+) -> NesResult<()> {
+    Image::from_bytes(width, height, pixels, PixelFormat::Rgba)
+        .and_then(|image| image.save(path))
+        .with_context(|| format!("failed to save screenshot {path:?}"))?;
+    embed_png_text(path, text)
+}
+
+/// Copies the PNG at `path` to the clipboard with the platform's clipboard tool.
+///
+/// # Errors
+///
+/// If the clipboard tool isn't installed or fails, an error is returned.
+fn copy_to_clipboard(path: &Path) -> NesResult<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        let path = path.to_string_lossy().replace('"', "\\\"");
+        let mut command = Command::new("osascript");
+        command.args([
+            "-e",
+            &format!("set the clipboard to (read (POSIX file \"{path}\") as «class PNGf»)"),
+        ]);
+        command
+    } else if cfg!(target_os = "windows") {
+        let path = path.to_string_lossy().replace('\'', "''");
+        let mut command = Command::new("powershell");
+        command.args([
+            "-NoProfile",
+            "-Command",
+            &format!(
+                "Add-Type -AssemblyName System.Windows.Forms,System.Drawing; \
+                [System.Windows.Forms.Clipboard]::SetImage([System.Drawing.Image]::FromFile('{path}'))"
+            ),
+        ]);
+        command
+    } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+        let mut command = Command::new("wl-copy");
+        command
+            .args(["--type", "image/png"])
+            .stdin(File::open(path).with_context(|| format!("failed to open {path:?}"))?);
+        command
+    } else {
+        let mut command = Command::new("xclip");
+        command
+            .args(["-selection", "clipboard", "-target", "image/png", "-i"])
+            .arg(path);
+        command
+    };
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .stdout(Stdio::null())
+        .status()
+        .with_context(|| format!("failed to start `{program}`. is it installed?"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("`{program}` exited with {status}"))
+    }
+}
+
+/// System palette indices of a frame of PPU output, dropping color emphasis.
+fn palette_indices(buffer: &[u16]) -> Vec<u8> {
+    buffer.iter().map(|&pixel| (pixel & 0x3F) as u8).collect()
+}
+
+/// The system palette as RGB triples.
+fn system_palette() -> Vec<u8> {
+    (0..64)
+        .flat_map(|color| {
+            let (red, green, blue) = Ppu::system_palette(color);
+            [red, green, blue]
+        })
+        .collect()
+}
+
+/// Path of the raw capture saved alongside `path`.
+fn raw_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().and_then(OsStr::to_str).unwrap_or_default();
+    path.with_file_name(format!("{stem}{RAW_SUFFIX}.png"))
+}
+
+impl Nes {
+    /// Game, frame number and video settings embedded in screenshots.
+    fn screenshot_metadata(&self) -> Vec<(&'static str, String)> {
+        let mut text = vec![("Software", format!("TetaNES {}", env!("CARGO_PKG_VERSION")))];
+        if let Some(name) = self
+            .control_deck
+            .loaded_rom()
+            .as_deref()
+            .and_then(|rom| Path::new(rom).file_stem())
+        {
+            text.push(("Title", name.to_string_lossy().into_owned()));
+        }
+        text.extend([
+            ("Creation Time", Local::now().to_rfc2822()),
+            ("Frame", self.control_deck.frame_number().to_string()),
+            ("Region", self.config.region.as_ref().to_string()),
+            ("Video Filter", self.config.filter.as_ref().to_string()),
+            (
+                "Color Filter",
+                self.config.color_filter.as_ref().to_string(),
+            ),
+            ("Scale", self.config.scale.to_string()),
+        ]);
+        text
+    }
+
+    /// Takes a screenshot of the window, or starts a burst if `Screenshot Burst` is more than one
+    /// frame.
+    pub(crate) fn save_screenshot(&mut self, s: &mut PixState) {
+        let dir = &self.config.screenshot_dir;
+        if !dir.exists() {
+            if let Err(err) = fs::create_dir_all(dir) {
+                log::error!("failed to create directory {dir:?}: {err:?}");
+                self.add_message("Failed to save screenshot");
+                return;
+            }
+        }
+        let name = format!(
+            "{}{}",
+            screenshot_prefix(self.control_deck.loaded_rom().as_deref()),
+            Local::now().format("%Y-%m-%d_at_%H_%M_%S")
+        );
+        if self.config.screenshot_burst > 1 && self.control_deck.loaded_rom().is_some() {
+            self.add_message(format!("Capturing {} frames", self.config.screenshot_burst));
+            self.screenshot_burst = Some(Burst {
+                name,
+                saved: 0,
+                count: self.config.screenshot_burst,
+            });
+            return;
+        }
+
+        let path = dir.join(&name).with_extension("png");
+        let text = self.screenshot_metadata();
+        let format = self.config.screenshot_format;
+        let mut result = if format.png() {
+            s.save_canvas(None, &path)
+                .context("failed to save screenshot")
+                .and_then(|()| embed_png_text(&path, &text))
+        } else {
+            Ok(())
+        };
+        if result.is_ok() {
+            result = self.save_extra_captures(&path, &text);
+        }
+        match result {
+            Ok(()) if format.png() => {
+                self.add_message(format!("{name}.png"));
+                self.copy_screenshot(&path);
+            }
+            Ok(()) => self.add_message(format!("{name}.idx")),
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message("Failed to save screenshot");
+            }
+        }
+    }
+
+    /// Saves palette indices and the unfiltered frame next to the screenshot at `path`, if
+    /// enabled.
+    fn save_extra_captures(&self, path: &Path, text: &[(&str, String)]) -> NesResult<()> {
+        if self.config.screenshot_format.indices() {
+            let idx = path.with_extension("idx");
+            let indices = palette_indices(self.control_deck.cpu().frame_buffer());
+            fs::write(&idx, indices).with_context(|| format!("failed to write {idx:?}"))?;
+            let pal = path.with_extension("pal");
+            fs::write(&pal, system_palette())
+                .with_context(|| format!("failed to write {pal:?}"))?;
+        }
+        if self.config.screenshot_raw {
+            let frame = self.control_deck.raw_frame();
+            save_png(
+                &raw_path(path),
+                frame.width(),
+                frame.height(),
+                frame.pixels(),
+                text,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn copy_screenshot(&mut self, path: &Path) {
+        if !self.config.screenshot_clipboard {
+            return;
+        }
+        if let Err(err) = copy_to_clipboard(path) {
+            log::error!("{err:?}");
+            self.add_message("Failed to copy screenshot to clipboard");
+        }
+    }
+
+    /// Saves the latest emulated frame if a screenshot burst is in progress.
+    pub(crate) fn capture_burst_frame(&mut self) {
+        let Some(mut burst) = self.screenshot_burst.take() else {
+            return;
+        };
+        let path =
+            self.config
+                .screenshot_dir
+                .join(format!("{}_{:03}.png", burst.name, burst.saved + 1));
+        let text = self.screenshot_metadata();
+        let png = self.config.screenshot_format.png();
+        let mut result = if png {
+            let frame = self.control_deck.frame();
+            save_png(&path, frame.width(), frame.height(), frame.pixels(), &text)
+        } else {
+            Ok(())
+        };
+        if result.is_ok() {
+            result = self.save_extra_captures(&path, &text);
+        }
+        if let Err(err) = result {
+            log::error!("{err:?}");
+            self.add_message("Failed to save screenshot");
+            return;
+        }
+        if png && burst.saved == 0 {
+            self.copy_screenshot(&path);
+        }
+        burst.saved += 1;
+        if burst.saved < burst.count {
+            self.screenshot_burst = Some(burst);
+        } else {
+            self.add_message(format!("Saved {} frames of {}", burst.count, burst.name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_png() -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend(chunk(b"IHDR", &[0x00; 13]));
+        png.extend(chunk(b"IDAT", &[0x01, 0x02]));
+        png.extend(chunk(b"IEND", &[]));
+        png
+    }
+
+    #[test]
+    fn png_text_before_image_data() {
+        let png = test_png();
+        let text = [("Title", "Zelda ゼルダ".to_string())];
+        let output = add_png_text(&png, &text).expect("valid png");
+
+        let ihdr_end = PNG_SIGNATURE.len() + 25;
+        assert_eq!(&output[..ihdr_end], &png[..ihdr_end]);
+        let itxt = text_chunk("Title", "Zelda ゼルダ");
+        assert_eq!(&output[ihdr_end..ihdr_end + itxt.len()], &itxt[..]);
+        assert_eq!(&output[ihdr_end + itxt.len()..], &png[ihdr_end..]);
+        assert_eq!(&itxt[4..8], b"iTXt");
+        assert_eq!(
+            u32::from_be_bytes([itxt[0], itxt[1], itxt[2], itxt[3]]) as usize,
+            itxt.len() - 12
+        );
+
+        assert!(add_png_text(&png[1..], &text).is_err());
+        assert!(add_png_text(PNG_SIGNATURE, &text).is_err());
+    }
+
+    #[test]
+    fn palette_index_export() {
+        // Emphasis bits are dropped
+        assert_eq!(
+            palette_indices(&[0x0F, 0x30, 0x1C0 | 0x16]),
+            [0x0F, 0x30, 0x16]
+        );
+        let palette = system_palette();
+        assert_eq!(palette.len(), 64 * 3);
+        let (red, green, blue) = Ppu::system_palette(0x16);
+        assert_eq!(&palette[0x16 * 3..0x16 * 3 + 3], &[red, green, blue]);
+    }
+
+    #[test]
+    fn raw_capture_path() {
+        assert_eq!(
+            raw_path(Path::new("shots/Game_Screen_Shot_2024_001.png")),
+            Path::new("shots/Game_Screen_Shot_2024_001_raw.png")
+        );
+    }
+}
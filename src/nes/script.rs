@@ -0,0 +1,125 @@
+//! Options for scripted runs started from the command line.
+//!
+//! A run can start from a save state file, record a replay from the first frame, and stop after
+//! a number of frames. With `exit_on_end`, `TetaNES` quits when the frame limit is reached or a
+//! replay finishes playing instead of pausing, so a script can wait for it to finish. Stop
+//! conditions pause the run when met, or quit with the condition's exit code with `exit_on_end`.
+
+use crate::{
+    cpu::Cpu,
+    nes::{filesystem::load_data, state::ReplayMode, Nes},
+    stop_condition::StopCondition,
+};
+use anyhow::Context;
+use pix_engine::prelude::*;
+use std::path::PathBuf;
+
+/// Options for a scripted run of the initial ROM.
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct ScriptOptions {
+    /// A save state file to load once the game starts.
+    pub state: Option<PathBuf>,
+    /// Starts recording a replay once the game starts.
+    pub record: bool,
+    /// Where to save the recorded replay, instead of a timestamped file.
+    pub output: Option<PathBuf>,
+    /// Frames to run before pausing.
+    pub frames: Option<u32>,
+    /// Quits instead of pausing when the frame limit is reached, when replay playback ends, or
+    /// when a stop condition is met.
+    pub exit_on_end: bool,
+    /// Conditions checked after every instruction that end the run.
+    pub stop_conditions: Vec<StopCondition>,
+}
+
+/// Progress of a scripted run.
+#[derive(Default, Debug, Clone)]
+#[must_use]
+pub(crate) struct Script {
+    pub(crate) options: ScriptOptions,
+    /// Frame number the run started on, once the game has started.
+    start_frame: Option<u32>,
+    playing_replay: bool,
+    /// Status code to exit with once the run quits.
+    pub(crate) exit_code: Option<i32>,
+}
+
+impl Nes {
+    /// Applies script options once the initial ROM is loaded. Options only apply to the first
+    /// game loaded.
+    pub(crate) fn start_script(&mut self) {
+        if self.script.start_frame.is_some() || self.control_deck.loaded_rom().is_none() {
+            return;
+        }
+        if let Some(state) = self.script.options.state.take() {
+            match load_data(&state).and_then(|data| {
+                bincode::deserialize::<Cpu>(&data)
+                    .with_context(|| format!("failed to deserialize save state {state:?}"))
+            }) {
+                Ok(cpu) => {
+                    self.control_deck.load_cpu(cpu);
+                    self.add_message(format!("Loaded {state:?}"));
+                }
+                Err(err) => {
+                    log::error!("{err:?}");
+                    self.add_message(format!("Failed to load {state:?}"));
+                }
+            }
+        }
+        if self.script.options.record {
+            self.start_replay();
+        }
+        self.script.playing_replay = self.replay.mode == ReplayMode::Playback;
+        self.script.start_frame = Some(self.control_deck.frame_number());
+    }
+
+    /// Key to save a replay recorded by the script under, if an output path was given.
+    pub(crate) fn script_replay_key(&mut self) -> Option<String> {
+        self.script
+            .options
+            .output
+            .take()
+            .map(|output| output.to_string_lossy().into_owned())
+    }
+
+    /// Pauses, or quits with its exit code, once a stop condition is met. Conditions only stop
+    /// the run once.
+    pub(crate) fn handle_stop_condition(&mut self, s: &mut PixState, condition: StopCondition) {
+        self.script.options.stop_conditions.clear();
+        if self.script.options.exit_on_end {
+            log::info!("Stopped on {condition}");
+            self.script.exit_code = Some(condition.exit_code);
+            s.quit();
+        } else {
+            self.pause_play();
+            self.add_message(format!("Stopped on {condition}"));
+        }
+    }
+
+    /// Pauses or quits once the frame limit is reached or replay playback ends.
+    pub(crate) fn check_script_end(&mut self, s: &mut PixState) {
+        let Some(start_frame) = self.script.start_frame else {
+            return;
+        };
+        let frames_run = self.control_deck.frame_number().saturating_sub(start_frame);
+        let limit_reached = self
+            .script
+            .options
+            .frames
+            .map_or(false, |frames| frames_run >= frames);
+        let replay_ended = self.script.playing_replay && self.replay.mode == ReplayMode::Off;
+        if !limit_reached && !replay_ended {
+            return;
+        }
+        self.script.options.frames = None;
+        self.script.playing_replay = false;
+        if self.script.options.exit_on_end {
+            log::info!("Finished after {frames_run} frames");
+            s.quit();
+        } else if limit_reached {
+            self.pause_play();
+            self.add_message(format!("Paused after {frames_run} frames"));
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Developer overlay showing the scroll position and any mid-frame scroll changes (raster
+//! splits), such as a status bar that doesn't scroll with the playfield.
+
+use crate::nes::{Nes, NES_FRAME_SRC};
+use pix_engine::prelude::*;
+
+/// Colors cycled through for each split so neighboring splits are easy to tell apart.
+const SPLIT_COLORS: [Color; 6] = [
+    Color::RED,
+    Color::LIME,
+    Color::CYAN,
+    Color::YELLOW,
+    Color::MAGENTA,
+    Color::ORANGE,
+];
+
+impl Nes {
+    pub(crate) fn toggle_scroll_overlay(&mut self) {
+        self.scroll_overlay = !self.scroll_overlay;
+        // The nametable viewer also outlines the scroll position
+        let enabled = self.scroll_overlay || self.nametable_viewer.is_some();
+        self.control_deck
+            .ppu_mut()
+            .set_scroll_splits_enabled(enabled);
+        self.add_message(if self.scroll_overlay {
+            "Scroll Overlay Enabled"
+        } else {
+            "Scroll Overlay Disabled"
+        });
+    }
+
+    /// Draws a line with the scroll origin at the top of the frame and at each scanline where
+    /// the scroll changed during the last frame.
+    pub(crate) fn render_scroll_overlay(&mut self, s: &mut PixState) -> PixResult<()> {
+        if !self.scroll_overlay {
+            return Ok(());
+        }
+        // Loading a save state replaces the PPU and stops recording
+        let ppu = self.control_deck.ppu_mut();
+        if !ppu.scroll_splits_enabled() {
+            ppu.set_scroll_splits_enabled(true);
+        }
+
+        let width = s.width()? as i32;
+        let scale = s.height()? as f32 / NES_FRAME_SRC.height() as f32;
+        s.push();
+        for (i, split) in self.control_deck.ppu().scroll_splits().iter().enumerate() {
+            let y = ((split.scanline as i32 - NES_FRAME_SRC.top()) as f32 * scale) as i32;
+            let y = y.max(0);
+            let color = SPLIT_COLORS[i % SPLIT_COLORS.len()];
+            s.stroke(color);
+            s.line([0, y, width, y])?;
+            s.stroke(None);
+            s.fill(color);
+            s.set_cursor_pos([4, y + 2]);
+            s.text(&format!(
+                "Line {}: X {} Y {}",
+                split.scanline, split.x, split.y
+            ))?;
+        }
+        s.pop();
+        Ok(())
+    }
+}
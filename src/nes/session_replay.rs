@@ -0,0 +1,246 @@
+//! Rolling replay of the session's input, so the last few minutes can be saved as a replay after
+//! something interesting happens.
+//!
+//! Input is recorded in segments that each start from a compressed save state. Segments older
+//! than the configured window are dropped, so a saved replay starts from the oldest kept segment
+//! and covers at least the last `session_replay_minutes`. Anything a replay can't reproduce, like
+//! loading a state, rewinding or resetting, starts the recording over from the current state.
+
+use crate::{
+    input::Slot,
+    nes::{
+        event::{Action, ActionEvent},
+        filesystem::{decode_data, encode_data},
+        state::Replay,
+        Nes,
+    },
+    NesResult,
+};
+use anyhow::Context;
+use std::collections::VecDeque;
+
+/// Emulated seconds between save states.
+const SEGMENT_SECONDS: f32 = 60.0;
+/// Memory kept for the session replay before the oldest segments are dropped, in bytes.
+const BUDGET: usize = 32 * 1024 * 1024;
+
+#[derive(Debug)]
+#[must_use]
+struct Segment {
+    start_frame: u32,
+    /// Compressed save state at `start_frame`.
+    state: Vec<u8>,
+    events: Vec<ActionEvent>,
+}
+
+impl Segment {
+    fn size(&self) -> usize {
+        self.state.len() + self.events.len() * std::mem::size_of::<ActionEvent>()
+    }
+}
+
+#[derive(Default, Debug)]
+#[must_use]
+pub(crate) struct SessionReplay {
+    segments: VecDeque<Segment>,
+    /// Frame the last update ended on, to catch loaded states.
+    last_frame: Option<u32>,
+}
+
+impl SessionReplay {
+    pub(crate) fn clear(&mut self) {
+        self.segments.clear();
+        self.last_frame = None;
+    }
+
+    /// Drops segments no longer needed to cover `window` frames before `frame`, or while over
+    /// `budget` bytes. The newest segment is always kept.
+    fn trim(&mut self, frame: u32, window: u32, budget: usize) {
+        while self.segments.len() > 1
+            && self.segments[1].start_frame.saturating_add(window) <= frame
+        {
+            self.segments.pop_front();
+        }
+        let mut size: usize = self.segments.iter().map(Segment::size).sum();
+        while self.segments.len() > 1 && size > budget {
+            if let Some(segment) = self.segments.pop_front() {
+                size -= segment.size();
+            }
+        }
+    }
+
+    fn start_frame(&self) -> Option<u32> {
+        self.segments.front().map(|segment| segment.start_frame)
+    }
+}
+
+/// Whether an action is game input that can be replayed. Anything else, like a reset or opening a
+/// menu, isn't part of the session replay.
+const fn is_input(action: Action) -> bool {
+    matches!(
+        action,
+        Action::Joypad(_) | Action::Turbo(_) | Action::ZapperTrigger | Action::ZeroAxis(_)
+    )
+}
+
+impl Nes {
+    /// Records an input event into the session replay, if enabled.
+    pub(crate) fn record_session_event(
+        &mut self,
+        slot: Slot,
+        action: Action,
+        pressed: bool,
+        repeat: bool,
+    ) {
+        if self.config.session_replay_minutes == 0 || !is_input(action) {
+            return;
+        }
+        let frame = self.control_deck.frame_number();
+        if let Some(segment) = self.session_replay.segments.back_mut() {
+            segment.events.push(ActionEvent {
+                frame,
+                slot,
+                action,
+                pressed,
+                repeat,
+            });
+        }
+    }
+
+    /// Starts a new segment when one is due and drops segments outside the window. Called after
+    /// emulating from `prev_frame` up to `frame`.
+    pub(crate) fn update_session_replay(&mut self, prev_frame: u32, frame: u32) {
+        let minutes = self.config.session_replay_minutes;
+        if minutes == 0 {
+            if !self.session_replay.segments.is_empty() {
+                self.session_replay.clear();
+            }
+            return;
+        }
+        let frame_rate = self.config.region.frame_rate();
+        let segment_frames = (SEGMENT_SECONDS * frame_rate) as u32;
+        let continuous = self.session_replay.last_frame == Some(prev_frame);
+        let due = self.session_replay.segments.back().map_or(true, |segment| {
+            frame.wrapping_sub(segment.start_frame) >= segment_frames
+        });
+        if !continuous {
+            self.session_replay.clear();
+        }
+        if !continuous || due {
+            if let Err(err) = self.push_session_segment(frame) {
+                log::error!("{err:?}");
+                self.config.session_replay_minutes = 0;
+                self.session_replay.clear();
+                return;
+            }
+        }
+        self.session_replay.last_frame = Some(frame);
+        let window = (minutes as f32 * 60.0 * frame_rate) as u32;
+        self.session_replay.trim(frame, window, BUDGET);
+    }
+
+    /// Starts the session replay over from the current state.
+    pub(crate) fn restart_session_replay(&mut self) {
+        self.session_replay.clear();
+    }
+
+    fn push_session_segment(&mut self, frame: u32) -> NesResult<()> {
+        let state = bincode::serialize(self.control_deck.cpu())
+            .context("failed to serialize session replay state")
+            .and_then(|data| encode_data(&data))?;
+        self.session_replay.segments.push_back(Segment {
+            start_frame: frame,
+            state,
+            events: vec![],
+        });
+        Ok(())
+    }
+
+    /// Saves the session replay as a replay file.
+    pub(crate) fn save_session_replay(&mut self) {
+        if self.config.session_replay_minutes == 0 {
+            self.add_message("Session replay disabled. You can enable it in the Config menu.");
+            return;
+        }
+        let Some(start_frame) = self.session_replay.start_frame() else {
+            self.add_message("Nothing recorded for the session replay yet");
+            return;
+        };
+        match self.write_session_replay() {
+            Ok(()) => {
+                let frames = self.control_deck.frame_number().wrapping_sub(start_frame);
+                let seconds = (frames as f32 / self.config.region.frame_rate()) as u32;
+                self.add_message(format!(
+                    "Saved the last {}:{:02} as a replay",
+                    seconds / 60,
+                    seconds % 60
+                ));
+            }
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message("Failed to save session replay");
+            }
+        }
+    }
+
+    fn write_session_replay(&mut self) -> NesResult<()> {
+        let Some(first) = self.session_replay.segments.front() else {
+            return Ok(());
+        };
+        let start = decode_data(&first.state).and_then(|data| {
+            bincode::deserialize(&data).context("failed to deserialize session replay state")
+        })?;
+        let mut replay = Replay {
+            start: Some(start),
+            // Replays are stored with the first event last
+            buffer: self
+                .session_replay
+                .segments
+                .iter()
+                .flat_map(|segment| segment.events.iter().copied())
+                .rev()
+                .collect(),
+            initial: Some(self.initial_state()),
+            ..Replay::default()
+        };
+        replay.lag_frames = replay.lag_frames_since_start(self.control_deck.cpu());
+        let key = self.new_replay_key("tetanes_session_%Y-%m-%d_at_%H.%M.%S");
+        self.write_replay(&key, &replay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start_frame: u32, size: usize) -> Segment {
+        Segment {
+            start_frame,
+            state: vec![0x00; size],
+            events: vec![],
+        }
+    }
+
+    #[test]
+    fn trim_to_window() {
+        let mut replay = SessionReplay::default();
+        replay.segments.extend([
+            segment(0, 10),
+            segment(100, 10),
+            segment(200, 10),
+            segment(300, 10),
+        ]);
+
+        // Frame 250 needs the segment from 100 to cover 150 frames back
+        replay.trim(250, 150, usize::MAX);
+        assert_eq!(replay.start_frame(), Some(100));
+
+        replay.trim(350, 150, usize::MAX);
+        assert_eq!(replay.start_frame(), Some(200));
+
+        replay.trim(350, 150, 15);
+        assert_eq!(replay.start_frame(), Some(300));
+        replay.trim(350, 150, 0);
+        assert_eq!(replay.segments.len(), 1, "the newest segment is kept");
+    }
+}
@@ -0,0 +1,306 @@
+//! Sound recording to WAV or FLAC, with optional per-channel stems for music ripping.
+//!
+//! Audio is captured per emulated frame like video recordings, so recordings play back at native
+//! speed. Samples are always written as WAV first, then converted by an external `ffmpeg` binary
+//! when FLAC is selected.
+
+use crate::{
+    audio::{wav::WavWriter, AudioMixer, NesAudioCallback},
+    nes::Nes,
+    NesResult,
+};
+use anyhow::{anyhow, Context};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const FFMPEG: &str = "ffmpeg";
+const AUDIO_SAMPLE_RATE: u32 = 48_000;
+const AUDIO_BUFFER_SIZE: usize = 8192;
+/// Stem names in the order channels are interleaved by `ControlDeck::channel_samples`.
+const STEMS: [&str; 6] = ["pulse1", "pulse2", "triangle", "noise", "dmc", "expansion"];
+
+/// File format used for sound recordings.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub(crate) enum SoundFormat {
+    #[default]
+    Wav,
+    Flac,
+}
+
+impl SoundFormat {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[Self::Wav, Self::Flac]
+    }
+}
+
+impl AsRef<str> for SoundFormat {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Wav => "WAV",
+            Self::Flac => "FLAC",
+        }
+    }
+}
+
+impl From<usize> for SoundFormat {
+    fn from(value: usize) -> Self {
+        if value == 1 {
+            Self::Flac
+        } else {
+            Self::Wav
+        }
+    }
+}
+
+/// WAV path for the mixed output or a named stem of the recording `name`.
+fn track_path(dir: &Path, name: &str, stem: Option<&str>) -> PathBuf {
+    match stem {
+        Some(stem) => dir.join(format!("{name}_{stem}.wav")),
+        None => dir.join(format!("{name}.wav")),
+    }
+}
+
+/// A single resampled output file.
+struct Track {
+    path: PathBuf,
+    wav: WavWriter<BufWriter<File>>,
+    mixer: AudioMixer,
+    samples: NesAudioCallback,
+    buffer: Vec<f32>,
+}
+
+impl Track {
+    fn create(path: PathBuf, sample_rate: f32) -> NesResult<Self> {
+        let wav = WavWriter::new(
+            BufWriter::new(
+                File::create(&path).with_context(|| format!("failed to create file {path:?}"))?,
+            ),
+            AUDIO_SAMPLE_RATE,
+            1,
+        )?;
+        let mut mixer = AudioMixer::new(sample_rate, AUDIO_SAMPLE_RATE as f32, AUDIO_BUFFER_SIZE);
+        let samples = mixer.open_callback()?;
+        Ok(Self {
+            path,
+            wav,
+            mixer,
+            samples,
+            buffer: Vec::with_capacity(AUDIO_BUFFER_SIZE),
+        })
+    }
+
+    fn push<I: IntoIterator<Item = f32>>(&mut self, samples: I) -> NesResult<()> {
+        self.buffer.clear();
+        self.buffer.extend(samples);
+        self.mixer.consume(&self.buffer, false, 0.0);
+        self.buffer.resize(self.samples.len(), 0.0);
+        self.samples.read(&mut self.buffer);
+        self.wav.write_samples(&self.buffer)
+    }
+
+    /// Finishes the WAV file, converting it to `format` if needed.
+    fn finish(self, format: SoundFormat) -> NesResult<PathBuf> {
+        self.wav.finish()?;
+        match format {
+            SoundFormat::Wav => Ok(self.path),
+            SoundFormat::Flac => {
+                let output = self.path.with_extension("flac");
+                let status = Command::new(FFMPEG)
+                    .args(["-y", "-loglevel", "error", "-i"])
+                    .arg(&self.path)
+                    .args(["-c:a", "flac"])
+                    .arg(&output)
+                    .status()
+                    .context("failed to start `ffmpeg`. is it installed?")?;
+                if !status.success() {
+                    return Err(anyhow!("flac encoder exited with {status}"));
+                }
+                let _ = fs::remove_file(&self.path);
+                Ok(output)
+            }
+        }
+    }
+}
+
+/// An in-progress sound recording.
+#[must_use]
+pub(crate) struct SoundRecorder {
+    format: SoundFormat,
+    mix: Track,
+    stems: Vec<Track>,
+}
+
+impl SoundRecorder {
+    /// Starts a new recording of the mixed output and, if `stems` is set, each audio channel.
+    ///
+    /// # Errors
+    ///
+    /// If the output directory or files can't be created, an error is returned.
+    pub(crate) fn start<P: AsRef<Path>>(
+        dir: P,
+        format: SoundFormat,
+        stems: bool,
+        sample_rate: f32,
+    ) -> NesResult<Self> {
+        let dir = dir.as_ref();
+        if !dir.exists() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create directory {dir:?}"))?;
+        }
+        let name = Local::now()
+            .format("tetanes_%Y-%m-%d_at_%H-%M-%S")
+            .to_string();
+        let mix = Track::create(track_path(dir, &name, None), sample_rate)?;
+        let stems = if stems {
+            STEMS
+                .iter()
+                .map(|stem| Track::create(track_path(dir, &name, Some(stem)), sample_rate))
+                .collect::<NesResult<_>>()?
+        } else {
+            vec![]
+        };
+        Ok(Self { format, mix, stems })
+    }
+
+    #[inline]
+    pub(crate) fn has_stems(&self) -> bool {
+        !self.stems.is_empty()
+    }
+
+    /// Resamples and writes the mixed output and interleaved per-channel samples.
+    ///
+    /// # Errors
+    ///
+    /// If the samples fail to write to disk, an error is returned.
+    pub(crate) fn push_samples(&mut self, samples: &[f32], channels: &[f32]) -> NesResult<()> {
+        self.mix.push(samples.iter().copied())?;
+        for (i, stem) in self.stems.iter_mut().enumerate() {
+            stem.push(channels.iter().skip(i).step_by(STEMS.len()).copied())?;
+        }
+        Ok(())
+    }
+
+    /// Finishes all files, returning the path of the mixed output.
+    ///
+    /// # Errors
+    ///
+    /// If any file fails to finish or convert, an error is returned.
+    pub(crate) fn finish(self) -> NesResult<PathBuf> {
+        let Self { format, mix, stems } = self;
+        for stem in stems {
+            stem.finish(format)?;
+        }
+        mix.finish(format)
+    }
+}
+
+impl std::fmt::Debug for SoundRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SoundRecorder")
+            .field("format", &self.format)
+            .field("output", &self.mix.path)
+            .field("stems", &self.stems.len())
+            .finish()
+    }
+}
+
+impl Nes {
+    pub(crate) fn toggle_sound_recording(&mut self) {
+        if self.sound_recorder.is_some() {
+            self.stop_sound_recording();
+        } else {
+            self.start_sound_recording();
+        }
+    }
+
+    pub(crate) fn start_sound_recording(&mut self) {
+        if self.control_deck.loaded_rom().is_none() {
+            return;
+        }
+        match SoundRecorder::start(
+            &self.config.sound_recording_dir,
+            self.config.sound_recording_format,
+            self.config.sound_recording_stems,
+            self.control_deck.sample_rate(),
+        ) {
+            Ok(recorder) => {
+                self.sound_recorder = Some(recorder);
+                self.update_channel_samples();
+                self.add_message("Sound Recording Started");
+            }
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message("Failed to start sound recording");
+            }
+        }
+    }
+
+    pub(crate) fn stop_sound_recording(&mut self) {
+        if let Some(recorder) = self.sound_recorder.take() {
+            self.update_channel_samples();
+            match recorder.finish() {
+                Ok(output) => self.add_message(format!("Saved sound recording {output:?}")),
+                Err(err) => {
+                    log::error!("{err:?}");
+                    self.add_message("Failed to save sound recording");
+                }
+            }
+        }
+    }
+
+    /// Captures the audio generated since the last update.
+    pub(crate) fn record_sound_samples(&mut self) {
+        if let Some(ref mut recorder) = self.sound_recorder {
+            let result = recorder.push_samples(
+                self.control_deck.audio_samples(),
+                self.control_deck.channel_samples(),
+            );
+            if let Err(err) = result {
+                log::error!("{err:?}");
+                self.add_message("Sound recording failed");
+                self.sound_recorder = None;
+            }
+            // Loading a save state replaces the CPU and drops the channel tap
+            self.update_channel_samples();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn track_paths_are_distinct() {
+        let dir = Path::new("recordings");
+        let name = "tetanes_2024-01-02_at_03-04-05";
+        let mix = track_path(dir, name, None);
+        assert_eq!(
+            mix,
+            Path::new("recordings/tetanes_2024-01-02_at_03-04-05.wav")
+        );
+
+        let mut paths = HashSet::new();
+        paths.insert(mix);
+        for stem in STEMS {
+            assert!(paths.insert(track_path(dir, name, Some(stem))));
+        }
+        assert_eq!(
+            track_path(dir, name, Some("dmc")),
+            Path::new("recordings/tetanes_2024-01-02_at_03-04-05_dmc.wav")
+        );
+        // The seconds must survive in the file name
+        assert_ne!(
+            track_path(dir, "tetanes_2024-01-02_at_03-04-06", None),
+            track_path(dir, name, None)
+        );
+    }
+}
@@ -0,0 +1,693 @@
+//! SRAM editor window for cartridge PRG-RAM, for hacking save files and testing how games handle
+//! battery-backed RAM.
+//!
+//! PRG-RAM is shown at its `$6000` CPU addresses, with bytes the game wrote in the last second in
+//! red. Click a byte to select it, or Shift-click another to select a range, then write or fill
+//! bytes, or export the range to a file and import it back later. Named ranges are bookmarked per
+//! game in `sram_bookmarks/<rom name>.json` under the config directory.
+
+use crate::{
+    common::config_dir,
+    debugger::{console::parse_bytes, Address},
+    nes::Nes,
+    NesResult,
+};
+use anyhow::{anyhow, Context};
+use pix_engine::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsStr,
+    fs::{self, File},
+    io::{BufReader, BufWriter},
+    ops::{Range, RangeInclusive},
+    path::PathBuf,
+};
+
+const PADDING: i32 = 10;
+/// CPU address PRG-RAM is mapped to.
+const SRAM_START: usize = 0x6000;
+const BYTES_PER_ROW: usize = 16;
+/// Rows shown in the hex view at once.
+const ROWS: usize = 16;
+const ADDR_WIDTH: i32 = 64;
+const CELL_WIDTH: i32 = 24;
+const GRID_WIDTH: i32 = ADDR_WIDTH + BYTES_PER_ROW as i32 * CELL_WIDTH;
+const BOOKMARKS_X: i32 = 2 * PADDING + GRID_WIDTH + 40;
+const BOOKMARKS_WIDTH: i32 = 300;
+/// Frames a byte written by the game stays highlighted.
+const WRITE_HIGHLIGHT_FRAMES: u8 = 60;
+
+/// A named range of PRG-RAM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub(crate) struct SramBookmark {
+    pub(crate) name: String,
+    /// First PRG-RAM offset.
+    pub(crate) start: usize,
+    /// Last PRG-RAM offset, inclusive.
+    pub(crate) end: usize,
+}
+
+impl SramBookmark {
+    fn range(&self) -> RangeInclusive<usize> {
+        self.start..=self.end
+    }
+}
+
+/// Finds the bytes the game writes each frame.
+#[derive(Default, Debug)]
+struct WriteTracker {
+    /// PRG-RAM as of the last frame.
+    previous: Vec<u8>,
+    /// Frames left to highlight each written byte.
+    written: Vec<u8>,
+    last_frame: Option<u32>,
+}
+
+impl WriteTracker {
+    /// Highlights bytes that changed since the last frame. Returns `true` if PRG-RAM changed size,
+    /// e.g. when another game is loaded.
+    fn update(&mut self, sram: &[u8], frame: u32) -> bool {
+        if self.last_frame == Some(frame) {
+            return false;
+        }
+        self.last_frame = Some(frame);
+        if self.previous.len() != sram.len() {
+            self.previous = sram.to_vec();
+            self.written = vec![0; sram.len()];
+            return true;
+        }
+        for ((previous, written), &byte) in self
+            .previous
+            .iter_mut()
+            .zip(self.written.iter_mut())
+            .zip(sram)
+        {
+            if *previous == byte {
+                *written = written.saturating_sub(1);
+            } else {
+                *previous = byte;
+                *written = WRITE_HIGHLIGHT_FRAMES;
+            }
+        }
+        false
+    }
+
+    /// Ignores changes made by the editor itself.
+    fn edited(&mut self, sram: &[u8]) {
+        if self.previous.len() == sram.len() {
+            self.previous.copy_from_slice(sram);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct SramEditor {
+    window_id: WindowId,
+    bookmarks: Vec<SramBookmark>,
+    /// First row shown in the hex view.
+    top_row: usize,
+    /// Selected PRG-RAM offsets.
+    selection: RangeInclusive<usize>,
+    writes: WriteTracker,
+    range_text: String,
+    bytes_text: String,
+    path: String,
+    bookmark_name: String,
+    error: Option<String>,
+}
+
+impl SramEditor {
+    fn new(window_id: WindowId) -> Self {
+        Self {
+            window_id,
+            bookmarks: vec![],
+            top_row: 0,
+            selection: 0..=0,
+            writes: WriteTracker::default(),
+            range_text: format_range(&(0..=0)),
+            bytes_text: String::new(),
+            path: String::new(),
+            bookmark_name: String::new(),
+            error: None,
+        }
+    }
+
+    pub(crate) const fn window_id(&self) -> WindowId {
+        self.window_id
+    }
+
+    /// Clears the selection and write highlights, e.g. when another game is loaded.
+    fn reset(&mut self) {
+        self.top_row = 0;
+        self.selection = 0..=0;
+        self.range_text = format_range(&self.selection);
+        self.writes = WriteTracker::default();
+    }
+
+    /// Selects a range and scrolls to it if it's out of view.
+    fn select(&mut self, range: RangeInclusive<usize>, len: usize) {
+        let row = range.start() / BYTES_PER_ROW;
+        if row < self.top_row || row >= self.top_row + ROWS {
+            let max_top = ((len + BYTES_PER_ROW - 1) / BYTES_PER_ROW).saturating_sub(ROWS);
+            self.top_row = row.min(max_top);
+        }
+        self.range_text = format_range(&range);
+        self.selection = range;
+    }
+}
+
+/// Formats PRG-RAM offsets as CPU addresses, e.g. `$6000-$60FF`.
+fn format_range(range: &RangeInclusive<usize>) -> String {
+    if range.start() == range.end() {
+        format!("${:04X}", SRAM_START + range.start())
+    } else {
+        format!(
+            "${:04X}-${:04X}",
+            SRAM_START + range.start(),
+            SRAM_START + range.end()
+        )
+    }
+}
+
+/// Parses a CPU address or inclusive range like `$6000` or `6000-60FF` into PRG-RAM offsets.
+fn parse_range(text: &str, len: usize) -> Result<RangeInclusive<usize>, String> {
+    let (start, end) = match text.parse::<Address>() {
+        Ok(Address::Addr(addr)) => (addr, addr),
+        Ok(Address::AddrRange(range)) => (*range.start(), *range.end()),
+        Err(_) => return Err(format!("Invalid address or range `{}`", text.trim())),
+    };
+    let offset = |addr: u16| {
+        usize::from(addr)
+            .checked_sub(SRAM_START)
+            .filter(|&offset| offset < len)
+            .ok_or_else(|| {
+                format!(
+                    "${addr:04X} is outside of PRG-RAM at $6000-${:04X}",
+                    SRAM_START + len.saturating_sub(1)
+                )
+            })
+    };
+    Ok(offset(start)?..=offset(end)?)
+}
+
+/// Offsets a file of `file_len` bytes is imported to. With a single byte selected, as much of the
+/// file as fits is imported from there, otherwise the import stops at the end of the selection.
+fn import_range(
+    selection: &RangeInclusive<usize>,
+    sram_len: usize,
+    file_len: usize,
+) -> Range<usize> {
+    let start = *selection.start();
+    let end = if selection.start() == selection.end() {
+        sram_len
+    } else {
+        (selection.end() + 1).min(sram_len)
+    };
+    start..(start + file_len).min(end)
+}
+
+impl Nes {
+    pub(crate) fn toggle_sram_editor(&mut self, s: &mut PixState) -> PixResult<()> {
+        match self.sram_editor {
+            None => {
+                let window_id = s
+                    .window()
+                    .dimensions(
+                        (BOOKMARKS_X + BOOKMARKS_WIDTH + PADDING) as u32,
+                        ROWS as u32 * 22 + 260,
+                    )
+                    .title("SRAM Editor")
+                    .position(10, 10)
+                    .build()?;
+                self.sram_editor = Some(SramEditor::new(window_id));
+                self.load_sram_bookmarks();
+            }
+            Some(ref editor) => {
+                s.close_window(editor.window_id())?;
+                self.close_sram_editor();
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn close_sram_editor(&mut self) {
+        self.sram_editor = None;
+    }
+
+    /// Returns the path where PRG-RAM bookmarks for the loaded game are stored.
+    pub(crate) fn sram_bookmarks_path(&self) -> NesResult<PathBuf> {
+        match self.control_deck.loaded_rom() {
+            Some(ref rom) => PathBuf::from(rom)
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .map_or_else(
+                    || {
+                        Err(anyhow!(
+                            "failed to create sram bookmarks path for `{rom:?}`"
+                        ))
+                    },
+                    |name| {
+                        Ok(config_dir()
+                            .join("sram_bookmarks")
+                            .join(name)
+                            .with_extension("json"))
+                    },
+                ),
+            None => Err(anyhow!("no rom is loaded")),
+        }
+    }
+
+    /// Loads the PRG-RAM bookmarks for the loaded game while the SRAM editor is open.
+    pub(crate) fn load_sram_bookmarks(&mut self) {
+        if self.sram_editor.is_none() || self.control_deck.loaded_rom().is_none() {
+            return;
+        }
+        let bookmarks = match self.sram_bookmarks_path().and_then(|path| {
+            if !path.exists() {
+                return Ok(None);
+            }
+            let file = File::open(&path).with_context(|| format!("failed to open {path:?}"))?;
+            serde_json::from_reader(BufReader::new(file))
+                .with_context(|| format!("failed to parse {path:?}"))
+                .map(Some)
+        }) {
+            Ok(bookmarks) => bookmarks.unwrap_or_default(),
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message("Failed to load SRAM bookmarks");
+                vec![]
+            }
+        };
+        if let Some(ref mut editor) = self.sram_editor {
+            editor.bookmarks = bookmarks;
+            editor.reset();
+        }
+    }
+
+    fn save_sram_bookmarks(&self) -> NesResult<()> {
+        let Some(ref editor) = self.sram_editor else {
+            return Ok(());
+        };
+        let path = self.sram_bookmarks_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create directory {dir:?}"))?;
+        }
+        let file = File::create(&path).with_context(|| format!("failed to create {path:?}"))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), &editor.bookmarks)
+            .context("failed to serialize sram bookmarks")
+    }
+
+    pub(crate) fn render_sram_editor(&mut self, s: &mut PixState) -> PixResult<()> {
+        let Some(ref editor) = self.sram_editor else {
+            return Ok(());
+        };
+        s.set_window_target(editor.window_id())?;
+        s.clear()?;
+        s.fill(Color::WHITE);
+        s.stroke(None);
+        let result = self.render_sram_controls(s);
+        s.reset_window_target();
+        result
+    }
+
+    fn render_sram_controls(&mut self, s: &mut PixState) -> PixResult<()> {
+        let battery_backed = self.control_deck.cart_battery_backed();
+        let frame = self.control_deck.frame_number();
+        let Some(ref mut editor) = self.sram_editor else {
+            return Ok(());
+        };
+        let sram = self.control_deck.sram_mut();
+        let len = sram.len();
+        if len == 0 {
+            s.text("The loaded cartridge has no PRG-RAM")?;
+            return Ok(());
+        }
+        if editor.writes.update(sram, frame) || *editor.selection.end() >= len {
+            editor.select(0..=0, len);
+        }
+
+        s.text(format!(
+            "PRG-RAM: {len} bytes at $6000-${:04X}, {}",
+            SRAM_START + len - 1,
+            if battery_backed {
+                "battery-backed"
+            } else {
+                "not battery-backed"
+            }
+        ))?;
+        let mut save = false;
+        let mut reload = false;
+        if battery_backed {
+            s.same_line(None);
+            save = s.button("Save SRAM")?;
+            s.same_line(None);
+            reload = s.button("Reload SRAM")?;
+            s.same_line(None);
+            s.help_marker(
+                "Save SRAM stores PRG-RAM as the game's save now, instead of when the game is \
+                closed. Reload SRAM replaces PRG-RAM with the stored save.",
+            )?;
+        }
+
+        // Hex view
+        let line_height = s.theme().font_size as i32 + 6;
+        let rows = (len + BYTES_PER_ROW - 1) / BYTES_PER_ROW;
+        let grid = rect![
+            PADDING,
+            s.cursor_pos().y() + 4,
+            GRID_WIDTH,
+            ROWS as i32 * line_height
+        ];
+        let m = s.mouse_pos();
+        let hovered = (s.focused_window(editor.window_id)
+            && grid.contains(m)
+            && m.x() >= grid.x() + ADDR_WIDTH)
+            .then(|| {
+                let col = ((m.x() - grid.x() - ADDR_WIDTH) / CELL_WIDTH) as usize;
+                let row = ((m.y() - grid.y()) / line_height) as usize;
+                (editor.top_row + row) * BYTES_PER_ROW + col
+            })
+            .filter(|&offset| offset < len);
+        if let Some(offset) = hovered {
+            if s.mouse_clicked(Mouse::Left) {
+                let range = if s.keymod_down(KeyMod::SHIFT) {
+                    let anchor = *editor.selection.start();
+                    anchor.min(offset)..=anchor.max(offset)
+                } else {
+                    offset..=offset
+                };
+                editor.select(range, len);
+            }
+        }
+        s.push();
+        for row in 0..ROWS {
+            let offset = (editor.top_row + row) * BYTES_PER_ROW;
+            if offset >= len {
+                break;
+            }
+            let y = grid.y() + row as i32 * line_height;
+            s.fill(Color::GRAY);
+            s.set_cursor_pos([grid.x(), y]);
+            s.text(format!("${:04X}", SRAM_START + offset))?;
+            let end = (offset + BYTES_PER_ROW).min(len);
+            for (col, &byte) in sram[offset..end].iter().enumerate() {
+                let offset = offset + col;
+                let x = grid.x() + ADDR_WIDTH + col as i32 * CELL_WIDTH;
+                let selected = editor.selection.contains(&offset);
+                if selected || hovered == Some(offset) {
+                    s.fill(if selected {
+                        rgb!(50, 60, 140)
+                    } else {
+                        rgb!(60)
+                    });
+                    s.rect([x - 3, y, CELL_WIDTH - 2, line_height - 2])?;
+                }
+                s.fill(if editor.writes.written[offset] > 0 {
+                    Color::RED
+                } else if editor
+                    .bookmarks
+                    .iter()
+                    .any(|bookmark| bookmark.range().contains(&offset))
+                {
+                    rgb!(120, 200, 255)
+                } else {
+                    Color::WHITE
+                });
+                s.set_cursor_pos([x, y]);
+                s.text(format!("{byte:02X}"))?;
+            }
+        }
+        s.pop();
+        s.set_cursor_pos([PADDING, grid.bottom() + 4]);
+
+        if s.button("Prev Page")? {
+            editor.top_row = editor.top_row.saturating_sub(ROWS);
+        }
+        s.same_line(None);
+        if s.button("Next Page")? {
+            editor.top_row = (editor.top_row + ROWS).min(rows.saturating_sub(ROWS));
+        }
+        s.same_line(None);
+        s.text(format!(
+            "Selected: {} ({} bytes)",
+            format_range(&editor.selection),
+            editor.selection.end() - editor.selection.start() + 1
+        ))?;
+
+        s.next_width(200);
+        s.text_field("Range##sram", &mut editor.range_text)?;
+        s.same_line(None);
+        if s.button("Select")? {
+            match parse_range(&editor.range_text, len) {
+                Ok(range) => {
+                    editor.select(range, len);
+                    editor.error = None;
+                }
+                Err(err) => editor.error = Some(err),
+            }
+        }
+        s.same_line(None);
+        s.help_marker(
+            "An address or range like $6000 or 6000-60FF. Click a byte to select it, or \
+            Shift-click another byte to select the bytes between them.",
+        )?;
+
+        s.next_width(200);
+        s.text_field("Bytes (hex)##sram", &mut editor.bytes_text)?;
+        s.same_line(None);
+        let write = s.button("Write")?;
+        s.same_line(None);
+        let fill = s.button("Fill")?;
+        s.same_line(None);
+        s.help_marker(
+            "Write stores the bytes from the start of the selection. Fill repeats them across the \
+            whole selection.",
+        )?;
+        if write || fill {
+            match parse_bytes(&editor.bytes_text) {
+                Ok(bytes) => {
+                    let start = *editor.selection.start();
+                    let range = if write {
+                        start..(start + bytes.len()).min(len)
+                    } else {
+                        start..editor.selection.end() + 1
+                    };
+                    for (byte, &value) in sram[range].iter_mut().zip(bytes.iter().cycle()) {
+                        *byte = value;
+                    }
+                    editor.writes.edited(sram);
+                    editor.error = None;
+                }
+                Err(err) => editor.error = Some(err),
+            }
+        }
+
+        let mut message = None;
+        s.next_width(300);
+        s.text_field("File##sram", &mut editor.path)?;
+        s.same_line(None);
+        let export = s.button("Export")?;
+        s.same_line(None);
+        let import = s.button("Import")?;
+        s.same_line(None);
+        s.help_marker(
+            "Export saves the selected bytes to the file. Import loads the file into the \
+            selection, or from a single selected byte to the end of PRG-RAM.",
+        )?;
+        let path = PathBuf::from(editor.path.trim());
+        if export {
+            let bytes = &sram[editor.selection.clone()];
+            match fs::write(&path, bytes).with_context(|| format!("failed to write {path:?}")) {
+                Ok(()) => {
+                    editor.error = None;
+                    message = Some(format!(
+                        "Exported {} to {}",
+                        format_range(&editor.selection),
+                        path.display()
+                    ));
+                }
+                Err(err) => {
+                    log::error!("{err:?}");
+                    editor.error = Some(err.to_string());
+                }
+            }
+        } else if import {
+            match fs::read(&path).with_context(|| format!("failed to read {path:?}")) {
+                Ok(data) => {
+                    let range = import_range(&editor.selection, len, data.len());
+                    let count = range.len();
+                    sram[range.clone()].copy_from_slice(&data[..count]);
+                    editor.writes.edited(sram);
+                    editor.error = None;
+                    message = Some(match count {
+                        0 => format!("{} is empty", path.display()),
+                        _ if count < data.len() => format!(
+                            "Imported {count} of {} bytes to {}",
+                            data.len(),
+                            format_range(&(range.start..=range.end - 1))
+                        ),
+                        _ => format!(
+                            "Imported {} to {}",
+                            path.display(),
+                            format_range(&(range.start..=range.end - 1))
+                        ),
+                    });
+                }
+                Err(err) => {
+                    log::error!("{err:?}");
+                    editor.error = Some(err.to_string());
+                }
+            }
+        }
+
+        if let Some(ref err) = editor.error {
+            s.push();
+            s.fill(Color::RED);
+            s.text(err)?;
+            s.pop();
+        }
+
+        // Bookmarks
+        let mut bookmarks_changed = false;
+        s.set_cursor_pos([BOOKMARKS_X, PADDING]);
+        s.text("Bookmarks:")?;
+        let mut remove = None;
+        let mut go_to = None;
+        for (i, bookmark) in editor.bookmarks.iter().enumerate() {
+            s.set_cursor_pos([BOOKMARKS_X, PADDING + (i as i32 + 1) * (line_height + 8)]);
+            if s.button(format!("Go##bookmark{i}"))? {
+                go_to = Some(bookmark.range());
+            }
+            s.same_line(None);
+            if s.button(format!("Remove##bookmark{i}"))? {
+                remove = Some(i);
+            }
+            s.same_line(None);
+            s.text(format!(
+                "{}  {}",
+                bookmark.name,
+                format_range(&bookmark.range())
+            ))?;
+        }
+        if let Some(range) = go_to {
+            editor.select(range, len);
+        }
+        if let Some(i) = remove {
+            editor.bookmarks.remove(i);
+            bookmarks_changed = true;
+        }
+        let y = PADDING + (editor.bookmarks.len() as i32 + 1) * (line_height + 8);
+        s.set_cursor_pos([BOOKMARKS_X, y]);
+        s.next_width(160);
+        s.text_field("Name##bookmark", &mut editor.bookmark_name)?;
+        s.set_cursor_pos([BOOKMARKS_X, y + line_height + 12]);
+        if s.button("Bookmark Selection")? {
+            let name = editor.bookmark_name.trim();
+            if name.is_empty() {
+                editor.error = Some("Enter a name for the bookmark".to_string());
+            } else {
+                editor.bookmarks.push(SramBookmark {
+                    name: name.to_string(),
+                    start: *editor.selection.start(),
+                    end: *editor.selection.end(),
+                });
+                editor.bookmark_name.clear();
+                editor.error = None;
+                bookmarks_changed = true;
+            }
+        }
+
+        if bookmarks_changed {
+            if let Err(err) = self.save_sram_bookmarks() {
+                log::error!("{err:?}");
+                self.add_message("Failed to save SRAM bookmarks");
+            }
+        }
+        if let Some(message) = message {
+            self.add_message(message);
+        }
+        if save {
+            match self.save_sram() {
+                Ok(()) => self.add_message("Saved SRAM"),
+                Err(err) => {
+                    log::error!("{err:?}");
+                    self.add_message("Failed to save SRAM");
+                }
+            }
+        } else if reload {
+            match self.load_sram() {
+                Ok(()) => self.add_message("Reloaded SRAM"),
+                Err(err) => {
+                    log::error!("{err:?}");
+                    self.add_message("Failed to reload SRAM");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_range("$6000", 0x2000), Ok(0..=0));
+        assert_eq!(parse_range("6100-60F0", 0x2000), Ok(0x00F0..=0x0100));
+        assert_eq!(parse_range(" 7FFF ", 0x2000), Ok(0x1FFF..=0x1FFF));
+        assert!(parse_range("5FFF", 0x2000).is_err());
+        assert!(parse_range("6000-8000", 0x2000).is_err());
+        assert!(parse_range("zz", 0x2000).is_err());
+        assert_eq!(format_range(&(0x00F0..=0x0100)), "$60F0-$6100");
+    }
+
+    #[test]
+    fn parses_bytes() {
+        assert_eq!(parse_bytes("DE AD beef"), Ok(vec![0xDE, 0xAD, 0xBE, 0xEF]));
+        assert_eq!(parse_bytes("00"), Ok(vec![0x00]));
+        assert_eq!(parse_bytes("$DE,$AD"), Ok(vec![0xDE, 0xAD]));
+        assert!(parse_bytes("").is_err());
+        assert!(parse_bytes("ABC").is_err());
+        assert!(parse_bytes("GG").is_err());
+        assert!(parse_bytes("éé").is_err());
+    }
+
+    #[test]
+    fn import_ranges() {
+        // A single byte imports as much as fits
+        assert_eq!(import_range(&(0x10..=0x10), 0x2000, 0x100), 0x10..0x110);
+        assert_eq!(
+            import_range(&(0x1FF0..=0x1FF0), 0x2000, 0x100),
+            0x1FF0..0x2000
+        );
+        // A range limits the import
+        assert_eq!(import_range(&(0x10..=0x1F), 0x2000, 0x100), 0x10..0x20);
+        assert_eq!(import_range(&(0x10..=0x1F), 0x2000, 4), 0x10..0x14);
+    }
+
+    #[test]
+    fn tracks_game_writes() {
+        let mut writes = WriteTracker::default();
+        let mut sram = vec![0; 32];
+        assert!(writes.update(&sram, 1));
+        assert!(writes.written.iter().all(|&frames| frames == 0));
+        sram[5] = 0xFF;
+        // Nothing changes until the next frame
+        assert!(!writes.update(&sram, 1));
+        assert_eq!(writes.written[5], 0);
+        writes.update(&sram, 2);
+        assert_eq!(writes.written[5], WRITE_HIGHLIGHT_FRAMES);
+        writes.update(&sram, 3);
+        assert_eq!(writes.written[5], WRITE_HIGHLIGHT_FRAMES - 1);
+        // Edits made in the editor aren't highlighted
+        sram[6] = 0xFF;
+        writes.edited(&sram);
+        writes.update(&sram, 4);
+        assert_eq!(writes.written[6], 0);
+        assert!(writes.update(&[0; 64], 5));
+        assert_eq!(writes.written.len(), 64);
+    }
+}
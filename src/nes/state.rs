@@ -1,19 +1,26 @@
 use crate::{
-    common::config_dir,
+    common::{Kind, Reset},
     cpu::Cpu,
     nes::{
         event::ActionEvent,
-        filesystem::{decode_data, encode_data, load_data, save_data},
+        filesystem::{decode_data, encode_data},
+        initial_state::InitialState,
+        legacy,
         menu::Menu,
+        persistence::DataKind,
+        replay_controls::ReplayGreenzone,
+        tutorial::TutorialEvent,
         Mode, Nes,
     },
     NesError, NesResult,
 };
 use anyhow::{anyhow, Context};
-use chrono::{DateTime, Local};
 use pix_engine::prelude::{PixResult, PixState};
 use serde::{Deserialize, Serialize};
-use std::{ffi::OsStr, path::PathBuf};
+use std::{cmp::Ordering, ffi::OsStr, fmt, path::PathBuf};
+
+/// Number of volatile quick save slots. These are kept in memory only and are lost on exit.
+pub(crate) const QUICK_SLOT_COUNT: usize = 4;
 
 /// Represents which mode the emulator is in for the Replay feature.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
@@ -23,12 +30,41 @@ pub(crate) enum ReplayMode {
     Playback,
 }
 
+/// A named marker at a specific frame of a replay.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub(crate) struct ReplayBookmark {
+    pub(crate) frame: u32,
+    pub(crate) name: String,
+    pub(crate) note: String,
+}
+
+/// A recorded replay. The binary replay format serializes fields in order, so changing them needs
+/// a new binary replay version, see [`replay_format`](crate::nes::replay_format).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[must_use]
 pub(crate) struct Replay {
     pub(crate) mode: ReplayMode,
     pub(crate) start: Option<Cpu>,
     pub(crate) buffer: Vec<ActionEvent>,
+    pub(crate) bookmarks: Vec<ReplayBookmark>,
+    /// Lag frames while recording, where the game didn't read the controllers.
+    pub(crate) lag_frames: u32,
+    /// RAM state and CPU/PPU alignment the replay was recorded with. Written after the rest of the
+    /// replay by each format, so it's left out here.
+    #[serde(skip)]
+    pub(crate) initial: Option<InitialState>,
+    /// Events already played back, kept so playback can seek backwards.
+    #[serde(skip)]
+    pub(crate) played: Vec<ActionEvent>,
+    /// Whether bookmarks were changed during playback and the replay file needs updating.
+    #[serde(skip)]
+    pub(crate) bookmarks_changed: bool,
+    #[serde(skip)]
+    pub(crate) greenzone: ReplayGreenzone,
+    /// Whether playback is fast forwarded by `Toggle Replay Fast Forward`.
+    #[serde(skip)]
+    pub(crate) fast_forward: bool,
 }
 
 impl Default for Replay {
@@ -37,8 +73,125 @@ impl Default for Replay {
             mode: ReplayMode::Off,
             start: None,
             buffer: vec![],
+            bookmarks: vec![],
+            lag_frames: 0,
+            initial: None,
+            played: vec![],
+            bookmarks_changed: false,
+            greenzone: ReplayGreenzone::default(),
+            fast_forward: false,
+        }
+    }
+}
+
+impl Replay {
+    /// First frame of the replay.
+    #[must_use]
+    pub(crate) fn start_frame(&self) -> u32 {
+        self.start
+            .as_ref()
+            .map_or(0, |cpu| cpu.ppu().frame_number())
+    }
+
+    /// Last frame with a recorded event. The buffer is stored in reverse during playback.
+    #[must_use]
+    pub(crate) fn end_frame(&self) -> u32 {
+        self.buffer
+            .first()
+            .or_else(|| self.played.last())
+            .map_or_else(|| self.start_frame(), |event| event.frame)
+    }
+
+    /// Lag frames between the start of the replay and `cpu`.
+    #[must_use]
+    pub(crate) fn lag_frames_since_start(&self, cpu: &Cpu) -> u32 {
+        self.start
+            .as_ref()
+            .map_or(0, |start| cpu.lag_frames().wrapping_sub(start.lag_frames()))
+    }
+
+    /// Starts a replay saved without a start state from `start`, moving its events and bookmarks
+    /// to begin at the frame of `start`.
+    pub(crate) fn set_start(&mut self, start: Cpu) {
+        let offset = start.ppu().frame_number();
+        for event in &mut self.buffer {
+            event.frame += offset;
+        }
+        for bookmark in &mut self.bookmarks {
+            bookmark.frame += offset;
+        }
+        self.start = Some(start);
+    }
+
+    /// Moves all played events back into the buffer so playback can restart from the beginning.
+    pub(crate) fn rewind_buffer(&mut self) {
+        self.buffer.extend(self.played.drain(..).rev());
+    }
+
+    /// Moves events before `frame` to the played events and the rest back to the buffer, so
+    /// playback continues from a state at `frame`.
+    pub(crate) fn seek_events(&mut self, frame: u32) {
+        self.rewind_buffer();
+        while self
+            .buffer
+            .last()
+            .map_or(false, |event| event.frame < frame)
+        {
+            self.played.extend(self.buffer.pop());
         }
     }
+
+    /// Moves the events for `frame` to the played events, returning the ones to handle. While
+    /// `seeking`, events that don't change emulation are skipped so their side effects, like
+    /// writing save states, don't happen for every frame seeked past.
+    pub(crate) fn take_frame_events(&mut self, frame: u32, seeking: bool) -> Vec<ActionEvent> {
+        let mut events = vec![];
+        while let Some(&event) = self.buffer.last() {
+            match event.frame.cmp(&frame) {
+                Ordering::Equal => {
+                    self.buffer.pop();
+                    self.played.push(event);
+                    if !seeking || event.action.affects_emulation() {
+                        events.push(event);
+                    }
+                }
+                Ordering::Less => {
+                    log::warn!(
+                        "Encountered action event out of order: {} < {}",
+                        event.frame,
+                        frame
+                    );
+                    self.buffer.pop();
+                }
+                Ordering::Greater => break,
+            }
+        }
+        events
+    }
+
+    /// Discards events and bookmarks recorded after `frame`, so recording continues from a state
+    /// at `frame`.
+    pub(crate) fn truncate(&mut self, frame: u32) {
+        self.buffer.retain(|event| event.frame < frame);
+        self.bookmarks.retain(|bookmark| bookmark.frame <= frame);
+    }
+
+    /// Adds a bookmark, keeping bookmarks sorted by frame.
+    pub(crate) fn add_bookmark(&mut self, frame: u32) {
+        let name = format!("Bookmark {}", self.bookmarks.len() + 1);
+        let index = self
+            .bookmarks
+            .partition_point(|bookmark| bookmark.frame <= frame);
+        self.bookmarks.insert(
+            index,
+            ReplayBookmark {
+                frame,
+                name,
+                note: String::new(),
+            },
+        );
+        self.bookmarks_changed = true;
+    }
 }
 
 impl Nes {
@@ -65,43 +218,22 @@ impl Nes {
         }
     }
 
-    /// Returns the path where battery-backed Save RAM files are stored
-    pub(crate) fn sram_path(&self) -> NesResult<PathBuf> {
+    /// Returns the key battery-backed Save RAM is stored under.
+    pub(crate) fn sram_key(&self) -> NesResult<String> {
         match self.control_deck.loaded_rom() {
             Some(ref rom) => PathBuf::from(rom)
                 .file_stem()
                 .and_then(OsStr::to_str)
-                .map_or_else(
-                    || Err(anyhow!("failed to create sram path for `{rom:?}`")),
-                    |save_name| {
-                        Ok(config_dir()
-                            .join("sram")
-                            .join(save_name)
-                            .with_extension("sram"))
-                    },
-                ),
+                .map(ToString::to_string)
+                .ok_or_else(|| anyhow!("failed to create sram key for `{rom:?}`")),
             None => Err(anyhow!("no rom is loaded")),
         }
     }
 
-    /// Returns the path where Save states are stored
-    pub(crate) fn save_path(&self, slot: u8) -> NesResult<PathBuf> {
-        match self.control_deck.loaded_rom() {
-            Some(ref rom) => PathBuf::from(rom)
-                .file_stem()
-                .and_then(OsStr::to_str)
-                .map_or_else(
-                    || Err(anyhow!("failed to create save path for `{rom:?}`")),
-                    |save_name| {
-                        Ok(config_dir()
-                            .join("save")
-                            .join(save_name)
-                            .join(slot.to_string())
-                            .with_extension("save"))
-                    },
-                ),
-            None => Err(anyhow!("no rom is loaded")),
-        }
+    /// Returns the key a save state slot is stored under.
+    pub(crate) fn save_key(&self, slot: impl fmt::Display) -> NesResult<String> {
+        self.sram_key()
+            .map(|save_name| format!("{save_name}/{slot}"))
     }
 
     /// Save the current state of the console into a save file
@@ -110,12 +242,18 @@ impl Nes {
         if self.config.rom_path.to_string_lossy().contains("test") {
             return;
         }
-        match self.save_path(slot).and_then(|save_path| {
-            bincode::serialize(self.control_deck.cpu())
-                .context("failed to serialize save state")
-                .map(|data| save_data(save_path, &data))
-        }) {
-            Ok(_) => self.add_message(format!("Saved slot {slot}")),
+        match self
+            .save_key(slot)
+            .and_then(|key| self.write_state(&key))
+            .and_then(|_| self.save_state_label(slot))
+        {
+            Ok(label) => {
+                self.add_message(match label {
+                    Some(label) => format!("Saved slot {slot}: {label}"),
+                    None => format!("Saved slot {slot}"),
+                });
+                self.tutorial_event(TutorialEvent::StateSaved);
+            }
             Err(err) => {
                 log::error!("{:?}", err);
                 self.add_message(format!("Failed to save slot {slot}"));
@@ -123,43 +261,118 @@ impl Nes {
         }
     }
 
+    /// Writes the current state of the console under the given save key.
+    pub(crate) fn write_state(&mut self, key: &str) -> NesResult<()> {
+        bincode::serialize(self.control_deck.cpu())
+            .context("failed to serialize save state")
+            .and_then(|data| self.persistence.save(DataKind::State, key, &data))
+    }
+
     /// Load the console with data saved from a save state
     pub(crate) fn load_state(&mut self, slot: u8) {
-        match self.save_path(slot) {
-            Ok(path) => {
-                if path.exists() {
-                    match load_data(path).and_then(|data| {
-                        bincode::deserialize(&data)
-                            .context("failed to deserialize load state")
-                            .map(|cpu| self.control_deck.load_cpu(cpu))
-                    }) {
-                        Ok(_) => self.add_message(format!("Loaded slot {slot}")),
-                        Err(err) => {
-                            log::error!("{:?}", err);
-                            self.add_message(format!("Failed to load slot {slot}"));
-                        }
-                    }
-                } else {
-                    self.add_message(format!("No save state found for slot {slot}"));
-                }
+        self.load_state_slot(slot, &format!("slot {slot}"));
+    }
+
+    /// Load the console with data saved from a save state slot, using `name` in messages.
+    pub(crate) fn load_state_slot(&mut self, slot: impl fmt::Display, name: &str) {
+        let key = match self.save_key(slot) {
+            Ok(key) => key,
+            Err(err) => {
+                log::error!("{:?}", err);
+                self.add_message(format!("Failed to determine save path for {name}"));
+                return;
             }
+        };
+        match self.persistence.load(DataKind::State, &key) {
+            // States saved before versioning are checked first since they need every byte to match
+            Ok(Some(data)) => match legacy::deserialize(&data)
+                .map_or_else(|| bincode::deserialize(&data), Ok)
+                .context("failed to deserialize load state")
+                .map(|cpu| self.control_deck.load_cpu(cpu))
+            {
+                Ok(_) => self.add_message(format!("Loaded {name}")),
+                Err(err) => {
+                    log::error!("{:?}", err);
+                    self.add_message(format!("Failed to load {name}"));
+                }
+            },
+            Ok(None) => self.add_message(format!("No save state found for {name}")),
             Err(err) => {
                 log::error!("{:?}", err);
-                self.add_message(format!("Failed to determine save path {slot}"));
+                self.add_message(format!("Failed to load {name}"));
             }
         }
     }
 
-    pub(crate) fn save_screenshot(&mut self, s: &mut PixState) {
-        let filename = Local::now()
-            .format("Screen_Shot_%Y-%m-%d_at_%H_%M_%S.png")
-            .to_string();
-        match s.save_canvas(None, &filename) {
-            Ok(()) => self.add_message(filename),
+    /// Save the current state of the console into a volatile, in-memory quick slot. Quick slots
+    /// are never written to disk and are cleared when a new ROM is loaded or the emulator exits.
+    pub(crate) fn save_quick_slot(&mut self, slot: u8) {
+        if self.control_deck.loaded_rom().is_none() {
+            return;
+        }
+        let index = match Self::quick_slot_index(slot) {
+            Some(index) => index,
+            None => {
+                log::warn!("invalid quick slot: {slot}");
+                return;
+            }
+        };
+        match bincode::serialize(self.control_deck.cpu())
+            .context("failed to serialize quick save state")
+            .and_then(|data| encode_data(&data))
+        {
+            Ok(data) => {
+                self.quick_slots[index] = Some(data);
+                self.add_message(format!("Saved quick slot {slot} (RAM only)"));
+            }
             Err(err) => {
-                log::error!("{err:?}");
-                self.add_message("Failed to save screenshot");
+                log::error!("{:?}", err);
+                self.add_message(format!("Failed to save quick slot {slot}"));
+            }
+        }
+    }
+
+    /// Load the console with data saved from a volatile, in-memory quick slot.
+    pub(crate) fn load_quick_slot(&mut self, slot: u8) {
+        if self.control_deck.loaded_rom().is_none() {
+            return;
+        }
+        let index = match Self::quick_slot_index(slot) {
+            Some(index) => index,
+            None => {
+                log::warn!("invalid quick slot: {slot}");
+                return;
             }
+        };
+        match self.quick_slots[index].as_ref() {
+            Some(data) => match decode_data(data).and_then(|data| {
+                bincode::deserialize(&data).context("failed to deserialize quick save state")
+            }) {
+                Ok(cpu) => {
+                    self.control_deck.load_cpu(cpu);
+                    self.add_message(format!("Loaded quick slot {slot} (RAM only)"));
+                }
+                Err(err) => {
+                    log::error!("{:?}", err);
+                    self.add_message(format!("Failed to load quick slot {slot}"));
+                }
+            },
+            None => self.add_message(format!("Quick slot {slot} is empty")),
+        }
+    }
+
+    /// Clears all volatile quick slots.
+    pub(crate) fn clear_quick_slots(&mut self) {
+        self.quick_slots = Default::default();
+    }
+
+    #[inline]
+    const fn quick_slot_index(slot: u8) -> Option<usize> {
+        let slot = slot as usize;
+        if slot > 0 && slot <= QUICK_SLOT_COUNT {
+            Some(slot - 1)
+        } else {
+            None
         }
     }
 
@@ -192,15 +405,7 @@ impl Nes {
 
     pub(crate) fn rewind(&mut self) {
         if let Some(data) = self.rewind_buffer.pop_front() {
-            if let Err(err) = decode_data(&data).and_then(|data| {
-                bincode::deserialize(&data)
-                    .context("failed to deserialize rewind state")
-                    .map(|cpu| self.control_deck.load_cpu(cpu))
-            }) {
-                log::error!("{err:?}");
-                self.config.rewind = false;
-                self.rewind_buffer.clear();
-            }
+            self.load_rewind_state(&data);
         }
     }
 
@@ -215,48 +420,76 @@ impl Nes {
 
             if let Some(data) = self.rewind_buffer.pop_front() {
                 self.add_message("Rewind");
-                if let Err(err) = decode_data(&data).and_then(|data| {
-                    bincode::deserialize(&data)
-                        .context("failed to deserialize rewind state")
-                        .map(|cpu| self.control_deck.load_cpu(cpu))
-                }) {
-                    log::error!("{err:?}");
-                    self.config.rewind = false;
-                    self.rewind_buffer.clear();
-                }
+                self.load_rewind_state(&data);
+                self.tutorial_event(TutorialEvent::Rewound);
             }
         } else {
             self.add_message("Rewind disabled. You can enable it in the Config menu.");
         }
     }
 
-    /// Save battery-backed Save RAM to a file (if cartridge supports it)
-    pub(crate) fn save_sram(&self) -> NesResult<()> {
+    /// Flushes all rewind snapshots. Must be called whenever the loaded ROM changes or the console
+    /// is power-cycled so that stale snapshots are never applied to a different cartridge.
+    pub(crate) fn clear_rewind(&mut self) {
+        self.rewind_frame = 0;
+        self.rewind_buffer.clear();
+    }
+
+    fn load_rewind_state(&mut self, data: &[u8]) {
+        if let Err(err) = decode_data(data).and_then(|data| {
+            bincode::deserialize(&data)
+                .context("failed to deserialize rewind state")
+                .and_then(|cpu| self.control_deck.try_load_cpu(cpu))
+        }) {
+            log::error!("{err:?}");
+            self.clear_rewind();
+            self.add_message("Rewind state invalid, rewind buffer cleared");
+        }
+    }
+
+    /// Save battery-backed Save RAM (if cartridge supports it)
+    pub(crate) fn save_sram(&mut self) -> NesResult<()> {
         if self.control_deck.cart_battery_backed() {
-            let sram_path = self.sram_path()?;
-            save_data(sram_path, self.control_deck.sram())?;
+            let key = self.sram_key()?;
+            self.persistence
+                .save(DataKind::Sram, &key, self.control_deck.sram())?;
         }
         Ok(())
     }
 
-    /// Load battery-backed Save RAM from a file (if cartridge supports it)
+    /// Load battery-backed Save RAM (if cartridge supports it)
     pub(crate) fn load_sram(&mut self) -> NesResult<()> {
-        let sram_path = self.sram_path()?;
-        if self.control_deck.cart_battery_backed() && sram_path.exists() {
-            load_data(&sram_path).map(|data| self.control_deck.load_sram(data))?;
+        let key = self.sram_key()?;
+        if self.control_deck.cart_battery_backed() {
+            if let Some(data) = self.persistence.load(DataKind::Sram, &key)? {
+                self.control_deck.load_sram(data);
+            }
         }
         Ok(())
     }
 
     pub(crate) fn start_replay(&mut self) {
-        self.replay.start = Some(self.control_deck.cpu().clone());
-        self.replay.mode = ReplayMode::Recording;
+        self.replay = Replay {
+            mode: ReplayMode::Recording,
+            start: Some(self.control_deck.cpu().clone()),
+            initial: Some(self.initial_state()),
+            ..Replay::default()
+        };
         self.add_message("Replay Recording Started");
     }
 
     pub(crate) fn stop_replay(&mut self) {
         if self.replay.mode == ReplayMode::Playback {
             self.add_message("Replay Playback Stopped");
+            if self.replay.fast_forward {
+                self.set_speed(1.0);
+            }
+            if self.replay.initial.is_some() {
+                self.restore_initial_state();
+            }
+            if self.replay.bookmarks_changed {
+                self.save_replay_bookmarks();
+            }
         } else {
             self.add_message("Replay Recording Stopped");
             self.save_replay();
@@ -266,15 +499,15 @@ impl Nes {
 
     /// Saves the replay buffer out to a file
     pub(crate) fn save_replay(&mut self) {
-        let datetime: DateTime<Local> = Local::now();
-        let replay_path =
-            PathBuf::from(datetime.format("tetanes_%Y-%m-%d_at_%H.%M.%S").to_string())
-                .with_extension("replay");
+        let key = self
+            .script_replay_key()
+            .unwrap_or_else(|| self.new_replay_key("tetanes_%Y-%m-%d_at_%H.%M.%S"));
         self.replay.buffer.reverse();
-        match bincode::serialize(&self.replay)
-            .context("failed to serialize replay recording")
-            .map(|data| save_data(replay_path, &data))
-        {
+        self.replay.lag_frames = self.replay.lag_frames_since_start(self.control_deck.cpu());
+        let replay = std::mem::take(&mut self.replay);
+        let result = self.write_replay(&key, &replay);
+        self.replay = replay;
+        match result {
             Ok(_) => {
                 self.replay.buffer.clear();
                 self.add_message("Saved replay recording");
@@ -286,18 +519,55 @@ impl Nes {
         }
     }
 
+    /// Writes bookmarks added or edited during playback back to the loaded replay file.
+    pub(crate) fn save_replay_bookmarks(&mut self) {
+        let Some(replay_path) = self.replay_path.clone() else {
+            return;
+        };
+        self.replay.rewind_buffer();
+        let replay = std::mem::take(&mut self.replay);
+        let result = self.write_replay(&replay_path.to_string_lossy(), &replay);
+        self.replay = replay;
+        match result {
+            Ok(_) => {
+                self.replay.bookmarks_changed = false;
+                self.add_message("Saved replay bookmarks");
+            }
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message("Failed to save replay bookmarks");
+            }
+        }
+    }
+
+    /// Adds a bookmark at the current frame of the replay being recorded or played back.
+    pub(crate) fn add_replay_bookmark(&mut self) {
+        if self.replay.mode == ReplayMode::Off {
+            return;
+        }
+        let frame = self.control_deck.frame_number();
+        self.replay.add_bookmark(frame);
+        self.add_message(format!("Added replay bookmark at frame {frame}"));
+    }
+
     /// Loads a replay file
     pub(crate) fn load_replay(&mut self) {
-        if let Some(replay_path) = &self.replay_path {
-            match load_data(replay_path).and_then(|data| {
-                bincode::deserialize::<Replay>(&data)
-                    .context("failed to deserialize replay recording")
-                    .map(|mut replay| {
-                        self.control_deck
-                            .load_cpu(replay.start.take().expect("valid replay start"));
-                        self.replay = replay;
-                        self.replay.mode = ReplayMode::Playback;
-                    })
+        if let Some(replay_path) = self.replay_path.clone() {
+            match self.read_replay(&replay_path).map(|mut replay| {
+                if let Some(initial) = replay.initial {
+                    log::info!("Playing back replay with {initial}");
+                    self.apply_initial_state(initial);
+                }
+                match replay.start.clone() {
+                    Some(start) => self.control_deck.load_cpu(start),
+                    // Movies from other emulators start from power on
+                    None => {
+                        self.control_deck.reset(Kind::Hard);
+                        replay.set_start(self.control_deck.cpu().clone());
+                    }
+                }
+                self.replay = replay;
+                self.replay.mode = ReplayMode::Playback;
             }) {
                 Ok(_) => self.add_message("Loaded replay recording"),
                 Err(err) => {
@@ -320,10 +590,126 @@ impl Nes {
         }
         Ok(())
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        control_deck::ControlDeck,
+        input::{JoypadBtn, Slot},
+        nes::{
+            config::Config,
+            event::{Action, Feature},
+        },
+    };
+    use std::fs::File;
+
+    fn event(frame: u32) -> ActionEvent {
+        action_event(frame, Action::Feature(Feature::TakeScreenshot))
+    }
+
+    fn action_event(frame: u32, action: Action) -> ActionEvent {
+        ActionEvent {
+            frame,
+            slot: Slot::One,
+            action,
+            pressed: true,
+            repeat: false,
+        }
+    }
+
+    fn frames(events: &[ActionEvent]) -> Vec<u32> {
+        events.iter().map(|event| event.frame).collect()
+    }
+
+    #[test]
+    fn seek_replay_events() {
+        // Stored in reverse during playback
+        let mut replay = Replay {
+            mode: ReplayMode::Playback,
+            buffer: vec![event(30), event(20)],
+            played: vec![event(5), event(10)],
+            ..Replay::default()
+        };
+        replay.seek_events(20);
+        assert_eq!(frames(&replay.played), [5, 10]);
+        assert_eq!(frames(&replay.buffer), [30, 20]);
+        replay.seek_events(21);
+        assert_eq!(frames(&replay.played), [5, 10, 20]);
+        assert_eq!(frames(&replay.buffer), [30]);
+        replay.seek_events(0);
+        assert!(replay.played.is_empty());
+        assert_eq!(frames(&replay.buffer), [30, 20, 10, 5]);
+    }
+
+    #[test]
+    fn seeking_skips_side_effects() {
+        let save_state = action_event(5, Action::Feature(Feature::SaveState));
+        let jump = action_event(5, Action::Joypad(JoypadBtn::A));
+        // Stored in reverse during playback
+        let mut replay = Replay {
+            mode: ReplayMode::Playback,
+            buffer: vec![event(6), save_state, jump],
+            ..Replay::default()
+        };
+        assert_eq!(replay.take_frame_events(5, true), [jump]);
+        assert_eq!(replay.played, [jump, save_state]);
+        assert_eq!(frames(&replay.buffer), [6]);
+
+        replay.seek_events(0);
+        assert_eq!(replay.take_frame_events(5, false), [jump, save_state]);
+    }
+
+    #[test]
+    fn truncate_recording() {
+        let mut replay = Replay {
+            mode: ReplayMode::Recording,
+            buffer: vec![event(5), event(10), event(20)],
+            ..Replay::default()
+        };
+        replay.add_bookmark(10);
+        replay.add_bookmark(15);
+        replay.truncate(10);
+        assert_eq!(frames(&replay.buffer), [5]);
+        assert_eq!(replay.bookmarks.len(), 1);
+        assert_eq!(replay.bookmarks[0].frame, 10);
+    }
+
+    fn nes_with_rom() -> Nes {
+        let mut nes = Nes::new(ControlDeck::default(), Config::default(), None, false);
+        let mut rom = File::open("test_roms/cpu/nestest.nes").expect("valid rom");
+        nes.control_deck
+            .load_rom("nestest.nes", &mut rom)
+            .expect("loaded rom");
+        nes
+    }
+
+    fn clock_frames(nes: &mut Nes, frames: u32) {
+        for _ in 0..frames {
+            nes.control_deck.clock_frame().expect("valid frame clock");
+        }
+    }
+
+    #[test]
+    fn quick_slots() {
+        let mut nes = nes_with_rom();
+        clock_frames(&mut nes, 10);
+        nes.save_quick_slot(1);
+        let saved = nes.control_deck.frame_number();
+        clock_frames(&mut nes, 10);
+
+        nes.load_quick_slot(1);
+        assert_eq!(nes.control_deck.frame_number(), saved);
+        // Other slots are left empty
+        nes.load_quick_slot(2);
+        assert_eq!(nes.control_deck.frame_number(), saved);
 
-    pub(crate) fn toggle_sound_recording(&mut self, _s: &mut PixState) {
-        self.record_sound = !self.record_sound;
-        // TODO
-        self.add_message("Toggle sound recording not implemented yet");
+        // Loading a ROM drops the slots saved for the previous one
+        nes.reset_game_state();
+        clock_frames(&mut nes, 10);
+        nes.load_quick_slot(1);
+        assert_eq!(nes.control_deck.frame_number(), saved + 10);
+        assert!(nes.quick_slots.iter().all(Option::is_none));
     }
 }
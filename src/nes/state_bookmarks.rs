@@ -0,0 +1,237 @@
+//! Named save states at specific frames, kept per game.
+//!
+//! A bookmark can be dropped while playing, recording a replay or playing one back, and jumping
+//! to it loads its state instantly. Jumping while recording a replay discards the input recorded
+//! after the bookmark, so recording carries on from it as a branch of the same replay. Jumping
+//! during playback continues playback from the bookmark's frame.
+//!
+//! Bookmarks are stored with save states under `<ROM name>/bookmarks`.
+
+use crate::{
+    cpu::Cpu,
+    nes::{
+        filesystem::{decode_data, encode_data},
+        persistence::DataKind,
+        state::ReplayMode,
+        Nes,
+    },
+    NesResult,
+};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+/// A named save state at a specific frame.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub(crate) struct StateBookmark {
+    pub(crate) name: String,
+    pub(crate) frame: u32,
+    /// Compressed, serialized `Cpu` state.
+    pub(crate) state: Vec<u8>,
+}
+
+/// Bookmarks for the loaded game, sorted by frame.
+#[derive(Default, Debug, Clone)]
+#[must_use]
+pub(crate) struct StateBookmarks {
+    pub(crate) list: Vec<StateBookmark>,
+    /// Key the bookmarks are stored under, kept so changes are saved to the game they belong to.
+    key: Option<String>,
+    /// Whether bookmarks changed since they were last saved.
+    pub(crate) changed: bool,
+}
+
+impl StateBookmarks {
+    /// Adds a bookmark, keeping bookmarks sorted by frame. Returns its index.
+    fn insert(&mut self, bookmark: StateBookmark) -> usize {
+        let index = self
+            .list
+            .partition_point(|existing| existing.frame <= bookmark.frame);
+        self.list.insert(index, bookmark);
+        index
+    }
+
+    /// Index of the closest bookmark after `frame`, or before it if not `forward`.
+    fn adjacent(&self, frame: u32, forward: bool) -> Option<usize> {
+        if forward {
+            self.list.iter().position(|bookmark| bookmark.frame > frame)
+        } else {
+            self.list
+                .iter()
+                .rposition(|bookmark| bookmark.frame < frame)
+        }
+    }
+}
+
+impl Nes {
+    /// Loads the bookmarks for the loaded game, saving any unsaved changes to the previous game's
+    /// bookmarks first.
+    pub(crate) fn load_state_bookmarks(&mut self) {
+        self.save_state_bookmarks();
+        let key = self.save_key("bookmarks").ok();
+        let list = match key
+            .as_deref()
+            .map_or(Ok(None), |key| self.persistence.load(DataKind::State, key))
+        {
+            Ok(Some(data)) => bincode::deserialize(&data).unwrap_or_else(|err| {
+                log::error!("failed to deserialize state bookmarks: {err:?}");
+                self.add_message("Failed to load bookmarks");
+                vec![]
+            }),
+            Ok(None) => vec![],
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message("Failed to load bookmarks");
+                vec![]
+            }
+        };
+        self.state_bookmarks = StateBookmarks {
+            list,
+            key,
+            changed: false,
+        };
+    }
+
+    /// Saves bookmarks if they changed since they were last saved.
+    pub(crate) fn save_state_bookmarks(&mut self) {
+        if !self.state_bookmarks.changed {
+            return;
+        }
+        self.state_bookmarks.changed = false;
+        if let Err(err) = self.write_state_bookmarks() {
+            log::error!("{err:?}");
+            self.add_message("Failed to save bookmarks");
+        }
+    }
+
+    fn write_state_bookmarks(&mut self) -> NesResult<()> {
+        let key = self
+            .state_bookmarks
+            .key
+            .as_deref()
+            .ok_or_else(|| anyhow!("no rom is loaded"))?;
+        let data = bincode::serialize(&self.state_bookmarks.list)
+            .context("failed to serialize state bookmarks")?;
+        self.persistence.save(DataKind::State, key, &data)
+    }
+
+    /// Bookmarks the current state.
+    pub(crate) fn add_state_bookmark(&mut self) {
+        if self.control_deck.loaded_rom().is_none() {
+            return;
+        }
+        let frame = self.control_deck.frame_number();
+        let state = match bincode::serialize(self.control_deck.cpu())
+            .context("failed to serialize bookmark state")
+            .and_then(|data| encode_data(&data))
+        {
+            Ok(state) => state,
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message("Failed to add bookmark");
+                return;
+            }
+        };
+        let name = format!("Bookmark {}", self.state_bookmarks.list.len() + 1);
+        self.add_message(format!("Added {name} at frame {frame}"));
+        self.state_bookmarks
+            .insert(StateBookmark { name, frame, state });
+        self.state_bookmarks.changed = true;
+        self.save_state_bookmarks();
+    }
+
+    pub(crate) fn remove_state_bookmark(&mut self, index: usize) {
+        if index < self.state_bookmarks.list.len() {
+            self.state_bookmarks.list.remove(index);
+            self.state_bookmarks.changed = true;
+            self.save_state_bookmarks();
+        }
+    }
+
+    /// Loads the state of a bookmark, keeping a replay being recorded or played back in step.
+    pub(crate) fn jump_to_state_bookmark(&mut self, index: usize) {
+        let Some(bookmark) = self.state_bookmarks.list.get(index) else {
+            return;
+        };
+        let (name, frame) = (bookmark.name.clone(), bookmark.frame);
+        let in_replay = match self.replay.mode {
+            ReplayMode::Off => true,
+            ReplayMode::Recording => frame >= self.replay.start_frame(),
+            ReplayMode::Playback => {
+                (self.replay.start_frame()..=self.replay.end_frame()).contains(&frame)
+            }
+        };
+        if !in_replay {
+            self.add_message(format!("{name} is outside of the replay"));
+            return;
+        }
+        match decode_data(&bookmark.state).and_then(|data| {
+            bincode::deserialize::<Cpu>(&data)
+                .context("failed to deserialize bookmark state")
+                .and_then(|cpu| self.control_deck.try_load_cpu(cpu))
+        }) {
+            Ok(()) => {
+                match self.replay.mode {
+                    ReplayMode::Off => (),
+                    ReplayMode::Recording => self.replay.truncate(frame),
+                    ReplayMode::Playback => self.replay.seek_events(frame),
+                }
+                self.clear_rewind();
+                self.restart_session_replay();
+                self.add_message(format!("Jumped to {name} at frame {frame}"));
+            }
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message(format!("Failed to load {name}"));
+            }
+        }
+    }
+
+    /// Jumps to the closest bookmark after the current frame, or before it if not `forward`.
+    pub(crate) fn jump_to_adjacent_state_bookmark(&mut self, forward: bool) {
+        let frame = self.control_deck.frame_number();
+        match self.state_bookmarks.adjacent(frame, forward) {
+            Some(index) => self.jump_to_state_bookmark(index),
+            None if forward => self.add_message("No bookmarks after this frame"),
+            None => self.add_message("No bookmarks before this frame"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bookmark(name: &str, frame: u32) -> StateBookmark {
+        StateBookmark {
+            name: name.to_string(),
+            frame,
+            state: vec![],
+        }
+    }
+
+    #[test]
+    fn bookmarks_sorted_by_frame() {
+        let mut bookmarks = StateBookmarks::default();
+        assert_eq!(bookmarks.insert(bookmark("a", 100)), 0);
+        assert_eq!(bookmarks.insert(bookmark("b", 50)), 0);
+        assert_eq!(bookmarks.insert(bookmark("c", 100)), 2);
+        assert_eq!(bookmarks.insert(bookmark("d", 75)), 1);
+        let names: Vec<_> = bookmarks.list.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["b", "d", "a", "c"]);
+    }
+
+    #[test]
+    fn adjacent_bookmarks() {
+        let mut bookmarks = StateBookmarks::default();
+        assert_eq!(bookmarks.adjacent(10, true), None);
+        bookmarks.insert(bookmark("a", 50));
+        bookmarks.insert(bookmark("b", 100));
+        assert_eq!(bookmarks.adjacent(10, true), Some(0));
+        assert_eq!(bookmarks.adjacent(50, true), Some(1));
+        assert_eq!(bookmarks.adjacent(100, true), None);
+        assert_eq!(bookmarks.adjacent(100, false), Some(0));
+        assert_eq!(bookmarks.adjacent(101, false), Some(1));
+        assert_eq!(bookmarks.adjacent(50, false), None);
+    }
+}
@@ -0,0 +1,233 @@
+//! Save state labels describing where in the game each state was saved.
+//!
+//! A per-game RAM map in `ram_maps/<rom name>.json` under the config directory names the RAM
+//! addresses holding values like the level or lives, and a label to fill in with them:
+//!
+//! ```json
+//! {
+//!   "label": "World {world}-{level}, {lives} lives",
+//!   "values": {
+//!     "world": { "address": 1887, "offset": 1 },
+//!     "level": { "address": 1884, "offset": 1 },
+//!     "lives": { "address": 1882, "offset": 1 },
+//!     "score": { "address": 2013, "len": 6, "encoding": "Digits" }
+//!   }
+//! }
+//! ```
+//!
+//! When a state is saved, the label is filled in from RAM and stored with the state so the save
+//! slot list shows it.
+
+use crate::{
+    common::config_dir,
+    mem::{Access, Mem},
+    nes::{persistence::DataKind, Nes},
+    NesResult,
+};
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, ffi::OsStr, fs::File, io::BufReader, path::PathBuf};
+
+/// Number of save state slots.
+pub(crate) const SAVE_SLOT_COUNT: usize = 4;
+
+/// How a value is stored in RAM.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum RamEncoding {
+    /// Little-endian binary.
+    #[default]
+    Binary,
+    /// Packed binary-coded decimal, most significant byte first.
+    Bcd,
+    /// One decimal digit per byte, most significant digit first.
+    Digits,
+}
+
+/// A value read from RAM.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct RamValue {
+    pub(crate) address: u16,
+    /// Number of bytes the value spans.
+    pub(crate) len: u16,
+    pub(crate) encoding: RamEncoding,
+    /// Added to the value, for games that count levels or lives from zero.
+    pub(crate) offset: i64,
+}
+
+impl Default for RamValue {
+    fn default() -> Self {
+        Self {
+            address: 0x0000,
+            len: 1,
+            encoding: RamEncoding::default(),
+            offset: 0,
+        }
+    }
+}
+
+impl RamValue {
+    fn read(&self, peek: &impl Fn(u16) -> u8) -> i64 {
+        let bytes = (0..self.len.max(1)).map(|i| i64::from(peek(self.address.wrapping_add(i))));
+        let value = match self.encoding {
+            RamEncoding::Binary => bytes.rev().fold(0, |value, byte| (value << 8) | byte),
+            RamEncoding::Bcd => bytes.fold(0, |value, byte| {
+                value * 100 + (byte >> 4) * 10 + (byte & 0x0F)
+            }),
+            RamEncoding::Digits => bytes.fold(0, |value, byte| value * 10 + byte % 10),
+        };
+        value + self.offset
+    }
+}
+
+/// RAM addresses and a label describing the game's progress.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+#[must_use]
+pub(crate) struct RamMap {
+    /// Label with `{name}` placeholders for each value.
+    pub(crate) label: String,
+    pub(crate) values: BTreeMap<String, RamValue>,
+}
+
+impl RamMap {
+    /// Fills in the label with values read using `peek`.
+    pub(crate) fn label(&self, peek: impl Fn(u16) -> u8) -> String {
+        self.values
+            .iter()
+            .fold(self.label.clone(), |label, (name, value)| {
+                label.replace(&format!("{{{name}}}"), &value.read(&peek).to_string())
+            })
+    }
+}
+
+impl Nes {
+    /// Returns the path where the RAM map for the loaded game is stored.
+    pub(crate) fn ram_map_path(&self) -> NesResult<PathBuf> {
+        match self.control_deck.loaded_rom() {
+            Some(ref rom) => PathBuf::from(rom)
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .map_or_else(
+                    || Err(anyhow!("failed to create ram map path for `{rom:?}`")),
+                    |name| {
+                        Ok(config_dir()
+                            .join("ram_maps")
+                            .join(name)
+                            .with_extension("json"))
+                    },
+                ),
+            None => Err(anyhow!("no rom is loaded")),
+        }
+    }
+
+    /// Returns the key a save state label is stored under.
+    fn state_label_key(&self, slot: u8) -> NesResult<String> {
+        self.save_key(format!("{slot}_label"))
+    }
+
+    /// Loads the RAM map and save state labels for the loaded game.
+    pub(crate) fn load_state_labels(&mut self) {
+        self.ram_map = match self.ram_map_path().and_then(|path| {
+            if !path.exists() {
+                return Ok(None);
+            }
+            let file = File::open(&path).with_context(|| format!("failed to open {path:?}"))?;
+            serde_json::from_reader(BufReader::new(file))
+                .with_context(|| format!("failed to parse {path:?}"))
+                .map(Some)
+        }) {
+            Ok(ram_map) => ram_map,
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message("Failed to load RAM map");
+                None
+            }
+        };
+        for slot in 1..=SAVE_SLOT_COUNT as u8 {
+            self.state_labels[slot as usize - 1] = self
+                .state_label_key(slot)
+                .and_then(|key| self.persistence.load(DataKind::State, &key))
+                .map(|data| {
+                    data.and_then(|data| String::from_utf8(data).ok())
+                        .filter(|label| !label.is_empty())
+                })
+                .unwrap_or_else(|err| {
+                    log::warn!("failed to load label for slot {slot}: {err:?}");
+                    None
+                });
+        }
+    }
+
+    /// Labels a newly saved state from the RAM map, if the game has one. Saving without a RAM
+    /// map clears any previous label so it doesn't describe the wrong state. Returns the label.
+    pub(crate) fn save_state_label(&mut self, slot: u8) -> NesResult<Option<String>> {
+        let label = self.ram_map.as_ref().map(|ram_map| {
+            let cpu = self.control_deck.cpu();
+            ram_map.label(|addr| cpu.peek(addr, Access::Dummy))
+        });
+        let label = label.filter(|label| !label.is_empty());
+        let key = self.state_label_key(slot)?;
+        self.persistence.save(
+            DataKind::State,
+            &key,
+            label.as_deref().unwrap_or_default().as_bytes(),
+        )?;
+        if let Some(index) = (slot as usize).checked_sub(1) {
+            if let Some(saved) = self.state_labels.get_mut(index) {
+                *saved = label.clone();
+            }
+        }
+        Ok(label)
+    }
+
+    /// Save slot names for the slot list, including the label of the state in each slot.
+    pub(crate) fn save_slot_names(&self) -> Vec<String> {
+        self.state_labels
+            .iter()
+            .zip(1..)
+            .map(|(label, slot)| match label {
+                Some(label) => format!("{slot}: {label}"),
+                None => slot.to_string(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ram_map_label() {
+        let ram_map: RamMap = serde_json::from_str(
+            r#"{
+                "label": "World {world}-{level}, {lives} lives, {score} points, {missing}",
+                "values": {
+                    "world": { "address": 0, "offset": 1 },
+                    "level": { "address": 1, "offset": 1 },
+                    "lives": { "address": 2 },
+                    "score": { "address": 3, "len": 3, "encoding": "Digits" }
+                }
+            }"#,
+        )
+        .expect("valid ram map");
+        let ram = [3, 1, 3, 1, 2, 5];
+        assert_eq!(
+            ram_map.label(|addr| ram[addr as usize]),
+            "World 4-2, 3 lives, 125 points, {missing}"
+        );
+
+        let bcd = RamValue {
+            len: 2,
+            encoding: RamEncoding::Bcd,
+            ..RamValue::default()
+        };
+        assert_eq!(bcd.read(&|addr| [0x12, 0x34][addr as usize]), 1234);
+        let binary = RamValue {
+            len: 2,
+            ..RamValue::default()
+        };
+        assert_eq!(binary.read(&|addr| [0x34, 0x12][addr as usize]), 0x1234);
+    }
+}
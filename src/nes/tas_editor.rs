@@ -0,0 +1,850 @@
+//! TAS editor window with a piano roll of joypad input for every frame of a movie.
+//!
+//! A movie starts from power on, from the current state or from an imported FM2 movie. While the
+//! editor has a movie the console runs one frame at a time, either playing back the movie's input
+//! or recording live input over it. Clicking a button in the piano roll toggles it for that frame
+//! and clicking a frame number seeks to it.
+//!
+//! Save states are kept every few frames as a greenzone, so seeking only emulates the frames since
+//! the nearest state. Editing a frame drops the states after it, and frames known to match the
+//! movie are marked green. Markers label frames, and branches keep copies of the input to try
+//! alternatives. Movies can be exported as replays or FM2 movies.
+
+use crate::{
+    common::{Kind, NesRegion, Reset},
+    cpu::Cpu,
+    input::{FourPlayer, JoypadBtnState, Slot},
+    movie::{Fm2Frame, Fm2Movie},
+    nes::{
+        event::ActionEvent,
+        greenzone::Greenzone,
+        replay_format::{fm2_events, movie_buttons, MovieInfo, BUTTONS, SLOTS},
+        state::{Replay, ReplayBookmark, ReplayMode},
+        Mode, Nes,
+    },
+    NesResult,
+};
+use anyhow::{anyhow, Context};
+use pix_engine::prelude::*;
+use std::fs;
+
+/// Frames between greenzone save states.
+const GREENZONE_INTERVAL: usize = 10;
+const VISIBLE_ROWS: usize = 20;
+/// Most frames run per update, so a slow update doesn't snowball.
+const MAX_FRAMES_PER_UPDATE: u32 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct TasMarker {
+    pub(crate) frame: usize,
+    pub(crate) note: String,
+}
+
+/// A saved copy of a movie's input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct TasBranch {
+    pub(crate) name: String,
+    frames: Vec<Fm2Frame>,
+    markers: Vec<TasMarker>,
+}
+
+#[derive(Debug, Clone)]
+#[must_use]
+pub(crate) struct TasMovie {
+    start: Cpu,
+    /// Whether the movie starts from power on, which FM2 movies require.
+    power_on: bool,
+    frames: Vec<Fm2Frame>,
+    markers: Vec<TasMarker>,
+    branches: Vec<TasBranch>,
+    greenzone: Greenzone<GREENZONE_INTERVAL>,
+    /// Frames before this one were emulated with the movie's current input.
+    valid: usize,
+    /// Frame the console is about to run.
+    cursor: usize,
+    recording: bool,
+    rerecords: u32,
+}
+
+impl TasMovie {
+    fn new(start: Cpu, power_on: bool) -> Self {
+        Self {
+            start,
+            power_on,
+            frames: vec![],
+            markers: vec![],
+            branches: vec![],
+            greenzone: Greenzone::default(),
+            valid: 0,
+            cursor: 0,
+            recording: true,
+            rerecords: 0,
+        }
+    }
+
+    fn from_fm2(start: Cpu, fm2: Fm2Movie) -> Self {
+        Self {
+            frames: fm2.frames,
+            recording: false,
+            rerecords: fm2.rerecord_count,
+            ..Self::new(start, true)
+        }
+    }
+
+    fn to_fm2(&self, info: MovieInfo) -> Fm2Movie {
+        Fm2Movie {
+            rom_filename: info.rom_filename,
+            pal: info.pal,
+            four_score: info.four_score,
+            rerecord_count: self.rerecords,
+            frames: self.frames.clone(),
+            extra: vec![],
+        }
+    }
+
+    /// Drops greenzone states that depend on the input of `frame`.
+    fn invalidate(&mut self, frame: usize) {
+        self.greenzone.invalidate(frame);
+        self.valid = self.valid.min(frame);
+    }
+
+    fn pressed(&self, frame: usize, slot: Slot, button: JoypadBtnState) -> bool {
+        self.frames
+            .get(frame)
+            .map_or(false, |input| input.joypads[slot as usize].contains(button))
+    }
+
+    /// Changes the input of `frame`, extending the movie to reach it.
+    fn edit(&mut self, frame: usize, edit: impl FnOnce(&mut Fm2Frame)) {
+        if frame >= self.frames.len() {
+            self.frames.resize(frame + 1, Fm2Frame::default());
+        }
+        let before = self.frames[frame];
+        edit(&mut self.frames[frame]);
+        if self.frames[frame] != before {
+            if frame < self.valid {
+                self.rerecords += 1;
+            }
+            self.invalidate(frame);
+        }
+    }
+
+    fn toggle(&mut self, frame: usize, slot: Slot, button: JoypadBtnState) {
+        self.edit(frame, |input| input.joypads[slot as usize].toggle(button));
+    }
+
+    fn insert_frame(&mut self, frame: usize) {
+        let frame = frame.min(self.frames.len());
+        self.frames.insert(frame, Fm2Frame::default());
+        for marker in self
+            .markers
+            .iter_mut()
+            .filter(|marker| marker.frame >= frame)
+        {
+            marker.frame += 1;
+        }
+        self.invalidate(frame);
+    }
+
+    fn delete_frame(&mut self, frame: usize) {
+        if frame >= self.frames.len() {
+            return;
+        }
+        self.frames.remove(frame);
+        self.markers.retain(|marker| marker.frame != frame);
+        for marker in self
+            .markers
+            .iter_mut()
+            .filter(|marker| marker.frame > frame)
+        {
+            marker.frame -= 1;
+        }
+        self.invalidate(frame);
+    }
+
+    /// Adds a marker at `frame`, keeping markers sorted by frame.
+    fn add_marker(&mut self, frame: usize) {
+        if self.markers.iter().any(|marker| marker.frame == frame) {
+            return;
+        }
+        let note = format!("Marker {}", self.markers.len() + 1);
+        let index = self.markers.partition_point(|marker| marker.frame < frame);
+        self.markers.insert(index, TasMarker { frame, note });
+    }
+
+    fn save_branch(&mut self) {
+        self.branches.push(TasBranch {
+            name: format!("Branch {}", self.branches.len() + 1),
+            frames: self.frames.clone(),
+            markers: self.markers.clone(),
+        });
+    }
+
+    /// Replaces the input with a branch, returning the first frame that changed.
+    fn load_branch(&mut self, index: usize) -> Option<usize> {
+        let branch = self.branches.get(index)?.clone();
+        let changed = self
+            .frames
+            .iter()
+            .zip(&branch.frames)
+            .position(|(a, b)| a != b)
+            .unwrap_or_else(|| self.frames.len().min(branch.frames.len()));
+        self.frames = branch.frames;
+        self.markers = branch.markers;
+        self.invalidate(changed);
+        self.rerecords += 1;
+        Some(changed)
+    }
+
+    /// Input changes as replay events, starting from the joypad state of the movie start.
+    fn replay_events(&self) -> Vec<ActionEvent> {
+        fm2_events(
+            self.start.frame_number(),
+            SLOTS.map(|slot| movie_buttons(self.start.joypad(slot).buttons())),
+            &self.frames,
+        )
+    }
+}
+
+#[derive(Debug)]
+#[must_use]
+pub(crate) struct TasEditor {
+    window_id: WindowId,
+    movie: Option<TasMovie>,
+    fm2_path: String,
+    /// First frame shown in the piano roll, or `None` to follow the cursor.
+    scroll: Option<usize>,
+    /// Emulated time not yet run as a whole frame, in seconds.
+    elapsed: f32,
+}
+
+impl TasEditor {
+    const fn new(window_id: WindowId) -> Self {
+        Self {
+            window_id,
+            movie: None,
+            fm2_path: String::new(),
+            scroll: None,
+            elapsed: 0.0,
+        }
+    }
+
+    pub(crate) const fn window_id(&self) -> WindowId {
+        self.window_id
+    }
+
+    /// Whether the editor has a movie and controls emulation.
+    pub(crate) const fn active(&self) -> bool {
+        self.movie.is_some()
+    }
+}
+
+impl Nes {
+    pub(crate) fn toggle_tas_editor(&mut self, s: &mut PixState) -> PixResult<()> {
+        match self.tas_editor {
+            None => {
+                let window_id = s
+                    .window()
+                    .dimensions(760, 720)
+                    .title("TAS Editor")
+                    .position(10, 10)
+                    .resizable()
+                    .build()?;
+                self.tas_editor = Some(TasEditor::new(window_id));
+            }
+            Some(ref editor) => {
+                s.close_window(editor.window_id())?;
+                self.tas_editor = None;
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts a new movie from power on or from the current state.
+    fn new_tas_movie(&mut self, power_on: bool) {
+        if power_on {
+            self.control_deck.reset(Kind::Hard);
+            self.clear_rewind();
+        }
+        let movie = TasMovie::new(self.control_deck.cpu().clone(), power_on);
+        if let Some(ref mut editor) = self.tas_editor {
+            editor.movie = Some(movie);
+            editor.scroll = None;
+        }
+    }
+
+    fn import_fm2(&mut self) -> NesResult<()> {
+        let Some(ref editor) = self.tas_editor else {
+            return Ok(());
+        };
+        let path = editor.fm2_path.clone();
+        let fm2 = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read movie {path:?}"))?
+            .parse::<Fm2Movie>()?;
+        if fm2.pal != (self.config.region == NesRegion::Pal) {
+            log::warn!("{path:?} was recorded for another region");
+        }
+        self.control_deck.reset(Kind::Hard);
+        self.clear_rewind();
+        let movie = TasMovie::from_fm2(self.control_deck.cpu().clone(), fm2);
+        if let Some(ref mut editor) = self.tas_editor {
+            editor.movie = Some(movie);
+            editor.scroll = None;
+        }
+        self.add_message(format!("Imported {path}"));
+        Ok(())
+    }
+
+    fn export_fm2(&mut self) -> NesResult<()> {
+        let Some(TasEditor {
+            movie: Some(ref movie),
+            ref fm2_path,
+            ..
+        }) = self.tas_editor
+        else {
+            return Ok(());
+        };
+        if !movie.power_on {
+            return Err(anyhow!(
+                "only movies started from power on can be exported to FM2"
+            ));
+        }
+        let fm2 = movie.to_fm2(self.movie_info());
+        let path = fm2_path.clone();
+        fs::write(&path, fm2.to_string()).with_context(|| format!("failed to write {path:?}"))?;
+        self.add_message(format!("Exported {path}"));
+        Ok(())
+    }
+
+    fn export_tas_replay(&mut self) -> NesResult<()> {
+        let Some(TasEditor {
+            movie: Some(ref movie),
+            ..
+        }) = self.tas_editor
+        else {
+            return Ok(());
+        };
+        let start_frame = movie.start.frame_number();
+        let mut buffer = movie.replay_events();
+        // Replays are stored last event first
+        buffer.reverse();
+        let replay = Replay {
+            mode: ReplayMode::Off,
+            start: Some(movie.start.clone()),
+            buffer,
+            bookmarks: movie
+                .markers
+                .iter()
+                .map(|marker| ReplayBookmark {
+                    frame: start_frame + marker.frame as u32,
+                    name: marker.note.clone(),
+                    note: String::new(),
+                })
+                .collect(),
+            initial: Some(self.initial_state()),
+            ..Replay::default()
+        };
+        let key = self.new_replay_key("tetanes_tas_%Y-%m-%d_at_%H.%M.%S");
+        self.write_replay(&key, &replay)?;
+        self.add_message(format!("Exported {key}"));
+        Ok(())
+    }
+
+    /// Runs the frame at the movie cursor, playing back its input or recording live input.
+    fn run_tas_frame(&mut self, s: &mut PixState, record: bool) -> PixResult<()> {
+        let Some(TasEditor {
+            movie: Some(ref mut movie),
+            ..
+        }) = self.tas_editor
+        else {
+            return Ok(());
+        };
+        let frame = movie.cursor;
+        // The movie start is kept separately
+        if frame > 0 {
+            movie.greenzone.update(frame, self.control_deck.cpu());
+        }
+        if record {
+            let mut input = Fm2Frame::default();
+            for slot in SLOTS {
+                input.joypads[slot as usize] =
+                    movie_buttons(self.control_deck.joypad(slot).buttons());
+            }
+            movie.edit(frame, |current| *current = input);
+        }
+        if let Some(input) = movie.frames.get(frame) {
+            input.apply(&mut self.control_deck);
+        }
+        if frame == movie.valid {
+            movie.valid += 1;
+        }
+        movie.cursor += 1;
+
+        let prev_frame = self.control_deck.frame_number();
+        let result = self.control_deck.clock_frame();
+        self.handle_clock_result(s, prev_frame, result)
+    }
+
+    /// Runs a single frame of the movie for frame advance, stopping at the end unless recording.
+    pub(crate) fn step_tas(&mut self, s: &mut PixState) -> PixResult<()> {
+        let Some(TasEditor {
+            movie: Some(ref movie),
+            ..
+        }) = self.tas_editor
+        else {
+            return Ok(());
+        };
+        let recording = movie.recording;
+        if !recording && movie.cursor >= movie.frames.len() {
+            self.add_message("End of TAS movie");
+            return Ok(());
+        }
+        self.run_tas_frame(s, recording)
+    }
+
+    /// Runs the movie for the time passed since the last update, pausing at the end of the movie
+    /// unless recording.
+    pub(crate) fn clock_tas(&mut self, s: &mut PixState) -> PixResult<()> {
+        let seconds = self.seconds_to_run();
+        let frame_time = self.config.region.frame_rate().recip();
+        let mut frames = 0;
+        loop {
+            let Some(ref mut editor) = self.tas_editor else {
+                return Ok(());
+            };
+            let Some(ref movie) = editor.movie else {
+                return Ok(());
+            };
+            if frames == 0 {
+                editor.elapsed += seconds;
+            }
+            if editor.elapsed < frame_time || frames == MAX_FRAMES_PER_UPDATE {
+                editor.elapsed = editor.elapsed.min(frame_time);
+                return Ok(());
+            }
+            editor.elapsed -= frame_time;
+            let recording = movie.recording;
+            if !recording && movie.cursor >= movie.frames.len() {
+                self.pause_play();
+                self.add_message("End of TAS movie");
+                return Ok(());
+            }
+            self.run_tas_frame(s, recording)?;
+            frames += 1;
+        }
+    }
+
+    /// Seeks the movie to `frame`, starting from the nearest greenzone state.
+    fn seek_tas(&mut self, s: &mut PixState, frame: usize) -> PixResult<()> {
+        let Some(TasEditor {
+            movie: Some(ref mut movie),
+            ..
+        }) = self.tas_editor
+        else {
+            return Ok(());
+        };
+        let frame = frame.min(movie.frames.len());
+        let in_sync = movie.cursor <= movie.valid;
+        let nearest = movie.greenzone.nearest_frame(frame);
+        let from_cursor = in_sync
+            && movie.cursor <= frame
+            && nearest.map_or(true, |nearest| nearest <= movie.cursor);
+        if !from_cursor {
+            match movie.greenzone.load(frame) {
+                Some(Ok((nearest, cpu))) => {
+                    self.control_deck.load_cpu(cpu);
+                    movie.cursor = nearest;
+                }
+                Some(Err(err)) => {
+                    log::error!("{err:?}");
+                    movie.valid = 0;
+                    self.control_deck.load_cpu(movie.start.clone());
+                    movie.cursor = 0;
+                }
+                None => {
+                    self.control_deck.load_cpu(movie.start.clone());
+                    movie.cursor = 0;
+                }
+            }
+        }
+        while self.tas_cursor().map_or(false, |cursor| cursor < frame) {
+            self.run_tas_frame(s, false)?;
+        }
+        self.control_deck.clear_audio_samples();
+        if let Some(ref mut editor) = self.tas_editor {
+            editor.scroll = None;
+        }
+        Ok(())
+    }
+
+    fn tas_cursor(&self) -> Option<usize> {
+        self.tas_editor
+            .as_ref()
+            .and_then(|editor| editor.movie.as_ref())
+            .map(|movie| movie.cursor)
+    }
+
+    /// Applies an edit to the movie, seeking back to the edited frame if it was already run.
+    fn edit_tas(
+        &mut self,
+        s: &mut PixState,
+        frame: usize,
+        edit: impl FnOnce(&mut TasMovie),
+    ) -> PixResult<()> {
+        let Some(TasEditor {
+            movie: Some(ref mut movie),
+            ..
+        }) = self.tas_editor
+        else {
+            return Ok(());
+        };
+        edit(movie);
+        if movie.cursor > frame {
+            self.seek_tas(s, frame)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn render_tas_editor(&mut self, s: &mut PixState) -> PixResult<()> {
+        let Some(ref editor) = self.tas_editor else {
+            return Ok(());
+        };
+        s.set_window_target(editor.window_id())?;
+        s.clear()?;
+        s.fill(Color::WHITE);
+        s.stroke(None);
+        let result = if editor.active() {
+            self.render_tas_movie(s)
+        } else {
+            self.render_tas_start(s)
+        };
+        s.reset_window_target();
+        result
+    }
+
+    fn render_tas_start(&mut self, s: &mut PixState) -> PixResult<()> {
+        if !self.control_deck.is_running() {
+            s.text("Load a ROM to start a TAS movie.")?;
+            return Ok(());
+        }
+        if s.button("New from Power On")? {
+            self.new_tas_movie(true);
+        }
+        s.same_line(None);
+        if s.button("New from Current State")? {
+            self.new_tas_movie(false);
+        }
+        s.same_line(None);
+        s.help_marker(
+            "Movies started from the current state can only be exported as replays, which keep \
+            the starting state.",
+        )?;
+        s.spacing()?;
+        self.render_fm2_path(s)?;
+        s.same_line(None);
+        if s.button("Import FM2")? {
+            if let Err(err) = self.import_fm2() {
+                log::error!("{err:?}");
+                self.add_message(format!("Failed to import FM2: {err}"));
+            }
+        }
+        Ok(())
+    }
+
+    fn render_fm2_path(&mut self, s: &mut PixState) -> PixResult<()> {
+        if let Some(ref mut editor) = self.tas_editor {
+            s.next_width(300);
+            s.text_field("FM2 Path", &mut editor.fm2_path)?;
+        }
+        Ok(())
+    }
+
+    fn render_tas_movie(&mut self, s: &mut PixState) -> PixResult<()> {
+        let players = if self.config.four_player == FourPlayer::Disabled {
+            2
+        } else {
+            4
+        };
+        let Some(TasEditor {
+            movie: Some(ref mut movie),
+            ref mut scroll,
+            ..
+        }) = self.tas_editor
+        else {
+            return Ok(());
+        };
+
+        // Toolbar
+        s.text(format!(
+            "Frame {} of {}   Greenzone {}   Rerecords {}",
+            movie.cursor,
+            movie.frames.len(),
+            movie.valid,
+            movie.rerecords
+        ))?;
+        s.checkbox("Recording", &mut movie.recording)?;
+        s.same_line(None);
+        s.help_marker(
+            "While recording, live input replaces the movie's input for each frame that runs. \
+            Otherwise the movie plays back and pauses at the end.",
+        )?;
+        let cursor = movie.cursor;
+        let mut edit: Option<(usize, Box<dyn FnOnce(&mut TasMovie)>)> = None;
+        let mut seek = None;
+        if s.button("Insert Frame")? {
+            edit = Some((cursor, Box::new(move |movie| movie.insert_frame(cursor))));
+        }
+        s.same_line(None);
+        if s.button("Delete Frame")? {
+            edit = Some((cursor, Box::new(move |movie| movie.delete_frame(cursor))));
+        }
+        s.same_line(None);
+        if s.button("Add Marker")? {
+            movie.add_marker(cursor);
+        }
+        s.same_line(None);
+        if s.button("Save Branch")? {
+            movie.save_branch();
+        }
+        s.same_line(None);
+        if s.button("Rewind to Start")? {
+            seek = Some(0);
+        }
+
+        // Piano roll
+        let first = scroll.unwrap_or_else(|| cursor.saturating_sub(VISIBLE_ROWS / 2));
+        if s.button("Scroll Up")? {
+            *scroll = Some(first.saturating_sub(VISIBLE_ROWS));
+        }
+        s.same_line(None);
+        if s.button("Scroll Down")? {
+            *scroll = Some(first + VISIBLE_ROWS);
+        }
+        s.same_line(None);
+        if s.button("Follow Cursor")? {
+            *scroll = None;
+        }
+        let header = s.cursor_pos();
+        s.text(" ")?;
+        let row_height = s.theme().font_size as i32;
+        let mut columns = vec![];
+        for frame in first..first + VISIBLE_ROWS {
+            let pos = s.cursor_pos();
+            s.push();
+            s.fill(if frame < movie.valid {
+                Color::GREEN
+            } else {
+                rgb!(60)
+            });
+            s.rect([pos.x(), pos.y(), 4, row_height])?;
+            s.pop();
+            s.set_cursor_pos([pos.x() + 8, pos.y()]);
+            let label = if frame == cursor {
+                format!("> {frame}##tasframe")
+            } else {
+                format!("{frame}##tasframe")
+            };
+            if s.button(label)? {
+                seek = Some(frame);
+            }
+            for &slot in &SLOTS[..players] {
+                s.same_line(None);
+                s.text("|")?;
+                for &(name, _, button) in &BUTTONS {
+                    s.same_line(None);
+                    if frame == first {
+                        columns.push((s.cursor_pos().x(), name));
+                    }
+                    let mut pressed = movie.pressed(frame, slot, button);
+                    if s.checkbox(
+                        format!("##tas{frame}_{}_{name}", slot as usize),
+                        &mut pressed,
+                    )? {
+                        edit = Some((
+                            frame,
+                            Box::new(move |movie| movie.toggle(frame, slot, button)),
+                        ));
+                    }
+                }
+            }
+            if let Some(marker) = movie.markers.iter().find(|marker| marker.frame == frame) {
+                s.same_line(None);
+                s.push();
+                s.fill(Color::YELLOW);
+                s.text(&marker.note)?;
+                s.pop();
+            }
+        }
+        let end = s.cursor_pos();
+        for (x, name) in columns {
+            s.set_cursor_pos([x, header.y()]);
+            s.text(name)?;
+        }
+        s.set_cursor_pos(end);
+
+        // Markers and branches
+        let mut load_branch = None;
+        let mut remove_branch = None;
+        let mut remove_marker = None;
+        s.collapsing_tree("Markers", |s: &mut PixState| {
+            for (i, marker) in movie.markers.iter_mut().enumerate() {
+                if s.button(format!("{}##marker{i}", marker.frame))? {
+                    seek = Some(marker.frame);
+                }
+                s.same_line(None);
+                s.next_width(200);
+                s.text_field(format!("##marker_note{i}"), &mut marker.note)?;
+                s.same_line(None);
+                if s.button(format!("Remove##marker{i}"))? {
+                    remove_marker = Some(i);
+                }
+            }
+            Ok(())
+        })?;
+        s.collapsing_tree("Branches", |s: &mut PixState| {
+            for (i, branch) in movie.branches.iter_mut().enumerate() {
+                s.next_width(200);
+                s.text_field(format!("##branch_name{i}"), &mut branch.name)?;
+                s.same_line(None);
+                s.text(format!("{} frames", branch.frames.len()))?;
+                s.same_line(None);
+                if s.button(format!("Load##branch{i}"))? {
+                    load_branch = Some(i);
+                }
+                s.same_line(None);
+                if s.button(format!("Remove##branch{i}"))? {
+                    remove_branch = Some(i);
+                }
+            }
+            Ok(())
+        })?;
+        if let Some(i) = remove_marker {
+            movie.markers.remove(i);
+        }
+        if let Some(i) = remove_branch {
+            movie.branches.remove(i);
+        }
+        if let Some(i) = load_branch {
+            if let Some(changed) = movie.load_branch(i) {
+                if movie.cursor > changed {
+                    seek = Some(changed);
+                }
+            }
+        }
+
+        // Export
+        s.spacing()?;
+        if s.button("Export Replay")? {
+            if let Err(err) = self.export_tas_replay() {
+                log::error!("{err:?}");
+                self.add_message(format!("Failed to export replay: {err}"));
+            }
+        }
+        self.render_fm2_path(s)?;
+        s.same_line(None);
+        if s.button("Export FM2")? {
+            if let Err(err) = self.export_fm2() {
+                log::error!("{err:?}");
+                self.add_message(format!("Failed to export FM2: {err}"));
+            }
+        }
+        s.same_line(None);
+        if s.button("Close Movie")? {
+            if let Some(ref mut editor) = self.tas_editor {
+                editor.movie = None;
+            }
+            return Ok(());
+        }
+
+        if let Some((frame, edit)) = edit {
+            self.edit_tas(s, frame, edit)?;
+        }
+        if let Some(frame) = seek {
+            if self.mode == Mode::Playing {
+                self.pause_play();
+            }
+            self.seek_tas(s, frame)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bus::CpuBus, input::JoypadBtn, nes::event::Action};
+
+    fn movie(frames: usize) -> TasMovie {
+        let mut movie = TasMovie::new(Cpu::new(CpuBus::default()), true);
+        movie.frames = vec![Fm2Frame::default(); frames];
+        movie
+    }
+
+    #[test]
+    fn edits_invalidate_greenzone() {
+        let mut movie = movie(40);
+        let cpu = movie.start.clone();
+        for frame in [10, 20, 30] {
+            movie.greenzone.update(frame, &cpu);
+        }
+        movie.valid = 35;
+        movie.toggle(15, Slot::One, JoypadBtnState::A);
+        assert!(movie.pressed(15, Slot::One, JoypadBtnState::A));
+        assert_eq!(movie.valid, 15);
+        assert_eq!(movie.greenzone.nearest_frame(40), Some(10));
+        assert_eq!(movie.rerecords, 1);
+
+        // Editing past the end grows the movie
+        movie.toggle(45, Slot::Two, JoypadBtnState::LEFT);
+        assert_eq!(movie.frames.len(), 46);
+        assert_eq!(movie.rerecords, 1);
+    }
+
+    #[test]
+    fn insert_and_delete_frames() {
+        let mut movie = movie(5);
+        movie.toggle(2, Slot::One, JoypadBtnState::B);
+        movie.add_marker(3);
+        movie.insert_frame(1);
+        assert!(movie.pressed(3, Slot::One, JoypadBtnState::B));
+        assert_eq!(movie.markers[0].frame, 4);
+        movie.delete_frame(0);
+        assert!(movie.pressed(2, Slot::One, JoypadBtnState::B));
+        assert_eq!(movie.markers[0].frame, 3);
+        assert_eq!(movie.frames.len(), 5);
+    }
+
+    #[test]
+    fn branches() {
+        let mut movie = movie(10);
+        movie.save_branch();
+        movie.toggle(6, Slot::One, JoypadBtnState::START);
+        assert_eq!(movie.load_branch(0), Some(6));
+        assert!(!movie.pressed(6, Slot::One, JoypadBtnState::START));
+        assert_eq!(movie.load_branch(1), None);
+    }
+
+    #[test]
+    fn replay_events() {
+        let mut movie = movie(3);
+        movie.toggle(0, Slot::One, JoypadBtnState::A);
+        movie.toggle(1, Slot::One, JoypadBtnState::A);
+        movie.toggle(1, Slot::Two, JoypadBtnState::UP);
+        let events = movie
+            .replay_events()
+            .into_iter()
+            .map(|event| (event.frame, event.slot, event.action, event.pressed))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            [
+                (0, Slot::One, Action::Joypad(JoypadBtn::A), true),
+                (1, Slot::One, Action::Joypad(JoypadBtn::A), false),
+                (1, Slot::Two, Action::Joypad(JoypadBtn::Up), true),
+                (2, Slot::Two, Action::Joypad(JoypadBtn::Up), false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+//! Game titles for the ROM browser and window title.
+//!
+//! Titles come from the game database, or from a title stored at the end of the ROM file.
+//! Titles in non-Latin scripts, like Japanese, are only shown when a Unicode font is found to
+//! render them with, otherwise the romanized title is shown instead.
+
+use crate::{cart::GameTitle, nes::Nes};
+use pix_engine::prelude::*;
+use std::{borrow::Cow, ffi::OsStr, path::PathBuf};
+
+/// Common system fonts with CJK coverage, checked when no `unicode_font` is configured.
+#[cfg(not(target_arch = "wasm32"))]
+const FALLBACK_FONTS: [&str; 6] = [
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/google-noto-cjk/NotoSansCJK-Regular.ttc",
+    "/System/Library/Fonts/Supplemental/Arial Unicode.ttf",
+    "/Library/Fonts/Arial Unicode.ttf",
+    "C:\\Windows\\Fonts\\msgothic.ttc",
+];
+
+/// Finds a font able to render titles in non-Latin scripts, preferring the configured font.
+pub(crate) fn unicode_font(configured: Option<&PathBuf>) -> Option<Font> {
+    if let Some(path) = configured {
+        if path.is_file() {
+            return Some(Font::from_file("Unicode", path.clone()));
+        }
+        log::warn!("unicode font not found: {path:?}");
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(path) = FALLBACK_FONTS
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.is_file())
+    {
+        log::debug!("using unicode font: {path:?}");
+        return Some(Font::from_file("Unicode", path));
+    }
+    None
+}
+
+impl Nes {
+    /// Updates the titles of ROMs listed in the ROM browser.
+    pub(crate) fn update_rom_titles(&mut self) {
+        self.rom_titles = self
+            .paths
+            .iter()
+            .map(|path| {
+                if !path.is_file() {
+                    return None;
+                }
+                GameTitle::from_path(path).unwrap_or_else(|err| {
+                    log::debug!("{path:?}: {err:?}");
+                    None
+                })
+            })
+            .collect();
+    }
+
+    /// Label for a path in the ROM browser, including the game title if known.
+    pub(crate) fn rom_label<'a>(&self, index: usize, name: Cow<'a, str>) -> Cow<'a, str> {
+        match self.rom_titles.get(index) {
+            Some(Some(title)) => {
+                let title = title.display(self.unicode_font.is_some());
+                let stem = name.strip_suffix(".nes").unwrap_or(&name);
+                if title == stem {
+                    name
+                } else {
+                    format!("{name} - {title}").into()
+                }
+            }
+            _ => name,
+        }
+    }
+
+    /// Whether any ROM browser label needs the Unicode font to render.
+    pub(crate) fn rom_titles_need_unicode(&self) -> bool {
+        self.unicode_font.is_some()
+            && self
+                .rom_titles
+                .iter()
+                .flatten()
+                .any(|title| title.native.is_some())
+    }
+
+    /// Title of the loaded game for the window title bar. The window title is drawn by the
+    /// operating system, so native titles are always used. Patched ROMs are marked as such.
+    pub(crate) fn window_title(&self) -> String {
+        let title = match self.control_deck.title() {
+            Some(title) => title.display(true).to_string(),
+            None => self
+                .config
+                .rom_path
+                .file_stem()
+                .map_or_else(|| "unknown".into(), OsStr::to_string_lossy)
+                .into_owned(),
+        };
+        if self.rom_patches.applied.is_empty() {
+            title
+        } else {
+            format!("{title} [Patched]")
+        }
+    }
+}
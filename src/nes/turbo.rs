@@ -0,0 +1,163 @@
+//! Turbo buttons that repeatedly press and release a joypad button while held.
+//!
+//! Any joypad button can be bound to turbo in the Keybindings menu, and each player and button has
+//! its own rate in presses per second. Presses are timed by the emulated frame number rather than
+//! the wall clock, so turbo behaves the same at any emulation speed and during replay playback.
+
+use crate::{
+    common::Regional,
+    input::{JoypadBtn, JoypadBtnState, Slot},
+    nes::{config::Config, Mode, Nes},
+};
+
+/// Buttons that can be turbo'd, in the order they're listed in menus.
+pub(crate) const TURBO_BUTTONS: [JoypadBtn; 8] = [
+    JoypadBtn::A,
+    JoypadBtn::B,
+    JoypadBtn::Select,
+    JoypadBtn::Start,
+    JoypadBtn::Up,
+    JoypadBtn::Down,
+    JoypadBtn::Left,
+    JoypadBtn::Right,
+];
+pub(crate) const MIN_TURBO_RATE: u32 = 1;
+/// A press and release takes at least two frames.
+pub(crate) const MAX_TURBO_RATE: u32 = 30;
+const SLOTS: [Slot; 4] = [Slot::One, Slot::Two, Slot::Three, Slot::Four];
+
+/// Turbo buttons held by each player.
+#[derive(Default, Debug, Copy, Clone)]
+#[must_use]
+pub(crate) struct Turbo {
+    held: [JoypadBtnState; 4],
+}
+
+impl Turbo {
+    pub(crate) fn set_held(&mut self, slot: Slot, button: JoypadBtn, held: bool) {
+        self.held[slot as usize].set(button.into(), held);
+    }
+
+    pub(crate) const fn held(&self, slot: Slot) -> JoypadBtnState {
+        self.held[slot as usize]
+    }
+
+    pub(crate) fn release(&mut self, slot: Slot) {
+        self.held[slot as usize] = JoypadBtnState::empty();
+    }
+
+    /// Whether a turbo button pressing `rate` times per second is down during `frame`.
+    fn pressed(frame: u32, rate: u32, frame_rate: f32) -> bool {
+        let rate = rate.clamp(MIN_TURBO_RATE, MAX_TURBO_RATE);
+        // Down for the first half of each press, counted in half presses since power on
+        let half_presses = f64::from(frame) * 2.0 * f64::from(rate) / f64::from(frame_rate);
+        (half_presses as u64) % 2 == 0
+    }
+}
+
+impl Config {
+    /// Turbo rate in presses per second for a player's button.
+    pub(crate) fn turbo_rate(&self, slot: Slot, button: JoypadBtn) -> u32 {
+        self.turbo_rates
+            .get(&slot)
+            .and_then(|rates| rates.get(&button))
+            .copied()
+            .unwrap_or(self.turbo_rate)
+    }
+
+    pub(crate) fn set_turbo_rate(&mut self, slot: Slot, button: JoypadBtn, rate: u32) {
+        self.turbo_rates
+            .entry(slot)
+            .or_default()
+            .insert(button, rate.clamp(MIN_TURBO_RATE, MAX_TURBO_RATE));
+    }
+}
+
+impl Nes {
+    pub(crate) fn handle_turbo(&mut self, slot: Slot, button: JoypadBtn, pressed: bool) -> bool {
+        if self.mode != Mode::Playing {
+            return false;
+        }
+        let button = match button {
+            JoypadBtn::TurboA => JoypadBtn::A,
+            JoypadBtn::TurboB => JoypadBtn::B,
+            button => button,
+        };
+        self.turbo.set_held(slot, button, pressed);
+        if !pressed {
+            // Ensure the button isn't left stuck down mid-press
+            self.control_deck
+                .joypad_mut(slot)
+                .set_button(button.into(), false);
+        }
+        true
+    }
+
+    /// Presses or releases held turbo buttons for the next frame.
+    pub(crate) fn update_turbo(&mut self) {
+        let frame = self.control_deck.frame_number();
+        let frame_rate = self.control_deck.region().frame_rate();
+        for slot in SLOTS {
+            let held = self.turbo.held(slot);
+            if held.is_empty() {
+                continue;
+            }
+            for button in TURBO_BUTTONS {
+                if held.contains(button.into()) {
+                    let rate = self.config.turbo_rate(slot, button);
+                    self.control_deck
+                        .joypad_mut(slot)
+                        .set_button(button.into(), Turbo::pressed(frame, rate, frame_rate));
+                }
+            }
+        }
+    }
+
+    /// Status text listing each player's held turbo buttons, if any.
+    pub(crate) fn turbo_status(&self) -> Option<String> {
+        let players = SLOTS
+            .into_iter()
+            .filter_map(|slot| {
+                let held = self.turbo.held(slot);
+                let buttons = TURBO_BUTTONS
+                    .into_iter()
+                    .filter(|&button| held.contains(button.into()))
+                    .map(|button| button.as_ref().to_string())
+                    .collect::<Vec<_>>();
+                (!buttons.is_empty())
+                    .then(|| format!("P{} {}", slot as usize + 1, buttons.join(" ")))
+            })
+            .collect::<Vec<_>>();
+        (!players.is_empty()).then(|| format!("Turbo {}", players.join(", ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turbo_rate() {
+        const FPS: f32 = 60.0;
+        let presses = |rate| {
+            (0..60)
+                .map(|frame| Turbo::pressed(frame, rate, FPS))
+                .collect::<Vec<_>>()
+                .windows(2)
+                .filter(|pair| !pair[0] && pair[1])
+                .count()
+                + 1
+        };
+        assert_eq!(presses(10), 10);
+        assert_eq!(presses(30), 30);
+        assert_eq!(presses(1), 1);
+        // Clamped so presses are never shorter than a frame
+        assert_eq!(presses(100), 30);
+        assert!(Turbo::pressed(0, 15, FPS));
+
+        let mut turbo = Turbo::default();
+        turbo.set_held(Slot::Two, JoypadBtn::Up, true);
+        assert!(turbo.held(Slot::One).is_empty());
+        assert_eq!(turbo.held(Slot::Two), JoypadBtnState::UP);
+    }
+}
@@ -0,0 +1,230 @@
+//! Onboarding overlay that walks new users through the main features one step at a time. Each
+//! step points at the key or menu to use and waits for the user to actually do it.
+
+use crate::nes::{
+    event::{Action, DebugAction, Feature},
+    menu::Menu,
+    Mode, Nes,
+};
+use pix_engine::prelude::*;
+
+/// Something the user did that a tutorial step can wait for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum TutorialEvent {
+    RomLoaded,
+    StateSaved,
+    Rewound,
+    DebuggerOpened,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct TutorialStep {
+    title: &'static str,
+    text: &'static str,
+    /// Action whose key binding or menu is highlighted.
+    action: Action,
+    until: TutorialEvent,
+}
+
+const STEPS: &[TutorialStep] = &[
+    TutorialStep {
+        title: "Load a ROM",
+        text: "Pick a game from the list and press Enter to start playing. The list can be opened \
+               at any time with the highlighted key.",
+        action: Action::Menu(Menu::LoadRom),
+        until: TutorialEvent::RomLoaded,
+    },
+    TutorialStep {
+        title: "Save your progress",
+        text: "Save states capture the entire console so you can pick up exactly where you left \
+               off. Press the highlighted key to save to the current slot.",
+        action: Action::Feature(Feature::SaveState),
+        until: TutorialEvent::StateSaved,
+    },
+    TutorialStep {
+        title: "Rewind",
+        text: "Made a mistake? Tap the highlighted key to jump back a couple of seconds, or hold \
+               it to rewind continuously.",
+        action: Action::Feature(Feature::Rewind),
+        until: TutorialEvent::Rewound,
+    },
+    TutorialStep {
+        title: "Open the debugger",
+        text: "The debugger shows the CPU state and disassembly, and lets you step through \
+               instructions and set breakpoints. Press the highlighted key to open it.",
+        action: Action::Debug(DebugAction::ToggleCpuDebugger),
+        until: TutorialEvent::DebuggerOpened,
+    },
+];
+
+/// Progress through the tutorial steps.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub(crate) struct Tutorial {
+    step: Option<usize>,
+}
+
+impl Tutorial {
+    pub(crate) fn start(&mut self) {
+        self.step = Some(0);
+    }
+
+    pub(crate) fn stop(&mut self) {
+        self.step = None;
+    }
+
+    fn current(&self) -> Option<&'static TutorialStep> {
+        self.step.and_then(|step| STEPS.get(step))
+    }
+
+    /// Moves to the next step, returning whether the tutorial has finished.
+    fn next(&mut self) -> bool {
+        self.step = self.step.map(|step| step + 1);
+        if self.current().is_none() {
+            self.stop();
+            return true;
+        }
+        false
+    }
+
+    /// Advances past the current step if it was waiting for `event`. Returns whether the
+    /// tutorial has finished.
+    fn handle(&mut self, event: TutorialEvent) -> bool {
+        match self.current() {
+            Some(step) if step.until == event => self.next(),
+            _ => false,
+        }
+    }
+}
+
+impl Nes {
+    pub(crate) fn start_tutorial(&mut self) {
+        self.tutorial.start();
+        // Skip loading a ROM if one is already running
+        if self.control_deck.is_running() {
+            self.tutorial_event(TutorialEvent::RomLoaded);
+        }
+    }
+
+    pub(crate) fn tutorial_event(&mut self, event: TutorialEvent) {
+        if self.tutorial.handle(event) {
+            self.finish_tutorial();
+            self.add_message("Tutorial complete! Run it again from the command palette.");
+        }
+    }
+
+    /// Stops the tutorial and keeps it from showing on the next launch.
+    fn finish_tutorial(&mut self) {
+        self.tutorial.stop();
+        self.config.show_tutorial = false;
+        self.save_config();
+    }
+
+    /// Renders the current step in a panel along the bottom of the window and highlights where
+    /// to go next: the open menu, or the key bound to the action otherwise.
+    pub(crate) fn render_tutorial(&mut self, s: &mut PixState) -> PixResult<()> {
+        let Some(step) = self.tutorial.current() else {
+            return Ok(());
+        };
+        let number = self.tutorial.step.unwrap_or_default() + 1;
+
+        s.push();
+        let width = s.width()? as i32;
+        let height = s.height()? as i32;
+        let pad = s.theme().spacing.frame_pad;
+        let line_height = s.theme().font_size as i32 + 4 * s.theme().spacing.item_pad.y();
+        s.wrap((width - 2 * pad.x()) as u32);
+        let (_, text_height) = s.size_of(step.text)?;
+        let panel_height = text_height as i32 + 4 * line_height + 2 * pad.y();
+        let panel_top = height - panel_height;
+
+        // Pulse the highlight so it stands out against the game
+        let pulse = (s.elapsed().as_secs_f32() * 4.0).sin().mul_add(0.5, 0.5);
+        let mut highlight = Color::YELLOW;
+        highlight.set_alpha((105.0 + 150.0 * pulse) as u8);
+
+        s.stroke(None);
+        s.fill(rgb!(0, 220));
+        s.rect([0, panel_top, width, panel_height])?;
+
+        s.set_cursor_pos([pad.x(), panel_top + pad.y()]);
+        s.fill(Color::YELLOW);
+        s.text(format!("{} ({number}/{})", step.title, STEPS.len()))?;
+        s.fill(Color::WHITE);
+        s.text(step.text)?;
+
+        match step.action {
+            Action::Menu(menu) if self.mode == Mode::InMenu(menu) => {
+                s.stroke(highlight);
+                s.stroke_weight(3);
+                s.fill(None);
+                s.rect([2, 2, width - 4, panel_top - 4])?;
+                s.stroke(None);
+                s.stroke_weight(1);
+            }
+            action => {
+                let binding = self
+                    .key_bindings()
+                    .remove(&action)
+                    .unwrap_or_else(|| "Unbound, see Keybindings".to_string());
+                s.fill(Color::WHITE);
+                s.text("Key:")?;
+                s.same_line(None);
+                let pos = s.cursor_pos();
+                let (key_width, key_height) = s.size_of(&binding)?;
+                s.fill(None);
+                s.stroke(highlight);
+                s.stroke_weight(2);
+                s.rect([
+                    pos.x() - 4,
+                    pos.y() - 2,
+                    key_width as i32 + 8,
+                    key_height as i32 + 4,
+                ])?;
+                s.stroke(None);
+                s.stroke_weight(1);
+                s.fill(highlight);
+                s.text(&binding)?;
+            }
+        }
+
+        s.fill(Color::WHITE);
+        if step.until == TutorialEvent::Rewound && !self.config.rewind {
+            if s.button("Enable Rewind##tutorial")? {
+                self.config.rewind = true;
+            }
+            s.same_line(None);
+        }
+        if s.button("Skip Step##tutorial")? && self.tutorial.next() {
+            self.finish_tutorial();
+        }
+        s.same_line(None);
+        if s.button("Don't Show Again##tutorial")? {
+            self.finish_tutorial();
+        }
+        s.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn steps_advance_in_order() {
+        let mut tutorial = Tutorial::default();
+        assert_eq!(tutorial.current(), None);
+        assert!(!tutorial.handle(TutorialEvent::RomLoaded), "not started");
+
+        tutorial.start();
+        assert!(!tutorial.handle(TutorialEvent::StateSaved), "out of order");
+        assert_eq!(tutorial.current(), Some(&STEPS[0]));
+
+        assert!(!tutorial.handle(TutorialEvent::RomLoaded));
+        assert!(!tutorial.handle(TutorialEvent::StateSaved));
+        assert!(!tutorial.handle(TutorialEvent::Rewound));
+        assert!(tutorial.handle(TutorialEvent::DebuggerOpened));
+        assert_eq!(tutorial.current(), None);
+    }
+}
@@ -0,0 +1,372 @@
+//! Video recording of emulated gameplay with synchronized audio.
+//!
+//! Frames and audio samples are captured per emulated frame, so the resulting video always plays
+//! back at native speed regardless of fast-forward or pausing. Encoding is delegated to an
+//! external `ffmpeg` binary which must be available on the `PATH`.
+
+use crate::{
+    audio::{AudioMixer, NesAudioCallback},
+    common::NesRegion,
+    nes::Nes,
+    ppu::Ppu,
+    NesError, NesResult,
+};
+use anyhow::{anyhow, Context};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+};
+
+const FFMPEG: &str = "ffmpeg";
+const AUDIO_SAMPLE_RATE: f32 = 48_000.0;
+const AUDIO_BUFFER_SIZE: usize = 8192;
+
+/// Container and codec used for video recordings.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub(crate) enum VideoFormat {
+    /// MP4 container with x264 video and AAC audio.
+    #[default]
+    Mp4,
+    /// WebM container with VP9 video and Opus audio.
+    WebM,
+}
+
+impl VideoFormat {
+    pub(crate) const fn as_slice() -> &'static [Self] {
+        &[Self::Mp4, Self::WebM]
+    }
+
+    const fn extension(self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::WebM => "webm",
+        }
+    }
+
+    const fn video_codec(self) -> &'static [&'static str] {
+        match self {
+            Self::Mp4 => &["-c:v", "libx264", "-preset", "veryfast", "-crf", "18"],
+            Self::WebM => &["-c:v", "libvpx-vp9", "-b:v", "0", "-crf", "30"],
+        }
+    }
+
+    const fn audio_codec(self) -> &'static [&'static str] {
+        match self {
+            Self::Mp4 => &["-c:a", "aac", "-b:a", "192k"],
+            Self::WebM => &["-c:a", "libopus", "-b:a", "128k"],
+        }
+    }
+}
+
+impl AsRef<str> for VideoFormat {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Mp4 => "MP4 (x264)",
+            Self::WebM => "WebM (VP9)",
+        }
+    }
+}
+
+impl From<usize> for VideoFormat {
+    fn from(value: usize) -> Self {
+        if value == 1 {
+            Self::WebM
+        } else {
+            Self::Mp4
+        }
+    }
+}
+
+/// An in-progress video recording.
+#[must_use]
+pub(crate) struct VideoRecorder {
+    format: VideoFormat,
+    output: PathBuf,
+    video_path: PathBuf,
+    audio_path: PathBuf,
+    encoder: Child,
+    video: ChildStdin,
+    audio: BufWriter<File>,
+    mixer: AudioMixer,
+    samples: NesAudioCallback,
+    sample_buffer: Vec<f32>,
+    frames: u32,
+}
+
+impl VideoRecorder {
+    /// Starts a new recording, spawning the video encoder.
+    ///
+    /// # Errors
+    ///
+    /// If the output directory can't be created or the encoder fails to start, an error is
+    /// returned.
+    pub(crate) fn start<P: AsRef<Path>>(
+        dir: P,
+        format: VideoFormat,
+        region: NesRegion,
+        sample_rate: f32,
+    ) -> NesResult<Self> {
+        let dir = dir.as_ref();
+        if !dir.exists() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create directory {dir:?}"))?;
+        }
+        let name = Local::now()
+            .format("tetanes_%Y-%m-%d_at_%H-%M-%S")
+            .to_string();
+        let extension = format.extension();
+        let output = dir.join(format!("{name}.{extension}"));
+        let video_path = dir.join(format!("{name}.video.{extension}"));
+        let audio_path = dir.join(format!("{name}.pcm"));
+
+        let mut encoder = Command::new(FFMPEG)
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pixel_format", "rgba"])
+            .args(["-video_size", &format!("{}x{}", Ppu::WIDTH, Ppu::HEIGHT)])
+            .args(["-framerate", &region.frame_rate().to_string()])
+            .args(["-i", "-"])
+            // Trim top and bottom 8 scanlines and correct for the 8:7 pixel aspect ratio
+            .args(["-vf", "crop=256:224:0:8,setsar=8/7", "-pix_fmt", "yuv420p"])
+            .args(format.video_codec())
+            .arg(&video_path)
+            .stdin(Stdio::piped())
+            .spawn()
+            .context("failed to start `ffmpeg`. is it installed?")?;
+        let video = encoder
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("failed to open encoder input"))?;
+        let audio = BufWriter::new(
+            File::create(&audio_path)
+                .with_context(|| format!("failed to create file {audio_path:?}"))?,
+        );
+
+        let mut mixer = AudioMixer::new(sample_rate, AUDIO_SAMPLE_RATE, AUDIO_BUFFER_SIZE);
+        let samples = mixer.open_callback()?;
+
+        Ok(Self {
+            format,
+            output,
+            video_path,
+            audio_path,
+            encoder,
+            video,
+            audio,
+            mixer,
+            samples,
+            sample_buffer: Vec::with_capacity(AUDIO_BUFFER_SIZE),
+            frames: 0,
+        })
+    }
+
+    /// The final output path of this recording.
+    pub(crate) fn output(&self) -> &Path {
+        &self.output
+    }
+
+    /// Number of frames recorded so far.
+    pub(crate) const fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Writes a single emulated frame of RGBA pixels.
+    ///
+    /// # Errors
+    ///
+    /// If the encoder has stopped accepting input, an error is returned.
+    pub(crate) fn push_frame(&mut self, frame: &[u8]) -> NesResult<()> {
+        self.video
+            .write_all(frame)
+            .context("failed to write video frame")?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Resamples and writes emulated audio samples.
+    ///
+    /// # Errors
+    ///
+    /// If the audio samples fail to write to disk, an error is returned.
+    pub(crate) fn push_samples(&mut self, samples: &[f32]) -> NesResult<()> {
+        self.mixer.consume(samples, false, 0.0);
+        self.sample_buffer.resize(self.samples.len(), 0.0);
+        self.samples.read(&mut self.sample_buffer);
+        for sample in &self.sample_buffer {
+            self.audio
+                .write_all(&sample.to_le_bytes())
+                .context("failed to write audio samples")?;
+        }
+        Ok(())
+    }
+
+    /// Finishes encoding and muxes the audio and video streams into the final output file.
+    ///
+    /// # Errors
+    ///
+    /// If either encoding step fails, an error is returned.
+    pub(crate) fn finish(self) -> NesResult<PathBuf> {
+        let Self {
+            format,
+            output,
+            video_path,
+            audio_path,
+            mut encoder,
+            video,
+            mut audio,
+            ..
+        } = self;
+
+        audio.flush().context("failed to flush audio samples")?;
+        drop(audio);
+        // Closing stdin signals the encoder to finish
+        drop(video);
+        let status = encoder.wait().context("failed to wait for video encoder")?;
+        if !status.success() {
+            return Err(anyhow!("video encoder exited with {status}"));
+        }
+
+        let status = Command::new(FFMPEG)
+            .args(["-y", "-loglevel", "error"])
+            .arg("-i")
+            .arg(&video_path)
+            .args([
+                "-f",
+                "f32le",
+                "-ar",
+                &AUDIO_SAMPLE_RATE.to_string(),
+                "-ac",
+                "1",
+            ])
+            .arg("-i")
+            .arg(&audio_path)
+            .args(["-c:v", "copy"])
+            .args(format.audio_codec())
+            .arg("-shortest")
+            .arg(&output)
+            .status()
+            .context("failed to mux video recording")?;
+        if !status.success() {
+            return Err(anyhow!("video muxer exited with {status}"));
+        }
+
+        let _ = fs::remove_file(video_path);
+        let _ = fs::remove_file(audio_path);
+        Ok(output)
+    }
+
+    /// Stops the encoder and deletes the partial streams, leaving no output behind.
+    pub(crate) fn abort(self) {
+        let Self {
+            video_path,
+            audio_path,
+            mut encoder,
+            video,
+            audio,
+            ..
+        } = self;
+
+        drop(video);
+        drop(audio);
+        if let Err(err) = encoder.kill().and_then(|_| encoder.wait()) {
+            log::warn!("failed to stop video encoder: {err:?}");
+        }
+        let _ = fs::remove_file(video_path);
+        let _ = fs::remove_file(audio_path);
+    }
+}
+
+impl std::fmt::Debug for VideoRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VideoRecorder")
+            .field("format", &self.format)
+            .field("output", &self.output)
+            .field("frames", &self.frames)
+            .finish()
+    }
+}
+
+impl Nes {
+    pub(crate) fn toggle_video_recording(&mut self) {
+        if self.video_recorder.is_some() {
+            self.stop_video_recording();
+        } else {
+            self.start_video_recording();
+        }
+    }
+
+    pub(crate) fn start_video_recording(&mut self) {
+        if self.control_deck.loaded_rom().is_none() {
+            return;
+        }
+        match VideoRecorder::start(
+            &self.config.video_recording_dir,
+            self.config.video_format,
+            self.config.region,
+            self.control_deck.sample_rate(),
+        ) {
+            Ok(recorder) => {
+                self.video_recorder = Some(recorder);
+                self.add_message("Video Recording Started");
+            }
+            Err(err) => {
+                log::error!("{err:?}");
+                self.add_message("Failed to start video recording");
+            }
+        }
+    }
+
+    pub(crate) fn stop_video_recording(&mut self) {
+        if let Some(recorder) = self.video_recorder.take() {
+            log::info!(
+                "Finishing video recording {:?}: {} frames",
+                recorder.output(),
+                recorder.frames()
+            );
+            match recorder.finish() {
+                Ok(output) => self.add_message(format!("Saved video recording {output:?}")),
+                Err(err) => {
+                    log::error!("{err:?}");
+                    self.add_message("Failed to save video recording");
+                }
+            }
+        }
+    }
+
+    /// Captures `frames` emulated frames. Frames are counted in emulated time, so the latest frame
+    /// is duplicated if more than one frame was emulated since the last update.
+    pub(crate) fn record_video_frames(&mut self, frames: u32) {
+        if self.video_recorder.is_none() {
+            return;
+        }
+        let frame = self.control_deck.frame_buffer();
+        if let Some(ref mut recorder) = self.video_recorder {
+            let result = (0..frames).try_for_each(|_| recorder.push_frame(frame));
+            if let Err(err) = result {
+                self.abort_video_recording(&err);
+            }
+        }
+    }
+
+    /// Captures the audio generated since the last update.
+    pub(crate) fn record_video_samples(&mut self) {
+        if let Some(ref mut recorder) = self.video_recorder {
+            if let Err(err) = recorder.push_samples(self.control_deck.audio_samples()) {
+                self.abort_video_recording(&err);
+            }
+        }
+    }
+
+    /// Ends a recording that failed part way through, discarding what was recorded.
+    fn abort_video_recording(&mut self, err: &NesError) {
+        log::error!("{err:?}");
+        if let Some(recorder) = self.video_recorder.take() {
+            recorder.abort();
+        }
+        self.add_message("Video recording failed and was discarded");
+    }
+}
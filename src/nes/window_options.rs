@@ -0,0 +1,311 @@
+//! Window options pix-engine doesn't expose, set on the main window through SDL and, for
+//! click-through, the platform window underneath it.
+//!
+//! pix-engine creates the main window before any other, so it's the SDL window with the lowest
+//! ID. Click-through makes the window ignore the mouse so clicks go to the windows behind it:
+//! `WS_EX_TRANSPARENT` on Windows, `ignoresMouseEvents` on macOS and an empty input shape on X11.
+//! Wayland doesn't allow it.
+
+use crate::{nes::Nes, NesResult};
+use anyhow::{anyhow, bail};
+use std::{
+    ffi::{c_void, CStr},
+    os::raw::{c_char, c_int},
+};
+
+const SDL_FALSE: c_int = 0;
+const SDL_TRUE: c_int = 1;
+/// Highest window ID searched for the main window.
+const MAX_WINDOW_ID: u32 = 16;
+#[cfg(target_os = "windows")]
+const SYSWM_WINDOWS: c_int = 1;
+#[cfg(all(unix, not(target_os = "macos")))]
+const SYSWM_X11: c_int = 2;
+#[cfg(target_os = "macos")]
+const SYSWM_COCOA: c_int = 4;
+
+/// `SDL_SysWMinfo`, with the window system specific union as raw words.
+#[repr(C)]
+struct SysWmInfo {
+    version: [u8; 3],
+    subsystem: c_int,
+    info: [usize; 8],
+}
+
+extern "C" {
+    fn SDL_GetError() -> *const c_char;
+    fn SDL_GetWindowFromID(id: u32) -> *mut c_void;
+    fn SDL_GetWindowWMInfo(window: *mut c_void, info: *mut SysWmInfo) -> c_int;
+    fn SDL_SetWindowAlwaysOnTop(window: *mut c_void, on_top: c_int);
+    fn SDL_SetWindowBordered(window: *mut c_void, bordered: c_int);
+}
+
+const fn sdl_bool(value: bool) -> c_int {
+    if value {
+        SDL_TRUE
+    } else {
+        SDL_FALSE
+    }
+}
+
+fn sdl_error() -> String {
+    // SAFETY: SDL always returns a valid, possibly empty, string
+    unsafe { CStr::from_ptr(SDL_GetError()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn main_window() -> NesResult<*mut c_void> {
+    (1..=MAX_WINDOW_ID)
+        // SAFETY: Looking up an ID without a window returns null
+        .map(|id| unsafe { SDL_GetWindowFromID(id) })
+        .find(|window| !window.is_null())
+        .ok_or_else(|| anyhow!("failed to find the main window: {}", sdl_error()))
+}
+
+fn set_bordered(bordered: bool) -> NesResult<()> {
+    let window = main_window()?;
+    // SAFETY: The window was just looked up and is owned by pix-engine for the whole run
+    unsafe { SDL_SetWindowBordered(window, sdl_bool(bordered)) };
+    Ok(())
+}
+
+fn set_always_on_top(on_top: bool) -> NesResult<()> {
+    let window = main_window()?;
+    // SAFETY: The window was just looked up and is owned by pix-engine for the whole run
+    unsafe { SDL_SetWindowAlwaysOnTop(window, sdl_bool(on_top)) };
+    Ok(())
+}
+
+fn set_click_through(enabled: bool) -> NesResult<()> {
+    let window = main_window()?;
+    let mut info = SysWmInfo {
+        version: [2, 0, 16],
+        subsystem: 0,
+        info: [0; 8],
+    };
+    // SAFETY: The window was just looked up and info is laid out as SDL 2.0.16 expects
+    if unsafe { SDL_GetWindowWMInfo(window, &mut info) } == SDL_FALSE {
+        bail!("failed to get the native window: {}", sdl_error());
+    }
+    match info.subsystem {
+        #[cfg(target_os = "windows")]
+        SYSWM_WINDOWS => windows::set_click_through(info.info[0] as *mut c_void, enabled),
+        #[cfg(target_os = "macos")]
+        SYSWM_COCOA => macos::set_click_through(info.info[0] as *mut c_void, enabled),
+        #[cfg(all(unix, not(target_os = "macos")))]
+        SYSWM_X11 => x11::set_click_through(info.info[0] as *mut c_void, info.info[1], enabled),
+        _ => bail!("click-through isn't supported by this window system"),
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod windows {
+    use crate::NesResult;
+    use anyhow::bail;
+    use std::{ffi::c_void, os::raw::c_int};
+
+    const GWL_EXSTYLE: c_int = -20;
+    const WS_EX_TRANSPARENT: isize = 0x20;
+    const WS_EX_LAYERED: isize = 0x8_0000;
+    const LWA_ALPHA: u32 = 0x2;
+
+    #[link(name = "user32")]
+    extern "system" {
+        #[cfg_attr(target_pointer_width = "32", link_name = "GetWindowLongW")]
+        fn GetWindowLongPtrW(window: *mut c_void, index: c_int) -> isize;
+        #[cfg_attr(target_pointer_width = "32", link_name = "SetWindowLongW")]
+        fn SetWindowLongPtrW(window: *mut c_void, index: c_int, value: isize) -> isize;
+        fn SetLayeredWindowAttributes(window: *mut c_void, key: u32, alpha: u8, flags: u32) -> i32;
+    }
+
+    pub(super) fn set_click_through(window: *mut c_void, enabled: bool) -> NesResult<()> {
+        // SAFETY: The window handle comes from SDL and stays valid while the window is open
+        unsafe {
+            let style = GetWindowLongPtrW(window, GWL_EXSTYLE);
+            if enabled {
+                SetWindowLongPtrW(
+                    window,
+                    GWL_EXSTYLE,
+                    style | WS_EX_LAYERED | WS_EX_TRANSPARENT,
+                );
+                // Layered windows aren't drawn until their attributes are set
+                if SetLayeredWindowAttributes(window, 0, 255, LWA_ALPHA) == 0 {
+                    bail!("failed to make the window layered");
+                }
+            } else {
+                SetWindowLongPtrW(
+                    window,
+                    GWL_EXSTYLE,
+                    style & !(WS_EX_LAYERED | WS_EX_TRANSPARENT),
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use crate::NesResult;
+    use std::{ffi::c_void, os::raw::c_char};
+
+    #[link(name = "objc")]
+    extern "C" {
+        fn sel_registerName(name: *const c_char) -> *mut c_void;
+        fn objc_msgSend();
+    }
+
+    pub(super) fn set_click_through(window: *mut c_void, enabled: bool) -> NesResult<()> {
+        // SAFETY: The window is the NSWindow from SDL, and objc_msgSend is called with the
+        // signature of `-[NSWindow setIgnoresMouseEvents:]`
+        unsafe {
+            let send: unsafe extern "C" fn(*mut c_void, *mut c_void, i8) =
+                std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+            let selector = sel_registerName(b"setIgnoresMouseEvents:\0".as_ptr().cast());
+            send(window, selector, i8::from(enabled));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod x11 {
+    use crate::NesResult;
+    use anyhow::anyhow;
+    use std::{
+        ffi::c_void,
+        os::raw::{c_char, c_int, c_ulong},
+        ptr,
+    };
+
+    const RTLD_NOW: c_int = 2;
+    const SHAPE_INPUT: c_int = 2;
+    const SHAPE_SET: c_int = 0;
+    const UNSORTED: c_int = 0;
+
+    type CombineRectangles = unsafe extern "C" fn(
+        *mut c_void,
+        c_ulong,
+        c_int,
+        c_int,
+        c_int,
+        *mut c_void,
+        c_int,
+        c_int,
+        c_int,
+    );
+    type CombineMask =
+        unsafe extern "C" fn(*mut c_void, c_ulong, c_int, c_int, c_int, c_ulong, c_int);
+    type Flush = unsafe extern "C" fn(*mut c_void) -> c_int;
+
+    extern "C" {
+        fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    }
+
+    /// Looks up `symbol` in `library`, which SDL already loads for X11 or is loaded here.
+    fn symbol(library: &[u8], symbol: &[u8]) -> NesResult<*mut c_void> {
+        // SAFETY: Both names are nul-terminated
+        let address = unsafe {
+            let handle = dlopen(library.as_ptr().cast(), RTLD_NOW);
+            if handle.is_null() {
+                ptr::null_mut()
+            } else {
+                dlsym(handle, symbol.as_ptr().cast())
+            }
+        };
+        if address.is_null() {
+            Err(anyhow!(
+                "failed to load {}",
+                String::from_utf8_lossy(&symbol[..symbol.len() - 1])
+            ))
+        } else {
+            Ok(address)
+        }
+    }
+
+    pub(super) fn set_click_through(
+        display: *mut c_void,
+        window: usize,
+        enabled: bool,
+    ) -> NesResult<()> {
+        let window = window as c_ulong;
+        let flush = symbol(b"libX11.so.6\0", b"XFlush\0")?;
+        // SAFETY: The symbols are the Xlib and XShape functions with these signatures, and the
+        // display and window come from SDL
+        unsafe {
+            if enabled {
+                // An empty input shape lets every click through
+                let combine = symbol(b"libXext.so.6\0", b"XShapeCombineRectangles\0")?;
+                let combine: CombineRectangles = std::mem::transmute(combine);
+                combine(
+                    display,
+                    window,
+                    SHAPE_INPUT,
+                    0,
+                    0,
+                    ptr::null_mut(),
+                    0,
+                    SHAPE_SET,
+                    UNSORTED,
+                );
+            } else {
+                // No mask restores the default input shape
+                let combine = symbol(b"libXext.so.6\0", b"XShapeCombineMask\0")?;
+                let combine: CombineMask = std::mem::transmute(combine);
+                combine(display, window, SHAPE_INPUT, 0, 0, 0, SHAPE_SET);
+            }
+            let flush: Flush = std::mem::transmute(flush);
+            flush(display);
+        }
+        Ok(())
+    }
+}
+
+impl Nes {
+    /// Applies the window options pix-engine can't set when the window is created.
+    pub(crate) fn apply_window_options(&mut self) {
+        if self.config.always_on_top {
+            self.set_always_on_top(true);
+        }
+        if self.config.click_through {
+            self.set_click_through(true);
+        }
+    }
+
+    /// Shows or hides the main window borders.
+    pub(crate) fn set_window_bordered(&mut self, bordered: bool) {
+        if let Err(err) = set_bordered(bordered) {
+            log::error!("{err:?}");
+        }
+    }
+
+    /// Keeps the main window above other windows, even when they're focused.
+    pub(crate) fn set_always_on_top(&mut self, enabled: bool) {
+        self.config.always_on_top = enabled;
+        if let Err(err) = set_always_on_top(enabled) {
+            log::error!("{err:?}");
+            self.add_message("Failed to change Always on Top");
+        }
+    }
+
+    /// Lets mouse clicks go through the main window to the windows behind it.
+    pub(crate) fn set_click_through(&mut self, enabled: bool) {
+        match set_click_through(enabled) {
+            Ok(()) => {
+                self.config.click_through = enabled;
+                if enabled {
+                    self.add_message("Click-Through Enabled");
+                } else {
+                    self.add_message("Click-Through Disabled");
+                }
+            }
+            Err(err) => {
+                log::error!("{err:?}");
+                self.config.click_through = false;
+                self.add_message("Click-through isn't supported on this system");
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Conditions that stop a run, for automated pass/fail checks against test ROMs that don't use
+//! the status protocol in [`harness`](crate::harness).
+//!
+//! A condition is written as `pc=C66E`, `mem=6000:00` or `opcode=02`, with an optional exit code
+//! after a slash, e.g. `mem=6000:01/1`. Values are hexadecimal and can be prefixed with `$` or
+//! `0x`. Conditions are checked between instructions, so `pc` and `opcode` conditions stop before
+//! the instruction at the program counter runs.
+//!
+//! For example, to pass when a test ROM reaches the address it loops at once finished, and fail
+//! if it writes an error code to `$02` or jams on opcode `$02` first:
+//!
+//! ```text
+//! tetanes run test.nes --headless --frames 600 --stop-at pc=C66E --stop-at mem=02:01/1 \
+//!     --stop-at opcode=02/2
+//! ```
+
+use crate::{
+    cpu::Cpu,
+    mem::{Access, Mem},
+};
+use anyhow::{anyhow, bail, Context};
+use std::{fmt, str::FromStr};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub enum Condition {
+    /// The program counter reaches an address.
+    Pc(u16),
+    /// A memory address holds a value.
+    Mem { addr: u16, value: u8 },
+    /// The next instruction has an opcode.
+    Opcode(u8),
+}
+
+/// A condition and the status code to exit with when it's met.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct StopCondition {
+    pub condition: Condition,
+    pub exit_code: i32,
+}
+
+impl StopCondition {
+    /// Whether the condition is met by the current state of `cpu`.
+    #[must_use]
+    pub fn check(&self, cpu: &Cpu) -> bool {
+        match self.condition {
+            Condition::Pc(addr) => cpu.pc() == addr,
+            Condition::Mem { addr, value } => cpu.peek(addr, Access::Dummy) == value,
+            Condition::Opcode(opcode) => cpu.peek(cpu.pc(), Access::Dummy) == opcode,
+        }
+    }
+
+    /// The first of `conditions` met by the current state of `cpu`.
+    #[must_use]
+    pub fn first_met(conditions: &[Self], cpu: &Cpu) -> Option<Self> {
+        conditions
+            .iter()
+            .copied()
+            .find(|condition| condition.check(cpu))
+    }
+}
+
+fn parse_hex<T: TryFrom<u32>>(value: &str) -> anyhow::Result<T> {
+    let value = value.trim();
+    let digits = value
+        .strip_prefix('$')
+        .or_else(|| value.strip_prefix("0x"))
+        .unwrap_or(value);
+    u32::from_str_radix(digits, 16)
+        .ok()
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or_else(|| anyhow!("invalid hexadecimal value `{value}`"))
+}
+
+impl FromStr for StopCondition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (condition, exit_code) = match s.split_once('/') {
+            Some((condition, exit_code)) => (
+                condition,
+                exit_code
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("invalid exit code `{exit_code}`"))?,
+            ),
+            None => (s, 0),
+        };
+        let (kind, value) = condition
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected `pc=`, `mem=` or `opcode=`, found `{s}`"))?;
+        let condition = match kind.trim() {
+            "pc" => Condition::Pc(parse_hex(value)?),
+            "mem" => {
+                let (addr, value) = value
+                    .split_once(':')
+                    .ok_or_else(|| anyhow!("expected `mem=address:value`, found `{s}`"))?;
+                Condition::Mem {
+                    addr: parse_hex(addr)?,
+                    value: parse_hex(value)?,
+                }
+            }
+            "opcode" => Condition::Opcode(parse_hex(value)?),
+            kind => bail!("unknown stop condition `{kind}`"),
+        };
+        Ok(Self {
+            condition,
+            exit_code,
+        })
+    }
+}
+
+impl fmt::Display for StopCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.condition {
+            Condition::Pc(addr) => write!(f, "pc=${addr:04X}")?,
+            Condition::Mem { addr, value } => write!(f, "mem=${addr:04X}:${value:02X}")?,
+            Condition::Opcode(opcode) => write!(f, "opcode=${opcode:02X}")?,
+        }
+        write!(f, " (exit code {})", self.exit_code)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_stop_conditions() {
+        assert_eq!(
+            "pc=C66E".parse::<StopCondition>().expect("valid condition"),
+            StopCondition {
+                condition: Condition::Pc(0xC66E),
+                exit_code: 0,
+            }
+        );
+        assert_eq!(
+            "mem=$6000:0x01/1"
+                .parse::<StopCondition>()
+                .expect("valid condition"),
+            StopCondition {
+                condition: Condition::Mem {
+                    addr: 0x6000,
+                    value: 0x01
+                },
+                exit_code: 1,
+            }
+        );
+        assert_eq!(
+            "opcode=02/3"
+                .parse::<StopCondition>()
+                .expect("valid condition")
+                .to_string(),
+            "opcode=$02 (exit code 3)"
+        );
+        for invalid in [
+            "pc",
+            "pc=10000",
+            "mem=6000",
+            "opcode=100",
+            "sp=FD",
+            "pc=C000/x",
+        ] {
+            assert!(invalid.parse::<StopCondition>().is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,24 @@
+use std::env;
+use tetanes::harness::{run_manifest, TestManifest};
+
+const MANIFEST: &str = "test_roms/manifest.json";
+
+// Set TETANES_TEST_MANIFEST to run a different manifest and TETANES_TEST_FILTER to only run ROMs
+// whose path contains the filter
+#[test]
+fn test_rom_manifest() {
+    let path = env::var("TETANES_TEST_MANIFEST").unwrap_or_else(|_| MANIFEST.to_string());
+    let filter = env::var("TETANES_TEST_FILTER").ok();
+    let manifest = TestManifest::from_path(&path).expect("valid test manifest");
+    let results = run_manifest(&manifest, filter.as_deref()).expect("valid test roms");
+
+    let mut failed = 0;
+    for (rom, result) in &results {
+        println!("test {} ... {result}", rom.name());
+        if result.failed() {
+            failed += 1;
+        }
+    }
+    println!("{} test roms, {failed} failed", results.len());
+    assert_eq!(failed, 0, "{failed} test roms failed");
+}
@@ -0,0 +1,77 @@
+//! Compares the vectorized video filters against straightforward per-pixel loops.
+//!
+//! Run with `cargo bench -p tetanes-core --bench video`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tetanes_core::{
+    ppu::Ppu,
+    video::{Video, NTSC_PALETTE},
+};
+
+fn frame() -> Vec<u16> {
+    // Cheap hash so every pixel and emphasis combination shows up
+    (0..Ppu::SIZE as u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 23) as u16)
+        .collect()
+}
+
+fn decode_per_pixel(buffer: &[u16], output: &mut [u8]) {
+    for (pixel, colors) in buffer.iter().zip(output.chunks_exact_mut(4)) {
+        let (red, green, blue) = Ppu::system_palette(*pixel);
+        colors[0] = red;
+        colors[1] = green;
+        colors[2] = blue;
+    }
+}
+
+fn ntsc_per_pixel(buffer: &[u16], output: &mut [u8], frame_number: u32) {
+    let mut prev_pixel = 0;
+    for (idx, (pixel, colors)) in buffer.iter().zip(output.chunks_exact_mut(4)).enumerate() {
+        let x = idx % 256;
+        let color = if x == 0 {
+            0
+        } else {
+            let y = idx / 256;
+            let even_phase = if frame_number & 0x01 == 0x01 { 0 } else { 1 };
+            let phase = (2 + y * 341 + x + even_phase) % 3;
+            NTSC_PALETTE[phase + ((prev_pixel & 0x3F) as usize) * 3 + (*pixel as usize) * 3 * 64]
+        };
+        prev_pixel = u32::from(*pixel);
+        colors[0] = (color >> 16 & 0xFF) as u8;
+        colors[1] = (color >> 8 & 0xFF) as u8;
+        colors[2] = (color & 0xFF) as u8;
+    }
+}
+
+fn decode(c: &mut Criterion) {
+    let buffer = frame();
+    let mut output = vec![0xFF; 4 * Ppu::SIZE];
+    let mut video = Video::new();
+    let mut group = c.benchmark_group("decode");
+    group.bench_function("per_pixel", |b| {
+        b.iter(|| decode_per_pixel(black_box(&buffer), &mut output));
+    });
+    group.bench_function("simd", |b| {
+        b.iter(|| video.decode_buffer(black_box(&buffer)))
+    });
+    group.finish();
+}
+
+fn ntsc(c: &mut Criterion) {
+    let buffer = frame();
+    let mut output = vec![0xFF; 4 * Ppu::SIZE];
+    let mut video = Video::new();
+    // Build the palette up front so it isn't measured
+    black_box(NTSC_PALETTE.len());
+    let mut group = c.benchmark_group("ntsc");
+    group.bench_function("per_pixel", |b| {
+        b.iter(|| ntsc_per_pixel(black_box(&buffer), &mut output, 1));
+    });
+    group.bench_function("simd", |b| {
+        b.iter(|| video.apply_ntsc_filter(black_box(&buffer), 1));
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode, ntsc);
+criterion_main!(benches);
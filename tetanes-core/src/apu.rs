@@ -8,7 +8,7 @@ use crate::{
     },
     audio::Audio,
     common::{Clock, Kind, NesRegion, Regional, Reset},
-    cpu::Irq,
+    cpu::{Cpu, Irq},
 };
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
@@ -34,6 +34,92 @@ pub enum Channel {
     Dmc,
 }
 
+impl Channel {
+    #[inline]
+    #[must_use]
+    pub const fn as_slice() -> &'static [Self] {
+        &[
+            Self::Pulse1,
+            Self::Pulse2,
+            Self::Triangle,
+            Self::Noise,
+            Self::Dmc,
+        ]
+    }
+}
+
+/// How channel outputs are combined into the final APU output.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub enum ApuMixing {
+    /// Nonlinear mixer formulas with separate weights for the triangle, noise and DMC channels.
+    #[default]
+    Accurate,
+    /// Lookup tables that approximate the triangle, noise and DMC weights with a linear sum.
+    Legacy,
+}
+
+impl ApuMixing {
+    #[must_use]
+    pub const fn as_slice() -> &'static [Self] {
+        &[Self::Accurate, Self::Legacy]
+    }
+}
+
+impl AsRef<str> for ApuMixing {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Accurate => "Accurate",
+            Self::Legacy => "Legacy",
+        }
+    }
+}
+
+impl From<usize> for ApuMixing {
+    fn from(value: usize) -> Self {
+        if value == 1 {
+            Self::Legacy
+        } else {
+            Self::Accurate
+        }
+    }
+}
+
+impl AsRef<str> for Channel {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Pulse1 => "Pulse 1",
+            Self::Pulse2 => "Pulse 2",
+            Self::Triangle => "Triangle",
+            Self::Noise => "Noise",
+            Self::Dmc => "DMC",
+        }
+    }
+}
+
+/// State of a channel for debugging displays, like the APU viewer.
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
+#[must_use]
+pub struct ChannelState {
+    /// Enabled through `$4015`.
+    pub enabled: bool,
+    /// Timer period reload value.
+    pub period: u16,
+    /// Tone frequency in Hz, the rate the noise shift register is clocked, or the DMC bit rate.
+    pub frequency: f32,
+    /// Volume from 0-15, or the DMC output level from 0-127. The triangle has no volume control,
+    /// so it's 15 while it's running.
+    pub volume: u8,
+    /// Whether the volume comes from the decaying envelope rather than being constant.
+    pub envelope: bool,
+    /// Length counter, or bytes left in the DMC sample.
+    pub length: u16,
+    /// Pulse duty cycle index, or 1 for the noise short mode or a looping DMC sample.
+    pub mode: u8,
+    /// Triangle linear counter.
+    pub linear_counter: u8,
+}
+
 pub trait ApuRegisters {
     fn write_ctrl(&mut self, channel: Channel, val: u8);
     fn write_sweep(&mut self, channel: Channel, val: u8);
@@ -62,6 +148,13 @@ pub struct Apu {
     triangle: Triangle,
     noise: Noise,
     dmc: Dmc,
+    // A user preference that `ControlDeck` restores after loading a state, so it's not saved
+    #[serde(skip, default = "Apu::default_volumes")]
+    volumes: [f32; 5], // Mixer volume for each channel in `Channel` order
+    #[serde(skip)]
+    mixing: ApuMixing, // Set from the config, like the volumes
+    #[serde(skip)]
+    registers: [u8; 0x18], // Last values written to $4000-$4017
 }
 
 impl Apu {
@@ -77,9 +170,16 @@ impl Apu {
             triangle: Triangle::new(),
             noise: Noise::new(),
             dmc: Dmc::new(),
+            volumes: [1.0; 5],
+            mixing: ApuMixing::default(),
+            registers: [0x00; 0x18],
         }
     }
 
+    const fn default_volumes() -> [f32; 5] {
+        [1.0; 5]
+    }
+
     #[inline]
     #[must_use]
     pub const fn channel_enabled(&self, channel: Channel) -> bool {
@@ -103,6 +203,81 @@ impl Apu {
         }
     }
 
+    /// Mixer volume of a channel, from `0.0` (muted) to `1.0` (full volume).
+    #[inline]
+    #[must_use]
+    pub const fn channel_volume(&self, channel: Channel) -> f32 {
+        self.volumes[channel as usize]
+    }
+
+    #[inline]
+    pub fn set_channel_volume(&mut self, channel: Channel, volume: f32) {
+        self.volumes[channel as usize] = volume.clamp(0.0, 1.0);
+    }
+
+    #[inline]
+    pub const fn mixing(&self) -> ApuMixing {
+        self.mixing
+    }
+
+    #[inline]
+    pub fn set_mixing(&mut self, mixing: ApuMixing) {
+        self.mixing = mixing;
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn dmc_pop_reduction(&self) -> bool {
+        self.dmc.pop_reduction()
+    }
+
+    /// Enables or disables smoothing large jumps caused by writing the DMC output level directly.
+    #[inline]
+    pub fn set_dmc_pop_reduction(&mut self, enabled: bool) {
+        self.dmc.set_pop_reduction(enabled);
+    }
+
+    /// Output of each channel in `Channel` order, run through the mixer as if it were the only
+    /// channel playing. Used to record isolated channels.
+    #[must_use]
+    pub fn channel_outputs(&self) -> [f32; 5] {
+        let pulse = |output: f32| PULSE_TABLE[(output as usize) % PULSE_TABLE.len()];
+        let tnd = |output: f32| TND_TABLE[(output as usize) % TND_TABLE.len()];
+        [
+            pulse(self.pulse1.output()),
+            pulse(self.pulse2.output()),
+            tnd(3.0 * self.triangle.output()),
+            tnd(2.0 * self.noise.output()),
+            tnd(self.dmc.output()),
+        ]
+    }
+
+    /// State of a channel for debugging displays.
+    pub fn channel_state(&self, channel: Channel) -> ChannelState {
+        let clock_rate = Cpu::region_clock_rate(self.region);
+        match channel {
+            Channel::Pulse1 => self.pulse1.state(clock_rate),
+            Channel::Pulse2 => self.pulse2.state(clock_rate),
+            Channel::Triangle => self.triangle.state(clock_rate),
+            Channel::Noise => self.noise.state(clock_rate),
+            Channel::Dmc => self.dmc.state(clock_rate),
+        }
+    }
+
+    /// Last values written to `$4000`-`$4017`. The registers are write-only, so these are what
+    /// the game wrote rather than anything read back from the APU. They start at zero after
+    /// loading a save state.
+    #[inline]
+    #[must_use]
+    pub const fn registers(&self) -> &[u8; 0x18] {
+        &self.registers
+    }
+
+    #[inline]
+    fn log_write(&mut self, addr: u16, val: u8) {
+        self.registers[usize::from(addr - 0x4000)] = val;
+    }
+
     #[inline]
     pub fn irqs_pending(&self) -> Irq {
         let mut irq = Irq::empty();
@@ -194,9 +369,18 @@ impl ApuRegisters for Apu {
     // $4000 Pulse1, $4004 Pulse2, and $400C Noise Control
     fn write_ctrl(&mut self, channel: Channel, val: u8) {
         match channel {
-            Channel::Pulse1 => self.pulse1.write_ctrl(val),
-            Channel::Pulse2 => self.pulse2.write_ctrl(val),
-            Channel::Noise => self.noise.write_ctrl(val),
+            Channel::Pulse1 => {
+                self.log_write(0x4000, val);
+                self.pulse1.write_ctrl(val);
+            }
+            Channel::Pulse2 => {
+                self.log_write(0x4004, val);
+                self.pulse2.write_ctrl(val);
+            }
+            Channel::Noise => {
+                self.log_write(0x400C, val);
+                self.noise.write_ctrl(val);
+            }
             _ => panic!("{channel:?} does not have a control register"),
         }
     }
@@ -204,8 +388,14 @@ impl ApuRegisters for Apu {
     // $4001 Pulse1 and $4005 Pulse2 Sweep
     fn write_sweep(&mut self, channel: Channel, val: u8) {
         match channel {
-            Channel::Pulse1 => self.pulse1.write_sweep(val),
-            Channel::Pulse2 => self.pulse2.write_sweep(val),
+            Channel::Pulse1 => {
+                self.log_write(0x4001, val);
+                self.pulse1.write_sweep(val);
+            }
+            Channel::Pulse2 => {
+                self.log_write(0x4005, val);
+                self.pulse2.write_sweep(val);
+            }
             _ => panic!("{channel:?} does not have a sweep register"),
         }
     }
@@ -213,20 +403,44 @@ impl ApuRegisters for Apu {
     // $4002 Pulse1, $4006 Pulse2, $400A Triangle, $400E Noise, and $4010 DMC Timer Low Byte
     fn write_timer_lo(&mut self, channel: Channel, val: u8) {
         match channel {
-            Channel::Pulse1 => self.pulse1.write_timer_lo(val),
-            Channel::Pulse2 => self.pulse2.write_timer_lo(val),
-            Channel::Triangle => self.triangle.write_timer_lo(val),
-            Channel::Noise => self.noise.write_timer(val),
-            Channel::Dmc => self.dmc.write_timer(val),
+            Channel::Pulse1 => {
+                self.log_write(0x4002, val);
+                self.pulse1.write_timer_lo(val);
+            }
+            Channel::Pulse2 => {
+                self.log_write(0x4006, val);
+                self.pulse2.write_timer_lo(val);
+            }
+            Channel::Triangle => {
+                self.log_write(0x400A, val);
+                self.triangle.write_timer_lo(val);
+            }
+            Channel::Noise => {
+                self.log_write(0x400E, val);
+                self.noise.write_timer(val);
+            }
+            Channel::Dmc => {
+                self.log_write(0x4010, val);
+                self.dmc.write_timer(val);
+            }
         }
     }
 
     // $4003 Pulse1, $4007 Pulse2, and $400B Triangle Timer High Byte
     fn write_timer_hi(&mut self, channel: Channel, val: u8) {
         match channel {
-            Channel::Pulse1 => self.pulse1.write_timer_hi(val),
-            Channel::Pulse2 => self.pulse2.write_timer_hi(val),
-            Channel::Triangle => self.triangle.write_timer_hi(val),
+            Channel::Pulse1 => {
+                self.log_write(0x4003, val);
+                self.pulse1.write_timer_hi(val);
+            }
+            Channel::Pulse2 => {
+                self.log_write(0x4007, val);
+                self.pulse2.write_timer_hi(val);
+            }
+            Channel::Triangle => {
+                self.log_write(0x400B, val);
+                self.triangle.write_timer_hi(val);
+            }
             _ => panic!("{channel:?} does not have a timer_hi register"),
         }
     }
@@ -234,6 +448,7 @@ impl ApuRegisters for Apu {
     // $4008 Triangle Linear Counter
     fn write_linear_counter(&mut self, channel: Channel, val: u8) {
         if channel == Channel::Triangle {
+            self.log_write(0x4008, val);
             self.triangle.write_linear_counter(val);
         } else {
             panic!("{channel:?} does not have a linear_counter register");
@@ -243,8 +458,14 @@ impl ApuRegisters for Apu {
     // $400F Noise and $4013 DMC Length
     fn write_length(&mut self, channel: Channel, val: u8) {
         match channel {
-            Channel::Noise => self.noise.write_length(val),
-            Channel::Dmc => self.dmc.write_length(val),
+            Channel::Noise => {
+                self.log_write(0x400F, val);
+                self.noise.write_length(val);
+            }
+            Channel::Dmc => {
+                self.log_write(0x4013, val);
+                self.dmc.write_length(val);
+            }
             _ => panic!("{channel:?} does not have a length register"),
         }
     }
@@ -252,6 +473,7 @@ impl ApuRegisters for Apu {
     // $4011 DMC Output
     fn write_output(&mut self, channel: Channel, val: u8) {
         if channel == Channel::Dmc {
+            self.log_write(0x4011, val);
             // Only 7-bits are used
             self.dmc.write_output(val & 0x7F);
         } else {
@@ -262,6 +484,7 @@ impl ApuRegisters for Apu {
     // $4012 DMC Addr Load
     fn write_addr_load(&mut self, channel: Channel, val: u8) {
         if channel == Channel::Dmc {
+            self.log_write(0x4012, val);
             self.dmc.write_addr_load(val);
         } else {
             panic!("{channel:?} does not have addr_load register");
@@ -324,6 +547,7 @@ impl ApuRegisters for Apu {
     //       |   4 | Channel 5, 1 = enable sound
     //       | 5-7 | Unused (???)
     fn write_status(&mut self, val: u8) {
+        self.log_write(0x4015, val);
         self.pulse1.set_enabled(val & 0x01 == 0x01);
         self.pulse2.set_enabled(val & 0x02 == 0x02);
         self.triangle.set_enabled(val & 0x04 == 0x04);
@@ -333,6 +557,7 @@ impl ApuRegisters for Apu {
 
     // $4017 APU Frame Counter
     fn write_frame_counter(&mut self, val: u8) {
+        self.log_write(0x4017, val);
         self.frame_counter.write(val, self.cycle);
         self.irq_disabled = val & 0x40 == 0x40; // D6
         if self.irq_disabled {
@@ -341,14 +566,46 @@ impl ApuRegisters for Apu {
     }
 }
 
-impl Audio for Apu {
-    #[must_use]
-    fn output(&self) -> f32 {
-        let pulse1 = self.pulse1.output();
-        let pulse2 = self.pulse2.output();
-        let triangle = self.triangle.output();
-        let noise = self.noise.output();
-        let dmc = self.dmc.output();
+impl Apu {
+    /// Mixes channel outputs, in `Channel` order, into the final APU output.
+    fn mix(&self, [pulse1, pulse2, triangle, noise, dmc]: [f32; 5]) -> f32 {
+        if self.mixing == ApuMixing::Accurate {
+            // https://www.nesdev.org/wiki/APU_Mixer
+            let [pulse1_vol, pulse2_vol, triangle_vol, noise_vol, dmc_vol] = self.volumes;
+            let pulse = pulse1_vol.mul_add(pulse1, pulse2_vol * pulse2);
+            let tnd = (triangle_vol * triangle / 8_227.0)
+                + (noise_vol * noise / 12_241.0)
+                + (dmc_vol * dmc / 22_638.0);
+            let pulse_out = if pulse > 0.0 {
+                95.88 / (8_128.0 / pulse + 100.0)
+            } else {
+                0.0
+            };
+            let tnd_out = if tnd > 0.0 {
+                159.79 / (1.0 / tnd + 100.0)
+            } else {
+                0.0
+            };
+            return pulse_out + tnd_out;
+        }
+        if self.volumes != [1.0; 5] {
+            // Scaled levels fall between table entries, so use the mixer formulas directly
+            let [pulse1_vol, pulse2_vol, triangle_vol, noise_vol, dmc_vol] = self.volumes;
+            let pulse = pulse1_vol.mul_add(pulse1, pulse2_vol * pulse2);
+            let tnd = (3.0 * triangle_vol)
+                .mul_add(triangle, (2.0 * noise_vol).mul_add(noise, dmc_vol * dmc));
+            let pulse_out = if pulse > 0.0 {
+                95.52 / (8_128.0 / pulse + 100.0)
+            } else {
+                0.0
+            };
+            let tnd_out = if tnd > 0.0 {
+                163.67 / (24_329.0 / tnd + 100.0)
+            } else {
+                0.0
+            };
+            return pulse_out + tnd_out;
+        }
         let mut pulse_idx = (pulse1 + pulse2) as usize;
         if pulse_idx > PULSE_TABLE.len() {
             pulse_idx %= PULSE_TABLE.len();
@@ -361,6 +618,19 @@ impl Audio for Apu {
     }
 }
 
+impl Audio for Apu {
+    #[must_use]
+    fn output(&self) -> f32 {
+        self.mix([
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        ])
+    }
+}
+
 impl Clock for Apu {
     fn clock(&mut self) -> usize {
         self.dmc.check_pending_dma();
@@ -419,6 +689,9 @@ impl std::fmt::Debug for Apu {
             .field("triangle", &self.triangle)
             .field("noise", &self.noise)
             .field("dmc", &self.dmc)
+            .field("volumes", &self.volumes)
+            .field("mixing", &self.mixing)
+            .field("registers", &self.registers)
             .finish()
     }
 }
@@ -444,3 +717,79 @@ impl Apu {
         self.cycle
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_roms;
+
+    #[test]
+    fn channel_state() {
+        let mut apu = Apu::new();
+        apu.write_status(0x01);
+        apu.write_ctrl(Channel::Pulse1, 0xBF);
+        apu.write_timer_lo(Channel::Pulse1, 0xFD);
+        apu.write_timer_hi(Channel::Pulse1, 0x08);
+        let state = apu.channel_state(Channel::Pulse1);
+        assert!(state.enabled);
+        assert_eq!(state.period, 0x0FD);
+        assert_eq!(state.mode, 2);
+        assert_eq!(state.volume, 15);
+        assert!(!state.envelope);
+        // A4 at 440 Hz
+        assert!((state.frequency - 440.0).abs() < 1.0, "{}", state.frequency);
+        assert_eq!(&apu.registers()[..4], &[0xBF, 0x00, 0xFD, 0x08]);
+        assert_eq!(apu.registers()[0x15], 0x01);
+    }
+
+    #[test]
+    fn channel_volumes_are_not_saved() {
+        let mut apu = Apu::new();
+        apu.set_channel_volume(Channel::Triangle, 0.5);
+        let state = serde_json::to_string(&apu).expect("serialized apu");
+        let apu: Apu = serde_json::from_str(&state).expect("deserialized apu");
+        assert!((apu.channel_volume(Channel::Triangle) - 1.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn mixing_settings_are_not_saved() {
+        let mut apu = Apu::new();
+        apu.set_mixing(ApuMixing::Legacy);
+        apu.set_dmc_pop_reduction(true);
+        let state = serde_json::to_string(&apu).expect("serialized apu");
+        let apu: Apu = serde_json::from_str(&state).expect("deserialized apu");
+        assert_eq!(apu.mixing(), ApuMixing::Accurate);
+        assert!(!apu.dmc_pop_reduction());
+    }
+
+    #[test]
+    fn nonlinear_mixer() {
+        let mut apu = Apu::new();
+        assert_eq!(apu.mix([0.0; 5]), 0.0);
+
+        let pulse = 95.88 / (8_128.0 / 30.0 + 100.0);
+        assert!((apu.mix([15.0, 15.0, 0.0, 0.0, 0.0]) - pulse).abs() < 1e-6);
+        let tnd = 159.79 / (1.0 / (127.0 / 22_638.0) + 100.0);
+        assert!((apu.mix([0.0, 0.0, 0.0, 0.0, 127.0]) - tnd).abs() < 1e-6);
+
+        let outputs = [8.0, 4.0, 15.0, 6.0, 64.0];
+        let pulse = 95.88 / (8_128.0 / 12.0 + 100.0);
+        let tnd = 159.79 / (1.0 / (15.0 / 8_227.0 + 6.0 / 12_241.0 + 64.0 / 22_638.0) + 100.0);
+        let accurate = apu.mix(outputs);
+        assert!((accurate - (pulse + tnd)).abs() < 1e-6, "{accurate}");
+
+        apu.set_mixing(ApuMixing::Legacy);
+        let legacy = apu.mix(outputs);
+        assert_eq!(legacy, PULSE_TABLE[12] + TND_TABLE[3 * 15 + 2 * 6 + 64]);
+        assert!((legacy - accurate).abs() > 1e-4, "{legacy} {accurate}");
+    }
+
+    test_roms!(
+        "test_roms/apu",
+        dmc_dma_2007_read,
+        dmc_dma_2007_write,
+        dmc_dma_4016_read,
+        dmc_dma_double_2007_read,
+        dmc_dma_read_write_2007,
+    );
+}
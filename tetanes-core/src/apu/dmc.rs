@@ -1,4 +1,8 @@
-use crate::common::{Clock, Kind, NesRegion, Regional, Reset};
+use crate::{
+    apu::ChannelState,
+    common::{Clock, Kind, NesRegion, Regional, Reset},
+    cpu::Cpu,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -23,6 +27,15 @@ pub struct Dmc {
     output_bits: u8,
     output_shift: u8,
     output_silent: bool,
+    // Pop reduction is a setting restored after loading a state, and the slewing it does only
+    // lasts a few hundred cycles, so none of it is saved
+    #[serde(skip)]
+    pop_reduction: bool,
+    /// Level heard with pop reduction while slewing towards `output` after a large jump.
+    #[serde(skip)]
+    pop_output: u8,
+    #[serde(skip)]
+    pop_slewing: bool,
 }
 
 impl Default for Dmc {
@@ -41,6 +54,10 @@ impl Dmc {
         0x04E, 0x042, 0x032,
     ];
 
+    const POP_THRESHOLD: u8 = 50;
+    /// Output levels moved per APU clock while slewing, reaching any level within 128 clocks.
+    const POP_SLEW_STEP: u8 = 1;
+
     pub fn new() -> Self {
         let region = NesRegion::default();
         let freq_timer = Self::freq_timer(region, 0);
@@ -64,6 +81,9 @@ impl Dmc {
             output_bits: 0x00,
             output_shift: 0x00,
             output_silent: true,
+            pop_reduction: false,
+            pop_output: 0x00,
+            pop_slewing: false,
         }
     }
 
@@ -78,12 +98,37 @@ impl Dmc {
         self.force_silent = !self.force_silent;
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn pop_reduction(&self) -> bool {
+        self.pop_reduction
+    }
+
+    #[inline]
+    pub fn set_pop_reduction(&mut self, enabled: bool) {
+        self.pop_reduction = enabled;
+        self.pop_slewing = false;
+    }
+
     #[inline]
     #[must_use]
     pub const fn length(&self) -> u16 {
         self.length
     }
 
+    /// State for debugging displays, with the bit rate based on the CPU `clock_rate`.
+    pub fn state(&self, clock_rate: f32) -> ChannelState {
+        ChannelState {
+            enabled: self.length > 0,
+            period: self.freq_timer + 2,
+            frequency: clock_rate / f32::from(self.freq_timer + 2),
+            volume: self.output,
+            length: self.length,
+            mode: u8::from(self.loops),
+            ..ChannelState::default()
+        }
+    }
+
     #[inline]
     #[must_use]
     pub const fn irq_enabled(&self) -> bool {
@@ -145,11 +190,23 @@ impl Dmc {
         }
     }
 
+    /// Output bits per second for a `$4010` rate index.
+    #[must_use]
+    pub fn bit_rate(region: NesRegion, rate: u8) -> f32 {
+        let period = match region {
+            NesRegion::Ntsc => Self::FREQ_TABLE_NTSC[(rate & 0x0F) as usize],
+            NesRegion::Pal | NesRegion::Dendy => Self::FREQ_TABLE_PAL[(rate & 0x0F) as usize],
+        };
+        Cpu::region_clock_rate(region) / f32::from(period)
+    }
+
     #[inline]
     #[must_use]
     pub fn output(&self) -> f32 {
         if self.force_silent {
             0.0
+        } else if self.pop_reduction && self.pop_slewing {
+            f32::from(self.pop_output)
         } else {
             f32::from(self.output)
         }
@@ -168,6 +225,14 @@ impl Dmc {
     // $4011 DMC output
     #[inline]
     pub fn write_output(&mut self, val: u8) {
+        // Games that play PCM samples through $4011 often start by jumping to a large level,
+        // which is heard as a loud click. Slew to it over the next APU clocks to soften it.
+        if self.pop_reduction && self.output.abs_diff(val) > Self::POP_THRESHOLD {
+            if !self.pop_slewing {
+                self.pop_output = self.output;
+            }
+            self.pop_slewing = true;
+        }
         self.output = val;
     }
 
@@ -207,8 +272,26 @@ impl Dmc {
     }
 }
 
+impl Dmc {
+    /// Moves the level heard with pop reduction towards `output`.
+    fn clock_pop_reduction(&mut self) {
+        if self.pop_output < self.output {
+            self.pop_output = (self.pop_output + Self::POP_SLEW_STEP).min(self.output);
+        } else {
+            self.pop_output = self
+                .pop_output
+                .saturating_sub(Self::POP_SLEW_STEP)
+                .max(self.output);
+        }
+        self.pop_slewing = self.pop_output != self.output;
+    }
+}
+
 impl Clock for Dmc {
     fn clock(&mut self) -> usize {
+        if self.pop_slewing {
+            self.clock_pop_reduction();
+        }
         // Because APU is only clocked every other CPU cycle
         if self.freq_counter >= 2 {
             self.freq_counter -= 2;
@@ -285,5 +368,37 @@ impl Reset for Dmc {
         self.output_bits = 0x00;
         self.output_shift = 0x00;
         self.output_silent = true;
+        self.pop_output = 0x00;
+        self.pop_slewing = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pop_reduction_reaches_written_level() {
+        let mut dmc = Dmc::new();
+        dmc.set_pop_reduction(true);
+        dmc.write_output(100);
+        assert_eq!(dmc.output(), 0.0);
+        dmc.clock();
+        assert_eq!(dmc.output(), 1.0);
+        for _ in 0..100 {
+            dmc.clock();
+        }
+        assert_eq!(dmc.output(), 100.0);
+
+        dmc.write_output(10);
+        for _ in 0..100 {
+            dmc.clock();
+        }
+        assert_eq!(dmc.output(), 10.0);
+
+        // Small steps aren't slewed
+        dmc.write_output(40);
+        dmc.clock();
+        assert_eq!(dmc.output(), 40.0);
     }
 }
@@ -24,6 +24,17 @@ impl Envelope {
         }
     }
 
+    /// Current volume, decaying or constant.
+    #[inline]
+    #[must_use]
+    pub(crate) const fn output(&self) -> u8 {
+        if self.enabled {
+            self.volume
+        } else {
+            self.constant_volume
+        }
+    }
+
     // $4000/$4004/$400C Envelope control
     #[inline]
     pub(crate) fn write_ctrl(&mut self, val: u8) {
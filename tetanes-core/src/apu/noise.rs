@@ -1,5 +1,5 @@
 use crate::{
-    apu::{envelope::Envelope, length_counter::LengthCounter},
+    apu::{envelope::Envelope, length_counter::LengthCounter, ChannelState},
     common::{Clock, Kind, NesRegion, Regional, Reset},
 };
 use serde::{Deserialize, Serialize};
@@ -88,6 +88,20 @@ impl Noise {
         self.length.clock();
     }
 
+    /// State for debugging displays, with frequencies based on the CPU `clock_rate`.
+    pub fn state(&self, clock_rate: f32) -> ChannelState {
+        ChannelState {
+            enabled: self.enabled,
+            period: self.freq_timer + 1,
+            frequency: clock_rate / f32::from(self.freq_timer + 1),
+            volume: self.envelope.output(),
+            envelope: self.envelope.enabled,
+            length: u16::from(self.length.counter),
+            mode: u8::from(self.shift_mode == ShiftMode::One),
+            linear_counter: 0,
+        }
+    }
+
     #[must_use]
     pub fn output(&self) -> f32 {
         if self.shift & 1 == 0 && self.length.counter != 0 && !self.force_silent {
@@ -1,5 +1,5 @@
 use crate::{
-    apu::{envelope::Envelope, length_counter::LengthCounter, sweep::Sweep},
+    apu::{envelope::Envelope, length_counter::LengthCounter, sweep::Sweep, ChannelState},
     common::{Clock, Kind, Reset},
 };
 use serde::{Deserialize, Serialize};
@@ -91,6 +91,20 @@ impl Pulse {
         self.envelope.clock();
     }
 
+    /// State for debugging displays, with frequencies based on the CPU `clock_rate`.
+    pub fn state(&self, clock_rate: f32) -> ChannelState {
+        ChannelState {
+            enabled: self.enabled,
+            period: self.freq_timer,
+            frequency: clock_rate / (16.0 * f32::from(self.freq_timer + 1)),
+            volume: self.envelope.output(),
+            envelope: self.envelope.enabled,
+            length: u16::from(self.length.counter),
+            mode: self.duty_cycle,
+            linear_counter: 0,
+        }
+    }
+
     pub fn clock_half_frame(&mut self) {
         let sweep_forcing_silence = self.sweep_forcing_silence();
         let mut swp = &mut self.sweep;
@@ -1,5 +1,5 @@
 use crate::{
-    apu::{length_counter::LengthCounter, linear_counter::LinearCounter},
+    apu::{length_counter::LengthCounter, linear_counter::LinearCounter, ChannelState},
     common::{Clock, Kind, Reset},
 };
 use serde::{Deserialize, Serialize};
@@ -70,6 +70,20 @@ impl Triangle {
         self.length.clock();
     }
 
+    /// State for debugging displays, with frequencies based on the CPU `clock_rate`.
+    pub fn state(&self, clock_rate: f32) -> ChannelState {
+        let running = self.length.counter > 0 && self.linear.counter > 0;
+        ChannelState {
+            enabled: self.enabled,
+            period: self.freq_timer,
+            frequency: clock_rate / (32.0 * f32::from(self.freq_timer + 1)),
+            volume: if running { 15 } else { 0 },
+            length: u16::from(self.length.counter),
+            linear_counter: self.linear.counter,
+            ..ChannelState::default()
+        }
+    }
+
     #[must_use]
     pub fn output(&self) -> f32 {
         if self.force_silent {
@@ -0,0 +1,11 @@
+//! Audio filtering and recording shared by the APU and frontends.
+
+pub mod dpcm;
+pub mod equalizer;
+pub mod filter;
+pub mod wav;
+pub mod window_sinc;
+
+pub trait Audio {
+    fn output(&self) -> f32;
+}
@@ -0,0 +1,347 @@
+//! Conversion of WAV audio to 1-bit delta-encoded DPCM samples played by the APU DMC channel.
+
+use crate::{
+    apu::dmc::Dmc,
+    common::{Clock, NesRegion, Regional},
+    cpu::Cpu,
+    NesResult,
+};
+use anyhow::{anyhow, bail};
+
+/// Longest sample the DMC can play without looping, set by `$4013`.
+pub const MAX_LENGTH: usize = 0xFF * 16 + 1;
+/// Byte used to pad samples to a valid length. Alternating bits hold the output level steady.
+const PADDING: u8 = 0x55;
+
+/// Decoded WAV audio, mixed down to mono.
+#[derive(Debug, Clone, PartialEq)]
+#[must_use]
+pub struct Wav {
+    pub sample_rate: u32,
+    pub samples: Vec<f32>,
+}
+
+impl Wav {
+    /// Decodes 8, 16, 24 or 32-bit integer PCM or 32-bit float WAV data.
+    ///
+    /// # Errors
+    ///
+    /// If the data isn't a WAV file or uses an unsupported format, an error is returned.
+    pub fn decode(data: &[u8]) -> NesResult<Self> {
+        if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+            bail!("not a WAV file");
+        }
+        let mut format = None;
+        let mut pos = 12;
+        while pos + 8 <= data.len() {
+            let id = &data[pos..pos + 4];
+            let len =
+                u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]])
+                    as usize;
+            let chunk = &data[pos + 8..(pos + 8 + len).min(data.len())];
+            match id {
+                b"fmt " if chunk.len() >= 16 => {
+                    let mut tag = u16::from_le_bytes([chunk[0], chunk[1]]);
+                    if tag == 0xFFFE && chunk.len() >= 26 {
+                        // WAVE_FORMAT_EXTENSIBLE stores the real format in its sub-format GUID
+                        tag = u16::from_le_bytes([chunk[24], chunk[25]]);
+                    }
+                    let channels = u16::from_le_bytes([chunk[2], chunk[3]]);
+                    let sample_rate = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+                    let bits = u16::from_le_bytes([chunk[14], chunk[15]]);
+                    format = Some((tag, channels, sample_rate, bits));
+                }
+                b"data" => {
+                    let (tag, channels, sample_rate, bits) =
+                        format.ok_or_else(|| anyhow!("missing WAV format chunk"))?;
+                    if channels == 0 || sample_rate == 0 {
+                        bail!("invalid WAV format");
+                    }
+                    let decode: fn(&[u8]) -> f32 = match (tag, bits) {
+                        (1, 8) => |b| (f32::from(b[0]) - 128.0) / 128.0,
+                        (1, 16) => |b| f32::from(i16::from_le_bytes([b[0], b[1]])) / 32_768.0,
+                        (1, 24) => {
+                            |b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2_147_483_648.0
+                        }
+                        (1, 32) => |b| {
+                            i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0
+                        },
+                        (3, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+                        _ => bail!("unsupported WAV format {tag} with {bits} bits per sample"),
+                    };
+                    let frame_size = usize::from(channels) * usize::from(bits / 8);
+                    let samples = chunk
+                        .chunks_exact(frame_size)
+                        .map(|frame| {
+                            frame
+                                .chunks_exact(usize::from(bits / 8))
+                                .map(decode)
+                                .sum::<f32>()
+                                / f32::from(channels)
+                        })
+                        .collect();
+                    return Ok(Self {
+                        sample_rate,
+                        samples,
+                    });
+                }
+                _ => (),
+            }
+            // Chunks are padded to an even length
+            pos += 8 + len + (len & 1);
+        }
+        bail!("missing WAV data chunk")
+    }
+}
+
+/// A DPCM sample and the DMC register values to play it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct Dpcm {
+    pub region: NesRegion,
+    /// `$4010` rate index.
+    pub rate: u8,
+    /// `$4011` output level to start from.
+    pub initial_level: u8,
+    /// Sample bytes, padded to a length the DMC can play.
+    pub data: Vec<u8>,
+    /// Whether the source was longer than the DMC can play.
+    pub truncated: bool,
+}
+
+impl Dpcm {
+    /// Encodes `wav` at the DMC `rate`, optionally scaling it to use the full output range.
+    pub fn encode(wav: &Wav, rate: u8, region: NesRegion, normalize: bool) -> Self {
+        let bit_rate = Dmc::bit_rate(region, rate);
+        let gain = if normalize {
+            let peak = wav.samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+            if peak > 0.0 {
+                1.0 / peak
+            } else {
+                1.0
+            }
+        } else {
+            1.0
+        };
+
+        // Average the source over each output bit, since the DMC rate is usually lower
+        let step = wav.sample_rate as f32 / bit_rate;
+        let bits = (wav.samples.len() as f32 / step).ceil() as usize;
+        let max_bits = MAX_LENGTH * 8;
+        let truncated = bits > max_bits;
+        let targets = (0..bits.min(max_bits)).map(|bit| {
+            let start = (bit as f32 * step) as usize;
+            let end = (((bit + 1) as f32 * step) as usize)
+                .max(start + 1)
+                .min(wav.samples.len());
+            let window = &wav.samples[start.min(end - 1)..end];
+            let value = window.iter().sum::<f32>() / window.len() as f32;
+            // Levels are 7-bit, centered on 64
+            (gain * value).mul_add(63.5, 64.0).clamp(0.0, 127.0) as u8
+        });
+
+        let mut targets = targets.peekable();
+        let initial_level = targets.peek().copied().unwrap_or(64);
+        let mut level = initial_level;
+        let mut data = Vec::with_capacity((bits.min(max_bits) + 7) / 8);
+        for (i, target) in targets.enumerate() {
+            if i % 8 == 0 {
+                data.push(0x00);
+            }
+            // Matches how the DMC clamps its output
+            if target > level {
+                data[i / 8] |= 1 << (i % 8);
+                if level <= 125 {
+                    level += 2;
+                }
+            } else if level >= 2 {
+                level -= 2;
+            }
+        }
+        // Lengths are 16n + 1 bytes
+        let len = data.len().max(1);
+        data.resize((len + 14) / 16 * 16 + 1, PADDING);
+
+        Self {
+            region,
+            rate: rate & 0x0F,
+            initial_level,
+            data,
+            truncated,
+        }
+    }
+
+    /// Playback length in seconds.
+    #[must_use]
+    pub fn duration(&self) -> f32 {
+        (self.data.len() * 8) as f32 / Dmc::bit_rate(self.region, self.rate)
+    }
+
+    /// Values to write to `$4010`-`$4013` to play the sample from `addr`, which must be 64-byte
+    /// aligned in `$C000-$FFFF`.
+    #[must_use]
+    pub fn registers(&self, addr: u16) -> [u8; 4] {
+        [
+            self.rate,
+            self.initial_level,
+            (addr.saturating_sub(0xC000) >> 6) as u8,
+            ((self.data.len() - 1) / 16) as u8,
+        ]
+    }
+
+    /// Output levels the DMC steps through while playing the sample.
+    #[must_use]
+    pub fn levels(&self) -> Vec<u8> {
+        let mut level = self.initial_level;
+        let mut levels = Vec::with_capacity(self.data.len() * 8);
+        for byte in &self.data {
+            for bit in 0..8 {
+                if byte & (1 << bit) != 0 {
+                    if level <= 125 {
+                        level += 2;
+                    }
+                } else if level >= 2 {
+                    level -= 2;
+                }
+                levels.push(level);
+            }
+        }
+        levels
+    }
+}
+
+/// Plays a [`Dpcm`] sample through an emulated DMC channel, mapped at `$C000`.
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct DpcmPlayer {
+    dmc: Dmc,
+    data: Vec<u8>,
+    cycle: usize,
+    cycles: usize,
+}
+
+impl DpcmPlayer {
+    pub fn new(dpcm: &Dpcm) -> Self {
+        let mut dmc = Dmc::new();
+        dmc.set_region(dpcm.region);
+        let [rate, level, addr, length] = dpcm.registers(0xC000);
+        dmc.write_timer(rate);
+        dmc.write_output(level);
+        dmc.write_addr_load(addr);
+        dmc.write_length(length);
+        dmc.set_enabled(true, 0);
+        // Leave time for the last byte to shift out
+        let period = Cpu::region_clock_rate(dpcm.region) / Dmc::bit_rate(dpcm.region, rate);
+        let cycles = ((dpcm.data.len() + 2) * 8) as f32 * period;
+        Self {
+            dmc,
+            data: dpcm.data.clone(),
+            cycle: 0,
+            cycles: cycles as usize,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn finished(&self) -> bool {
+        self.cycle >= self.cycles
+    }
+
+    /// Runs up to `cycles` CPU cycles, appending a mixed sample per cycle to `samples`, the same
+    /// rate the APU produces samples at.
+    pub fn clock(&mut self, cycles: usize, samples: &mut Vec<f32>) {
+        for _ in 0..cycles.min(self.cycles.saturating_sub(self.cycle)) {
+            self.dmc.check_pending_dma();
+            if self.cycle & 0x01 == 0x00 {
+                self.dmc.clock();
+            }
+            if self.dmc.dma() {
+                let index = usize::from(self.dmc.dma_addr().wrapping_sub(0xC000));
+                self.dmc
+                    .load_buffer(self.data.get(index).copied().unwrap_or(PADDING));
+            }
+            // https://www.nesdev.org/wiki/APU_Mixer with only the DMC playing
+            let dmc = self.dmc.output();
+            samples.push(if dmc > 0.0 {
+                159.79 / (22_638.0 / dmc + 100.0)
+            } else {
+                0.0
+            });
+            self.cycle += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::wav::WavWriter;
+    use std::io::Cursor;
+
+    fn sine(sample_rate: u32, seconds: f32) -> Vec<f32> {
+        (0..(sample_rate as f32 * seconds) as usize)
+            .map(|i| (i as f32 * 110.0 * std::f32::consts::TAU / sample_rate as f32).sin() * 0.5)
+            .collect()
+    }
+
+    #[test]
+    fn decode_wav() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 22_050, 2).expect("valid header");
+        wav.write_samples(&[0.5, 0.5, -0.5, 0.0])
+            .expect("valid samples");
+        let data = wav.finish().expect("finished").into_inner();
+        let wav = Wav::decode(&data).expect("valid wav");
+        assert_eq!(wav.sample_rate, 22_050);
+        assert_eq!(wav.samples.len(), 2);
+        assert!((wav.samples[0] - 0.5).abs() < 0.001);
+        assert!((wav.samples[1] + 0.25).abs() < 0.001);
+        assert!(Wav::decode(b"RIFF\0\0\0\0WAVE").is_err());
+    }
+
+    #[test]
+    fn encode_dpcm() {
+        let wav = Wav {
+            sample_rate: 44_100,
+            samples: sine(44_100, 0.25),
+        };
+        let dpcm = Dpcm::encode(&wav, 0x0F, NesRegion::Ntsc, true);
+        assert_eq!(dpcm.data.len() % 16, 1);
+        assert!(!dpcm.truncated);
+        assert!((dpcm.duration() - 0.25).abs() < 0.01);
+        let [rate, _, addr, length] = dpcm.registers(0xC040);
+        assert_eq!(rate, 0x0F);
+        assert_eq!(addr, 0x01);
+        assert_eq!(usize::from(length) * 16 + 1, dpcm.data.len());
+
+        // The decoded output should follow the normalized source
+        let levels = dpcm.levels();
+        assert!(levels.iter().any(|&level| level > 110));
+        assert!(levels.iter().any(|&level| level < 18));
+
+        let long = Wav {
+            sample_rate: 44_100,
+            samples: sine(44_100, 2.0),
+        };
+        let dpcm = Dpcm::encode(&long, 0x0F, NesRegion::Ntsc, false);
+        assert!(dpcm.truncated);
+        assert_eq!(dpcm.data.len(), MAX_LENGTH);
+    }
+
+    #[test]
+    fn play_dpcm() {
+        let wav = Wav {
+            sample_rate: 44_100,
+            samples: sine(44_100, 0.05),
+        };
+        let dpcm = Dpcm::encode(&wav, 0x0F, NesRegion::Ntsc, true);
+        let mut player = DpcmPlayer::new(&dpcm);
+        let mut samples = vec![];
+        while !player.finished() {
+            player.clock(10_000, &mut samples);
+        }
+        assert!(samples.len() as f32 > dpcm.duration() * Cpu::region_clock_rate(NesRegion::Ntsc));
+        let min = samples.iter().copied().fold(f32::MAX, f32::min);
+        let max = samples.iter().copied().fold(f32::MIN, f32::max);
+        assert!(max - min > 0.1, "DMC output should swing with the sample");
+    }
+}
@@ -0,0 +1,146 @@
+//! Two band shelving equalizer applied to the final mix.
+//!
+//! Sources:
+//! - <https://www.w3.org/TR/audio-eq-cookbook/>
+
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+const LOW_SHELF_FREQUENCY: f32 = 250.0;
+const HIGH_SHELF_FREQUENCY: f32 = 4_000.0;
+
+#[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
+#[must_use]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl Biquad {
+    /// Creates a shelving filter with a slope of 1. `high` selects a high shelf instead of a low
+    /// shelf.
+    fn shelf(sample_rate: f32, frequency: f32, gain_db: f32, high: bool) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let cos = w0.cos();
+        let alpha = w0.sin() / 2.0 * 2f32.sqrt();
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+        // High shelf coefficients mirror the low shelf with the sign of cos(w0) flipped
+        let (cos, sign) = if high { (-cos, -1.0) } else { (cos, 1.0) };
+        let b0 = a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha);
+        let b1 = sign * 2.0 * a * ((a - 1.0) - (a + 1.0) * cos);
+        let b2 = a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha);
+        let a0 = (a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha;
+        let a1 = sign * -2.0 * ((a - 1.0) + (a + 1.0) * cos);
+        let a2 = (a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha;
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+            ..Self::default()
+        }
+    }
+
+    #[inline]
+    fn apply(&mut self, x: f32) -> f32 {
+        let y = self.b0 * x + self.b1 * self.x1 + self.b2 * self.x2
+            - self.a1 * self.y1
+            - self.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// Low and high shelf equalizer with gains in decibels. A gain of `0.0` leaves the band
+/// unchanged.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct Equalizer {
+    low_gain: f32,
+    high_gain: f32,
+    low: Biquad,
+    high: Biquad,
+}
+
+impl Equalizer {
+    pub fn new(sample_rate: f32, low_gain: f32, high_gain: f32) -> Self {
+        Self {
+            low_gain,
+            high_gain,
+            low: Biquad::shelf(sample_rate, LOW_SHELF_FREQUENCY, low_gain, false),
+            high: Biquad::shelf(sample_rate, HIGH_SHELF_FREQUENCY, high_gain, true),
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn low_gain(&self) -> f32 {
+        self.low_gain
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn high_gain(&self) -> f32 {
+        self.high_gain
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn apply(&mut self, sample: f32) -> f32 {
+        if self.low_gain == 0.0 && self.high_gain == 0.0 {
+            return sample;
+        }
+        self.high.apply(self.low.apply(sample))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settle(eq: &mut Equalizer, sample: impl Fn(usize) -> f32) -> f32 {
+        (0..4096).fold(0.0f32, |peak, i| {
+            let out = eq.apply(sample(i));
+            if i > 2048 {
+                peak.max(out.abs())
+            } else {
+                peak
+            }
+        })
+    }
+
+    #[test]
+    fn low_shelf_boosts_bass() {
+        let mut eq = Equalizer::new(44_100.0, 6.0, 0.0);
+        let dc = settle(&mut eq, |_| 1.0);
+        assert!((dc - 10f32.powf(6.0 / 20.0)).abs() < 0.01, "dc gain {dc}");
+        let mut eq = Equalizer::new(44_100.0, 6.0, 0.0);
+        let nyquist = settle(&mut eq, |i| if i % 2 == 0 { 1.0 } else { -1.0 });
+        assert!((nyquist - 1.0).abs() < 0.01, "nyquist gain {nyquist}");
+    }
+
+    #[test]
+    fn high_shelf_cuts_treble() {
+        let mut eq = Equalizer::new(44_100.0, 0.0, -6.0);
+        let dc = settle(&mut eq, |_| 1.0);
+        assert!((dc - 1.0).abs() < 0.01, "dc gain {dc}");
+        let mut eq = Equalizer::new(44_100.0, 0.0, -6.0);
+        let nyquist = settle(&mut eq, |i| if i % 2 == 0 { 1.0 } else { -1.0 });
+        assert!(
+            (nyquist - 10f32.powf(-6.0 / 20.0)).abs() < 0.01,
+            "nyquist gain {nyquist}"
+        );
+    }
+}
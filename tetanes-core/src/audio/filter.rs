@@ -35,6 +35,13 @@ impl Filter {
         }
     }
 
+    /// Delay introduced by this filter, in samples.
+    #[inline]
+    #[must_use]
+    pub const fn latency(&self) -> usize {
+        self.sinc.latency()
+    }
+
     #[inline]
     #[must_use]
     pub fn apply(&self, sample: f32) -> f32 {
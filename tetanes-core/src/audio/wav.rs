@@ -0,0 +1,114 @@
+//! Minimal 16-bit PCM WAV writer.
+
+use crate::NesResult;
+use anyhow::Context;
+use std::io::{Seek, SeekFrom, Write};
+
+const HEADER_SIZE: u32 = 44;
+const BITS_PER_SAMPLE: u16 = 16;
+
+/// Writes interleaved `f32` samples as 16-bit PCM WAV data. The RIFF chunk sizes are patched in
+/// when the writer is finished.
+#[derive(Debug)]
+#[must_use]
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    channels: u16,
+    data_len: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Creates a new `WavWriter`, writing out the WAV header.
+    ///
+    /// # Errors
+    ///
+    /// If the header fails to write, an error is returned.
+    pub fn new(mut writer: W, sample_rate: u32, channels: u16) -> NesResult<Self> {
+        let block_align = channels * BITS_PER_SAMPLE / 8;
+        let mut header = Vec::with_capacity(HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(HEADER_SIZE - 8).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        header.extend_from_slice(&channels.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * u32::from(block_align)).to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&BITS_PER_SAMPLE.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+        writer
+            .write_all(&header)
+            .context("failed to write wav header")?;
+        Ok(Self {
+            writer,
+            channels,
+            data_len: 0,
+        })
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    /// Writes samples in the range `-1.0..=1.0`, clamping anything outside it.
+    ///
+    /// # Errors
+    ///
+    /// If the samples fail to write, an error is returned.
+    pub fn write_samples(&mut self, samples: &[f32]) -> NesResult<()> {
+        for sample in samples {
+            let sample = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+            self.writer
+                .write_all(&sample.to_le_bytes())
+                .context("failed to write wav samples")?;
+        }
+        self.data_len = self
+            .data_len
+            .saturating_add(samples.len() as u32 * u32::from(BITS_PER_SAMPLE / 8));
+        Ok(())
+    }
+
+    /// Patches the header chunk sizes and flushes the output, returning the inner writer.
+    ///
+    /// # Errors
+    ///
+    /// If the header fails to update, an error is returned.
+    pub fn finish(mut self) -> NesResult<W> {
+        let riff_len = self.data_len.saturating_add(HEADER_SIZE - 8);
+        self.writer
+            .seek(SeekFrom::Start(4))
+            .and_then(|_| self.writer.write_all(&riff_len.to_le_bytes()))
+            .and_then(|_| {
+                self.writer
+                    .seek(SeekFrom::Start(u64::from(HEADER_SIZE) - 4))
+            })
+            .and_then(|_| self.writer.write_all(&self.data_len.to_le_bytes()))
+            .and_then(|_| self.writer.seek(SeekFrom::End(0)))
+            .and_then(|_| self.writer.flush())
+            .context("failed to finish wav file")?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn header_sizes() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 48_000, 2).expect("valid header");
+        wav.write_samples(&[0.0, 1.0, -1.0, 2.0])
+            .expect("valid samples");
+        let data = wav.finish().expect("finished").into_inner();
+        assert_eq!(data.len(), 44 + 8);
+        assert_eq!(&data[4..8], &44u32.to_le_bytes());
+        assert_eq!(&data[40..44], &8u32.to_le_bytes());
+        assert_eq!(&data[46..48], &i16::MAX.to_le_bytes());
+        assert_eq!(&data[50..52], &i16::MAX.to_le_bytes());
+    }
+}
@@ -1,15 +1,15 @@
 use crate::{
-    apu::{Apu, ApuRegisters, Channel},
+    apu::{Apu, ApuMixing, ApuRegisters, Channel},
     audio::Audio,
     cart::Cart,
     common::{Clock, Kind, NesRegion, Regional, Reset},
     cpu::{Cpu, Irq},
     genie::GenieCode,
     input::{FourPlayer, Input, InputRegisters, Joypad, Slot, Zapper},
-    mapper::{Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mapper::{AudioChip, ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
     mem::{Access, Mem, RamState},
     ppu::{Ppu, PpuRegisters},
-    trace::Trace,
+    trace::{Trace, TraceTiming},
     NesResult,
 };
 use serde::{Deserialize, Serialize};
@@ -53,6 +53,8 @@ pub struct CpuBus {
     battery_backed: bool,
     prg_ram: Vec<u8>,
     prg_ram_protect: bool,
+    #[serde(deserialize_with = "crate::legacy::added")]
+    prg_ram_write_protect: bool, // Cartridge write-protect switch, independent of the mapper
     prg_rom: Vec<u8>,
     ppu: Ppu,
     apu: Apu,
@@ -60,9 +62,16 @@ pub struct CpuBus {
     oam_dma: bool,
     oam_dma_addr: u16,
     audio_samples: Vec<f32>,
+    #[serde(skip, default = "CpuBus::default_expansion_volume")]
+    expansion_volume: f32, // Mixer setting, restored from the current settings on load
+    #[serde(skip, default = "CpuBus::default_audio_chip_gains")]
+    audio_chip_gains: [f32; 6], // Gain for each sound chip in `AudioChip` order, from the config
+    #[serde(skip)]
+    channel_samples: Option<Vec<f32>>, // Interleaved per-channel samples, when enabled
     genie_codes: HashMap<u16, GenieCode>,
     cycle: usize, // Total number of CPU cycles ran
     open_bus: u8,
+    #[serde(deserialize_with = "Trace::deserialize_legacy")]
     trace: Trace,
 }
 
@@ -85,6 +94,7 @@ impl CpuBus {
             battery_backed: false,
             prg_ram: vec![],
             prg_ram_protect: false,
+            prg_ram_write_protect: false,
             prg_rom: vec![],
             ppu: Ppu::new(),
             apu: Apu::new(),
@@ -92,6 +102,9 @@ impl CpuBus {
             oam_dma: false,
             oam_dma_addr: 0x0000,
             audio_samples: vec![],
+            expansion_volume: 1.0,
+            audio_chip_gains: [1.0; 6],
+            channel_samples: None,
             genie_codes: HashMap::new(),
             cycle: 0,
             open_bus: 0x00,
@@ -144,6 +157,18 @@ impl CpuBus {
         self.input.connect_zapper(enabled);
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn lagged(&self) -> bool {
+        self.input.lagged()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn lag_frames(&self) -> u32 {
+        self.input.lag_frames()
+    }
+
     #[inline]
     pub const fn zapper(&self) -> &Zapper {
         self.input.zapper()
@@ -156,10 +181,12 @@ impl CpuBus {
 
     #[inline]
     pub fn load_cart(&mut self, cart: Cart) {
-        // Start with ~20ms of audio capacity
-        self.audio_samples
-            .resize((Cpu::region_clock_rate(cart.region()) * 0.02) as usize, 0.0);
+        // Start with ~20ms of audio capacity. Only capacity, since samples are generated one per
+        // CPU cycle and padding would play as a gap
+        self.audio_samples.clear();
+        self.audio_samples.reserve((Cpu::region_clock_rate(cart.region()) * 0.02) as usize);
         self.battery_backed = cart.battery_backed();
+        self.prg_ram_write_protect = false;
         self.set_region(cart.region());
         self.load_prg_rom(cart.prg_rom);
         self.load_prg_ram(cart.prg_ram);
@@ -185,6 +212,24 @@ impl CpuBus {
         self.battery_backed
     }
 
+    /// Overrides whether the cartridge has a battery keeping Save RAM between sessions.
+    #[inline]
+    pub fn set_cart_battery_backed(&mut self, battery_backed: bool) {
+        self.battery_backed = battery_backed;
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn prg_ram_write_protected(&self) -> bool {
+        self.prg_ram_write_protect
+    }
+
+    /// Write-protects PRG-RAM so CPU writes are ignored, regardless of mapper registers.
+    #[inline]
+    pub fn set_prg_ram_write_protected(&mut self, protect: bool) {
+        self.prg_ram_write_protect = protect;
+    }
+
     #[inline]
     #[must_use]
     pub fn sram(&self) -> &[u8] {
@@ -198,6 +243,30 @@ impl CpuBus {
         }
     }
 
+    /// PRG-RAM for editing directly, bypassing write protection.
+    #[inline]
+    #[must_use]
+    pub fn sram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    /// Offset into PRG-ROM that an address is currently mapped to, if any.
+    #[inline]
+    #[must_use]
+    pub fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        match self.mapper().map_peek(addr) {
+            MappedRead::PrgRom(offset) if offset < self.prg_rom.len() => Some(offset),
+            _ => None,
+        }
+    }
+
+    /// PRG-ROM for patching directly.
+    #[inline]
+    #[must_use]
+    pub fn prg_rom_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_rom
+    }
+
     #[inline]
     #[must_use]
     pub fn wram(&self) -> &[u8] {
@@ -221,6 +290,17 @@ impl CpuBus {
         self.genie_codes.retain(|_, gc| gc.code() != code);
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn genie_codes(&self) -> &HashMap<u16, GenieCode> {
+        &self.genie_codes
+    }
+
+    #[inline]
+    pub fn set_genie_codes(&mut self, genie_codes: HashMap<u16, GenieCode>) {
+        self.genie_codes = genie_codes;
+    }
+
     #[inline]
     fn genie_read(&self, addr: u16, val: u8) -> u8 {
         self.genie_codes
@@ -242,6 +322,30 @@ impl CpuBus {
     #[inline]
     pub fn clear_audio_samples(&mut self) {
         self.audio_samples.clear();
+        if let Some(ref mut samples) = self.channel_samples {
+            samples.clear();
+        }
+    }
+
+    /// Enables or disables capturing each audio channel separately.
+    #[inline]
+    pub fn set_channel_samples_enabled(&mut self, enabled: bool) {
+        self.channel_samples = enabled.then(Vec::new);
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn channel_samples_enabled(&self) -> bool {
+        self.channel_samples.is_some()
+    }
+
+    /// Per-channel audio samples captured since the last clear, interleaved in `Channel` order
+    /// followed by cartridge expansion audio. Empty unless enabled with
+    /// `set_channel_samples_enabled`.
+    #[inline]
+    #[must_use]
+    pub fn channel_samples(&self) -> &[f32] {
+        self.channel_samples.as_deref().unwrap_or_default()
     }
 
     #[inline]
@@ -292,6 +396,13 @@ impl CpuBus {
         self.oam_dma = false;
     }
 
+    /// The last value read from or written to the CPU bus.
+    #[inline]
+    #[must_use]
+    pub const fn open_bus(&self) -> u8 {
+        self.open_bus
+    }
+
     #[inline]
     #[must_use]
     pub const fn ppu_cycle(&self) -> u32 {
@@ -327,6 +438,81 @@ impl CpuBus {
         self.apu.toggle_channel(channel);
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn audio_channel_volume(&self, channel: Channel) -> f32 {
+        self.apu.channel_volume(channel)
+    }
+
+    #[inline]
+    pub fn set_audio_channel_volume(&mut self, channel: Channel, volume: f32) {
+        self.apu.set_channel_volume(channel, volume);
+    }
+
+    const fn default_expansion_volume() -> f32 {
+        1.0
+    }
+
+    const fn default_audio_chip_gains() -> [f32; 6] {
+        [1.0; 6]
+    }
+
+    /// Mixer volume of cartridge expansion audio, from `0.0` (muted) to `1.0` (full volume).
+    #[inline]
+    #[must_use]
+    pub const fn expansion_volume(&self) -> f32 {
+        self.expansion_volume
+    }
+
+    #[inline]
+    pub const fn ram_state(&self) -> RamState {
+        self.ram_state
+    }
+
+    /// Set the RAM contents used on the next power cycle.
+    #[inline]
+    pub fn set_ram_state(&mut self, ram_state: RamState) {
+        self.ram_state = ram_state;
+    }
+
+    #[inline]
+    pub fn set_expansion_volume(&mut self, volume: f32) {
+        self.expansion_volume = volume.clamp(0.0, 1.0);
+    }
+
+    /// Gain applied to a cartridge sound chip before it's mixed with the APU.
+    #[inline]
+    #[must_use]
+    pub const fn audio_chip_gain(&self, chip: AudioChip) -> f32 {
+        self.audio_chip_gains[chip as usize]
+    }
+
+    #[inline]
+    pub fn set_audio_chip_gain(&mut self, chip: AudioChip, gain: f32) {
+        self.audio_chip_gains[chip as usize] = gain.clamp(0.0, 2.0);
+    }
+
+    #[inline]
+    pub const fn apu_mixing(&self) -> ApuMixing {
+        self.apu.mixing()
+    }
+
+    #[inline]
+    pub fn set_apu_mixing(&mut self, mixing: ApuMixing) {
+        self.apu.set_mixing(mixing);
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn dmc_pop_reduction(&self) -> bool {
+        self.apu.dmc_pop_reduction()
+    }
+
+    #[inline]
+    pub fn set_dmc_pop_reduction(&mut self, enabled: bool) {
+        self.apu.set_dmc_pop_reduction(enabled);
+    }
+
     #[inline]
     pub const fn four_player(&self) -> FourPlayer {
         self.input.four_player()
@@ -341,17 +527,28 @@ impl CpuBus {
 impl Clock for CpuBus {
     fn clock(&mut self) -> usize {
         self.cycle = self.cycle.wrapping_add(1);
+        self.trace.set_timing(TraceTiming {
+            frame: self.ppu.frame_number(),
+            scanline: self.ppu.scanline(),
+            dot: self.ppu.cycle(),
+            cycle: self.cycle as u64,
+        });
         self.apu.clock();
         self.mapper_mut().clock();
         self.input.clock();
+        self.input.update_lag(self.ppu.frame_number());
 
+        let _span = tracing::trace_span!("apu_mix").entered();
         let apu_output = self.apu.output();
-        let mapper_output = match self.mapper() {
-            Mapper::Exrom(ref exrom) => exrom.output(),
-            Mapper::Vrc6(ref vrc6) => vrc6.output(),
-            _ => 0.0,
-        };
-        self.mix_audio(apu_output, mapper_output);
+        let mapper = self.mapper();
+        let mapper_output = mapper.audio_chip().map_or(0.0, |chip| {
+            mapper.output() * self.audio_chip_gains[chip as usize]
+        });
+        self.mix_audio(apu_output, mapper_output * self.expansion_volume);
+        if let Some(ref mut samples) = self.channel_samples {
+            samples.extend(self.apu.channel_outputs());
+            samples.push(mapper_output);
+        }
 
         1
     }
@@ -638,7 +835,9 @@ impl Mem for CpuBus {
                 );
             },
             0x4020..=0xFFFF => {
-                let prg_ram_enabled = !self.prg_ram.is_empty() && !self.prg_ram_protect;
+                let prg_ram_enabled = !self.prg_ram.is_empty()
+                    && !self.prg_ram_protect
+                    && !self.prg_ram_write_protect;
                 match self.mapper_mut().map_write(addr, val) {
                     MappedWrite::PrgRam(addr, val) if prg_ram_enabled => {
                         self.prg_ram[addr] = val;
@@ -952,6 +1151,7 @@ impl std::fmt::Debug for CpuBus {
             .field("battery_backed", &self.battery_backed)
             .field("prg_ram_len", &self.prg_ram.len())
             .field("prg_ram_protect", &self.prg_ram_protect)
+            .field("prg_ram_write_protect", &self.prg_ram_write_protect)
             .field("prg_rom_len", &self.prg_rom.len())
             .field("ppu", &self.ppu)
             .field("apu", &self.apu)
@@ -1055,6 +1255,24 @@ mod test {
         assert_eq!(bus.read(0x2007, Access::Read), 0x77, "chr_ram write");
     }
 
+    #[test]
+    fn prg_ram_write_protect() {
+        let mut bus = CpuBus::default();
+        let mut cart = Cart::empty();
+        cart.prg_ram = vec![0x00; 0x2000];
+        bus.load_cart(cart);
+
+        bus.write(0x6000, 0x11, Access::Write);
+        assert_eq!(bus.read(0x6000, Access::Read), 0x11, "prg_ram write");
+
+        bus.set_prg_ram_write_protected(true);
+        bus.write(0x6000, 0x22, Access::Write);
+        assert_eq!(bus.read(0x6000, Access::Read), 0x11, "prg_ram write-protected");
+
+        bus.load_cart(Cart::empty());
+        assert!(!bus.prg_ram_write_protected(), "cleared on load");
+    }
+
     #[test]
     fn genie_codes() {
         let mut bus = CpuBus::default();
@@ -0,0 +1,1046 @@
+use crate::{
+    common::{NesRegion, Regional},
+    mapper::{
+        m024_m026_vrc6::Vrc6Revision, Action52, Action53, Axrom, Bf909x, Bmc64in1, Bnrom, Cnrom,
+        Exrom, Fxrom, Gxrom, Mapper, Mmc1Revision, Namco163, NesEvent, Nrom, Pxrom, SunsoftFme7,
+        Sxrom, Txrom, Uxrom, Vrc24, Vrc6, Vrc7,
+    },
+    mem::RamState,
+    ppu::Mirroring,
+    NesResult,
+};
+use anyhow::{anyhow, bail, Context};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+};
+
+const PRG_ROM_BANK_SIZE: usize = 0x4000;
+const CHR_ROM_BANK_SIZE: usize = 0x2000;
+
+#[cfg(not(target_arch = "wasm32"))]
+const GAME_DB: &[u8] = include_bytes!("../config/game_database.txt");
+#[cfg(not(target_arch = "wasm32"))]
+const BAD_DUMP_DB: &[u8] = include_bytes!("../config/bad_dumps.txt");
+
+/// A problem detected with a ROM dump that's likely to cause emulation issues.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub enum DumpIssue {
+    /// Listed in the bad dump or game database as a known bad dump, with the symptoms it's
+    /// known to cause if listed.
+    KnownBad {
+        title: String,
+        symptoms: Option<String>,
+    },
+    /// Listed in the bad dump database as a known overdump.
+    KnownOverdump {
+        title: String,
+        symptoms: Option<String>,
+    },
+    /// PRG-ROM or CHR-ROM consists of repeated copies of a smaller ROM.
+    Mirrored { chr: bool },
+    /// Extra bytes found after the end of the ROM data described by the header.
+    TrailingData(u64),
+    /// PRG-ROM or CHR-ROM size isn't a power of two.
+    UnusualSize { chr: bool },
+}
+
+impl DumpIssue {
+    /// A short explanation of the symptoms this issue is likely to cause.
+    #[must_use]
+    pub fn symptoms(&self) -> &str {
+        match self {
+            Self::KnownBad {
+                symptoms: Some(symptoms),
+                ..
+            }
+            | Self::KnownOverdump {
+                symptoms: Some(symptoms),
+                ..
+            } => symptoms,
+            Self::KnownBad { .. } => {
+                "Expect graphical corruption, crashes or freezes that don't happen with a good dump."
+            }
+            Self::KnownOverdump { .. } | Self::Mirrored { .. } => {
+                "Usually plays fine, but region detection and save states may not work as expected."
+            }
+            Self::TrailingData(_) => {
+                "The header may describe the wrong ROM size, which can cause garbled graphics or crashes."
+            }
+            Self::UnusualSize { .. } => {
+                "This often means an incomplete dump. Expect garbled graphics, crashes or freezes."
+            }
+        }
+    }
+}
+
+impl fmt::Display for DumpIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rom = |chr: bool| if chr { "CHR-ROM" } else { "PRG-ROM" };
+        match self {
+            Self::KnownBad { title, .. } => write!(f, "Known bad dump of {title}."),
+            Self::KnownOverdump { title, .. } => write!(f, "Known overdump of {title}."),
+            Self::Mirrored { chr } => write!(f, "{} contains repeated data (overdump).", rom(*chr)),
+            Self::TrailingData(len) => write!(f, "{len} extra bytes found after ROM data."),
+            Self::UnusualSize { chr } => write!(f, "{} size isn't a power of two.", rom(*chr)),
+        }?;
+        write!(f, " {}", self.symptoms())
+    }
+}
+
+/// A game title from the game database or stored at the end of the ROM file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct GameTitle {
+    /// Title in Latin script.
+    pub title: String,
+    /// Title in the original script of the game, such as Japanese, if known.
+    pub native: Option<String>,
+}
+
+impl GameTitle {
+    /// Some ROM files have a 127 or 128 byte title appended after the ROM data.
+    fn from_trailing_data(data: &[u8]) -> Option<Self> {
+        if !matches!(data.len(), 127 | 128) {
+            return None;
+        }
+        let end = data
+            .iter()
+            .rposition(|&byte| !matches!(byte, 0x00 | 0xFF | b' '))?;
+        let title = std::str::from_utf8(&data[..=end]).ok()?;
+        if title.chars().any(char::is_control) {
+            return None;
+        }
+        let native = (!title.is_ascii()).then(|| title.to_string());
+        Some(Self {
+            title: title.to_string(),
+            native,
+        })
+    }
+
+    /// Reads the title of a ROM file without loading the cartridge.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be read or the NES header is invalid, an error is returned.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> NesResult<Option<Self>> {
+        let path = path.as_ref();
+        let mut rom =
+            BufReader::new(File::open(path).with_context(|| format!("failed to open {path:?}"))?);
+        let header = NesHeader::load(&mut rom)?;
+        let mut prg_rom = vec![0x00; (header.prg_rom_banks as usize) * PRG_ROM_BANK_SIZE];
+        rom.read_exact(&mut prg_rom)
+            .with_context(|| format!("failed to read prg-rom from {path:?}"))?;
+        let chr_len = (header.chr_rom_banks as u64) * CHR_ROM_BANK_SIZE as u64;
+        io::copy(&mut (&mut rom).take(chr_len), &mut io::sink())?;
+        let mut trailing = vec![];
+        rom.read_to_end(&mut trailing)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(game) = Cart::lookup_game(&prg_rom) {
+            return Ok(Some(game.title));
+        }
+        Ok(Self::from_trailing_data(&trailing))
+    }
+
+    /// The title to display, preferring the native title when it can be rendered.
+    #[must_use]
+    pub fn display(&self, native: bool) -> &str {
+        match self.native {
+            Some(ref title) if native => title,
+            _ => &self.title,
+        }
+    }
+}
+
+/// A game database entry.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct GameInfo {
+    region: NesRegion,
+    battery: bool,
+    title: GameTitle,
+    /// Marked as a bad dump with a `[b]` tag in its title.
+    bad_dump: bool,
+}
+
+/// An NES cartridge.
+#[derive(Default, Clone)]
+#[must_use]
+pub struct Cart {
+    name: String,
+    title: Option<GameTitle>,
+    header: NesHeader,
+    region: NesRegion,
+    game_region: Option<NesRegion>,
+    battery_backed: bool,
+    ram_state: RamState,
+    pub(crate) mapper: Mapper,
+    pub(crate) chr_rom: Vec<u8>, // Character ROM
+    pub(crate) chr_ram: Vec<u8>, // Character RAM
+    pub(crate) ex_ram: Vec<u8>,  // Internal Extra RAM
+    pub(crate) prg_rom: Vec<u8>, // Program ROM
+    pub(crate) prg_ram: Vec<u8>, // Program RAM
+    dump_issues: Vec<DumpIssue>,
+}
+
+impl Cart {
+    pub fn empty() -> Self {
+        let mut empty = Self {
+            name: "Empty Cart".to_string(),
+            title: None,
+            header: NesHeader::default(),
+            region: NesRegion::default(),
+            game_region: None,
+            battery_backed: false,
+            ram_state: RamState::default(),
+            mapper: Mapper::none(),
+            chr_rom: vec![0x00; CHR_ROM_BANK_SIZE],
+            chr_ram: vec![],
+            ex_ram: vec![],
+            prg_rom: vec![0x00; PRG_ROM_BANK_SIZE],
+            prg_ram: vec![],
+            dump_issues: vec![],
+        };
+        empty.mapper = Nrom::load(&mut empty);
+        empty
+    }
+
+    /// Load `Cart` from a ROM path.
+    ///
+    /// # Errors
+    ///
+    /// If the NES header is corrupted, the ROM file cannot be read, or the data does not match
+    /// the header, then an error is returned.
+    pub fn from_path<P: AsRef<Path>>(path: P, ram_state: RamState) -> NesResult<Self> {
+        let path = path.as_ref();
+        let mut rom = BufReader::new(
+            File::open(path).with_context(|| format!("failed to open rom {path:?}"))?,
+        );
+        Self::from_rom(&path.to_string_lossy(), &mut rom, ram_state)
+    }
+
+    /// Load `Cart` from ROM data.
+    ///
+    /// # Errors
+    ///
+    /// If the NES header is invalid, or the ROM data does not match the header, then an error is
+    /// returned.
+    pub fn from_rom<S, F>(name: S, mut rom_data: &mut F, ram_state: RamState) -> NesResult<Self>
+    where
+        S: ToString,
+        F: Read,
+    {
+        let name = name.to_string();
+        let header = NesHeader::load(&mut rom_data)?;
+
+        let mut prg_rom = vec![0x00; (header.prg_rom_banks as usize) * PRG_ROM_BANK_SIZE];
+        rom_data.read_exact(&mut prg_rom).with_context(|| {
+            let bytes_rem = rom_data
+                .read_to_end(&mut prg_rom)
+                .map_or_else(|_| "unknown".to_string(), |rem| rem.to_string());
+            format!(
+                "invalid rom header '{}'. prg-rom banks: {}. bytes remaining: {}",
+                name, header.prg_rom_banks, bytes_rem
+            )
+        })?;
+
+        let prg_ram_size = Self::calculate_ram_size(header.prg_ram_shift).context("prg_ram")?;
+        let mut prg_ram = vec![0x00; prg_ram_size];
+        RamState::fill(&mut prg_ram, ram_state);
+
+        let mut chr_rom = vec![0x00; (header.chr_rom_banks as usize) * CHR_ROM_BANK_SIZE];
+        if header.chr_rom_banks > 0 {
+            rom_data.read_exact(&mut chr_rom).with_context(|| {
+                let bytes_rem = rom_data
+                    .read_to_end(&mut chr_rom)
+                    .map_or_else(|_| "unknown".to_string(), |rem| rem.to_string());
+                format!(
+                    "invalid rom header \"{}\". chr-rom banks: {}. bytes remaining: {}",
+                    name, header.chr_rom_banks, bytes_rem,
+                )
+            })?;
+        }
+
+        let mut trailing = vec![];
+        rom_data.read_to_end(&mut trailing).unwrap_or_default();
+        let internal_title = GameTitle::from_trailing_data(&trailing);
+        // A title isn't a sign of a bad dump
+        let trailing_bytes = if internal_title.is_some() {
+            0
+        } else {
+            trailing.len() as u64
+        };
+
+        let mut chr_ram = vec![];
+        if chr_rom.is_empty() {
+            let chr_ram_size = Self::calculate_ram_size(header.chr_ram_shift).context("chr_ram")?;
+            chr_ram.resize(chr_ram_size, 0x00);
+            RamState::fill(&mut chr_ram, ram_state);
+        }
+
+        // The game database corrects the battery flag for ROMs with bad headers
+        let header_battery = header.flags & 0x02 == 0x02;
+        #[cfg(not(target_arch = "wasm32"))]
+        let (game_region, battery_backed, title, bad_dump) = match Self::lookup_game(&prg_rom) {
+            Some(game) => (
+                Some(game.region),
+                game.battery,
+                Some(game.title),
+                game.bad_dump,
+            ),
+            None => (None, header_battery, None, false),
+        };
+        #[cfg(target_arch = "wasm32")]
+        let (game_region, battery_backed, title, bad_dump) = (None, header_battery, None, false);
+        let region = game_region.unwrap_or_default();
+
+        let mut dump_issues = Self::check_dump(&prg_rom, &chr_rom, trailing_bytes);
+        if let Some(ref title) = title {
+            let listed = dump_issues
+                .iter()
+                .any(|issue| matches!(issue, DumpIssue::KnownBad { .. }));
+            if bad_dump && !listed {
+                dump_issues.insert(
+                    0,
+                    DumpIssue::KnownBad {
+                        title: title.title.clone(),
+                        symptoms: None,
+                    },
+                );
+            }
+        }
+        let title = title.or(internal_title);
+        for issue in &dump_issues {
+            log::warn!("{name}: {issue}");
+        }
+
+        let mut cart = Self {
+            name,
+            title,
+            header,
+            region,
+            game_region,
+            battery_backed,
+            ram_state,
+            mapper: Mapper::none(),
+            chr_rom,
+            chr_ram,
+            ex_ram: vec![],
+            prg_rom,
+            prg_ram,
+            dump_issues,
+        };
+        cart.mapper = match cart.header.mapper_num {
+            0 => Nrom::load(&mut cart),
+            1 => Sxrom::load(&mut cart, Mmc1Revision::BC),
+            2 => Uxrom::load(&mut cart),
+            3 => Cnrom::load(&mut cart),
+            4 => Txrom::load(&mut cart),
+            5 => Exrom::load(&mut cart),
+            7 => Axrom::load(&mut cart),
+            9 => Pxrom::load(&mut cart),
+            10 => Fxrom::load(&mut cart),
+            19 => Namco163::load(&mut cart),
+            21 | 22 | 23 | 25 => Vrc24::load(&mut cart),
+            24 => Vrc6::load(&mut cart, Vrc6Revision::A),
+            26 => Vrc6::load(&mut cart, Vrc6Revision::B),
+            28 => Action53::load(&mut cart),
+            34 => Bnrom::load(&mut cart),
+            66 => Gxrom::load(&mut cart),
+            69 => SunsoftFme7::load(&mut cart),
+            71 => Bf909x::load(&mut cart),
+            85 => Vrc7::load(&mut cart),
+            105 => NesEvent::load(&mut cart),
+            155 => Sxrom::load(&mut cart, Mmc1Revision::A),
+            225 => Bmc64in1::load(&mut cart),
+            228 => Action52::load(&mut cart),
+            _ => bail!("unimplemented mapper: {}", cart.header.mapper_num),
+        };
+
+        log::info!("Loaded `{}`", cart);
+        log::debug!("{:?}", cart);
+        Ok(cart)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The game title, if found in the game database or the ROM file.
+    #[inline]
+    pub const fn title(&self) -> Option<&GameTitle> {
+        self.title.as_ref()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn chr_rom(&self) -> &[u8] {
+        &self.chr_rom
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn chr_ram(&self) -> &[u8] {
+        &self.chr_ram
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn prg_rom(&self) -> &[u8] {
+        &self.prg_rom
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn has_chr(&self) -> bool {
+        !self.chr_rom.is_empty() || !self.chr_ram.is_empty()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn chr_len(&self) -> usize {
+        if !self.chr_rom.is_empty() {
+            self.chr_rom.len()
+        } else if !self.chr_ram.is_empty() {
+            self.chr_ram.len()
+        } else {
+            0
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn has_prg_ram(&self) -> bool {
+        !self.prg_ram.is_empty()
+    }
+
+    /// Problems detected with this ROM dump when it was loaded.
+    #[inline]
+    pub fn dump_issues(&self) -> &[DumpIssue] {
+        &self.dump_issues
+    }
+
+    /// Region the game was released for, if found in the game database.
+    #[inline]
+    #[must_use]
+    pub const fn game_region(&self) -> Option<NesRegion> {
+        self.game_region
+    }
+
+    /// Returns whether this cartridge has battery-backed Save RAM, preferring the game database
+    /// over the header.
+    #[inline]
+    #[must_use]
+    pub const fn battery_backed(&self) -> bool {
+        self.battery_backed
+    }
+
+    /// Returns `RamState`.
+    #[inline]
+    pub const fn ram_state(&self) -> RamState {
+        self.ram_state
+    }
+
+    /// Returns hardware configured `Mirroring`.
+    #[inline]
+    pub fn mirroring(&self) -> Mirroring {
+        if self.header.flags & 0x08 == 0x08 {
+            Mirroring::FourScreen
+        } else {
+            match self.header.flags & 0x01 {
+                0 => Mirroring::Horizontal,
+                1 => Mirroring::Vertical,
+                _ => unreachable!("impossible mirroring"),
+            }
+        }
+    }
+
+    /// Returns the Mapper number for this Cart.
+    #[inline]
+    #[must_use]
+    pub const fn mapper_num(&self) -> u16 {
+        self.header.mapper_num
+    }
+
+    /// Returns the Sub-Mapper number for this Cart.
+    #[inline]
+    #[must_use]
+    pub const fn submapper_num(&self) -> u8 {
+        self.header.submapper_num
+    }
+
+    /// Returns the Mapper and Board name for this Cart.
+    #[inline]
+    #[must_use]
+    pub const fn mapper_board(&self) -> &'static str {
+        self.header.mapper_board()
+    }
+
+    /// Allows mappers to add PRG-RAM.
+    pub(crate) fn add_prg_ram(&mut self, capacity: usize) {
+        self.prg_ram.resize(capacity, 0x00);
+        RamState::fill(&mut self.prg_ram, self.ram_state);
+    }
+
+    /// Allows mappers to add CHR-RAM.
+    pub(crate) fn add_chr_ram(&mut self, capacity: usize) {
+        self.chr_ram.resize(capacity, 0x00);
+        RamState::fill(&mut self.chr_ram, self.ram_state);
+    }
+
+    /// Allows mappers to add EX-RAM.
+    pub(crate) fn add_ex_ram(&mut self, capacity: usize) {
+        self.ex_ram.resize(capacity, 0x00);
+        RamState::fill(&mut self.ex_ram, self.ram_state);
+    }
+
+    fn calculate_ram_size(value: u8) -> NesResult<usize> {
+        if value > 0 {
+            64usize
+                .checked_shl(value.into())
+                .ok_or_else(|| anyhow!("invalid header ram size: ${:02X}", value))
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Checks ROM data for signs of a bad dump or overdump.
+    fn check_dump(prg_rom: &[u8], chr_rom: &[u8], trailing_bytes: u64) -> Vec<DumpIssue> {
+        let mut issues = vec![];
+
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(issue) = Self::lookup_bad_dump(BAD_DUMP_DB, crc32(prg_rom), crc32(chr_rom)) {
+            issues.push(issue);
+        }
+
+        // A ROM made of two identical halves is a larger ROM chip read by a dumper that
+        // didn't know the real size
+        let mirrored = |rom: &[u8], bank_size: usize| {
+            rom.len() >= 2 * bank_size && {
+                let (lo, hi) = rom.split_at(rom.len() / 2);
+                lo == hi
+            }
+        };
+        for (chr, rom, bank_size) in [
+            (false, prg_rom, PRG_ROM_BANK_SIZE),
+            (true, chr_rom, CHR_ROM_BANK_SIZE),
+        ] {
+            if rom.is_empty() {
+                continue;
+            }
+            if !rom.len().is_power_of_two() {
+                issues.push(DumpIssue::UnusualSize { chr });
+            } else if mirrored(rom, bank_size) {
+                issues.push(DumpIssue::Mirrored { chr });
+            }
+        }
+
+        if trailing_bytes > 0 {
+            issues.push(DumpIssue::TrailingData(trailing_bytes));
+        }
+        issues
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn lookup_bad_dump(db: &[u8], prg_crc: u32, chr_crc: u32) -> Option<DumpIssue> {
+        use std::io::BufRead;
+
+        BufReader::new(db)
+            .lines()
+            .map_while(Result::ok)
+            .filter(|line| !line.starts_with('#'))
+            .find_map(|line| {
+                let mut fields = line.splitn(4, ',');
+                let mut crc = || {
+                    fields
+                        .next()
+                        .and_then(|crc| u32::from_str_radix(crc.trim(), 16).ok())
+                };
+                if crc()? != prg_crc || crc()? != chr_crc {
+                    return None;
+                }
+                let status = fields.next()?.trim().to_string();
+                // Title and symptoms are quoted and may contain commas
+                let mut quoted = Self::parse_titles(fields.next()?).into_iter();
+                let title = quoted.next()?;
+                let symptoms = quoted.next();
+                match status.as_str() {
+                    "Bad" => Some(DumpIssue::KnownBad { title, symptoms }),
+                    "Overdump" => Some(DumpIssue::KnownOverdump { title, symptoms }),
+                    _ => None,
+                }
+            })
+    }
+
+    /// Looks up a game in the game database by its PRG-ROM.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn lookup_game(prg_rom: &[u8]) -> Option<GameInfo> {
+        use std::io::BufRead;
+
+        let mut hasher = DefaultHasher::new();
+        prg_rom.hash(&mut hasher);
+        let lookup_hash = hasher.finish();
+
+        let db = BufReader::new(GAME_DB);
+        let lines: Vec<String> = db.lines().map_while(Result::ok).collect();
+        let line = lines
+            .binary_search_by(|line| {
+                let hash = line
+                    .split(',')
+                    .next()
+                    .map(|hash| hash.parse::<u64>().unwrap_or_default())
+                    .unwrap_or_default();
+                hash.cmp(&lookup_hash)
+            })
+            .ok()?;
+        Self::parse_game_info(&lines[line])
+    }
+
+    /// Parses a game database entry.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn parse_game_info(line: &str) -> Option<GameInfo> {
+        // Titles are last and may contain commas
+        let mut fields = line.splitn(14, ',').skip(1);
+        let region = NesRegion::try_from(fields.next()?).unwrap_or_default();
+        let battery = fields.nth(8)? == "true";
+        let mut titles = Self::parse_titles(fields.nth(2)?).into_iter();
+        let title = titles.next()?;
+        let title = title.strip_suffix(".nes").unwrap_or(&title).to_string();
+        // GoodNES style tags: `[b]`, or `[b1]` and up for alternate bad dumps
+        let bad_dump = title.split('[').skip(1).any(|tag| {
+            tag.strip_prefix('b')
+                .and_then(|tag| tag.split_once(']'))
+                .map_or(false, |(n, _)| n.chars().all(|c| c.is_ascii_digit()))
+        });
+        Some(GameInfo {
+            region,
+            battery,
+            title: GameTitle {
+                title,
+                native: titles.next(),
+            },
+            bad_dump,
+        })
+    }
+
+    /// Parses comma-separated, quoted title fields.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn parse_titles(fields: &str) -> Vec<String> {
+        let mut titles = vec![];
+        let mut chars = fields.chars();
+        while let Some(c) = chars.next() {
+            if c != '"' {
+                continue;
+            }
+            let mut title = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => title.extend(chars.next()),
+                    '"' => break,
+                    _ => title.push(c),
+                }
+            }
+            titles.push(title);
+        }
+        titles
+    }
+}
+
+/// CRC-32 checksum of ROM data, or `0` if there is no data.
+#[must_use]
+pub fn crc32(data: &[u8]) -> u32 {
+    if data.is_empty() {
+        return 0;
+    }
+    let mut crc = flate2::Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+impl Regional for Cart {
+    #[inline]
+    fn region(&self) -> NesRegion {
+        self.region
+    }
+
+    #[inline]
+    fn set_region(&mut self, region: NesRegion) {
+        self.region = region;
+    }
+}
+
+impl std::fmt::Display for Cart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "{} - {}, CHR-ROM: {}K, CHR-RAM: {}K, PRG-ROM: {}K, PRG-RAM: {}K, Mirroring: {:?}, Battery: {}",
+            self.name,
+            self.mapper_board(),
+            self.chr_rom.len() / 0x0400,
+            self.chr_ram.len() / 0x0400,
+            self.prg_rom.len() / 0x0400,
+            self.prg_ram.len() / 0x0400,
+            self.mirroring(),
+            self.battery_backed(),
+        )
+    }
+}
+
+impl std::fmt::Debug for Cart {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        f.debug_struct("Cart")
+            .field("name", &self.name)
+            .field("title", &self.title)
+            .field("header", &self.header)
+            .field("region", &self.region)
+            .field("ram_state", &self.ram_state)
+            .field("mapper", &self.mapper)
+            .field("mirroring", &self.mirroring())
+            .field("battery_backed", &self.battery_backed())
+            .field("chr_rom_len", &self.chr_rom.len())
+            .field("chr_ram_len", &self.chr_ram.len())
+            .field("ex_ram_len", &self.ex_ram.len())
+            .field("prg_rom_len", &self.prg_rom.len())
+            .field("prg_ram_len", &self.prg_ram.len())
+            .finish()
+    }
+}
+
+/// An `iNES` or `NES 2.0` formatted header representing hardware specs of a given NES cartridge.
+///
+/// <http://wiki.nesdev.com/w/index.php/INES>
+/// <http://wiki.nesdev.com/w/index.php/NES_2.0>
+/// <http://nesdev.com/NESDoc.pdf> (page 28)
+#[derive(Default, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct NesHeader {
+    pub version: u8,        // 1 for iNES or 2 for NES 2.0
+    pub mapper_num: u16,    // The primary mapper number
+    pub submapper_num: u8,  // NES 2.0 https://wiki.nesdev.com/w/index.php/NES_2.0_submappers
+    pub flags: u8,          // Mirroring, Battery, Trainer, VS Unisystem, Playchoice-10, NES 2.0
+    pub prg_rom_banks: u16, // Number of 16KB PRG-ROM banks (Program ROM)
+    pub chr_rom_banks: u16, // Number of 8KB CHR-ROM banks (Character ROM)
+    pub prg_ram_shift: u8,  // NES 2.0 PRG-RAM
+    pub chr_ram_shift: u8,  // NES 2.0 CHR-RAM
+    pub tv_mode: u8,        // NES 2.0 NTSC/PAL indicator
+    pub vs_data: u8,        // NES 2.0 VS System data
+}
+
+impl NesHeader {
+    /// Load `NesHeader` from a ROM path.
+    ///
+    /// # Errors
+    ///
+    /// If the NES header is corrupted, the ROM file cannot be read, or the data does not match
+    /// the header, then an error is returned.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> NesResult<Self> {
+        let path = path.as_ref();
+        let mut rom = BufReader::new(
+            File::open(path).with_context(|| format!("failed to open rom {path:?}"))?,
+        );
+        Self::load(&mut rom)
+    }
+
+    /// Load `NesHeader` from ROM data.
+    ///
+    /// # Errors
+    ///
+    /// If the NES header is invalid, then an error is returned.
+    pub fn load<F: Read>(rom_data: &mut F) -> NesResult<Self> {
+        let mut header = [0u8; 16];
+        rom_data.read_exact(&mut header)?;
+
+        // Header checks
+        if header[0..4] != *b"NES\x1a" {
+            bail!("nes header signature not found");
+        } else if (header[7] & 0x0C) == 0x04 {
+            bail!("header is corrupted by `DiskDude!`. repair and try again");
+        } else if (header[7] & 0x0C) == 0x0C {
+            bail!("unrecognized header format. repair and try again");
+        }
+
+        let mut prg_rom_banks = u16::from(header[4]);
+        let mut chr_rom_banks = u16::from(header[5]);
+        // Upper 4 bits of flags 6 = D0..D3 and 7 = D4..D7
+        let mut mapper_num = u16::from(((header[6] & 0xF0) >> 4) | (header[7] & 0xF0));
+        // Lower 4 bits of flag 6 = D0..D3, upper 4 bits of flag 7 = D4..D7
+        let flags = (header[6] & 0x0F) | ((header[7] & 0x0F) << 4);
+
+        // NES 2.0 Format
+        let mut version = 1; // Start off checking for iNES format
+        let mut submapper_num = 0;
+        let mut prg_ram_shift = 0;
+        let mut chr_ram_shift = 0;
+        let mut tv_mode = 0;
+        let mut vs_data = 0;
+        // If D2..D3 of flag 7 == 2
+        if header[7] & 0x0C == 0x08 {
+            version = 2;
+            // lower 4 bits of flag 8 = D8..D11 of mapper num
+            mapper_num |= u16::from(header[8] & 0x0F) << 8;
+            // upper 4 bits of flag 8 = D0..D3 of submapper
+            submapper_num = (header[8] & 0xF0) >> 4;
+            // lower 4 bits of flag 9 = D8..D11 of prg_rom_size
+            prg_rom_banks |= u16::from(header[9] & 0x0F) << 8;
+            // upper 4 bits of flag 9 = D8..D11 of chr_rom_size
+            chr_rom_banks |= u16::from(header[9] & 0xF0) << 4;
+            prg_ram_shift = header[10];
+            chr_ram_shift = header[11];
+            tv_mode = header[12];
+            vs_data = header[13];
+
+            if prg_ram_shift & 0x0F == 0x0F || prg_ram_shift & 0xF0 == 0xF0 {
+                bail!("invalid prg-ram size in header");
+            } else if chr_ram_shift & 0x0F == 0x0F || chr_ram_shift & 0xF0 == 0xF0 {
+                bail!("invalid chr-ram size in header");
+            } else if chr_ram_shift & 0xF0 == 0xF0 {
+                bail!("battery-backed chr-ram is currently not supported");
+            } else if header[14] > 0 || header[15] > 0 {
+                bail!("unrecognized data found at header offsets 14-15");
+            }
+        } else {
+            for (i, header) in header.iter().enumerate().take(16).skip(8) {
+                if *header > 0 {
+                    bail!(
+                        "unrecogonized data found at header offset {}. repair and try again",
+                        i,
+                    );
+                }
+            }
+        }
+
+        // Trainer
+        if flags & 0x04 == 0x04 {
+            bail!("trained roms are currently not supported.");
+        }
+
+        Ok(Self {
+            version,
+            mapper_num,
+            submapper_num,
+            flags,
+            prg_rom_banks,
+            chr_rom_banks,
+            prg_ram_shift,
+            chr_ram_shift,
+            tv_mode,
+            vs_data,
+        })
+    }
+
+    #[must_use]
+    pub const fn mapper_board(&self) -> &'static str {
+        match self.mapper_num {
+            0 => "Mapper 000 - NROM",
+            1 => "Mapper 001 - SxROM/MMC1B/C",
+            2 => "Mapper 002 - UxROM",
+            3 => "Mapper 003 - CNROM",
+            4 => "Mapper 004 - TxROM/MMC3/MMC6",
+            5 => "Mapper 005 - ExROM/MMC5",
+            7 => "Mapper 007 - AxROM",
+            9 => "Mapper 009 - PxROM/MMC2",
+            10 => "Mapper 010 - FxROM/MMC4",
+            19 => "Mapper 019 - Namco 163",
+            21 => "Mapper 021 - VRC4a/VRC4c",
+            22 => "Mapper 022 - VRC2a",
+            23 => "Mapper 023 - VRC2b/VRC4e/VRC4f",
+            25 => "Mapper 025 - VRC2c/VRC4b/VRC4d",
+            24 => "Mapper 024 - Vrc6a",
+            26 => "Mapper 026 - Vrc6b",
+            28 => "Mapper 028 - Action 53",
+            34 => "Mapper 034 - BNROM/NINA-001",
+            66 => "Mapper 066 - GxROM/MxROM",
+            69 => "Mapper 069 - Sunsoft FME-7/5B",
+            71 => "Mapper 071 - Camerica/Codemasters/BF909x",
+            85 => "Mapper 085 - VRC7",
+            105 => "Mapper 105 - NES-EVENT",
+            155 => "Mapper 155 - SxROM/MMC1A",
+            225 => "Mapper 225 - BMC 64-in-1",
+            228 => "Mapper 228 - Action 52",
+            _ => "Unimplemented Mapper",
+        }
+    }
+}
+
+impl std::fmt::Debug for NesHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
+        f.debug_struct("NesHeader")
+            .field("version", &self.version)
+            .field("mapper_num", &format_args!("{:03}", &self.mapper_num))
+            .field("submapper_num", &self.submapper_num)
+            .field("flags", &format_args!("0b{:08b}", &self.flags))
+            .field("prg_rom_banks", &self.prg_rom_banks)
+            .field("chr_rom_banks", &self.chr_rom_banks)
+            .field("prg_ram_shift", &self.prg_ram_shift)
+            .field("chr_ram_shift", &self.chr_ram_shift)
+            .field("tv_mode", &self.tv_mode)
+            .field("vs_data", &self.vs_data)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! test_headers {
+        ($(($test:ident, $data:expr, $header:expr$(,)?)),*$(,)?) => {$(
+            #[test]
+            fn $test() {
+                let header = NesHeader::load(&mut $data.as_slice()).expect("valid header");
+                assert_eq!(header, $header);
+            }
+        )*};
+    }
+
+    #[rustfmt::skip]
+    test_headers!(
+        (
+            mapper000_horizontal,
+            [0x4E, 0x45, 0x53, 0x1A,
+             0x02, 0x01, 0x01, 0x00,
+             0x00, 0x00, 0x00, 0x00,
+             0x00, 0x00, 0x00, 0x00],
+            NesHeader {
+                version: 1,
+                mapper_num: 0,
+                flags: 0b0000_0001,
+                prg_rom_banks: 2,
+                chr_rom_banks: 1,
+                ..NesHeader::default()
+            },
+        ),
+        (
+            mapper001_vertical,
+            [0x4E, 0x45, 0x53, 0x1A,
+             0x08, 0x00, 0x10, 0x00,
+             0x00, 0x00, 0x00, 0x00,
+             0x00, 0x00, 0x00, 0x00],
+            NesHeader {
+                version: 1,
+                mapper_num: 1,
+                flags: 0b0000_0000,
+                prg_rom_banks: 8,
+                chr_rom_banks: 0,
+                ..NesHeader::default()
+            },
+        ),
+    );
+
+    #[test]
+    fn dump_issues() {
+        let mut prg_rom = vec![0x00; 2 * PRG_ROM_BANK_SIZE];
+        prg_rom[0] = 0xFF;
+        let chr_rom = vec![0x00; CHR_ROM_BANK_SIZE];
+        assert!(Cart::check_dump(&prg_rom, &chr_rom, 0).is_empty());
+
+        prg_rom[PRG_ROM_BANK_SIZE] = 0xFF;
+        assert_eq!(
+            Cart::check_dump(&prg_rom, &chr_rom, 16),
+            [
+                DumpIssue::Mirrored { chr: false },
+                DumpIssue::TrailingData(16)
+            ]
+        );
+
+        let chr_rom = vec![0x00; 3 * CHR_ROM_BANK_SIZE];
+        assert_eq!(
+            Cart::check_dump(&prg_rom[..PRG_ROM_BANK_SIZE], &chr_rom, 0),
+            [DumpIssue::UnusualSize { chr: true }]
+        );
+    }
+
+    #[test]
+    fn known_bad_dumps() {
+        let db = b"# PrgCrc32, ChrCrc32, Status, Title, Symptoms\n\
+            1234ABCD,00000000,Bad,\"Game, The (USA)\",\"Level 2 graphics are garbled.\"\n\
+            89ABCDEF,01234567,Overdump,\"Other Game (Japan)\"\n";
+        let issue = Cart::lookup_bad_dump(db, 0x1234_ABCD, 0).expect("listed bad dump");
+        assert_eq!(
+            issue,
+            DumpIssue::KnownBad {
+                title: "Game, The (USA)".to_string(),
+                symptoms: Some("Level 2 graphics are garbled.".to_string()),
+            }
+        );
+        assert_eq!(
+            issue.to_string(),
+            "Known bad dump of Game, The (USA). Level 2 graphics are garbled."
+        );
+        let issue = Cart::lookup_bad_dump(db, 0x89AB_CDEF, 0x0123_4567).expect("listed overdump");
+        assert!(matches!(
+            issue,
+            DumpIssue::KnownOverdump { symptoms: None, .. }
+        ));
+        assert!(issue
+            .to_string()
+            .starts_with("Known overdump of Other Game (Japan). Usually"));
+        assert_eq!(Cart::lookup_bad_dump(db, 0x1234_ABCD, 1), None);
+
+        // Every entry in the shipped database parses
+        let entries = BAD_DUMP_DB
+            .split(|&b| b == b'\n')
+            .filter(|line| !line.is_empty() && !line.starts_with(b"#"));
+        for line in entries {
+            let line = std::str::from_utf8(line).expect("valid utf8");
+            let mut crcs = line.split(',').map(|crc| u32::from_str_radix(crc, 16));
+            let (Some(Ok(prg_crc)), Some(Ok(chr_crc))) = (crcs.next(), crcs.next()) else {
+                panic!("invalid entry: {line}");
+            };
+            assert!(
+                Cart::lookup_bad_dump(BAD_DUMP_DB, prg_crc, chr_crc).is_some(),
+                "{line}"
+            );
+        }
+
+        // Dumps tagged as bad in the game database
+        let info = |title: &str| {
+            Cart::parse_game_info(&format!(
+                "0,NTSC,,,,4,8,16,0,0,false,Horizontal,0,\"{title}\""
+            ))
+            .expect("valid entry")
+        };
+        assert!(info("Bugs Bunny Birthday Bash (USA) (Beta) [b].nes").bad_dump);
+        assert!(info("Game (USA) [b2].nes").bad_dump);
+        assert!(!info("Game (USA) [!].nes").bad_dump);
+        assert!(!info("Game (USA) (Beta).nes").bad_dump);
+        let info = info("Exerion (Japan) (En) (Proto) [b].nes");
+        assert_eq!(info.title.title, "Exerion (Japan) (En) (Proto) [b]");
+    }
+
+    #[test]
+    fn game_titles() {
+        assert_eq!(
+            Cart::parse_titles(r#""Nobunaga\'s Ambition II (USA).nes""#),
+            ["Nobunaga's Ambition II (USA).nes"]
+        );
+        assert_eq!(
+            Cart::parse_titles(r#""Addams Family, The (USA).nes","アダムス・ファミリー""#),
+            ["Addams Family, The (USA).nes", "アダムス・ファミリー"]
+        );
+
+        let mut trailing = "星のカービィ".as_bytes().to_vec();
+        trailing.resize(128, 0x00);
+        let title = GameTitle::from_trailing_data(&trailing).expect("valid title");
+        assert_eq!(title.display(true), "星のカービィ");
+        assert!(GameTitle::from_trailing_data(&[0x01; 128]).is_none());
+        assert!(GameTitle::from_trailing_data(b"Too short").is_none());
+    }
+}
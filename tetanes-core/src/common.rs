@@ -2,9 +2,9 @@ use crate::{NesError, NesResult};
 use anyhow::anyhow;
 use enum_dispatch::enum_dispatch;
 use serde::{Deserialize, Serialize};
-use std::fmt::Write;
 #[cfg(not(target_arch = "wasm32"))]
 use std::path::{Path, PathBuf};
+use std::{fmt::Write, str::FromStr};
 
 pub const CONFIG_DIR: &str = ".config/tetanes";
 pub const SAVE_DIR: &str = "save";
@@ -23,6 +23,16 @@ impl NesRegion {
     pub const fn as_slice() -> &'static [Self] {
         &[NesRegion::Ntsc, NesRegion::Pal, NesRegion::Dendy]
     }
+
+    /// Emulated video frames per second for this region.
+    #[inline]
+    #[must_use]
+    pub const fn frame_rate(&self) -> f32 {
+        match self {
+            Self::Ntsc => 60.098_8,
+            Self::Pal | Self::Dendy => 50.007,
+        }
+    }
 }
 
 impl AsRef<str> for NesRegion {
@@ -48,6 +58,19 @@ impl TryFrom<&str> for NesRegion {
     }
 }
 
+impl FromStr for NesRegion {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ntsc" => Ok(Self::Ntsc),
+            "pal" => Ok(Self::Pal),
+            "dendy" => Ok(Self::Dendy),
+            _ => Err("invalid NesRegion value. valid options: `ntsc`, `pal`, or `dendy`"),
+        }
+    }
+}
+
 impl From<usize> for NesRegion {
     fn from(value: usize) -> Self {
         match value {
@@ -103,14 +126,14 @@ macro_rules! hashmap {
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-pub(crate) fn config_dir() -> PathBuf {
+pub fn config_dir() -> PathBuf {
     dirs::home_dir()
         .unwrap_or_else(|| PathBuf::from("./"))
         .join(CONFIG_DIR)
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-pub(crate) fn config_path<P: AsRef<Path>>(path: P) -> PathBuf {
+pub fn config_path<P: AsRef<Path>>(path: P) -> PathBuf {
     config_dir().join(path)
 }
 
@@ -174,15 +197,14 @@ pub(crate) mod tests {
     use super::*;
     use crate::{
         control_deck::ControlDeck,
-        input::Slot,
+        input::{JoypadBtn, Slot},
         mapper::{Mapper, MapperRevision},
-        nes::event::{Action, NesState, Setting},
+        mem::{Access, Mem},
         ppu::Ppu,
         video::VideoFilter,
     };
     use anyhow::Context;
     use once_cell::sync::Lazy;
-    use pix_engine::prelude::{Image, PixelFormat};
     use serde::{Deserialize, Serialize};
     use std::fmt::Write;
     use std::{
@@ -194,22 +216,24 @@ pub(crate) mod tests {
         path::{Path, PathBuf},
     };
 
+    /// Test ROMs and results live in the workspace root, shared with the frontend tests.
+    pub(crate) const ROOT_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
     pub(crate) const RESULT_DIR: &str = "test_results";
 
     static INIT_TESTS: Lazy<bool> = Lazy::new(|| {
-        let result_dir = PathBuf::from(RESULT_DIR);
+        let result_dir = PathBuf::from(ROOT_DIR).join(RESULT_DIR);
         if result_dir.exists() {
             fs::remove_dir_all(result_dir).expect("cleared test results dir");
         }
         true
     });
     static PASS_DIR: Lazy<PathBuf> = Lazy::new(|| {
-        let directory = PathBuf::from(RESULT_DIR).join("pass");
+        let directory = PathBuf::from(ROOT_DIR).join(RESULT_DIR).join("pass");
         fs::create_dir_all(&directory).expect("created pass test results dir");
         directory
     });
     static FAIL_DIR: Lazy<PathBuf> = Lazy::new(|| {
-        let directory = PathBuf::from(RESULT_DIR).join("fail");
+        let directory = PathBuf::from(ROOT_DIR).join(RESULT_DIR).join("fail");
         fs::create_dir_all(&directory).expect("created fail test results dir");
         directory
     });
@@ -225,6 +249,27 @@ pub(crate) mod tests {
         )*};
     }
 
+    /// Input actions recorded in `tests.json`, serialized the same way as the frontend's actions.
+    #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+    enum Action {
+        Nes(NesState),
+        Setting(Setting),
+        Joypad(JoypadBtn),
+    }
+
+    #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+    enum NesState {
+        SoftReset,
+        HardReset,
+        MapperRevision(MapperRevision),
+    }
+
+    #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+    enum Setting {
+        SetVideoFilter(VideoFilter),
+        SetNesFormat(NesRegion),
+    }
+
     #[derive(Debug, Clone, Serialize, Deserialize)]
     #[must_use]
     struct TestFrame {
@@ -237,6 +282,10 @@ pub(crate) mod tests {
         slot: Option<Slot>,
         #[serde(skip_serializing_if = "Option::is_none")]
         action: Option<Action>,
+        /// Text on screen by this frame, for ROMs like blargg's older tests that print whether
+        /// they passed instead of reporting it through $6000.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        text: Option<String>,
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -247,7 +296,8 @@ pub(crate) mod tests {
     }
 
     fn get_rom_tests(directory: &str) -> (PathBuf, Vec<RomTest>) {
-        let file = PathBuf::from(directory)
+        let file = PathBuf::from(ROOT_DIR)
+            .join(directory)
             .join("tests")
             .with_extension("json");
         let tests = File::open(&file)
@@ -260,7 +310,7 @@ pub(crate) mod tests {
         (file, tests)
     }
 
-    fn load_control_deck<P: AsRef<Path>>(path: P) -> ControlDeck {
+    pub(crate) fn load_control_deck<P: AsRef<Path>>(path: P) -> ControlDeck {
         let path = path.as_ref();
         let mut rom = BufReader::new(File::open(path).expect("failed to open path"));
         let mut deck = ControlDeck::default();
@@ -286,19 +336,16 @@ pub(crate) mod tests {
                         }
                         _ => panic!("unhandled MapperRevision {board:?}"),
                     },
-                    _ => panic!("unhandled Nes state: {state:?}"),
                 },
                 Action::Setting(setting) => match setting {
                     Setting::SetVideoFilter(filter) => deck.set_filter(filter),
                     Setting::SetNesFormat(format) => deck.set_region(format),
-                    _ => panic!("unhandled Setting: {setting:?}"),
                 },
                 Action::Joypad(button) => {
                     let slot = test_frame.slot.unwrap_or(Slot::One);
                     let joypad = deck.joypad_mut(slot);
                     joypad.set_button(button.into(), true);
                 }
-                _ => (),
             }
         }
     }
@@ -335,15 +382,34 @@ pub(crate) mod tests {
                 .join(PathBuf::from(filename))
                 .with_extension("png");
 
-            Image::from_bytes(Ppu::WIDTH, Ppu::HEIGHT, frame, PixelFormat::Rgba)
-                .expect("valid frame")
-                .save(&screenshot)
+            let mut encoder = png::Encoder::new(
+                BufWriter::new(File::create(&screenshot).expect("result screenshot")),
+                Ppu::WIDTH,
+                Ppu::HEIGHT,
+            );
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder
+                .write_header()
+                .and_then(|mut writer| writer.write_image_data(frame))
                 .expect("result screenshot");
 
             (expected, actual, test_frame.number, screenshot)
         })
     }
 
+    /// Whether any nametable holds `text`. Tests that print results load an ASCII font, so
+    /// each character is its own tile index.
+    fn nametables_contain(deck: &ControlDeck, text: &str) -> bool {
+        let ppu = deck.ppu();
+        let nametables = (Ppu::NT_START..Ppu::NT_START + 4 * Ppu::NT_SIZE)
+            .map(|addr| ppu.peek(addr, Access::Dummy))
+            .collect::<Vec<_>>();
+        nametables
+            .windows(text.len())
+            .any(|tiles| tiles == text.as_bytes())
+    }
+
     pub(crate) fn test_rom(directory: &str, test_name: &str) {
         if !&*INIT_TESTS {
             log::debug!("Initialized tests");
@@ -354,7 +420,8 @@ pub(crate) mod tests {
         assert!(test.is_some(), "No test found matching {test_name:?}");
         let test = test.as_mut().expect("definitely has a test");
 
-        let rom = PathBuf::from(directory)
+        let rom = PathBuf::from(ROOT_DIR)
+            .join(directory)
             .join(PathBuf::from(&test.name))
             .with_extension("nes");
         assert!(rom.exists(), "No test rom found for {rom:?}");
@@ -380,6 +447,13 @@ pub(crate) mod tests {
             {
                 results.push(result);
             }
+            if let Some(ref text) = test_frame.text {
+                assert!(
+                    nametables_contain(&deck, text),
+                    "{rom:?} didn't print {text:?} by frame {}",
+                    test_frame.number
+                );
+            }
         }
         let mut update_required = false;
         for (mut expected, actual, frame_number, screenshot) in results {
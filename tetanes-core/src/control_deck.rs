@@ -0,0 +1,904 @@
+use crate::{
+    apu::{Apu, ApuMixing, Channel},
+    bus::CpuBus,
+    cart::{Cart, DumpIssue, GameTitle},
+    common::{Clock, Kind, NesRegion, Regional, Reset},
+    cpu::{Cpu, UnofficialOpcodes},
+    input::{FourPlayer, Joypad, Slot},
+    mapper::{AudioChip, ExpansionAudio, Mapped, Mapper, NesEvent},
+    mem::RamState,
+    ppu::Ppu,
+    profiling::ScanlineSpan,
+    video::{ColorFilter, Frame, Video, VideoFilter},
+    NesResult,
+};
+use anyhow::anyhow;
+use std::{io::Read, mem, ops::ControlFlow};
+
+/// Represents an NES Control Deck
+#[derive(Debug, Clone)]
+#[must_use]
+pub struct ControlDeck {
+    running: bool,
+    ram_state: RamState,
+    region: NesRegion,
+    video: Video,
+    loaded_rom: Option<String>,
+    dump_issues: Vec<DumpIssue>,
+    title: Option<GameTitle>,
+    game_region: Option<NesRegion>,
+    dip_switches: u8,
+    state_cheats: bool,
+    cycles_remaining: f32,
+    cpu: Cpu,
+}
+
+impl Default for ControlDeck {
+    fn default() -> Self {
+        Self::new(RamState::default())
+    }
+}
+
+impl ControlDeck {
+    /// Create a NES `ControlDeck`.
+    pub fn new(ram_state: RamState) -> Self {
+        let cpu = Cpu::new(CpuBus::new(ram_state));
+        Self {
+            running: false,
+            ram_state,
+            region: NesRegion::default(),
+            video: Video::default(),
+            loaded_rom: None,
+            dump_issues: vec![],
+            title: None,
+            game_region: None,
+            dip_switches: NesEvent::DEFAULT_DIP_SWITCHES,
+            state_cheats: true,
+            cycles_remaining: 0.0,
+            cpu,
+        }
+    }
+
+    /// Loads a ROM cartridge into memory
+    ///
+    /// # Errors
+    ///
+    /// If there is any issue loading the ROM, then an error is returned.
+    pub fn load_rom<S: ToString, F: Read>(&mut self, name: S, rom: &mut F) -> NesResult<()> {
+        self.loaded_rom = Some(name.to_string());
+        let cart = Cart::from_rom(name, rom, self.ram_state)?;
+        self.dump_issues = cart.dump_issues().to_vec();
+        self.title = cart.title().cloned();
+        self.game_region = cart.game_region();
+        self.set_region(cart.region());
+        self.cpu.load_cart(cart);
+        self.mapper_mut().set_dip_switches(self.dip_switches);
+        self.reset(Kind::Hard);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn load_cpu(&mut self, mut cpu: Cpu) {
+        self.keep_user_settings(&mut cpu);
+        self.cpu = cpu;
+        self.video.invalidate();
+    }
+
+    /// Loads a CPU state, verifying that it was captured with the same mapper configuration as
+    /// the currently loaded cartridge.
+    ///
+    /// # Errors
+    ///
+    /// If the mapper of the given CPU state does not match the loaded cartridge, an error is
+    /// returned and the current state is left unchanged.
+    pub fn try_load_cpu(&mut self, mut cpu: Cpu) -> NesResult<()> {
+        if mem::discriminant(self.cpu.mapper()) != mem::discriminant(cpu.mapper()) {
+            return Err(anyhow!(
+                "cpu state mapper does not match the loaded cartridge"
+            ));
+        }
+        self.keep_user_settings(&mut cpu);
+        self.cpu = cpu;
+        self.video.invalidate();
+        Ok(())
+    }
+
+    /// Mixer and accuracy settings are user preferences, so carry them over when loading a saved
+    /// CPU state. Game Genie codes are carried over too unless save states restore them.
+    fn keep_user_settings(&self, cpu: &mut Cpu) {
+        for channel in Channel::as_slice() {
+            cpu.set_audio_channel_volume(*channel, self.cpu.audio_channel_volume(*channel));
+        }
+        cpu.set_expansion_volume(self.cpu.expansion_volume());
+        for chip in AudioChip::as_slice() {
+            cpu.set_audio_chip_gain(*chip, self.cpu.audio_chip_gain(*chip));
+        }
+        cpu.set_apu_mixing(self.cpu.apu_mixing());
+        cpu.set_dmc_pop_reduction(self.cpu.dmc_pop_reduction());
+        cpu.set_cycle_accurate(self.cpu.cycle_accurate());
+        cpu.set_ppu_alignment(self.cpu.ppu_alignment());
+        cpu.ppu_mut().set_frame_skip(self.cpu.ppu().frame_skip());
+        cpu.set_unofficial_opcodes(self.cpu.unofficial_opcodes());
+        if !self.state_cheats {
+            cpu.set_genie_codes(self.cpu.genie_codes().clone());
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn loaded_rom(&self) -> &Option<String> {
+        &self.loaded_rom
+    }
+
+    /// Problems detected with the loaded ROM dump.
+    #[inline]
+    #[must_use]
+    pub fn dump_issues(&self) -> &[DumpIssue] {
+        &self.dump_issues
+    }
+
+    /// Title of the loaded game, if found in the game database or the ROM file.
+    #[inline]
+    #[must_use]
+    pub const fn title(&self) -> Option<&GameTitle> {
+        self.title.as_ref()
+    }
+
+    /// Region the loaded game was released for, if found in the game database.
+    #[inline]
+    #[must_use]
+    pub const fn game_region(&self) -> Option<NesRegion> {
+        self.game_region
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn cart_battery_backed(&self) -> bool {
+        self.cpu.cart_battery_backed()
+    }
+
+    /// Overrides whether the loaded cartridge has battery-backed Save RAM, which otherwise
+    /// comes from the game database or ROM header.
+    #[inline]
+    pub fn set_cart_battery_backed(&mut self, battery_backed: bool) {
+        self.cpu.set_cart_battery_backed(battery_backed);
+    }
+
+    /// Returns whether PRG-RAM is write-protected.
+    #[inline]
+    #[must_use]
+    pub const fn prg_ram_write_protected(&self) -> bool {
+        self.cpu.prg_ram_write_protected()
+    }
+
+    /// Write-protects PRG-RAM, which some games check to detect copiers or behave differently
+    /// with. Cleared when a new cartridge is loaded.
+    #[inline]
+    pub fn set_prg_ram_write_protected(&mut self, protect: bool) {
+        self.cpu.set_prg_ram_write_protected(protect);
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn sram(&self) -> &[u8] {
+        self.cpu.sram()
+    }
+
+    #[inline]
+    pub fn load_sram(&mut self, sram: Vec<u8>) {
+        self.cpu.load_sram(sram);
+    }
+
+    /// Returns PRG-RAM for editing, ignoring write protection.
+    #[inline]
+    #[must_use]
+    pub fn sram_mut(&mut self) -> &mut [u8] {
+        self.cpu.sram_mut()
+    }
+
+    /// Returns the offset into PRG-ROM that a CPU address is currently mapped to, if any.
+    #[inline]
+    #[must_use]
+    pub fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        self.cpu.prg_rom_offset(addr)
+    }
+
+    /// Returns PRG-ROM for patching.
+    #[inline]
+    #[must_use]
+    pub fn prg_rom_mut(&mut self) -> &mut [u8] {
+        self.cpu.prg_rom_mut()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn wram(&self) -> &[u8] {
+        self.cpu.wram()
+    }
+
+    /// Get a frame worth of pixels.
+    #[inline]
+    #[must_use]
+    pub fn frame_buffer(&mut self) -> &[u8] {
+        self.frame().pixels()
+    }
+
+    /// Get the last completed frame, filtered with the current video settings. Each frame is only
+    /// filtered once, so this is cheap to call repeatedly.
+    #[inline]
+    pub fn frame(&mut self) -> &Frame {
+        self.video
+            .apply_filter(self.cpu.frame_buffer(), self.cpu.frame_number());
+        self.video.frame()
+    }
+
+    /// Get the last completed frame at its native resolution with the system palette only,
+    /// ignoring the video and color filters.
+    #[inline]
+    pub fn raw_frame(&self) -> Frame {
+        Video::decode_raw(self.cpu.frame_buffer())
+    }
+
+    /// Get the current frame number.
+    #[inline]
+    #[must_use]
+    pub const fn frame_number(&self) -> u32 {
+        self.cpu.frame_number()
+    }
+
+    /// Audio sample rate.
+    #[inline]
+    #[must_use]
+    pub const fn sample_rate(&self) -> f32 {
+        self.cpu.clock_rate()
+    }
+
+    /// Get audio samples.
+    #[inline]
+    #[must_use]
+    pub fn audio_samples(&self) -> &[f32] {
+        self.cpu.audio_samples()
+    }
+
+    /// Clear audio samples.
+    #[inline]
+    pub fn clear_audio_samples(&mut self) {
+        self.cpu.clear_audio_samples();
+    }
+
+    /// Enable or disable capturing each audio channel separately.
+    #[inline]
+    pub fn set_channel_samples_enabled(&mut self, enabled: bool) {
+        self.cpu.set_channel_samples_enabled(enabled);
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn channel_samples_enabled(&self) -> bool {
+        self.cpu.channel_samples_enabled()
+    }
+
+    /// Get per-channel audio samples, interleaved in `Channel` order followed by expansion audio.
+    #[inline]
+    #[must_use]
+    pub fn channel_samples(&self) -> &[f32] {
+        self.cpu.channel_samples()
+    }
+
+    #[inline]
+    pub fn clock_rate(&mut self) -> f32 {
+        self.cpu.clock_rate()
+    }
+
+    /// Steps the control deck one CPU clock.
+    ///
+    /// # Errors
+    ///
+    /// If CPU encounteres an invalid opcode, an error is returned.
+    pub fn clock_instr(&mut self) -> NesResult<ControlFlow<usize, usize>> {
+        let cycles = self.clock();
+        if self.cpu_corrupted() {
+            Err(anyhow!("cpu corrupted"))
+        } else if self.cpu.trapped_opcode().is_some() {
+            Ok(ControlFlow::Break(cycles))
+        } else {
+            Ok(ControlFlow::Continue(cycles))
+        }
+    }
+
+    /// Steps the control deck the number of seconds.
+    ///
+    /// # Errors
+    ///
+    /// If CPU encounteres an invalid opcode, an error is returned.
+    pub fn clock_seconds(&mut self, seconds: f32) -> NesResult<ControlFlow<usize, usize>> {
+        self.cycles_remaining += self.clock_rate() * seconds;
+        let mut total_cycles = 0;
+        let mut scanline_span = ScanlineSpan::default();
+        while self.cycles_remaining > 0.0 {
+            scanline_span.update(self.cpu.ppu_scanline());
+            match self.clock_instr()? {
+                ControlFlow::Break(cycles) => {
+                    total_cycles += cycles;
+                    self.cycles_remaining = 0.0;
+                    return Ok(ControlFlow::Break(total_cycles));
+                }
+                ControlFlow::Continue(cycles) => {
+                    total_cycles += cycles;
+                    self.cycles_remaining -= cycles as f32;
+                }
+            }
+        }
+        Ok(ControlFlow::Continue(total_cycles))
+    }
+
+    /// Steps the control deck the number of seconds with an inspection function, executed on every
+    /// CPU clock.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if .
+    pub fn clock_seconds_inspect<F>(
+        &mut self,
+        seconds: f32,
+        mut inspect: F,
+    ) -> NesResult<ControlFlow<usize, usize>>
+    where
+        F: FnMut(&mut Cpu),
+    {
+        self.cycles_remaining += self.clock_rate() * seconds;
+        let mut total_cycles = 0;
+        let mut scanline_span = ScanlineSpan::default();
+        while self.cycles_remaining > 0.0 {
+            scanline_span.update(self.cpu.ppu_scanline());
+            let cycles = self.cpu.clock_inspect(&mut inspect);
+            total_cycles += cycles;
+            self.cycles_remaining -= cycles as f32;
+            if self.cpu.trapped_opcode().is_some() {
+                self.cycles_remaining = 0.0;
+                return Ok(ControlFlow::Break(total_cycles));
+            }
+        }
+        Ok(ControlFlow::Continue(total_cycles))
+    }
+
+    /// Steps the control deck the number of seconds, checking `should_break` after every
+    /// instruction and stopping early if it returns `true`.
+    ///
+    /// # Errors
+    ///
+    /// If CPU encounteres an invalid opcode, an error is returned.
+    pub fn clock_seconds_until<F>(
+        &mut self,
+        seconds: f32,
+        mut should_break: F,
+    ) -> NesResult<ControlFlow<usize, usize>>
+    where
+        F: FnMut(&mut Cpu) -> bool,
+    {
+        self.cycles_remaining += self.clock_rate() * seconds;
+        let mut total_cycles = 0;
+        let mut scanline_span = ScanlineSpan::default();
+        while self.cycles_remaining > 0.0 {
+            scanline_span.update(self.cpu.ppu_scanline());
+            let flow = self.clock_instr()?;
+            let (ControlFlow::Break(cycles) | ControlFlow::Continue(cycles)) = flow;
+            total_cycles += cycles;
+            self.cycles_remaining -= cycles as f32;
+            if should_break(&mut self.cpu) || flow.is_break() {
+                // Drop any leftover time so resuming doesn't run ahead
+                self.cycles_remaining = 0.0;
+                return Ok(ControlFlow::Break(total_cycles));
+            }
+        }
+        Ok(ControlFlow::Continue(total_cycles))
+    }
+
+    /// Steps the control deck an entire frame
+    ///
+    /// # Errors
+    ///
+    /// If CPU encounteres an invalid opcode, an error is returned.
+    pub fn clock_frame(&mut self) -> NesResult<ControlFlow<usize, usize>> {
+        let mut total_cycles = 0;
+        let frame = self.frame_number();
+        let mut scanline_span = ScanlineSpan::default();
+        while frame == self.frame_number() {
+            scanline_span.update(self.cpu.ppu_scanline());
+            match self.clock_instr()? {
+                ControlFlow::Break(cycles) => {
+                    total_cycles += cycles;
+                    return Ok(ControlFlow::Break(total_cycles));
+                }
+                ControlFlow::Continue(cycles) => {
+                    total_cycles += cycles;
+                }
+            }
+        }
+        Ok(ControlFlow::Continue(total_cycles))
+    }
+
+    /// Steps the control deck an entire frame, checking `should_break` after every instruction
+    /// and stopping early if it returns `true`.
+    ///
+    /// # Errors
+    ///
+    /// If CPU encounteres an invalid opcode, an error is returned.
+    pub fn clock_frame_until<F>(
+        &mut self,
+        mut should_break: F,
+    ) -> NesResult<ControlFlow<usize, usize>>
+    where
+        F: FnMut(&mut Cpu) -> bool,
+    {
+        let mut total_cycles = 0;
+        let frame = self.frame_number();
+        let mut scanline_span = ScanlineSpan::default();
+        while frame == self.frame_number() {
+            scanline_span.update(self.cpu.ppu_scanline());
+            let flow = self.clock_instr()?;
+            let (ControlFlow::Break(cycles) | ControlFlow::Continue(cycles)) = flow;
+            total_cycles += cycles;
+            if should_break(&mut self.cpu) || flow.is_break() {
+                return Ok(ControlFlow::Break(total_cycles));
+            }
+        }
+        Ok(ControlFlow::Continue(total_cycles))
+    }
+
+    /// Steps the control deck a single scanline.
+    ///
+    /// # Errors
+    ///
+    /// If CPU encounteres an invalid opcode, an error is returned.
+    pub fn clock_scanline(&mut self) -> NesResult<ControlFlow<usize, usize>> {
+        let current_scanline = self.cpu.ppu_scanline();
+        let mut total_cycles = 0;
+        while current_scanline == self.cpu.ppu_scanline() {
+            match self.clock_instr()? {
+                ControlFlow::Break(cycles) => {
+                    total_cycles += cycles;
+                    return Ok(ControlFlow::Break(total_cycles));
+                }
+                ControlFlow::Continue(cycles) => {
+                    total_cycles += cycles;
+                }
+            }
+        }
+        Ok(ControlFlow::Continue(total_cycles))
+    }
+
+    /// Returns whether the CPU is corrupted or not.
+    #[inline]
+    #[must_use]
+    pub const fn cpu_corrupted(&self) -> bool {
+        self.cpu.corrupted()
+    }
+
+    #[inline]
+    pub const fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    #[inline]
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        // The caller may change the PPU output
+        self.video.invalidate();
+        &mut self.cpu
+    }
+
+    #[inline]
+    pub const fn ppu(&self) -> &Ppu {
+        self.cpu.ppu()
+    }
+
+    #[inline]
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        self.cpu.ppu_mut()
+    }
+
+    #[inline]
+    pub const fn apu(&self) -> &Apu {
+        self.cpu.apu()
+    }
+
+    #[inline]
+    pub const fn mapper(&self) -> &Mapper {
+        self.cpu.mapper()
+    }
+
+    #[inline]
+    pub fn mapper_mut(&mut self) -> &mut Mapper {
+        self.cpu.mapper_mut()
+    }
+
+    /// Returns whether Four Score is enabled.
+    #[inline]
+    pub const fn four_player(&self) -> FourPlayer {
+        self.cpu.four_player()
+    }
+
+    /// Enable/Disable Four Score for 4-player controllers.
+    #[inline]
+    pub fn set_four_player(&mut self, four_player: FourPlayer) {
+        self.cpu.set_four_player(four_player);
+    }
+
+    /// Sets the DIP switches for boards that have them, like the timer of the Nintendo World
+    /// Championships cartridge.
+    #[inline]
+    pub fn set_dip_switches(&mut self, dip_switches: u8) {
+        self.dip_switches = dip_switches;
+        self.mapper_mut().set_dip_switches(dip_switches);
+    }
+
+    /// Returns whether cycle accurate mode is enabled.
+    #[inline]
+    #[must_use]
+    pub const fn cycle_accurate(&self) -> bool {
+        self.cpu.cycle_accurate()
+    }
+
+    /// Enable/Disable cycle accurate mode, which clocks the PPU and APU every CPU cycle and
+    /// emulates DMA register conflicts like the controller read glitch.
+    #[inline]
+    pub fn set_cycle_accurate(&mut self, enabled: bool) {
+        self.cpu.set_cycle_accurate(enabled);
+    }
+
+    /// Returns the number of frames skipped between rendered frames.
+    #[inline]
+    #[must_use]
+    pub const fn frame_skip(&self) -> u32 {
+        self.cpu.ppu().frame_skip()
+    }
+
+    /// Skips pixel output for `frame_skip` frames between each rendered frame, for faster runs
+    /// where most frames aren't shown. Emulation is unaffected, and `frame_buffer` returns the
+    /// last rendered frame. Set to `0` to render every frame again, starting with the next frame.
+    #[inline]
+    pub fn set_frame_skip(&mut self, frame_skip: u32) {
+        self.cpu.ppu_mut().set_frame_skip(frame_skip);
+    }
+
+    /// Returns whether pixel output was skipped for the last frame.
+    #[inline]
+    #[must_use]
+    pub const fn frame_skipped(&self) -> bool {
+        self.cpu.ppu().frame_skipped()
+    }
+
+    /// Returns how unofficial opcodes are handled.
+    #[inline]
+    pub const fn unofficial_opcodes(&self) -> UnofficialOpcodes {
+        self.cpu.unofficial_opcodes()
+    }
+
+    /// Set whether unofficial opcodes are allowed, logged or break emulation when executed.
+    #[inline]
+    pub fn set_unofficial_opcodes(&mut self, unofficial_opcodes: UnofficialOpcodes) {
+        self.cpu.set_unofficial_opcodes(unofficial_opcodes);
+    }
+
+    /// Returns the RAM contents used on power up.
+    #[inline]
+    pub const fn ram_state(&self) -> RamState {
+        self.ram_state
+    }
+
+    /// Set the RAM contents used on power up, taking effect on the next power cycle or ROM load.
+    #[inline]
+    pub fn set_ram_state(&mut self, ram_state: RamState) {
+        self.ram_state = ram_state;
+        self.cpu.set_ram_state(ram_state);
+    }
+
+    /// Returns how many master clocks the PPU runs behind the CPU.
+    #[inline]
+    #[must_use]
+    pub const fn ppu_alignment(&self) -> u8 {
+        self.cpu.ppu_alignment()
+    }
+
+    /// Set how many master clocks the PPU runs behind the CPU, clamped to the alignments possible
+    /// for the region. Real consoles pick one at random on power up.
+    #[inline]
+    pub fn set_ppu_alignment(&mut self, alignment: u8) {
+        self.cpu.set_ppu_alignment(alignment);
+    }
+
+    /// Returns a reference to a joypad.
+    #[inline]
+    pub const fn joypad(&self, slot: Slot) -> &Joypad {
+        self.cpu.joypad(slot)
+    }
+
+    /// Returns a mutable reference to a joypad.
+    #[inline]
+    pub fn joypad_mut(&mut self, slot: Slot) -> &mut Joypad {
+        self.cpu.joypad_mut(slot)
+    }
+
+    /// Returns the zapper aiming position for the given controller slot.
+    #[inline]
+    #[must_use]
+    pub const fn zapper_pos(&self) -> (i32, i32) {
+        let zapper = self.cpu.zapper();
+        (zapper.x(), zapper.y())
+    }
+
+    /// Whether the last frame was a lag frame, where the game didn't read the joypads.
+    #[inline]
+    #[must_use]
+    pub const fn lagged(&self) -> bool {
+        self.cpu.lagged()
+    }
+
+    /// Number of lag frames since power on.
+    #[inline]
+    #[must_use]
+    pub const fn lag_frames(&self) -> u32 {
+        self.cpu.lag_frames()
+    }
+
+    /// Trigger Zapper gun for a given controller slot.
+    #[inline]
+    pub fn trigger_zapper(&mut self) {
+        self.cpu.zapper_mut().trigger();
+    }
+
+    /// Aim Zapper gun for a given controller slot.
+    #[inline]
+    pub fn aim_zapper(&mut self, x: i32, y: i32) {
+        self.cpu.zapper_mut().aim(x, y);
+    }
+
+    /// Set the color blindness filter for video output.
+    #[inline]
+    pub fn set_color_filter(&mut self, filter: ColorFilter, simulate: bool) {
+        self.video.set_color_filter(filter, simulate);
+    }
+
+    /// Set the image filter for video output.
+    #[inline]
+    pub fn set_filter(&mut self, filter: VideoFilter) {
+        self.video.set_filter(filter);
+    }
+
+    /// Set how many scanlines share each filtered line, trading filter quality for speed.
+    #[inline]
+    pub fn set_filter_scale(&mut self, scale: u32) {
+        self.video.set_filter_scale(scale);
+    }
+
+    /// Get how many scanlines share each filtered line.
+    #[inline]
+    #[must_use]
+    pub const fn filter_scale(&self) -> u32 {
+        self.video.filter_scale()
+    }
+
+    /// Enable Zapper gun.
+    #[inline]
+    pub fn connect_zapper(&mut self, enabled: bool) {
+        self.cpu.connect_zapper(enabled);
+    }
+
+    /// Add NES Game Genie codes.
+    ///
+    /// # Errors
+    ///
+    /// If genie code is invalid, an error is returned.
+    #[inline]
+    pub fn add_genie_code(&mut self, genie_code: String) -> NesResult<()> {
+        self.cpu.add_genie_code(genie_code)
+    }
+
+    #[inline]
+    pub fn remove_genie_code(&mut self, genie_code: &str) {
+        self.cpu.remove_genie_code(genie_code);
+    }
+
+    /// Whether loading a CPU state restores the Game Genie codes that were active when it was
+    /// saved, instead of keeping the current ones.
+    #[inline]
+    #[must_use]
+    pub const fn state_cheats(&self) -> bool {
+        self.state_cheats
+    }
+
+    #[inline]
+    pub fn set_state_cheats(&mut self, enabled: bool) {
+        self.state_cheats = enabled;
+    }
+
+    /// Returns whether a given API audio channel is enabled.
+    #[inline]
+    #[must_use]
+    pub const fn channel_enabled(&self, channel: Channel) -> bool {
+        self.cpu.audio_channel_enabled(channel)
+    }
+
+    /// Toggle one of the APU audio channels.
+    #[inline]
+    pub fn toggle_channel(&mut self, channel: Channel) {
+        self.cpu.toggle_audio_channel(channel);
+    }
+
+    /// Returns the mixer volume of an APU audio channel.
+    #[inline]
+    #[must_use]
+    pub const fn channel_volume(&self, channel: Channel) -> f32 {
+        self.cpu.audio_channel_volume(channel)
+    }
+
+    /// Sets the mixer volume of an APU audio channel, from `0.0` (muted) to `1.0` (full volume).
+    #[inline]
+    pub fn set_channel_volume(&mut self, channel: Channel, volume: f32) {
+        self.cpu.set_audio_channel_volume(channel, volume);
+    }
+
+    /// Returns the mixer volume of cartridge expansion audio.
+    #[inline]
+    #[must_use]
+    pub const fn expansion_volume(&self) -> f32 {
+        self.cpu.expansion_volume()
+    }
+
+    /// Sets the mixer volume of cartridge expansion audio, from `0.0` (muted) to `1.0` (full
+    /// volume).
+    #[inline]
+    pub fn set_expansion_volume(&mut self, volume: f32) {
+        self.cpu.set_expansion_volume(volume);
+    }
+
+    /// Returns the gain applied to a cartridge sound chip.
+    #[inline]
+    #[must_use]
+    pub const fn audio_chip_gain(&self, chip: AudioChip) -> f32 {
+        self.cpu.audio_chip_gain(chip)
+    }
+
+    /// Sets the gain applied to a cartridge sound chip, from `0.0` (muted) to `2.0`, to balance
+    /// chips that are louder or quieter than the APU.
+    #[inline]
+    pub fn set_audio_chip_gain(&mut self, chip: AudioChip, gain: f32) {
+        self.cpu.set_audio_chip_gain(chip, gain);
+    }
+
+    /// Returns the sound chip on the loaded cartridge, if any.
+    #[inline]
+    pub fn audio_chip(&self) -> Option<AudioChip> {
+        self.cpu.mapper().audio_chip()
+    }
+
+    /// Set how APU channels are mixed together.
+    #[inline]
+    pub fn set_apu_mixing(&mut self, mixing: ApuMixing) {
+        self.cpu.set_apu_mixing(mixing);
+    }
+
+    /// Enable/Disable reducing pops when games write the DMC output level directly.
+    #[inline]
+    pub fn set_dmc_pop_reduction(&mut self, enabled: bool) {
+        self.cpu.set_dmc_pop_reduction(enabled);
+    }
+
+    /// Is control deck running.
+    #[inline]
+    #[must_use]
+    pub const fn is_running(&self) -> bool {
+        self.running
+    }
+}
+
+impl Clock for ControlDeck {
+    /// Steps the control deck a single clock cycle.
+    fn clock(&mut self) -> usize {
+        self.cpu.clock()
+    }
+}
+
+impl Regional for ControlDeck {
+    /// Get the NES format for the emulation.
+    #[inline]
+    fn region(&self) -> NesRegion {
+        self.region
+    }
+
+    /// Set the NES format for the emulation.
+    #[inline]
+    fn set_region(&mut self, region: NesRegion) {
+        self.region = region;
+        self.cpu.set_region(region);
+    }
+}
+
+impl Reset for ControlDeck {
+    /// Resets the console.
+    fn reset(&mut self, kind: Kind) {
+        self.cpu.reset(kind);
+        self.video.invalidate();
+        self.running = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::tests::{load_control_deck, ROOT_DIR};
+    use std::path::PathBuf;
+
+    fn load_deck() -> ControlDeck {
+        load_control_deck(PathBuf::from(ROOT_DIR).join("test_roms/apu/noise.nes"))
+    }
+
+    /// Collects the samples from one clock, checking there's one for every CPU cycle.
+    fn take_samples(
+        deck: &mut ControlDeck,
+        flow: NesResult<ControlFlow<usize, usize>>,
+    ) -> Vec<f32> {
+        let (ControlFlow::Break(cycles) | ControlFlow::Continue(cycles)) =
+            flow.expect("valid clock");
+        let samples = deck.audio_samples().to_vec();
+        assert_eq!(samples.len(), cycles);
+        deck.clear_audio_samples();
+        samples
+    }
+
+    /// Runs `deck` for about a quarter second in one-frame steps, returning the samples.
+    fn step_frames(deck: &mut ControlDeck) -> Vec<f32> {
+        (0..15)
+            .flat_map(|_| {
+                let flow = deck.clock_frame();
+                take_samples(deck, flow)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn samples_follow_cpu_cycles() {
+        let mut stepped = load_deck();
+        let expected = step_frames(&mut stepped);
+
+        // Uneven slices of time, like a varying display rate or emulation speed
+        let mut timed = load_deck();
+        let mut samples = vec![];
+        for seconds in [0.001, 0.0137, 0.02, 0.0005].into_iter().cycle() {
+            if samples.len() >= expected.len() {
+                break;
+            }
+            let flow = timed.clock_seconds(seconds);
+            samples.extend(take_samples(&mut timed, flow));
+        }
+        assert_eq!(samples[..expected.len()], expected[..]);
+    }
+
+    #[test]
+    fn pausing_and_frame_stepping_leave_no_gaps() {
+        let mut running = load_deck();
+        let mut expected = step_frames(&mut running);
+        expected.extend(step_frames(&mut running));
+
+        let mut paused = load_deck();
+        let mut samples = vec![];
+        for _ in 0..10 {
+            let flow = paused.clock_seconds(1.0 / 60.0);
+            samples.extend(take_samples(&mut paused, flow));
+        }
+        // No time passes while paused, then a few frames are stepped through before resuming
+        for _ in 0..3 {
+            let flow = paused.clock_seconds(0.0);
+            assert!(take_samples(&mut paused, flow).is_empty());
+        }
+        for _ in 0..3 {
+            let flow = paused.clock_frame();
+            samples.extend(take_samples(&mut paused, flow));
+        }
+        while samples.len() < expected.len() {
+            let flow = paused.clock_seconds(1.0 / 60.0);
+            samples.extend(take_samples(&mut paused, flow));
+        }
+        assert_eq!(samples[..expected.len()], expected[..]);
+    }
+}
@@ -3,14 +3,16 @@
 //! <http://wiki.nesdev.com/w/index.php/CPU>
 
 use crate::{
-    apu::{Apu, Channel},
+    apu::{Apu, ApuMixing, Channel},
     bus::CpuBus,
     cart::Cart,
     common::{Clock, Kind, NesRegion, Regional, Reset},
+    genie::GenieCode,
     input::{FourPlayer, Joypad, Slot, Zapper},
-    mapper::Mapper,
-    mem::{Access, Mem},
+    mapper::{AudioChip, Mapper},
+    mem::{Access, Mem, RamState},
     ppu::Ppu,
+    trace::TraceTiming,
     NesResult,
 };
 use bitflags::bitflags;
@@ -25,10 +27,15 @@ use instr::{
         TXA, TXS, TYA, XAA, XXX,
     },
 };
+use interrupt::{InterruptKind, InterruptLatency, InterruptRecorder};
 use serde::{Deserialize, Serialize};
-use std::fmt::{self, Write};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Write},
+};
 
 pub mod instr;
+pub mod interrupt;
 
 bitflags! {
     #[derive(Default, Serialize, Deserialize)]
@@ -68,6 +75,47 @@ bitflags! {
     }
 }
 
+/// How strictly to treat unofficial opcodes, which some games and test ROMs rely on but usually
+/// indicate a bug in homebrew code.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub enum UnofficialOpcodes {
+    /// Execute unofficial opcodes like the hardware does.
+    #[default]
+    Allow,
+    /// Execute unofficial opcodes, logging a warning the first time each one is executed.
+    Log,
+    /// Execute unofficial opcodes, then break emulation so they can be inspected.
+    Trap,
+}
+
+impl UnofficialOpcodes {
+    #[must_use]
+    pub const fn as_slice() -> &'static [Self] {
+        &[Self::Allow, Self::Log, Self::Trap]
+    }
+}
+
+impl AsRef<str> for UnofficialOpcodes {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Allow => "Allow",
+            Self::Log => "Log",
+            Self::Trap => "Trap",
+        }
+    }
+}
+
+impl From<usize> for UnofficialOpcodes {
+    fn from(value: usize) -> Self {
+        match value {
+            1 => Self::Log,
+            2 => Self::Trap,
+            _ => Self::Allow,
+        }
+    }
+}
+
 /// Every cycle is either a read or a write.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 enum Cycle {
@@ -108,7 +156,23 @@ pub struct Cpu {
     halt: bool,
     dummy_read: bool,
     cycle_accurate: bool,
+    // Kept out of save states so older states still load, and set from the current settings when
+    // loading one
+    #[serde(skip, default = "Cpu::default_ppu_alignment")]
+    ppu_alignment: u8,
+    #[serde(skip)]
+    unofficial_opcodes: UnofficialOpcodes,
+    #[serde(skip)]
+    logged_opcodes: HashSet<u8>,
+    #[serde(skip)]
+    trapped_opcode: Option<(u16, Instr)>,
     disasm: String,
+    #[serde(skip)]
+    watch_writes: bool, // Record written addresses for write breakpoints
+    #[serde(skip)]
+    writes: Vec<u16>,
+    #[serde(skip)]
+    interrupt_recorder: Option<InterruptRecorder>,
 }
 
 impl Cpu {
@@ -121,8 +185,8 @@ impl Cpu {
     const PAL_CPU_CLOCK_RATE: f32 = Self::PAL_MASTER_CLOCK_RATE / 16.0;
     const DENDY_CPU_CLOCK_RATE: f32 = Self::PAL_MASTER_CLOCK_RATE / 15.0;
 
-    // Represents CPU/PPU alignment and would range from 0..=ppu_divider-1, if random alignment was emulated
-    const PPU_OFFSET: u64 = 1;
+    /// Master clocks the PPU runs behind the CPU, from 0 to the PPU clock divider - 1.
+    pub const DEFAULT_PPU_ALIGNMENT: u8 = 1;
 
     const NMI_VECTOR: u16 = 0xFFFA; // NMI Vector address
     const IRQ_VECTOR: u16 = 0xFFFE; // IRQ Vector address
@@ -161,7 +225,14 @@ impl Cpu {
             halt: false,
             dummy_read: false,
             cycle_accurate: true,
+            ppu_alignment: Self::DEFAULT_PPU_ALIGNMENT,
+            unofficial_opcodes: UnofficialOpcodes::default(),
+            logged_opcodes: HashSet::new(),
+            trapped_opcode: None,
             disasm: String::with_capacity(100),
+            watch_writes: false,
+            writes: Vec::new(),
+            interrupt_recorder: None,
         };
         cpu.set_region(cpu.region);
         cpu
@@ -260,6 +331,60 @@ impl Cpu {
         &self.disasm
     }
 
+    /// Enables or disables recording of written addresses, used to check write breakpoints.
+    #[inline]
+    pub fn set_watch_writes(&mut self, enabled: bool) {
+        self.watch_writes = enabled;
+        if !enabled {
+            self.writes.clear();
+        }
+    }
+
+    /// Addresses written since the last call to `clear_writes`. Only recorded while
+    /// `set_watch_writes` is enabled.
+    #[inline]
+    #[must_use]
+    pub fn writes(&self) -> &[u16] {
+        &self.writes
+    }
+
+    #[inline]
+    pub fn clear_writes(&mut self) {
+        self.writes.clear();
+    }
+
+    /// Enables or disables recording interrupt latencies for each frame.
+    pub fn set_interrupt_latency_enabled(&mut self, enabled: bool) {
+        if enabled != self.interrupt_recorder.is_some() {
+            self.interrupt_recorder = enabled.then(InterruptRecorder::default);
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn interrupt_latency_enabled(&self) -> bool {
+        self.interrupt_recorder.is_some()
+    }
+
+    /// Returns the latency of each interrupt handled during the last completed frame. Empty
+    /// unless enabled with `set_interrupt_latency_enabled`.
+    #[inline]
+    pub fn interrupt_latencies(&self) -> &[InterruptLatency] {
+        self.interrupt_recorder
+            .as_ref()
+            .map_or(&[], InterruptRecorder::latencies)
+    }
+
+    fn timing(&self) -> TraceTiming {
+        let ppu = self.bus.ppu();
+        TraceTiming {
+            frame: ppu.frame_number(),
+            scanline: ppu.scanline(),
+            dot: ppu.cycle(),
+            cycle: self.cycle as u64,
+        }
+    }
+
     #[inline]
     pub const fn ppu(&self) -> &Ppu {
         self.bus.ppu()
@@ -305,6 +430,18 @@ impl Cpu {
         self.bus.connect_zapper(enabled);
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn lagged(&self) -> bool {
+        self.bus.lagged()
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn lag_frames(&self) -> u32 {
+        self.bus.lag_frames()
+    }
+
     #[inline]
     pub const fn zapper(&self) -> &Zapper {
         self.bus.zapper()
@@ -326,6 +463,22 @@ impl Cpu {
         self.bus.cart_battery_backed()
     }
 
+    #[inline]
+    pub fn set_cart_battery_backed(&mut self, battery_backed: bool) {
+        self.bus.set_cart_battery_backed(battery_backed);
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn prg_ram_write_protected(&self) -> bool {
+        self.bus.prg_ram_write_protected()
+    }
+
+    #[inline]
+    pub fn set_prg_ram_write_protected(&mut self, protect: bool) {
+        self.bus.set_prg_ram_write_protected(protect);
+    }
+
     #[inline]
     #[must_use]
     pub fn sram(&self) -> &[u8] {
@@ -337,6 +490,24 @@ impl Cpu {
         self.bus.load_sram(sram);
     }
 
+    #[inline]
+    #[must_use]
+    pub fn sram_mut(&mut self) -> &mut [u8] {
+        self.bus.sram_mut()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn prg_rom_offset(&self, addr: u16) -> Option<usize> {
+        self.bus.prg_rom_offset(addr)
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn prg_rom_mut(&mut self) -> &mut [u8] {
+        self.bus.prg_rom_mut()
+    }
+
     #[inline]
     #[must_use]
     pub fn wram(&self) -> &[u8] {
@@ -358,6 +529,17 @@ impl Cpu {
         self.bus.remove_genie_code(genie_code);
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn genie_codes(&self) -> &HashMap<u16, GenieCode> {
+        self.bus.genie_codes()
+    }
+
+    #[inline]
+    pub fn set_genie_codes(&mut self, genie_codes: HashMap<u16, GenieCode>) {
+        self.bus.set_genie_codes(genie_codes);
+    }
+
     #[inline]
     #[must_use]
     pub const fn ppu_cycle(&self) -> u32 {
@@ -393,6 +575,60 @@ impl Cpu {
         self.bus.toggle_audio_channel(channel);
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn audio_channel_volume(&self, channel: Channel) -> f32 {
+        self.bus.audio_channel_volume(channel)
+    }
+
+    #[inline]
+    pub fn set_audio_channel_volume(&mut self, channel: Channel, volume: f32) {
+        self.bus.set_audio_channel_volume(channel, volume);
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn expansion_volume(&self) -> f32 {
+        self.bus.expansion_volume()
+    }
+
+    #[inline]
+    pub fn set_expansion_volume(&mut self, volume: f32) {
+        self.bus.set_expansion_volume(volume);
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn audio_chip_gain(&self, chip: AudioChip) -> f32 {
+        self.bus.audio_chip_gain(chip)
+    }
+
+    #[inline]
+    pub fn set_audio_chip_gain(&mut self, chip: AudioChip, gain: f32) {
+        self.bus.set_audio_chip_gain(chip, gain);
+    }
+
+    #[inline]
+    pub const fn apu_mixing(&self) -> ApuMixing {
+        self.bus.apu_mixing()
+    }
+
+    #[inline]
+    pub fn set_apu_mixing(&mut self, mixing: ApuMixing) {
+        self.bus.set_apu_mixing(mixing);
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn dmc_pop_reduction(&self) -> bool {
+        self.bus.dmc_pop_reduction()
+    }
+
+    #[inline]
+    pub fn set_dmc_pop_reduction(&mut self, enabled: bool) {
+        self.bus.set_dmc_pop_reduction(enabled);
+    }
+
     #[inline]
     #[must_use]
     pub fn audio_samples(&self) -> &[f32] {
@@ -404,6 +640,23 @@ impl Cpu {
         self.bus.clear_audio_samples();
     }
 
+    #[inline]
+    pub fn set_channel_samples_enabled(&mut self, enabled: bool) {
+        self.bus.set_channel_samples_enabled(enabled);
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn channel_samples_enabled(&self) -> bool {
+        self.bus.channel_samples_enabled()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn channel_samples(&self) -> &[f32] {
+        self.bus.channel_samples()
+    }
+
     #[inline]
     pub const fn four_player(&self) -> FourPlayer {
         self.bus.four_player()
@@ -414,11 +667,103 @@ impl Cpu {
         self.bus.set_four_player(four_player);
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn cycle_accurate(&self) -> bool {
+        self.cycle_accurate
+    }
+
     #[inline]
     pub fn set_cycle_accurate(&mut self, enabled: bool) {
         self.cycle_accurate = enabled;
     }
 
+    const fn default_ppu_alignment() -> u8 {
+        Self::DEFAULT_PPU_ALIGNMENT
+    }
+
+    /// Highest CPU/PPU alignment for a region, one less than the PPU clock divider.
+    #[inline]
+    #[must_use]
+    pub const fn max_ppu_alignment(region: NesRegion) -> u8 {
+        match region {
+            NesRegion::Ntsc => 3,
+            NesRegion::Pal | NesRegion::Dendy => 4,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn ppu_alignment(&self) -> u8 {
+        self.ppu_alignment
+    }
+
+    /// Set how many master clocks the PPU runs behind the CPU, which real consoles pick at random
+    /// on power up. Clamped to the alignments possible for the region.
+    #[inline]
+    pub fn set_ppu_alignment(&mut self, alignment: u8) {
+        self.ppu_alignment = alignment.min(Self::max_ppu_alignment(self.region));
+    }
+
+    /// Master clock the PPU runs to, behind the CPU by the PPU alignment.
+    #[inline]
+    const fn ppu_clock(&self) -> u64 {
+        self.master_clock.saturating_sub(self.ppu_alignment as u64)
+    }
+
+    #[inline]
+    pub const fn ram_state(&self) -> RamState {
+        self.bus.ram_state()
+    }
+
+    /// Set the RAM contents used on the next power cycle.
+    #[inline]
+    pub fn set_ram_state(&mut self, ram_state: RamState) {
+        self.bus.set_ram_state(ram_state);
+    }
+
+    #[inline]
+    pub const fn unofficial_opcodes(&self) -> UnofficialOpcodes {
+        self.unofficial_opcodes
+    }
+
+    #[inline]
+    pub fn set_unofficial_opcodes(&mut self, unofficial_opcodes: UnofficialOpcodes) {
+        self.unofficial_opcodes = unofficial_opcodes;
+        self.logged_opcodes.clear();
+    }
+
+    /// The address and instruction of an unofficial opcode that was just executed with
+    /// [`UnofficialOpcodes::Trap`] set. Cleared when the next instruction starts.
+    #[inline]
+    #[must_use]
+    pub const fn trapped_opcode(&self) -> Option<(u16, Instr)> {
+        self.trapped_opcode
+    }
+
+    fn check_unofficial_opcode(&mut self, pc: u16) {
+        match self.unofficial_opcodes {
+            UnofficialOpcodes::Allow => (),
+            UnofficialOpcodes::Log => {
+                if self.logged_opcodes.insert(self.instr.opcode()) {
+                    log::warn!(
+                        "Unofficial opcode ${:02X} {:?} executed at ${pc:04X}",
+                        self.instr.opcode(),
+                        self.instr,
+                    );
+                }
+            }
+            UnofficialOpcodes::Trap => {
+                log::warn!(
+                    "Trapped unofficial opcode ${:02X} {:?} at ${pc:04X}",
+                    self.instr.opcode(),
+                    self.instr,
+                );
+                self.trapped_opcode = Some((pc, self.instr));
+            }
+        }
+    }
+
     // <http://wiki.nesdev.com/w/index.php/IRQ>
     //  #  address R/W description
     // --- ------- --- -----------------------------------------------
@@ -449,6 +794,7 @@ impl Cpu {
 
             self.set_pc(self.read_u16(Self::NMI_VECTOR));
             log::trace!("NMI: {}", self.cycle);
+            self.record_interrupt_handled(InterruptKind::Nmi);
         } else {
             self.push(status);
             self.status.set(Status::I, true);
@@ -456,6 +802,16 @@ impl Cpu {
 
             self.set_pc(self.read_u16(Self::IRQ_VECTOR));
             log::trace!("IRQ: {}", self.cycle);
+            self.record_interrupt_handled(InterruptKind::Irq);
+        }
+    }
+
+    fn record_interrupt_handled(&mut self, kind: InterruptKind) {
+        if self.interrupt_recorder.is_some() {
+            let timing = self.timing();
+            if let Some(ref mut recorder) = self.interrupt_recorder {
+                recorder.handled(kind, timing);
+            }
         }
     }
 
@@ -472,13 +828,21 @@ impl Cpu {
         // signal if the input goes from being high during one cycle to being low during the
         // next.
         let nmi_pending = self.bus.nmi_pending();
-        if !self.prev_nmi_pending && nmi_pending {
+        let nmi_edge = !self.prev_nmi_pending && nmi_pending;
+        if nmi_edge {
             self.nmi = true;
             log::trace!("NMI Edge Detected: {}", self.cycle);
         }
         self.prev_nmi_pending = nmi_pending;
 
         self.irq = self.bus.irqs_pending();
+        if self.interrupt_recorder.is_some() {
+            let timing = self.timing();
+            let irq_line = !self.irq.is_empty();
+            if let Some(ref mut recorder) = self.interrupt_recorder {
+                recorder.poll(timing, nmi_edge, irq_line);
+            }
+        }
 
         // The IRQ status at the end of the second-to-last cycle is what matters,
         // so keep the second-to-last status.
@@ -504,7 +868,7 @@ impl Cpu {
         self.cycle = self.cycle.wrapping_add(1);
 
         if self.cycle_accurate {
-            self.bus.clock_to(self.master_clock - Self::PPU_OFFSET);
+            self.bus.clock_to(self.ppu_clock());
             self.bus.clock();
         }
     }
@@ -517,7 +881,7 @@ impl Cpu {
         };
 
         if self.cycle_accurate {
-            self.bus.clock_to(self.master_clock - Self::PPU_OFFSET);
+            self.bus.clock_to(self.ppu_clock());
         }
 
         self.handle_interrupts();
@@ -534,31 +898,63 @@ impl Cpu {
     }
 
     fn handle_dma(&mut self, addr: u16) {
+        // The NTSC 2A03 repeats the read the CPU was halted on, so a DMC DMA during a controller
+        // read clocks the controller an extra time and drops a bit. Consecutive reads of the same
+        // register only clock once, so only the halt cycle matters. The PAL 2A07 fixed this and
+        // its halt and alignment reads have no side effects. The fast path leaves both out so
+        // input is never dropped.
+        // <https://www.nesdev.org/wiki/DMA#Register_conflicts>
+        let input_read = addr == 0x4016 || addr == 0x4017;
+        let repeat_reads = !self.cycle_accurate || self.region == NesRegion::Ntsc;
+        let input_glitch = self.cycle_accurate && self.region == NesRegion::Ntsc;
+        // A CPU halted while reading $4000-$401F leaves the internal registers selected, so DMA
+        // reads hit them too
+        let internal_reads = self.cycle_accurate && addr & 0xFFE0 == 0x4000;
+        // A DMC DMA reading the same register keeps /OE active, so the controller only sees the
+        // DMC read
+        let dmc_same_register = internal_reads
+            && input_read
+            && self.dmc_dma
+            && self.bus.dmc_dma_addr() & 0x1F == addr & 0x1F;
+
         self.start_cycle(Cycle::Read);
-        self.bus.read(addr, Access::Dummy);
+        let halt_read = if input_read {
+            input_glitch && !dmc_same_register
+        } else {
+            repeat_reads
+        };
+        if halt_read {
+            self.bus.read(addr, Access::Dummy);
+        }
         self.end_cycle(Cycle::Read);
         self.halt = false;
 
-        let skip_dummy_reads = addr == 0x4016 || addr == 0x4017;
+        let skip_dummy_reads = input_read || !repeat_reads;
 
         let oam_base_addr = self.bus.oam_dma_addr();
         let mut oam_offset = 0;
         let mut oam_dma_count = 0;
         let mut read_val = 0;
+        let mut prev_read_addr = addr;
 
         while self.bus.oam_dma() || self.dmc_dma {
             if self.cycle & 0x01 == 0x00 {
                 if self.dmc_dma && !self.halt && !self.dummy_read {
                     // DMC DMA ready to read a byte (halt and dummy read done before)
                     self.process_dma_cycle();
-                    read_val = self.bus.read(self.bus.dmc_dma_addr(), Access::Dummy);
+                    read_val =
+                        self.dma_read(self.bus.dmc_dma_addr(), &mut prev_read_addr, internal_reads);
                     self.end_cycle(Cycle::Read);
                     self.bus.load_dmc_buffer(read_val);
                     self.dmc_dma = false;
                 } else if self.bus.oam_dma() {
                     // DMC DMA not running or ready, run OAM DMA
                     self.process_dma_cycle();
-                    read_val = self.bus.read(oam_base_addr + oam_offset, Access::Dummy);
+                    read_val = self.dma_read(
+                        oam_base_addr + oam_offset,
+                        &mut prev_read_addr,
+                        internal_reads,
+                    );
                     self.end_cycle(Cycle::Read);
                     oam_offset += 1;
                     oam_dma_count += 1;
@@ -592,6 +988,57 @@ impl Cpu {
         }
     }
 
+    /// Reads a byte for DMC or OAM DMA. With the internal registers still selected from a halted
+    /// $4000-$401F read, the DMA also reads $4015-$4017, which can clear the frame IRQ flag,
+    /// clock the controllers or conflict with the byte on the external bus.
+    fn dma_read(&mut self, addr: u16, prev_read_addr: &mut u16, internal_reads: bool) -> u8 {
+        // Controller bits that aren't driven and keep the external bus value
+        const INPUT_OPEN_BUS: u8 = 0xE0;
+
+        if !self.cycle_accurate {
+            return self.bus.read(addr, Access::Dummy);
+        }
+        if !internal_reads {
+            *prev_read_addr = addr;
+            return if addr & 0xFFE0 == 0x4000 {
+                // Nothing drives $4000-$401F on the external bus
+                self.bus.open_bus()
+            } else {
+                self.bus.read(addr, Access::Dummy)
+            };
+        }
+
+        let internal_addr = 0x4000 | (addr & 0x1F);
+        let same_addr = internal_addr == addr;
+        let val = match internal_addr {
+            0x4015 => {
+                let val = self.bus.read(internal_addr, Access::Dummy);
+                if !same_addr {
+                    self.bus.read(addr, Access::Dummy);
+                }
+                val
+            }
+            0x4016 | 0x4017 => {
+                // Reading the same register again doesn't clock the controller, and the PAL
+                // 2A07 returns the previous read
+                let val = if self.region == NesRegion::Pal || *prev_read_addr == internal_addr {
+                    self.bus.open_bus()
+                } else {
+                    self.bus.read(internal_addr, Access::Dummy)
+                };
+                if same_addr {
+                    val
+                } else {
+                    let external = self.bus.read(addr, Access::Dummy);
+                    (external & INPUT_OPEN_BUS) | (val & external & !INPUT_OPEN_BUS)
+                }
+            }
+            _ => self.bus.read(addr, Access::Dummy),
+        };
+        *prev_read_addr = internal_addr;
+        val
+    }
+
     // Status Register functions
 
     // Convenience method to set both Z and N
@@ -745,6 +1192,12 @@ impl Cpu {
         u16::from_le_bytes([lo, hi])
     }
 
+    // Write a byte to the bus without clocking the CPU, for debugging tools.
+    #[inline]
+    pub fn poke(&mut self, addr: u16, val: u8) {
+        self.bus.write(addr, val, Access::Write);
+    }
+
     // Like read_word, but for Zero Page which means it'll wrap around at 0xFF
     #[must_use]
     #[inline]
@@ -925,15 +1378,21 @@ impl Cpu {
     where
         F: FnMut(&mut Cpu),
     {
+        let _span = tracing::trace_span!("cpu_step").entered();
         let start_cycle = self.cycle;
+        self.trapped_opcode = None;
 
         if log::log_enabled!(log::Level::Trace) {
             self.trace_instr();
         }
         inspect(self);
 
+        let pc = self.pc;
         let opcode = self.read_instr(); // Cycle 1 of instruction
         self.instr = Cpu::INSTRUCTIONS[opcode as usize];
+        if self.instr.is_unofficial() {
+            self.check_unofficial_opcode(pc);
+        }
 
         match self.instr.addr_mode() {
             IMM => self.imm(),
@@ -1036,7 +1495,7 @@ impl Cpu {
         }
 
         if !self.cycle_accurate {
-            self.bus.clock_to(self.master_clock - Self::PPU_OFFSET);
+            self.bus.clock_to(self.ppu_clock());
             let cycles = self.cycle - start_cycle;
             for _ in 0..cycles {
                 self.bus.clock();
@@ -1072,6 +1531,9 @@ impl Mem for Cpu {
     }
 
     fn write(&mut self, addr: u16, val: u8, access: Access) {
+        if self.watch_writes {
+            self.writes.push(addr);
+        }
         self.start_cycle(Cycle::Write);
         self.bus.write(addr, val, access);
         self.end_cycle(Cycle::Write);
@@ -1094,6 +1556,7 @@ impl Regional for Cpu {
         self.clock_divider = clock_divider;
         self.start_clocks = start_clocks;
         self.end_clocks = end_clocks;
+        self.set_ppu_alignment(self.ppu_alignment);
         self.bus.set_region(region);
     }
 }
@@ -1174,6 +1637,8 @@ impl fmt::Debug for Cpu {
             .field("last_run_irq", &self.prev_run_irq)
             .field("halt", &self.halt)
             .field("dummy_read", &self.dummy_read)
+            .field("unofficial_opcodes", &self.unofficial_opcodes)
+            .field("trapped_opcode", &self.trapped_opcode)
             .finish()
     }
 }
@@ -1217,6 +1682,106 @@ mod tests {
         }
     }
 
+    #[test]
+    fn unofficial_opcodes() {
+        use super::*;
+        let mut cpu = Cpu::new(CpuBus::default());
+        cpu.load_cart(Cart::empty());
+        cpu.reset(Kind::Hard);
+
+        // LAX #$5A
+        cpu.bus.write(0x0000, 0xAB, Access::Write);
+        cpu.bus.write(0x0001, 0x5A, Access::Write);
+        cpu.set_pc(0x0000);
+        cpu.set_acc(0xFF);
+        cpu.clock();
+        assert_eq!((cpu.a(), cpu.x()), (0x5A, 0x5A), "lax");
+        assert_eq!(cpu.trapped_opcode(), None, "allowed");
+
+        // LAS $0010,Y
+        cpu.bus.write(0x0000, 0xBB, Access::Write);
+        cpu.bus.write(0x0001, 0x10, Access::Write);
+        cpu.bus.write(0x0002, 0x00, Access::Write);
+        cpu.bus.write(0x0010, 0xF3, Access::Write);
+        cpu.set_pc(0x0000);
+        cpu.set_y(0x00);
+        cpu.set_sp(0x3C);
+        cpu.set_unofficial_opcodes(UnofficialOpcodes::Trap);
+        cpu.clock();
+        assert_eq!((cpu.a(), cpu.x(), cpu.sp()), (0x30, 0x30, 0x30), "las");
+        assert_eq!(
+            cpu.trapped_opcode().map(|(pc, instr)| (pc, instr.opcode())),
+            Some((0x0000, 0xBB)),
+            "trapped"
+        );
+
+        // NOP
+        cpu.bus.write(0x0003, 0xEA, Access::Write);
+        cpu.clock();
+        assert_eq!(cpu.trapped_opcode(), None, "official opcodes don't trap");
+    }
+
+    fn start_dmc_dma(cpu: &mut super::Cpu) {
+        cpu.dmc_dma = true;
+        cpu.halt = true;
+        cpu.dummy_read = true;
+    }
+
+    #[test]
+    fn dma_stall_cycles() {
+        use super::*;
+        let stall = |odd_cycle: bool, oam_dma: bool| {
+            let mut cpu = Cpu::new(CpuBus::default());
+            cpu.load_cart(Cart::empty());
+            cpu.reset(Kind::Hard);
+            if (cpu.cycle & 0x01 == 0x01) != odd_cycle {
+                cpu.read(0x0000, Access::Read);
+            }
+            if oam_dma {
+                cpu.bus.write(0x4014, 0x02, Access::Write);
+            } else {
+                start_dmc_dma(&mut cpu);
+            }
+            let start_cycle = cpu.cycle;
+            cpu.read(0x0000, Access::Read);
+            cpu.cycle - start_cycle - 1
+        };
+
+        // Halt, dummy read, alignment if needed, then the DMC read on a get cycle
+        assert_eq!(stall(false, false), 3, "dmc dma from even cycle");
+        assert_eq!(stall(true, false), 4, "dmc dma from odd cycle");
+        // Halt, alignment if needed, then 256 read/write pairs
+        assert_eq!(stall(true, true), 513, "oam dma from odd cycle");
+        assert_eq!(stall(false, true), 514, "oam dma from even cycle");
+    }
+
+    #[test]
+    fn controller_read_during_dmc_dma() {
+        use super::*;
+        use crate::input::JoypadBtnState;
+
+        // Reads the first two bits of controller one with A held, halted by a DMC DMA
+        let read_bits = |region: NesRegion, cycle_accurate: bool| {
+            let mut cpu = Cpu::new(CpuBus::default());
+            cpu.load_cart(Cart::empty());
+            cpu.set_region(region);
+            cpu.set_cycle_accurate(cycle_accurate);
+            cpu.reset(Kind::Hard);
+            cpu.joypad_mut(Slot::One)
+                .set_button(JoypadBtnState::A, true);
+            cpu.write(0x4016, 0x01, Access::Write);
+            cpu.write(0x4016, 0x00, Access::Write);
+            start_dmc_dma(&mut cpu);
+            let a = cpu.read(0x4016, Access::Read) & 0x01;
+            let b = cpu.read(0x4016, Access::Read) & 0x01;
+            (a, b)
+        };
+
+        assert_eq!(read_bits(NesRegion::Ntsc, true), (0, 0), "ntsc drops a bit");
+        assert_eq!(read_bits(NesRegion::Pal, true), (1, 0), "pal");
+        assert_eq!(read_bits(NesRegion::Ntsc, false), (1, 0), "fast path");
+    }
+
     test_roms!(
         "test_roms/cpu",
         branch_backward,
@@ -65,6 +65,18 @@ impl Instr {
     pub const fn cycles(&self) -> usize {
         self.3
     }
+    /// Whether this is an unofficial opcode, undocumented by MOS but still decoded by the 6502.
+    #[inline]
+    #[must_use]
+    pub const fn is_unofficial(&self) -> bool {
+        match self.op() {
+            XXX | ISB | DCP | AXS | LAS | LAX | AHX | SAX | XAA | SXA | RRA | TAS | SYA | ARR
+            | SRE | ALR | RLA | ANC | SLO | SKB | IGN => true,
+            NOP => self.opcode() != 0xEA, // 0xEA is the only official NOP
+            SBC => self.opcode() == 0xEB,
+            _ => false,
+        }
+    }
 }
 
 /// CPU Addressing Modes
@@ -1256,11 +1268,16 @@ impl Cpu {
         self.set_status(self.status);
         self.set_x((t & 0xFF) as u8);
     }
-    /// LAS: Shortcut for LDA then TSX
+    /// LAS/LAR: AND memory with SP, then store the result in A, X and SP
     #[inline]
     pub(super) fn las(&mut self) {
-        self.lda();
-        self.tsx();
+        self.fetch_data();
+        let val = self.fetched_data & self.sp();
+        self.set_acc(val);
+        self.set_x(val);
+        self.set_sp(val);
+        self.set_zn_status(val);
+        self.set_status(self.status);
     }
     /// LAX: Shortcut for LDA then TAX
     #[inline]
@@ -1268,17 +1285,10 @@ impl Cpu {
         self.lda();
         self.tax();
     }
-    /// AHX/SHA/AXA: AND X with A then AND with 7, then store in memory
+    /// AHX/SHA/AXA: AND A with X and the high byte of the target address + 1
     #[inline]
     pub(super) fn ahx(&mut self) {
-        let val = self.a()
-            & self.x()
-            & self
-                .fetched_data
-                .wrapping_sub(self.y())
-                .wrapping_shr(8)
-                .wrapping_add(1);
-        self.write_fetched(val);
+        self.store_unstable(self.a() & self.x());
     }
     /// SAX: AND A with X
     #[inline]
@@ -1303,19 +1313,28 @@ impl Cpu {
     /// SXA/SHX/XAS: AND X with the high byte of the target address + 1
     #[inline]
     pub(super) fn sxa(&mut self) {
-        let hi = (self.abs_addr >> 8) as u8;
-        let lo = (self.abs_addr & 0xFF) as u8;
-        let val = self.x() & hi.wrapping_add(1);
-        self.abs_addr = u16::from_le_bytes([lo, self.x & hi.wrapping_add(1)]);
-        self.write_fetched(val);
+        self.store_unstable(self.x());
     }
     /// SYA/SHY/SAY: AND Y with the high byte of the target address + 1
     #[inline]
     pub(super) fn sya(&mut self) {
-        let hi = (self.abs_addr >> 8) as u8;
-        let lo = (self.abs_addr & 0xFF) as u8;
-        let val = self.y() & hi.wrapping_add(1);
-        self.abs_addr = u16::from_le_bytes([lo, self.y() & hi.wrapping_add(1)]);
+        self.store_unstable(self.y());
+    }
+    /// Shared store for the unstable AHX, SHX, SHY and TAS instructions. The value is ANDed with
+    /// the high byte of the base address + 1 and, if indexing crossed a page, that value also
+    /// replaces the high byte of the target address.
+    #[inline]
+    fn store_unstable(&mut self, val: u8) {
+        let index = if self.instr.addr_mode() == ABX {
+            self.x()
+        } else {
+            self.y()
+        };
+        let base = self.abs_addr.wrapping_sub(index.into());
+        let val = val & ((base >> 8) as u8).wrapping_add(1);
+        if Self::pages_differ(base, self.abs_addr) {
+            self.abs_addr = u16::from_le_bytes([(self.abs_addr & 0xFF) as u8, val]);
+        }
         self.write_fetched(val);
     }
     /// RRA: Shortcut for ROR then ADC
@@ -1345,13 +1364,12 @@ impl Cpu {
         self.set_status(self.status);
         self.write_fetched(ret);
     }
-    /// TAS: Shortcut for STA then TXS
+    /// TAS/SHS/XAS: Store A AND X in SP, then store SP AND the high byte of the target address
+    /// + 1 in memory
     #[inline]
     pub(super) fn tas(&mut self) {
-        // STA
-        self.write(self.abs_addr, self.a(), Access::Write);
-        // TXS
-        self.set_sp(self.x());
+        self.set_sp(self.a() & self.x());
+        self.store_unstable(self.sp());
     }
     /// ARR: Shortcut for AND #imm then ROR, but sets flags differently
     /// C is bit 6 and V is bit 6 xor bit 5
@@ -1440,18 +1458,11 @@ impl Cpu {
 
 impl std::fmt::Debug for Instr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), std::fmt::Error> {
-        let mut op = self.op();
-        let unofficial = match self.op() {
-            XXX | ISB | DCP | AXS | LAS | LAX | AHX | SAX | XAA | SXA | RRA | TAS | SYA | ARR
-            | SRE | ALR | RLA | ANC | SLO => "*",
-            NOP if self.opcode() != 0xEA => "*", // 0xEA is the only official NOP
-            SKB | IGN => {
-                op = NOP;
-                "*"
-            }
-            SBC if self.opcode() == 0xEB => "*",
-            _ => "",
+        let op = match self.op() {
+            SKB | IGN => NOP,
+            op => op,
         };
+        let unofficial = if self.is_unofficial() { "*" } else { "" };
         write!(f, "{unofficial:1}{op:?}")
     }
 }
@@ -0,0 +1,141 @@
+//! Interrupt latency recording, used to tune timed IRQ raster effects.
+
+use crate::trace::TraceTiming;
+
+/// Which interrupt was handled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InterruptKind {
+    Irq,
+    Nmi,
+}
+
+impl AsRef<str> for InterruptKind {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Irq => "IRQ",
+            Self::Nmi => "NMI",
+        }
+    }
+}
+
+/// Time from an interrupt being asserted to the CPU entering its handler.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct InterruptLatency {
+    pub kind: InterruptKind,
+    /// When the IRQ line went low or the NMI edge was detected.
+    pub asserted: TraceTiming,
+    /// When the CPU jumped to the interrupt vector.
+    pub handled: TraceTiming,
+}
+
+impl InterruptLatency {
+    /// Latency in CPU cycles.
+    #[inline]
+    #[must_use]
+    pub const fn cycles(&self) -> u64 {
+        self.handled.cycle.saturating_sub(self.asserted.cycle)
+    }
+
+    /// Latency in PPU dots.
+    #[inline]
+    #[must_use]
+    pub const fn dots(&self) -> u32 {
+        let asserted = self.asserted.scanline * 341 + self.asserted.dot;
+        let handled = self.handled.scanline * 341 + self.handled.dot;
+        handled.saturating_sub(asserted)
+    }
+}
+
+/// Tracks pending interrupts and collects latencies for each frame.
+#[derive(Default, Debug, Clone)]
+#[must_use]
+pub(crate) struct InterruptRecorder {
+    frame: u32,
+    irq_asserted: Option<TraceTiming>,
+    nmi_asserted: Option<TraceTiming>,
+    latencies: Vec<InterruptLatency>,
+    frame_latencies: Vec<InterruptLatency>,
+}
+
+impl InterruptRecorder {
+    /// Updates interrupt line state. Called once per CPU cycle while polling interrupts.
+    pub(crate) fn poll(&mut self, timing: TraceTiming, nmi_edge: bool, irq_line: bool) {
+        if timing.frame != self.frame {
+            self.frame = timing.frame;
+            std::mem::swap(&mut self.latencies, &mut self.frame_latencies);
+            self.latencies.clear();
+        }
+        if nmi_edge && self.nmi_asserted.is_none() {
+            self.nmi_asserted = Some(timing);
+        }
+        if !irq_line {
+            // Acknowledged without being handled, e.g. while interrupts were disabled
+            self.irq_asserted = None;
+        } else if self.irq_asserted.is_none() {
+            self.irq_asserted = Some(timing);
+        }
+    }
+
+    /// Records entry into an interrupt handler.
+    pub(crate) fn handled(&mut self, kind: InterruptKind, timing: TraceTiming) {
+        let asserted = match kind {
+            InterruptKind::Irq => self.irq_asserted.take(),
+            InterruptKind::Nmi => self.nmi_asserted.take(),
+        };
+        if let Some(asserted) = asserted {
+            self.latencies.push(InterruptLatency {
+                kind,
+                asserted,
+                handled: timing,
+            });
+        }
+    }
+
+    /// Latencies recorded during the last completed frame.
+    #[inline]
+    pub(crate) fn latencies(&self) -> &[InterruptLatency] {
+        &self.frame_latencies
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn timing(frame: u32, scanline: u32, dot: u32, cycle: u64) -> TraceTiming {
+        TraceTiming {
+            frame,
+            scanline,
+            dot,
+            cycle,
+        }
+    }
+
+    #[test]
+    fn irq_latency() {
+        let mut recorder = InterruptRecorder::default();
+        recorder.poll(timing(1, 100, 260, 1000), false, false);
+        recorder.poll(timing(1, 100, 263, 1001), false, true);
+        recorder.poll(timing(1, 100, 266, 1002), false, true);
+        recorder.handled(InterruptKind::Irq, timing(1, 100, 287, 1008));
+        recorder.poll(timing(1, 100, 290, 1009), false, false);
+        assert!(recorder.latencies().is_empty(), "frame not yet complete");
+
+        recorder.poll(timing(2, 0, 0, 10_000), false, false);
+        let latency = recorder.latencies()[0];
+        assert_eq!(latency.kind, InterruptKind::Irq);
+        assert_eq!(latency.cycles(), 7);
+        assert_eq!(latency.dots(), 24);
+    }
+
+    #[test]
+    fn acknowledged_irq_is_not_recorded() {
+        let mut recorder = InterruptRecorder::default();
+        recorder.poll(timing(1, 10, 0, 100), false, true);
+        recorder.poll(timing(1, 10, 3, 101), false, false);
+        recorder.handled(InterruptKind::Irq, timing(1, 10, 30, 110));
+        recorder.poll(timing(2, 0, 0, 200), false, false);
+        assert!(recorder.latencies().is_empty());
+    }
+}
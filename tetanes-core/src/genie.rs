@@ -78,6 +78,19 @@ impl GenieCode {
         self.addr
     }
 
+    #[inline]
+    #[must_use]
+    pub const fn data(&self) -> u8 {
+        self.data
+    }
+
+    /// The value that must be read for the code to apply, for 8-letter codes.
+    #[inline]
+    #[must_use]
+    pub const fn compare(&self) -> Option<u8> {
+        self.compare
+    }
+
     #[inline]
     #[must_use]
     pub const fn read(&self, val: u8) -> u8 {
@@ -28,7 +28,7 @@ pub trait InputRegisters {
     fn write(&mut self, val: u8);
 }
 
-#[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[must_use]
 pub enum FourPlayer {
     #[default]
@@ -85,6 +85,15 @@ pub struct Input {
     zapper: Zapper,
     turbo_timer: u32,
     four_player: FourPlayer,
+    // Lag tracking was added after states without a version were written
+    #[serde(deserialize_with = "crate::legacy::added")]
+    polled: bool, // Whether the joypads were read since the last frame ended
+    #[serde(deserialize_with = "crate::legacy::added")]
+    lag_frame: u32,
+    #[serde(deserialize_with = "crate::legacy::added")]
+    lagged: bool,
+    #[serde(deserialize_with = "crate::legacy::added")]
+    lag_frames: u32,
 }
 
 impl Input {
@@ -99,6 +108,10 @@ impl Input {
             zapper: Zapper::new(),
             turbo_timer: 30,
             four_player: FourPlayer::default(),
+            polled: false,
+            lag_frame: 0,
+            lagged: false,
+            lag_frames: 0,
         }
     }
 
@@ -137,6 +150,34 @@ impl Input {
         self.four_player = four_player;
         self.reset(Kind::Hard);
     }
+
+    /// Checks whether `frame` has started, counting the last frame as a lag frame if the game
+    /// didn't read the joypads during it.
+    #[inline]
+    pub fn update_lag(&mut self, frame: u32) {
+        if frame != self.lag_frame {
+            self.lag_frame = frame;
+            self.lagged = !self.polled;
+            if self.lagged {
+                self.lag_frames = self.lag_frames.wrapping_add(1);
+            }
+            self.polled = false;
+        }
+    }
+
+    /// Whether the last frame was a lag frame.
+    #[inline]
+    #[must_use]
+    pub const fn lagged(&self) -> bool {
+        self.lagged
+    }
+
+    /// Number of lag frames since power on.
+    #[inline]
+    #[must_use]
+    pub const fn lag_frames(&self) -> u32 {
+        self.lag_frames
+    }
 }
 
 impl InputRegisters for Input {
@@ -144,6 +185,7 @@ impl InputRegisters for Input {
         // Read $4016/$4017 D0 8x for controller #1/#2.
         // Read $4016/$4017 D0 8x for controller #3/#4.
         // Read $4016/$4017 D0 8x for signature: 0b00010000/0b00100000
+        self.polled = true;
         let zapper = if slot == Slot::Two {
             self.zapper.read(ppu)
         } else {
@@ -245,6 +287,11 @@ impl Reset for Input {
             sig.reset(kind);
         }
         self.zapper.reset(kind);
+        if kind == Kind::Hard {
+            self.polled = false;
+            self.lagged = false;
+            self.lag_frames = 0;
+        }
     }
 }
 
@@ -272,6 +319,25 @@ pub enum JoypadBtn {
     Start,
 }
 
+impl JoypadBtn {
+    #[inline]
+    #[must_use]
+    pub const fn as_slice() -> &'static [Self] {
+        &[
+            Self::Up,
+            Self::Down,
+            Self::Left,
+            Self::Right,
+            Self::A,
+            Self::B,
+            Self::TurboA,
+            Self::TurboB,
+            Self::Select,
+            Self::Start,
+        ]
+    }
+}
+
 impl AsRef<str> for JoypadBtn {
     fn as_ref(&self) -> &str {
         match *self {
@@ -347,6 +413,11 @@ impl Joypad {
         self.buttons.contains(button)
     }
 
+    #[inline]
+    pub const fn buttons(&self) -> JoypadBtnState {
+        self.buttons
+    }
+
     #[inline]
     pub fn set_button(&mut self, button: JoypadBtnState, pressed: bool) {
         self.buttons.set(button, pressed);
@@ -514,8 +585,27 @@ impl Reset for Zapper {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
     use crate::test_roms;
 
+    #[test]
+    fn lag_frames() {
+        let mut input = Input::new();
+        let ppu = Ppu::default();
+        input.update_lag(1);
+        assert!(input.lagged());
+        let _ = input.read(Slot::One, &ppu);
+        input.update_lag(1);
+        assert_eq!(input.lag_frames(), 1);
+        input.update_lag(2);
+        assert!(!input.lagged());
+        input.update_lag(3);
+        assert!(input.lagged());
+        assert_eq!(input.lag_frames(), 2);
+        input.reset(Kind::Hard);
+        assert_eq!(input.lag_frames(), 0);
+    }
+
     test_roms!(
         "test_roms/input",
         #[ignore = "todo"]
@@ -0,0 +1,46 @@
+//! Reading save states written before their layout changed.
+//!
+//! Save states from before replays and states were versioned are plain `bincode`, which has no
+//! field names, so the fields added or changed since can't be recognized from the data alone.
+//! Deserializing inside [`decode`] reads the layout those versions wrote instead: fields added
+//! since are left at their defaults and fields that changed type are converted from what was
+//! saved. Outside of [`decode`], the current layout is read as usual.
+
+use serde::{Deserialize, Deserializer};
+use std::cell::Cell;
+
+thread_local! {
+    static DECODING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f`, deserializing save states in the layout written before versioning.
+pub fn decode<T>(f: impl FnOnce() -> T) -> T {
+    struct Restore(bool);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            DECODING.with(|decoding| decoding.set(self.0));
+        }
+    }
+
+    let _restore = Restore(DECODING.with(|decoding| decoding.replace(true)));
+    f()
+}
+
+/// Whether save states are being deserialized in the legacy layout.
+pub(crate) fn decoding() -> bool {
+    DECODING.with(Cell::get)
+}
+
+/// Deserializes a field missing from the legacy layout, which is left at its default.
+pub(crate) fn added<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    if decoding() {
+        Ok(T::default())
+    } else {
+        T::deserialize(deserializer)
+    }
+}
@@ -17,10 +17,21 @@ pub use m004_txrom::{Mmc3Revision, Txrom};
 pub use m005_exrom::Exrom;
 pub use m007_axrom::Axrom;
 pub use m009_pxrom::Pxrom;
+pub use m010_fxrom::Fxrom;
+pub use m019_namco163::Namco163;
+pub use m021_m022_m023_m025_vrc2_vrc4::{Vrc24, Vrc24Revision};
 pub use m024_m026_vrc6::Vrc6;
+pub use m028_action53::Action53;
+pub use m034_bnrom_nina001::{Bnrom, BnromRevision};
 pub use m066_gxrom::Gxrom;
+pub use m069_sunsoft_fme7::SunsoftFme7;
 pub use m071_bf909x::{Bf909Revision, Bf909x};
+pub use m085_vrc7::Vrc7;
+pub use m105_nes_event::NesEvent;
+pub use m225_bmc64in1::Bmc64in1;
+pub use m228_action52::Action52;
 
+pub mod chr_latch;
 pub mod m000_nrom;
 pub mod m001_sxrom;
 pub mod m002_uxrom;
@@ -29,9 +40,20 @@ pub mod m004_txrom;
 pub mod m005_exrom;
 pub mod m007_axrom;
 pub mod m009_pxrom;
+pub mod m010_fxrom;
+pub mod m019_namco163;
+pub mod m021_m022_m023_m025_vrc2_vrc4;
 pub mod m024_m026_vrc6;
+pub mod m028_action53;
+pub mod m034_bnrom_nina001;
 pub mod m066_gxrom;
+pub mod m069_sunsoft_fme7;
 pub mod m071_bf909x;
+pub mod m085_vrc7;
+pub mod m105_nes_event;
+pub mod m225_bmc64in1;
+pub mod m228_action52;
+pub mod opll;
 pub mod vrc_irq;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -59,6 +81,16 @@ pub enum Mapper {
     Vrc6,
     Gxrom,
     Bf909x,
+    Vrc7,
+    Namco163,
+    SunsoftFme7,
+    Vrc24,
+    Fxrom,
+    Bnrom,
+    Action53,
+    NesEvent,
+    Bmc64in1,
+    Action52,
 }
 
 impl Mapper {
@@ -121,18 +153,74 @@ pub trait Mapped {
         Mirroring::default()
     }
     fn set_mirroring(&mut self, _mirroring: Mirroring) {}
+    /// Sets the board DIP switches, for boards that have them.
+    fn set_dip_switches(&mut self, _dip_switches: u8) {}
     fn ppu_bus_read(&mut self, _addr: u16) {}
     fn ppu_bus_write(&mut self, _addr: u16, _val: u8) {}
     fn cpu_bus_read(&mut self, _addr: u16) {}
     fn cpu_bus_write(&mut self, _addr: u16, _val: u8) {}
 }
 
+/// Sound chips that cartridges can mix into the APU output.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub enum AudioChip {
+    Vrc6,
+    Vrc7,
+    Fds,
+    Mmc5,
+    Namco163,
+    Sunsoft5b,
+}
+
+impl AudioChip {
+    #[inline]
+    #[must_use]
+    pub const fn as_slice() -> &'static [Self] {
+        &[
+            Self::Vrc6,
+            Self::Vrc7,
+            Self::Fds,
+            Self::Mmc5,
+            Self::Namco163,
+            Self::Sunsoft5b,
+        ]
+    }
+}
+
+impl AsRef<str> for AudioChip {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Vrc6 => "VRC6",
+            Self::Vrc7 => "VRC7",
+            Self::Fds => "FDS",
+            Self::Mmc5 => "MMC5",
+            Self::Namco163 => "Namco 163",
+            Self::Sunsoft5b => "Sunsoft 5B",
+        }
+    }
+}
+
+/// Cartridge expansion audio, mixed alongside the APU output.
+#[enum_dispatch(Mapper)]
+pub trait ExpansionAudio {
+    /// The sound chip on the cartridge, if any.
+    fn audio_chip(&self) -> Option<AudioChip> {
+        None
+    }
+    /// Current output level of the sound chip.
+    fn output(&self) -> f32 {
+        0.0
+    }
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 #[must_use]
 pub struct Empty;
 
 impl MemMap for Empty {}
 impl Mapped for Empty {}
+impl ExpansionAudio for Empty {}
 impl Clock for Empty {}
 impl Regional for Empty {}
 impl Reset for Empty {}
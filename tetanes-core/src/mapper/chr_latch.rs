@@ -0,0 +1,136 @@
+//! `ChrLatch`
+//!
+//! CHR-ROM bank latches used by `MMC2` and `MMC4`, which switch banks when the PPU fetches
+//! specific tiles.
+//!
+//! <https://www.nesdev.org/wiki/MMC2#CHR_banking>
+
+use crate::{
+    common::{Kind, Reset},
+    legacy,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct ChrLatch {
+    // CHR-ROM $FD/0000 bank select ($B000-$BFFF)
+    // CHR-ROM $FE/0000 bank select ($C000-$CFFF)
+    // CHR-ROM $FD/1000 bank select ($D000-$DFFF)
+    // CHR-ROM $FE/1000 bank select ($E000-$EFFF)
+    // 7  bit  0
+    // ---- ----
+    // xxxC CCCC
+    //    | ||||
+    //    +-++++- Select 4K CHR-ROM bank for PPU $0000/$1000-$0FFF/$1FFF
+    //            used when latch 0/1 = $FD/$FE
+    banks: [u8; 4],
+    latch: [usize; 2],
+    /// `MMC4` triggers latch 0 on a range of addresses like latch 1, while `MMC2` only triggers
+    /// on $0FD8 and $0FE8.
+    ranged: bool,
+}
+
+impl ChrLatch {
+    pub const fn new(ranged: bool) -> Self {
+        Self {
+            banks: [0x00; 4],
+            latch: [0x00; 2],
+            ranged,
+        }
+    }
+
+    /// Sets the bank for one of the four bank select registers at $B000-$EFFF.
+    #[inline]
+    pub fn write_bank(&mut self, addr: u16, val: u8) {
+        self.banks[((addr - 0xB000) >> 12) as usize] = val & 0x1F;
+    }
+
+    /// Updates the latches after the PPU reads from the pattern tables. Returns whether a latch
+    /// changed.
+    pub fn ppu_read(&mut self, addr: u16) -> bool {
+        let triggered = match addr {
+            0x0FD8 | 0x0FE8 => true,
+            0x0FD9..=0x0FDF | 0x0FE9..=0x0FEF => self.ranged,
+            0x1FD8..=0x1FDF | 0x1FE8..=0x1FEF => true,
+            _ => false,
+        };
+        if triggered {
+            let addr = addr as usize;
+            let latch = ((addr >> 4) & 0xFF) - 0xFD;
+            let table = addr >> 12;
+            if self.latch[table] != latch {
+                self.latch[table] = latch;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The 4K CHR-ROM bank selected for pattern table 0 ($0000) or 1 ($1000).
+    #[inline]
+    #[must_use]
+    pub const fn bank(&self, table: usize) -> usize {
+        self.banks[2 * table + self.latch[table]] as usize
+    }
+
+    /// Deserializes the latches. `MMC2` was the only board with them in legacy save states,
+    /// which stored the latches and then the banks as fields of the mapper.
+    pub(crate) fn deserialize_legacy<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        if legacy::decoding() {
+            let (latch, banks) = <([usize; 2], [u8; 4])>::deserialize(deserializer)?;
+            Ok(Self {
+                banks,
+                latch,
+                ranged: false,
+            })
+        } else {
+            Self::deserialize(deserializer)
+        }
+    }
+}
+
+impl Reset for ChrLatch {
+    fn reset(&mut self, _kind: Kind) {
+        self.banks = [0x00; 4];
+        self.latch = [0x00; 2];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latch_switches_after_fetch() {
+        let mut latch = ChrLatch::new(false);
+        latch.write_bank(0xB000, 1);
+        latch.write_bank(0xC000, 2);
+        latch.write_bank(0xD000, 3);
+        latch.write_bank(0xE000, 4);
+        assert_eq!((latch.bank(0), latch.bank(1)), (1, 3));
+
+        assert!(latch.ppu_read(0x0FE8));
+        assert!(latch.ppu_read(0x1FEA));
+        assert_eq!((latch.bank(0), latch.bank(1)), (2, 4));
+
+        // MMC2 only triggers latch 0 on exact addresses
+        assert!(!latch.ppu_read(0x0FDA));
+        assert_eq!(latch.bank(0), 2);
+        assert!(latch.ppu_read(0x1FD8));
+        assert_eq!(latch.bank(1), 3);
+    }
+
+    #[test]
+    fn mmc4_latch_range() {
+        let mut latch = ChrLatch::new(true);
+        latch.write_bank(0xB000, 1);
+        latch.write_bank(0xC000, 2);
+        assert!(latch.ppu_read(0x0FEF));
+        assert_eq!(latch.bank(0), 2);
+        assert!(latch.ppu_read(0x0FDA));
+        assert_eq!(latch.bank(0), 1);
+    }
+}
@@ -5,7 +5,7 @@
 use crate::{
     cart::Cart,
     common::{Clock, Regional, Reset},
-    mapper::{Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mapper::{ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
     ppu::Mirroring,
 };
 use serde::{Deserialize, Serialize};
@@ -77,6 +77,8 @@ impl Mapped for Nrom {
     }
 }
 
+impl ExpansionAudio for Nrom {}
+
 impl Clock for Nrom {}
 impl Regional for Nrom {}
 impl Reset for Nrom {}
@@ -6,7 +6,7 @@
 use crate::{
     cart::Cart,
     common::{Clock, Kind, Regional, Reset},
-    mapper::{Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mapper::{ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
     mem::MemBanks,
     ppu::Mirroring,
 };
@@ -164,6 +164,8 @@ impl Mapped for Sxrom {
     }
 }
 
+impl ExpansionAudio for Sxrom {}
+
 impl MemMap for Sxrom {
     // PPU $0000..=$1FFF 4K CHR-ROM/RAM Bank Switchable
     // CPU $6000..=$7FFF 8K PRG-RAM Bank (optional)
@@ -5,7 +5,7 @@
 use crate::{
     cart::Cart,
     common::{Clock, Regional, Reset},
-    mapper::{Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mapper::{ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
     mem::MemBanks,
     ppu::Mirroring,
 };
@@ -73,6 +73,8 @@ impl Mapped for Uxrom {
     }
 }
 
+impl ExpansionAudio for Uxrom {}
+
 impl Clock for Uxrom {}
 impl Regional for Uxrom {}
 impl Reset for Uxrom {}
@@ -6,7 +6,7 @@
 use crate::{
     cart::Cart,
     common::{Clock, Regional, Reset},
-    mapper::{Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mapper::{ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
     mem::MemBanks,
     ppu::Mirroring,
 };
@@ -70,6 +70,8 @@ impl Mapped for Cnrom {
     }
 }
 
+impl ExpansionAudio for Cnrom {}
+
 impl Clock for Cnrom {}
 impl Regional for Cnrom {}
 impl Reset for Cnrom {}
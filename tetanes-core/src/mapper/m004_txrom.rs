@@ -6,7 +6,7 @@
 use crate::{
     cart::Cart,
     common::{Clock, Kind, Regional, Reset},
-    mapper::{Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mapper::{ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
     mem::MemBanks,
     ppu::Mirroring,
 };
@@ -204,6 +204,8 @@ impl Mapped for Txrom {
     }
 }
 
+impl ExpansionAudio for Txrom {}
+
 impl MemMap for Txrom {
     // PPU $0000..=$07FF (or $1000..=$17FF) 2K CHR-ROM/RAM Bank 1 Switchable --+
     // PPU $0800..=$0FFF (or $1800..=$1FFF) 2K CHR-ROM/RAM Bank 2 Switchable --|-+
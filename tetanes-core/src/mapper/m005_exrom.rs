@@ -9,11 +9,10 @@ use crate::{
         pulse::{OutputFreq, Pulse, PulseChannel},
         PULSE_TABLE,
     },
-    audio::Audio,
     cart::Cart,
     common::{Clock, Kind, NesRegion, Regional, Reset},
     cpu::Cpu,
-    mapper::{Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mapper::{AudioChip, ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
     mem::MemBanks,
     ppu::{bus::PpuAddr, Mirroring, Ppu},
 };
@@ -969,7 +968,11 @@ impl MemMap for Exrom {
     }
 }
 
-impl Audio for Exrom {
+impl ExpansionAudio for Exrom {
+    fn audio_chip(&self) -> Option<AudioChip> {
+        Some(AudioChip::Mmc5)
+    }
+
     #[must_use]
     fn output(&self) -> f32 {
         let pulse1 = self.pulse1.output();
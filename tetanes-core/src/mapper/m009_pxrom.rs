@@ -5,7 +5,10 @@
 use crate::{
     cart::Cart,
     common::{Clock, Kind, Regional, Reset},
-    mapper::{Mapped, MappedRead, MappedWrite, Mapper, MemMap, Mirroring},
+    mapper::{
+        chr_latch::ChrLatch, ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap,
+        Mirroring,
+    },
     mem::MemBanks,
 };
 use serde::{Deserialize, Serialize};
@@ -14,18 +17,8 @@ use serde::{Deserialize, Serialize};
 #[must_use]
 pub struct Pxrom {
     mirroring: Mirroring,
-    // CHR-ROM $FD/0000 bank select ($B000-$BFFF)
-    // CHR-ROM $FE/0000 bank select ($C000-$CFFF)
-    // CHR-ROM $FD/1000 bank select ($D000-$DFFF)
-    // CHR-ROM $FE/1000 bank select ($E000-$EFFF)
-    // 7  bit  0
-    // ---- ----
-    // xxxC CCCC
-    //    | ||||
-    //    +-++++- Select 4K CHR-ROM bank for PPU $0000/$1000-$0FFF/$1FFF
-    //            used when latch 0/1 = $FD/$FE
-    latch: [usize; 2],
-    latch_banks: [u8; 4],
+    #[serde(deserialize_with = "ChrLatch::deserialize_legacy")]
+    chr_latch: ChrLatch,
     chr_banks: MemBanks,
     prg_rom_banks: MemBanks,
 }
@@ -41,8 +34,7 @@ impl Pxrom {
         cart.add_prg_ram(Self::PRG_RAM_SIZE);
         let mut pxrom = Self {
             mirroring: cart.mirroring(),
-            latch: [0x00; 2],
-            latch_banks: [0x00; 4],
+            chr_latch: ChrLatch::new(false),
             chr_banks: MemBanks::new(0x0000, 0x1FFF, cart.chr_rom.len(), Self::CHR_ROM_WINDOW),
             prg_rom_banks: MemBanks::new(0x8000, 0xFFFF, cart.prg_rom.len(), Self::PRG_WINDOW),
         };
@@ -54,10 +46,8 @@ impl Pxrom {
     }
 
     fn update_banks(&mut self) {
-        let bank0 = self.latch_banks[self.latch[0]] as usize;
-        let bank1 = self.latch_banks[self.latch[1] + 2] as usize;
-        self.chr_banks.set(0, bank0);
-        self.chr_banks.set(1, bank1);
+        self.chr_banks.set(0, self.chr_latch.bank(0));
+        self.chr_banks.set(1, self.chr_latch.bank(1));
     }
 }
 
@@ -71,8 +61,17 @@ impl Mapped for Pxrom {
     fn set_mirroring(&mut self, mirroring: Mirroring) {
         self.mirroring = mirroring;
     }
+
+    #[inline]
+    fn ppu_bus_read(&mut self, addr: u16) {
+        if self.chr_latch.ppu_read(addr) {
+            self.update_banks();
+        }
+    }
 }
 
+impl ExpansionAudio for Pxrom {}
+
 impl MemMap for Pxrom {
     // PPU $0000..=$0FFF Two 4K switchable CHR-ROM banks
     // PPU $1000..=$1FFF Two 4K switchable CHR-ROM banks
@@ -80,20 +79,6 @@ impl MemMap for Pxrom {
     // CPU $8000..=$9FFF 8K switchable PRG-ROM bank
     // CPU $A000..=$FFFF Three 8K PRG-ROM banks, fixed to the last three banks
 
-    fn map_read(&mut self, addr: u16) -> MappedRead {
-        let val = self.map_peek(addr);
-        // Update latch after read
-        match addr {
-            0x0FD8 | 0x0FE8 | 0x1FD8..=0x1FDF | 0x1FE8..=0x1FEF => {
-                let addr = addr as usize;
-                self.latch[addr >> 12] = ((addr >> 4) & 0xFF) - 0xFD;
-                self.update_banks();
-            }
-            _ => (),
-        }
-        val
-    }
-
     fn map_peek(&self, addr: u16) -> MappedRead {
         match addr {
             0x0000..=0x1FFF => MappedRead::Chr(self.chr_banks.translate(addr)),
@@ -111,7 +96,7 @@ impl MemMap for Pxrom {
                 MappedWrite::None
             }
             0xB000..=0xEFFF => {
-                self.latch_banks[((addr - 0xB000) >> 12) as usize] = val & 0x1F;
+                self.chr_latch.write_bank(addr, val);
                 self.update_banks();
                 MappedWrite::None
             }
@@ -129,9 +114,8 @@ impl MemMap for Pxrom {
 }
 
 impl Reset for Pxrom {
-    fn reset(&mut self, _kind: Kind) {
-        self.latch = [0x00; 2];
-        self.latch_banks = [0x00; 4];
+    fn reset(&mut self, kind: Kind) {
+        self.chr_latch.reset(kind);
         self.update_banks();
     }
 }
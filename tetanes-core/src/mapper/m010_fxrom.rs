@@ -0,0 +1,122 @@
+//! `FxROM`/`MMC4` (Mapper 010)
+//!
+//! <https://www.nesdev.org/wiki/MMC4>
+
+use crate::{
+    cart::Cart,
+    common::{Clock, Kind, Regional, Reset},
+    mapper::{
+        chr_latch::ChrLatch, ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap,
+        Mirroring,
+    },
+    mem::MemBanks,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct Fxrom {
+    mirroring: Mirroring,
+    chr_latch: ChrLatch,
+    chr_banks: MemBanks,
+    prg_rom_banks: MemBanks,
+}
+
+impl Fxrom {
+    const PRG_WINDOW: usize = 16 * 1024;
+    const CHR_ROM_WINDOW: usize = 4 * 1024;
+    const PRG_RAM_SIZE: usize = 8 * 1024;
+
+    const MIRRORING_MASK: u8 = 0x01;
+
+    pub fn load(cart: &mut Cart) -> Mapper {
+        if !cart.has_prg_ram() {
+            cart.add_prg_ram(Self::PRG_RAM_SIZE);
+        }
+        let mut fxrom = Self {
+            mirroring: cart.mirroring(),
+            chr_latch: ChrLatch::new(true),
+            chr_banks: MemBanks::new(0x0000, 0x1FFF, cart.chr_rom.len(), Self::CHR_ROM_WINDOW),
+            prg_rom_banks: MemBanks::new(0x8000, 0xFFFF, cart.prg_rom.len(), Self::PRG_WINDOW),
+        };
+        fxrom.prg_rom_banks.set(1, fxrom.prg_rom_banks.last());
+        fxrom.into()
+    }
+
+    fn update_banks(&mut self) {
+        self.chr_banks.set(0, self.chr_latch.bank(0));
+        self.chr_banks.set(1, self.chr_latch.bank(1));
+    }
+}
+
+impl Mapped for Fxrom {
+    #[inline]
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    #[inline]
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+
+    #[inline]
+    fn ppu_bus_read(&mut self, addr: u16) {
+        if self.chr_latch.ppu_read(addr) {
+            self.update_banks();
+        }
+    }
+}
+
+impl ExpansionAudio for Fxrom {}
+
+impl MemMap for Fxrom {
+    // PPU $0000..=$0FFF Two 4K switchable CHR-ROM banks
+    // PPU $1000..=$1FFF Two 4K switchable CHR-ROM banks
+    // CPU $6000..=$7FFF 8K PRG-RAM bank
+    // CPU $8000..=$BFFF 16K switchable PRG-ROM bank
+    // CPU $C000..=$FFFF 16K PRG-ROM bank, fixed to the last bank
+
+    fn map_peek(&self, addr: u16) -> MappedRead {
+        match addr {
+            0x0000..=0x1FFF => MappedRead::Chr(self.chr_banks.translate(addr)),
+            0x6000..=0x7FFF => MappedRead::PrgRam((addr & 0x1FFF).into()),
+            0x8000..=0xFFFF => MappedRead::PrgRom(self.prg_rom_banks.translate(addr)),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, val: u8) -> MappedWrite {
+        match addr {
+            0x6000..=0x7FFF => MappedWrite::PrgRam((addr & 0x1FFF).into(), val),
+            0xA000..=0xAFFF => {
+                self.prg_rom_banks.set(0, (val & 0x0F).into());
+                MappedWrite::None
+            }
+            0xB000..=0xEFFF => {
+                self.chr_latch.write_bank(addr, val);
+                self.update_banks();
+                MappedWrite::None
+            }
+            0xF000..=0xFFFF => {
+                self.mirroring = match val & Self::MIRRORING_MASK {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    _ => unreachable!("impossible mirroring mode"),
+                };
+                MappedWrite::None
+            }
+            _ => MappedWrite::None,
+        }
+    }
+}
+
+impl Reset for Fxrom {
+    fn reset(&mut self, kind: Kind) {
+        self.chr_latch.reset(kind);
+        self.update_banks();
+    }
+}
+
+impl Clock for Fxrom {}
+impl Regional for Fxrom {}
@@ -0,0 +1,479 @@
+//! `Namco 163` (Mapper 019)
+//!
+//! <https://www.nesdev.org/wiki/INES_Mapper_019>
+//! <https://www.nesdev.org/wiki/Namco_163_audio>
+
+use crate::{
+    cart::Cart,
+    common::{Clock, Kind, Regional, Reset},
+    mapper::{AudioChip, ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mem::MemBanks,
+    ppu::Mirroring,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct Namco163 {
+    mirroring: Mirroring,
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_pending: bool,
+    chr_regs: [u8; 8],
+    nt_regs: [u8; 4],
+    // Disables CIRAM banks for CHR $0000-$0FFF and $1000-$1FFF respectively
+    ciram_disabled: [bool; 2],
+    prg_ram_protect: u8,
+    audio: Namco163Audio,
+    chr_banks: MemBanks,
+    nt_banks: MemBanks,
+    prg_ram_banks: MemBanks,
+    prg_rom_banks: MemBanks,
+}
+
+impl Namco163 {
+    const PRG_RAM_SIZE: usize = 8 * 1024;
+    const CHR_RAM_SIZE: usize = 8 * 1024;
+    const PRG_WINDOW: usize = 8 * 1024;
+    const CHR_WINDOW: usize = 1024;
+    const PRG_RAM_REGION: usize = 2 * 1024;
+
+    /// CHR and nametable register values at or above this select CIRAM.
+    const CIRAM_BANK: u8 = 0xE0;
+    const IRQ_MAX: u16 = 0x7FFF;
+
+    pub fn load(cart: &mut Cart) -> Mapper {
+        if !cart.has_prg_ram() {
+            cart.add_prg_ram(Self::PRG_RAM_SIZE);
+        }
+        if !cart.has_chr() {
+            cart.add_chr_ram(Self::CHR_RAM_SIZE);
+        }
+        let mut namco163 = Self {
+            mirroring: cart.mirroring(),
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            chr_regs: [0x00; 8],
+            nt_regs: [Self::CIRAM_BANK; 4],
+            ciram_disabled: [false; 2],
+            prg_ram_protect: 0x00,
+            audio: Namco163Audio::new(),
+            chr_banks: MemBanks::new(0x0000, 0x1FFF, cart.chr_len(), Self::CHR_WINDOW),
+            nt_banks: MemBanks::new(0x2000, 0x2FFF, cart.chr_len(), Self::CHR_WINDOW),
+            prg_ram_banks: MemBanks::new(0x6000, 0x7FFF, cart.prg_ram.len(), Self::PRG_RAM_SIZE),
+            prg_rom_banks: MemBanks::new(0x8000, 0xFFFF, cart.prg_rom.len(), Self::PRG_WINDOW),
+        };
+        let last_bank = namco163.prg_rom_banks.last();
+        namco163.prg_rom_banks.set(3, last_bank);
+        namco163.into()
+    }
+
+    /// Maps a pattern table or nametable access to CIRAM if its bank register selects it.
+    #[inline]
+    fn ciram_addr(bank: u8, addr: u16) -> Option<usize> {
+        (bank >= Self::CIRAM_BANK)
+            .then(|| (usize::from(bank & 0x01) << 10) | (addr as usize & 0x03FF))
+    }
+
+    fn map_chr(&self, addr: u16) -> Result<usize, usize> {
+        match addr {
+            0x0000..=0x1FFF => {
+                let slot = (addr >> 10) as usize;
+                if self.ciram_disabled[slot >> 2] {
+                    Ok(self.chr_banks.translate(addr))
+                } else {
+                    Self::ciram_addr(self.chr_regs[slot], addr)
+                        .map_or_else(|| Ok(self.chr_banks.translate(addr)), Err)
+                }
+            }
+            _ => {
+                let slot = ((addr >> 10) & 0x03) as usize;
+                Self::ciram_addr(self.nt_regs[slot], addr)
+                    .map_or_else(|| Ok(self.nt_banks.translate(addr)), Err)
+            }
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    const fn prg_ram_writable(&self, addr: u16) -> bool {
+        // [0100 DCBA] enables writes to each 2K region when the region bit is clear
+        let region = (addr as usize - 0x6000) / Self::PRG_RAM_REGION;
+        self.prg_ram_protect & 0xF0 == 0x40 && self.prg_ram_protect & (1 << region) == 0
+    }
+}
+
+impl Mapped for Namco163 {
+    #[inline]
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    #[inline]
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    #[inline]
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+
+    fn cpu_bus_read(&mut self, addr: u16) {
+        if matches!(addr, 0x4800..=0x4FFF) {
+            self.audio.increment_address();
+        }
+    }
+}
+
+impl MemMap for Namco163 {
+    // PPU $0000..=$1FFF Eight 1K switchable CHR-ROM banks or CIRAM pages
+    // PPU $2000..=$2FFF Four 1K switchable CHR-ROM banks or CIRAM pages
+    //
+    // CPU $4800..=$4FFF Sound chip RAM data port
+    // CPU $5000..=$5FFF IRQ counter
+    // CPU $6000..=$7FFF 8K PRG-RAM bank, fixed
+    // CPU $8000..=$9FFF 8K switchable PRG-ROM bank
+    // CPU $A000..=$BFFF 8K switchable PRG-ROM bank
+    // CPU $C000..=$DFFF 8K switchable PRG-ROM bank
+    // CPU $E000..=$FFFF 8K PRG-ROM bank, fixed to the last bank
+
+    fn map_peek(&self, addr: u16) -> MappedRead {
+        match addr {
+            0x0000..=0x3EFF => match self.map_chr(addr) {
+                Ok(addr) => MappedRead::Chr(addr),
+                Err(addr) => MappedRead::CIRam(addr),
+            },
+            0x4800..=0x4FFF => MappedRead::Data(self.audio.read_data()),
+            0x5000..=0x57FF => MappedRead::Data((self.irq_counter & 0xFF) as u8),
+            0x5800..=0x5FFF => {
+                MappedRead::Data((u8::from(self.irq_enabled) << 7) | (self.irq_counter >> 8) as u8)
+            }
+            0x6000..=0x7FFF => MappedRead::PrgRam(self.prg_ram_banks.translate(addr)),
+            0x8000..=0xFFFF => MappedRead::PrgRom(self.prg_rom_banks.translate(addr)),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, val: u8) -> MappedWrite {
+        match addr {
+            0x0000..=0x3EFF => match self.map_chr(addr) {
+                Ok(addr) => MappedWrite::Chr(addr, val),
+                Err(addr) => MappedWrite::CIRam(addr, val),
+            },
+            0x4800..=0x4FFF => {
+                self.audio.write_data(val);
+                MappedWrite::None
+            }
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0x7F00) | u16::from(val);
+                self.irq_pending = false;
+                MappedWrite::None
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (u16::from(val & 0x7F) << 8) | (self.irq_counter & 0xFF);
+                self.irq_enabled = val & 0x80 == 0x80;
+                self.irq_pending = false;
+                MappedWrite::None
+            }
+            0x6000..=0x7FFF if self.prg_ram_writable(addr) => {
+                MappedWrite::PrgRam(self.prg_ram_banks.translate(addr), val)
+            }
+            0x8000..=0xBFFF => {
+                let slot = ((addr - 0x8000) >> 11) as usize;
+                self.chr_regs[slot] = val;
+                self.chr_banks.set(slot, val.into());
+                MappedWrite::None
+            }
+            0xC000..=0xDFFF => {
+                let slot = ((addr - 0xC000) >> 11) as usize;
+                self.nt_regs[slot] = val;
+                self.nt_banks.set(slot, val.into());
+                MappedWrite::None
+            }
+            0xE000..=0xE7FF => {
+                // [.SPP PPPP]
+                //   |++-++++- Select 8K PRG-ROM bank at $8000-$9FFF
+                //   +-------- Disable sound
+                self.prg_rom_banks.set(0, (val & 0x3F).into());
+                self.audio.set_enabled(val & 0x40 == 0x00);
+                MappedWrite::None
+            }
+            0xE800..=0xEFFF => {
+                // [HLPP PPPP]
+                //  |||| ||||
+                //  ||++-++++- Select 8K PRG-ROM bank at $A000-$BFFF
+                //  |+-------- Disable CIRAM banks for CHR $0000-$0FFF
+                //  +--------- Disable CIRAM banks for CHR $1000-$1FFF
+                self.prg_rom_banks.set(1, (val & 0x3F).into());
+                self.ciram_disabled = [val & 0x40 == 0x40, val & 0x80 == 0x80];
+                MappedWrite::None
+            }
+            0xF000..=0xF7FF => {
+                self.prg_rom_banks.set(2, (val & 0x3F).into());
+                MappedWrite::None
+            }
+            0xF800..=0xFFFF => {
+                // Shared between PRG-RAM write protection and the sound chip RAM address
+                self.prg_ram_protect = val;
+                self.audio.write_address(val);
+                MappedWrite::None
+            }
+            _ => MappedWrite::None,
+        }
+    }
+}
+
+impl ExpansionAudio for Namco163 {
+    fn audio_chip(&self) -> Option<AudioChip> {
+        Some(AudioChip::Namco163)
+    }
+
+    #[inline]
+    #[must_use]
+    fn output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+impl Clock for Namco163 {
+    fn clock(&mut self) -> usize {
+        if self.irq_enabled && self.irq_counter < Self::IRQ_MAX {
+            self.irq_counter += 1;
+            if self.irq_counter == Self::IRQ_MAX {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.clock();
+        1
+    }
+}
+
+impl Reset for Namco163 {
+    fn reset(&mut self, kind: Kind) {
+        self.irq_enabled = false;
+        self.irq_pending = false;
+        self.audio.reset(kind);
+    }
+}
+
+impl Regional for Namco163 {}
+
+/// Up to 8 wavetable channels sharing 128 bytes of chip RAM, with waveforms stored as 4-bit
+/// samples.
+///
+/// The chip only updates and outputs one channel every 15 CPU cycles, multiplexing between
+/// them. With 6 or more channels enabled this switching falls into the audible range as a loud
+/// hiss, so the latest output of each channel is averaged instead. This preserves the relative
+/// volume of the multiplexed signal without the switching noise.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct Namco163Audio {
+    ram: Vec<u8>,
+    address: u8,
+    auto_increment: bool,
+    enabled: bool,
+    channel: usize,
+    timer: u8,
+    outputs: [i16; 8],
+    out: f32,
+}
+
+impl Default for Namco163Audio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Namco163Audio {
+    const RAM_SIZE: usize = 128;
+    const CHANNEL_COUNT: usize = 8;
+    const CHANNEL_START: usize = 0x40;
+    const CHANNEL_CYCLES: u8 = 15;
+    const CHANNEL_COUNT_REG: usize = 0x7F;
+
+    /// Scales the averaged channel output to roughly match the level of an APU pulse channel.
+    const OUTPUT_SCALE: f32 = 0.12 / 120.0;
+
+    fn new() -> Self {
+        Self {
+            ram: vec![0x00; Self::RAM_SIZE],
+            address: 0x00,
+            auto_increment: false,
+            enabled: true,
+            channel: Self::CHANNEL_COUNT - 1,
+            timer: 0,
+            outputs: [0; 8],
+            out: 0.0,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    fn output(&self) -> f32 {
+        if self.enabled {
+            self.out * Self::OUTPUT_SCALE
+        } else {
+            0.0
+        }
+    }
+
+    #[inline]
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn write_address(&mut self, val: u8) {
+        // [IAAA AAAA]
+        //  |+++-++++- Chip RAM address
+        //  +--------- Auto-increment address after each read or write
+        self.address = val & 0x7F;
+        self.auto_increment = val & 0x80 == 0x80;
+    }
+
+    #[inline]
+    fn increment_address(&mut self) {
+        if self.auto_increment {
+            self.address = (self.address + 1) & 0x7F;
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    fn read_data(&self) -> u8 {
+        self.ram[self.address as usize]
+    }
+
+    fn write_data(&mut self, val: u8) {
+        self.ram[self.address as usize] = val;
+        self.increment_address();
+    }
+
+    /// Number of enabled channels, counting down from channel 8.
+    #[inline]
+    #[must_use]
+    fn channel_count(&self) -> usize {
+        ((self.ram[Self::CHANNEL_COUNT_REG] >> 4) & 0x07) as usize + 1
+    }
+
+    /// Advances the phase of a channel and returns its current output.
+    fn update_channel(&mut self, channel: usize) -> i16 {
+        let base = Self::CHANNEL_START + channel * 8;
+        let reg = |offset: usize| u32::from(self.ram[base + offset]);
+
+        let freq = reg(0) | (reg(2) << 8) | ((reg(4) & 0x03) << 16);
+        let length = (256 - (reg(4) & 0xFC)) << 16;
+        let phase = ((reg(1) | (reg(3) << 8) | (reg(5) << 16)) + freq) % length;
+        let wave_addr = reg(6);
+        let volume = (reg(7) & 0x0F) as i16;
+
+        self.ram[base + 1] = (phase & 0xFF) as u8;
+        self.ram[base + 3] = ((phase >> 8) & 0xFF) as u8;
+        self.ram[base + 5] = ((phase >> 16) & 0xFF) as u8;
+
+        let sample_addr = (((phase >> 16) + wave_addr) & 0xFF) as usize;
+        let sample = (self.ram[sample_addr >> 1] >> ((sample_addr & 0x01) << 2)) & 0x0F;
+        (i16::from(sample) - 8) * volume
+    }
+}
+
+impl Clock for Namco163Audio {
+    fn clock(&mut self) -> usize {
+        self.timer += 1;
+        if self.timer < Self::CHANNEL_CYCLES {
+            return 0;
+        }
+        self.timer = 0;
+
+        let count = self.channel_count();
+        let first = Self::CHANNEL_COUNT - count;
+        if self.channel < first {
+            self.channel = Self::CHANNEL_COUNT - 1;
+        }
+        self.outputs[self.channel] = self.update_channel(self.channel);
+        self.channel = if self.channel == first {
+            Self::CHANNEL_COUNT - 1
+        } else {
+            self.channel - 1
+        };
+
+        let sum: i16 = self.outputs[first..].iter().sum();
+        self.out = f32::from(sum) / count as f32;
+        1
+    }
+}
+
+impl Reset for Namco163Audio {
+    fn reset(&mut self, _kind: Kind) {
+        self.channel = Self::CHANNEL_COUNT - 1;
+        self.timer = 0;
+        self.outputs = [0; 8];
+        self.out = 0.0;
+        self.enabled = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_ram(audio: &mut Namco163Audio, addr: u8, data: &[u8]) {
+        audio.write_address(0x80 | addr);
+        for &val in data {
+            audio.write_data(val);
+        }
+    }
+
+    #[test]
+    fn wavetable_channel() {
+        let mut audio = Namco163Audio::new();
+        // Square wave: 8 samples at 15 followed by 8 at 0
+        write_ram(
+            &mut audio,
+            0x00,
+            &[0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00],
+        );
+        // Channel 8 at full volume with one enabled channel, 16 sample waveform, one sample per
+        // update
+        write_ram(
+            &mut audio,
+            0x78,
+            &[0x00, 0x00, 0x00, 0x00, 0xF1, 0x00, 0x00, 0x0F],
+        );
+
+        let mut outputs = vec![];
+        for _ in 0..16 * 15 {
+            if audio.clock() == 1 {
+                outputs.push(audio.out);
+            }
+        }
+        assert_eq!(outputs.len(), 16);
+        assert!(outputs.iter().filter(|&&out| out > 0.0).count() >= 7);
+        assert!(outputs.iter().filter(|&&out| out < 0.0).count() >= 7);
+        assert!(outputs.iter().all(|out| out.abs() <= 120.0));
+    }
+
+    #[test]
+    fn averages_enabled_channels() {
+        let mut audio = Namco163Audio::new();
+        // Waveform of constant 15
+        write_ram(&mut audio, 0x00, &[0xFF; 8]);
+        // Channel 8 at full volume and channel 7 silent, with two enabled channels
+        write_ram(
+            &mut audio,
+            0x70,
+            &[0x00, 0x00, 0x00, 0x00, 0xF0, 0x00, 0x00, 0x00],
+        );
+        write_ram(
+            &mut audio,
+            0x78,
+            &[0x00, 0x00, 0x00, 0x00, 0xF0, 0x00, 0x00, 0x1F],
+        );
+
+        for _ in 0..2 * 15 {
+            audio.clock();
+        }
+        assert!((audio.out - 7.0 * 15.0 / 2.0).abs() < f32::EPSILON);
+    }
+}
@@ -0,0 +1,338 @@
+//! `VRC2`/`VRC4` (Mappers 021, 022, 023 and 025)
+//!
+//! <https://www.nesdev.org/wiki/VRC2_and_VRC4>
+
+use crate::{
+    cart::Cart,
+    common::{Clock, Kind, Regional, Reset},
+    mapper::{vrc_irq::VrcIrq, ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mem::MemBanks,
+    ppu::Mirroring,
+};
+use serde::{Deserialize, Serialize};
+
+/// Board variants, which differ in the CPU address lines wired to the register select pins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub enum Vrc24Revision {
+    /// VRC2a (Mapper 022): A1, A0. CHR banks ignore the low bit.
+    Vrc2a,
+    /// VRC2b (Mapper 023): A0, A1
+    Vrc2b,
+    /// VRC2c (Mapper 025): A1, A0
+    Vrc2c,
+    /// VRC4a (Mapper 021): A1, A2
+    Vrc4a,
+    /// VRC4b (Mapper 025): A1, A0
+    Vrc4b,
+    /// VRC4c (Mapper 021): A6, A7
+    Vrc4c,
+    /// VRC4d (Mapper 025): A3, A2
+    Vrc4d,
+    /// VRC4e (Mapper 023): A2, A3
+    Vrc4e,
+    /// VRC4f (Mapper 023): A0, A1
+    Vrc4f,
+    /// VRC4a or VRC4c, when a NES 2.0 submapper isn't provided.
+    Vrc4ac,
+    /// VRC4b or VRC4d, when a NES 2.0 submapper isn't provided.
+    Vrc4bd,
+    /// VRC4e, VRC4f or VRC2b, when a NES 2.0 submapper isn't provided.
+    Vrc4ef,
+}
+
+impl Vrc24Revision {
+    /// Determines the board variant from the mapper and NES 2.0 submapper numbers.
+    pub const fn from_mapper(mapper_num: u16, submapper_num: u8) -> Self {
+        match (mapper_num, submapper_num) {
+            (21, 1) => Self::Vrc4a,
+            (21, 2) => Self::Vrc4c,
+            (22, _) => Self::Vrc2a,
+            (23, 1) => Self::Vrc4f,
+            (23, 2) => Self::Vrc4e,
+            (23, 3) => Self::Vrc2b,
+            (25, 1) => Self::Vrc4b,
+            (25, 2) => Self::Vrc4d,
+            (25, 3) => Self::Vrc2c,
+            (23, _) => Self::Vrc4ef,
+            (25, _) => Self::Vrc4bd,
+            _ => Self::Vrc4ac,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn is_vrc2(self) -> bool {
+        matches!(self, Self::Vrc2a | Self::Vrc2b | Self::Vrc2c)
+    }
+
+    /// Whether PRG-RAM can be disabled. `VRC2` lacks the control and it's ignored for unknown
+    /// variants, since a `VRC2b` board would never enable PRG-RAM.
+    #[inline]
+    #[must_use]
+    pub const fn has_prg_ram_control(self) -> bool {
+        matches!(
+            self,
+            Self::Vrc4a | Self::Vrc4b | Self::Vrc4c | Self::Vrc4d | Self::Vrc4e | Self::Vrc4f
+        )
+    }
+
+    /// Address lines wired to the register select pins 0 and 1. Variants that can't be told
+    /// apart decode both pairs of lines, since games only use one pair.
+    #[must_use]
+    const fn select_lines(self) -> (u16, u16) {
+        match self {
+            Self::Vrc2b | Self::Vrc4f => (0x01, 0x02),
+            Self::Vrc2a | Self::Vrc2c | Self::Vrc4b => (0x02, 0x01),
+            Self::Vrc4a => (0x02, 0x04),
+            Self::Vrc4c => (0x40, 0x80),
+            Self::Vrc4d => (0x08, 0x04),
+            Self::Vrc4e => (0x04, 0x08),
+            Self::Vrc4ac => (0x42, 0x84),
+            Self::Vrc4bd => (0x0A, 0x05),
+            Self::Vrc4ef => (0x05, 0x0A),
+        }
+    }
+
+    /// Normalizes an address to `$x000..=$x003` with the register select in A0 and A1.
+    #[must_use]
+    pub const fn register(self, addr: u16) -> u16 {
+        let (select0, select1) = self.select_lines();
+        let mut register = addr & 0xF000;
+        if addr & select0 != 0 {
+            register |= 0x01;
+        }
+        if addr & select1 != 0 {
+            register |= 0x02;
+        }
+        register
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct Vrc24 {
+    revision: Vrc24Revision,
+    mirroring: Mirroring,
+    swap_mode: bool,
+    prg_ram_enabled: bool,
+    prg: [usize; 2],
+    chr: [usize; 8],
+    irq: VrcIrq,
+    chr_banks: MemBanks,
+    prg_ram_banks: MemBanks,
+    prg_rom_banks: MemBanks,
+}
+
+impl Vrc24 {
+    const PRG_RAM_SIZE: usize = 8 * 1024;
+    const CHR_RAM_SIZE: usize = 8 * 1024;
+    const PRG_WINDOW: usize = 8 * 1024;
+    const CHR_WINDOW: usize = 1024;
+
+    pub fn load(cart: &mut Cart) -> Mapper {
+        // VRC2 boards without PRG-RAM have a 1-bit latch at $6000 instead, which reads back the
+        // same as RAM would
+        if !cart.has_prg_ram() {
+            cart.add_prg_ram(Self::PRG_RAM_SIZE);
+        }
+        if !cart.has_chr() {
+            cart.add_chr_ram(Self::CHR_RAM_SIZE);
+        }
+        let revision = Vrc24Revision::from_mapper(cart.mapper_num(), cart.submapper_num());
+        let mut vrc24 = Self {
+            revision,
+            mirroring: cart.mirroring(),
+            swap_mode: false,
+            prg_ram_enabled: !revision.has_prg_ram_control(),
+            prg: [0, 1],
+            chr: [0; 8],
+            irq: VrcIrq::default(),
+            chr_banks: MemBanks::new(0x0000, 0x1FFF, cart.chr_len(), Self::CHR_WINDOW),
+            prg_ram_banks: MemBanks::new(0x6000, 0x7FFF, cart.prg_ram.len(), Self::PRG_RAM_SIZE),
+            prg_rom_banks: MemBanks::new(0x8000, 0xFFFF, cart.prg_rom.len(), Self::PRG_WINDOW),
+        };
+        vrc24.update_prg_banks();
+        vrc24.into()
+    }
+
+    fn update_prg_banks(&mut self) {
+        let second_last = self.prg_rom_banks.last().saturating_sub(1);
+        if self.swap_mode {
+            self.prg_rom_banks.set(0, second_last);
+            self.prg_rom_banks.set(2, self.prg[0]);
+        } else {
+            self.prg_rom_banks.set(0, self.prg[0]);
+            self.prg_rom_banks.set(2, second_last);
+        }
+        self.prg_rom_banks.set(1, self.prg[1]);
+        let last_bank = self.prg_rom_banks.last();
+        self.prg_rom_banks.set(3, last_bank);
+    }
+
+    fn write_chr_bank(&mut self, register: u16, val: u8) {
+        // $B000..=$E003 select eight 1K banks, each split across a low and high register
+        let bank = ((((register >> 12) - 0xB) << 1) | ((register >> 1) & 0x01)) as usize;
+        let val = usize::from(val);
+        self.chr[bank] = if register & 0x01 == 0x01 {
+            (self.chr[bank] & 0x0F) | ((val & 0x1F) << 4)
+        } else {
+            (self.chr[bank] & 0x1F0) | (val & 0x0F)
+        };
+        let chr_bank = if self.revision == Vrc24Revision::Vrc2a {
+            self.chr[bank] >> 1
+        } else if self.revision.is_vrc2() {
+            self.chr[bank] & 0xFF
+        } else {
+            self.chr[bank]
+        };
+        self.chr_banks.set(bank, chr_bank);
+    }
+}
+
+impl Mapped for Vrc24 {
+    #[inline]
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    #[inline]
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    #[inline]
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+}
+
+impl MemMap for Vrc24 {
+    // PPU $0000..=$1FFF Eight 1K switchable CHR banks
+    //
+    // CPU $6000..=$7FFF 8K PRG-RAM bank, fixed
+    // CPU $8000..=$9FFF 8K switchable PRG-ROM bank or fixed to the second last bank
+    // CPU $A000..=$BFFF 8K switchable PRG-ROM bank
+    // CPU $C000..=$DFFF 8K PRG-ROM bank fixed to the second last bank or switchable
+    // CPU $E000..=$FFFF 8K PRG-ROM bank, fixed to the last bank
+
+    fn map_peek(&self, addr: u16) -> MappedRead {
+        match addr {
+            0x0000..=0x1FFF => MappedRead::Chr(self.chr_banks.translate(addr)),
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                MappedRead::PrgRam(self.prg_ram_banks.translate(addr))
+            }
+            0x8000..=0xFFFF => MappedRead::PrgRom(self.prg_rom_banks.translate(addr)),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, val: u8) -> MappedWrite {
+        match addr {
+            0x0000..=0x1FFF => return MappedWrite::Chr(self.chr_banks.translate(addr), val),
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                return MappedWrite::PrgRam(self.prg_ram_banks.translate(addr), val);
+            }
+            0x8000..=0xFFFF => (),
+            _ => return MappedWrite::None,
+        }
+
+        let vrc2 = self.revision.is_vrc2();
+        let register = self.revision.register(addr);
+        match register {
+            0x8000..=0x8003 => {
+                self.prg[0] = (val & 0x1F).into();
+                self.update_prg_banks();
+            }
+            0x9000..=0x9003 if vrc2 => {
+                self.mirroring = match val & 0x01 {
+                    0 => Mirroring::Vertical,
+                    _ => Mirroring::Horizontal,
+                };
+            }
+            0x9000 | 0x9001 => {
+                self.mirroring = match val & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenA,
+                    _ => Mirroring::SingleScreenB,
+                };
+            }
+            0x9002 => {
+                // [.... ..MW]
+                //         ||
+                //         |+- PRG-RAM enable
+                //         +-- PRG-ROM swap mode
+                if self.revision.has_prg_ram_control() {
+                    self.prg_ram_enabled = val & 0x01 == 0x01;
+                }
+                self.swap_mode = val & 0x02 == 0x02;
+                self.update_prg_banks();
+            }
+            0xA000..=0xA003 => {
+                self.prg[1] = (val & 0x1F).into();
+                self.update_prg_banks();
+            }
+            0xB000..=0xE003 => self.write_chr_bank(register, val),
+            0xF000 if !vrc2 => self.irq.write_reload_low(val),
+            0xF001 if !vrc2 => self.irq.write_reload_high(val),
+            0xF002 if !vrc2 => self.irq.write_control(val),
+            0xF003 if !vrc2 => self.irq.acknowledge(),
+            _ => (),
+        }
+        MappedWrite::None
+    }
+}
+
+impl ExpansionAudio for Vrc24 {}
+
+impl Clock for Vrc24 {
+    fn clock(&mut self) -> usize {
+        if self.revision.is_vrc2() {
+            0
+        } else {
+            self.irq.clock();
+            1
+        }
+    }
+}
+
+impl Reset for Vrc24 {
+    fn reset(&mut self, kind: Kind) {
+        self.irq.reset(kind);
+        self.swap_mode = false;
+        self.update_prg_banks();
+    }
+}
+
+impl Regional for Vrc24 {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_select_lines() {
+        assert_eq!(Vrc24Revision::Vrc4a.register(0x8004), 0x8002);
+        assert_eq!(Vrc24Revision::Vrc4c.register(0xB040), 0xB001);
+        assert_eq!(Vrc24Revision::Vrc4ac.register(0xF080), 0xF002);
+        assert_eq!(Vrc24Revision::Vrc4ac.register(0xF004), 0xF002);
+        assert_eq!(Vrc24Revision::Vrc2a.register(0xB001), 0xB002);
+        assert_eq!(Vrc24Revision::Vrc4b.register(0xB002), 0xB001);
+        assert_eq!(Vrc24Revision::Vrc4d.register(0xC004), 0xC002);
+        assert_eq!(Vrc24Revision::Vrc4e.register(0xE00C), 0xE003);
+        assert_eq!(Vrc24Revision::Vrc4ef.register(0x9008), 0x9002);
+        assert_eq!(Vrc24Revision::Vrc4bd.register(0x9004), 0x9002);
+        // Unused address lines are ignored
+        assert_eq!(Vrc24Revision::Vrc2b.register(0x9FF0), 0x9000);
+    }
+
+    #[test]
+    fn revision_from_submapper() {
+        assert_eq!(Vrc24Revision::from_mapper(21, 0), Vrc24Revision::Vrc4ac);
+        assert_eq!(Vrc24Revision::from_mapper(22, 0), Vrc24Revision::Vrc2a);
+        assert_eq!(Vrc24Revision::from_mapper(23, 3), Vrc24Revision::Vrc2b);
+        assert_eq!(Vrc24Revision::from_mapper(25, 2), Vrc24Revision::Vrc4d);
+        assert!(Vrc24Revision::from_mapper(25, 3).is_vrc2());
+    }
+}
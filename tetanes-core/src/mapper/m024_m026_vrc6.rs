@@ -4,10 +4,11 @@
 
 use crate::{
     apu::PULSE_TABLE,
-    audio::Audio,
     cart::Cart,
     common::{Clock, Kind, Regional, Reset},
-    mapper::{vrc_irq::VrcIrq, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mapper::{
+        vrc_irq::VrcIrq, AudioChip, ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap,
+    },
     mem::MemBanks,
     ppu::Mirroring,
 };
@@ -334,7 +335,11 @@ impl MemMap for Vrc6 {
     }
 }
 
-impl Audio for Vrc6 {
+impl ExpansionAudio for Vrc6 {
+    fn audio_chip(&self) -> Option<AudioChip> {
+        Some(AudioChip::Vrc6)
+    }
+
     #[inline]
     #[must_use]
     fn output(&self) -> f32 {
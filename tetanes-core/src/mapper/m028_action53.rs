@@ -0,0 +1,233 @@
+//! `Action 53` (Mapper 028)
+//!
+//! Homebrew multicart mapper able to emulate the banking of `NROM`, `CNROM`, `UxROM`, `AxROM`
+//! and `BNROM` games within a larger ROM.
+//!
+//! <https://www.nesdev.org/wiki/Action_53_mapper>
+
+use crate::{
+    cart::Cart,
+    common::{Clock, Kind, Regional, Reset},
+    mapper::{ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mem::MemBanks,
+    ppu::Mirroring,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct Action53 {
+    reg_select: u8,
+    chr_bank: u8,
+    inner_bank: u8,
+    mode: u8,
+    outer_bank: u8,
+    mirroring: Mirroring,
+    chr_banks: MemBanks,
+    prg_rom_banks: MemBanks,
+}
+
+impl Action53 {
+    const PRG_ROM_WINDOW: usize = 16 * 1024;
+    const CHR_WINDOW: usize = 8 * 1024;
+    const CHR_RAM_SIZE: usize = 32 * 1024;
+
+    const REG_CHR: u8 = 0x00;
+    const REG_INNER: u8 = 0x01;
+    const REG_MODE: u8 = 0x80;
+    const REG_OUTER: u8 = 0x81;
+
+    const MIRRORING_MASK: u8 = 0x03; // 0b000011
+    const ONE_SCREEN_MIRRORING: u8 = 0x02; // 0b000010
+    const PRG_16K: u8 = 0x08; // 0b001000
+    const PRG_FIXED_LAST: u8 = 0x04; // 0b000100
+    const GAME_SIZE_MASK: u8 = 0x30; // 0b110000
+
+    pub fn load(cart: &mut Cart) -> Mapper {
+        if !cart.has_chr() {
+            cart.add_chr_ram(Self::CHR_RAM_SIZE);
+        }
+        let mut action53 = Self {
+            reg_select: 0x00,
+            chr_bank: 0x00,
+            inner_bank: 0x00,
+            mode: 0x00,
+            // The menu is in the last bank
+            outer_bank: 0xFF,
+            mirroring: Mirroring::SingleScreenA,
+            chr_banks: MemBanks::new(0x0000, 0x1FFF, cart.chr_len(), Self::CHR_WINDOW),
+            prg_rom_banks: MemBanks::new(0x8000, 0xFFFF, cart.prg_rom.len(), Self::PRG_ROM_WINDOW),
+        };
+        action53.update_banks();
+        action53.into()
+    }
+
+    /// 16K PRG-ROM bank for `$8000` when `high` is false or `$C000` when `high` is true.
+    const fn prg_bank(&self, high: bool) -> usize {
+        let outer = (self.outer_bank as usize) << 1;
+        let inner = if self.mode & Self::PRG_16K == 0 {
+            // 32K banks
+            ((self.inner_bank as usize) << 1) | high as usize
+        } else if high == (self.mode & Self::PRG_FIXED_LAST != 0) {
+            // UxROM-like fixed bank: first bank at $8000 or last bank at $C000
+            if high {
+                0xFF
+            } else {
+                0x00
+            }
+        } else {
+            self.inner_bank as usize
+        };
+        // The inner bank replaces as many low bits of the outer bank as the game size needs
+        let inner_mask = (2 << ((self.mode & Self::GAME_SIZE_MASK) >> 4)) - 1;
+        (outer & !inner_mask) | (inner & inner_mask)
+    }
+
+    fn update_banks(&mut self) {
+        self.mirroring = match self.mode & Self::MIRRORING_MASK {
+            0 => Mirroring::SingleScreenA,
+            1 => Mirroring::SingleScreenB,
+            2 => Mirroring::Vertical,
+            3 => Mirroring::Horizontal,
+            _ => unreachable!("impossible mirroring mode"),
+        };
+        self.chr_banks.set(0, (self.chr_bank & 0x03).into());
+        self.prg_rom_banks.set(0, self.prg_bank(false));
+        self.prg_rom_banks.set(1, self.prg_bank(true));
+    }
+}
+
+impl Mapped for Action53 {
+    #[inline]
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    #[inline]
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+}
+
+impl ExpansionAudio for Action53 {}
+
+impl MemMap for Action53 {
+    // PPU $0000..=$1FFF 8K CHR-RAM Bank Switchable
+    // CPU $8000..=$BFFF 16K PRG-ROM Bank Switchable or Fixed to First Bank
+    // CPU $C000..=$FFFF 16K PRG-ROM Bank Switchable or Fixed to Last Bank
+
+    fn map_peek(&self, addr: u16) -> MappedRead {
+        match addr {
+            0x0000..=0x1FFF => MappedRead::Chr(self.chr_banks.translate(addr)),
+            0x8000..=0xFFFF => MappedRead::PrgRom(self.prg_rom_banks.translate(addr)),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, val: u8) -> MappedWrite {
+        // Register Select $5000-$5FFF
+        // 7654 3210
+        // S... ...R
+        // +-------+- Register written by $8000-$FFFF
+        //            ($00: CHR bank; $01: Inner bank; $80: Mode; $81: Outer bank)
+        //
+        // CHR bank $00
+        // 7654 3210
+        // ...M ..CC
+        //    |   ++- Select 8K CHR-RAM bank
+        //    +------ One-screen mirroring select, when using one-screen mirroring
+        //
+        // Inner bank $01
+        // 7654 3210
+        // ...M PPPP
+        //    | ++++- Select 16K or 32K PRG-ROM bank within the current game
+        //    +------ One-screen mirroring select, when using one-screen mirroring
+        //
+        // Mode $80
+        // 7654 3210
+        // ..SS PPMM
+        //   || ||++- Mirroring (0: one-screen, lower bank; 1: one-screen, upper bank;
+        //   || ||               2: vertical; 3: horizontal)
+        //   || ++--- PRG-ROM bank mode (0, 1: switch 32K; 2: fix first bank at $8000 and switch
+        //   ||                          16K at $C000; 3: switch 16K at $8000 and fix last bank
+        //   ||                          at $C000)
+        //   ++------ Game size (0: 32K; 1: 64K; 2: 128K; 3: 256K)
+        //
+        // Outer bank $81
+        // 7654 3210
+        // PPPP PPPP
+        // ++++-++++- Select 32K PRG-ROM bank containing the current game
+        match addr {
+            0x0000..=0x1FFF => return MappedWrite::Chr(self.chr_banks.translate(addr), val),
+            0x5000..=0x5FFF => self.reg_select = val & 0x81,
+            0x8000..=0xFFFF => {
+                let one_screen = self.mode & Self::ONE_SCREEN_MIRRORING == 0;
+                match self.reg_select {
+                    Self::REG_CHR | Self::REG_INNER if one_screen => {
+                        self.mode = (self.mode & !0x01) | ((val >> 4) & 0x01);
+                    }
+                    _ => (),
+                }
+                match self.reg_select {
+                    Self::REG_CHR => self.chr_bank = val,
+                    Self::REG_INNER => self.inner_bank = val & 0x0F,
+                    Self::REG_MODE => self.mode = val & 0x3F,
+                    Self::REG_OUTER => self.outer_bank = val,
+                    _ => unreachable!("impossible register select"),
+                }
+                self.update_banks();
+            }
+            _ => (),
+        }
+        MappedWrite::None
+    }
+}
+
+impl Reset for Action53 {
+    fn reset(&mut self, kind: Kind) {
+        if kind == Kind::Hard {
+            self.reg_select = 0x00;
+            self.chr_bank = 0x00;
+            self.inner_bank = 0x00;
+            self.mode = 0x00;
+            self.outer_bank = 0xFF;
+            self.update_banks();
+        }
+    }
+}
+
+impl Clock for Action53 {}
+impl Regional for Action53 {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action53(mode: u8, outer_bank: u8, inner_bank: u8) -> Action53 {
+        Action53 {
+            reg_select: 0x00,
+            chr_bank: 0x00,
+            inner_bank,
+            mode,
+            outer_bank,
+            mirroring: Mirroring::Vertical,
+            chr_banks: MemBanks::new(0x0000, 0x1FFF, 0x8000, Action53::CHR_WINDOW),
+            prg_rom_banks: MemBanks::new(0x8000, 0xFFFF, 0x80000, Action53::PRG_ROM_WINDOW),
+        }
+    }
+
+    #[test]
+    fn prg_banks() {
+        // NROM-256 game at outer bank 3
+        let mapper = action53(0x00, 0x03, 0x00);
+        assert_eq!((mapper.prg_bank(false), mapper.prg_bank(true)), (6, 7));
+
+        // 128K UNROM game at outer bank 4, inner bank 2 at $8000 and last bank fixed at $C000
+        let mapper = action53(0x2C, 0x04, 0x02);
+        assert_eq!((mapper.prg_bank(false), mapper.prg_bank(true)), (10, 15));
+
+        // 64K BNROM game at outer bank 2, inner 32K bank 1
+        let mapper = action53(0x10, 0x02, 0x01);
+        assert_eq!((mapper.prg_bank(false), mapper.prg_bank(true)), (6, 7));
+    }
+}
@@ -0,0 +1,144 @@
+//! `BNROM`/`NINA-001` (Mapper 034)
+//!
+//! <https://www.nesdev.org/wiki/INES_Mapper_034>
+
+use crate::{
+    cart::Cart,
+    common::{Clock, Regional, Reset},
+    mapper::{ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mem::MemBanks,
+    ppu::Mirroring,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub enum BnromRevision {
+    /// `BNROM` with 8K CHR-RAM and PRG-ROM bank select at $8000-$FFFF.
+    Bnrom,
+    /// `NINA-001` with 8K PRG-RAM and bank selects at $7FFD-$7FFF.
+    Nina001,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct Bnrom {
+    revision: BnromRevision,
+    mirroring: Mirroring,
+    chr_banks: MemBanks,
+    prg_rom_banks: MemBanks,
+}
+
+impl Bnrom {
+    const PRG_ROM_WINDOW: usize = 32 * 1024;
+    const CHR_WINDOW: usize = 4 * 1024;
+    const CHR_RAM_SIZE: usize = 8 * 1024;
+    const PRG_RAM_SIZE: usize = 8 * 1024;
+
+    pub fn load(cart: &mut Cart) -> Mapper {
+        // Submappers distinguish the boards in NES 2.0 headers, otherwise only NINA-001 has
+        // more than 8K of CHR-ROM
+        let revision = match cart.submapper_num() {
+            1 => BnromRevision::Nina001,
+            2 => BnromRevision::Bnrom,
+            _ if cart.chr_len() > Self::CHR_RAM_SIZE => BnromRevision::Nina001,
+            _ => BnromRevision::Bnrom,
+        };
+        if !cart.has_chr() {
+            cart.add_chr_ram(Self::CHR_RAM_SIZE);
+        }
+        if revision == BnromRevision::Nina001 && !cart.has_prg_ram() {
+            cart.add_prg_ram(Self::PRG_RAM_SIZE);
+        }
+        let bnrom = Self {
+            revision,
+            mirroring: cart.mirroring(),
+            chr_banks: MemBanks::new(0x0000, 0x1FFF, cart.chr_len(), Self::CHR_WINDOW),
+            prg_rom_banks: MemBanks::new(0x8000, 0xFFFF, cart.prg_rom.len(), Self::PRG_ROM_WINDOW),
+        };
+        bnrom.into()
+    }
+}
+
+impl Mapped for Bnrom {
+    #[inline]
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    #[inline]
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+}
+
+impl ExpansionAudio for Bnrom {}
+
+impl MemMap for Bnrom {
+    // PPU $0000..=$0FFF 4K CHR-ROM Bank Switchable (NINA-001) or 8K CHR-RAM Fixed (BNROM)
+    // PPU $1000..=$1FFF 4K CHR-ROM Bank Switchable (NINA-001)
+    // CPU $6000..=$7FFF 8K PRG-RAM (NINA-001)
+    // CPU $8000..=$FFFF 32K PRG-ROM Bank Switchable
+
+    fn map_peek(&self, addr: u16) -> MappedRead {
+        match addr {
+            0x0000..=0x1FFF => MappedRead::Chr(self.chr_banks.translate(addr)),
+            0x6000..=0x7FFF if self.revision == BnromRevision::Nina001 => {
+                MappedRead::PrgRam((addr & 0x1FFF).into())
+            }
+            0x8000..=0xFFFF => MappedRead::PrgRom(self.prg_rom_banks.translate(addr)),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, val: u8) -> MappedWrite {
+        match (self.revision, addr) {
+            (_, 0x0000..=0x1FFF) => MappedWrite::Chr(self.chr_banks.translate(addr), val),
+            (BnromRevision::Bnrom, 0x8000..=0xFFFF) => {
+                self.prg_rom_banks.set(0, val.into());
+                MappedWrite::None
+            }
+            (BnromRevision::Nina001, 0x6000..=0x7FFF) => {
+                match addr {
+                    0x7FFD => self.prg_rom_banks.set(0, (val & 0x01).into()),
+                    0x7FFE => self.chr_banks.set(0, (val & 0x0F).into()),
+                    0x7FFF => self.chr_banks.set(1, (val & 0x0F).into()),
+                    _ => (),
+                }
+                // Bank registers overlap PRG-RAM, so writes go to both
+                MappedWrite::PrgRam((addr & 0x1FFF).into(), val)
+            }
+            _ => MappedWrite::None,
+        }
+    }
+}
+
+impl Clock for Bnrom {}
+impl Regional for Bnrom {}
+impl Reset for Bnrom {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mem::{Access, Mem},
+        ppu::bus::PpuBus,
+    };
+
+    #[test]
+    fn chr_ram_writes() {
+        let mut cart = Cart::empty();
+        cart.chr_rom = vec![];
+        cart.prg_rom = vec![0x00; Bnrom::PRG_ROM_WINDOW];
+        let mapper = Bnrom::load(&mut cart);
+        let mut ppu_bus = PpuBus::new();
+        ppu_bus.load_chr_ram(cart.chr_ram);
+        ppu_bus.load_mapper(mapper);
+
+        ppu_bus.write(0x0010, 0xAB, Access::Write);
+        ppu_bus.write(0x1FFF, 0xCD, Access::Write);
+        assert_eq!(ppu_bus.peek(0x0010, Access::Dummy), 0xAB);
+        assert_eq!(ppu_bus.peek(0x1FFF, Access::Dummy), 0xCD);
+        assert_eq!(ppu_bus.chr_ram()[0x0010], 0xAB);
+    }
+}
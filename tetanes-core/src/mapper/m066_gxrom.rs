@@ -5,7 +5,7 @@
 use crate::{
     cart::Cart,
     common::{Clock, Regional, Reset},
-    mapper::{Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mapper::{ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
     mem::MemBanks,
     ppu::Mirroring,
 };
@@ -70,6 +70,8 @@ impl Mapped for Gxrom {
     }
 }
 
+impl ExpansionAudio for Gxrom {}
+
 impl Clock for Gxrom {}
 impl Regional for Gxrom {}
 impl Reset for Gxrom {}
@@ -0,0 +1,524 @@
+//! `Sunsoft FME-7` and `Sunsoft 5B` (Mapper 069)
+//!
+//! <https://www.nesdev.org/wiki/Sunsoft_FME-7>
+//! <https://www.nesdev.org/wiki/Sunsoft_5B_audio>
+
+use crate::{
+    cart::Cart,
+    common::{Clock, Kind, Regional, Reset},
+    mapper::{AudioChip, ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mem::MemBanks,
+    ppu::Mirroring,
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct SunsoftFme7 {
+    mirroring: Mirroring,
+    command: u8,
+    prg_ram_enabled: bool,
+    prg_ram_selected: bool,
+    irq_enabled: bool,
+    irq_counter_enabled: bool,
+    irq_counter: u16,
+    irq_pending: bool,
+    audio: Sunsoft5b,
+    chr_banks: MemBanks,
+    prg_ram_banks: MemBanks,
+    prg_rom_ram_banks: MemBanks,
+    prg_rom_banks: MemBanks,
+}
+
+impl SunsoftFme7 {
+    const PRG_RAM_SIZE: usize = 8 * 1024;
+    const CHR_RAM_SIZE: usize = 8 * 1024;
+    const PRG_WINDOW: usize = 8 * 1024;
+    const CHR_WINDOW: usize = 1024;
+
+    pub fn load(cart: &mut Cart) -> Mapper {
+        if !cart.has_prg_ram() {
+            cart.add_prg_ram(Self::PRG_RAM_SIZE);
+        }
+        if !cart.has_chr() {
+            cart.add_chr_ram(Self::CHR_RAM_SIZE);
+        }
+        let mut fme7 = Self {
+            mirroring: cart.mirroring(),
+            command: 0x00,
+            prg_ram_enabled: false,
+            prg_ram_selected: false,
+            irq_enabled: false,
+            irq_counter_enabled: false,
+            irq_counter: 0x0000,
+            irq_pending: false,
+            audio: Sunsoft5b::new(),
+            chr_banks: MemBanks::new(0x0000, 0x1FFF, cart.chr_len(), Self::CHR_WINDOW),
+            prg_ram_banks: MemBanks::new(0x6000, 0x7FFF, cart.prg_ram.len(), Self::PRG_WINDOW),
+            prg_rom_ram_banks: MemBanks::new(0x6000, 0x7FFF, cart.prg_rom.len(), Self::PRG_WINDOW),
+            prg_rom_banks: MemBanks::new(0x8000, 0xFFFF, cart.prg_rom.len(), Self::PRG_WINDOW),
+        };
+        let last_bank = fme7.prg_rom_banks.last();
+        fme7.prg_rom_banks.set(3, last_bank);
+        fme7.into()
+    }
+
+    fn write_parameter(&mut self, val: u8) {
+        match self.command {
+            0x0..=0x7 => self.chr_banks.set(self.command.into(), val.into()),
+            0x8 => {
+                // [ERBB BBBB]
+                //  ||++-++++- Select 8K bank at $6000-$7FFF
+                //  |+-------- Select PRG-RAM instead of PRG-ROM
+                //  +--------- Enable PRG-RAM
+                self.prg_ram_enabled = val & 0x80 == 0x80;
+                self.prg_ram_selected = val & 0x40 == 0x40;
+                let bank = (val & 0x3F).into();
+                if self.prg_ram_selected {
+                    self.prg_ram_banks.set(0, bank);
+                } else {
+                    self.prg_rom_ram_banks.set(0, bank);
+                }
+            }
+            0x9..=0xB => {
+                let slot = (self.command - 0x9).into();
+                self.prg_rom_banks.set(slot, (val & 0x3F).into());
+            }
+            0xC => {
+                self.mirroring = match val & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenA,
+                    _ => Mirroring::SingleScreenB,
+                };
+            }
+            0xD => {
+                // [C... ...T]
+                //  |       +- Enable IRQ
+                //  +--------- Enable IRQ counter decrement
+                self.irq_enabled = val & 0x01 == 0x01;
+                self.irq_counter_enabled = val & 0x80 == 0x80;
+                self.irq_pending = false;
+            }
+            0xE => self.irq_counter = (self.irq_counter & 0xFF00) | u16::from(val),
+            0xF => self.irq_counter = (self.irq_counter & 0x00FF) | (u16::from(val) << 8),
+            _ => unreachable!("command is 4 bits"),
+        }
+    }
+}
+
+impl Mapped for SunsoftFme7 {
+    #[inline]
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    #[inline]
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    #[inline]
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+}
+
+impl MemMap for SunsoftFme7 {
+    // PPU $0000..=$1FFF Eight 1K switchable CHR banks
+    //
+    // CPU $6000..=$7FFF 8K switchable PRG-ROM or PRG-RAM bank
+    // CPU $8000..=$9FFF 8K switchable PRG-ROM bank
+    // CPU $A000..=$BFFF 8K switchable PRG-ROM bank
+    // CPU $C000..=$DFFF 8K switchable PRG-ROM bank
+    // CPU $E000..=$FFFF 8K PRG-ROM bank, fixed to the last bank
+
+    fn map_peek(&self, addr: u16) -> MappedRead {
+        match addr {
+            0x0000..=0x1FFF => MappedRead::Chr(self.chr_banks.translate(addr)),
+            0x6000..=0x7FFF if self.prg_ram_selected => {
+                if self.prg_ram_enabled {
+                    MappedRead::PrgRam(self.prg_ram_banks.translate(addr))
+                } else {
+                    MappedRead::None
+                }
+            }
+            0x6000..=0x7FFF => MappedRead::PrgRom(self.prg_rom_ram_banks.translate(addr)),
+            0x8000..=0xFFFF => MappedRead::PrgRom(self.prg_rom_banks.translate(addr)),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, val: u8) -> MappedWrite {
+        match addr {
+            0x0000..=0x1FFF => return MappedWrite::Chr(self.chr_banks.translate(addr), val),
+            0x6000..=0x7FFF if self.prg_ram_selected && self.prg_ram_enabled => {
+                return MappedWrite::PrgRam(self.prg_ram_banks.translate(addr), val);
+            }
+            0x8000..=0x9FFF => self.command = val & 0x0F,
+            0xA000..=0xBFFF => self.write_parameter(val),
+            0xC000..=0xDFFF => self.audio.write_register(val),
+            0xE000..=0xFFFF => self.audio.write_data(val),
+            _ => (),
+        }
+        MappedWrite::None
+    }
+}
+
+impl ExpansionAudio for SunsoftFme7 {
+    fn audio_chip(&self) -> Option<AudioChip> {
+        Some(AudioChip::Sunsoft5b)
+    }
+
+    #[inline]
+    #[must_use]
+    fn output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+impl Clock for SunsoftFme7 {
+    fn clock(&mut self) -> usize {
+        if self.irq_counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.clock();
+        1
+    }
+}
+
+impl Reset for SunsoftFme7 {
+    fn reset(&mut self, kind: Kind) {
+        self.irq_enabled = false;
+        self.irq_counter_enabled = false;
+        self.irq_pending = false;
+        self.audio.reset(kind);
+    }
+}
+
+impl Regional for SunsoftFme7 {}
+
+/// Volume levels for each 4-bit volume, 3dB apart.
+static VOLUME_TABLE: Lazy<[f32; 16]> = Lazy::new(|| {
+    let mut table = [0.0; 16];
+    for (volume, level) in table.iter_mut().enumerate().skip(1) {
+        *level = 10f32.powf((volume as f32 - 15.0) * 3.0 / 20.0);
+    }
+    table
+});
+
+/// A square wave tone generator with a 12-bit period.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+struct Tone {
+    period: u16,
+    timer: u16,
+    out: bool,
+}
+
+impl Tone {
+    fn clock(&mut self) {
+        self.timer += 1;
+        if self.timer >= self.period.max(1) {
+            self.timer = 0;
+            self.out = !self.out;
+        }
+    }
+}
+
+/// A 16-step volume envelope shared by all channels.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+struct Envelope {
+    period: u16,
+    timer: u16,
+    step: u8,
+    attack: bool,
+    alternate: bool,
+    hold: bool,
+    holding: bool,
+}
+
+impl Envelope {
+    const LAST_STEP: u8 = 15;
+
+    fn write_shape(&mut self, val: u8) {
+        // [CAtH]
+        //  |||+- Hold at the end of the first cycle
+        //  ||+-- Alternate direction each cycle
+        //  |+--- Attack, ramping up instead of down
+        //  +---- Continue after the first cycle
+        let cont = val & 0x08 == 0x08;
+        self.attack = val & 0x04 == 0x04;
+        self.alternate = val & 0x02 == 0x02;
+        // Without continue, the envelope drops to 0 and holds after the first cycle
+        self.hold = val & 0x01 == 0x01 || !cont;
+        if !cont {
+            self.alternate = self.attack;
+        }
+        self.step = 0;
+        self.timer = 0;
+        self.holding = false;
+    }
+
+    #[inline]
+    #[must_use]
+    const fn volume(&self) -> u8 {
+        if self.attack {
+            self.step
+        } else {
+            Self::LAST_STEP - self.step
+        }
+    }
+
+    fn clock(&mut self) {
+        if self.holding {
+            return;
+        }
+        self.timer += 1;
+        if self.timer < self.period.max(1) {
+            return;
+        }
+        self.timer = 0;
+        if self.step < Self::LAST_STEP {
+            self.step += 1;
+            return;
+        }
+        if self.alternate {
+            self.attack = !self.attack;
+        }
+        if self.hold {
+            self.holding = true;
+        } else {
+            self.step = 0;
+        }
+    }
+}
+
+/// The `Sunsoft 5B` sound chip, a variant of the `YM2149F` (`AY-3-8910`) with three square wave
+/// channels, a noise generator and a shared volume envelope.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct Sunsoft5b {
+    register: u8,
+    write_enabled: bool,
+    tones: [Tone; 3],
+    noise_period: u8,
+    noise_timer: u8,
+    noise_shift: u32,
+    // Tone and noise disable for each channel
+    tone_disabled: [bool; 3],
+    noise_disabled: [bool; 3],
+    volumes: [u8; 3],
+    envelope_enabled: [bool; 3],
+    envelope: Envelope,
+    divider: u8,
+    out: f32,
+}
+
+impl Default for Sunsoft5b {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sunsoft5b {
+    /// Tone, noise and envelope generators are clocked every 16 CPU cycles.
+    const CLOCK_DIVIDER: u8 = 16;
+
+    /// Scales each channel to roughly match the level of an APU pulse channel.
+    const OUTPUT_SCALE: f32 = 0.12;
+
+    fn new() -> Self {
+        Self {
+            register: 0x00,
+            write_enabled: true,
+            tones: Default::default(),
+            noise_period: 0,
+            noise_timer: 0,
+            noise_shift: 0x0001,
+            tone_disabled: [true; 3],
+            noise_disabled: [true; 3],
+            volumes: [0; 3],
+            envelope_enabled: [false; 3],
+            envelope: Envelope::default(),
+            divider: 0,
+            out: 0.0,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    fn output(&self) -> f32 {
+        self.out * Self::OUTPUT_SCALE
+    }
+
+    fn write_register(&mut self, val: u8) {
+        // Writes with the upper bits set are ignored by the data port
+        self.register = val & 0x0F;
+        self.write_enabled = val & 0xF0 == 0x00;
+    }
+
+    fn write_data(&mut self, val: u8) {
+        if !self.write_enabled {
+            return;
+        }
+        match self.register {
+            0x0 | 0x2 | 0x4 => {
+                let tone = &mut self.tones[(self.register >> 1) as usize];
+                tone.period = (tone.period & 0x0F00) | u16::from(val);
+            }
+            0x1 | 0x3 | 0x5 => {
+                let tone = &mut self.tones[(self.register >> 1) as usize];
+                tone.period = (tone.period & 0x00FF) | (u16::from(val & 0x0F) << 8);
+            }
+            0x6 => self.noise_period = val & 0x1F,
+            0x7 => {
+                // [..CB Acba]
+                //    || ||||
+                //    || |+++- Disable tone for channels A, B and C
+                //    ++-+---- Disable noise for channels A, B and C
+                for channel in 0..3 {
+                    self.tone_disabled[channel] = val & (0x01 << channel) != 0x00;
+                    self.noise_disabled[channel] = val & (0x08 << channel) != 0x00;
+                }
+            }
+            0x8..=0xA => {
+                // [...E VVVV]
+                //     | ++++- Volume
+                //     +------ Use envelope instead of volume
+                let channel = (self.register - 0x8) as usize;
+                self.volumes[channel] = val & 0x0F;
+                self.envelope_enabled[channel] = val & 0x10 == 0x10;
+            }
+            0xB => self.envelope.period = (self.envelope.period & 0xFF00) | u16::from(val),
+            0xC => self.envelope.period = (self.envelope.period & 0x00FF) | (u16::from(val) << 8),
+            0xD => self.envelope.write_shape(val),
+            // I/O ports unused by the 5B
+            _ => (),
+        }
+    }
+
+    fn clock_noise(&mut self) {
+        // The noise generator runs at half the rate of the tone generators
+        self.noise_timer += 1;
+        if self.noise_timer >= 2 * self.noise_period.max(1) {
+            self.noise_timer = 0;
+            // 17-bit LFSR
+            let feedback = (self.noise_shift ^ (self.noise_shift >> 3)) & 0x01;
+            self.noise_shift = (self.noise_shift >> 1) | (feedback << 16);
+        }
+    }
+
+    fn update_output(&mut self) {
+        let noise = self.noise_shift & 0x01 == 0x01;
+        let mut out = 0.0;
+        for channel in 0..3 {
+            let tone = self.tones[channel].out || self.tone_disabled[channel];
+            let noise = noise || self.noise_disabled[channel];
+            if tone && noise {
+                let volume = if self.envelope_enabled[channel] {
+                    self.envelope.volume()
+                } else {
+                    self.volumes[channel]
+                };
+                out += VOLUME_TABLE[volume as usize];
+            }
+        }
+        self.out = out;
+    }
+}
+
+impl Clock for Sunsoft5b {
+    fn clock(&mut self) -> usize {
+        self.divider += 1;
+        if self.divider < Self::CLOCK_DIVIDER {
+            return 0;
+        }
+        self.divider = 0;
+        for tone in &mut self.tones {
+            tone.clock();
+        }
+        self.clock_noise();
+        self.envelope.clock();
+        self.update_output();
+        1
+    }
+}
+
+impl Reset for Sunsoft5b {
+    fn reset(&mut self, _kind: Kind) {
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(audio: &mut Sunsoft5b, register: u8, val: u8) {
+        audio.write_register(register);
+        audio.write_data(val);
+    }
+
+    #[test]
+    fn tone_period() {
+        let mut audio = Sunsoft5b::new();
+        write(&mut audio, 0x0, 0x04);
+        write(&mut audio, 0x7, 0x3E); // Channel A tone only
+        write(&mut audio, 0x8, 0x0F);
+
+        let mut toggles = 0;
+        let mut prev = audio.output();
+        for _ in 0..16 * 4 * 8 {
+            audio.clock();
+            if (audio.output() - prev).abs() > f32::EPSILON {
+                toggles += 1;
+            }
+            prev = audio.output();
+        }
+        // Output toggles every 4 ticks of the 16 cycle divider
+        assert_eq!(toggles, 8);
+    }
+
+    #[test]
+    fn envelope_shapes() {
+        let mut envelope = Envelope {
+            period: 1,
+            ..Envelope::default()
+        };
+        // Decay, then hold at 0
+        envelope.write_shape(0x00);
+        assert_eq!(envelope.volume(), 15);
+        for _ in 0..32 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.volume(), 0);
+
+        // Decay, then hold high
+        envelope.write_shape(0x0B);
+        for _ in 0..32 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.volume(), 15);
+
+        // Repeating attack
+        envelope.write_shape(0x0C);
+        for _ in 0..16 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.volume(), 0);
+    }
+
+    #[test]
+    fn ignores_data_with_invalid_register() {
+        let mut audio = Sunsoft5b::new();
+        write(&mut audio, 0x08, 0x0F);
+        write(&mut audio, 0x18, 0x03);
+        assert_eq!(audio.volumes[0], 0x0F);
+    }
+}
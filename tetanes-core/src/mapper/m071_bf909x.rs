@@ -5,7 +5,7 @@
 use crate::{
     cart::Cart,
     common::{Clock, Regional, Reset},
-    mapper::{Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mapper::{ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
     mem::MemBanks,
     ppu::Mirroring,
 };
@@ -62,6 +62,8 @@ impl Mapped for Bf909x {
     }
 }
 
+impl ExpansionAudio for Bf909x {}
+
 impl MemMap for Bf909x {
     // PPU $0000..=$1FFF 8K Fixed CHR-ROM Banks
     // CPU $8000..=$BFFF 16K PRG-ROM Bank Switchable
@@ -0,0 +1,189 @@
+//! `VRC7` (Mapper 085)
+//!
+//! <https://www.nesdev.org/wiki/VRC7>
+
+use crate::{
+    cart::Cart,
+    common::{Clock, Kind, Regional, Reset},
+    mapper::{
+        opll::Opll, vrc_irq::VrcIrq, AudioChip, ExpansionAudio, Mapped, MappedRead, MappedWrite,
+        Mapper, MemMap,
+    },
+    mem::MemBanks,
+    ppu::Mirroring,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct Vrc7 {
+    mirroring: Mirroring,
+    prg_ram_enabled: bool,
+    audio_silenced: bool,
+    irq: VrcIrq,
+    audio: Opll,
+    chr_banks: MemBanks,
+    prg_ram_banks: MemBanks,
+    prg_rom_banks: MemBanks,
+}
+
+impl Vrc7 {
+    const PRG_RAM_SIZE: usize = 8 * 1024;
+    const CHR_RAM_SIZE: usize = 8 * 1024;
+    const PRG_WINDOW: usize = 8 * 1024;
+    const CHR_WINDOW: usize = 1024;
+
+    /// Scales the summed `OPLL` channels to roughly match the level of an APU pulse channel.
+    const OUTPUT_SCALE: f32 = 0.12 / 4096.0;
+
+    pub fn load(cart: &mut Cart) -> Mapper {
+        if !cart.has_prg_ram() {
+            cart.add_prg_ram(Self::PRG_RAM_SIZE);
+        }
+        if !cart.has_chr() {
+            cart.add_chr_ram(Self::CHR_RAM_SIZE);
+        }
+        let mut vrc7 = Self {
+            mirroring: cart.mirroring(),
+            prg_ram_enabled: false,
+            audio_silenced: false,
+            irq: VrcIrq::default(),
+            audio: Opll::new(),
+            chr_banks: MemBanks::new(0x0000, 0x1FFF, cart.chr_len(), Self::CHR_WINDOW),
+            prg_ram_banks: MemBanks::new(0x6000, 0x7FFF, cart.prg_ram.len(), Self::PRG_RAM_SIZE),
+            prg_rom_banks: MemBanks::new(0x8000, 0xFFFF, cart.prg_rom.len(), Self::PRG_WINDOW),
+        };
+        let last_bank = vrc7.prg_rom_banks.last();
+        vrc7.prg_rom_banks.set(3, last_bank);
+        vrc7.into()
+    }
+}
+
+impl Mapped for Vrc7 {
+    #[inline]
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+
+    #[inline]
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    #[inline]
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+}
+
+impl MemMap for Vrc7 {
+    // PPU $0000..=$1FFF Eight 1K switchable CHR banks
+    //
+    // CPU $6000..=$7FFF 8K PRG-RAM bank, fixed
+    // CPU $8000..=$9FFF 8K switchable PRG-ROM bank
+    // CPU $A000..=$BFFF 8K switchable PRG-ROM bank
+    // CPU $C000..=$DFFF 8K switchable PRG-ROM bank
+    // CPU $E000..=$FFFF 8K PRG-ROM bank, fixed to the last bank
+
+    fn map_peek(&self, addr: u16) -> MappedRead {
+        match addr {
+            0x0000..=0x1FFF => MappedRead::Chr(self.chr_banks.translate(addr)),
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                MappedRead::PrgRam(self.prg_ram_banks.translate(addr))
+            }
+            0x8000..=0xFFFF => MappedRead::PrgRom(self.prg_rom_banks.translate(addr)),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn map_write(&mut self, mut addr: u16, val: u8) -> MappedWrite {
+        match addr {
+            0x0000..=0x1FFF => return MappedWrite::Chr(self.chr_banks.translate(addr), val),
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                return MappedWrite::PrgRam(self.prg_ram_banks.translate(addr), val);
+            }
+            _ => (),
+        }
+
+        // VRC7a (Lagrange Point) selects registers with A4 and VRC7b (Tiny Toon Adventures 2)
+        // with A3. Normalize to A3, except for the audio data register at $9030.
+        if addr & 0x10 == 0x10 && addr & 0xF030 != 0x9030 {
+            addr = (addr | 0x08) & !0x10;
+        }
+
+        match addr & 0xF038 {
+            0x8000 => self.prg_rom_banks.set(0, (val & 0x3F).into()),
+            0x8008 => self.prg_rom_banks.set(1, (val & 0x3F).into()),
+            0x9000 => self.prg_rom_banks.set(2, (val & 0x3F).into()),
+            0x9008 => self.audio.write_address(val),
+            0x9030 => self.audio.write_data(val),
+            0xA000 => self.chr_banks.set(0, val.into()),
+            0xA008 => self.chr_banks.set(1, val.into()),
+            0xB000 => self.chr_banks.set(2, val.into()),
+            0xB008 => self.chr_banks.set(3, val.into()),
+            0xC000 => self.chr_banks.set(4, val.into()),
+            0xC008 => self.chr_banks.set(5, val.into()),
+            0xD000 => self.chr_banks.set(6, val.into()),
+            0xD008 => self.chr_banks.set(7, val.into()),
+            0xE000 => {
+                // [RS.. ..MM]
+                //  ||     ||
+                //  ||     ++- Mirroring (0: vertical, 1: horizontal, 2: one-screen A, 3: one-screen B)
+                //  |+-------- Silence and reset expansion audio
+                //  +--------- PRG-RAM enable
+                self.mirroring = match val & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenA,
+                    _ => Mirroring::SingleScreenB,
+                };
+                self.audio_silenced = val & 0x40 == 0x40;
+                if self.audio_silenced {
+                    self.audio.reset(Kind::Soft);
+                }
+                self.prg_ram_enabled = val & 0x80 == 0x80;
+            }
+            0xE008 => self.irq.write_reload(val),
+            0xF000 => self.irq.write_control(val),
+            0xF008 => self.irq.acknowledge(),
+            _ => (),
+        }
+        MappedWrite::None
+    }
+}
+
+impl ExpansionAudio for Vrc7 {
+    fn audio_chip(&self) -> Option<AudioChip> {
+        Some(AudioChip::Vrc7)
+    }
+
+    #[inline]
+    #[must_use]
+    fn output(&self) -> f32 {
+        if self.audio_silenced {
+            0.0
+        } else {
+            self.audio.output() as f32 * Self::OUTPUT_SCALE
+        }
+    }
+}
+
+impl Clock for Vrc7 {
+    fn clock(&mut self) -> usize {
+        self.irq.clock();
+        if !self.audio_silenced {
+            self.audio.clock();
+        }
+        1
+    }
+}
+
+impl Reset for Vrc7 {
+    fn reset(&mut self, kind: Kind) {
+        self.irq.reset(kind);
+        self.audio.reset(kind);
+        self.audio_silenced = false;
+    }
+}
+
+impl Regional for Vrc7 {}
@@ -0,0 +1,268 @@
+//! `NES-EVENT` (Mapper 105)
+//!
+//! Board used by Nintendo World Championships 1990. An `MMC1` with PRG-ROM split across two
+//! 128K chips and a CPU cycle timer that raises an IRQ once the time set by the DIP switches
+//! runs out.
+//!
+//! <https://www.nesdev.org/wiki/NES-EVENT>
+
+use crate::{
+    cart::Cart,
+    common::{Clock, Kind, Regional, Reset},
+    mapper::{ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mem::MemBanks,
+    ppu::Mirroring,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct NesEvent {
+    write_just_occurred: u8,
+    shift_register: u8, // $8000-$FFFF - 5 bit shift register
+    control: u8,        // $8000-$9FFF
+    chr0: u8,           // $A000-$BFFF - Timer and PRG chip select
+    prg: u8,            // $E000-$FFFF
+    init_state: u8,
+    dip_switches: u8,
+    timer: u32,
+    irq_pending: bool,
+    mirroring: Mirroring,
+    prg_rom_banks: MemBanks,
+}
+
+impl NesEvent {
+    const PRG_ROM_WINDOW: usize = 16 * 1024;
+    const PRG_RAM_SIZE: usize = 8 * 1024;
+    const CHR_RAM_SIZE: usize = 8 * 1024;
+
+    const SHIFT_REG_RESET: u8 = 0x80; // Reset shift register when bit 7 is set
+    const DEFAULT_SHIFT_REGISTER: u8 = 0x10; // 0b10000 the 1 is used to tell when register is full
+    const MIRRORING_MASK: u8 = 0x03; // 0b00011
+    const PRG_MODE_MASK: u8 = 0x0C; // 0b01100
+    const DEFAULT_PRG_MODE: u8 = 0x0C; // Mode 3, 16k Fixed Last
+    const PRG_RAM_DISABLED: u8 = 0x10; // 0b10000
+
+    const TIMER_RESET: u8 = 0x10; // 0b10000
+    const MMC1_CHIP_SELECT: u8 = 0x08; // 0b01000
+    const BANK_32K_MASK: u8 = 0x06; // 0b00110
+    const CPU_CLOCK_RATE: f32 = 1_789_773.0; // NES-EVENT was only released for NTSC
+
+    /// DIP switch setting used by default, giving a little over six minutes.
+    pub const DEFAULT_DIP_SWITCHES: u8 = 0x04;
+
+    pub fn load(cart: &mut Cart) -> Mapper {
+        if !cart.has_prg_ram() {
+            cart.add_prg_ram(Self::PRG_RAM_SIZE);
+        }
+        if !cart.has_chr() {
+            cart.add_chr_ram(Self::CHR_RAM_SIZE);
+        }
+        let mut event = Self {
+            write_just_occurred: 0x00,
+            shift_register: Self::DEFAULT_SHIFT_REGISTER,
+            control: Self::DEFAULT_PRG_MODE,
+            chr0: Self::TIMER_RESET,
+            prg: 0x00,
+            init_state: 0,
+            dip_switches: Self::DEFAULT_DIP_SWITCHES,
+            timer: 0,
+            irq_pending: false,
+            mirroring: Mirroring::SingleScreenA,
+            prg_rom_banks: MemBanks::new(0x8000, 0xFFFF, cart.prg_rom.len(), Self::PRG_ROM_WINDOW),
+        };
+        event.update_banks();
+        event.into()
+    }
+
+    /// CPU cycles until the timer IRQ for a DIP switch setting.
+    #[must_use]
+    pub const fn timer_cycles(dip_switches: u8) -> u32 {
+        0x2000_0000 | (((dip_switches & 0x0F) as u32) << 25)
+    }
+
+    /// Seconds until the timer IRQ for a DIP switch setting, from about 5:00 for `0x00` to
+    /// 9:41 for `0x0F`.
+    #[must_use]
+    pub fn timer_seconds(dip_switches: u8) -> f32 {
+        Self::timer_cycles(dip_switches) as f32 / Self::CPU_CLOCK_RATE
+    }
+
+    fn update_banks(&mut self) {
+        self.mirroring = match self.control & Self::MIRRORING_MASK {
+            0 => Mirroring::SingleScreenA,
+            1 => Mirroring::SingleScreenB,
+            2 => Mirroring::Vertical,
+            3 => Mirroring::Horizontal,
+            _ => unreachable!("impossible mirroring mode"),
+        };
+
+        // PRG-ROM is locked to the first 32K until the timer reset bit is cleared and set again
+        if self.init_state == 0 && self.chr0 & Self::TIMER_RESET == 0 {
+            self.init_state = 1;
+        } else if self.init_state == 1 && self.chr0 & Self::TIMER_RESET != 0 {
+            self.init_state = 2;
+        }
+        if self.chr0 & Self::TIMER_RESET != 0 {
+            self.timer = 0;
+            self.irq_pending = false;
+        }
+
+        if self.init_state < 2 {
+            self.prg_rom_banks.set_range(0, 1, 0);
+        } else if self.chr0 & Self::MMC1_CHIP_SELECT == 0 {
+            // First chip, 32K banks
+            let bank = (self.chr0 & Self::BANK_32K_MASK) as usize;
+            self.prg_rom_banks.set_range(0, 1, bank);
+        } else {
+            // Second chip, MMC1 banking
+            let prg_bank = (self.prg & 0x07) as usize | 0x08;
+            match (self.control & Self::PRG_MODE_MASK) >> 2 {
+                0 | 1 => self.prg_rom_banks.set_range(0, 1, prg_bank & 0x0E),
+                2 => {
+                    self.prg_rom_banks.set(0, 0x08);
+                    self.prg_rom_banks.set(1, prg_bank);
+                }
+                3 => {
+                    self.prg_rom_banks.set(0, prg_bank);
+                    self.prg_rom_banks.set(1, 0x0F);
+                }
+                _ => unreachable!("impossible prg mode"),
+            }
+        }
+    }
+
+    #[inline]
+    const fn prg_ram_enabled(&self) -> bool {
+        self.prg & Self::PRG_RAM_DISABLED == 0
+    }
+}
+
+impl Mapped for NesEvent {
+    #[inline]
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    #[inline]
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    #[inline]
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+
+    #[inline]
+    fn set_dip_switches(&mut self, dip_switches: u8) {
+        self.dip_switches = dip_switches & 0x0F;
+    }
+}
+
+impl ExpansionAudio for NesEvent {}
+
+impl MemMap for NesEvent {
+    // PPU $0000..=$1FFF 8K CHR-RAM Bank Fixed
+    // CPU $6000..=$7FFF 8K PRG-RAM Bank
+    // CPU $8000..=$FFFF 32K PRG-ROM Bank Switchable from the first chip, or
+    //                   MMC1 16K PRG-ROM Banks from the second chip
+
+    fn map_peek(&self, addr: u16) -> MappedRead {
+        match addr {
+            0x0000..=0x1FFF => MappedRead::Chr(addr.into()),
+            0x6000..=0x7FFF if self.prg_ram_enabled() => MappedRead::PrgRam((addr & 0x1FFF).into()),
+            0x8000..=0xFFFF => MappedRead::PrgRom(self.prg_rom_banks.translate(addr)),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, val: u8) -> MappedWrite {
+        match addr {
+            0x0000..=0x1FFF => MappedWrite::Chr(addr.into(), val),
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                MappedWrite::PrgRam((addr & 0x1FFF).into(), val)
+            }
+            0x8000..=0xFFFF => {
+                // Same serial interface as MMC1, except $A000-$BFFF controls the timer and PRG
+                // chip select and $C000-$DFFF is unused.
+                //
+                // $A000-$BFFF
+                // 43210
+                // ITPP.
+                // ||++-- Select 32K PRG-ROM bank from the first chip
+                // |+---- PRG-ROM chip select (0: first chip, 32K banks; 1: second chip, MMC1 banks)
+                // +----- Timer (0: counting; 1: reset and acknowledge IRQ)
+
+                if self.write_just_occurred > 0 {
+                    return MappedWrite::None;
+                }
+                self.write_just_occurred = 2;
+                if val & Self::SHIFT_REG_RESET > 0 {
+                    self.shift_register = Self::DEFAULT_SHIFT_REGISTER;
+                    self.control |= Self::PRG_MODE_MASK;
+                    self.update_banks();
+                } else {
+                    let write = self.shift_register & 1 == 1;
+                    self.shift_register >>= 1;
+                    self.shift_register |= (val & 1) << 4;
+                    if write {
+                        match addr {
+                            0x8000..=0x9FFF => self.control = self.shift_register,
+                            0xA000..=0xBFFF => self.chr0 = self.shift_register & 0x1F,
+                            0xC000..=0xDFFF => (),
+                            0xE000..=0xFFFF => self.prg = self.shift_register & 0x1F,
+                            _ => unreachable!("impossible write"),
+                        }
+                        self.shift_register = Self::DEFAULT_SHIFT_REGISTER;
+                        self.update_banks();
+                    }
+                }
+                MappedWrite::None
+            }
+            _ => MappedWrite::None,
+        }
+    }
+}
+
+impl Clock for NesEvent {
+    fn clock(&mut self) -> usize {
+        if self.write_just_occurred > 0 {
+            self.write_just_occurred -= 1;
+        }
+        if self.chr0 & Self::TIMER_RESET == 0 {
+            self.timer = self.timer.saturating_add(1);
+            if self.timer == Self::timer_cycles(self.dip_switches) {
+                self.irq_pending = true;
+            }
+        }
+        1
+    }
+}
+
+impl Reset for NesEvent {
+    fn reset(&mut self, kind: Kind) {
+        self.shift_register = Self::DEFAULT_SHIFT_REGISTER;
+        self.control = Self::DEFAULT_PRG_MODE;
+        self.chr0 = Self::TIMER_RESET;
+        self.prg = 0x00;
+        self.init_state = 0;
+        self.update_banks();
+        if kind == Kind::Hard {
+            self.write_just_occurred = 0;
+        }
+    }
+}
+
+impl Regional for NesEvent {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timer_duration() {
+        assert!((NesEvent::timer_seconds(0x00) - 299.96).abs() < 0.01);
+        assert!((NesEvent::timer_seconds(0x0F) - 581.19).abs() < 0.01);
+    }
+}
@@ -0,0 +1,111 @@
+//! `BMC 64-in-1` (Mapper 225)
+//!
+//! Common pirate multicart board used by many 52-in-1, 64-in-1 and 72-in-1 compilations.
+//!
+//! <https://www.nesdev.org/wiki/INES_Mapper_225>
+
+use crate::{
+    cart::Cart,
+    common::{Clock, Kind, Regional, Reset},
+    mapper::{ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mem::MemBanks,
+    ppu::Mirroring,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct Bmc64in1 {
+    mirroring: Mirroring,
+    ram: [u8; 4],
+    chr_banks: MemBanks,
+    prg_rom_banks: MemBanks,
+}
+
+impl Bmc64in1 {
+    const PRG_ROM_WINDOW: usize = 16 * 1024;
+    const CHR_WINDOW: usize = 8 * 1024;
+
+    pub fn load(cart: &mut Cart) -> Mapper {
+        let mut bmc = Self {
+            mirroring: Mirroring::Vertical,
+            ram: [0x00; 4],
+            chr_banks: MemBanks::new(0x0000, 0x1FFF, cart.chr_len(), Self::CHR_WINDOW),
+            prg_rom_banks: MemBanks::new(0x8000, 0xFFFF, cart.prg_rom.len(), Self::PRG_ROM_WINDOW),
+        };
+        bmc.update_banks(0x8000);
+        bmc.into()
+    }
+
+    fn update_banks(&mut self, addr: u16) {
+        let addr = addr as usize;
+        self.mirroring = if addr & 0x2000 == 0x2000 {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        };
+        let high = (addr >> 8) & 0x40;
+        let prg_bank = high | ((addr >> 6) & 0x3F);
+        if addr & 0x1000 == 0x1000 {
+            self.prg_rom_banks.set(0, prg_bank);
+            self.prg_rom_banks.set(1, prg_bank);
+        } else {
+            self.prg_rom_banks.set_range(0, 1, prg_bank & !0x01);
+        }
+        self.chr_banks.set(0, high | (addr & 0x3F));
+    }
+}
+
+impl Mapped for Bmc64in1 {
+    #[inline]
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    #[inline]
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+}
+
+impl ExpansionAudio for Bmc64in1 {}
+
+impl MemMap for Bmc64in1 {
+    // PPU $0000..=$1FFF 8K CHR-ROM Bank Switchable
+    // CPU $5800..=$5FFF Four 4-bit RAM registers
+    // CPU $8000..=$FFFF 16K or 32K PRG-ROM Bank Switchable
+
+    fn map_peek(&self, addr: u16) -> MappedRead {
+        match addr {
+            0x0000..=0x1FFF => MappedRead::Chr(self.chr_banks.translate(addr)),
+            0x5800..=0x5FFF => MappedRead::Data(self.ram[(addr & 0x03) as usize] & 0x0F),
+            0x8000..=0xFFFF => MappedRead::PrgRom(self.prg_rom_banks.translate(addr)),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, val: u8) -> MappedWrite {
+        // Bank Select $8000-$FFFF
+        // A~[.HMO PPPP PPCC CCCC]
+        //     ||| |||| ||++-++++- Select 8K CHR-ROM bank
+        //     ||| ++++-++-------- Select 16K PRG-ROM bank (low bit ignored in 32K mode)
+        //     ||+---------------- PRG-ROM bank mode (0: 32K; 1: 16K)
+        //     |+----------------- Mirroring (0: vertical; 1: horizontal)
+        //     +------------------ High PRG-ROM and CHR-ROM bank bit
+        match addr {
+            0x5800..=0x5FFF => self.ram[(addr & 0x03) as usize] = val & 0x0F,
+            0x8000..=0xFFFF => self.update_banks(addr),
+            _ => (),
+        }
+        MappedWrite::None
+    }
+}
+
+impl Reset for Bmc64in1 {
+    fn reset(&mut self, _kind: Kind) {
+        self.update_banks(0x8000);
+    }
+}
+
+impl Clock for Bmc64in1 {}
+impl Regional for Bmc64in1 {}
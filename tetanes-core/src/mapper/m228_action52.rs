@@ -0,0 +1,121 @@
+//! `Action 52` (Mapper 228)
+//!
+//! Board used by Active Enterprises for Action 52 and Cheetahmen II.
+//!
+//! <https://www.nesdev.org/wiki/INES_Mapper_228>
+
+use crate::{
+    cart::Cart,
+    common::{Clock, Kind, Regional, Reset},
+    mapper::{ExpansionAudio, Mapped, MappedRead, MappedWrite, Mapper, MemMap},
+    mem::MemBanks,
+    ppu::Mirroring,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct Action52 {
+    mirroring: Mirroring,
+    ram: [u8; 4],
+    chr_banks: MemBanks,
+    prg_rom_banks: MemBanks,
+}
+
+impl Action52 {
+    const PRG_ROM_WINDOW: usize = 16 * 1024;
+    const CHR_WINDOW: usize = 8 * 1024;
+    const CHIP_BANKS: usize = 32; // 512K PRG-ROM chips
+
+    pub fn load(cart: &mut Cart) -> Mapper {
+        let mut action52 = Self {
+            mirroring: Mirroring::Vertical,
+            ram: [0x00; 4],
+            chr_banks: MemBanks::new(0x0000, 0x1FFF, cart.chr_len(), Self::CHR_WINDOW),
+            prg_rom_banks: MemBanks::new(0x8000, 0xFFFF, cart.prg_rom.len(), Self::PRG_ROM_WINDOW),
+        };
+        action52.update_banks(0x8000, 0x00);
+        action52.into()
+    }
+
+    fn update_banks(&mut self, addr: u16, val: u8) {
+        let addr = addr as usize;
+        self.mirroring = if addr & 0x2000 == 0x2000 {
+            Mirroring::Horizontal
+        } else {
+            Mirroring::Vertical
+        };
+
+        // Action 52 has three 512K chips with the third wired to chip select 3, while
+        // chip select 2 is empty
+        let chip = match (addr >> 11) & 0x03 {
+            3 => 2,
+            chip => chip,
+        };
+        let bank = chip * Self::CHIP_BANKS + ((addr >> 6) & 0x1F);
+        if addr & 0x20 == 0x20 {
+            self.prg_rom_banks.set(0, bank);
+            self.prg_rom_banks.set(1, bank);
+        } else {
+            self.prg_rom_banks.set_range(0, 1, bank & !0x01);
+        }
+        self.chr_banks
+            .set(0, ((addr & 0x0F) << 2) | (val & 0x03) as usize);
+    }
+}
+
+impl Mapped for Action52 {
+    #[inline]
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    #[inline]
+    fn set_mirroring(&mut self, mirroring: Mirroring) {
+        self.mirroring = mirroring;
+    }
+}
+
+impl ExpansionAudio for Action52 {}
+
+impl MemMap for Action52 {
+    // PPU $0000..=$1FFF 8K CHR-ROM Bank Switchable
+    // CPU $4020..=$5FFF Four 4-bit RAM registers
+    // CPU $8000..=$FFFF 16K or 32K PRG-ROM Bank Switchable
+
+    fn map_peek(&self, addr: u16) -> MappedRead {
+        match addr {
+            0x0000..=0x1FFF => MappedRead::Chr(self.chr_banks.translate(addr)),
+            0x4020..=0x5FFF => MappedRead::Data(self.ram[(addr & 0x03) as usize] & 0x0F),
+            0x8000..=0xFFFF => MappedRead::PrgRom(self.prg_rom_banks.translate(addr)),
+            _ => MappedRead::None,
+        }
+    }
+
+    fn map_write(&mut self, addr: u16, val: u8) -> MappedWrite {
+        // Bank Select $8000-$FFFF
+        // A~[..MH HPPP PPO. CCCC]
+        //      || |||| ||   ++++- High CHR-ROM bank bits
+        //      || |||| |+-------- PRG-ROM bank mode (0: 32K; 1: 16K)
+        //      || ++++-+--------- Select 16K PRG-ROM bank within the chip
+        //      ++---------------- Select PRG-ROM chip
+        //      +----------------- Mirroring (0: vertical; 1: horizontal)
+        // D~[.... ..CC]
+        //           ++- Low CHR-ROM bank bits
+        match addr {
+            0x4020..=0x5FFF => self.ram[(addr & 0x03) as usize] = val & 0x0F,
+            0x8000..=0xFFFF => self.update_banks(addr, val),
+            _ => (),
+        }
+        MappedWrite::None
+    }
+}
+
+impl Reset for Action52 {
+    fn reset(&mut self, _kind: Kind) {
+        self.update_banks(0x8000, 0x00);
+    }
+}
+
+impl Clock for Action52 {}
+impl Regional for Action52 {}
@@ -0,0 +1,568 @@
+//! `OPLL` FM synthesis
+//!
+//! The `VRC7` contains a cut-down Yamaha YM2413 (OPLL) with six two-operator FM channels, 15
+//! built-in instruments, one custom instrument and no rhythm mode. Output is generated through
+//! the same log-sin and exponent lookup pipeline as the chip, producing one sample every 36 CPU
+//! cycles.
+//!
+//! <https://www.nesdev.org/wiki/VRC7_audio>
+
+use crate::common::{Clock, Kind, Reset};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+const CHANNEL_COUNT: usize = 6;
+/// CPU cycles per OPLL sample.
+const CLOCK_DIVIDER: u8 = 36;
+/// Phase accumulator width. The top 10 bits index the sine wave.
+const PHASE_MASK: u32 = 0x3FFFF;
+/// Envelope attenuation in 0.1875 dB steps. At this level an operator is silent.
+const MAX_ATTENUATION: u16 = 0x1FF;
+/// Samples per amplitude modulation cycle (~3.7 Hz).
+const AM_PERIOD: u16 = 13_432;
+/// Amplitude modulation depth in 0.1875 dB steps (~4.8 dB).
+const AM_DEPTH: u16 = 26;
+/// Samples per vibrato step, with 8 steps per cycle (~6.1 Hz).
+const PM_STEP_PERIOD: u16 = 1024;
+const PM_TABLE: [i32; 8] = [0, 1, 2, 1, 0, -1, -2, -1];
+/// Frequency multipliers, doubled so `0` can represent a multiplier of one half.
+const MULTIPLIERS: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+/// Key scale level attenuation by the top 4 bits of the frequency number.
+const KSL_TABLE: [i32; 16] = [
+    0, 32, 40, 45, 48, 51, 53, 55, 56, 58, 59, 60, 61, 62, 63, 64,
+];
+
+/// Built-in `VRC7` instruments 1-15. Instrument 0 is the custom instrument set by registers
+/// `$00-$07`.
+pub const VRC7_PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27], // Buzzy Bell
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12], // Guitar
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12], // Wurly
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27], // Flute
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28], // Clarinet
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4], // Synth
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07], // Trumpet
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17], // Organ
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01], // Bells
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02], // Vibes
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12], // Vibraphone
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16], // Tutti
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02], // Fretless
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6], // Synth Bass
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06], // Sweep
+];
+
+/// Quarter sine wave as `-log2(sin)` in 1/256 steps.
+static LOG_SIN: Lazy<[u16; 256]> = Lazy::new(|| {
+    let mut table = [0; 256];
+    for (i, val) in table.iter_mut().enumerate() {
+        let x = ((i as f64 + 0.5) * std::f64::consts::PI / 512.0).sin();
+        *val = (-x.log2() * 256.0).round() as u16;
+    }
+    table
+});
+
+/// Fractional part of `2^-x` for converting log attenuation back to linear amplitude.
+static EXP: Lazy<[u16; 256]> = Lazy::new(|| {
+    let mut table = [0; 256];
+    for (i, val) in table.iter_mut().enumerate() {
+        *val = (2f64.powf((255 - i) as f64 / 256.0) * 1024.0).round() as u16;
+    }
+    table
+});
+
+/// Looks up a sine wave sample for a 10-bit phase and log attenuation in 1/256 steps.
+fn wave(phase: u32, attenuation: u32, half_sine: bool) -> i32 {
+    let negative = phase & 0x200 == 0x200;
+    if negative && half_sine {
+        return 0;
+    }
+    let quarter = if phase & 0x100 == 0x100 {
+        !phase & 0xFF
+    } else {
+        phase & 0xFF
+    };
+    let level = u32::from(LOG_SIN[quarter as usize]) + attenuation;
+    let shift = level >> 8;
+    if shift > 12 {
+        return 0;
+    }
+    let magnitude = (i32::from(EXP[(level & 0xFF) as usize]) << 1) >> shift;
+    if negative {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// Envelope rate adjusted for key scaling, from 0 (stopped) to 63.
+fn effective_rate(rate: u8, key_scale: u8) -> u8 {
+    if rate == 0 {
+        0
+    } else {
+        (rate * 4 + key_scale).min(63)
+    }
+}
+
+/// Parameters for one operator, decoded from an instrument patch.
+#[derive(Debug, Copy, Clone)]
+struct OperatorPatch {
+    am: bool,
+    pm: bool,
+    sustained: bool,
+    ksr: bool,
+    mult: u8,
+    ksl: u8,
+    half_sine: bool,
+    attack: u8,
+    decay: u8,
+    sustain_level: u8,
+    release: u8,
+}
+
+impl OperatorPatch {
+    /// Decodes the modulator (`0`) or carrier (`1`) parameters from an 8-byte patch.
+    fn decode(patch: &[u8; 8], op: usize) -> Self {
+        let flags = patch[op];
+        Self {
+            am: flags & 0x80 == 0x80,
+            pm: flags & 0x40 == 0x40,
+            sustained: flags & 0x20 == 0x20,
+            ksr: flags & 0x10 == 0x10,
+            mult: flags & 0x0F,
+            ksl: patch[2 + op] >> 6,
+            half_sine: patch[3] & (0x08 << op) != 0,
+            attack: patch[4 + op] >> 4,
+            decay: patch[4 + op] & 0x0F,
+            sustain_level: patch[6 + op] >> 4,
+            release: patch[6 + op] & 0x0F,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[must_use]
+struct Operator {
+    phase: u32,
+    envelope: u16,
+    state: EnvelopeState,
+    rate_counter: u32,
+}
+
+impl Operator {
+    const fn new() -> Self {
+        Self {
+            phase: 0,
+            envelope: MAX_ATTENUATION,
+            state: EnvelopeState::Release,
+            rate_counter: 0,
+        }
+    }
+
+    fn key_on(&mut self) {
+        self.phase = 0;
+        self.rate_counter = 0;
+        self.state = EnvelopeState::Attack;
+    }
+
+    fn key_off(&mut self) {
+        self.state = EnvelopeState::Release;
+    }
+
+    /// Number of envelope steps to take this sample. Each increase of 4 in rate doubles the
+    /// speed.
+    fn envelope_steps(&mut self, rate: u8) -> u16 {
+        if rate < 4 {
+            return 0;
+        }
+        self.rate_counter += (4 + u32::from(rate & 0x03)) << (rate >> 2);
+        let steps = self.rate_counter >> 15;
+        self.rate_counter &= 0x7FFF;
+        steps as u16
+    }
+
+    fn clock_envelope(&mut self, patch: &OperatorPatch, key_scale: u8, release: u8) {
+        match self.state {
+            EnvelopeState::Attack => {
+                let rate = effective_rate(patch.attack, key_scale);
+                if rate >= 60 {
+                    self.envelope = 0;
+                } else {
+                    for _ in 0..self.envelope_steps(rate) {
+                        self.envelope = self.envelope.saturating_sub((self.envelope >> 3) + 1);
+                    }
+                }
+                if self.envelope == 0 {
+                    self.state = EnvelopeState::Decay;
+                }
+            }
+            EnvelopeState::Decay => {
+                let sustain_level = u16::from(patch.sustain_level) << 4;
+                self.envelope += self.envelope_steps(effective_rate(patch.decay, key_scale));
+                if self.envelope >= sustain_level {
+                    self.envelope = sustain_level;
+                    self.state = EnvelopeState::Sustain;
+                }
+            }
+            EnvelopeState::Sustain => {
+                // Percussive tones keep decaying at the release rate while the key is held
+                let rate = if patch.sustained { 0 } else { patch.release };
+                self.envelope += self.envelope_steps(effective_rate(rate, key_scale));
+            }
+            EnvelopeState::Release => {
+                self.envelope += self.envelope_steps(effective_rate(release, key_scale));
+            }
+        }
+        self.envelope = self.envelope.min(MAX_ATTENUATION);
+    }
+
+    #[inline]
+    fn clock_phase(&mut self, increment: u32) {
+        self.phase = (self.phase + increment) & PHASE_MASK;
+    }
+
+    /// Operator output with the phase offset by `modulation` and `attenuation` in 0.1875 dB
+    /// steps added to the envelope.
+    fn output(&self, modulation: i32, attenuation: u16, half_sine: bool) -> i32 {
+        let attenuation = self.envelope + attenuation;
+        if attenuation >= MAX_ATTENUATION {
+            return 0;
+        }
+        let phase = ((self.phase >> 8) as i32).wrapping_add(modulation) as u32 & 0x3FF;
+        wave(phase, u32::from(attenuation) << 3, half_sine)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[must_use]
+struct OpllChannel {
+    fnum: u16,
+    block: u8,
+    sustain: bool,
+    key_on: bool,
+    instrument: u8,
+    volume: u8,
+    modulator: Operator,
+    carrier: Operator,
+    feedback: [i32; 2],
+}
+
+impl OpllChannel {
+    const fn new() -> Self {
+        Self {
+            fnum: 0,
+            block: 0,
+            sustain: false,
+            key_on: false,
+            instrument: 0,
+            volume: 0,
+            modulator: Operator::new(),
+            carrier: Operator::new(),
+            feedback: [0; 2],
+        }
+    }
+
+    fn set_key_on(&mut self, key_on: bool) {
+        if key_on && !self.key_on {
+            self.modulator.key_on();
+            self.carrier.key_on();
+        } else if !key_on && self.key_on {
+            self.modulator.key_off();
+            self.carrier.key_off();
+        }
+        self.key_on = key_on;
+    }
+
+    fn key_scale(&self, ksr: bool) -> u8 {
+        let key_scale = (self.block << 1) | (self.fnum >> 8) as u8;
+        if ksr {
+            key_scale
+        } else {
+            key_scale >> 2
+        }
+    }
+
+    /// Attenuation for higher notes in 0.1875 dB steps: 0, 1.5, 3 or 6 dB per octave.
+    fn key_scale_level(&self, ksl: u8) -> u16 {
+        if ksl == 0 {
+            return 0;
+        }
+        let level =
+            (KSL_TABLE[(self.fnum >> 5) as usize] << 2) - ((8 - i32::from(self.block)) << 5);
+        if level <= 0 {
+            0
+        } else {
+            (level as u16) >> (3 - ksl)
+        }
+    }
+
+    fn phase_increment(&self, patch: &OperatorPatch, pm_step: usize) -> u32 {
+        let mut fnum = i32::from(self.fnum);
+        if patch.pm {
+            fnum += i32::from(self.fnum >> 6) * PM_TABLE[pm_step] / 2;
+        }
+        ((fnum.max(0) as u32 * MULTIPLIERS[patch.mult as usize]) << self.block) >> 2
+    }
+
+    /// Release rate after key off: slower when the channel sustain flag is set and fixed for
+    /// percussive tones.
+    fn release_rate(&self, patch: &OperatorPatch) -> u8 {
+        if self.sustain {
+            5
+        } else if patch.sustained {
+            patch.release
+        } else {
+            7
+        }
+    }
+
+    fn clock(&mut self, patch: &[u8; 8], am: u16, pm_step: usize) -> i32 {
+        let modulator = OperatorPatch::decode(patch, 0);
+        let carrier = OperatorPatch::decode(patch, 1);
+
+        self.modulator.clock_envelope(
+            &modulator,
+            self.key_scale(modulator.ksr),
+            self.release_rate(&modulator),
+        );
+        self.carrier.clock_envelope(
+            &carrier,
+            self.key_scale(carrier.ksr),
+            self.release_rate(&carrier),
+        );
+        self.modulator
+            .clock_phase(self.phase_increment(&modulator, pm_step));
+        self.carrier
+            .clock_phase(self.phase_increment(&carrier, pm_step));
+
+        let total_level = u16::from(patch[2] & 0x3F) << 2;
+        let feedback = patch[3] & 0x07;
+        let feedback = if feedback == 0 {
+            0
+        } else {
+            (self.feedback[0] + self.feedback[1]) >> (9 - feedback)
+        };
+        let modulation = self.modulator.output(
+            feedback,
+            total_level + self.key_scale_level(modulator.ksl) + if modulator.am { am } else { 0 },
+            modulator.half_sine,
+        );
+        self.feedback = [self.feedback[1], modulation];
+
+        let volume = u16::from(self.volume) << 4;
+        self.carrier.output(
+            modulation >> 1,
+            volume + self.key_scale_level(carrier.ksl) + if carrier.am { am } else { 0 },
+            carrier.half_sine,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct Opll {
+    address: u8,
+    custom_patch: [u8; 8],
+    channels: [OpllChannel; CHANNEL_COUNT],
+    am_counter: u16,
+    pm_counter: u16,
+    divider: u8,
+    out: i32,
+}
+
+impl Default for Opll {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Opll {
+    pub const fn new() -> Self {
+        Self {
+            address: 0x00,
+            custom_patch: [0x00; 8],
+            channels: [OpllChannel::new(); CHANNEL_COUNT],
+            am_counter: 0,
+            pm_counter: 0,
+            divider: 0,
+            out: 0,
+        }
+    }
+
+    /// Current output, the sum of all channels.
+    #[inline]
+    #[must_use]
+    pub const fn output(&self) -> i32 {
+        self.out
+    }
+
+    #[inline]
+    pub fn write_address(&mut self, val: u8) {
+        self.address = val;
+    }
+
+    pub fn write_data(&mut self, val: u8) {
+        let addr = self.address;
+        let channel = (addr & 0x0F) as usize;
+        match addr {
+            0x00..=0x07 => self.custom_patch[addr as usize] = val,
+            0x10..=0x15 => {
+                let channel = &mut self.channels[channel];
+                channel.fnum = (channel.fnum & 0x100) | u16::from(val);
+            }
+            0x20..=0x25 => {
+                let channel = &mut self.channels[channel];
+                channel.fnum = (u16::from(val & 0x01) << 8) | (channel.fnum & 0xFF);
+                channel.block = (val >> 1) & 0x07;
+                channel.sustain = val & 0x20 == 0x20;
+                channel.set_key_on(val & 0x10 == 0x10);
+            }
+            0x30..=0x35 => {
+                let channel = &mut self.channels[channel];
+                channel.instrument = val >> 4;
+                channel.volume = val & 0x0F;
+            }
+            _ => (),
+        }
+    }
+
+    fn patch(&self, instrument: u8) -> [u8; 8] {
+        match instrument {
+            0 => self.custom_patch,
+            _ => VRC7_PATCHES[instrument as usize - 1],
+        }
+    }
+
+    fn clock_sample(&mut self) {
+        self.am_counter = (self.am_counter + 1) % AM_PERIOD;
+        let half_period = u32::from(AM_PERIOD / 2);
+        let am_phase = u32::from(self.am_counter.min(AM_PERIOD - self.am_counter));
+        let am = (am_phase * u32::from(AM_DEPTH) / half_period) as u16;
+        self.pm_counter = (self.pm_counter + 1) % (PM_STEP_PERIOD * PM_TABLE.len() as u16);
+        let pm_step = (self.pm_counter / PM_STEP_PERIOD) as usize;
+
+        let mut out = 0;
+        for i in 0..CHANNEL_COUNT {
+            let patch = self.patch(self.channels[i].instrument);
+            out += self.channels[i].clock(&patch, am, pm_step);
+        }
+        self.out = out;
+    }
+}
+
+impl Clock for Opll {
+    fn clock(&mut self) -> usize {
+        self.divider += 1;
+        if self.divider == CLOCK_DIVIDER {
+            self.divider = 0;
+            self.clock_sample();
+            1
+        } else {
+            0
+        }
+    }
+}
+
+impl Reset for Opll {
+    fn reset(&mut self, _kind: Kind) {
+        *self = Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 49_716;
+
+    // Custom sine instrument: silent modulator, instant attack, full sustain, fast release
+    const SINE_PATCH: [u8; 8] = [0x20, 0x21, 0x3F, 0x00, 0xF0, 0xF0, 0x0F, 0x0F];
+
+    fn write_regs(opll: &mut Opll, regs: &[(u8, u8)]) {
+        for &(addr, val) in regs {
+            opll.write_address(addr);
+            opll.write_data(val);
+        }
+    }
+
+    fn samples(opll: &mut Opll, count: usize) -> Vec<i32> {
+        (0..count)
+            .map(|_| {
+                opll.clock_sample();
+                opll.output()
+            })
+            .collect()
+    }
+
+    fn peak(samples: &[i32]) -> i32 {
+        samples.iter().map(|s| s.abs()).max().unwrap_or(0)
+    }
+
+    #[test]
+    fn rom_tables() {
+        assert_eq!(LOG_SIN[0], 2137);
+        assert_eq!(LOG_SIN[255], 0);
+        assert_eq!(EXP[255], 1024);
+        assert!(EXP.windows(2).all(|w| w[0] >= w[1]));
+    }
+
+    #[test]
+    fn silent_without_key_on() {
+        let mut opll = Opll::new();
+        write_regs(&mut opll, &[(0x30, 0x10), (0x10, 0x20), (0x20, 0x09)]);
+        assert_eq!(peak(&samples(&mut opll, 1000)), 0);
+    }
+
+    #[test]
+    fn custom_patch_pitch() {
+        let mut opll = Opll::new();
+        // A4: fnum 288, block 4 on channel 0 at full volume
+        let mut regs: Vec<_> = (0..8).zip(SINE_PATCH).collect();
+        regs.extend([(0x30, 0x00), (0x10, 0x20), (0x20, 0x19)]);
+        write_regs(&mut opll, &regs);
+
+        let out = samples(&mut opll, SAMPLE_RATE);
+        let crossings = out.windows(2).filter(|w| w[0] <= 0 && w[1] > 0).count();
+        assert!((435..=439).contains(&crossings), "{crossings} Hz");
+        assert!(peak(&out) > 2000);
+    }
+
+    #[test]
+    fn key_off_releases() {
+        let mut opll = Opll::new();
+        let mut regs: Vec<_> = (0..8).zip(SINE_PATCH).collect();
+        regs.extend([(0x30, 0x00), (0x10, 0x20), (0x20, 0x19)]);
+        write_regs(&mut opll, &regs);
+        assert!(peak(&samples(&mut opll, 200)) > 0);
+
+        write_regs(&mut opll, &[(0x20, 0x09)]);
+        samples(&mut opll, 200);
+        assert_eq!(peak(&samples(&mut opll, 200)), 0);
+    }
+
+    #[test]
+    fn instrument_volume() {
+        let play = |volume: u8| {
+            let mut opll = Opll::new();
+            // Flute on channel 1
+            write_regs(
+                &mut opll,
+                &[(0x31, 0x40 | volume), (0x11, 0x20), (0x21, 0x19)],
+            );
+            peak(&samples(&mut opll, 4000))
+        };
+        let loud = play(0x00);
+        let quiet = play(0x08);
+        assert!(loud > 0);
+        assert!(quiet * 8 < loud, "loud: {loud}, quiet: {quiet}");
+    }
+}
@@ -1,6 +1,6 @@
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::str::FromStr;
+use std::{fmt, str::FromStr};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[must_use]
@@ -50,11 +50,33 @@ pub enum RamState {
     AllZeros,
     AllOnes,
     Random,
+    /// The pattern FCEUX powers up with: four bytes of `$00` followed by four bytes of `$FF`.
+    Fceux,
+    /// Random values generated from a seed, the same on every machine.
+    Seeded(u64),
 }
 
 impl RamState {
     pub const fn as_slice() -> &'static [Self] {
-        &[Self::AllZeros, Self::AllOnes, Self::Random]
+        &[
+            Self::AllZeros,
+            Self::AllOnes,
+            Self::Random,
+            Self::Fceux,
+            Self::Seeded(0),
+        ]
+    }
+
+    /// Index into `as_slice`, ignoring the seed.
+    #[must_use]
+    pub const fn index(&self) -> usize {
+        match self {
+            Self::AllZeros => 0,
+            Self::AllOnes => 1,
+            Self::Random => 2,
+            Self::Fceux => 3,
+            Self::Seeded(_) => 4,
+        }
     }
 
     #[must_use]
@@ -74,6 +96,22 @@ impl RamState {
                     *val = rng.gen_range(0x00..=0xFF);
                 }
             }
+            RamState::Fceux => {
+                for (i, val) in ram.iter_mut().enumerate() {
+                    *val = if i & 0x04 == 0x04 { 0xFF } else { 0x00 };
+                }
+            }
+            // SplitMix64, so the values never change with the `rand` version or platform
+            RamState::Seeded(seed) => {
+                let mut state = seed;
+                for val in ram {
+                    state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                    let mut z = state;
+                    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                    *val = (z ^ (z >> 31)) as u8;
+                }
+            }
         }
     }
 }
@@ -83,6 +121,8 @@ impl From<usize> for RamState {
         match value {
             0 => Self::AllZeros,
             1 => Self::AllOnes,
+            3 => Self::Fceux,
+            4 => Self::Seeded(0),
             _ => Self::Random,
         }
     }
@@ -94,6 +134,21 @@ impl AsRef<str> for RamState {
             Self::AllZeros => "All $00",
             Self::AllOnes => "All $FF",
             Self::Random => "Random",
+            Self::Fceux => "FCEUX Pattern",
+            Self::Seeded(_) => "Random with Seed",
+        }
+    }
+}
+
+/// Writes the same names `FromStr` parses.
+impl fmt::Display for RamState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AllZeros => write!(f, "all_zeros"),
+            Self::AllOnes => write!(f, "all_ones"),
+            Self::Random => write!(f, "random"),
+            Self::Fceux => write!(f, "fceux"),
+            Self::Seeded(seed) => write!(f, "seeded:{seed}"),
         }
     }
 }
@@ -105,7 +160,15 @@ impl FromStr for RamState {
             "all_zeros" => Ok(Self::AllZeros),
             "all_ones" => Ok(Self::AllOnes),
             "random" => Ok(Self::Random),
-            _ => Err("invalid RamState value. valid options: `all_zeros`, `all_ones`, or `random`"),
+            "fceux" => Ok(Self::Fceux),
+            _ => s
+                .strip_prefix("seeded:")
+                .and_then(|seed| seed.parse().ok())
+                .map(Self::Seeded)
+                .ok_or(
+                    "invalid RamState value. valid options: `all_zeros`, `all_ones`, `random`, \
+                    `fceux` or `seeded:<seed>`",
+                ),
         }
     }
 }
@@ -137,25 +200,30 @@ impl MemBanks {
             size,
             window,
             shift: window.trailing_zeros() as usize,
-            mask: page_count - 1,
+            mask: page_count.next_power_of_two() - 1,
             banks,
             page_count,
         }
     }
 
+    /// Wraps a bank number to the banks available, mirroring ROMs that aren't a power of two
+    /// in size.
+    #[inline]
+    const fn wrap(&self, bank: usize) -> usize {
+        (bank & self.mask) % self.page_count
+    }
+
     #[inline]
     pub fn set(&mut self, slot: usize, bank: usize) {
-        self.banks[slot] = (bank & self.mask) << self.shift;
+        self.banks[slot] = self.wrap(bank) << self.shift;
         debug_assert!(self.banks[slot] < self.page_count * self.window);
     }
 
     #[inline]
     pub fn set_range(&mut self, start: usize, end: usize, bank: usize) {
-        let mut new_addr = (bank & self.mask) << self.shift;
-        for slot in start..=end {
-            self.banks[slot] = new_addr;
+        for (i, slot) in (start..=end).enumerate() {
+            self.banks[slot] = self.wrap(bank + i) << self.shift;
             debug_assert!(self.banks[slot] < self.page_count * self.window);
-            new_addr += self.window;
         }
     }
 
@@ -225,6 +293,32 @@ impl std::fmt::Debug for MemBanks {
 mod tests {
     use super::*;
 
+    #[test]
+    fn ram_state_patterns() {
+        let ram = RamState::with_capacity(16, RamState::Fceux);
+        assert_eq!(&ram[..8], [0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(&ram[..8], &ram[8..]);
+
+        let seeded = RamState::with_capacity(64, RamState::Seeded(42));
+        assert_eq!(seeded, RamState::with_capacity(64, RamState::Seeded(42)));
+        assert_ne!(seeded, RamState::with_capacity(64, RamState::Seeded(43)));
+    }
+
+    #[test]
+    fn ram_state_names() {
+        for state in [
+            RamState::AllZeros,
+            RamState::AllOnes,
+            RamState::Random,
+            RamState::Fceux,
+            RamState::Seeded(1234),
+        ] {
+            assert_eq!(state.to_string().parse(), Ok(state));
+            assert_eq!(RamState::from(state.index()).index(), state.index());
+        }
+        assert!("seeded:abc".parse::<RamState>().is_err());
+    }
+
     #[test]
     fn get_bank() {
         let size = 128 * 1024;
@@ -257,4 +351,16 @@ mod tests {
         banks.set(0, banks.last());
         assert_eq!(banks.translate(0x8000), 0x1E000);
     }
+
+    #[test]
+    fn non_power_of_two_banks() {
+        let size = 1536 * 1024;
+        let mut banks = MemBanks::new(0x8000, 0xFFFF, size, 0x4000);
+        banks.set(0, 64);
+        assert_eq!(banks.translate(0x8000), 64 * 0x4000);
+        banks.set_range(0, 1, 94);
+        assert_eq!(banks.translate(0xC000), 95 * 0x4000);
+        banks.set(0, 96);
+        assert_eq!(banks.translate(0x8000), 0x0000);
+    }
 }
@@ -5,20 +5,24 @@ use crate::{
     ppu::{bus::PpuBus, frame::Frame},
 };
 use ctrl::PpuCtrl;
+use io_latch::IoLatch;
 use mask::PpuMask;
-use scroll::PpuScroll;
+use scroll::{PpuScroll, ScrollSplit};
 use serde::{Deserialize, Serialize};
-use sprite::Sprite;
+use sprite::{OamEntry, Sprite, SpriteSnapshot};
 use status::PpuStatus;
 use std::cmp::Ordering;
+use tile_capture::{hash_tile, TilePixel};
 
 pub mod bus;
 pub mod ctrl;
 pub mod frame;
+pub mod io_latch;
 pub mod mask;
 pub mod scroll;
 pub mod sprite;
 pub mod status;
+pub mod tile_capture;
 
 /// Nametable Mirroring Mode
 ///
@@ -103,7 +107,44 @@ pub struct Ppu {
     sprites: [Sprite; 8], // Each scanline can hold 8 sprites at a time
     spr_present: Vec<bool>,
 
-    open_bus: u8,
+    #[serde(deserialize_with = "IoLatch::deserialize_legacy")]
+    open_bus: IoLatch,
+
+    // Scroll splits for the frame being rendered and the last completed frame, only recorded
+    // while enabled for debugging
+    #[serde(skip)]
+    scroll_splits: Option<Vec<ScrollSplit>>,
+    #[serde(skip)]
+    frame_scroll_splits: Vec<ScrollSplit>,
+    // Sprites and palettes at the end of the last completed frame, only captured while enabled
+    // for debugging
+    #[serde(skip)]
+    sprite_snapshots: bool,
+    #[serde(skip)]
+    sprite_snapshot: Option<SpriteSnapshot>,
+    // Tile that produced each pixel of the frame being rendered and the last completed frame,
+    // along with the tiles and rows in the fetch pipeline, only captured while enabled
+    #[serde(skip)]
+    tile_capture: Option<Vec<TilePixel>>,
+    #[serde(skip)]
+    frame_tile_capture: Vec<TilePixel>,
+    #[serde(skip)]
+    prev_tile: (u64, u8),
+    #[serde(skip)]
+    curr_tile: (u64, u8),
+    #[serde(skip)]
+    next_tile: (u64, u8),
+    #[serde(skip)]
+    sprite_tiles: [(u64, u8); 8],
+    #[serde(skip)]
+    pixel_sprite: Option<usize>,
+
+    // Frames to skip pixel output for between rendered frames, and whether the current frame is
+    // skipped. Not saved, as it's a performance setting rather than console state
+    #[serde(skip)]
+    frame_skip: u32,
+    #[serde(skip)]
+    skip_frame: bool,
 }
 
 impl Default for Ppu {
@@ -224,7 +265,22 @@ impl Ppu {
             sprites: [Sprite::new(); 8],
             spr_present: vec![false; Self::VISIBLE_END as usize],
 
-            open_bus: 0x00,
+            open_bus: IoLatch::new(),
+
+            scroll_splits: None,
+            frame_scroll_splits: vec![],
+            sprite_snapshots: false,
+            sprite_snapshot: None,
+            tile_capture: None,
+            frame_tile_capture: vec![],
+            prev_tile: (0, 0),
+            curr_tile: (0, 0),
+            next_tile: (0, 0),
+            sprite_tiles: [(0, 0); 8],
+            pixel_sprite: None,
+
+            frame_skip: 0,
+            skip_frame: false,
         };
         ppu.set_region(ppu.region);
         ppu
@@ -265,6 +321,205 @@ impl Ppu {
         self.frame.number()
     }
 
+    /// Enables or disables recording scroll splits for each frame.
+    pub fn set_scroll_splits_enabled(&mut self, enabled: bool) {
+        if enabled != self.scroll_splits.is_some() {
+            self.scroll_splits = enabled.then(Vec::new);
+            self.frame_scroll_splits.clear();
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn scroll_splits_enabled(&self) -> bool {
+        self.scroll_splits.is_some()
+    }
+
+    /// Returns every scroll change during the last completed frame, starting with the scroll at
+    /// the top of the frame. Empty unless enabled with `set_scroll_splits_enabled`.
+    #[inline]
+    pub fn scroll_splits(&self) -> &[ScrollSplit] {
+        &self.frame_scroll_splits
+    }
+
+    /// Enables or disables capturing a `SpriteSnapshot` at the end of each frame.
+    pub fn set_sprite_snapshots_enabled(&mut self, enabled: bool) {
+        self.sprite_snapshots = enabled;
+        if !enabled {
+            self.sprite_snapshot = None;
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn sprite_snapshots_enabled(&self) -> bool {
+        self.sprite_snapshots
+    }
+
+    /// Returns sprites and palettes as of the end of the last completed frame. `None` unless
+    /// enabled with `set_sprite_snapshots_enabled` and a frame has completed since.
+    #[inline]
+    pub const fn sprite_snapshot(&self) -> Option<&SpriteSnapshot> {
+        self.sprite_snapshot.as_ref()
+    }
+
+    /// Enables or disables capturing which tile produced each pixel of a frame.
+    pub fn set_tile_capture_enabled(&mut self, enabled: bool) {
+        if enabled != self.tile_capture.is_some() {
+            let len = if enabled {
+                (Self::WIDTH * Self::HEIGHT) as usize
+            } else {
+                0
+            };
+            self.tile_capture = enabled.then(|| vec![TilePixel::default(); len]);
+            self.frame_tile_capture = vec![TilePixel::default(); len];
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn tile_capture_enabled(&self) -> bool {
+        self.tile_capture.is_some()
+    }
+
+    /// Returns the tile that produced each pixel of the last completed frame, row by row. Empty
+    /// unless enabled with `set_tile_capture_enabled`, and all backdrop until a frame completes.
+    #[inline]
+    pub fn tile_capture(&self) -> &[TilePixel] {
+        &self.frame_tile_capture
+    }
+
+    /// Hashes the tile containing the pattern address `addr`, returning the hash and the row
+    /// within the tile.
+    fn capture_tile(&self, addr: u16) -> (u64, u8) {
+        let base = addr & !0x0F;
+        let mut pattern = [0x00; 16];
+        for (offset, byte) in (0..).zip(pattern.iter_mut()) {
+            *byte = self.bus.peek(base + offset, Access::Dummy);
+        }
+        (hash_tile(&pattern), (addr & 0x07) as u8)
+    }
+
+    /// Records the tile that produced the pixel just rendered at `x`, `y`.
+    fn capture_pixel(&mut self, x: u32, y: u32, rendered: bool) {
+        let left_clip_bg = x < 8 && !self.mask.show_left_bg();
+        let pixel = match self.pixel_sprite {
+            Some(i) if rendered => {
+                let sprite = &self.sprites[i];
+                let (tile, row) = self.sprite_tiles[i];
+                let column = (x - sprite.x) as u8;
+                let mut flags = TilePixel::SPRITE;
+                if sprite.flip_horizontal {
+                    flags |= TilePixel::FLIP_HORIZONTAL;
+                }
+                if sprite.flip_vertical {
+                    flags |= TilePixel::FLIP_VERTICAL;
+                }
+                if sprite.bg_priority {
+                    flags |= TilePixel::BG_PRIORITY;
+                }
+                TilePixel {
+                    tile,
+                    palette: ((sprite.palette >> 2) & 0x03) + 4,
+                    x: if sprite.flip_horizontal {
+                        7 - column
+                    } else {
+                        column
+                    },
+                    y: row,
+                    flags,
+                }
+            }
+            None if rendered && self.mask.show_bg() && !left_clip_bg => {
+                let offset = self.scroll.fine_x() + (x & 0x07) as u16;
+                let ((tile, row), palette) = if offset < 8 {
+                    (self.prev_tile, self.prev_palette)
+                } else {
+                    (self.curr_tile, self.curr_palette)
+                };
+                TilePixel {
+                    tile,
+                    palette: palette >> 2,
+                    x: (offset & 0x07) as u8,
+                    y: row,
+                    flags: 0,
+                }
+            }
+            _ => TilePixel::default(),
+        };
+        if let Some(ref mut capture) = self.tile_capture {
+            capture[(y * Self::WIDTH + x) as usize] = pixel;
+        }
+    }
+
+    /// Captures sprites and palettes as they are now.
+    pub fn snapshot_sprites(&self) -> SpriteSnapshot {
+        let mut oam = [OamEntry::default(); 64];
+        for (entry, bytes) in oam.iter_mut().zip(self.oamdata.chunks_exact(4)) {
+            *entry = OamEntry::new([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        let height = self.ctrl.spr_height();
+        let patterns = oam
+            .iter()
+            .map(|entry| {
+                let addr = entry.tile_addr(height, self.ctrl.spr_select());
+                let mut pattern = [0; 8 * 16];
+                for y in 0..height as u16 {
+                    // The bottom half of an 8x16 sprite is the next tile
+                    let row = addr + (y / 8) * 16 + y % 8;
+                    let lo = self.bus.peek(row, Access::Dummy);
+                    let hi = self.bus.peek(row + 8, Access::Dummy);
+                    let py = if entry.flip_vertical() {
+                        height as usize - 1 - y as usize
+                    } else {
+                        y as usize
+                    };
+                    for x in 0..8 {
+                        let value = ((lo >> (7 - x)) & 0x01) | ((hi >> (7 - x)) & 0x01) << 1;
+                        let px = if entry.flip_horizontal() { 7 - x } else { x };
+                        pattern[py * 8 + px] = value;
+                    }
+                }
+                pattern
+            })
+            .collect();
+        let mut palette = [0; 32];
+        for (addr, color) in (Self::PALETTE_START..Self::PALETTE_END).zip(palette.iter_mut()) {
+            *color = self.bus.peek(addr, Access::Dummy);
+        }
+        SpriteSnapshot {
+            oam,
+            patterns,
+            palette,
+            height,
+            sprite_zero_hit: self.status.spr_zero_hit(),
+        }
+    }
+
+    /// Skips pixel output for `frame_skip` frames between each rendered frame, so only every
+    /// `frame_skip + 1` frames are drawn. Timing related state like sprite zero hits and sprite
+    /// overflow is still emulated, and the frame buffer keeps the last rendered frame. `0` renders
+    /// every frame. Takes effect from the start of the next frame.
+    ///
+    /// The Zapper reads light from rendered pixels, so it can't see skipped frames.
+    pub fn set_frame_skip(&mut self, frame_skip: u32) {
+        self.frame_skip = frame_skip;
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn frame_skip(&self) -> u32 {
+        self.frame_skip
+    }
+
+    /// Whether pixel output was skipped for the current frame, or the last completed frame
+    /// between frames.
+    #[inline]
+    #[must_use]
+    pub const fn frame_skipped(&self) -> bool {
+        self.skip_frame
+    }
+
     #[must_use]
     pub fn pixel_brightness(&self, x: u32, y: u32) -> u32 {
         self.frame.pixel_brightness(x, y)
@@ -329,15 +584,34 @@ impl Ppu {
         self.oamaddr
     }
 
+    /// Object Attribute Memory, 4 bytes for each of the 64 sprites.
+    #[inline]
+    #[must_use]
+    pub fn oamdata(&self) -> &[u8] {
+        &self.oamdata
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn chr_ram(&self) -> &[u8] {
+        self.bus.chr_ram()
+    }
+
     #[inline]
     #[must_use]
     pub const fn open_bus(&self) -> u8 {
-        self.open_bus
+        self.open_bus.read()
     }
 
     #[inline]
     pub fn set_open_bus(&mut self, val: u8) {
-        self.open_bus = val;
+        self.open_bus.write(val, 0xFF, self.frame.number());
+    }
+
+    /// Drives only the bits in `mask` of the I/O latch, leaving the rest to decay.
+    #[inline]
+    fn set_open_bus_bits(&mut self, val: u8, mask: u8) {
+        self.open_bus.write(val, mask, self.frame.number());
     }
 }
 
@@ -362,6 +636,32 @@ impl Ppu {
         }
     }
 
+    // Records the scroll used for the next visible scanline if it differs from the previous one.
+    // Fine and coarse Y advance every scanline, so they're stored relative to the top of the
+    // frame to only flag writes to PPUSCROLL/PPUADDR.
+    fn record_scroll_split(&mut self, prerender_scanline: bool) {
+        let Some(ref mut splits) = self.scroll_splits else {
+            return;
+        };
+        let scanline = if prerender_scanline {
+            0
+        } else {
+            self.scanline + 1
+        };
+        if scanline > Self::VISIBLE_SCANLINE_END {
+            return;
+        }
+        let height = 2 * Self::HEIGHT as u16;
+        let (x, y) = self.scroll.position();
+        let y = (y + height - scanline as u16) % height;
+        if splits
+            .last()
+            .map_or(true, |split| (split.x, split.y) != (x, y))
+        {
+            splits.push(ScrollSplit { scanline, x, y });
+        }
+    }
+
     fn start_vblank(&mut self) {
         log::trace!("({}, {}): Set VBL flag", self.cycle, self.scanline);
         if !self.prevent_vbl {
@@ -401,6 +701,8 @@ impl Ppu {
 
         self.prev_palette = self.curr_palette;
         self.curr_palette = self.next_palette;
+        self.prev_tile = self.curr_tile;
+        self.curr_tile = self.next_tile;
 
         self.tile_shift_lo |= u16::from(self.tile_lo);
         self.tile_shift_hi |= u16::from(self.tile_hi);
@@ -409,6 +711,9 @@ impl Ppu {
         let addr = Self::NT_START | (self.addr() & nametable_addr_mask);
         let tile_index = u16::from(self.bus.read(addr, Access::Read));
         self.tile_addr = self.ctrl.bg_select() | (tile_index << 4) | self.scroll.fine_y();
+        if self.tile_capture.is_some() {
+            self.next_tile = self.capture_tile(self.tile_addr);
+        }
     }
 
     #[inline]
@@ -592,6 +897,9 @@ impl Ppu {
                 for spr in self.spr_present.iter_mut().skip(sprite.x as usize).take(8) {
                     *spr = true;
                 }
+                if self.tile_capture.is_some() {
+                    self.sprite_tiles[idx] = self.capture_tile(tile_addr);
+                }
             } else {
                 // Fetches for remaining sprites/hidden fetch tile $FF - used by MMC3 IRQ
                 // counter
@@ -620,6 +928,7 @@ impl Ppu {
 
     fn pixel_color(&mut self) -> u8 {
         let x = self.cycle - 1;
+        self.pixel_sprite = None;
 
         let left_clip_bg = x < 8 && !self.mask.show_left_bg();
         let bg_color = if self.mask.show_bg() && !left_clip_bg {
@@ -654,6 +963,7 @@ impl Ppu {
                         }
 
                         if bg_color == 0 || !sprite.bg_priority {
+                            self.pixel_sprite = Some(i);
                             return sprite.palette + spr_color;
                         }
                         break;
@@ -672,9 +982,9 @@ impl Ppu {
     fn render_pixel(&mut self) {
         let x = self.cycle - 1;
         let y = self.scanline;
-        let palette_addr = if self.rendering_enabled()
-            || (self.addr() & Self::PALETTE_START) != Self::PALETTE_START
-        {
+        let rendered =
+            self.rendering_enabled() || (self.addr() & Self::PALETTE_START) != Self::PALETTE_START;
+        let palette_addr = if rendered {
             let color = self.pixel_color();
             if color & 0x03 > 0 {
                 u16::from(color)
@@ -691,6 +1001,19 @@ impl Ppu {
         color &= if self.mask.grayscale() { 0x30 } else { 0x3F };
         color |= u16::from(self.mask.emphasis(self.region)) << 1;
         self.frame.set_pixel(x, y, color);
+        if self.tile_capture.is_some() {
+            self.capture_pixel(x, y, rendered);
+        }
+    }
+
+    /// Emulates the side effects of rendering a pixel without outputting it. Pixels are only
+    /// evaluated while a sprite zero hit is still possible, and the palette is still read because
+    /// mappers like the MMC3 watch PPU A12 on every fetch.
+    fn skip_pixel(&mut self) {
+        if self.rendering_enabled() && self.spr_zero_visible && !self.status.spr_zero_hit() {
+            self.pixel_color();
+        }
+        self.bus.read(Self::PALETTE_START, Access::Read);
     }
 
     fn tick(&mut self) {
@@ -771,6 +1094,9 @@ impl Ppu {
                 if spr_dummy_cycle {
                     self.oam_fetch = self.secondary_oamdata[0];
                 }
+                if self.cycle == Self::BG_PREFETCH_START {
+                    self.record_scroll_split(prerender_scanline);
+                }
 
                 if self.cycle == Self::ODD_SKIP
                     && prerender_scanline
@@ -793,7 +1119,11 @@ impl Ppu {
         // Pixels should be put even if rendering is disabled, as this is what blanks out the
         // screen. Rendering disabled just means we don't evaluate/read bg/sprite info
         if visible_cycle && visible_scanline {
-            self.render_pixel();
+            if self.skip_frame {
+                self.skip_pixel();
+            } else {
+                self.render_pixel();
+            }
         }
         if bg_fetch_cycle {
             self.tile_shift_lo <<= 1;
@@ -828,7 +1158,7 @@ impl PpuRegisters for Ppu {
     //       |   7 | VBlank Switch, 1 = generate interrupts on VBlank
     #[inline]
     fn write_ctrl(&mut self, val: u8) {
-        self.open_bus = val;
+        self.set_open_bus(val);
         if self.reset_signal {
             return;
         }
@@ -860,9 +1190,12 @@ impl PpuRegisters for Ppu {
     //       |   3 | BG Switch, 1 = show background, 0 = hide background
     //       |   4 | Sprites Switch, 1 = show sprites, 0 = hide sprites
     //       | 5-7 | Unknown (???)
+    //
+    // Grayscale and emphasis apply from the next pixel rendered after the write. The short delay
+    // the analog output adds on hardware isn't emulated, since no test ROM measures it.
     #[inline]
     fn write_mask(&mut self, val: u8) {
-        self.open_bus = val;
+        self.set_open_bus(val);
         if self.reset_signal {
             return;
         }
@@ -891,7 +1224,7 @@ impl PpuRegisters for Ppu {
             log::trace!("({}, {}): $2002 Prevent VBL", self.cycle, self.scanline);
             self.prevent_vbl = true;
         }
-        self.open_bus |= status & 0xE0;
+        self.set_open_bus_bits(status, 0xE0);
         self.mapper_mut().ppu_bus_write(0x2002, status);
         status
     }
@@ -907,7 +1240,7 @@ impl PpuRegisters for Ppu {
     #[inline]
     fn peek_status(&self) -> u8 {
         // Only upper 3 bits are connected for this register
-        (self.status.read() & 0xE0) | (self.open_bus & 0x1F)
+        (self.status.read() & 0xE0) | (self.open_bus.read() & 0x1F)
     }
 
     // $2003 | W   | OAMADDR
@@ -917,7 +1250,7 @@ impl PpuRegisters for Ppu {
     //       |     | colors, and other attributes of the sprites.
     #[inline]
     fn write_oamaddr(&mut self, val: u8) {
-        self.open_bus = val;
+        self.set_open_bus(val);
         self.oamaddr = val;
     }
 
@@ -930,7 +1263,7 @@ impl PpuRegisters for Ppu {
     #[must_use]
     fn read_oamdata(&mut self) -> u8 {
         let val = self.peek_oamdata();
-        self.open_bus = val;
+        self.set_open_bus(val);
         val
     }
 
@@ -961,7 +1294,7 @@ impl PpuRegisters for Ppu {
     //       |     | sprites.
     #[inline]
     fn write_oamdata(&mut self, mut val: u8) {
-        self.open_bus = val;
+        self.set_open_bus(val);
         if self.rendering_enabled()
             && (self.scanline <= Self::VISIBLE_SCANLINE_END
                 || self.scanline == self.prerender_scanline
@@ -1000,7 +1333,7 @@ impl PpuRegisters for Ppu {
     //       |     | only 2 real Name Tables, not 4.
     #[inline]
     fn write_scroll(&mut self, val: u8) {
-        self.open_bus = val;
+        self.set_open_bus(val);
         if self.reset_signal {
             return;
         }
@@ -1010,7 +1343,7 @@ impl PpuRegisters for Ppu {
     // $2006 | W   | PPUADDR
     #[inline]
     fn write_addr(&mut self, val: u8) {
-        self.open_bus = val;
+        self.set_open_bus(val);
         if self.reset_signal {
             return;
         }
@@ -1020,6 +1353,15 @@ impl PpuRegisters for Ppu {
         self.mapper_mut().ppu_bus_write(addr, val);
     }
 
+    // Palette RAM is only 6 bits wide and is read through the same grayscale mask applied when
+    // rendering. The hi 2 bits are open bus.
+    #[inline]
+    #[must_use]
+    const fn palette_read(&self, val: u8) -> u8 {
+        let mask = if self.mask.grayscale() { 0x30 } else { 0x3F };
+        (val & mask) | (self.open_bus.read() & 0xC0)
+    }
+
     // $2007 | RW  | PPUDATA
     #[inline]
     #[must_use]
@@ -1033,17 +1375,19 @@ impl PpuRegisters for Ppu {
         let val = if addr < Self::PALETTE_START {
             let buffer = self.vram_buffer;
             self.vram_buffer = val;
+            self.set_open_bus(buffer);
             buffer
         } else {
             // Set internal buffer with mirrors of nametable when reading palettes
             // Since we're reading from > $3EFF subtract $1000 to fill
             // buffer with nametable mirror data
             self.vram_buffer = self.bus.read(addr - 0x1000, Access::Dummy);
-            // Hi 2 bits of palette should be open bus
-            val | (self.open_bus & 0xC0)
+            // Hi 2 bits of palette are open bus and aren't driven by the read
+            let val = self.palette_read(val);
+            self.set_open_bus_bits(val, 0x3F);
+            val
         };
 
-        self.open_bus = val;
         // MMC3 clocks using A12
         let addr = self.scroll.read_addr();
         self.mapper_mut().ppu_bus_write(addr, val);
@@ -1061,15 +1405,14 @@ impl PpuRegisters for Ppu {
         if addr < Self::PALETTE_START {
             self.vram_buffer
         } else {
-            // Hi 2 bits of palette should be open bus
-            self.bus.peek(addr, Access::Dummy) | (self.open_bus & 0xC0)
+            self.palette_read(self.bus.peek(addr, Access::Dummy))
         }
     }
 
     // $2007 | RW  | PPUDATA
     #[inline]
     fn write_data(&mut self, val: u8) {
-        self.open_bus = val;
+        self.set_open_bus(val);
         let addr = self.scroll.read_addr();
         self.increment_vram_addr();
         self.bus.write(addr, val, Access::Write);
@@ -1092,19 +1435,34 @@ impl Mem for Ppu {
 
 impl Clock for Ppu {
     fn clock(&mut self) -> usize {
-        // Clear open bus roughly once every frame
-        if self.scanline == 0 {
-            self.open_bus = 0x00;
-        }
-
         if self.cycle >= Self::CYCLE_END {
             self.cycle = 0;
             self.scanline += 1;
             // Post-render line
             if self.scanline == self.vblank_scanline - 1 {
-                self.frame.increment();
+                // Keep showing the last rendered frame when this one was skipped
+                if self.skip_frame {
+                    self.frame.skip();
+                } else {
+                    self.frame.increment();
+                }
+                self.open_bus.decay(self.frame.number());
+                if let Some(ref mut splits) = self.scroll_splits {
+                    std::mem::swap(splits, &mut self.frame_scroll_splits);
+                    splits.clear();
+                }
+                if self.sprite_snapshots {
+                    self.sprite_snapshot = Some(self.snapshot_sprites());
+                }
+                if let Some(ref mut capture) = self.tile_capture {
+                    if !self.skip_frame {
+                        std::mem::swap(capture, &mut self.frame_tile_capture);
+                    }
+                }
             } else if self.scanline > self.prerender_scanline {
                 self.scanline = 0;
+                self.skip_frame =
+                    self.frame_skip > 0 && self.frame.number() % (self.frame_skip + 1) != 0;
             }
         } else {
             // cycle > 0
@@ -1173,6 +1531,7 @@ impl Reset for Ppu {
         self.nmi_pending = false;
         self.prevent_vbl = false;
         self.frame.reset(kind);
+        self.skip_frame = false;
         self.oam_fetch = 0x00;
         self.oam_eval_done = false;
         self.overflow_count = 0;
@@ -1182,7 +1541,7 @@ impl Reset for Ppu {
         self.spr_count = 0;
         self.sprites = [Sprite::new(); 8];
         self.spr_present.fill(false);
-        self.open_bus = 0x00;
+        self.open_bus.reset();
         self.bus.reset(kind);
     }
 }
@@ -1249,6 +1608,7 @@ mod tests {
     use crate::{
         cart::Cart,
         mapper::{Mapped, Mmc1Revision, Sxrom},
+        test_roms,
     };
 
     #[test]
@@ -1275,6 +1635,27 @@ mod tests {
         assert_eq!(ppu.scroll.read_addr(), 0x2307);
     }
 
+    #[test]
+    fn palette_reads() {
+        let mut ppu = Ppu::default();
+        ppu.bus.write(0x2F10, 0x55, Access::Write);
+        ppu.bus.write(0x3F10, 0x2A, Access::Write);
+
+        ppu.write_addr(0x3F);
+        ppu.write_addr(0x10);
+        ppu.set_open_bus(0xC0);
+        assert_eq!(ppu.read_data(), 0xEA, "hi 2 bits are open bus");
+        assert_eq!(ppu.vram_buffer, 0x55, "buffer filled from nametable mirror");
+
+        ppu.write_mask(0x01); // grayscale
+        ppu.write_addr(0x3F);
+        ppu.write_addr(0x10);
+        assert_eq!(ppu.read_data(), 0x20);
+
+        ppu.open_bus.decay(IoLatch::DECAY_FRAMES);
+        assert_eq!(ppu.open_bus(), 0x00);
+    }
+
     #[test]
     fn vram_read_pagecross() {
         let mut ppu = Ppu::default();
@@ -1360,6 +1741,62 @@ mod tests {
         assert_eq!(ppu.read_data(), 0x77); // read B from $2405
     }
 
+    #[test]
+    fn sprite_snapshots() {
+        let mut ppu = Ppu::default();
+        let mut chr = vec![0x00; 0x2000];
+        chr[0x10] = 0x80; // Tile 1, top left pixel in the low plane
+        chr[0x10 + 8 + 7] = 0x01; // Tile 1, bottom right pixel in the high plane
+        ppu.load_chr_ram(chr);
+        ppu.oamdata[4..8].copy_from_slice(&[0x20, 0x01, 0xC1, 0x30]);
+        ppu.bus.write(0x3F15, 0x16, Access::Write);
+        ppu.bus.write(0x3F16, 0x2A, Access::Write);
+
+        let snapshot = ppu.snapshot_sprites();
+        let entry = snapshot.oam[1];
+        assert_eq!(entry, OamEntry::new([0x20, 0x01, 0xC1, 0x30]));
+        assert_eq!(entry.palette(), 1);
+        assert!(entry.flip_horizontal() && entry.flip_vertical());
+        assert_eq!(snapshot.height, 8);
+
+        // Flipping both ways swaps the top left and bottom right pixels
+        let pattern = &snapshot.patterns[1];
+        assert_eq!(pattern[0], 2);
+        assert_eq!(pattern[7 * 8 + 7], 1);
+        assert_eq!(pattern[1..7 * 8 + 7].iter().filter(|&&v| v != 0).count(), 0);
+        assert_eq!(snapshot.color(entry, 1), Some(0x16));
+        assert_eq!(snapshot.color(entry, 2), Some(0x2A));
+        assert_eq!(snapshot.color(entry, 0), None);
+
+        assert!(ppu.sprite_snapshot().is_none(), "disabled by default");
+    }
+
+    #[test]
+    fn tile_capture() {
+        let mut ppu = Ppu::default();
+        let mut chr = vec![0x00; 0x2000];
+        chr[0x10] = 0x80;
+        chr[0x1F] = 0x01;
+        ppu.load_chr_ram(chr.clone());
+        assert!(ppu.tile_capture().is_empty(), "disabled by default");
+
+        ppu.set_tile_capture_enabled(true);
+        assert!(ppu.tile_capture_enabled());
+        assert_eq!(
+            ppu.tile_capture().len(),
+            (Ppu::WIDTH * Ppu::HEIGHT) as usize
+        );
+        assert!(ppu.tile_capture().iter().all(TilePixel::is_backdrop));
+
+        let tile: [u8; 16] = chr[0x10..0x20].try_into().unwrap();
+        assert_eq!(ppu.capture_tile(0x0013), (hash_tile(&tile), 3));
+        assert_eq!(ppu.capture_tile(0x001B), (hash_tile(&tile), 3));
+        assert_ne!(ppu.capture_tile(0x0000).0, hash_tile(&tile));
+
+        ppu.set_tile_capture_enabled(false);
+        assert!(ppu.tile_capture().is_empty());
+    }
+
     #[test]
     fn read_status_resets_latch() {
         let mut ppu = Ppu::default();
@@ -1416,4 +1853,33 @@ mod tests {
         ppu.write_oamaddr(0x11);
         assert_eq!(ppu.read_oamdata(), 0x77);
     }
+
+    #[test]
+    fn scroll_position() {
+        let mut ppu = Ppu::default();
+        ppu.write_ctrl(0x03); // bottom-right nametable
+        ppu.write_scroll(125);
+        ppu.write_scroll(94);
+        ppu.scroll.copy_x();
+        ppu.scroll.copy_y();
+        assert_eq!(ppu.scroll.position(), (256 + 125, 240 + 94));
+    }
+
+    #[test]
+    fn mask_write_mid_scanline() {
+        let mut ppu = Ppu::default();
+        ppu.bus.write(0x3F00, 0x21, Access::Write);
+        ppu.scanline = 0;
+        ppu.cycle = 11;
+        ppu.render_pixel();
+
+        ppu.write_mask(0x21); // grayscale and emphasize red
+        ppu.cycle = 12;
+        ppu.render_pixel();
+
+        assert_eq!(ppu.frame.pixel(10, 0), 0x21, "before write");
+        assert_eq!(ppu.frame.pixel(11, 0), 0x60, "after write");
+    }
+
+    test_roms!("test_roms/ppu", open_bus, palette_ram, read_buffer);
 }
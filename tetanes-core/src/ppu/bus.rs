@@ -80,6 +80,12 @@ impl PpuBus {
         self.chr_ram = chr_ram;
     }
 
+    #[inline]
+    #[must_use]
+    pub fn chr_ram(&self) -> &[u8] {
+        &self.chr_ram
+    }
+
     #[inline]
     pub fn load_ex_ram(&mut self, ex_ram: Vec<u8>) {
         self.exram = ex_ram;
@@ -123,6 +129,16 @@ impl PpuBus {
         (nametable) | (!nametable & addr & 0x03FF)
     }
 
+    /// Reads CHR-ROM, or CHR-RAM for cartridges without CHR-ROM.
+    #[inline]
+    fn read_chr(&self, addr: usize) -> u8 {
+        if self.chr_rom.is_empty() {
+            self.chr_ram[addr]
+        } else {
+            self.chr_rom[addr]
+        }
+    }
+
     #[inline]
     const fn palette_mirror(&self, addr: usize) -> usize {
         let addr = addr & 0x001F;
@@ -137,19 +153,23 @@ impl PpuBus {
 impl Mem for PpuBus {
     fn read(&mut self, addr: u16, _access: Access) -> u8 {
         let val = match addr {
-            0x0000..=0x1FFF => {
-                let addr = if let MappedRead::Chr(addr) = self.mapper.map_read(addr) {
-                    addr
-                } else {
-                    addr.into()
-                };
-                if self.chr_rom.is_empty() {
-                    self.chr_ram[addr]
-                } else {
-                    self.chr_rom[addr]
+            0x0000..=0x1FFF => match self.mapper.map_read(addr) {
+                MappedRead::CIRam(addr) => self.ciram[addr & 0x07FF],
+                mapped => {
+                    let addr = if let MappedRead::Chr(addr) = mapped {
+                        addr
+                    } else {
+                        addr.into()
+                    };
+                    if self.chr_rom.is_empty() {
+                        self.chr_ram[addr]
+                    } else {
+                        self.chr_rom[addr]
+                    }
                 }
-            }
+            },
             0x2000..=0x3EFF => match self.mapper.map_read(addr) {
+                MappedRead::Chr(addr) => self.read_chr(addr),
                 MappedRead::CIRam(addr) => self.ciram[addr & 0x07FF],
                 MappedRead::ExRam(addr) => self.exram[addr & 0x03FF],
                 MappedRead::Data(data) => data,
@@ -167,6 +187,8 @@ impl Mem for PpuBus {
                 0x00
             }
         };
+        // Some mappers switch banks after specific pattern fetches, like the MMC2/MMC4 latches
+        self.mapper.ppu_bus_read(addr);
         self.open_bus = val;
         val
     }
@@ -174,6 +196,7 @@ impl Mem for PpuBus {
     fn peek(&self, addr: u16, _access: Access) -> u8 {
         match addr {
             0x2000..=0x3EFF => match self.mapper.map_peek(addr) {
+                MappedRead::Chr(addr) => self.read_chr(addr),
                 MappedRead::CIRam(addr) => self.ciram[addr & 0x07FF],
                 MappedRead::ExRam(addr) => self.exram[addr & 0x03FF],
                 MappedRead::Data(data) => data,
@@ -185,18 +208,21 @@ impl Mem for PpuBus {
                     }
                 }
             },
-            0x0000..=0x1FFF => {
-                let addr = if let MappedRead::Chr(addr) = self.mapper.map_peek(addr) {
-                    addr
-                } else {
-                    addr.into()
-                };
-                if !self.chr_ram.is_empty() {
-                    self.chr_ram[addr]
-                } else {
-                    self.chr_rom[addr]
+            0x0000..=0x1FFF => match self.mapper.map_peek(addr) {
+                MappedRead::CIRam(addr) => self.ciram[addr & 0x07FF],
+                mapped => {
+                    let addr = if let MappedRead::Chr(addr) = mapped {
+                        addr
+                    } else {
+                        addr.into()
+                    };
+                    if !self.chr_ram.is_empty() {
+                        self.chr_ram[addr]
+                    } else {
+                        self.chr_rom[addr]
+                    }
                 }
-            }
+            },
             0x3F00..=0x3FFF => self.palette[self.palette_mirror(addr as usize)],
             _ => {
                 log::error!("unexpected PPU memory access at ${:04X}", addr);
@@ -208,6 +234,11 @@ impl Mem for PpuBus {
     fn write(&mut self, addr: u16, val: u8, _access: Access) {
         match addr {
             0x2000..=0x3EFF => match self.mapper.map_write(addr, val) {
+                MappedWrite::Chr(addr, val) => {
+                    if !self.chr_ram.is_empty() {
+                        self.chr_ram[addr] = val;
+                    }
+                }
                 MappedWrite::CIRam(addr, val) => self.ciram[addr] = val,
                 MappedWrite::ExRam(addr, val) => self.exram[addr] = val,
                 _ => {
@@ -217,13 +248,11 @@ impl Mem for PpuBus {
                     }
                 }
             },
-            0x0000..=0x1FFF => {
-                if !self.chr_ram.is_empty() {
-                    if let MappedWrite::Chr(addr, val) = self.mapper.map_write(addr, val) {
-                        self.chr_ram[addr] = val;
-                    }
-                }
-            }
+            0x0000..=0x1FFF => match self.mapper.map_write(addr, val) {
+                MappedWrite::CIRam(addr, val) => self.ciram[addr & 0x07FF] = val,
+                MappedWrite::Chr(addr, val) if !self.chr_ram.is_empty() => self.chr_ram[addr] = val,
+                _ => (),
+            },
             0x3F00..=0x3FFF => {
                 self.palette[self.palette_mirror(addr as usize)] = val;
             }
@@ -33,6 +33,13 @@ impl Frame {
         std::mem::swap(&mut self.front_buffer, &mut self.back_buffer);
     }
 
+    /// Moves to the next frame without presenting the back buffer, for frames that weren't
+    /// rendered.
+    #[inline]
+    pub fn skip(&mut self) {
+        self.count = self.count.wrapping_add(1);
+    }
+
     #[inline]
     #[must_use]
     pub fn pixel(&self, x: u32, y: u32) -> u16 {
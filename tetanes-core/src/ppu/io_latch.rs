@@ -0,0 +1,98 @@
+use crate::legacy;
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// The PPU I/O data bus latch, returned for the undriven bits of PPU register reads.
+///
+/// Every register write and read drives some bits of the latch. Each bit holds its value until
+/// it decays to `0` roughly 600ms after it was last driven high.
+///
+/// <https://www.nesdev.org/wiki/Open_bus_behavior#PPU_open_bus>
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[must_use]
+pub struct IoLatch {
+    value: u8,
+    // Frame each bit was last driven high
+    refreshed: [u32; 8],
+}
+
+impl IoLatch {
+    /// Number of frames before an undriven bit decays, ~600ms at 60Hz.
+    pub const DECAY_FRAMES: u32 = 36;
+
+    pub const fn new() -> Self {
+        Self {
+            value: 0x00,
+            refreshed: [0; 8],
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn read(&self) -> u8 {
+        self.value
+    }
+
+    /// Drives the bits set in `mask` with `val`, leaving the rest of the latch untouched.
+    #[inline]
+    pub fn write(&mut self, val: u8, mask: u8, frame: u32) {
+        self.value = (self.value & !mask) | (val & mask);
+        for (bit, refreshed) in self.refreshed.iter_mut().enumerate() {
+            if mask & val & (1 << bit) != 0 {
+                *refreshed = frame;
+            }
+        }
+    }
+
+    /// Clears any bits that haven't been driven high in the last `DECAY_FRAMES` frames.
+    #[inline]
+    pub fn decay(&mut self, frame: u32) {
+        for (bit, refreshed) in self.refreshed.iter().enumerate() {
+            if frame.wrapping_sub(*refreshed) >= Self::DECAY_FRAMES {
+                self.value &= !(1 << bit);
+            }
+        }
+    }
+
+    #[inline]
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Deserializes the latch. Legacy save states only kept its value, so every bit decays the
+    /// next time the latch is checked.
+    pub(crate) fn deserialize_legacy<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        if legacy::decoding() {
+            Ok(Self {
+                value: u8::deserialize(deserializer)?,
+                refreshed: [0; 8],
+            })
+        } else {
+            Self::deserialize(deserializer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bits_decay_independently() {
+        let mut latch = IoLatch::new();
+        latch.write(0xFF, 0xFF, 0);
+        latch.write(0x00, 0x0F, 10);
+        assert_eq!(latch.read(), 0xF0);
+
+        latch.write(0xC0, 0xE0, 20);
+        assert_eq!(latch.read(), 0xD0);
+
+        latch.decay(IoLatch::DECAY_FRAMES - 1);
+        assert_eq!(latch.read(), 0xD0, "bits hold until decay time");
+        latch.decay(IoLatch::DECAY_FRAMES);
+        assert_eq!(latch.read(), 0xC0, "bit 4 decays first");
+        latch.decay(20 + IoLatch::DECAY_FRAMES);
+        assert_eq!(latch.read(), 0x00);
+    }
+}
@@ -1,6 +1,16 @@
 use crate::common::{Kind, Reset};
 use serde::{Deserialize, Serialize};
 
+/// Scroll origin in effect from `scanline` until the next split, in pixels across the 512x480
+/// nametable layout.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct ScrollSplit {
+    pub scanline: u32,
+    pub x: u16,
+    pub y: u16,
+}
+
 #[derive(Default, Debug, Copy, Clone, Serialize, Deserialize)]
 #[must_use]
 pub struct PpuScroll {
@@ -19,10 +29,10 @@ impl PpuScroll {
     // ||| |+------------- Nametable X offset
     // ||| +-------------- Nametable Y offset
     // +++---------------- 3 bit fine Y
-    pub(crate) const COARSE_X_MASK: u16 = 0x001F;
-    pub(crate) const COARSE_Y_MASK: u16 = 0x03E0;
-    pub(crate) const NT_X_MASK: u16 = 0x0400;
-    pub(crate) const NT_Y_MASK: u16 = 0x0800;
+    pub const COARSE_X_MASK: u16 = 0x001F;
+    pub const COARSE_Y_MASK: u16 = 0x03E0;
+    pub const NT_X_MASK: u16 = 0x0400;
+    pub const NT_Y_MASK: u16 = 0x0800;
     const FINE_Y_MASK: u16 = 0x7000;
     const X_MAX_COL: u16 = 31; // last column of tiles - 255 pixel width / 8 pixel wide tiles
     const Y_MAX_COL: u16 = 29; // last row of tiles - (240 pixel height / 8 pixel tall tiles) - 1
@@ -157,6 +167,17 @@ impl PpuScroll {
         self.v >> 12
     }
 
+    // Returns the pixel position of PPUADDR v and fine X across all four nametables
+    // yyy NN YYYYY XXXXX
+    #[inline]
+    #[must_use]
+    pub const fn position(&self) -> (u16, u16) {
+        let x = ((self.v & Self::NT_X_MASK) >> 2) | (self.coarse_x() << 3) | self.fine_x();
+        let nt_y = (self.v & Self::NT_Y_MASK) >> 11;
+        let y = nt_y * 240 + (self.coarse_y() << 3) + self.fine_y();
+        (x, y)
+    }
+
     // Increment PPUADDR v by either 1 (going across) or 32 (going down)
     // Address wraps around
     #[inline]
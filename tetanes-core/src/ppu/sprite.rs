@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+#[must_use]
+pub struct Sprite {
+    pub x: u32,
+    pub y: u32,
+    pub tile_lo: u8,
+    pub tile_hi: u8,
+    pub attr: u8,
+    pub palette: u8,
+    pub bg_priority: bool,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+}
+
+impl Sprite {
+    pub const fn new() -> Self {
+        Self {
+            x: 0xFF,
+            y: 0xFF,
+            tile_lo: 0x00,
+            tile_hi: 0x00,
+            attr: 0xFF,
+            palette: 0x07,
+            bg_priority: true,
+            flip_horizontal: true,
+            flip_vertical: true,
+        }
+    }
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Sprite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sprite")
+            .field("x", &self.x)
+            .field("y", &self.y)
+            .field("palette", &format_args!("${:02X}", &self.palette))
+            .field("has_priority", &self.bg_priority)
+            .field("flip_horizontal", &self.flip_horizontal)
+            .field("flip_vertical", &self.flip_vertical)
+            .finish()
+    }
+}
+
+/// A sprite as stored in Object Attribute Memory.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct OamEntry {
+    pub y: u8,
+    pub tile: u8,
+    pub attr: u8,
+    pub x: u8,
+}
+
+impl OamEntry {
+    pub const fn new([y, tile, attr, x]: [u8; 4]) -> Self {
+        Self { y, tile, attr, x }
+    }
+
+    /// Sprite palette, 0-3.
+    #[inline]
+    #[must_use]
+    pub const fn palette(&self) -> u8 {
+        self.attr & 0x03
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn behind_background(&self) -> bool {
+        self.attr & 0x20 == 0x20
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn flip_horizontal(&self) -> bool {
+        self.attr & 0x40 == 0x40
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn flip_vertical(&self) -> bool {
+        self.attr & 0x80 == 0x80
+    }
+
+    /// Pattern table address of the sprite's top tile. 8x16 sprites select their pattern table
+    /// with bit 0 of the tile index instead of `spr_select` from `PPUCTRL`.
+    #[must_use]
+    pub const fn tile_addr(&self, height: u32, spr_select: u16) -> u16 {
+        let tile = self.tile as u16;
+        if height == 16 {
+            (tile & 0x01) * 0x1000 | (tile & 0xFE) << 4
+        } else {
+            spr_select | tile << 4
+        }
+    }
+}
+
+/// Sprites and palettes as they were at the end of a frame's visible scanlines, for debugging.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct SpriteSnapshot {
+    pub oam: [OamEntry; 64],
+    /// Pattern values 0-3 of each sprite, 8 pixels wide and `height` tall, flipped as drawn.
+    pub patterns: Vec<[u8; 8 * 16]>,
+    /// Palette RAM from `$3F00` to `$3F1F`.
+    pub palette: [u8; 32],
+    /// Sprite height in pixels, 8 or 16.
+    pub height: u32,
+    pub sprite_zero_hit: bool,
+}
+
+impl SpriteSnapshot {
+    /// Palette RAM color index of a sprite pattern value, or `None` if it's transparent.
+    #[must_use]
+    pub const fn color(&self, entry: OamEntry, value: u8) -> Option<u8> {
+        if value == 0 {
+            None
+        } else {
+            Some(self.palette[(0x10 | entry.palette() << 2 | value) as usize])
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Which pattern tile produced each pixel of a frame, used to replace tiles with high-resolution
+//! art and to help authors of replacement packs find the tiles a game draws.
+
+/// The tile that produced a single pixel.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+#[must_use]
+pub struct TilePixel {
+    /// Hash of the 16 pattern bytes of the tile, or `0` for the backdrop color.
+    pub tile: u64,
+    /// Palette used, `0..=3` for the background and `4..=7` for sprites.
+    pub palette: u8,
+    /// Column within the tile's pattern data, before any flipping.
+    pub x: u8,
+    /// Row within the tile's pattern data, before any flipping.
+    pub y: u8,
+    pub flags: u8,
+}
+
+impl TilePixel {
+    pub const SPRITE: u8 = 0x01;
+    pub const FLIP_HORIZONTAL: u8 = 0x02;
+    pub const FLIP_VERTICAL: u8 = 0x04;
+    pub const BG_PRIORITY: u8 = 0x08;
+
+    #[inline]
+    #[must_use]
+    pub const fn is_backdrop(&self) -> bool {
+        self.tile == 0
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn is_sprite(&self) -> bool {
+        self.flags & Self::SPRITE != 0
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn flip_horizontal(&self) -> bool {
+        self.flags & Self::FLIP_HORIZONTAL != 0
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn flip_vertical(&self) -> bool {
+        self.flags & Self::FLIP_VERTICAL != 0
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn bg_priority(&self) -> bool {
+        self.flags & Self::BG_PRIORITY != 0
+    }
+}
+
+/// Hashes the 16 pattern bytes of a tile with FNV-1a. Never returns `0`, which marks the
+/// backdrop.
+#[must_use]
+pub fn hash_tile(pattern: &[u8; 16]) -> u64 {
+    let hash = pattern
+        .iter()
+        .fold(0xCBF2_9CE4_8422_2325_u64, |hash, &byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01B3)
+        });
+    hash.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_hashes() {
+        let blank = hash_tile(&[0x00; 16]);
+        let mut pattern = [0x00; 16];
+        pattern[15] = 0x01;
+        assert_ne!(blank, 0);
+        assert_ne!(blank, hash_tile(&pattern));
+        assert_eq!(hash_tile(&pattern), hash_tile(&pattern));
+    }
+}
@@ -0,0 +1,92 @@
+//! Performance counters recorded as `tracing` spans.
+//!
+//! The core is instrumented with these spans, from coarsest to finest:
+//!
+//! - `frame_present`: Uploading and drawing a finished frame in the UI (`DEBUG`).
+//! - `ppu_scanline`: All CPU, PPU and APU work done while the PPU renders a scanline (`DEBUG`).
+//! - `cpu_step`: A single CPU instruction, including the PPU and APU cycles it clocks (`TRACE`).
+//! - `apu_mix`: Mixing the APU and expansion audio output for a CPU cycle (`TRACE`).
+//!
+//! Spans cost almost nothing unless a subscriber is listening, so they're always compiled in.
+//! Running with `--profile <path>` writes them as folded stacks for flamegraph tools.
+
+use tracing::span::EnteredSpan;
+
+/// Keeps a `ppu_scanline` span open for as long as the PPU stays on the same scanline.
+#[derive(Default, Debug)]
+#[must_use]
+pub(crate) struct ScanlineSpan {
+    current: Option<(u32, EnteredSpan)>,
+}
+
+impl ScanlineSpan {
+    /// Closes the current span and opens a new one if `scanline` changed.
+    #[inline]
+    pub(crate) fn update(&mut self, scanline: u32) {
+        if !matches!(self.current, Some((current, _)) if current == scanline) {
+            // Exit the previous scanline before entering the next so they don't nest
+            self.current = None;
+            self.current = Some((
+                scanline,
+                tracing::debug_span!("ppu_scanline", scanline).entered(),
+            ));
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use flamegraph::{profile_to_file, ProfileGuard};
+
+#[cfg(not(target_arch = "wasm32"))]
+mod flamegraph {
+    use crate::NesResult;
+    use anyhow::Context;
+    use std::{fs::File, io::BufWriter, path::Path};
+    use tracing::{level_filters::LevelFilter, Level};
+    use tracing_flame::{FlameLayer, FlushGuard};
+    use tracing_subscriber::prelude::*;
+
+    /// Flushes recorded spans to the profile file when dropped.
+    #[must_use]
+    pub struct ProfileGuard(FlushGuard<BufWriter<File>>);
+
+    impl std::fmt::Debug for ProfileGuard {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("ProfileGuard").finish_non_exhaustive()
+        }
+    }
+
+    /// Records spans up to `level` to `path` as folded stacks, which can be turned into a
+    /// flamegraph with tools like `inferno-flamegraph` or `flamegraph.pl`. `TRACE` includes every
+    /// CPU instruction and slows emulation down considerably.
+    ///
+    /// # Errors
+    ///
+    /// If the profile file can't be created or a global subscriber is already set, an error is
+    /// returned.
+    pub fn profile_to_file(path: &Path, level: Level) -> NesResult<ProfileGuard> {
+        let (flame_layer, guard) = FlameLayer::with_file(path)
+            .with_context(|| format!("failed to create profile file: {path:?}"))?;
+        let subscriber = tracing_subscriber::registry()
+            .with(LevelFilter::from_level(level))
+            .with(flame_layer);
+        tracing::subscriber::set_global_default(subscriber).context("failed to start profiling")?;
+        log::info!("Profiling to {path:?} at {level} level");
+        Ok(ProfileGuard(guard))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scanline_span_changes_with_scanline() {
+        let mut span = ScanlineSpan::default();
+        span.update(10);
+        assert!(matches!(span.current, Some((10, _))));
+        span.update(10);
+        span.update(11);
+        assert!(matches!(span.current, Some((11, _))));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::legacy;
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+enum MemOp {
+    Read,
+    Write,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Debug)]
+pub enum MachineStateType {
+    X,
+    Y,
+    A,
+    S,
+    PC,
+    SP,
+    ADDR,
+}
+
+/// Video timing at the time of a traced access, used to correlate entries with frames.
+#[derive(Default, Copy, Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub struct TraceTiming {
+    pub frame: u32,
+    pub scanline: u32,
+    pub dot: u32,
+    pub cycle: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct MemoryEntry {
+    step: u64, // CPU cycles since the trace started
+    frame: u32,
+    scanline: u32,
+    dot: u32,
+    cycle: u64,
+    addr: Option<u32>,
+    etype: MachineStateType,
+    val: u16, //support up to u16 to include the PC
+    op_rw: MemOp,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Trace {
+    start_cycle: Option<u64>,
+    timing: TraceTiming,
+    entries: Vec<MemoryEntry>,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Self {
+            start_cycle: None,
+            timing: TraceTiming::default(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Deserializes the trace. Legacy save states hold every access since power on without
+    /// their timing, so those entries are dropped and the trace starts over.
+    pub(crate) fn deserialize_legacy<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        if legacy::decoding() {
+            type LegacyEntry = (u64, Option<u32>, MachineStateType, u16, MemOp);
+            <(u64, Vec<LegacyEntry>)>::deserialize(deserializer)?;
+            Ok(Self::new())
+        } else {
+            Self::deserialize(deserializer)
+        }
+    }
+
+    /// Updates the timing recorded with subsequent entries. Called once per CPU cycle.
+    #[inline]
+    pub fn set_timing(&mut self, timing: TraceTiming) {
+        self.timing = timing;
+    }
+
+    pub fn read(&mut self, addr: u32, val: u16, etype: MachineStateType) {
+        self.push(addr, val, etype, MemOp::Read);
+    }
+
+    pub fn write(&mut self, addr: u32, val: u16, etype: MachineStateType) {
+        self.push(addr, val, etype, MemOp::Write);
+    }
+
+    fn push(&mut self, addr: u32, val: u16, etype: MachineStateType, op_rw: MemOp) {
+        let TraceTiming {
+            frame,
+            scanline,
+            dot,
+            cycle,
+        } = self.timing;
+        let start_cycle = *self.start_cycle.get_or_insert(cycle);
+        self.entries.push(MemoryEntry {
+            step: cycle.wrapping_sub(start_cycle),
+            frame,
+            scanline,
+            dot,
+            cycle,
+            addr: if etype == MachineStateType::ADDR {
+                Some(addr)
+            } else {
+                None
+            },
+            val,
+            op_rw,
+            etype,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_record_timing() {
+        let mut trace = Trace::new();
+        let timing = TraceTiming {
+            frame: 2,
+            scanline: 10,
+            dot: 100,
+            cycle: 500,
+        };
+        trace.set_timing(timing);
+        trace.read(0x0010, 0x01, MachineStateType::ADDR);
+        trace.set_timing(TraceTiming {
+            dot: 103,
+            cycle: 501,
+            ..timing
+        });
+        trace.write(0x0010, 0x02, MachineStateType::ADDR);
+
+        let steps: Vec<_> = trace.entries.iter().map(|e| (e.step, e.dot)).collect();
+        assert_eq!(steps, [(0, 100), (1, 103)]);
+        assert_eq!(trace.entries[1].cycle, 501);
+        assert_eq!(trace.entries[1].frame, 2);
+    }
+}
@@ -0,0 +1,663 @@
+use crate::ppu::Ppu;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{f64::consts::PI, str::FromStr};
+
+mod simd;
+
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub enum VideoFilter {
+    Pixellate,
+    #[default]
+    Ntsc,
+}
+
+impl VideoFilter {
+    pub const fn as_slice() -> &'static [Self] {
+        &[Self::Pixellate, Self::Ntsc]
+    }
+}
+
+impl AsRef<str> for VideoFilter {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Pixellate => "Pixellate",
+            Self::Ntsc => "NTSC",
+        }
+    }
+}
+
+impl FromStr for VideoFilter {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pixellate" => Ok(Self::Pixellate),
+            "ntsc" => Ok(Self::Ntsc),
+            _ => Err("invalid VideoFilter value. valid options: `pixellate` or `ntsc`"),
+        }
+    }
+}
+
+impl From<usize> for VideoFilter {
+    fn from(value: usize) -> Self {
+        if value == 1 {
+            Self::Ntsc
+        } else {
+            Self::Pixellate
+        }
+    }
+}
+
+/// Color vision deficiency to simulate or correct for as a final post-process stage.
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[must_use]
+pub enum ColorFilter {
+    #[default]
+    None,
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+impl ColorFilter {
+    pub const fn as_slice() -> &'static [Self] {
+        &[
+            Self::None,
+            Self::Protanopia,
+            Self::Deuteranopia,
+            Self::Tritanopia,
+        ]
+    }
+
+    // http://www.daltonize.org/ and Fidaner, Lin, Ozguven, "Analysis of Color Blindness"
+    const RGB_TO_LMS: Mat3 = [
+        [17.882_4, 43.516_1, 4.119_35],
+        [3.455_65, 27.155_4, 3.867_14],
+        [0.029_956_6, 0.184_309, 1.467_09],
+    ];
+    const LMS_TO_RGB: Mat3 = [
+        [0.080_944_45, -0.130_504_4, 0.116_721_07],
+        [-0.010_248_534, 0.054_019_327, -0.113_614_71],
+        [-0.000_365_296_94, -0.004_121_614_7, 0.693_511_4],
+    ];
+    // Shifts colors lost to protanopia and deuteranopia towards blue, and tritanopia towards red
+    // and green
+    const ERROR_SHIFT: Mat3 = [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]];
+
+    /// Cone response of the missing cone type, derived from the remaining two.
+    const fn deficiency(self) -> Option<Mat3> {
+        match self {
+            Self::None => None,
+            Self::Protanopia => {
+                Some([[0.0, 2.023_44, -2.525_81], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]])
+            }
+            Self::Deuteranopia => {
+                Some([[1.0, 0.0, 0.0], [0.494_207, 0.0, 1.248_27], [0.0, 0.0, 1.0]])
+            }
+            Self::Tritanopia => Some([
+                [1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [-0.395_913, 0.801_109, 0.0],
+            ]),
+        }
+    }
+
+    /// RGB transform that either simulates how colors appear with this deficiency, or daltonizes
+    /// colors to make them easier to distinguish.
+    fn matrix(self, simulate: bool) -> Option<Mat3> {
+        let deficiency = self.deficiency()?;
+        let simulation = mat3_mul(&mat3_mul(&Self::LMS_TO_RGB, &deficiency), &Self::RGB_TO_LMS);
+        if simulate {
+            return Some(simulation);
+        }
+        // corrected = rgb + shift * (rgb - simulated)
+        let mut error = [[0.0; 3]; 3];
+        for (i, row) in error.iter_mut().enumerate() {
+            for (j, val) in row.iter_mut().enumerate() {
+                let identity = if i == j { 1.0 } else { 0.0 };
+                *val = identity - simulation[i][j];
+            }
+        }
+        let mut correction = mat3_mul(&Self::ERROR_SHIFT, &error);
+        for (i, row) in correction.iter_mut().enumerate() {
+            row[i] += 1.0;
+        }
+        Some(correction)
+    }
+}
+
+impl AsRef<str> for ColorFilter {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::None => "None",
+            Self::Protanopia => "Protanopia",
+            Self::Deuteranopia => "Deuteranopia",
+            Self::Tritanopia => "Tritanopia",
+        }
+    }
+}
+
+impl From<usize> for ColorFilter {
+    fn from(value: usize) -> Self {
+        match value {
+            1 => Self::Protanopia,
+            2 => Self::Deuteranopia,
+            3 => Self::Tritanopia,
+            _ => Self::None,
+        }
+    }
+}
+
+type Mat3 = [[f32; 3]; 3];
+
+fn mat3_mul(a: &Mat3, b: &Mat3) -> Mat3 {
+    let mut out = [[0.0; 3]; 3];
+    for (i, row) in out.iter_mut().enumerate() {
+        for (j, val) in row.iter_mut().enumerate() {
+            *val = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    out
+}
+
+/// Layout of the pixels in a [`Frame`].
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq)]
+#[must_use]
+pub enum PixelFormat {
+    /// 8-bit red, green, blue and alpha channels, in that order.
+    #[default]
+    Rgba8,
+}
+
+impl PixelFormat {
+    #[inline]
+    #[must_use]
+    pub const fn bytes_per_pixel(&self) -> usize {
+        match self {
+            Self::Rgba8 => 4,
+        }
+    }
+}
+
+/// A filtered frame of pixels ready to be presented.
+#[derive(Clone)]
+#[must_use]
+pub struct Frame {
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    pixels: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(width: u32, height: u32, format: PixelFormat) -> Self {
+        let mut pixels = vec![0x00; width as usize * height as usize * format.bytes_per_pixel()];
+        match format {
+            PixelFormat::Rgba8 => {
+                // Force alpha to 255.
+                for p in pixels.iter_mut().skip(3).step_by(4) {
+                    *p = 255;
+                }
+            }
+        }
+        Self {
+            width,
+            height,
+            format,
+            pixels,
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[inline]
+    pub const fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Number of bytes between the start of one row and the next.
+    #[inline]
+    #[must_use]
+    pub const fn stride(&self) -> usize {
+        self.width as usize * self.format.bytes_per_pixel()
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn pixels_mut(&mut self) -> &mut [u8] {
+        &mut self.pixels
+    }
+}
+
+impl std::fmt::Debug for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Frame")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("format", &self.format)
+            .finish()
+    }
+}
+
+/// Filters PPU output into presentable frames.
+///
+/// Filtering alternates between two persistent frames: the next frame is filtered in place into
+/// the back frame, which then becomes the front frame handed out by reference. A frame is only
+/// filtered once, no matter how many times it's requested.
+#[derive(Clone)]
+#[must_use]
+pub struct Video {
+    filter: VideoFilter,
+    color_matrix: Option<Mat3>,
+    frames: [Frame; 2],
+    front: usize,
+    // PPU frame number the front frame was filtered from
+    filtered: Option<u32>,
+    filter_scale: u32,
+}
+
+impl Default for Video {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Video {
+    pub fn new() -> Self {
+        let frame = Frame::new(Ppu::WIDTH, Ppu::HEIGHT, PixelFormat::Rgba8);
+        Self {
+            filter: VideoFilter::default(),
+            color_matrix: None,
+            frames: [frame.clone(), frame],
+            front: 0,
+            filtered: None,
+            filter_scale: 1,
+        }
+    }
+
+    #[inline]
+    pub const fn filter(&self) -> VideoFilter {
+        self.filter
+    }
+
+    #[inline]
+    pub fn set_filter(&mut self, filter: VideoFilter) {
+        self.filter = filter;
+        self.invalidate();
+    }
+
+    /// Set a color blindness filter applied after the video filter. If `simulate` is set, colors
+    /// are shown as they appear with the deficiency instead of being corrected for it.
+    #[inline]
+    pub fn set_color_filter(&mut self, filter: ColorFilter, simulate: bool) {
+        self.color_matrix = filter.matrix(simulate);
+        self.invalidate();
+    }
+
+    /// Set how many scanlines share each filtered line. At a scale of 2 only every other line is
+    /// filtered and repeated below it, roughly halving the cost of filtering a frame.
+    #[inline]
+    pub fn set_filter_scale(&mut self, scale: u32) {
+        self.filter_scale = scale.max(1);
+        self.invalidate();
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn filter_scale(&self) -> u32 {
+        self.filter_scale
+    }
+
+    /// Forces the next call to `apply_filter` to filter the frame again, for when the PPU
+    /// output changed without advancing the frame number, like after a reset or loading a state.
+    #[inline]
+    pub fn invalidate(&mut self) {
+        self.filtered = None;
+    }
+
+    /// Filters a frame of PPU output into the front frame, unless `frame_number` was already
+    /// filtered.
+    pub fn apply_filter(&mut self, buffer: &[u16], frame_number: u32) {
+        if self.filtered == Some(frame_number) {
+            return;
+        }
+        let back = 1 - self.front;
+        let step = self.filter_scale as usize;
+        let stride = self.frames[back].stride();
+        let output = self.frames[back].pixels_mut();
+        match self.filter {
+            VideoFilter::Pixellate if step == 1 => simd::decode(buffer, output),
+            VideoFilter::Pixellate => {
+                let width = stride / 4;
+                for (row, colors) in buffer
+                    .chunks_exact(width)
+                    .zip(output.chunks_exact_mut(stride))
+                    .step_by(step)
+                {
+                    simd::decode(row, colors);
+                }
+            }
+            VideoFilter::Ntsc => simd::ntsc(buffer, output, frame_number, &NTSC_PALETTE, step),
+        }
+        if let Some(matrix) = self.color_matrix {
+            for colors in output.chunks_exact_mut(stride).step_by(step) {
+                Self::apply_color_matrix(colors, &matrix);
+            }
+        }
+        if step > 1 {
+            Self::repeat_rows(output, stride, step);
+        }
+        self.front = back;
+        self.filtered = Some(frame_number);
+    }
+
+    fn apply_color_matrix(output: &mut [u8], matrix: &Mat3) {
+        for colors in output.chunks_exact_mut(4) {
+            let rgb = [
+                f32::from(colors[0]),
+                f32::from(colors[1]),
+                f32::from(colors[2]),
+            ];
+            for (color, row) in colors.iter_mut().zip(matrix) {
+                let val = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+                *color = val.clamp(0.0, 255.0) as u8;
+            }
+            // Alpha should always be 255
+        }
+    }
+
+    /// Copies the first row of every `step` rows over the rest of them.
+    fn repeat_rows(output: &mut [u8], stride: usize, step: usize) {
+        for rows in output.chunks_mut(step * stride) {
+            let (first, rest) = rows.split_at_mut(stride);
+            for row in rest.chunks_exact_mut(stride) {
+                row.copy_from_slice(first);
+            }
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn output(&self) -> &[u8] {
+        self.frame().pixels()
+    }
+
+    /// The most recently filtered frame.
+    #[inline]
+    pub fn frame(&self) -> &Frame {
+        &self.frames[self.front]
+    }
+
+    /// Decodes a frame of PPU output with the system palette only, skipping the video and color
+    /// filters.
+    pub fn decode_raw(buffer: &[u16]) -> Frame {
+        let mut frame = Frame::new(Ppu::WIDTH, Ppu::HEIGHT, PixelFormat::Rgba8);
+        simd::decode(buffer, frame.pixels_mut());
+        frame
+    }
+
+    pub fn decode_buffer(&mut self, buffer: &[u16]) {
+        self.invalidate();
+        simd::decode(buffer, self.frames[self.front].pixels_mut());
+    }
+
+    // Amazing implementation Bisqwit! Much faster than my original, but boy what a pain
+    // to translate it to Rust
+    // Source: https://bisqwit.iki.fi/jutut/kuvat/programming_examples/nesemu1/nesemu1.cc
+    // http://wiki.nesdev.com/w/index.php/NTSC_video
+    pub fn apply_ntsc_filter(&mut self, buffer: &[u16], frame_number: u32) {
+        self.invalidate();
+        simd::ntsc(
+            buffer,
+            self.frames[self.front].pixels_mut(),
+            frame_number,
+            &NTSC_PALETTE,
+            1,
+        );
+    }
+}
+
+impl std::fmt::Debug for Video {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Video")
+            .field("filter", &self.filter)
+            .field("color_matrix", &self.color_matrix)
+            .field("frame", self.frame())
+            .field("filtered", &self.filtered)
+            .field("filter_scale", &self.filter_scale)
+            .finish()
+    }
+}
+
+pub static NTSC_PALETTE: Lazy<Vec<u32>> = Lazy::new(|| {
+    // NOTE: There's lot's to clean up here -- too many magic numbers and duplication but
+    // I'm afraid to touch it now that it works
+    // Source: https://bisqwit.iki.fi/jutut/kuvat/programming_examples/nesemu1/nesemu1.cc
+    // http://wiki.nesdev.com/w/index.php/NTSC_video
+
+    // Calculate the luma and chroma by emulating the relevant circuits:
+    const VOLTAGES: [i32; 16] = [
+        -6, -69, 26, -59, 29, -55, 73, -40, 68, -17, 125, 11, 68, 33, 125, 78,
+    ];
+
+    let mut ntsc_palette = vec![0; 512 * 64 * 3];
+
+    // Helper functions for converting YIQ to RGB
+    let gamma = 2.0; // Assumed display gamma
+    let gammafix = |color: f64| {
+        if color <= 0.0 {
+            0.0
+        } else {
+            color.powf(2.2 / gamma)
+        }
+    };
+    let yiq_divider = f64::from(9 * 10u32.pow(6));
+    for palette_offset in 0..3 {
+        for channel in 0..3 {
+            for color0_offset in 0..512 {
+                let emphasis = color0_offset / 64;
+
+                for color1_offset in 0..64 {
+                    let mut y = 0;
+                    let mut i = 0;
+                    let mut q = 0;
+                    // 12 samples of NTSC signal constitute a color.
+                    for sample in 0..12 {
+                        let noise = (sample + palette_offset * 4) % 12;
+                        // Sample either the previous or the current pixel.
+                        // Use pixel=color0 to disable artifacts.
+                        let pixel = if noise < 6 - channel * 2 {
+                            color0_offset
+                        } else {
+                            color1_offset
+                        };
+
+                        // Decode the color index.
+                        let chroma = pixel & 0x0F;
+                        // Forces luma to 0, 4, 8, or 12 for easy lookup
+                        let luma = if chroma < 0x0E { (pixel / 4) & 12 } else { 4 };
+                        // NES NTSC modulator (square wave between up to four voltage levels):
+                        let limit = if (chroma + 8 + sample) % 12 < 6 {
+                            12
+                        } else {
+                            0
+                        };
+                        let high = if chroma > limit { 1 } else { 0 };
+                        let emp_effect = if (152_278 >> (sample / 2 * 3)) & emphasis > 0 {
+                            0
+                        } else {
+                            2
+                        };
+                        let level = 40 + VOLTAGES[high + emp_effect + luma];
+                        // Ideal TV NTSC demodulator:
+                        let (sin, cos) = (PI * sample as f64 / 6.0).sin_cos();
+                        y += level;
+                        i += level * (cos * 5909.0) as i32;
+                        q += level * (sin * 5909.0) as i32;
+                    }
+                    // Store color at subpixel precision
+                    let y = f64::from(y) / 1980.0;
+                    let i = f64::from(i) / yiq_divider;
+                    let q = f64::from(q) / yiq_divider;
+                    let idx = palette_offset + color0_offset * 3 * 64 + color1_offset * 3;
+                    match channel {
+                        2 => {
+                            let rgb =
+                                255.95 * gammafix(q.mul_add(0.623_557, i.mul_add(0.946_882, y)));
+                            ntsc_palette[idx] += 0x10000 * rgb.clamp(0.0, 255.0) as u32;
+                        }
+                        1 => {
+                            let rgb =
+                                255.95 * gammafix(q.mul_add(-0.635_691, i.mul_add(-0.274_788, y)));
+                            ntsc_palette[idx] += 0x00100 * rgb.clamp(0.0, 255.0) as u32;
+                        }
+                        0 => {
+                            let rgb =
+                                255.95 * gammafix(q.mul_add(1.709_007, i.mul_add(-1.108_545, y)));
+                            ntsc_palette[idx] += rgb.clamp(0.0, 255.0) as u32;
+                        }
+                        _ => (), // invalid channel
+                    }
+                }
+            }
+        }
+    }
+
+    ntsc_palette
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_filters_preserve_grays() {
+        assert!(ColorFilter::None.matrix(false).is_none());
+        for filter in &ColorFilter::as_slice()[1..] {
+            for simulate in [false, true] {
+                let matrix = filter.matrix(simulate).expect("valid matrix");
+                let mut output = [0x80, 0x80, 0x80, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+                Video::apply_color_matrix(&mut output, &matrix);
+                for (color, expected) in output.iter().zip([0x80, 0x80, 0x80, 0xFF, 0xFF]) {
+                    assert!(
+                        (i16::from(*color) - expected).abs() <= 1,
+                        "{filter:?} simulate: {simulate}"
+                    );
+                }
+                assert_eq!(output[7], 0xFF);
+            }
+        }
+    }
+
+    #[test]
+    fn decode_raw_ignores_filters() {
+        let buffer = vec![0x16; (Ppu::WIDTH * Ppu::HEIGHT) as usize];
+        let mut video = Video::new();
+        video.set_color_filter(ColorFilter::Protanopia, false);
+        video.apply_filter(&buffer, 1);
+        let raw = Video::decode_raw(&buffer);
+        assert_eq!((raw.width(), raw.height()), (Ppu::WIDTH, Ppu::HEIGHT));
+        assert_eq!(raw.pixels()[3], 0xFF);
+        assert_ne!(raw.pixels(), video.output());
+    }
+
+    #[test]
+    fn filters_each_frame_once() {
+        let mut video = Video::new();
+        video.set_filter(VideoFilter::Pixellate);
+        let frame = video.frame();
+        assert_eq!(frame.stride(), 4 * Ppu::WIDTH as usize);
+        assert_eq!(frame.pixels().len(), frame.stride() * Ppu::HEIGHT as usize);
+
+        let white = vec![0x30; Ppu::SIZE];
+        video.apply_filter(&white, 1);
+        let front = video.frame().pixels().as_ptr();
+        assert_eq!(&video.output()[..4], [0xEC, 0xEE, 0xEC, 0xFF]);
+
+        // The same frame number isn't filtered again
+        video.apply_filter(&[0x0F; Ppu::SIZE], 1);
+        assert_eq!(video.frame().pixels().as_ptr(), front);
+        assert_eq!(&video.output()[..4], [0xEC, 0xEE, 0xEC, 0xFF]);
+
+        // The next frame is filtered into the other buffer
+        video.apply_filter(&[0x0F; Ppu::SIZE], 2);
+        assert_ne!(video.frame().pixels().as_ptr(), front);
+        assert_eq!(&video.output()[..4], [0x00, 0x00, 0x00, 0xFF]);
+
+        video.invalidate();
+        video.apply_filter(&white, 2);
+        assert_eq!(video.frame().pixels().as_ptr(), front);
+        assert_eq!(&video.output()[..4], [0xEC, 0xEE, 0xEC, 0xFF]);
+    }
+
+    #[test]
+    fn filters_match_per_pixel_output() {
+        let buffer: Vec<u16> = (0..Ppu::SIZE as u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 23) as u16)
+            .collect();
+        let mut video = Video::new();
+
+        video.decode_buffer(&buffer);
+        for (pixel, colors) in buffer.iter().zip(video.output().chunks_exact(4)) {
+            let (red, green, blue) = Ppu::system_palette(*pixel);
+            assert_eq!(colors, [red, green, blue, 0xFF]);
+        }
+
+        for frame_number in [0, 1] {
+            video.apply_ntsc_filter(&buffer, frame_number);
+            let even_phase = if frame_number & 0x01 == 0x01 { 0 } else { 1 };
+            for (idx, colors) in video.output().chunks_exact(4).enumerate() {
+                let (x, y) = (idx % 256, idx / 256);
+                let color = if x == 0 {
+                    0
+                } else {
+                    let phase = (2 + y * 341 + x + even_phase) % 3;
+                    let prev = (buffer[idx - 1] & 0x3F) as usize;
+                    NTSC_PALETTE[phase + prev * 3 + buffer[idx] as usize * 3 * 64]
+                };
+                let expected = [(color >> 16) as u8, (color >> 8) as u8, color as u8, 0xFF];
+                assert_eq!(colors, expected, "pixel {idx}");
+            }
+        }
+    }
+
+    #[test]
+    fn filter_scale_repeats_filtered_rows() {
+        let buffer: Vec<u16> = (0..Ppu::SIZE as u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 23) as u16)
+            .collect();
+        let stride = 4 * Ppu::WIDTH as usize;
+        for filter in VideoFilter::as_slice() {
+            let mut video = Video::new();
+            video.set_filter(*filter);
+            video.apply_filter(&buffer, 1);
+            let full = video.output().to_vec();
+
+            video.set_filter_scale(2);
+            video.apply_filter(&buffer, 1);
+            let rows: Vec<&[u8]> = video.output().chunks_exact(stride).collect();
+            for (y, row) in full.chunks_exact(stride).enumerate() {
+                let expected = if y % 2 == 0 { row } else { rows[y - 1] };
+                assert_eq!(rows[y], expected, "{filter:?} row {y}");
+            }
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Vectorized frame conversion loops.
+//!
+//! Both filters boil down to computing a palette index for each pixel, looking up a color and
+//! writing it out as RGBA. Index math and color swizzling are done four pixels at a time with
+//! SSE2 on `x86_64` or NEON on `aarch64`, both of which are always available on those targets so
+//! no runtime detection is needed. Palette lookups remain scalar since neither has a gather.
+//! Other targets use the scalar kernels, which produce identical output.
+
+use crate::ppu::Ppu;
+
+/// `Ppu::system_palette` packed as `0x00RRGGBB`, matching `NTSC_PALETTE`.
+const SYSTEM_PALETTE: [u32; 64] = {
+    let mut palette = [0; 64];
+    let mut i = 0;
+    while i < palette.len() {
+        let (red, green, blue) = Ppu::system_palette(i as u16);
+        palette[i] = (red as u32) << 16 | (green as u32) << 8 | blue as u32;
+        i += 1;
+    }
+    palette
+};
+
+/// NTSC phase of each of four consecutive pixels, indexed by the phase of the first.
+const PHASES: [[u32; 4]; 3] = [[0, 1, 2, 0], [1, 2, 0, 1], [2, 0, 1, 2]];
+
+/// Writes `buffer` to `output` as RGBA using the system palette.
+pub(super) fn decode(buffer: &[u16], output: &mut [u8]) {
+    assert!(buffer.len() * 4 == output.len());
+    let mut pixels = buffer.chunks_exact(4);
+    let mut colors = output.chunks_exact_mut(16);
+    for (pixels, colors) in pixels.by_ref().zip(colors.by_ref()) {
+        let indices = kernel::palette_indices(to_array(pixels));
+        kernel::store_rgba(lookup(&SYSTEM_PALETTE, indices), to_array_mut(colors));
+    }
+    for (pixel, colors) in pixels
+        .remainder()
+        .iter()
+        .zip(colors.into_remainder().chunks_exact_mut(4))
+    {
+        write_rgba(SYSTEM_PALETTE[(*pixel & 0x3F) as usize], colors);
+    }
+}
+
+/// Writes `buffer` to `output` as RGBA using the NTSC palette, which blends each pixel with the
+/// one before it based on its phase in the signal. Only every `row_step`th row is written.
+pub(super) fn ntsc(
+    buffer: &[u16],
+    output: &mut [u8],
+    frame_number: u32,
+    palette: &[u32],
+    row_step: usize,
+) {
+    const WIDTH: usize = Ppu::WIDTH as usize;
+    assert!(buffer.len() * 4 == output.len());
+    assert!(buffer.len() % WIDTH == 0);
+    let even_phase = if frame_number & 0x01 == 0x01 { 0 } else { 1 };
+    for (y, (row, colors)) in buffer
+        .chunks_exact(WIDTH)
+        .zip(output.chunks_exact_mut(4 * WIDTH))
+        .enumerate()
+        .step_by(row_step)
+    {
+        let mut phase = (2 + y * 341 + even_phase) % 3;
+        for x in (0..WIDTH).step_by(4) {
+            let pixels = to_array(&row[x..x + 4]);
+            let prev = if x == 0 {
+                [0, row[0], row[1], row[2]]
+            } else {
+                *to_array(&row[x - 1..x + 3])
+            };
+            let indices = kernel::ntsc_indices(pixels, &prev, &PHASES[phase]);
+            let mut color = lookup(palette, indices);
+            if x == 0 {
+                // Remove pixel 0 artifact from not having a valid previous pixel
+                color[0] = 0;
+            }
+            kernel::store_rgba(color, to_array_mut(&mut colors[4 * x..4 * x + 16]));
+            // Four pixels advance the phase by one
+            phase = (phase + 1) % 3;
+        }
+    }
+}
+
+#[inline]
+fn lookup(palette: &[u32], indices: [u32; 4]) -> [u32; 4] {
+    indices.map(|index| palette[index as usize])
+}
+
+#[inline]
+fn to_array(pixels: &[u16]) -> &[u16; 4] {
+    pixels.try_into().expect("4 pixels")
+}
+
+#[inline]
+fn to_array_mut(colors: &mut [u8]) -> &mut [u8; 16] {
+    colors.try_into().expect("4 RGBA colors")
+}
+
+#[inline]
+fn write_rgba(color: u32, colors: &mut [u8]) {
+    colors[0] = (color >> 16 & 0xFF) as u8;
+    colors[1] = (color >> 8 & 0xFF) as u8;
+    colors[2] = (color & 0xFF) as u8;
+    colors[3] = 0xFF;
+}
+
+#[cfg(target_arch = "x86_64")]
+use sse2 as kernel;
+
+#[cfg(target_arch = "aarch64")]
+use neon as kernel;
+
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+use scalar as kernel;
+
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64"), allow(dead_code))]
+mod scalar {
+    use super::write_rgba;
+
+    #[inline]
+    pub(super) fn palette_indices(pixels: &[u16; 4]) -> [u32; 4] {
+        pixels.map(|pixel| u32::from(pixel & 0x3F))
+    }
+
+    #[inline]
+    pub(super) fn ntsc_indices(pixels: &[u16; 4], prev: &[u16; 4], phase: &[u32; 4]) -> [u32; 4] {
+        let mut indices = *phase;
+        for ((index, pixel), prev) in indices.iter_mut().zip(pixels).zip(prev) {
+            *index += u32::from(prev & 0x3F) * 3 + u32::from(*pixel) * 3 * 64;
+        }
+        indices
+    }
+
+    #[inline]
+    pub(super) fn store_rgba(colors: [u32; 4], output: &mut [u8; 16]) {
+        for (color, output) in colors.into_iter().zip(output.chunks_exact_mut(4)) {
+            write_rgba(color, output);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod sse2 {
+    use std::arch::x86_64::{
+        __m128i, _mm_add_epi32, _mm_and_si128, _mm_loadl_epi64, _mm_loadu_si128, _mm_or_si128,
+        _mm_set1_epi32, _mm_setzero_si128, _mm_slli_epi32, _mm_srli_epi32, _mm_storeu_si128,
+        _mm_unpacklo_epi16,
+    };
+
+    // SAFETY (for every block below): SSE2 is part of the x86_64 baseline, and all loads and
+    // stores are unaligned and go through references to arrays of exactly 8 or 16 bytes.
+
+    /// Loads four `u16` pixels zero-extended to 32-bit lanes.
+    #[inline]
+    fn load_pixels(pixels: &[u16; 4]) -> __m128i {
+        unsafe {
+            let pixels = _mm_loadl_epi64(pixels.as_ptr().cast());
+            _mm_unpacklo_epi16(pixels, _mm_setzero_si128())
+        }
+    }
+
+    #[inline]
+    fn to_array(lanes: __m128i) -> [u32; 4] {
+        let mut out = [0; 4];
+        unsafe { _mm_storeu_si128(out.as_mut_ptr().cast(), lanes) };
+        out
+    }
+
+    #[inline]
+    pub(super) fn palette_indices(pixels: &[u16; 4]) -> [u32; 4] {
+        let indices = unsafe { _mm_and_si128(load_pixels(pixels), _mm_set1_epi32(0x3F)) };
+        to_array(indices)
+    }
+
+    #[inline]
+    pub(super) fn ntsc_indices(pixels: &[u16; 4], prev: &[u16; 4], phase: &[u32; 4]) -> [u32; 4] {
+        let indices = unsafe {
+            let pixels = load_pixels(pixels);
+            let prev = _mm_and_si128(load_pixels(prev), _mm_set1_epi32(0x3F));
+            let phase = _mm_loadu_si128(phase.as_ptr().cast());
+            // phase + prev * 3 + pixel * 192, without SSE4.1 32-bit multiplies
+            let prev = _mm_add_epi32(_mm_slli_epi32(prev, 1), prev);
+            let pixels = _mm_add_epi32(_mm_slli_epi32(pixels, 7), _mm_slli_epi32(pixels, 6));
+            _mm_add_epi32(phase, _mm_add_epi32(prev, pixels))
+        };
+        to_array(indices)
+    }
+
+    /// Swizzles four `0x00RRGGBB` colors into RGBA bytes with full alpha.
+    #[inline]
+    pub(super) fn store_rgba(colors: [u32; 4], output: &mut [u8; 16]) {
+        unsafe {
+            let colors = _mm_loadu_si128(colors.as_ptr().cast());
+            let byte = _mm_set1_epi32(0xFF);
+            let red = _mm_and_si128(_mm_srli_epi32(colors, 16), byte);
+            let green = _mm_and_si128(colors, _mm_set1_epi32(0xFF00));
+            let blue = _mm_slli_epi32(_mm_and_si128(colors, byte), 16);
+            let alpha = _mm_set1_epi32(0xFF00_0000_u32 as i32);
+            let rgba = _mm_or_si128(_mm_or_si128(red, green), _mm_or_si128(blue, alpha));
+            _mm_storeu_si128(output.as_mut_ptr().cast(), rgba);
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::{
+        uint32x4_t, vandq_u32, vdupq_n_u32, vld1_u16, vld1q_u32, vmlaq_n_u32, vmovl_u16, vorrq_u32,
+        vreinterpretq_u8_u32, vshlq_n_u32, vshrq_n_u32, vst1q_u32, vst1q_u8,
+    };
+
+    // SAFETY (for every block below): NEON is part of the aarch64 baseline, and all loads and
+    // stores go through references to arrays of exactly 8 or 16 bytes.
+
+    /// Loads four `u16` pixels zero-extended to 32-bit lanes.
+    #[inline]
+    fn load_pixels(pixels: &[u16; 4]) -> uint32x4_t {
+        unsafe { vmovl_u16(vld1_u16(pixels.as_ptr())) }
+    }
+
+    #[inline]
+    fn to_array(lanes: uint32x4_t) -> [u32; 4] {
+        let mut out = [0; 4];
+        unsafe { vst1q_u32(out.as_mut_ptr(), lanes) };
+        out
+    }
+
+    #[inline]
+    pub(super) fn palette_indices(pixels: &[u16; 4]) -> [u32; 4] {
+        let indices = unsafe { vandq_u32(load_pixels(pixels), vdupq_n_u32(0x3F)) };
+        to_array(indices)
+    }
+
+    #[inline]
+    pub(super) fn ntsc_indices(pixels: &[u16; 4], prev: &[u16; 4], phase: &[u32; 4]) -> [u32; 4] {
+        let indices = unsafe {
+            let prev = vandq_u32(load_pixels(prev), vdupq_n_u32(0x3F));
+            let phase = vld1q_u32(phase.as_ptr());
+            // phase + prev * 3 + pixel * 192
+            let indices = vmlaq_n_u32(phase, prev, 3);
+            vmlaq_n_u32(indices, load_pixels(pixels), 3 * 64)
+        };
+        to_array(indices)
+    }
+
+    /// Swizzles four `0x00RRGGBB` colors into RGBA bytes with full alpha.
+    #[inline]
+    pub(super) fn store_rgba(colors: [u32; 4], output: &mut [u8; 16]) {
+        unsafe {
+            let colors = vld1q_u32(colors.as_ptr());
+            let byte = vdupq_n_u32(0xFF);
+            let red = vandq_u32(vshrq_n_u32(colors, 16), byte);
+            let green = vandq_u32(colors, vdupq_n_u32(0xFF00));
+            let blue = vshlq_n_u32(vandq_u32(colors, byte), 16);
+            let alpha = vdupq_n_u32(0xFF00_0000);
+            let rgba = vorrq_u32(vorrq_u32(red, green), vorrq_u32(blue, alpha));
+            vst1q_u8(output.as_mut_ptr(), vreinterpretq_u8_u32(rgba));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernels_match_scalar() {
+        let pixels = [0x1FF, 0x0D, 0x13A, 0x30];
+        let prev = [0x41, 0x1FF, 0x0D, 0x13A];
+        for phase in &PHASES {
+            assert_eq!(
+                kernel::ntsc_indices(&pixels, &prev, phase),
+                scalar::ntsc_indices(&pixels, &prev, phase),
+            );
+        }
+        assert_eq!(
+            kernel::palette_indices(&pixels),
+            scalar::palette_indices(&pixels)
+        );
+
+        let colors = [0x00FF_8001, 0x0012_3456, 0, 0x00FF_FFFF];
+        let mut expected = [0; 16];
+        let mut output = [0; 16];
+        scalar::store_rgba(colors, &mut expected);
+        kernel::store_rgba(colors, &mut output);
+        assert_eq!(output, expected);
+        assert_eq!(&output[..4], &[0xFF, 0x80, 0x01, 0xFF]);
+    }
+}